@@ -0,0 +1,22 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+fn main() {
+    // The gRPC transport to Bonsai is generated from its service definition.
+    #[cfg(feature = "grpc")]
+    {
+        std::env::set_var("PROTOC", protobuf_src::protoc());
+        tonic_build::compile_protos("src/bonsai_transport/bonsai.proto").unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Alerts handed to a local program, e.g. a paging script, on persistent
+//! failures of the relayer, for environments without Prometheus. The program
+//! is run with a JSON description of the alert on its stdin, at most once per
+//! cooldown for each kind of alert, so that a flapping condition doesn't
+//! spawn a process on every flap.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use bonsai_sdk::{alpha::Client, alpha_async::health};
+use ethers::{core::types::Address, utils::format_ether};
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, process::Command, sync::broadcast, time::MissedTickBehavior};
+use tracing::{debug, info, warn};
+
+use crate::{alerts::signer_removed_message, events::RelayerEvent, metrics::Metrics};
+
+/// Default minimum time between two runs of the alert program for the same
+/// kind of alert.
+pub const DEFAULT_ALERT_CMD_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// Default time for which Bonsai is to be unreachable before alerting.
+pub const DEFAULT_BONSAI_UNREACHABLE_ALERT: Duration = Duration::from_secs(5 * 60);
+
+/// Default number of failed callback requests in the dead-letter list at
+/// which to alert.
+pub const DEFAULT_DEAD_LETTER_ALERT_THRESHOLD: usize = 10;
+
+/// Interval between the checks of the reachability of Bonsai and of the
+/// number of dead letters.
+const ALERT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Program run on persistent failures of the relayer, and the conditions it
+/// is run on besides the reconnects to the Ethereum node being exhausted and
+/// the wallet balance dropping below the minimum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertCommand {
+    pub program: PathBuf,
+    /// Minimum time between two runs of the program for the same kind of
+    /// alert.
+    pub cooldown: Duration,
+    /// Time for which Bonsai is to be unreachable before alerting.
+    pub bonsai_unreachable_after: Duration,
+    /// Number of failed callback requests in the dead-letter list at which
+    /// to alert, once crossed.
+    pub dead_letter_threshold: usize,
+}
+
+impl AlertCommand {
+    pub fn new(program: PathBuf) -> Self {
+        Self {
+            program,
+            cooldown: DEFAULT_ALERT_CMD_COOLDOWN,
+            bonsai_unreachable_after: DEFAULT_BONSAI_UNREACHABLE_ALERT,
+            dead_letter_threshold: DEFAULT_DEAD_LETTER_ALERT_THRESHOLD,
+        }
+    }
+
+    /// Sets the minimum time between two runs of the program for the same
+    /// kind of alert.
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Sets the time for which Bonsai is to be unreachable before alerting.
+    pub fn with_bonsai_unreachable_after(mut self, bonsai_unreachable_after: Duration) -> Self {
+        self.bonsai_unreachable_after = bonsai_unreachable_after;
+        self
+    }
+
+    /// Sets the number of dead letters at which to alert.
+    pub fn with_dead_letter_threshold(mut self, dead_letter_threshold: usize) -> Self {
+        self.dead_letter_threshold = dead_letter_threshold;
+        self
+    }
+}
+
+/// Kind of an alert, each with its own cooldown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AlertKind {
+    EthReconnectsExhausted,
+    BonsaiUnreachable,
+    DeadLetters,
+    LowBalance,
+    SignerRemoved,
+}
+
+/// JSON description of an alert, written to the stdin of the program.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct CommandAlert {
+    pub(crate) kind: AlertKind,
+    pub(crate) message: String,
+    /// Chain of the failure, if specific to one.
+    pub(crate) chain_id: Option<u64>,
+    /// Relay contracts served by the relayer.
+    pub(crate) relay_addresses: Vec<Address>,
+    /// RFC 3339 time of the failure.
+    pub(crate) timestamp: String,
+}
+
+/// The conditions alerted on, and the cooldowns of the alerts.
+#[derive(Debug)]
+pub(crate) struct AlertConditions {
+    config: AlertCommand,
+    relay_addresses: Vec<Address>,
+    /// Time of the last run of the program, by kind of alert.
+    last_runs: HashMap<AlertKind, Instant>,
+    /// Time since which Bonsai is unreachable, and whether it was alerted.
+    bonsai_unreachable: Option<(Instant, bool)>,
+    /// Whether the dead letters were over the threshold when last counted.
+    dead_letters_over: bool,
+}
+
+impl AlertConditions {
+    pub(crate) fn new(config: AlertCommand, relay_addresses: Vec<Address>) -> Self {
+        Self {
+            config,
+            relay_addresses,
+            last_runs: HashMap::new(),
+            bonsai_unreachable: None,
+            dead_letters_over: false,
+        }
+    }
+
+    fn alert(
+        &self,
+        kind: AlertKind,
+        message: String,
+        chain_id: Option<u64>,
+        timestamp: u64,
+    ) -> CommandAlert {
+        let time = UNIX_EPOCH + Duration::from_millis(timestamp);
+        CommandAlert {
+            kind,
+            message,
+            chain_id,
+            relay_addresses: self.relay_addresses.clone(),
+            timestamp: humantime::format_rfc3339_millis(time).to_string(),
+        }
+    }
+
+    /// The alert for a relayer event, if it reports the reconnects to the
+    /// Ethereum node being exhausted, the balance of a signer dropping below
+    /// the minimum, or a signer being removed from rotation.
+    pub(crate) fn on_event(&self, event: &RelayerEvent) -> Option<CommandAlert> {
+        match event {
+            RelayerEvent::EthReconnectsExhausted {
+                timestamp,
+                chain_id,
+                error,
+            } => Some(self.alert(
+                AlertKind::EthReconnectsExhausted,
+                format!("Failed to reconnect to the Ethereum node of chain {chain_id}: {error}"),
+                Some(*chain_id),
+                *timestamp,
+            )),
+            RelayerEvent::LowBalance {
+                timestamp,
+                chain_id,
+                signer,
+                balance,
+                min_eth_balance,
+            } => Some(self.alert(
+                AlertKind::LowBalance,
+                format!(
+                    "Balance of {} ETH of signer {signer:?} on chain {chain_id} is below the \
+                     minimum of {} ETH: its relay transactions are sent by the other signers, \
+                     or held back",
+                    format_ether(*balance),
+                    format_ether(*min_eth_balance)
+                ),
+                Some(*chain_id),
+                *timestamp,
+            )),
+            RelayerEvent::SignerRemoved {
+                timestamp,
+                chain_id,
+                signer,
+                error,
+                remaining,
+            } => Some(self.alert(
+                AlertKind::SignerRemoved,
+                signer_removed_message(*chain_id, *signer, error, *remaining),
+                Some(*chain_id),
+                *timestamp,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Record whether Bonsai answered its health check at `now`, returning
+    /// the alert once it has been unreachable for the configured time. Each
+    /// outage is alerted once.
+    pub(crate) fn on_bonsai_check(
+        &mut self,
+        reachable: bool,
+        now: Instant,
+    ) -> Option<CommandAlert> {
+        if reachable {
+            self.bonsai_unreachable = None;
+            return None;
+        }
+        let (since, alerted) = *self.bonsai_unreachable.get_or_insert((now, false));
+        let unreachable_for = now.duration_since(since);
+        if alerted || unreachable_for < self.config.bonsai_unreachable_after {
+            return None;
+        }
+        self.bonsai_unreachable = Some((since, true));
+        let message = format!(
+            "Bonsai unreachable for {}",
+            humantime::format_duration(Duration::from_secs(unreachable_for.as_secs()))
+        );
+        Some(self.alert(
+            AlertKind::BonsaiUnreachable,
+            message,
+            None,
+            crate::events::now(),
+        ))
+    }
+
+    /// Record the number of dead letters, returning the alert when it crosses
+    /// the threshold.
+    pub(crate) fn on_dead_letters(&mut self, count: usize) -> Option<CommandAlert> {
+        let over = count >= self.config.dead_letter_threshold;
+        let crossed = over && !self.dead_letters_over;
+        self.dead_letters_over = over;
+        crossed.then(|| {
+            self.alert(
+                AlertKind::DeadLetters,
+                format!(
+                    "{count} failed callback requests in the dead-letter list, over the \
+                     threshold of {}",
+                    self.config.dead_letter_threshold
+                ),
+                None,
+                crate::events::now(),
+            )
+        })
+    }
+
+    /// Returns whether an alert of the given kind is to be run at `now`,
+    /// outside the cooldown of the previous one, recording the run if so.
+    pub(crate) fn should_run(&mut self, kind: AlertKind, now: Instant) -> bool {
+        match self.last_runs.get(&kind) {
+            Some(last_run) if now.duration_since(*last_run) < self.config.cooldown => false,
+            _ => {
+                self.last_runs.insert(kind, now);
+                true
+            }
+        }
+    }
+}
+
+/// Run the alert program for the failures reported on `receiver`, and for
+/// Bonsai or the dead letters checked every [ALERT_CHECK_INTERVAL]. The
+/// program runs in tasks of their own, never holding back the relayer.
+pub(crate) async fn run_alert_command(
+    config: AlertCommand,
+    mut receiver: broadcast::Receiver<RelayerEvent>,
+    bonsai_client: Client,
+    relay_addresses: Vec<Address>,
+) {
+    let program = config.program.clone();
+    let mut conditions = AlertConditions::new(config, relay_addresses);
+    let mut checks = tokio::time::interval(ALERT_CHECK_INTERVAL);
+    checks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let alerts: Vec<CommandAlert> = tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => conditions.on_event(&event).into_iter().collect(),
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!(count, "Alert command missed relayer events");
+                    Vec::new()
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = checks.tick() => {
+                let reachable = health(bonsai_client.clone()).await.is_ok();
+                let dead_letters = Metrics::global().dead_letters.get() as usize;
+                [
+                    conditions.on_bonsai_check(reachable, Instant::now()),
+                    conditions.on_dead_letters(dead_letters),
+                ]
+                .into_iter()
+                .flatten()
+                .collect()
+            }
+        };
+        for alert in alerts {
+            if conditions.should_run(alert.kind, Instant::now()) {
+                spawn_program(program.clone(), alert);
+            } else {
+                debug!(kind = ?alert.kind, "Alert within its cooldown, not running the program");
+            }
+        }
+    }
+}
+
+/// Run the program with the alert on its stdin in a task of its own, logging
+/// its exit status.
+fn spawn_program(program: PathBuf, alert: CommandAlert) {
+    tokio::spawn(async move {
+        let kind = alert.kind;
+        match run_program(&program, &alert).await {
+            Ok(status) if status.success() => info!(
+                program = %program.display(),
+                ?kind,
+                %status,
+                "Alert command exited"
+            ),
+            Ok(status) => warn!(
+                program = %program.display(),
+                ?kind,
+                %status,
+                "Alert command failed"
+            ),
+            Err(error) => warn!(
+                program = %program.display(),
+                ?kind,
+                ?error,
+                "Failed to run the alert command"
+            ),
+        }
+    });
+}
+
+/// Run the program with the alert on its stdin, returning its exit status.
+pub(crate) async fn run_program(
+    program: &Path,
+    alert: &CommandAlert,
+) -> std::io::Result<ExitStatus> {
+    let mut child = Command::new(program)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let alert = serde_json::to_vec(alert)?;
+        // A program that doesn't read its stdin still runs.
+        let _ = stdin.write_all(&alert).await;
+    }
+    child.wait().await
+}
@@ -0,0 +1,162 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Alerts posted to a webhook, e.g. a Slack incoming webhook or a PagerDuty
+//! integration, when relaying fails or the daily spend limit is reached.
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use ethers::{
+    core::types::{Address, H256},
+    utils::format_ether,
+};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+use tracing::warn;
+
+use crate::events::RelayerEvent;
+
+/// Timeout of each request to the alert webhook.
+pub const ALERT_WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of relayer events buffered for the alert webhook when the relayer
+/// was not given an event channel.
+pub(crate) const ALERT_EVENTS_CAPACITY: usize = 1024;
+
+/// Body of the requests posted to the alert webhook.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct Alert {
+    pub(crate) level: &'static str,
+    pub(crate) message: String,
+    /// Relay contracts served by the relayer.
+    pub(crate) relay_addresses: Vec<Address>,
+    pub(crate) tx_hash: Option<H256>,
+    /// RFC 3339 time of the failure.
+    pub(crate) timestamp: String,
+}
+
+impl Alert {
+    /// The alert to post for a relayer event, if it reports a failure, e.g.
+    /// of a signer, or the daily spend limit being reached.
+    pub(crate) fn from_event(event: &RelayerEvent, relay_addresses: &[Address]) -> Option<Self> {
+        let (timestamp, message, tx_hash) = match event {
+            RelayerEvent::RelayError {
+                timestamp,
+                error,
+                tx_hash,
+            } => (*timestamp, error.clone(), *tx_hash),
+            RelayerEvent::SpendLimitReached {
+                timestamp,
+                chain_id,
+                spent,
+                max_daily_spend,
+            } => (
+                *timestamp,
+                format!(
+                    "Daily spend limit of {} ETH reached on chain {chain_id}, {} ETH spent over \
+                     the last 24 hours: relay transactions are held back",
+                    format_ether(*max_daily_spend),
+                    format_ether(*spent)
+                ),
+                None,
+            ),
+            RelayerEvent::SignerRemoved {
+                timestamp,
+                chain_id,
+                signer,
+                error,
+                remaining,
+            } => (
+                *timestamp,
+                signer_removed_message(*chain_id, *signer, error, *remaining),
+                None,
+            ),
+            _ => return None,
+        };
+        let time = UNIX_EPOCH + Duration::from_millis(timestamp);
+        Some(Self {
+            level: "error",
+            message,
+            relay_addresses: relay_addresses.to_vec(),
+            tx_hash,
+            timestamp: humantime::format_rfc3339_millis(time).to_string(),
+        })
+    }
+}
+
+/// Describes a signer removed from rotation after failing.
+pub(crate) fn signer_removed_message(
+    chain_id: u64,
+    signer: Option<Address>,
+    error: &str,
+    remaining: usize,
+) -> String {
+    let signer = signer.map_or_else(
+        || "A signer".to_string(),
+        |signer| format!("Signer {signer:?}"),
+    );
+    format!(
+        "{signer} of chain {chain_id} failed and was removed from rotation, \
+         {remaining} left: {error}"
+    )
+}
+
+/// Post an [Alert] to the current `webhook`, if any, for each failure
+/// reported on `receiver`. Alerts that fail to post are logged and dropped, so
+/// that an unreachable webhook never stops the relayer.
+pub(crate) async fn post_alerts(
+    mut receiver: broadcast::Receiver<RelayerEvent>,
+    webhook: watch::Receiver<Option<String>>,
+    relay_addresses: Vec<Address>,
+) {
+    let client = match reqwest::Client::builder()
+        .timeout(ALERT_WEBHOOK_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(error) => {
+            warn!(?error, "Failed to create the alert webhook client");
+            return;
+        }
+    };
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                warn!(count, "Alert webhook missed relayer events");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let Some(alert) = Alert::from_event(&event, &relay_addresses) else {
+            continue;
+        };
+        let Some(webhook) = webhook.borrow().clone() else {
+            continue;
+        };
+        if let Err(error) = post_alert(&client, &webhook, &alert).await {
+            warn!(?error, "Failed to post alert to webhook");
+        }
+    }
+}
+
+async fn post_alert(client: &reqwest::Client, webhook: &str, alert: &Alert) -> reqwest::Result<()> {
+    client
+        .post(webhook)
+        .json(alert)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,311 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use axum::{extract::State, http::Request, middleware::Next, response::Response, Json};
+use ethers::utils::{format_ether, parse_ether};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{json::JsonBody, state::ApiState, Error, Result};
+use crate::{
+    images::{upload_images, GuestImage, ImageUploadResult},
+    intake::Intake,
+    spend::SpendLimit,
+    storage::Storage,
+};
+
+/// Header carrying the admin API key of the relayer.
+pub(crate) const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// Reject the requests not carrying the admin API key of the relayer.
+pub(crate) async fn authorize_admin<B>(
+    State(admin_api_key): State<String>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response> {
+    let key = req
+        .headers()
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|header| header.to_str().ok());
+    if is_admin_key(&admin_api_key, key) {
+        Ok(next.run(req).await)
+    } else {
+        Err(Error::Unauthorized)
+    }
+}
+
+/// Whether `key` is the admin API key, compared in constant time.
+pub(crate) fn is_admin_key(admin_api_key: &str, key: Option<&str>) -> bool {
+    let Some(key) = key else {
+        return false;
+    };
+    key.len() == admin_api_key.len()
+        && key
+            .bytes()
+            .zip(admin_api_key.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub(crate) struct UploadImagesRequest {
+    /// Names or hex-encoded image IDs of the guests to upload. All guests are
+    /// uploaded if empty.
+    #[serde(default)]
+    pub images: Vec<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct UploadImagesResponse {
+    pub results: Vec<ImageUploadResult>,
+}
+
+/// Select the guest images named by `selectors`, by name, in any case, or by
+/// hex-encoded image ID, or all of them if `selectors` is empty.
+pub(crate) fn select_images(
+    images: &[GuestImage],
+    selectors: &[String],
+) -> Result<Vec<GuestImage>> {
+    if selectors.is_empty() {
+        return Ok(images.to_vec());
+    }
+    selectors
+        .iter()
+        .map(|selector| {
+            let image_id = selector.trim_start_matches("0x").to_lowercase();
+            images
+                .iter()
+                .find(|image| {
+                    image.name.eq_ignore_ascii_case(selector)
+                        || hex::encode(image.image_id) == image_id
+                })
+                .cloned()
+                .ok_or_else(|| Error::UnknownImage(selector.clone()))
+        })
+        .collect()
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/admin/upload-images",
+    request_body = UploadImagesRequest,
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Upload results of each image", body = UploadImagesResponse),
+        (status = 400, description = "Malformed request body"),
+        (status = 401, description = "Missing or wrong admin API key"),
+        (status = 404, description = "Unknown guest image"),
+    )
+)]
+pub(crate) async fn post_upload_images<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    JsonBody(request): JsonBody<UploadImagesRequest>,
+) -> Result<Json<UploadImagesResponse>, Error> {
+    let images = select_images(&s.images, &request.images)?;
+    let results = upload_images(&*s.bonsai_client, &images).await;
+    Ok(Json(UploadImagesResponse { results }))
+}
+
+/// Progress of a drain of the relayer.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct DrainStatus {
+    /// Whether the relayer is draining, taking in no new callback request.
+    pub draining: bool,
+    /// Bonsai sessions new or being proven.
+    pub proving: usize,
+    /// Bonsai sessions proven, waiting for or being relayed on chain.
+    pub relaying: usize,
+    /// On-chain events deferred at intake, processed once the intake reopens,
+    /// or on the next start.
+    pub deferred_events: usize,
+    /// Whether the relayer is draining with no session left in flight, and
+    /// can be restarted.
+    pub drained: bool,
+}
+
+async fn drain_status<S: Storage + Sync>(intake: &Intake, storage: &S) -> Result<DrainStatus> {
+    let (in_flight, deferred_events) = intake.in_flight(storage).await?;
+    let draining = intake.is_draining();
+    Ok(DrainStatus {
+        draining,
+        proving: in_flight.proving,
+        relaying: in_flight.relaying,
+        deferred_events,
+        drained: draining && in_flight.total() == 0,
+    })
+}
+
+/// Report the progress of a drain.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/drain",
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Progress of the drain", body = DrainStatus),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn get_drain<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<Json<DrainStatus>> {
+    Ok(Json(drain_status(&s.intake, &s.storage).await?))
+}
+
+/// Stop taking in new callback requests, e.g. before a restart, letting the
+/// ones in flight finish.
+///
+/// On-chain events are deferred, and REST requests are refused with status
+/// 503, until the relayer resumes.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/drain",
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Progress of the drain", body = DrainStatus),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn post_drain<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<Json<DrainStatus>> {
+    s.intake.drain();
+    Ok(Json(drain_status(&s.intake, &s.storage).await?))
+}
+
+/// Take in new callback requests again after a drain. The deferred on-chain
+/// events are processed within a few seconds.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/resume",
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "State of the intake", body = DrainStatus),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn post_resume<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Result<Json<DrainStatus>> {
+    s.intake.resume();
+    Ok(Json(drain_status(&s.intake, &s.storage).await?))
+}
+
+/// Daily spend limit of the relayer wallet.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct SpendLimitStatus {
+    /// Limit, in ether, on the gas spent by the relay transactions of each
+    /// chain over the last 24 hours, or null if unlimited.
+    pub max_daily_spend: Option<String>,
+    /// Spend of each chain against the limit.
+    pub chains: Vec<ChainSpendStatus>,
+}
+
+/// Spend of a chain against the daily spend limit.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ChainSpendStatus {
+    pub chain_id: u64,
+    /// Gas spent by the relay transactions of the chain over the last 24
+    /// hours, in ether.
+    pub spent: String,
+    /// Whether the relay transactions of the chain are held back, the spend
+    /// having reached the limit.
+    pub limited: bool,
+}
+
+fn spend_limit_status(spend_limit: &SpendLimit) -> SpendLimitStatus {
+    let chains = spend_limit
+        .chain_ids()
+        .into_iter()
+        .map(|chain_id| ChainSpendStatus {
+            chain_id,
+            spent: format_ether(spend_limit.spent(chain_id)),
+            limited: spend_limit.is_limited(chain_id),
+        })
+        .collect();
+    SpendLimitStatus {
+        max_daily_spend: spend_limit.max_daily_spend().map(format_ether),
+        chains,
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub(crate) struct SpendLimitRequest {
+    /// New limit, in ether, e.g. "0.5", or null to lift it.
+    pub max_daily_spend: Option<String>,
+}
+
+/// Report the daily spend limit of the relayer wallet, and the gas spent
+/// against it.
+#[utoipa::path(
+    get,
+    path = "/v1/admin/spend-limit",
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Daily spend limit", body = SpendLimitStatus),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn get_spend_limit<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Json<SpendLimitStatus> {
+    Json(spend_limit_status(&s.spend_limit))
+}
+
+/// Change the daily spend limit of the relayer wallet, e.g. to raise it once
+/// reached, until the next restart. The relay transactions held back are
+/// sent within a second if the spend is below the new limit.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/spend-limit",
+    request_body = SpendLimitRequest,
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Daily spend limit", body = SpendLimitStatus),
+        (status = 400, description = "Malformed request body or ether amount"),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn post_spend_limit<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    JsonBody(request): JsonBody<SpendLimitRequest>,
+) -> Result<Json<SpendLimitStatus>> {
+    let max_daily_spend = request
+        .max_daily_spend
+        .map(|amount| {
+            parse_ether(&amount)
+                .map_err(|e| Error::MalformedBody(format!("invalid ether amount {amount}: {e}")))
+        })
+        .transpose()?;
+    s.spend_limit.set_max_daily_spend(max_daily_spend);
+    Ok(Json(spend_limit_status(&s.spend_limit)))
+}
+
+/// Send the relay transactions held back by the daily spend limit again,
+/// without waiting for the spend to leave the 24-hour window. The gas spent
+/// so far is no longer counted against the limit.
+#[utoipa::path(
+    post,
+    path = "/v1/admin/spend-limit/resume",
+    security(("admin_key" = [])),
+    responses(
+        (status = 200, description = "Daily spend limit", body = SpendLimitStatus),
+        (status = 401, description = "Missing or wrong admin API key"),
+    )
+)]
+pub(crate) async fn post_spend_limit_resume<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> Json<SpendLimitStatus> {
+    s.spend_limit.resume();
+    Json(spend_limit_status(&s.spend_limit))
+}
@@ -12,41 +12,340 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use axum::{extract::State, Extension};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    Extension, Json,
+};
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-use bonsai_sdk::alpha_async::get_client_from_parts;
+use bonsai_sdk::alpha_async::{get_client_from_parts, RoundRobinBonsaiClient};
+use ethers::types::{Address, H256};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use super::{bincode::Bincode, state::ApiState, Error, Result};
+use super::{
+    bincode::Bincode,
+    state::{select_relay, ApiState},
+    Error, Result,
+};
 use crate::{
-    downloader::{
-        event_processor::EventProcessor,
-        proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
-    },
+    downloader::proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
+    metrics::Metrics,
     sdk::client::CallbackRequest,
-    storage::Storage,
+    storage::{
+        dead_letters::{DeadLetter, FailureStage},
+        ProofRequestState, Storage,
+    },
 };
 
+/// State of a callback request, in the order of its lifecycle. `failed`,
+/// `completed_onchain`, `expired` and `skipped` are terminal, short of
+/// retrying a failed request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CallbackRequestState {
+    /// Received, waiting to be proven on Bonsai.
+    New,
+    /// Being proven on Bonsai.
+    Pending,
+    /// Proven, waiting to be relayed.
+    Completed,
+    /// Failed to be proven or relayed, and kept in the dead-letter list.
+    Failed,
+    /// Being relayed on chain.
+    PreparingOnchain,
+    /// Proven, held back as the relayer wallet reached its daily spend limit.
+    SpendLimited,
+    /// Relayed, its callback invoked on chain.
+    CompletedOnchain,
+    /// Not proven before its deadline.
+    Expired,
+    /// Proven, not relayed as its journal matches none of the journal
+    /// filters of the relayer.
+    Skipped,
+}
+
+impl From<&ProofRequestState> for CallbackRequestState {
+    fn from(state: &ProofRequestState) -> Self {
+        match state {
+            ProofRequestState::New => Self::New,
+            ProofRequestState::Pending => Self::Pending,
+            ProofRequestState::Completed => Self::Completed,
+            ProofRequestState::Failed => Self::Failed,
+            ProofRequestState::PreparingOnchain => Self::PreparingOnchain,
+            ProofRequestState::SpendLimited => Self::SpendLimited,
+            ProofRequestState::CompletedOnchain(_) => Self::CompletedOnchain,
+            ProofRequestState::Expired => Self::Expired,
+            ProofRequestState::Skipped => Self::Skipped,
+        }
+    }
+}
+
+/// Status of a callback request.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CallbackRequestStatus {
+    /// ID of the callback request.
+    pub request_id: String,
+    /// Bonsai session proving the request, which identical requests share.
+    pub session_id: String,
+    pub state: CallbackRequestState,
+    /// Transaction that invoked the callback, once completed on chain.
+    #[schema(value_type = Option<String>)]
+    pub tx_hash: Option<H256>,
+}
+
+/// A failed callback request, kept in the dead-letter list.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct FailedCallbackRequest {
+    /// ID of the callback request, by which it is retried.
+    pub request_id: String,
+    /// IDs of the identical requests attached to its Bonsai session, retried
+    /// along with it.
+    pub attached_request_ids: Vec<String>,
+    pub chain_id: u64,
+    #[schema(value_type = String)]
+    pub relay_address: Address,
+    /// Stage at which the request failed, `proving`, `relaying` or
+    /// `precheck`.
+    pub stage: String,
+    /// Reason of the failure.
+    pub reason: String,
+    /// Unix time, in milliseconds, at which the request failed.
+    pub failed_at: u64,
+}
+
+impl From<DeadLetter> for FailedCallbackRequest {
+    fn from(dead_letter: DeadLetter) -> Self {
+        let request = dead_letter.request;
+        Self {
+            request_id: request.proof_request_id.uuid,
+            attached_request_ids: request
+                .attached_requests
+                .into_iter()
+                .map(|attached| attached.request_id)
+                .collect(),
+            chain_id: request.chain_id,
+            relay_address: request.relay_contract_address,
+            stage: dead_letter.stage.to_string(),
+            reason: dead_letter.reason,
+            failed_at: dead_letter.failed_at,
+        }
+    }
+}
+
+/// Filter of the callback requests to list.
+#[derive(Debug, Deserialize)]
+pub(crate) struct CallbackRequestsQuery {
+    status: String,
+}
+
 /// Publish a CallbackRequest to the Relayer.
 ///
-/// Return status 200 on success.
+/// Return status 200 on success, with the ID of the callback request. Requests
+/// are refused with status 503 while the relayer drains, and 429 while too
+/// many requests are in flight.
 #[utoipa::path(
     post,
     path = "/v1/callbacks",
-    request_body = CallbackRequest,
+    request_body(content = CallbackRequest, content_type = "application/octet-stream", description = "Bincode serialized callback request"),
+    security(("api_key" = [])),
     responses(
-        (status = 200, description = "Callback request sent successfully"),
+        (status = 200, description = "Callback request sent successfully", body = String),
         (status = 400, description = "Bad request error"),
         (status = 500, description = "Internal server error"),
+        (status = 503, description = "Relayer draining for maintenance"),
     )
 )]
 pub(crate) async fn post_callback_request<S: Storage + Sync + Send + Clone>(
     Extension(api_key): Extension<String>,
     State(s): State<ApiState<S>>,
     Bincode(request): Bincode<CallbackRequest>,
-) -> Result<(), Error> {
+) -> Result<String, Error> {
+    let relay = select_relay(&s.relays, request.chain_id, request.relay_address)?;
     let client = get_client_from_parts(s.bonsai_url, api_key).await?;
-    let proxy = ProxyCallbackProofRequestProcessor::new(client, s.storage, Some(s.notifier));
-    proxy.process_event(request.into()).await
+    // Published requests are proven with the API key of their publisher,
+    // over the REST API.
+    let proxy = ProxyCallbackProofRequestProcessor::new(
+        relay.chain_id,
+        Arc::new(RoundRobinBonsaiClient::from(client)),
+        s.storage,
+        Some(s.notifier),
+        s.events,
+    )
+    .with_images(s.images)
+    .with_intake(s.intake);
+    let deadline = request.deadline.map(deadline_instant);
+    proxy.submit(relay.address, request.into(), deadline).await
+}
+
+/// Returns the instant of a deadline given as a Unix time, in seconds, which
+/// is now if it already passed.
+fn deadline_instant(deadline: u64) -> Instant {
+    let deadline = UNIX_EPOCH + Duration::from_secs(deadline);
+    let remaining = deadline
+        .duration_since(SystemTime::now())
+        .unwrap_or_default();
+    Instant::now() + remaining
+}
+
+/// Get the status of a CallbackRequest, by the request ID returned when
+/// publishing it.
+///
+/// Return status 404 if the request is unknown.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks/{request_id}",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Callback request found", body = CallbackRequestStatus),
+        (status = 404, description = "Callback request not found"),
+    )
+)]
+pub(crate) async fn get_callback_request_status<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<Json<CallbackRequestStatus>, Error> {
+    let (session_id, state) = s.storage.get_request_state(&request_id).await?;
+    Ok(Json(CallbackRequestStatus {
+        request_id,
+        session_id: session_id.uuid,
+        state: CallbackRequestState::from(&state),
+        tx_hash: state.tx_hash(),
+    }))
+}
+
+/// List the failed CallbackRequests kept in the dead-letter list, oldest
+/// first.
+///
+/// Only the `failed` status is supported.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks",
+    params(("status" = String, Query, description = "Status of the callback requests, `failed`")),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Failed callback requests", body = [FailedCallbackRequest]),
+        (status = 400, description = "Unsupported status"),
+    )
+)]
+pub(crate) async fn get_callback_requests<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    Query(query): Query<CallbackRequestsQuery>,
+) -> Result<Json<Vec<FailedCallbackRequest>>, Error> {
+    if query.status != "failed" {
+        return Err(Error::UnsupportedStatus(query.status));
+    }
+    let dead_letters = s.storage.fetch_dead_letters().await?;
+    Ok(Json(dead_letters.into_iter().map(Into::into).collect()))
+}
+
+/// Retry a failed CallbackRequest of the dead-letter list, along with the
+/// requests attached to its Bonsai session, by the ID of any of them.
+///
+/// A request that failed to be proven is proven again, under a new request
+/// ID, while the proof of a request whose relay transaction or gas precheck
+/// failed is relayed again. Return status 200 on success, with the ID of the retried request.
+///
+/// Not served in submission-only mode, where the submitted proofs are not
+/// kept.
+#[utoipa::path(
+    post,
+    path = "/v1/callbacks/{request_id}/retry",
+    params(("request_id" = String, Path, description = "ID of the failed callback request")),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Callback request retried", body = String),
+        (status = 404, description = "Failed callback request not found"),
+        (status = 422, description = "Callback request not retryable"),
+    )
+)]
+pub(crate) async fn post_retry_callback_request<S: Storage + Sync + Send + Clone>(
+    Extension(api_key): Extension<String>,
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+) -> Result<String, Error> {
+    let dead_letter = s.storage.take_dead_letter(&request_id).await?;
+    let retried = match dead_letter.stage {
+        FailureStage::Proving => retry_proving(&s, api_key, &dead_letter).await,
+        FailureStage::Relaying | FailureStage::Precheck => retry_relaying(&s, &dead_letter).await,
+    };
+    match retried {
+        Ok(request_id) => {
+            Metrics::global().record_dead_letter_retry(dead_letter.stage);
+            Ok(request_id)
+        }
+        Err(err) => {
+            // Keep the request to retry it again.
+            s.storage.add_dead_letter(dead_letter).await?;
+            Err(err)
+        }
+    }
+}
+
+/// Prove a failed request again, in a new Bonsai session to which the
+/// requests it had attached are attached again, returning its new ID.
+async fn retry_proving<S: Storage + Sync + Send + Clone>(
+    s: &ApiState<S>,
+    api_key: String,
+    dead_letter: &DeadLetter,
+) -> Result<String, Error> {
+    let request = &dead_letter.request;
+    select_relay(
+        &s.relays,
+        Some(request.chain_id),
+        Some(request.relay_contract_address),
+    )?;
+    let client = get_client_from_parts(s.bonsai_url.clone(), api_key).await?;
+    let proxy = ProxyCallbackProofRequestProcessor::new(
+        request.chain_id,
+        Arc::new(RoundRobinBonsaiClient::from(client)),
+        s.storage.clone(),
+        Some(s.notifier.clone()),
+        s.events.clone(),
+    )
+    .with_images(s.images.clone());
+    let request_id = proxy
+        .submit(
+            request.relay_contract_address,
+            request.callback_proof_request_event.clone(),
+            None,
+        )
+        .await?;
+    for attached in request.attached_requests.iter() {
+        proxy
+            .submit(
+                request.relay_contract_address,
+                attached.callback_proof_request_event.clone(),
+                None,
+            )
+            .await?;
+    }
+    Ok(request_id)
+}
+
+/// Relay the proof of a failed request again, once fetched from Bonsai by the
+/// complete proof manager of its chain, returning its ID.
+async fn retry_relaying<S: Storage + Sync + Send + Clone>(
+    s: &ApiState<S>,
+    dead_letter: &DeadLetter,
+) -> Result<String, Error> {
+    let request = &dead_letter.request;
+    let Some(notifier) = s.complete_proof_notifiers.get(&request.chain_id) else {
+        return Err(Error::RetryRejected(format!(
+            "chain {} is no longer served by this relayer",
+            request.chain_id
+        )));
+    };
+    s.storage
+        .add_completed_bonsai_proof_request(request.clone())
+        .await?;
+    notifier.notify_one();
+    Ok(request.proof_request_id.uuid.clone())
 }
 
 impl From<CallbackRequest> for CallbackRequestFilter {
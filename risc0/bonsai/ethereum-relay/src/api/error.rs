@@ -16,18 +16,20 @@ use std::{
     error::Error as StdError,
     fmt::{self, Debug},
     num::TryFromIntError,
+    time::Duration,
 };
 
-use axum::{http::StatusCode, response};
-use bonsai_sdk::alpha::SdkErr;
-use ethers::{
-    prelude::signer::SignerMiddlewareError,
-    providers::{Provider, Ws},
+use axum::{
+    http::{header, StatusCode},
+    response,
 };
-use ethers_signers::LocalWallet;
+use bonsai_sdk::alpha::SdkErr;
+use ethers::prelude::signer::SignerMiddlewareError;
 use tokio::task::JoinError;
 use validator::ValidationErrors;
 
+use crate::{client_config::EthersProvider, intake::IntakeClosed, signer::RelaySigner};
+
 #[derive(Debug, thiserror::Error)]
 pub(crate) enum Error {
     #[error("Unauthorized")]
@@ -53,7 +55,25 @@ pub(crate) enum Error {
     #[error("Ethers parse error")]
     EthersParse(#[from] ethers::abi::Error),
     #[error("Signer middleware error")]
-    SignerMiddleware(#[from] SignerMiddlewareError<Provider<Ws>, LocalWallet>),
+    SignerMiddleware(#[from] SignerMiddlewareError<EthersProvider, RelaySigner>),
+    #[error("Submission rejected: {0}")]
+    SubmissionRejected(String),
+    #[error("Unknown guest image: {0}")]
+    UnknownImage(String),
+    #[error("Invalid relay address: {0}")]
+    InvalidRelay(String),
+    #[error("Unsupported status filter: {0}")]
+    UnsupportedStatus(String),
+    #[error("Retry rejected: {0}")]
+    RetryRejected(String),
+    #[error("Malformed request body: {0}")]
+    MalformedBody(String),
+    #[error("Request body larger than {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("Rate limit exceeded, retry in {}s", retry_after_secs(.0))]
+    RateLimited(Duration),
+    #[error("Request not taken in: {0}")]
+    IntakeClosed(#[from] IntakeClosed),
     #[error("Unspecified error")]
     Unspecified(#[from] anyhow::Error),
 }
@@ -61,10 +81,27 @@ pub(crate) enum Error {
 impl Error {
     pub(crate) fn status_code(&self) -> StatusCode {
         match self {
-            Error::Validation { .. } | Error::Bonsai { .. } | Error::Client { .. } => {
-                StatusCode::BAD_REQUEST
-            }
+            Error::Validation { .. }
+            | Error::Bonsai { .. }
+            | Error::Client { .. }
+            | Error::InvalidRelay { .. }
+            | Error::UnsupportedStatus { .. }
+            | Error::MalformedBody { .. } => StatusCode::BAD_REQUEST,
             Error::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            Error::Storage(crate::storage::Error::RequestNotFound { .. })
+            | Error::Storage(crate::storage::Error::DeadLetterNotFound { .. })
+            | Error::UnknownImage { .. } => StatusCode::NOT_FOUND,
+            Error::SubmissionRejected { .. } | Error::RetryRejected { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            Error::PayloadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::RateLimited { .. }
+            | Error::IntakeClosed(
+                IntakeClosed::Full { .. }
+                | IntakeClosed::Busy { .. }
+                | IntakeClosed::BudgetSpent { .. },
+            ) => StatusCode::TOO_MANY_REQUESTS,
+            Error::IntakeClosed(IntakeClosed::Draining) => StatusCode::SERVICE_UNAVAILABLE,
             Error::Bincode { .. }
             | Error::Storage { .. }
             | Error::SignerMiddleware { .. }
@@ -78,6 +115,11 @@ impl Error {
     }
 }
 
+/// Whole seconds to wait before retrying a rate limited request, rounded up.
+fn retry_after_secs(retry_after: &Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
 impl From<TryFromIntError> for Error {
     fn from(err: TryFromIntError) -> Self {
         Self::Unspecified(err.into())
@@ -88,6 +130,10 @@ impl From<TryFromIntError> for Error {
 impl response::IntoResponse for Error {
     fn into_response(self) -> response::Response {
         let status = self.status_code();
+        let retry_after = match &self {
+            Error::RateLimited(retry_after) => Some(retry_after_secs(retry_after)),
+            _ => None,
+        };
         // log errors as one line with all its causes
         if status.is_client_error() || status.is_server_error() {
             tracing::error!(
@@ -106,8 +152,17 @@ impl response::IntoResponse for Error {
             _ => format!("{}", DisplayErrorCauses(&self)),
         };
 
-        // return the message as simple text
-        (status, message).into_response()
+        // return the message as simple text, with the time to wait before
+        // retrying a rate limited request
+        match retry_after {
+            Some(retry_after) => (
+                status,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                message,
+            )
+                .into_response(),
+            None => (status, message).into_response(),
+        }
     }
 }
 
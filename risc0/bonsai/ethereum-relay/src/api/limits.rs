@@ -0,0 +1,164 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Limits shared by the v1 REST API routes: a rate limit per API key, or per
+//! client IP address for requests without one, and a maximum body size.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+
+use super::{admin::ADMIN_KEY_HEADER, Error, Result};
+
+/// Default maximum size, in bytes, of the body of a REST API request.
+pub const DEFAULT_MAX_REQUEST_BODY_SIZE: usize = 1024 * 1024;
+
+/// Number of clients tracked by the rate limiter beyond which the ones that
+/// have their full burst again are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Rate limit of the REST API requests of each client.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Requests accepted per minute, on average.
+    pub requests_per_minute: u32,
+    /// Requests accepted at once, after the client made none for a while.
+    pub burst: u32,
+}
+
+/// Limits enforced on the v1 REST API requests.
+#[derive(Clone)]
+pub(crate) struct ApiLimits {
+    pub(crate) rate_limiter: Option<RateLimiter>,
+    pub(crate) max_body_size: usize,
+}
+
+impl ApiLimits {
+    pub(crate) fn new(rate_limit: Option<RateLimit>, max_body_size: usize) -> Self {
+        Self {
+            rate_limiter: rate_limit.map(RateLimiter::new),
+            max_body_size,
+        }
+    }
+}
+
+/// Token bucket of a client, holding up to `burst` requests and refilled
+/// at `requests_per_minute`.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Rate limiter of the REST API requests, with a token bucket per client.
+#[derive(Clone)]
+pub(crate) struct RateLimiter {
+    rate_limit: RateLimit,
+    buckets: Arc<Mutex<HashMap<String, Bucket>>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate_limit: RateLimit) -> Self {
+        Self {
+            rate_limit,
+            buckets: Default::default(),
+        }
+    }
+
+    fn burst(&self) -> f64 {
+        f64::from(self.rate_limit.burst.max(1))
+    }
+
+    /// Tokens refilled per second.
+    fn rate(&self) -> f64 {
+        f64::from(self.rate_limit.requests_per_minute.max(1)) / 60.0
+    }
+
+    /// Take a request of the bucket of `client` at `now`, or return the time
+    /// after which the client may make another request.
+    pub(crate) fn acquire(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let (burst, rate) = (self.burst(), self.rate());
+        let mut buckets = self
+            .buckets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated);
+            (bucket.tokens + elapsed.as_secs_f64() * rate).min(burst)
+        };
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| refill(bucket) < burst);
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Returns the client a request is rate limited as: its API key, or its IP
+/// address if it carries none.
+fn client<B>(req: &Request<B>) -> String {
+    let api_key = ["x-api-key", ADMIN_KEY_HEADER]
+        .into_iter()
+        .find_map(|name| req.headers().get(name))
+        .and_then(|header| header.to_str().ok());
+    match (api_key, req.extensions().get::<ConnectInfo<SocketAddr>>()) {
+        (Some(api_key), _) => format!("key:{api_key}"),
+        (None, Some(ConnectInfo(address))) => format!("ip:{}", address.ip()),
+        (None, None) => "unknown".to_string(),
+    }
+}
+
+/// Reject the requests of clients past their rate limit, and the ones whose
+/// declared body size is past the maximum. Bodies without a declared size are
+/// limited as they are read.
+pub(crate) async fn enforce_limits<B>(
+    State(limits): State<ApiLimits>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Result<Response> {
+    if let Some(rate_limiter) = &limits.rate_limiter {
+        rate_limiter
+            .acquire(&client(&req), Instant::now())
+            .map_err(Error::RateLimited)?;
+    }
+    let body_size = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|size| size.parse::<usize>().ok());
+    if body_size.is_some_and(|size| size > limits.max_body_size) {
+        return Err(Error::PayloadTooLarge(limits.max_body_size));
+    }
+    Ok(next.run(req).await)
+}
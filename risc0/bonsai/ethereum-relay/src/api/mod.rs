@@ -14,17 +14,58 @@
 
 use self::error::Error;
 
+pub(crate) mod admin;
 pub(crate) mod auth;
 pub(crate) mod bincode;
 pub(crate) mod callback_request;
 pub(crate) mod error;
+pub(crate) mod json;
+pub(crate) mod limits;
+pub(crate) mod metrics;
+pub(crate) mod openapi;
+pub(crate) mod readiness;
+pub(crate) mod request_events;
 pub(crate) mod server;
 pub(crate) mod state;
+pub(crate) mod submission;
+pub(crate) mod tls;
 
 /// The routes for the API.
 pub mod routes {
     /// Route for `Callback` related APIs.
     pub const CALLBACK_ROUTE: &str = "/v1/callbacks";
+    /// Route for the status of a `Callback` request.
+    pub const CALLBACK_STATUS_ROUTE: &str = "/v1/callbacks/:request_id";
+    /// Route retrying a failed `Callback` request.
+    pub const CALLBACK_RETRY_ROUTE: &str = "/v1/callbacks/:request_id/retry";
+    /// Route streaming the state transitions of a `Callback` request.
+    pub const CALLBACK_EVENTS_ROUTE: &str = "/v1/callbacks/:request_id/events";
+    /// Route streaming the state transitions of all `Callback` requests.
+    pub const EVENTS_ROUTE: &str = "/v1/events";
+    /// Route for submitting pre-generated proofs in submission-only mode.
+    pub const SUBMISSION_ROUTE: &str = "/v1/submissions";
+    /// Route serving the OpenAPI specification of the REST API.
+    pub const OPENAPI_ROUTE: &str = "/v1/openapi.json";
+    /// Route of the Swagger UI of the REST API, if served.
+    pub const SWAGGER_UI_ROUTE: &str = "/swagger-ui";
+    /// Route exposing the relayer metrics in the Prometheus text format.
+    pub const METRICS_ROUTE: &str = "/metrics";
+    /// Route reporting whether the relayer is ready to take in and relay
+    /// callback requests.
+    pub const READY_ROUTE: &str = "/ready";
+    /// Route re-uploading the guest images of the relayer to Bonsai.
+    pub const ADMIN_UPLOAD_IMAGES_ROUTE: &str = "/v1/admin/upload-images";
+    /// Route draining the relayer before a maintenance, and reporting the
+    /// progress of the drain.
+    pub const ADMIN_DRAIN_ROUTE: &str = "/v1/admin/drain";
+    /// Route taking in new callback requests again after a drain.
+    pub const ADMIN_RESUME_ROUTE: &str = "/v1/admin/resume";
+    /// Route reporting and changing the daily spend limit of the relayer
+    /// wallet.
+    pub const ADMIN_SPEND_LIMIT_ROUTE: &str = "/v1/admin/spend-limit";
+    /// Route sending the relay transactions held back by the daily spend
+    /// limit again, before the spend leaves the 24-hour window.
+    pub const ADMIN_SPEND_LIMIT_RESUME_ROUTE: &str = "/v1/admin/spend-limit/resume";
 }
 
 pub(crate) type Result<T, E = Error> = ::std::result::Result<T, E>;
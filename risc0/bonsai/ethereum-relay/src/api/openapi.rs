@@ -0,0 +1,211 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! OpenAPI specification of the REST API, generated from the annotations and
+//! types of its handlers.
+
+use axum::Json;
+use once_cell::sync::Lazy;
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, SecurityScheme},
+        Content, HeaderBuilder, ObjectBuilder, OpenApi as OpenApiSpec, RefOr, Response,
+        ResponseBuilder, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    api::{
+        admin::{
+            __path_get_drain, __path_get_spend_limit, __path_post_drain, __path_post_resume,
+            __path_post_spend_limit, __path_post_spend_limit_resume, __path_post_upload_images,
+            ChainSpendStatus, DrainStatus, SpendLimitRequest, SpendLimitStatus,
+            UploadImagesRequest, UploadImagesResponse, ADMIN_KEY_HEADER,
+        },
+        callback_request::{
+            __path_get_callback_request_status, __path_get_callback_requests,
+            __path_post_callback_request, __path_post_retry_callback_request, CallbackRequestState,
+            CallbackRequestStatus, FailedCallbackRequest,
+        },
+        metrics::__path_get_metrics,
+        readiness::{__path_get_ready, Readiness},
+        request_events::{
+            __path_get_callback_request_events, __path_get_events, CallbackRequestEvent,
+        },
+        submission::__path_post_submission,
+    },
+    images::ImageUploadResult,
+    sdk::client::{CallbackRequest, ProofSubmission},
+};
+
+/// Header of the API key authenticating the requests of the v1 routes.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Content type of the error responses, whose body is the error message.
+const ERROR_CONTENT_TYPE: &str = "text/plain";
+
+static SPEC: Lazy<OpenApiSpec> = Lazy::new(ApiDoc::openapi);
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Bonsai Ethereum Relay",
+        description = "REST API of the relayer integrating Ethereum with Bonsai."
+    ),
+    paths(
+        post_callback_request,
+        get_callback_requests,
+        get_callback_request_status,
+        post_retry_callback_request,
+        get_callback_request_events,
+        get_events,
+        post_submission,
+        post_upload_images,
+        get_drain,
+        post_drain,
+        post_resume,
+        get_spend_limit,
+        post_spend_limit,
+        post_spend_limit_resume,
+        get_openapi,
+        get_metrics,
+        get_ready
+    ),
+    components(schemas(
+        CallbackRequest,
+        CallbackRequestState,
+        CallbackRequestStatus,
+        CallbackRequestEvent,
+        FailedCallbackRequest,
+        ProofSubmission,
+        UploadImagesRequest,
+        UploadImagesResponse,
+        ImageUploadResult,
+        DrainStatus,
+        SpendLimitStatus,
+        ChainSpendStatus,
+        SpendLimitRequest,
+        Readiness
+    )),
+    modifiers(&ApiKeys, &ErrorResponses)
+)]
+pub(crate) struct ApiDoc;
+
+/// Declares the API keys referred to by the `security` of the handlers.
+struct ApiKeys;
+
+impl Modify for ApiKeys {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                API_KEY_HEADER,
+                "API key of the client, also used as its Bonsai API key",
+            ))),
+        );
+        components.add_security_scheme(
+            "admin_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                ADMIN_KEY_HEADER,
+                "Admin API key of the relayer",
+            ))),
+        );
+    }
+}
+
+/// Documents the body of the error responses, as written by
+/// [super::Error], and the responses of the authentication and of the limits
+/// shared by the v1 routes, which are enforced by their layers rather than by
+/// the handlers.
+struct ErrorResponses;
+
+impl Modify for ErrorResponses {
+    fn modify(&self, openapi: &mut OpenApiSpec) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            for operation in item.operations.values_mut() {
+                let responses = &mut operation.responses.responses;
+                if path.starts_with("/v1/") {
+                    if operation.security.is_some() {
+                        responses
+                            .entry("401".to_string())
+                            .or_insert_with(|| error_response("Missing or wrong API key").into());
+                    }
+                    if operation.request_body.is_some() {
+                        responses
+                            .entry("413".to_string())
+                            .or_insert_with(|| error_response("Request body too large").into());
+                    }
+                    responses
+                        .entry("429".to_string())
+                        .or_insert_with(|| rate_limited_response().into());
+                }
+                for (status, response) in responses.iter_mut() {
+                    if let RefOr::T(response) = response {
+                        if !status.starts_with('2') && response.content.is_empty() {
+                            response
+                                .content
+                                .insert(ERROR_CONTENT_TYPE.to_string(), error_content());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn error_content() -> Content {
+    Content::new(
+        ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .description(Some(
+                "Error message, with its causes separated by colons. Internal errors only \
+                 name their kind.",
+            )),
+    )
+}
+
+fn error_response(description: &str) -> Response {
+    ResponseBuilder::new()
+        .description(description)
+        .content(ERROR_CONTENT_TYPE, error_content())
+        .build()
+}
+
+fn rate_limited_response() -> Response {
+    ResponseBuilder::new()
+        .description("Rate limit exceeded")
+        .content(ERROR_CONTENT_TYPE, error_content())
+        .header(
+            "Retry-After",
+            HeaderBuilder::new()
+                .schema(ObjectBuilder::new().schema_type(SchemaType::Integer))
+                .description(Some("Seconds to wait before retrying"))
+                .build(),
+        )
+        .build()
+}
+
+/// Serve the OpenAPI specification of the REST API.
+#[utoipa::path(
+    get,
+    path = "/v1/openapi.json",
+    responses(
+        (status = 200, description = "OpenAPI specification of the REST API", content_type = "application/json"),
+    )
+)]
+pub(crate) async fn get_openapi() -> Json<&'static OpenApiSpec> {
+    Json(&*SPEC)
+}
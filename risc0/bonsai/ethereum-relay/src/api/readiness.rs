@@ -0,0 +1,66 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Readiness of the relayer, e.g. for the readiness probe of an orchestrator
+//! or the health check of a load balancer.
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::state::ApiState;
+use crate::storage::Storage;
+
+/// Readiness of the relayer to take in and relay callback requests.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct Readiness {
+    /// Whether the relayer is neither draining nor held back by the daily
+    /// spend limit on any chain.
+    pub ready: bool,
+    /// Whether the relayer is draining, taking in no new callback request.
+    pub draining: bool,
+    /// Chains whose relay transactions are held back, the relayer wallet
+    /// having reached its daily spend limit on them.
+    pub spend_limited: Vec<u64>,
+}
+
+/// Report whether the relayer is ready, with status 503 while it drains or
+/// is held back by the daily spend limit on a chain.
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, description = "The relayer is ready", body = Readiness),
+        (status = 503, description = "The relayer is draining or held back by the daily spend limit", body = Readiness),
+    )
+)]
+pub(crate) async fn get_ready<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+) -> (StatusCode, Json<Readiness>) {
+    let draining = s.intake.is_draining();
+    let spend_limited = s.spend_limit.limited_chain_ids();
+    let ready = !draining && spend_limited.is_empty();
+    let status = match ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (
+        status,
+        Json(Readiness {
+            ready,
+            draining,
+            spend_limited,
+        }),
+    )
+}
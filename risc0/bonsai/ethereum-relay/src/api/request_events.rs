@@ -0,0 +1,313 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Streams of the state transitions of the callback requests, over a
+//! WebSocket or, for clients not asking for one, as server-sent events.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use ethers::types::H256;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::{
+    sync::{broadcast, Notify},
+    task::JoinHandle,
+};
+use utoipa::ToSchema;
+
+use super::{callback_request::CallbackRequestState, state::ApiState, Result};
+use crate::{
+    events,
+    storage::{
+        transitions::{PendingTransitions, StateTransition},
+        Storage,
+    },
+};
+
+/// Time to wait for a client to accept an event before closing its stream.
+const CLIENT_SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A state transition of callback requests, as streamed to the clients.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct CallbackRequestEvent {
+    /// IDs of the callback requests entering the state, which share a Bonsai
+    /// session.
+    pub request_ids: Vec<String>,
+    /// Bonsai session proving the requests.
+    pub session_id: String,
+    pub state: CallbackRequestState,
+    /// Transaction that invoked the callbacks, once completed on chain.
+    #[schema(value_type = Option<String>)]
+    pub tx_hash: Option<H256>,
+    /// Unix time, in milliseconds, of the transition.
+    pub timestamp: u64,
+    /// Number of earlier transitions dropped because the client was too slow
+    /// to receive them. Terminal transitions are only dropped past 256
+    /// pending ones.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub dropped: u64,
+}
+
+fn is_zero(count: &u64) -> bool {
+    *count == 0
+}
+
+impl CallbackRequestEvent {
+    fn new(transition: StateTransition, dropped: u64) -> Self {
+        Self {
+            request_ids: transition.request_ids,
+            session_id: transition.session_id,
+            state: CallbackRequestState::from(&transition.state),
+            tx_hash: transition.state.tx_hash(),
+            timestamp: transition.timestamp,
+            dropped,
+        }
+    }
+}
+
+/// Transitions followed by a client: all of them, or the ones of the session
+/// of a request until it reaches a terminal state.
+enum Follow<S> {
+    All,
+    Request {
+        storage: S,
+        request_id: String,
+        session_id: String,
+    },
+}
+
+/// Transitions waiting to be sent to a client, filled by a task of their own
+/// so that the client never holds back the relayer, nor the other clients.
+struct Subscription {
+    pending: Arc<Mutex<PendingTransitions>>,
+    notify: Arc<Notify>,
+    forwarder: JoinHandle<()>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
+
+impl Subscription {
+    fn new<S: Storage + Sync + Send + Clone + 'static>(
+        mut receiver: broadcast::Receiver<StateTransition>,
+        initial: Option<StateTransition>,
+        follow: Follow<S>,
+    ) -> Self {
+        let pending = Arc::new(Mutex::new(PendingTransitions::default()));
+        let notify = Arc::new(Notify::new());
+        if let Some(initial) = initial {
+            lock(&pending).push(initial);
+        }
+        let forwarder = tokio::spawn({
+            let (pending, notify) = (pending.clone(), notify.clone());
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(transition) => {
+                            let followed = match &follow {
+                                Follow::All => true,
+                                Follow::Request { session_id, .. } => {
+                                    transition.session_id == *session_id
+                                }
+                            };
+                            if followed {
+                                lock(&pending).push(transition);
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(count)) => {
+                            lock(&pending).record_dropped(count);
+                            // The terminal transition of the request may be
+                            // among the missed ones.
+                            if let Follow::Request {
+                                storage,
+                                request_id,
+                                ..
+                            } = &follow
+                            {
+                                if let Ok((session_id, state)) =
+                                    storage.get_request_state(request_id).await
+                                {
+                                    if state.is_terminal() {
+                                        lock(&pending).push(StateTransition {
+                                            session_id: session_id.uuid,
+                                            request_ids: vec![request_id.clone()],
+                                            state,
+                                            timestamp: events::now(),
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            // Wake the stream up to end it.
+                            notify.notify_one();
+                            return;
+                        }
+                    }
+                    notify.notify_one();
+                }
+            }
+        });
+        Self {
+            pending,
+            notify,
+            forwarder,
+        }
+    }
+
+    /// Stream the pending transitions, ending after a terminal one if
+    /// `until_terminal` is set.
+    fn into_stream(self, until_terminal: bool) -> impl Stream<Item = CallbackRequestEvent> {
+        futures::stream::unfold(Some(self), move |subscription| async move {
+            let subscription = subscription?;
+            loop {
+                let next = lock(&subscription.pending).pop();
+                if let Some((transition, dropped)) = next {
+                    let done = until_terminal && transition.state.is_terminal();
+                    let event = CallbackRequestEvent::new(transition, dropped);
+                    return Some((event, (!done).then_some(subscription)));
+                }
+                if subscription.forwarder.is_finished() {
+                    return None;
+                }
+                subscription.notify.notified().await;
+            }
+        })
+    }
+}
+
+fn lock(pending: &Mutex<PendingTransitions>) -> std::sync::MutexGuard<'_, PendingTransitions> {
+    pending
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Send the events over a WebSocket if the client asked for one, or as
+/// server-sent events otherwise.
+fn respond(
+    websocket: Option<WebSocketUpgrade>,
+    events: impl Stream<Item = CallbackRequestEvent> + Send + 'static,
+) -> Response {
+    match websocket {
+        Some(websocket) => websocket
+            .on_upgrade(|socket| send_over_websocket(socket, events))
+            .into_response(),
+        None => Sse::new(events.map(|event| Event::default().json_data(event)))
+            .keep_alive(KeepAlive::default())
+            .into_response(),
+    }
+}
+
+/// Send the events as JSON text messages, then close the socket once they
+/// end. A client that does not accept an event within [CLIENT_SEND_TIMEOUT]
+/// is disconnected.
+async fn send_over_websocket(
+    mut socket: WebSocket,
+    events: impl Stream<Item = CallbackRequestEvent> + Send,
+) {
+    futures::pin_mut!(events);
+    while let Some(event) = events.next().await {
+        let Ok(text) = serde_json::to_string(&event) else {
+            break;
+        };
+        match tokio::time::timeout(CLIENT_SEND_TIMEOUT, socket.send(Message::Text(text))).await {
+            Ok(Ok(())) => {}
+            // The client is gone or too slow.
+            Ok(Err(_)) | Err(_) => return,
+        }
+    }
+    let _ = tokio::time::timeout(CLIENT_SEND_TIMEOUT, socket.close()).await;
+}
+
+/// Stream the state transitions of a CallbackRequest, by the request ID
+/// returned when publishing it.
+///
+/// The state of the request is sent first, then each transition of the
+/// requests sharing its Bonsai session, until a terminal state: `failed`,
+/// `completed_onchain`, `expired` or `skipped`. Events are sent over a WebSocket if the
+/// request asks for an upgrade, or as server-sent events otherwise.
+#[utoipa::path(
+    get,
+    path = "/v1/callbacks/{request_id}/events",
+    params(("request_id" = String, Path, description = "ID of the callback request")),
+    security(("api_key" = [])),
+    responses(
+        (status = 101, description = "Transitions streamed over a WebSocket"),
+        (status = 200, description = "Transitions streamed as server-sent events", body = CallbackRequestEvent),
+        (status = 404, description = "Callback request not found"),
+    )
+)]
+pub(crate) async fn get_callback_request_events<S: Storage + Sync + Send + Clone + 'static>(
+    State(s): State<ApiState<S>>,
+    Path(request_id): Path<String>,
+    websocket: Option<WebSocketUpgrade>,
+) -> Result<Response> {
+    // Subscribe before reading the state, not to miss a transition between.
+    let receiver = s.storage.subscribe_transitions();
+    let (session_id, state) = s.storage.get_request_state(&request_id).await?;
+    let initial = StateTransition {
+        session_id: session_id.uuid.clone(),
+        request_ids: vec![request_id.clone()],
+        state,
+        timestamp: events::now(),
+    };
+    let subscription = Subscription::new(
+        receiver,
+        Some(initial),
+        Follow::Request {
+            storage: s.storage,
+            request_id,
+            session_id: session_id.uuid,
+        },
+    );
+    Ok(respond(websocket, subscription.into_stream(true)))
+}
+
+/// Stream the state transitions of all CallbackRequests from now on, e.g. for
+/// dashboards.
+///
+/// Events are sent over a WebSocket if the request asks for an upgrade, or as
+/// server-sent events otherwise.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    security(("api_key" = [])),
+    responses(
+        (status = 101, description = "Transitions streamed over a WebSocket"),
+        (status = 200, description = "Transitions streamed as server-sent events", body = CallbackRequestEvent),
+    )
+)]
+pub(crate) async fn get_events<S: Storage + Sync + Send + Clone + 'static>(
+    State(s): State<ApiState<S>>,
+    websocket: Option<WebSocketUpgrade>,
+) -> Response {
+    let subscription = Subscription::new::<S>(s.storage.subscribe_transitions(), None, Follow::All);
+    respond(websocket, subscription.into_stream(false))
+}
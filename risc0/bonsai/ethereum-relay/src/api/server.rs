@@ -12,55 +12,213 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    net::{IpAddr, SocketAddr, TcpListener},
+    time::Duration,
+};
+
 use anyhow::Context;
-use axum::{extract::DefaultBodyLimit, middleware::from_fn, routing::post, Router};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, DefaultBodyLimit},
+    http::Request,
+    middleware::{from_fn, from_fn_with_state},
+    routing::{get, post},
+    Router,
+};
+use hyper::{
+    server::conn::Http,
+    service::{service_fn, Service},
+};
 use tower_http::trace::{DefaultOnRequest, TraceLayer};
-use tracing::Level;
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
+use tracing::{debug, warn, Level};
+use utoipa_swagger_ui::{Config, SwaggerUi};
 
 use crate::{
     api::{
+        admin::{
+            authorize_admin, get_drain, get_spend_limit, post_drain, post_resume, post_spend_limit,
+            post_spend_limit_resume, post_upload_images,
+        },
         auth::authorize,
-        callback_request::{__path_post_callback_request, post_callback_request},
-        routes::CALLBACK_ROUTE,
+        callback_request::{
+            get_callback_request_status, get_callback_requests, post_callback_request,
+            post_retry_callback_request,
+        },
+        limits::enforce_limits,
+        metrics::get_metrics,
+        openapi::get_openapi,
+        readiness::get_ready,
+        request_events::{get_callback_request_events, get_events},
+        routes::{
+            ADMIN_DRAIN_ROUTE, ADMIN_RESUME_ROUTE, ADMIN_SPEND_LIMIT_RESUME_ROUTE,
+            ADMIN_SPEND_LIMIT_ROUTE, ADMIN_UPLOAD_IMAGES_ROUTE, CALLBACK_EVENTS_ROUTE,
+            CALLBACK_RETRY_ROUTE, CALLBACK_ROUTE, CALLBACK_STATUS_ROUTE, EVENTS_ROUTE,
+            METRICS_ROUTE, OPENAPI_ROUTE, READY_ROUTE, SUBMISSION_ROUTE, SWAGGER_UI_ROUTE,
+        },
         state::ApiState,
+        submission::post_submission,
+        tls::{ReloadableTls, RestApiTls},
     },
-    sdk::client::CallbackRequest,
     storage::Storage,
 };
 
 pub(crate) fn app<S: Storage + Sync + Send + Clone + 'static>(state: ApiState<S>) -> Router {
-    #[derive(OpenApi)]
-    #[openapi(paths(post_callback_request,), components(schemas(CallbackRequest)))]
-    struct ApiDoc;
-
-    Router::new()
-        .route(CALLBACK_ROUTE, post(post_callback_request))
+    // In submission-only mode, proofs are submitted pre-generated instead of
+    // being requested from Bonsai, and failed requests can't be retried.
+    let router = match state.submissions {
+        Some(_) => Router::new()
+            .route(SUBMISSION_ROUTE, post(post_submission))
+            .route(CALLBACK_ROUTE, get(get_callback_requests)),
+        None => Router::new()
+            .route(
+                CALLBACK_ROUTE,
+                post(post_callback_request).get(get_callback_requests),
+            )
+            .route(CALLBACK_RETRY_ROUTE, post(post_retry_callback_request)),
+    };
+    // The admin routes are only served with an admin API key to check.
+    let admin_router = match state.admin_api_key.clone() {
+        Some(admin_api_key) => Router::new()
+            .route(ADMIN_UPLOAD_IMAGES_ROUTE, post(post_upload_images))
+            .route(ADMIN_DRAIN_ROUTE, get(get_drain).post(post_drain))
+            .route(ADMIN_RESUME_ROUTE, post(post_resume))
+            .route(
+                ADMIN_SPEND_LIMIT_ROUTE,
+                get(get_spend_limit).post(post_spend_limit),
+            )
+            .route(
+                ADMIN_SPEND_LIMIT_RESUME_ROUTE,
+                post(post_spend_limit_resume),
+            )
+            .layer(from_fn_with_state(admin_api_key, authorize_admin)),
+        None => Router::new(),
+    };
+    // The Swagger UI reads the specification served on the OpenAPI route.
+    let docs_router: Router = match state.rest_api_docs {
+        true => SwaggerUi::new(SWAGGER_UI_ROUTE)
+            .config(Config::from(OPENAPI_ROUTE))
+            .into(),
+        false => Router::new(),
+    };
+    // The rate limit and the body size limit are shared by all v1 routes.
+    let limits = state.limits.clone();
+    router
+        .route(CALLBACK_STATUS_ROUTE, get(get_callback_request_status))
+        .route(CALLBACK_EVENTS_ROUTE, get(get_callback_request_events))
+        .route(EVENTS_ROUTE, get(get_events))
         .layer(from_fn(authorize))
+        .merge(admin_router)
+        .route(OPENAPI_ROUTE, get(get_openapi))
+        .layer(DefaultBodyLimit::max(limits.max_body_size))
+        .layer(from_fn_with_state(limits, enforce_limits))
+        .route(METRICS_ROUTE, get(get_metrics))
+        .route(READY_ROUTE, get(get_ready))
         .with_state(state)
-        .layer(DefaultBodyLimit::max(256 * 1024 * 1024))
         .layer(TraceLayer::new_for_http().on_request(
             DefaultOnRequest::new().level(Level::TRACE), // make on_request less visible
         ))
-        .merge(
-            Router::new().merge(
-                SwaggerUi::new("/swagger-ui").url("/api-doc/openapi.json", ApiDoc::openapi()),
-            ),
-        )
+        .merge(docs_router)
+}
+
+/// Time for a client to complete the TLS handshake before its connection is
+/// dropped.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay before accepting connections again after failing to.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Socket the REST API is served on, bound on start so that a bad address, a
+/// port in use or an invalid certificate stops the relayer.
+pub(crate) struct ApiListener {
+    pub(crate) listener: TcpListener,
+    /// Set when the REST API is served over TLS.
+    pub(crate) tls: Option<ReloadableTls>,
+}
+
+impl ApiListener {
+    pub(crate) fn bind(bind: &str, port: &str, tls: Option<RestApiTls>) -> anyhow::Result<Self> {
+        // Parsed apart, as an IPv6 address holds colons.
+        let ip: IpAddr = bind
+            .parse()
+            .with_context(|| format!("invalid REST API address {bind}"))?;
+        let port: u16 = port
+            .parse()
+            .with_context(|| format!("invalid REST API port {port}"))?;
+        let address = SocketAddr::new(ip, port);
+        let tls = tls.map(ReloadableTls::load).transpose()?;
+        let listener = TcpListener::bind(address)
+            .with_context(|| format!("failed to bind the REST API to {address}"))?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, tls })
+    }
 }
 
 pub(crate) async fn serve<S: Storage + Sync + Send + Clone + 'static>(
     state: ApiState<S>,
-    port: String,
+    listener: ApiListener,
 ) -> anyhow::Result<()> {
-    let bind_address = &format!("0.0.0.0:{port}");
-    axum::Server::bind(
-        &bind_address
-            .parse()
-            .context("failed to parse bind address")?,
-    )
-    .serve(app(state).into_make_service())
-    .await
-    .context(format!("failed to serve API on {bind_address}"))
+    let address = listener.listener.local_addr()?;
+    let app = app(state);
+    match listener.tls {
+        None => axum::Server::from_tcp(listener.listener)?
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .context(format!("failed to serve API on {address}")),
+        Some(tls) => {
+            tokio::spawn(tls.clone().reload_on_sighup());
+            serve_tls(
+                tokio::net::TcpListener::from_std(listener.listener)?,
+                tls,
+                app,
+            )
+            .await;
+            Ok(())
+        }
+    }
+}
+
+/// Serve the app over TLS, with the certificate of `tls` at the time each
+/// connection is accepted, until aborted.
+async fn serve_tls(listener: tokio::net::TcpListener, tls: ReloadableTls, app: Router) {
+    loop {
+        let (stream, remote) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(err) => {
+                // e.g. out of file descriptors, until connections close.
+                warn!("Failed to accept a REST API connection: {err}");
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
+        let acceptor = tls.acceptor();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        debug!(%remote, "REST API TLS handshake failed: {err}");
+                        return;
+                    }
+                    Err(_) => {
+                        debug!(%remote, "REST API TLS handshake timed out");
+                        return;
+                    }
+                };
+            // Rate limits fall back on the address of the client, as for
+            // plaintext connections.
+            let service = service_fn(move |mut request: Request<Body>| {
+                request.extensions_mut().insert(ConnectInfo(remote));
+                app.clone().call(request)
+            });
+            if let Err(err) = Http::new()
+                .serve_connection(stream, service)
+                .with_upgrades()
+                .await
+            {
+                debug!(%remote, "REST API connection failed: {err}");
+            }
+        });
+    }
 }
@@ -12,11 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use tokio::sync::Notify;
+use ethers::types::{Address, H256};
+use tokio::sync::{mpsc, watch, Notify};
 
-use crate::storage::Storage;
+use super::{limits::ApiLimits, Error};
+use crate::{
+    bonsai_transport::BonsaiTransport, events::EventSender, images::GuestImage, intake::Intake,
+    spend::SpendLimit, storage::Storage, uploader::completed_proofs::complete_proof::CompleteProof,
+    EthersClientConfig,
+};
 
 #[derive(Clone)]
 pub(crate) struct ApiState<S>
@@ -24,6 +30,104 @@ where
     S: Storage + Sync + Send + Clone,
 {
     pub(crate) bonsai_url: String,
+    /// Relay contracts served by the relayer, on all its chains, one of which
+    /// each callback request targets.
+    pub(crate) relays: Vec<RelayContract>,
     pub(crate) storage: S,
     pub(crate) notifier: Arc<Notify>,
+    /// Notifiers of the complete proof managers, by chain, picking the failed
+    /// requests retried from relaying.
+    pub(crate) complete_proof_notifiers: HashMap<u64, Arc<Notify>>,
+    pub(crate) events: Option<EventSender>,
+    /// Set in submission-only mode, where proofs are submitted pre-generated
+    /// rather than requested from Bonsai.
+    pub(crate) submissions: Option<SubmissionConfig>,
+    /// Bonsai client of the relayer, uploading the guest images.
+    pub(crate) bonsai_client: Arc<dyn BonsaiTransport>,
+    /// Guest images re-uploaded to Bonsai on demand.
+    pub(crate) images: Arc<[GuestImage]>,
+    /// Key of the admin routes, which are only served if set.
+    pub(crate) admin_api_key: Option<String>,
+    /// Rate limit and body size limit of the v1 routes.
+    pub(crate) limits: ApiLimits,
+    /// Toggle to serve the Swagger UI of the REST API.
+    pub(crate) rest_api_docs: bool,
+    /// Intake of the new callback requests, drained from the admin routes.
+    pub(crate) intake: Intake,
+    /// Daily spend limit of the relayer wallet, changed from the admin
+    /// routes.
+    pub(crate) spend_limit: SpendLimit,
+}
+
+/// How the proofs submitted in submission-only mode are checked, and where
+/// they are sent for relaying.
+#[derive(Clone)]
+pub(crate) struct SubmissionConfig {
+    /// Image IDs accepted for submission, as reloaded. If empty, all image
+    /// IDs are accepted.
+    pub(crate) image_ids: watch::Receiver<Vec<H256>>,
+    pub(crate) dev_mode: bool,
+    pub(crate) skip_proof_validation: bool,
+    /// Chains the submitted proofs are relayed on.
+    pub(crate) chains: Vec<SubmissionChain>,
+}
+
+/// Where the proofs submitted for the relay contracts of a chain are sent for
+/// relaying.
+#[derive(Clone)]
+pub(crate) struct SubmissionChain {
+    pub(crate) client_config: EthersClientConfig,
+    /// Queue of the complete proof manager of the chain, batching the
+    /// callbacks on chain.
+    pub(crate) sender: mpsc::UnboundedSender<CompleteProof>,
+}
+
+impl SubmissionConfig {
+    /// Returns where the proofs submitted on the given chain are sent.
+    pub(crate) fn chain(&self, chain_id: u64) -> Option<&SubmissionChain> {
+        self.chains
+            .iter()
+            .find(|chain| chain.client_config.eth_chain_id == chain_id)
+    }
+}
+
+/// A relay contract served by the relayer, on one of its chains.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RelayContract {
+    pub(crate) chain_id: u64,
+    pub(crate) address: Address,
+}
+
+/// Returns the relay contract a REST request targets: the one at the
+/// `requested` address on the `requested_chain`, which the relayer must serve.
+/// Either can be omitted if it leaves a single relay contract served by the
+/// relayer.
+pub(crate) fn select_relay(
+    relays: &[RelayContract],
+    requested_chain: Option<u64>,
+    requested: Option<Address>,
+) -> Result<RelayContract, Error> {
+    let candidates: Vec<&RelayContract> = relays
+        .iter()
+        .filter(|relay| requested_chain.map_or(true, |chain_id| relay.chain_id == chain_id))
+        .filter(|relay| requested.map_or(true, |address| relay.address == address))
+        .collect();
+    match (candidates.as_slice(), requested, requested_chain) {
+        ([relay], _, _) => Ok(**relay),
+        ([], Some(address), None) => Err(Error::InvalidRelay(format!(
+            "{address:?} is not served by this relayer"
+        ))),
+        ([], Some(address), Some(chain_id)) => Err(Error::InvalidRelay(format!(
+            "{address:?} on chain {chain_id} is not served by this relayer"
+        ))),
+        ([], None, Some(chain_id)) => Err(Error::InvalidRelay(format!(
+            "chain {chain_id} is not served by this relayer"
+        ))),
+        (_, None, _) => Err(Error::InvalidRelay(
+            "the relayer serves several relay contracts, select one with relay_address".to_string(),
+        )),
+        (_, Some(address), _) => Err(Error::InvalidRelay(format!(
+            "{address:?} is served on several chains, select one with chain_id"
+        ))),
+    }
 }
@@ -0,0 +1,241 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use axum::extract::State;
+use bonsai_ethereum_contracts::{
+    i_bonsai_relay::{CallbackAuthorization, CallbackRequestFilter},
+    IBonsaiRelay,
+};
+use bonsai_sdk::alpha::SessionId;
+use ethers::{types::H256, utils::keccak256};
+use risc0_zkvm::Receipt;
+use tracing::info;
+
+use super::{
+    bincode::Bincode,
+    state::{select_relay, ApiState},
+    Error, Result,
+};
+use crate::{
+    events::{self, RelayerEvent},
+    metrics::Metrics,
+    sdk::client::{ProofSubmission, SubmittedProof},
+    storage::{ProofRequestInformation, Storage},
+    uploader::completed_proofs::{
+        complete_proof::{build_callback, CompleteProof},
+        snark::{proof_to_calldata, validate_snark_proof},
+    },
+};
+
+/// Submit a pre-generated proof to the Relayer, in submission-only mode.
+///
+/// Return status 200 on success, with the ID of the callback request, or
+/// status 422 with the failed check if the proof is rejected. Submissions are
+/// refused with status 503 while the relayer drains, and 429 while too many
+/// requests are in flight.
+#[utoipa::path(
+    post,
+    path = "/v1/submissions",
+    request_body(content = ProofSubmission, content_type = "application/octet-stream", description = "Bincode serialized proof submission"),
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "Proof submitted successfully", body = String),
+        (status = 400, description = "Bad request error"),
+        (status = 422, description = "Proof rejected"),
+        (status = 500, description = "Internal server error"),
+        (status = 503, description = "Relayer draining for maintenance"),
+    )
+)]
+pub(crate) async fn post_submission<S: Storage + Sync + Send + Clone>(
+    State(s): State<ApiState<S>>,
+    Bincode(submission): Bincode<ProofSubmission>,
+) -> Result<String, Error> {
+    let config = s
+        .submissions
+        .context("the relayer is not in submission-only mode")?;
+    s.intake.admit(&s.storage).await?;
+    let relay = select_relay(&s.relays, submission.chain_id, submission.relay_address)?;
+    let relay_contract_address = relay.address;
+    let chain = config
+        .chain(relay.chain_id)
+        .context("the chain of the relay contract has no complete proof manager")?;
+    let image_ids = config.image_ids.borrow().clone();
+    let auth = match validate_submission(
+        &submission,
+        &image_ids,
+        config.dev_mode,
+        config.skip_proof_validation,
+    ) {
+        Ok(auth) => auth,
+        Err(err) => {
+            Metrics::global().submissions_rejected.inc();
+            return Err(err);
+        }
+    };
+
+    // A callback failing its authorization check reverts the whole batch, so
+    // check it against the verifier of the relay contract before queuing it.
+    let ethers_client = Arc::new(chain.client_config.get_client().await?);
+    let relay = IBonsaiRelay::new(relay_contract_address, ethers_client);
+    let authorized = relay
+        .callback_is_authorized(
+            submission.image_id,
+            submission.journal.clone().into(),
+            auth.clone(),
+        )
+        .call()
+        .await
+        .context("failed to check the callback authorization")?;
+    if !authorized {
+        Metrics::global().submissions_rejected.inc();
+        return Err(Error::SubmissionRejected(
+            "the relay contract does not authorize the callback with this proof".to_string(),
+        ));
+    }
+
+    let request_id = submission_id(&submission);
+    let callback_request = CallbackRequestFilter {
+        account: submission.callback_contract,
+        image_id: submission.image_id,
+        input: Default::default(),
+        callback_contract: submission.callback_contract,
+        function_selector: submission.function_selector,
+        gas_limit: submission.gas_limit,
+    };
+    let proof_request_id = SessionId::new(request_id.clone());
+    s.storage
+        .add_submitted_proof_request(ProofRequestInformation {
+            proof_request_id: proof_request_id.clone(),
+            chain_id: relay.chain_id,
+            relay_contract_address,
+            callback_proof_request_event: callback_request.clone(),
+            created_at: Instant::now(),
+            attached_requests: Vec::new(),
+            deadline: None,
+        })
+        .await?;
+    events::emit(
+        &s.events,
+        RelayerEvent::RequestReceived {
+            timestamp: events::now(),
+            relay_address: relay_contract_address,
+            image_id: H256(submission.image_id),
+            callback_contract: submission.callback_contract,
+        },
+    );
+    events::emit(
+        &s.events,
+        RelayerEvent::ProofReady {
+            timestamp: events::now(),
+            session_id: request_id.clone(),
+        },
+    );
+
+    chain
+        .sender
+        .send(CompleteProof {
+            bonsai_proof_id: proof_request_id,
+            relay_contract_address,
+            ethereum_callbacks: vec![build_callback(&callback_request, &submission.journal, auth)],
+            journal: submission.journal.clone(),
+        })
+        .map_err(|_| anyhow!("the complete proof manager has stopped"))?;
+    info!(
+        chain_id = relay.chain_id,
+        relay = ?relay_contract_address,
+        %request_id,
+        image_id = hex::encode(submission.image_id),
+        "proof submitted"
+    );
+
+    Ok(request_id)
+}
+
+/// Checks a submitted proof without going on chain, returning the
+/// authorization of its callback.
+pub(crate) fn validate_submission(
+    submission: &ProofSubmission,
+    image_ids: &[H256],
+    dev_mode: bool,
+    skip_proof_validation: bool,
+) -> Result<CallbackAuthorization, Error> {
+    let image_id = H256(submission.image_id);
+    if !image_ids.is_empty() && !image_ids.contains(&image_id) {
+        return Err(rejected(format!("image ID {image_id:?} is not allowed")));
+    }
+
+    let seal = match &submission.proof {
+        SubmittedProof::Snark(snark_proof) => {
+            if !dev_mode && !skip_proof_validation {
+                validate_snark_proof(snark_proof)
+                    .map_err(|err| rejected(format!("invalid SNARK proof: {err:#}")))?;
+            }
+            match dev_mode {
+                true => vec![],
+                false => proof_to_calldata(snark_proof)
+                    .map_err(|err| rejected(format!("invalid SNARK proof: {err:#}")))?
+                    .to_vec(),
+            }
+        }
+        SubmittedProof::Receipt(receipt) => {
+            if !dev_mode {
+                return Err(rejected(
+                    "receipts are only accepted in dev mode, submit a SNARK proof instead",
+                ));
+            }
+            let receipt: Receipt = bincode::deserialize(receipt)
+                .map_err(|err| rejected(format!("invalid receipt: {err}")))?;
+            if receipt.journal != submission.journal {
+                return Err(rejected("the journal does not match the receipt"));
+            }
+            receipt
+                .verify(submission.image_id)
+                .map_err(|err| rejected(format!("the receipt does not verify: {err}")))?;
+            vec![]
+        }
+    };
+
+    Ok(CallbackAuthorization {
+        seal: seal.into(),
+        post_state_digest: submission.post_state_digest,
+    })
+}
+
+fn rejected(check: impl Into<String>) -> Error {
+    Error::SubmissionRejected(check.into())
+}
+
+/// Returns a unique ID for the callback request of a submission.
+fn submission_id(submission: &ProofSubmission) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let digest = keccak256(
+        [
+            submission.image_id.as_slice(),
+            &submission.journal,
+            submission.callback_contract.as_bytes(),
+            &nanos.to_be_bytes(),
+        ]
+        .concat(),
+    );
+    hex::encode(&digest[..16])
+}
@@ -0,0 +1,161 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TLS termination of the REST API, with certificates reloaded on SIGHUP.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use anyhow::{bail, Context, Result};
+use tokio_rustls::{
+    rustls::{Certificate, PrivateKey, ServerConfig},
+    TlsAcceptor,
+};
+use tracing::{info, warn};
+
+/// PEM files of the certificate chain and private key the REST API is served
+/// with over TLS.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RestApiTls {
+    /// Certificate chain, leaf certificate first.
+    pub cert: PathBuf,
+    /// Private key of the leaf certificate, in PKCS#8, PKCS#1 or SEC1.
+    pub key: PathBuf,
+}
+
+/// TLS configuration of the REST API, swapped when its files are reloaded.
+/// Connections keep the configuration they were accepted with.
+#[derive(Clone)]
+pub(crate) struct ReloadableTls {
+    files: RestApiTls,
+    config: Arc<RwLock<Arc<ServerConfig>>>,
+}
+
+impl ReloadableTls {
+    pub(crate) fn load(files: RestApiTls) -> Result<Self> {
+        let config = server_config(&files)?;
+        Ok(Self {
+            files,
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        })
+    }
+
+    /// Read the files again, keeping the current configuration if they are
+    /// invalid.
+    pub(crate) fn reload(&self) -> Result<()> {
+        let config = server_config(&self.files)?;
+        *self
+            .config
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Arc::new(config);
+        info!(
+            cert = %self.files.cert.display(),
+            "Reloaded the REST API TLS certificate"
+        );
+        Ok(())
+    }
+
+    pub(crate) fn acceptor(&self) -> TlsAcceptor {
+        let config = self
+            .config
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+        TlsAcceptor::from(config)
+    }
+
+    /// Reload the files on each SIGHUP, e.g. after a certificate renewal.
+    #[cfg(unix)]
+    pub(crate) async fn reload_on_sighup(self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "Failed to listen for SIGHUP, not reloading the REST API TLS certificate"
+                );
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            if let Err(error) = self.reload() {
+                tracing::error!(
+                    "Failed to reload the REST API TLS certificate, keeping the current one: \
+                     {error:#}"
+                );
+            }
+        }
+    }
+
+    /// Certificates are only reloaded on SIGHUP, which doesn't exist here.
+    #[cfg(not(unix))]
+    pub(crate) async fn reload_on_sighup(self) {
+        warn!("SIGHUP is not supported, not reloading the REST API TLS certificate");
+    }
+}
+
+fn server_config(files: &RestApiTls) -> Result<ServerConfig> {
+    let certs = rustls_pemfile::certs(&mut open(&files.cert)?).with_context(|| {
+        format!(
+            "failed to read the certificates of {}",
+            files.cert.display()
+        )
+    })?;
+    if certs.is_empty() {
+        bail!("no certificate found in {}", files.cert.display());
+    }
+    let key = read_private_key(&files.key)?;
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs.into_iter().map(Certificate).collect(), key)
+        .with_context(|| {
+            format!(
+                "invalid TLS certificate {} or key {}",
+                files.cert.display(),
+                files.key.display()
+            )
+        })?;
+    // WebSockets are upgraded from HTTP/1.1 connections.
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn read_private_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = open(path)?;
+    loop {
+        let item = rustls_pemfile::read_one(&mut reader)
+            .with_context(|| format!("failed to read the private key of {}", path.display()))?;
+        match item {
+            Some(
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key),
+            ) => return Ok(PrivateKey(key)),
+            Some(_) => continue,
+            None => bail!("no private key found in {}", path.display()),
+        }
+    }
+}
+
+fn open(path: &Path) -> Result<BufReader<File>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(BufReader::new(file))
+}
@@ -0,0 +1,139 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use async_trait::async_trait;
+use bonsai_sdk::alpha::{responses::SessionStatusRes, SdkErr, SessionId};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Channel, Endpoint},
+    Code, Request, Status,
+};
+
+use super::BonsaiTransport;
+use crate::RelayError;
+
+pub(crate) mod proto {
+    tonic::include_proto!("bonsai");
+}
+
+use proto::{
+    bonsai_client::BonsaiClient, CreateSessionRequest, GetSessionRequest, PutImageRequest,
+};
+
+/// Header carrying the Bonsai API key, as in the REST API.
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Sets the Bonsai API key of each request.
+#[derive(Clone)]
+struct ApiKey(MetadataValue<Ascii>);
+
+impl Interceptor for ApiKey {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request
+            .metadata_mut()
+            .insert(API_KEY_HEADER, self.0.clone());
+        Ok(request)
+    }
+}
+
+/// Client of the gRPC API of Bonsai, balancing the requests over the given
+/// instances.
+#[derive(Clone)]
+pub struct GrpcBonsaiClient {
+    client: BonsaiClient<InterceptedService<Channel, ApiKey>>,
+}
+
+impl GrpcBonsaiClient {
+    /// Connects to the instances at `urls`, e.g. `http://localhost:8081`,
+    /// lazily: unreachable instances fail the requests rather than the
+    /// construction.
+    pub fn new(urls: &[String], api_key: &str) -> Result<Self, RelayError> {
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                Endpoint::from_shared(url.clone()).map_err(|err| {
+                    RelayError::ConfigError(format!("invalid Bonsai gRPC URL {url}: {err}"))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if endpoints.is_empty() {
+            return Err(RelayError::ConfigError(
+                "no Bonsai gRPC URL given".to_string(),
+            ));
+        }
+        let api_key = MetadataValue::try_from(api_key)
+            .map_err(|err| RelayError::ConfigError(format!("invalid Bonsai API key: {err}")))?;
+        let channel = Channel::balance_list(endpoints.into_iter());
+        Ok(Self {
+            client: BonsaiClient::with_interceptor(channel, ApiKey(api_key)),
+        })
+    }
+}
+
+/// Returns the [SdkErr] the REST API answers with in the same case, keeping
+/// the message of Bonsai, e.g. to tell unknown images apart.
+fn sdk_err(status: Status) -> SdkErr {
+    SdkErr::InternalServerErr(status.message().to_string())
+}
+
+#[async_trait]
+impl BonsaiTransport for GrpcBonsaiClient {
+    async fn put_image(&self, image_id: String, image: Vec<u8>) -> Result<(), SdkErr> {
+        match self
+            .client
+            .clone()
+            .put_image(PutImageRequest { image_id, image })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(status) if status.code() == Code::AlreadyExists => Err(SdkErr::ImageIdExists),
+            Err(status) => Err(sdk_err(status)),
+        }
+    }
+
+    async fn create_session(&self, image_id: String, input: Vec<u8>) -> Result<SessionId, SdkErr> {
+        let response = self
+            .client
+            .clone()
+            .create_session(CreateSessionRequest { image_id, input })
+            .await
+            .map_err(sdk_err)?;
+        Ok(SessionId::new(response.into_inner().session_id))
+    }
+
+    async fn get_session(&self, session: SessionId) -> Result<SessionStatusRes, SdkErr> {
+        let response = match self
+            .client
+            .clone()
+            .get_session(GetSessionRequest {
+                session_id: session.uuid.clone(),
+            })
+            .await
+        {
+            Ok(response) => response.into_inner(),
+            Err(status) if status.code() == Code::NotFound => {
+                return Err(SdkErr::SessionNotFound(session.uuid))
+            }
+            Err(status) => return Err(sdk_err(status)),
+        };
+        Ok(SessionStatusRes {
+            status: response.status,
+            receipt_url: response.receipt_url,
+            error_msg: response.error_msg,
+            state: response.state,
+            stats: None,
+        })
+    }
+}
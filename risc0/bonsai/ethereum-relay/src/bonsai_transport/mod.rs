@@ -0,0 +1,63 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transports of the requests of the relayer to Bonsai: its REST API, or the
+//! gRPC API some deployments offer for a lower latency, built with the `grpc`
+//! feature.
+
+#[cfg(feature = "grpc")]
+pub(crate) mod grpc;
+
+use async_trait::async_trait;
+use bonsai_sdk::{
+    alpha::{responses::SessionStatusRes, SdkErr, SessionId},
+    alpha_async::{create_session, put_input, RoundRobinBonsaiClient},
+};
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcBonsaiClient;
+
+/// Requests of the relayer to Bonsai, to upload the guest images, prove the
+/// callback requests and poll their sessions.
+///
+/// Errors follow the REST API: [SdkErr::ImageIdExists] for known images,
+/// [SdkErr::SessionNotFound] for unknown sessions and
+/// [SdkErr::InternalServerErr] with the message of Bonsai otherwise.
+#[async_trait]
+pub trait BonsaiTransport: Send + Sync {
+    /// Upload the image of a guest, by its hex-encoded image ID.
+    async fn put_image(&self, image_id: String, image: Vec<u8>) -> Result<(), SdkErr>;
+
+    /// Start a session proving the image with the input.
+    async fn create_session(&self, image_id: String, input: Vec<u8>) -> Result<SessionId, SdkErr>;
+
+    /// Fetch the status of a session.
+    async fn get_session(&self, session: SessionId) -> Result<SessionStatusRes, SdkErr>;
+}
+
+#[async_trait]
+impl BonsaiTransport for RoundRobinBonsaiClient {
+    async fn put_image(&self, image_id: String, image: Vec<u8>) -> Result<(), SdkErr> {
+        RoundRobinBonsaiClient::put_image(self, image_id, image).await
+    }
+
+    /// Upload the input, then create the session, on the primary instance.
+    async fn create_session(&self, image_id: String, input: Vec<u8>) -> Result<SessionId, SdkErr> {
+        let input_id = put_input(self.primary().clone(), input).await?;
+        create_session(self.primary().clone(), image_id, input_id).await
+    }
+
+    async fn get_session(&self, session: SessionId) -> Result<SessionStatusRes, SdkErr> {
+        self.session_status(session).await
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Chains served by a relayer in addition to the one it is run with.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ethers::types::{Address, U256};
+use serde::Deserialize;
+
+use crate::{
+    remote_signer::RemoteSignerConfig,
+    uploader::completed_proofs::{
+        fee_cap::FeeCap,
+        private_relay::{PrivateRelay, DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS},
+        relay::SafeConfig,
+    },
+    EthersClientConfig, RelayError, WalletKeyIdentifier, DEFAULT_FEE_QUEUE_DEPTH,
+    DEFAULT_MIN_ETH_BALANCE,
+};
+
+/// A chain served by a [crate::Relayer], with its own Ethereum client, relay
+/// contracts, wallet and fee policy.
+#[derive(Clone, Debug)]
+pub struct ChainConfig {
+    /// Ethereum node, chain ID and wallet of the chain.
+    pub client_config: EthersClientConfig,
+    /// The Ethereum addresses of the Bonsai Relay contracts deployed on the
+    /// chain.
+    pub relay_contract_addresses: Vec<Address>,
+    /// Safe multi-sig wallet to send the relay transactions through, if any.
+    pub safe: Option<SafeConfig>,
+    /// Minimum wallet balance, in wei, required to submit a relay transaction.
+    pub min_eth_balance: U256,
+    /// Cap on the fees of the relay transactions, if any.
+    pub fee_cap: Option<FeeCap>,
+    /// Private relay to submit the relay transactions to instead of the public
+    /// mempool, if any.
+    pub private_relay: Option<PrivateRelay>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainsFile {
+    #[serde(default)]
+    chain: Vec<ChainEntry>,
+}
+
+/// A `[[chain]]` entry of a chains file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainEntry {
+    chain_id: u64,
+    eth_node: String,
+    relay_addresses: Vec<Address>,
+    private_key: String,
+    /// Further keys signing the relay transactions in turn with
+    /// `private_key`.
+    #[serde(default)]
+    additional_private_keys: Vec<String>,
+    /// Address of the wallet, if the private key is a remote signer URL.
+    signer_address: Option<Address>,
+    /// In ether.
+    min_eth_balance: Option<String>,
+    /// In gwei.
+    fee_cap: Option<String>,
+    fee_queue_depth: Option<usize>,
+    private_relay_url: Option<String>,
+    private_relay_fallback_blocks: Option<u64>,
+}
+
+/// Read the `[[chain]]` entries of a TOML chains file. The Ethereum node
+/// retries, wait time and timeout of the chains, and how they reach KMS and
+/// their remote signer, are the ones of `defaults`.
+///
+/// ```toml
+/// [[chain]]
+/// chain_id = 84532
+/// eth_node = "wss://base-sepolia.example.com"
+/// relay_addresses = ["0x5FbDB2315678afecb367f032d93F642f64180aa3"]
+/// private_key = "0x..."
+/// additional_private_keys = ["0x..."] # optional
+/// signer_address = "0x..." # optional, with a remote signer private_key
+/// min_eth_balance = "0.01" # ether, optional
+/// fee_cap = "30"           # gwei, optional
+/// fee_queue_depth = 100    # optional
+/// private_relay_url = "https://rpc.flashbots.net" # optional
+/// private_relay_fallback_blocks = 25 # optional
+/// ```
+pub fn read_chain_configs(
+    path: &Path,
+    defaults: &EthersClientConfig,
+) -> Result<Vec<ChainConfig>, RelayError> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))
+        .map_err(RelayError::config)?;
+    parse_chain_configs(&contents, defaults)
+        .with_context(|| format!("invalid chains file {}", path.display()))
+        .map_err(RelayError::config)
+}
+
+pub(crate) fn parse_chain_configs(
+    contents: &str,
+    defaults: &EthersClientConfig,
+) -> Result<Vec<ChainConfig>> {
+    let file: ChainsFile = toml::from_str(contents)?;
+    file.chain
+        .into_iter()
+        .map(|entry| {
+            let chain_id = entry.chain_id;
+            chain_config(entry, defaults)
+                .with_context(|| format!("invalid [[chain]] entry for chain {chain_id}"))
+        })
+        .collect()
+}
+
+fn chain_config(entry: ChainEntry, defaults: &EthersClientConfig) -> Result<ChainConfig> {
+    if entry.relay_addresses.is_empty() {
+        anyhow::bail!("at least one relay address is required");
+    }
+    let min_eth_balance = entry
+        .min_eth_balance
+        .as_deref()
+        .unwrap_or(DEFAULT_MIN_ETH_BALANCE);
+    let fee_cap = match (entry.fee_cap, entry.fee_queue_depth) {
+        (Some(fee_cap), queue_depth) => Some(FeeCap {
+            max_fee_per_gas: ethers::utils::parse_units(&fee_cap, "gwei")
+                .with_context(|| format!("invalid gwei amount: {fee_cap}"))?
+                .into(),
+            queue_depth: queue_depth.unwrap_or(DEFAULT_FEE_QUEUE_DEPTH),
+        }),
+        (None, Some(_)) => anyhow::bail!("fee_queue_depth requires fee_cap"),
+        (None, None) => None,
+    };
+    let private_relay = match (entry.private_relay_url, entry.private_relay_fallback_blocks) {
+        (Some(url), fallback_blocks) => Some(PrivateRelay {
+            url,
+            fallback_blocks: fallback_blocks.unwrap_or(DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS),
+        }),
+        (None, Some(_)) => {
+            anyhow::bail!("private_relay_fallback_blocks requires private_relay_url")
+        }
+        (None, None) => None,
+    };
+    let additional_wallet_keys = entry
+        .additional_private_keys
+        .into_iter()
+        .map(WalletKeyIdentifier::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(ChainConfig {
+        client_config: EthersClientConfig::new(
+            entry.eth_node,
+            entry.chain_id,
+            entry.private_key.try_into()?,
+            defaults.retries,
+            defaults.wait_time,
+            defaults.eth_node_timeout,
+        )
+        .with_json_rpc_batch(defaults.json_rpc_batch)
+        .with_additional_wallet_keys(additional_wallet_keys)
+        .with_kms(defaults.kms.clone())
+        .with_ledger(defaults.ledger.clone())
+        .with_eth_node_pool_size(defaults.eth_node_pool_size)
+        .with_remote_signer(RemoteSignerConfig {
+            signer_address: entry.signer_address,
+            ..defaults.remote_signer.clone()
+        }),
+        relay_contract_addresses: entry.relay_addresses,
+        safe: None,
+        min_eth_balance: ethers::utils::parse_ether(min_eth_balance)
+            .with_context(|| format!("invalid ether amount: {min_eth_balance}"))?,
+        fee_cap,
+        private_relay,
+    })
+}
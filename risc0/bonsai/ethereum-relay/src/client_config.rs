@@ -12,33 +12,80 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{str::FromStr, time::Duration};
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
 use ethers::{
     core::k256::{ecdsa::SigningKey, SecretKey},
     middleware::SignerMiddleware,
     prelude::*,
-    providers::{Provider, Ws},
+    providers::{JsonRpcClient, JsonRpcError, Provider, ProviderError, PubsubClient, RpcError},
+    signers::{coins_bip39::English, MnemonicBuilder},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{debug, error, warn};
+
+use crate::{
+    eth_node::{
+        EthNodeEndpoint, EthNodeTransport, WsPing, DEFAULT_ETH_NODE_POOL_SIZE,
+        DEFAULT_WS_PING_INTERVAL, DEFAULT_WS_PONG_TIMEOUT,
+    },
+    json_rpc_batch::json_rpc_batch,
+    ledger_signer::{LedgerConfig, LEDGER_PREFIX},
+    metrics::Metrics,
+    remote_signer::{RemoteSignerConfig, REMOTE_SIGNER_PREFIX},
+    signer::{KmsConfig, RelaySigner},
+    RelayError,
 };
-use tracing::{debug, error};
+
+/// Default upper bound on the duration of a single Ethereum provider request.
+pub const DEFAULT_ETH_NODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default interval between the requests for new blocks and logs to Ethereum
+/// nodes reached over HTTP, which are polled rather than subscribed to.
+pub const DEFAULT_ETH_NODE_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Default interval between the requests for the head block checking that the
+/// connection to a subscribed Ethereum node is alive.
+pub const DEFAULT_WS_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time without a new block after which the connection to a
+/// subscribed Ethereum node is considered silently dropped.
+pub const DEFAULT_WS_STALE_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Mnemonic of the accounts funded by a default Anvil instance, whose private
+/// keys are public.
+const ANVIL_MNEMONIC: &str = "test test test test test test test test test test test junk";
+
+/// Number of accounts funded by a default Anvil instance.
+const ANVIL_ACCOUNTS: u32 = 10;
+
+/// Ethereum provider whose requests are bounded by a timeout.
+pub type EthersProvider = Provider<TimeoutClient<EthNodeTransport>>;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct WalletKey(SecretKey);
 
 impl TryFrom<String> for WalletKey {
-    type Error = Error;
+    type Error = RelayError;
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        let decoded =
-            hex::decode(value.trim_start_matches("0x")).context("Failed to decode private key.")?;
-        let key =
-            SecretKey::from_slice(&decoded).context("Failed to derive SecretKey instance.")?;
+        let decoded = hex::decode(value.trim_start_matches("0x"))
+            .context("Failed to decode private key.")
+            .map_err(RelayError::config)?;
+        let key = SecretKey::from_slice(&decoded)
+            .context("Failed to derive SecretKey instance.")
+            .map_err(RelayError::config)?;
         Ok(Self(key))
     }
 }
 
 impl FromStr for WalletKey {
-    type Err = Error;
+    type Err = RelayError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         s.to_string().try_into()
     }
@@ -62,55 +109,561 @@ impl WalletKey {
     }
 }
 
+/// Key signing the transactions of a wallet: a private key, an AWS KMS key
+/// identifier, the URL of a remote signer prefixed with `remote:`, or the
+/// derivation path of a Ledger account prefixed with `ledger:`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WalletKeyIdentifier {
+    PrivateKey(WalletKey),
+    /// Key ID, key ARN, alias name or alias ARN of an AWS KMS key.
+    Kms(String),
+    /// URL of a remote signer, e.g. Web3Signer, holding the key.
+    Remote(String),
+    /// Derivation path of a Ledger account, e.g. `m/44'/60'/0'/0/0`.
+    Ledger(String),
+}
+
+impl WalletKeyIdentifier {
+    /// Returns whether the key is held by a hardware wallet, signing one
+    /// transaction at a time on confirmation by its owner.
+    pub fn is_hardware_wallet(&self) -> bool {
+        matches!(self, Self::Ledger(_))
+    }
+
+    /// Returns whether the key is the private key of one of the accounts
+    /// funded by a default Anvil instance, which anyone can sign with.
+    pub fn is_anvil_key(&self) -> bool {
+        let Self::PrivateKey(key) = self else {
+            return false;
+        };
+        let address = LocalWallet::from(SigningKey::from(key.get_key())).address();
+        (0..ANVIL_ACCOUNTS).any(|index| {
+            MnemonicBuilder::<English>::default()
+                .phrase(ANVIL_MNEMONIC)
+                .index(index)
+                .and_then(|builder| builder.build())
+                .map_or(false, |wallet| wallet.address() == address)
+        })
+    }
+
+    /// Returns whether `value` is an AWS KMS key identifier rather than a
+    /// private key.
+    fn is_kms_key_id(value: &str) -> bool {
+        // Key IDs are UUIDs, or `mrk-` and 32 hex digits for multi-Region
+        // keys.
+        let is_key_id = match value.strip_prefix("mrk-") {
+            Some(id) => id.len() == 32 && id.chars().all(|c| c.is_ascii_hexdigit()),
+            None => {
+                value.len() == 36
+                    && value.char_indices().all(|(i, c)| match i {
+                        8 | 13 | 18 | 23 => c == '-',
+                        _ => c.is_ascii_hexdigit(),
+                    })
+            }
+        };
+        is_key_id || value.starts_with("arn:aws") || value.starts_with("alias/")
+    }
+}
+
+impl TryFrom<String> for WalletKeyIdentifier {
+    type Error = RelayError;
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if let Some(url) = value.strip_prefix(REMOTE_SIGNER_PREFIX) {
+            reqwest::Url::parse(url)
+                .with_context(|| format!("invalid remote signer URL: {url}"))
+                .map_err(RelayError::config)?;
+            Ok(Self::Remote(url.to_string()))
+        } else if let Some(path) = value.strip_prefix(LEDGER_PREFIX) {
+            if !path.starts_with("m/") {
+                return Err(RelayError::ConfigError(format!(
+                    "invalid Ledger derivation path: {path}, expected e.g. m/44'/60'/0'/0/0"
+                )));
+            }
+            Ok(Self::Ledger(path.to_string()))
+        } else if Self::is_kms_key_id(&value) {
+            Ok(Self::Kms(value))
+        } else {
+            Ok(Self::PrivateKey(value.try_into()?))
+        }
+    }
+}
+
+impl FromStr for WalletKeyIdentifier {
+    type Err = RelayError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.to_string().try_into()
+    }
+}
+
+impl From<WalletKey> for WalletKeyIdentifier {
+    fn from(value: WalletKey) -> Self {
+        Self::PrivateKey(value)
+    }
+}
+
+impl From<SecretKey> for WalletKeyIdentifier {
+    fn from(value: SecretKey) -> Self {
+        Self::PrivateKey(value.into())
+    }
+}
+
+/// A [JsonRpcClient] wrapper that bounds every request by a timeout.
+#[derive(Clone, Debug)]
+pub struct TimeoutClient<C> {
+    inner: C,
+    timeout: Duration,
+}
+
+impl<C> TimeoutClient<C> {
+    pub fn new(inner: C, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TimeoutClientError<E> {
+    #[error(transparent)]
+    Client(E),
+    #[error("request `{method}` timed out after {timeout:?}")]
+    Timeout { method: String, timeout: Duration },
+}
+
+impl<E: RpcError> RpcError for TimeoutClientError<E> {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            TimeoutClientError::Client(err) => err.as_error_response(),
+            TimeoutClientError::Timeout { .. } => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            TimeoutClientError::Client(err) => err.as_serde_error(),
+            TimeoutClientError::Timeout { .. } => None,
+        }
+    }
+}
+
+impl<E: RpcError + Into<ProviderError> + 'static> From<TimeoutClientError<E>> for ProviderError {
+    fn from(src: TimeoutClientError<E>) -> Self {
+        match src {
+            TimeoutClientError::Client(err) => err.into(),
+            TimeoutClientError::Timeout { .. } => ProviderError::JsonRpcClientError(Box::new(src)),
+        }
+    }
+}
+
+#[async_trait]
+impl<C> JsonRpcClient for TimeoutClient<C>
+where
+    C: JsonRpcClient,
+    C::Error: 'static,
+{
+    type Error = TimeoutClientError<C::Error>;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match tokio::time::timeout(self.timeout, self.inner.request(method, params)).await {
+            Ok(res) => res.map_err(TimeoutClientError::Client),
+            Err(_) => {
+                warn!(method, timeout = ?self.timeout, "Ethereum node request timed out");
+                Metrics::global()
+                    .eth_node_timeouts
+                    .with_label_values(&[method])
+                    .inc();
+                Err(TimeoutClientError::Timeout {
+                    method: method.to_string(),
+                    timeout: self.timeout,
+                })
+            }
+        }
+    }
+}
+
+impl<C> PubsubClient for TimeoutClient<C>
+where
+    C: PubsubClient,
+    C::Error: 'static,
+{
+    type NotificationStream = C::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        self.inner.subscribe(id).map_err(TimeoutClientError::Client)
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.inner
+            .unsubscribe(id)
+            .map_err(TimeoutClientError::Client)
+    }
+}
+
+/// Outcome of the heartbeat of a subscribed Ethereum node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Heartbeat {
+    /// No new block was observed for the given time, over the stale timeout.
+    Stale(Duration),
+    /// The node answered again after failed requests, e.g. once the
+    /// transport reconnected, possibly having missed logs meanwhile.
+    Recovered,
+    /// A WebSocket ping was left without a pong for the given pong timeout.
+    PongTimeout(Duration),
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct EthersClientConfig {
     pub eth_node_url: String,
     pub eth_chain_id: u64,
-    pub wallet_key_identifier: WalletKey,
+    pub wallet_key_identifier: WalletKeyIdentifier,
+    /// Further keys signing the relay transactions in turn with the wallet
+    /// key, each with its own nonces.
+    pub additional_wallet_keys: Vec<WalletKeyIdentifier>,
     pub retries: u64,
     pub wait_time: Duration,
+    pub eth_node_timeout: Duration,
+    /// Send the requests of the startup checks of the relayer to the Ethereum
+    /// node in a single JSON-RPC batch, rather than one after the other.
+    pub json_rpc_batch: bool,
+    /// How to reach AWS KMS if the wallet key identifier is a KMS key.
+    pub kms: KmsConfig,
+    /// How to reach the remote signer if the wallet key identifier is a
+    /// remote signer URL.
+    pub remote_signer: RemoteSignerConfig,
+    /// How to use the Ledger if the wallet key identifier is a Ledger
+    /// derivation path.
+    pub ledger: LedgerConfig,
+    /// Interval between the requests for new blocks and logs if the
+    /// Ethereum node is reached over HTTP.
+    pub poll_interval: Duration,
+    /// Interval between the requests for the head block checking that the
+    /// connection to a subscribed Ethereum node is alive.
+    pub ws_heartbeat_interval: Duration,
+    /// Time without a new block after which the connection to a subscribed
+    /// Ethereum node is torn down and made again. Zero disables the check.
+    pub ws_stale_timeout: Duration,
+    /// Interval between the WebSocket ping frames sent to the Ethereum node.
+    /// Zero disables the pings.
+    pub ws_ping_interval: Duration,
+    /// Time to wait for the pong answering a WebSocket ping frame, after
+    /// which the connection is torn down and made again.
+    pub ws_pong_timeout: Duration,
+    /// Number of WebSocket connections to the Ethereum node, taking the
+    /// subscriptions and requests in turn.
+    pub eth_node_pool_size: usize,
+}
+
+/// State of a chain read by the startup checks of the relayer, with the error
+/// message of each failed request.
+#[derive(Debug)]
+pub struct ChainState {
+    /// Chain ID served by the Ethereum node.
+    pub chain_id: U256,
+    /// Code deployed at each contract address.
+    pub codes: Vec<Result<Bytes, String>>,
+    /// Balance of each wallet.
+    pub balances: Vec<Result<U256, String>>,
 }
 
 impl EthersClientConfig {
     pub fn new(
         eth_node_url: String,
         eth_chain_id: u64,
-        wallet_key_identifier: WalletKey,
+        wallet_key_identifier: WalletKeyIdentifier,
         retries: u64,
         wait_time: Duration,
+        eth_node_timeout: Duration,
     ) -> Self {
         Self {
             eth_node_url,
             eth_chain_id,
             wallet_key_identifier,
+            additional_wallet_keys: Vec::new(),
             retries,
             wait_time,
+            eth_node_timeout,
+            json_rpc_batch: false,
+            kms: KmsConfig::default(),
+            remote_signer: RemoteSignerConfig::default(),
+            ledger: LedgerConfig::default(),
+            poll_interval: DEFAULT_ETH_NODE_POLL_INTERVAL,
+            ws_heartbeat_interval: DEFAULT_WS_HEARTBEAT_INTERVAL,
+            ws_stale_timeout: DEFAULT_WS_STALE_TIMEOUT,
+            ws_ping_interval: DEFAULT_WS_PING_INTERVAL,
+            ws_pong_timeout: DEFAULT_WS_PONG_TIMEOUT,
+            eth_node_pool_size: DEFAULT_ETH_NODE_POOL_SIZE,
         }
     }
 
-    pub async fn get_client(&self) -> Result<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>> {
+    pub fn with_json_rpc_batch(mut self, json_rpc_batch: bool) -> Self {
+        self.json_rpc_batch = json_rpc_batch;
+        self
+    }
+
+    /// Sets further keys signing the relay transactions in turn with the
+    /// wallet key, to relay more transactions at once than the sequential
+    /// nonces of a single key allow.
+    pub fn with_additional_wallet_keys(
+        mut self,
+        additional_wallet_keys: Vec<WalletKeyIdentifier>,
+    ) -> Self {
+        self.additional_wallet_keys = additional_wallet_keys;
+        self
+    }
+
+    /// Returns the config of each key signing the relay transactions, the
+    /// wallet key first, sharing the Ethereum node and signer settings.
+    pub fn signer_configs(&self) -> Vec<EthersClientConfig> {
+        std::iter::once(self.wallet_key_identifier.clone())
+            .chain(self.additional_wallet_keys.iter().cloned())
+            .map(|wallet_key_identifier| Self {
+                wallet_key_identifier,
+                additional_wallet_keys: Vec::new(),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Sets how to reach AWS KMS if the wallet key identifier is a KMS key.
+    pub fn with_kms(mut self, kms: KmsConfig) -> Self {
+        self.kms = kms;
+        self
+    }
+
+    /// Sets how to reach the remote signer if the wallet key identifier is a
+    /// remote signer URL.
+    pub fn with_remote_signer(mut self, remote_signer: RemoteSignerConfig) -> Self {
+        self.remote_signer = remote_signer;
+        self
+    }
+
+    /// Sets how to use the Ledger if the wallet key identifier is a Ledger
+    /// derivation path.
+    pub fn with_ledger(mut self, ledger: LedgerConfig) -> Self {
+        self.ledger = ledger;
+        self
+    }
+
+    /// Sets the interval between the requests for new blocks and logs if the
+    /// Ethereum node is reached over HTTP.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets how often the head block of a subscribed Ethereum node is
+    /// requested, and after how long without a new block its connection is
+    /// made again. A zero `stale_timeout` disables the check.
+    pub fn with_ws_heartbeat(
+        mut self,
+        heartbeat_interval: Duration,
+        stale_timeout: Duration,
+    ) -> Self {
+        self.ws_heartbeat_interval = heartbeat_interval;
+        self.ws_stale_timeout = stale_timeout;
+        self
+    }
+
+    /// Sets how often a WebSocket ping frame is sent to the Ethereum node,
+    /// and how long to wait for its pong before the connection is made
+    /// again. A zero `ping_interval` disables the pings.
+    pub fn with_ws_ping(mut self, ping_interval: Duration, pong_timeout: Duration) -> Self {
+        self.ws_ping_interval = ping_interval;
+        self.ws_pong_timeout = pong_timeout;
+        self
+    }
+
+    /// Sets the number of WebSocket connections to the Ethereum node, taking
+    /// the subscriptions and requests in turn. Ignored for nodes reached over
+    /// HTTP or IPC.
+    pub fn with_eth_node_pool_size(mut self, eth_node_pool_size: usize) -> Self {
+        self.eth_node_pool_size = eth_node_pool_size;
+        self
+    }
+
+    /// Requests the head block of the Ethereum node every heartbeat interval,
+    /// and returns once the time since the last new block exceeds the stale
+    /// timeout, failed requests counting as no new block, or once the node
+    /// answers again after failed requests. Hosted nodes may drop idle
+    /// WebSocket connections without a close frame, leaving their
+    /// subscriptions open but silent, and the logs emitted while the
+    /// transport reconnects are not delivered. Never returns if the stale
+    /// timeout is zero.
+    pub(crate) async fn wait_for_stale_connection<M: Middleware>(&self, client: &M) -> Heartbeat {
+        if self.ws_stale_timeout.is_zero() {
+            return std::future::pending().await;
+        }
+        let mut interval = tokio::time::interval(self.ws_heartbeat_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut head = None;
+        let mut last_new_block = Instant::now();
+        let mut failed = false;
+        loop {
+            interval.tick().await;
+            match client.get_block_number().await {
+                Ok(_) if failed => return Heartbeat::Recovered,
+                Ok(block) if head.map_or(true, |head| block > head) => {
+                    head = Some(block);
+                    last_new_block = Instant::now();
+                }
+                Ok(_) => {}
+                Err(error) => {
+                    warn!(?error, "Ethereum node heartbeat failed");
+                    failed = true;
+                }
+            }
+            let elapsed = last_new_block.elapsed();
+            Metrics::global().set_seconds_since_last_block(self.eth_chain_id, elapsed);
+            if elapsed >= self.ws_stale_timeout {
+                return Heartbeat::Stale(elapsed);
+            }
+        }
+    }
+
+    /// Returns once a ping sent by the WebSocket transport of `provider` is
+    /// left without a pong for the pong timeout, counting it in the
+    /// `ping_timeout` metric. The transport makes its connection again, while
+    /// the subscriptions of the silent one end. A WebSocket may go silent
+    /// without a close frame, which the head block requests of
+    /// [Self::wait_for_stale_connection] only notice after the stale timeout.
+    /// Never returns if the ping interval is zero or the node is not reached
+    /// over WebSocket.
+    pub(crate) async fn wait_for_pong_timeout(&self, provider: &EthersProvider) -> Heartbeat {
+        provider.as_ref().inner().wait_for_pong_timeout().await;
+        Metrics::global().record_ping_timeout(self.eth_chain_id);
+        Heartbeat::PongTimeout(self.ws_pong_timeout)
+    }
+
+    /// Returns the pings sent on the WebSocket connections to the Ethereum
+    /// node, unless the ping interval is zero.
+    fn ws_ping(&self) -> Option<WsPing> {
+        (!self.ws_ping_interval.is_zero()).then_some(WsPing {
+            interval: self.ws_ping_interval,
+            pong_timeout: self.ws_pong_timeout,
+        })
+    }
+
+    pub async fn get_client(
+        &self,
+    ) -> Result<SignerMiddleware<EthersProvider, RelaySigner>, RelayError> {
         let provider = self.provider().await?;
-        let signer = self.get_signer()?;
+        let signer = self.get_signer().await?;
         let client = SignerMiddleware::new(provider, signer);
         Ok(client)
     }
 
-    pub async fn provider(&self) -> Result<Provider<Ws>> {
-        let provider = Provider::<Ws>::connect_with_reconnects(self.eth_node_url.clone(), 60)
+    /// Returns the endpoint of the Ethereum node, failing if its URL scheme
+    /// is not supported.
+    pub fn eth_node_endpoint(&self) -> Result<EthNodeEndpoint, RelayError> {
+        self.eth_node_url.parse()
+    }
+
+    /// Returns whether the Ethereum node is polled for new blocks and logs,
+    /// its URL being an `http://` or `https://` one.
+    pub(crate) fn polls(&self) -> bool {
+        self.eth_node_endpoint()
+            .map_or(false, |endpoint| endpoint.is_polling())
+    }
+
+    pub async fn provider(&self) -> Result<EthersProvider, RelayError> {
+        let endpoint = self.eth_node_endpoint()?;
+        let transport = endpoint
+            .connect(self.eth_node_pool_size, self.ws_ping())
+            .await?;
+        let provider = Provider::new(TimeoutClient::new(transport, self.eth_node_timeout));
+        if endpoint.is_polling() {
+            Ok(provider.interval(self.poll_interval))
+        } else {
+            Ok(provider)
+        }
+    }
+
+    /// Read the chain ID served by the Ethereum node, the code of the
+    /// `contracts` and the balance of the `wallets`, in a single JSON-RPC batch
+    /// if `json_rpc_batch` is set, or one request after the other. Fails if
+    /// the node is unreachable.
+    pub async fn chain_state(
+        &self,
+        contracts: &[Address],
+        wallets: &[Address],
+    ) -> Result<ChainState> {
+        if !self.json_rpc_batch {
+            let provider = self.provider().await?;
+            let chain_id = provider.get_chainid().await?;
+            let mut codes = Vec::with_capacity(contracts.len());
+            for contract in contracts {
+                let code = provider.get_code(*contract, None).await;
+                codes.push(code.map_err(|err| err.to_string()));
+            }
+            let mut balances = Vec::with_capacity(wallets.len());
+            for wallet in wallets {
+                let balance = provider.get_balance(*wallet, None).await;
+                balances.push(balance.map_err(|err| err.to_string()));
+            }
+            return Ok(ChainState {
+                chain_id,
+                codes,
+                balances,
+            });
+        }
+
+        let mut requests = vec![("eth_chainId", serde_json::json!([]))];
+        requests.extend(
+            contracts
+                .iter()
+                .map(|contract| ("eth_getCode", serde_json::json!([contract, "latest"]))),
+        );
+        requests.extend(
+            wallets
+                .iter()
+                .map(|wallet| ("eth_getBalance", serde_json::json!([wallet, "latest"]))),
+        );
+        let batch = json_rpc_batch(&self.eth_node_url, &requests);
+        let mut results = tokio::time::timeout(self.eth_node_timeout, batch)
             .await
-            .context("Failed to connect to Ethereum node.")?;
-        Ok(provider)
+            .map_err(|_| anyhow!("JSON-RPC batch timed out after {:?}", self.eth_node_timeout))??
+            .into_iter();
+        let chain_id = decode_batch_result(results.next()).map_err(|err| anyhow!(err))?;
+        let codes = contracts
+            .iter()
+            .map(|_| decode_batch_result(results.next()))
+            .collect();
+        let balances = wallets
+            .iter()
+            .map(|_| decode_batch_result(results.next()))
+            .collect();
+        Ok(ChainState {
+            chain_id,
+            codes,
+            balances,
+        })
     }
 
-    pub fn get_signer(&self) -> Result<Wallet<SigningKey>> {
-        let signing_key = SigningKey::from(self.wallet_key_identifier.get_key());
-        let signer = LocalWallet::from(signing_key).with_chain_id(self.eth_chain_id);
-        Ok(signer)
+    pub async fn get_signer(&self) -> Result<RelaySigner, RelayError> {
+        let signer = match &self.wallet_key_identifier {
+            WalletKeyIdentifier::PrivateKey(key) => {
+                RelaySigner::Local(LocalWallet::from(SigningKey::from(key.get_key())))
+            }
+            WalletKeyIdentifier::Kms(key_id) => self.kms.signer(key_id, self.eth_chain_id).await?,
+            WalletKeyIdentifier::Remote(url) => {
+                RelaySigner::Remote(self.remote_signer.signer(url, self.eth_chain_id).await?)
+            }
+            WalletKeyIdentifier::Ledger(path) => {
+                RelaySigner::Ledger(self.ledger.signer(path, self.eth_chain_id).await?)
+            }
+        };
+        Ok(signer.with_chain_id(self.eth_chain_id))
     }
 
     pub async fn get_client_with_reconnects(
         &self,
-    ) -> Result<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>> {
+    ) -> Result<SignerMiddleware<EthersProvider, RelaySigner>, RelayError> {
         for _ in 0..self.retries {
             let client = self.get_client().await;
             if client.is_ok() {
@@ -124,6 +677,16 @@ impl EthersClientConfig {
             }
         }
         error!("Failed to create client.");
-        Err(anyhow!("Failed to create client."))
+        Err(anyhow!("Failed to create client.").into())
     }
 }
+
+/// Decode the result of a request of a JSON-RPC batch, or its error message.
+fn decode_batch_result<T: DeserializeOwned>(
+    result: Option<Result<serde_json::Value, JsonRpcError>>,
+) -> Result<T, String> {
+    let value = result
+        .ok_or("missing from the JSON-RPC batch")?
+        .map_err(|err| err.to_string())?;
+    serde_json::from_value(value).map_err(|err| format!("invalid response: {err}"))
+}
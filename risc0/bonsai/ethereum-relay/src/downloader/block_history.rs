@@ -14,6 +14,7 @@
 
 use std::{
     cmp::{max, min},
+    collections::HashMap,
     sync::Arc,
     time::Duration,
 };
@@ -21,18 +22,17 @@ use std::{
 use anyhow::{anyhow, Result};
 use ethers::{
     core::types::{BlockNumber, Filter},
-    prelude::{k256::ecdsa::SigningKey, signer::SignerMiddlewareError, SignerMiddleware},
-    providers::{Middleware, MiddlewareError, Provider, ProviderError, StreamExt, Ws},
-    types::{Log, U64},
+    prelude::{signer::SignerMiddlewareError, SignerMiddleware},
+    providers::{Middleware, MiddlewareError, ProviderError, StreamExt},
+    types::{Log, H256, U256, U64},
     utils::__serde_json::Value,
 };
-use ethers_signers::Wallet;
 use futures::FutureExt;
 use tokio::sync::mpsc::{self, Sender};
 use tracing::{debug, error, trace, warn};
 
 use super::block_history;
-use crate::EthersClientConfig;
+use crate::{client_config::EthersProvider, signer::RelaySigner, EthersClientConfig};
 
 #[tracing::instrument(skip_all)]
 pub(crate) async fn recover_delay(state: State, sender: mpsc::Sender<Log>) -> Result<State> {
@@ -138,7 +138,7 @@ fn parse_error_response(error: ProviderError) -> Option<(BlockNumber, BlockNumbe
 }
 
 #[tracing::instrument(skip_all)]
-pub(crate) async fn get_latest_block(client: &Provider<Ws>) -> Result<Option<BlockNumber>> {
+pub(crate) async fn get_latest_block(client: &EthersProvider) -> Result<Option<BlockNumber>> {
     Ok(client
         .get_block(BlockNumber::Latest)
         .await?
@@ -257,13 +257,78 @@ fn match_block_numbers(
 #[derive(Clone, Debug)]
 pub(crate) struct State {
     pub client_config: EthersClientConfig,
-    pub client: SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>,
+    pub client: SignerMiddleware<EthersProvider, RelaySigner>,
     pub recreate_client: bool,
     pub last_processed_block: U64,
     pub latest_block: U64,
     pub filter: Filter,
     pub from: BlockNumber,
     pub to: BlockNumber,
+    /// Logs processed so far, from which the logs missed while disconnected
+    /// are backfilled. Kept across reconnections.
+    pub seen_logs: SeenLogs,
+}
+
+/// Number of blocks before the last one processed from which the logs missed
+/// while disconnected are backfilled, so that the logs of blocks the Ethereum
+/// node had not indexed yet, or which were reorganized, are not missed.
+pub(crate) const BACKFILL_OVERLAP: u64 = 5;
+
+/// Logs processed on the subscriptions to the Ethereum node, by transaction
+/// hash and log index, so that the logs backfilled after a reconnection, or
+/// delivered again by a new subscription, are processed once. Only the logs
+/// of the blocks backfilled again are kept.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SeenLogs {
+    /// First block whose logs are processed. The logs of the blocks before it
+    /// were processed on start, or are not to be.
+    first_block: u64,
+    /// Last block in which a log was processed, or up to which logs were
+    /// backfilled.
+    last_block: u64,
+    /// Processed logs, with their block number.
+    logs: HashMap<(Option<H256>, Option<U256>), u64>,
+}
+
+impl SeenLogs {
+    /// Track the logs processed from `first_block` on.
+    pub(crate) fn new(first_block: u64) -> Self {
+        Self {
+            first_block,
+            last_block: first_block.saturating_sub(1),
+            logs: HashMap::new(),
+        }
+    }
+
+    /// First block from which missed logs are backfilled.
+    pub(crate) fn backfill_from(&self) -> u64 {
+        self.last_block
+            .saturating_sub(BACKFILL_OVERLAP)
+            .max(self.first_block)
+    }
+
+    /// Record a log as processed, returning whether it was not already.
+    /// Logs of blocks before the first one are never processed.
+    pub(crate) fn insert(&mut self, log: &Log) -> bool {
+        let block = log
+            .block_number
+            .map_or(self.last_block, |block| block.as_u64());
+        if block < self.first_block {
+            return false;
+        }
+        self.last_block = self.last_block.max(block);
+        self.logs
+            .insert((log.transaction_hash, log.log_index), block)
+            .is_none()
+    }
+
+    /// Record that the logs were backfilled up to the `head` block, and
+    /// forget the logs of the blocks no longer backfilled again.
+    pub(crate) fn backfilled_to(&mut self, head: u64) {
+        self.last_block = self.last_block.max(head);
+        let from = self.backfill_from();
+        self.logs.retain(|_, block| *block >= from);
+    }
 }
 
 impl State {
@@ -12,9 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use ethers::types::Address;
+
 #[async_trait::async_trait]
 pub(crate) trait EventProcessor {
     type Event;
 
-    async fn process_event(&self, event: Self::Event) -> Result<(), crate::api::error::Error>;
+    /// Process an event emitted by the contract at `address`, returning the
+    /// ID of the request it was submitted as.
+    async fn process_event(
+        &self,
+        address: Address,
+        event: Self::Event,
+    ) -> Result<String, crate::api::error::Error>;
 }
@@ -0,0 +1,90 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Filter, Log, H256, U256},
+};
+use tracing::debug;
+
+use super::replay::{block_ranges, HISTORY_SCAN_BLOCK_RANGE};
+
+/// Number of blocks already polled whose logs are requested again on each
+/// poll, so that the logs of blocks the Ethereum node had not indexed yet, or
+/// which were reorganized, are not missed.
+pub(crate) const LOG_POLL_OVERLAP: u64 = 5;
+
+/// Poller of new logs, for Ethereum nodes reached over HTTP which cannot be
+/// subscribed to. The logs of the blocks polled again are only returned once.
+#[derive(Clone, Debug)]
+pub(crate) struct LogPoller {
+    filter: Filter,
+    /// First block to poll, before which blocks are never polled again.
+    start_block: u64,
+    /// First block not polled yet.
+    next_block: u64,
+    /// Logs returned so far, by transaction hash and log index, with their
+    /// block number. Only the ones of the blocks polled again are kept.
+    seen: HashMap<(Option<H256>, Option<U256>), u64>,
+}
+
+impl LogPoller {
+    pub(crate) fn new(filter: Filter, start_block: u64) -> Self {
+        Self {
+            filter,
+            start_block,
+            next_block: start_block,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Get the logs matching the filter mined since the last poll, up to the
+    /// head block, in order.
+    pub(crate) async fn poll<M: Middleware>(&mut self, client: &M) -> Result<Vec<Log>> {
+        let head = client
+            .get_block_number()
+            .await
+            .map_err(|err| anyhow!("Failed to get the head block: {err}"))?
+            .as_u64();
+        let from = self
+            .next_block
+            .saturating_sub(LOG_POLL_OVERLAP)
+            .max(self.start_block);
+        let mut logs = Vec::new();
+        for (from, to) in block_ranges(from, head, HISTORY_SCAN_BLOCK_RANGE) {
+            debug!(from, to, "Polling logs");
+            let range_filter = self.filter.clone().from_block(from).to_block(to);
+            logs.extend(client.get_logs(&range_filter).await.map_err(|err| {
+                anyhow!("Failed to get the logs of blocks {from} to {to}: {err}")
+            })?);
+        }
+
+        self.seen.retain(|_, block| *block >= from);
+        let logs = logs
+            .into_iter()
+            .filter(|log| !log.removed.unwrap_or(false))
+            .filter(|log| {
+                let block = log.block_number.map_or(head, |block| block.as_u64());
+                self.seen
+                    .insert((log.transaction_hash, log.log_index), block)
+                    .is_none()
+            })
+            .collect();
+        self.next_block = self.next_block.max(head + 1);
+        Ok(logs)
+    }
+}
@@ -14,5 +14,7 @@
 
 pub(crate) mod block_history;
 pub(crate) mod event_processor;
+pub(crate) mod log_poller;
 pub(crate) mod proxy_callback_proof_processor;
 pub(crate) mod proxy_callback_proof_request_stream;
+pub(crate) mod replay;
@@ -12,67 +12,223 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-use bonsai_sdk::{
-    alpha::Client,
-    alpha_async::{create_session, put_input},
-};
+use bonsai_sdk::alpha::{SdkErr, SessionId};
+use ethers::types::{Address, H256};
 use tokio::sync::Notify;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
+    bonsai_transport::BonsaiTransport,
     downloader::event_processor::EventProcessor,
+    events::{self, EventSender, RelayerEvent},
+    images::{is_image_not_found, upload_image, GuestImage},
+    intake::Intake,
     storage::{ProofRequestInformation, Storage},
 };
 
 #[derive(Clone)]
 pub(crate) struct ProxyCallbackProofRequestProcessor<S: Storage> {
-    pub bonsai_client: Client,
+    /// Chain of the relay contracts the requests are made to.
+    pub chain_id: u64,
+    pub bonsai_client: Arc<dyn BonsaiTransport>,
     pub storage: S,
     pub notifier: Option<Arc<Notify>>,
+    pub events: Option<EventSender>,
+    /// Guest images re-uploaded when Bonsai no longer knows them.
+    pub images: Arc<[GuestImage]>,
+    /// Age after which the requests of on-chain events expire, if not proven.
+    pub request_max_age: Option<Duration>,
+    /// Intake the requests are admitted through, closed while draining.
+    pub intake: Intake,
 }
 
 impl<S: Storage> ProxyCallbackProofRequestProcessor<S> {
-    pub(crate) fn new(bonsai_client: Client, storage: S, notifier: Option<Arc<Notify>>) -> Self {
+    pub(crate) fn new(
+        chain_id: u64,
+        bonsai_client: Arc<dyn BonsaiTransport>,
+        storage: S,
+        notifier: Option<Arc<Notify>>,
+        events: Option<EventSender>,
+    ) -> Self {
         Self {
+            chain_id,
             bonsai_client,
             storage,
             notifier,
+            events,
+            images: Arc::new([]),
+            request_max_age: None,
+            intake: Intake::default(),
         }
     }
+
+    /// Re-upload the image of a callback request once, and retry creating its
+    /// session, when Bonsai no longer knows the image, e.g. after a local
+    /// Bonsai instance was wiped.
+    pub(crate) fn with_images(self, images: Arc<[GuestImage]>) -> Self {
+        Self { images, ..self }
+    }
+
+    /// Expire the requests of on-chain events not proven within the given age
+    /// of being received.
+    pub(crate) fn with_request_max_age(self, request_max_age: Option<Duration>) -> Self {
+        Self {
+            request_max_age,
+            ..self
+        }
+    }
+
+    /// Admit the requests through the given intake, failing them with
+    /// [crate::api::error::Error::IntakeClosed] while it is draining or full.
+    pub(crate) fn with_intake(self, intake: Intake) -> Self {
+        Self { intake, ..self }
+    }
 }
 
-#[async_trait::async_trait]
-impl<S: Storage + Sync + Send> EventProcessor for ProxyCallbackProofRequestProcessor<S> {
-    type Event = CallbackRequestFilter;
+impl<S: Storage + Sync + Send> ProxyCallbackProofRequestProcessor<S> {
+    /// Submit a callback request made to the given relay contract to Bonsai,
+    /// returning its request ID. Requests identical to one still being proven
+    /// share its session, rather than proving the same input again. A request
+    /// not proven by its deadline, if any, expires. Requests are only taken
+    /// in while the intake is open, and wait for the scheduler to hand out
+    /// their session.
+    pub(crate) async fn submit(
+        &self,
+        relay_contract_address: Address,
+        event: CallbackRequestFilter,
+        deadline: Option<Instant>,
+    ) -> Result<String, crate::api::error::Error> {
+        self.submit_scheduled(relay_contract_address, event, deadline, true)
+            .await
+    }
 
-    async fn process_event(
+    /// Submit a callback request as [Self::submit] does, failing with
+    /// [crate::intake::IntakeClosed::Busy] rather than waiting for a session
+    /// unless `wait_for_session` is set.
+    async fn submit_scheduled(
         &self,
+        relay_contract_address: Address,
         event: CallbackRequestFilter,
-    ) -> Result<(), crate::api::error::Error> {
-        let input_id = put_input(self.bonsai_client.clone(), event.input.clone().to_vec()).await?;
-        let bonsai_session_id = create_session(
-            self.bonsai_client.clone(),
-            hex::encode(event.image_id),
-            input_id.clone(),
-        )
-        .await?;
+        deadline: Option<Instant>,
+        wait_for_session: bool,
+    ) -> Result<String, crate::api::error::Error> {
+        self.intake.admit(&self.storage).await?;
+        let image_id = H256::from(event.image_id);
+        events::emit(
+            &self.events,
+            RelayerEvent::RequestReceived {
+                timestamp: events::now(),
+                relay_address: relay_contract_address,
+                image_id,
+                callback_contract: event.callback_contract,
+            },
+        );
+
+        if let Some((session_id, request_id)) = self
+            .storage
+            .attach_to_proving_request(
+                self.chain_id,
+                relay_contract_address,
+                event.clone(),
+                deadline,
+            )
+            .await?
+        {
+            info!(
+                chain_id = self.chain_id,
+                relay = ?relay_contract_address,
+                ?session_id,
+                request_id, "attached callback event to identical request"
+            );
+            return Ok(request_id);
+        }
+
+        let scheduler = self.intake.scheduler();
+        let slot = match wait_for_session {
+            true => scheduler.acquire(image_id).await?,
+            false => scheduler.try_acquire(image_id)?,
+        };
+        let bonsai_session_id = self.create_session(image_id, &event.input).await?;
+        events::emit(
+            &self.events,
+            RelayerEvent::SessionCreated {
+                timestamp: events::now(),
+                session_id: bonsai_session_id.uuid.clone(),
+                image_id,
+            },
+        );
 
         // Store the request in storage
         self.storage
             .add_new_bonsai_proof_request(ProofRequestInformation {
-                proof_request_id: bonsai_session_id,
+                proof_request_id: bonsai_session_id.clone(),
+                chain_id: self.chain_id,
+                relay_contract_address,
                 callback_proof_request_event: event,
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline,
             })
             .await?;
+        slot.hold_for(&bonsai_session_id.uuid);
 
         if let Some(notifier) = self.notifier.clone() {
             notifier.notify_one()
         }
 
-        info!(?input_id, "sent new callback event to bonsai");
-        Ok(())
+        info!(
+            chain_id = self.chain_id,
+            relay = ?relay_contract_address,
+            session_id = %bonsai_session_id.uuid,
+            "sent new callback event to bonsai"
+        );
+        Ok(bonsai_session_id.uuid)
+    }
+}
+
+impl<S: Storage> ProxyCallbackProofRequestProcessor<S> {
+    /// Create a Bonsai session, re-uploading its image and retrying once if
+    /// Bonsai no longer knows the image.
+    async fn create_session(&self, image_id: H256, input: &[u8]) -> Result<SessionId, SdkErr> {
+        let create = || {
+            self.bonsai_client
+                .create_session(hex::encode(image_id), input.to_vec())
+        };
+        let err = match create().await {
+            Err(err) if is_image_not_found(&err) => err,
+            result => return result,
+        };
+        let Some(image) = self.images.iter().find(|image| image.image_id == image_id) else {
+            return Err(err);
+        };
+        warn!(
+            name = %image.name,
+            ?image_id,
+            "image not found by Bonsai, uploading it again"
+        );
+        upload_image(&*self.bonsai_client, image).await?;
+        create().await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Storage + Sync + Send> EventProcessor for ProxyCallbackProofRequestProcessor<S> {
+    type Event = CallbackRequestFilter;
+
+    async fn process_event(
+        &self,
+        address: Address,
+        event: CallbackRequestFilter,
+    ) -> Result<String, crate::api::error::Error> {
+        // Events finding no free session are deferred, not to hold back the
+        // events of the other images.
+        let deadline = self.request_max_age.map(|max_age| Instant::now() + max_age);
+        self.submit_scheduled(address, event, deadline, false).await
     }
 }
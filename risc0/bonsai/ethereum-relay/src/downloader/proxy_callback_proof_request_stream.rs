@@ -12,28 +12,77 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
 use anyhow::Result;
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
 use ethers::{
-    prelude::{k256::ecdsa::SigningKey, signer::SignerMiddlewareError},
-    providers::{Middleware, Provider, PubsubClient, SubscriptionStream, Ws},
-    types::{Address, BlockNumber, Log},
+    prelude::signer::SignerMiddlewareError,
+    providers::{Middleware, PubsubClient, SubscriptionStream},
+    types::{Address, BlockNumber, Filter, Log, H256},
 };
-use ethers_signers::Wallet;
 use futures::{Stream, StreamExt};
+use tokio::{
+    sync::watch,
+    time::{Instant, MissedTickBehavior},
+};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use super::{block_history, block_history::State};
-use crate::{api::error::Error, downloader::event_processor::EventProcessor, EthersClientConfig};
+use super::{
+    block_history,
+    block_history::{SeenLogs, State},
+    log_poller::LogPoller,
+    replay::{self, HistoryScan},
+};
+use crate::{
+    api::error::Error,
+    client_config::{EthersProvider, Heartbeat},
+    downloader::event_processor::EventProcessor,
+    event_db::{self, event_log, EventDb, EventStatus, PersistedEvent, RESUMED_TX_TIMEOUT},
+    events::{self, EventSender, RelayerEvent},
+    intake::{Intake, IntakeClosed},
+    metrics::Metrics,
+    signer::RelaySigner,
+    EthersClientConfig,
+};
 
 #[derive(Debug)]
 pub(crate) struct ProxyCallbackProofRequestStream<
     EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send,
 > {
     client_config: EthersClientConfig,
-    proxy_contract_address: Address,
+    proxy_contract_addresses: Vec<Address>,
+    /// Image IDs of the events to process, as reloaded.
+    image_ids: watch::Receiver<Vec<H256>>,
     event_processor: EP,
+    events: Option<EventSender>,
+    replay_logs: Vec<Log>,
+    history_scan: Option<HistoryScan>,
+    event_db: Option<EventDb>,
+    intake: Intake,
+    /// Logs of the events deferred at intake, oldest first, processed again
+    /// once the intake reopens.
+    deferred: Mutex<VecDeque<Log>>,
+}
+
+/// Interval between the attempts to process the deferred events again.
+pub(crate) const DEFERRED_EVENTS_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Outcome of processing the event of a log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogOutcome {
+    Processed,
+    /// The event was deferred at intake, for the given reason.
+    Deferred(IntakeClosed),
+    /// The event was skipped, or failed to be processed.
+    Dropped,
+}
+
+/// Returns whether the event requests a callback for one of the given image
+/// IDs. An empty list of image IDs matches all events.
+pub(crate) fn matches_image_ids(image_ids: &[H256], event: &CallbackRequestFilter) -> bool {
+    image_ids.is_empty() || image_ids.contains(&H256::from(event.image_id))
 }
 
 impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
@@ -41,24 +90,88 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
 {
     pub(crate) fn new(
         client_config: EthersClientConfig,
-        proxy_contract_address: Address,
+        proxy_contract_addresses: Vec<Address>,
+        image_ids: watch::Receiver<Vec<H256>>,
         event_processor: EP,
+        events: Option<EventSender>,
+        replay_logs: Vec<Log>,
     ) -> ProxyCallbackProofRequestStream<EP> {
         Self {
             client_config,
-            proxy_contract_address,
+            proxy_contract_addresses,
+            image_ids,
             event_processor,
+            events,
+            replay_logs,
+            history_scan: None,
+            event_db: None,
+            intake: Intake::default(),
+            deferred: Mutex::new(VecDeque::new()),
         }
     }
 
-    pub(crate) async fn run(self) -> Result<(), Error> {
+    /// Process the events of past blocks on start, before subscribing to new
+    /// ones.
+    pub(crate) fn with_history_scan(mut self, history_scan: Option<HistoryScan>) -> Self {
+        self.history_scan = history_scan;
+        self
+    }
+
+    /// Record the processed events in the event database, skipping the ones
+    /// already recorded, and resume on start the events left unfinished by
+    /// the previous run.
+    pub(crate) fn with_event_db(mut self, event_db: Option<EventDb>) -> Self {
+        self.event_db = event_db;
+        self
+    }
+
+    /// Count the events deferred at intake in the given intake, closed while
+    /// the relayer is draining or too many requests are in flight.
+    pub(crate) fn with_intake(mut self, intake: Intake) -> Self {
+        self.intake = intake;
+        self
+    }
+
+    pub(crate) async fn run(mut self) -> Result<(), Error> {
         const EVENT_NAME: &str = "CallbackRequest(address,bytes32,bytes,address,bytes4,uint64)";
 
         let filter = ethers::types::Filter::new()
-            .address(self.proxy_contract_address)
+            .address(self.proxy_contract_addresses.clone())
             .event(EVENT_NAME);
         let client = self.client_config.get_client().await?;
+
+        // Process the logs to replay before subscribing to new ones.
+        let replay_logs = std::mem::take(&mut self.replay_logs);
+        if !replay_logs.is_empty() {
+            let logs =
+                replay::canonical_logs(&client, &self.proxy_contract_addresses, replay_logs).await;
+            self.process_logs(futures::stream::iter(logs)).await;
+        }
+        self.resume_events(&client).await;
+
+        // Past blocks are scanned up to the head block, after which new
+        // events are subscribed to, or polled for.
         let last_processed_block_number = client.get_block_number().await?;
+        if let Some(scan) = self.history_scan {
+            let logs = replay::historical_logs(
+                &client,
+                &filter,
+                scan,
+                last_processed_block_number.as_u64(),
+            )
+            .await?;
+            let found = logs.len();
+            let processed = self.process_logs(futures::stream::iter(logs)).await;
+            info!(
+                found,
+                processed, "Processed past events, waiting for new ones"
+            );
+        }
+        if self.client_config.polls() {
+            return self
+                .poll_logs(&client, filter, last_processed_block_number.as_u64() + 1)
+                .await;
+        }
         let last_processed_block = BlockNumber::Number(last_processed_block_number);
         let mut state = State {
             client_config: self.client_config.clone(),
@@ -69,38 +182,353 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
             filter,
             from: last_processed_block,
             to: last_processed_block,
+            // The logs up to the head block were scanned, or are not to be
+            // processed.
+            seen_logs: SeenLogs::new(last_processed_block_number.as_u64() + 1),
         };
 
         loop {
             state = self.recreate_client(state.clone()).await?;
             state = self.recover_block_delay(state.clone()).await;
             let logs = state.client.subscribe_logs(&state.filter).await;
-            self.match_logs(state.clone(), logs).await;
+            state = self.match_logs(state.clone(), logs).await;
+        }
+    }
+
+    /// Poll the Ethereum node for new events from `start_block` on, for
+    /// nodes reached over HTTP which cannot be subscribed to.
+    async fn poll_logs<M: Middleware>(
+        &self,
+        client: &M,
+        filter: Filter,
+        start_block: u64,
+    ) -> Result<(), Error> {
+        info!(
+            start_block,
+            poll_interval = ?self.client_config.poll_interval,
+            "Polling for new events"
+        );
+        let mut poller = LogPoller::new(filter, start_block);
+        let mut interval = tokio::time::interval(self.client_config.poll_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.retry_deferred_events(client).await;
+            match poller.poll(client).await {
+                Ok(logs) => {
+                    self.process_logs(futures::stream::iter(logs)).await;
+                }
+                Err(error) => error!(?error, "Failed to poll for new events, retrying"),
+            }
         }
     }
 
     async fn recreate_client(&self, state: State) -> Result<State, Error> {
         let state = if state.recreate_client {
             debug!("Recreating client.");
-            state.recreate_client().await?
+            let state = match state.recreate_client().await {
+                Ok(state) => state,
+                Err(error) => {
+                    events::emit(
+                        &self.events,
+                        RelayerEvent::EthReconnectsExhausted {
+                            timestamp: events::now(),
+                            chain_id: self.client_config.eth_chain_id,
+                            error: format!("{error:#}"),
+                        },
+                    );
+                    return Err(error.into());
+                }
+            };
+            events::emit(
+                &self.events,
+                RelayerEvent::EthReconnected {
+                    timestamp: events::now(),
+                },
+            );
+            state
         } else {
             state
         };
         Ok(state)
     }
 
-    async fn process_logs(&self, stream: impl Stream<Item = Log>) {
+    /// Process the events of the logs, returning the number of events
+    /// processed successfully.
+    async fn process_logs(&self, stream: impl Stream<Item = Log>) -> usize {
         tokio::pin!(stream);
+        let mut processed = 0;
         while let Some(log) = stream.next().await {
-            let parsed_event: Result<CallbackRequestFilter, _> = ethers::contract::parse_log(log);
-            match parsed_event {
-                Ok(event) => {
-                    if let Err(error) = self.event_processor.process_event(event).await {
-                        error!(?error, "Error processing event");
+            if self.process_log(log).await {
+                processed += 1;
+            }
+        }
+        processed
+    }
+
+    /// Process the event of the log, returning whether it was processed
+    /// successfully. Events already recorded in the event database are
+    /// skipped.
+    async fn process_log(&self, log: Log) -> bool {
+        if let (Some(db), Some((tx_hash, log_index, _))) = (&self.event_db, event_db::log_key(&log))
+        {
+            match db.contains(self.client_config.eth_chain_id, tx_hash, log_index) {
+                Ok(true) => {
+                    debug!(?tx_hash, log_index, "Skipping event already processed");
+                    return false;
+                }
+                Ok(false) => (),
+                Err(error) => warn!(?error, "Failed to look up the event in the event database"),
+            }
+        }
+        self.process_new_log(log).await == LogOutcome::Processed
+    }
+
+    /// Process the event of the log, recording it in the event database.
+    /// Events not taken in by the intake are deferred, to be processed again
+    /// once it reopens.
+    async fn process_new_log(&self, log: Log) -> LogOutcome {
+        let relay = log.address;
+        let parsed_event: Result<CallbackRequestFilter, _> =
+            ethers::contract::parse_log(log.clone());
+        match parsed_event {
+            // The imageId is not an indexed field of the event, so it can't be
+            // part of the log filter topics.
+            Ok(event) if !matches_image_ids(&self.image_ids.borrow(), &event) => {
+                debug!(?relay, image_id = ?H256::from(event.image_id), "Skipping event")
+            }
+            Ok(event) => {
+                let image_id = H256::from(event.image_id);
+                match self.event_processor.process_event(relay, event).await {
+                    Ok(request_id) => {
+                        if let Some(db) = &self.event_db {
+                            let chain_id = self.client_config.eth_chain_id;
+                            if let Err(error) =
+                                db.record_received(chain_id, &log, image_id, &request_id)
+                            {
+                                warn!(?error, "Failed to record the event in the event database");
+                            }
+                        }
+                        return LogOutcome::Processed;
+                    }
+                    Err(Error::IntakeClosed(reason)) => {
+                        info!(?relay, ?image_id, %reason, "Deferring event");
+                        if let Some(db) = &self.event_db {
+                            let chain_id = self.client_config.eth_chain_id;
+                            if let Err(error) = db.record_deferred(chain_id, &log, image_id) {
+                                warn!(?error, "Failed to record the event in the event database");
+                            }
+                        }
+                        self.deferred
+                            .lock()
+                            .unwrap_or_else(|poisoned| poisoned.into_inner())
+                            .push_back(log);
+                        self.intake.add_deferred_events(1);
+                        return LogOutcome::Deferred(reason);
+                    }
+                    Err(error) => {
+                        error!(?relay, ?error, "Error processing event");
+                        events::emit(
+                            &self.events,
+                            RelayerEvent::RelayError {
+                                timestamp: events::now(),
+                                error: events::error_chain(&error),
+                                tx_hash: None,
+                            },
+                        );
+                    }
+                }
+            }
+            Err(error) => error!(?relay, ?error, "Error parsing log"),
+        }
+        LogOutcome::Dropped
+    }
+
+    /// Process the deferred events again, oldest first, until one is deferred
+    /// again for all images. The events deferred again for their image only
+    /// keep their place. The events no longer on chain, e.g. after a reorg,
+    /// are dropped.
+    async fn retry_deferred_events<M: Middleware>(&self, client: &M) {
+        let mut kept = Vec::new();
+        loop {
+            let Some(log) = self
+                .deferred
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .pop_front()
+            else {
+                break;
+            };
+            self.intake.remove_deferred_events(1);
+            let tx_hash = log.transaction_hash.unwrap_or_default();
+            match client.get_transaction_receipt(tx_hash).await {
+                Ok(receipt) if replay::in_receipt(&log, receipt.as_ref()) => {}
+                Ok(_) => {
+                    warn!(?tx_hash, "Dropping deferred event no longer on chain");
+                    continue;
+                }
+                Err(error) => {
+                    // Tried again on the next attempt.
+                    warn!(
+                        ?tx_hash,
+                        ?error,
+                        "Failed to get the receipt of a deferred event"
+                    );
+                    self.deferred
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push_front(log);
+                    self.intake.add_deferred_events(1);
+                    break;
+                }
+            }
+            if let LogOutcome::Deferred(reason) = self.process_new_log(log).await {
+                // Deferred again, behind the events deferred after it.
+                let mut deferred = self
+                    .deferred
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let Some(log) = deferred.pop_back() else {
+                    break;
+                };
+                if reason.is_global() {
+                    deferred.push_front(log);
+                    break;
+                }
+                kept.push(log);
+            }
+        }
+        let mut deferred = self
+            .deferred
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for log in kept.into_iter().rev() {
+            deferred.push_front(log);
+        }
+    }
+
+    fn has_deferred_events(&self) -> bool {
+        !self
+            .deferred
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .is_empty()
+    }
+
+    /// Resume the events of the event database left unfinished by the
+    /// previous run. The relay transactions sent but not confirmed are
+    /// followed until mined, for up to [RESUMED_TX_TIMEOUT], and the events
+    /// whose relay transaction was dropped are processed again, as are the
+    /// events not relayed yet, whose Bonsai session was only known in memory,
+    /// and the events deferred at intake.
+    async fn resume_events<M: Middleware>(&self, client: &M) {
+        let Some(db) = &self.event_db else {
+            return;
+        };
+        let chain_id = self.client_config.eth_chain_id;
+        let (received, submitted, deferred) = match (
+            db.events_with_status(chain_id, EventStatus::Received),
+            db.events_with_status(chain_id, EventStatus::Submitted),
+            db.events_with_status(chain_id, EventStatus::Deferred),
+        ) {
+            (Ok(received), Ok(submitted), Ok(deferred)) => (received, submitted, deferred),
+            (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => {
+                error!(
+                    ?error,
+                    "Failed to read the unfinished events of the event database"
+                );
+                return;
+            }
+        };
+        if received.is_empty() && submitted.is_empty() && deferred.is_empty() {
+            return;
+        }
+        info!(
+            received = received.len(),
+            submitted = submitted.len(),
+            deferred = deferred.len(),
+            "Resuming the unfinished events of the event database"
+        );
+
+        let deadline = Instant::now() + RESUMED_TX_TIMEOUT;
+        let mut reprocessed = received;
+        for event in submitted {
+            if self.track_relay_tx(client, db, &event, deadline).await {
+                reprocessed.push(event);
+            }
+        }
+        // The events deferred at intake by the previous run, e.g. while it
+        // was draining before a restart, come after the ones taken in.
+        reprocessed.extend(deferred);
+        for event in reprocessed {
+            match event_log(client, &event).await {
+                Ok(Some(log)) => {
+                    self.process_new_log(log).await;
+                }
+                Ok(None) => warn!(
+                    tx_hash = ?event.tx_hash,
+                    log_index = event.log_index,
+                    "Skipping resumed event no longer on chain"
+                ),
+                Err(error) => warn!(
+                    tx_hash = ?event.tx_hash,
+                    log_index = event.log_index,
+                    ?error,
+                    "Skipping resumed event, receipt unavailable"
+                ),
+            }
+        }
+    }
+
+    /// Follow the relay transaction of a submitted event until mined or
+    /// `deadline`, recording its outcome. Returns whether the transaction was
+    /// dropped, in which case the event is to be processed again.
+    async fn track_relay_tx<M: Middleware>(
+        &self,
+        client: &M,
+        db: &EventDb,
+        event: &PersistedEvent,
+        deadline: Instant,
+    ) -> bool {
+        let Some(relay_tx_hash) = event.relay_tx_hash else {
+            return true;
+        };
+        loop {
+            match client.get_transaction_receipt(relay_tx_hash).await {
+                Ok(Some(receipt)) => {
+                    let status = if receipt.status == Some(1.into()) {
+                        info!(?relay_tx_hash, "Resumed relay transaction confirmed");
+                        EventStatus::Completed
+                    } else {
+                        warn!(?relay_tx_hash, "Resumed relay transaction reverted");
+                        EventStatus::Failed
+                    };
+                    if let Err(error) = db.set_status(event, status, None) {
+                        warn!(?error, "Failed to record the event in the event database");
                     }
+                    return false;
                 }
-                Err(error) => error!(?error, "Error parsing log"),
+                Ok(None) => match client.get_transaction(relay_tx_hash).await {
+                    Ok(Some(_)) => debug!(?relay_tx_hash, "Resumed relay transaction pending"),
+                    Ok(None) => {
+                        warn!(
+                            ?relay_tx_hash,
+                            "Resumed relay transaction dropped, processing its event again"
+                        );
+                        return true;
+                    }
+                    Err(error) => warn!(?relay_tx_hash, ?error, "Failed to get relay transaction"),
+                },
+                Err(error) => warn!(?relay_tx_hash, ?error, "Failed to get relay receipt"),
+            }
+            if Instant::now() >= deadline {
+                warn!(
+                    ?relay_tx_hash,
+                    "Resumed relay transaction still pending, checking it again on next start"
+                );
+                return false;
             }
+            tokio::time::sleep(self.client_config.poll_interval).await;
         }
     }
 
@@ -109,14 +537,89 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
         state: State,
         logs: Result<
             SubscriptionStream<'_, impl PubsubClient, Log>,
-            SignerMiddlewareError<Provider<Ws>, Wallet<SigningKey>>,
+            SignerMiddlewareError<EthersProvider, RelaySigner>,
         >,
     ) -> State {
         match logs {
             Ok(logs) => {
                 debug!("Successfully subscribed to logs");
-                self.process_logs(logs).await;
-                state
+                // The subscription is open before backfilling, so that no log
+                // is missed in between, and may deliver backfilled logs again.
+                let mut state = self.backfill_logs(state).await;
+                let mut seen_logs = std::mem::take(&mut state.seen_logs);
+                let heartbeat = {
+                    // The connection is only checked between logs, so that
+                    // the processing of an event is never interrupted.
+                    let client_config = &state.client_config;
+                    let heartbeat = async {
+                        tokio::select! {
+                            outcome = client_config.wait_for_stale_connection(&state.client) => outcome,
+                            outcome = client_config.wait_for_pong_timeout(state.client.inner()) => outcome,
+                        }
+                    };
+                    let mut retry_deferred = tokio::time::interval(DEFERRED_EVENTS_RETRY_INTERVAL);
+                    retry_deferred.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                    tokio::pin!(logs, heartbeat);
+                    loop {
+                        tokio::select! {
+                            log = logs.next() => match log {
+                                Some(log) if !seen_logs.insert(&log) => {}
+                                Some(log) => {
+                                    self.process_log(log).await;
+                                }
+                                None => break None,
+                            },
+                            outcome = &mut heartbeat => break Some(outcome),
+                            _ = retry_deferred.tick(), if self.has_deferred_events() => {
+                                self.retry_deferred_events(&state.client).await;
+                            }
+                        }
+                    }
+                };
+                match heartbeat {
+                    None => {
+                        // The subscription ends when the transport gives up
+                        // reconnecting, or, if pinged, as it reconnects.
+                        warn!("Log subscription ended, reconnecting");
+                        State {
+                            recreate_client: true,
+                            seen_logs,
+                            ..state
+                        }
+                    }
+                    Some(Heartbeat::Recovered) => {
+                        // The transport reconnected on its own, and the new
+                        // subscription backfills the logs missed meanwhile.
+                        info!("Ethereum node answering again, subscribing again");
+                        State { seen_logs, ..state }
+                    }
+                    Some(Heartbeat::Stale(elapsed)) => {
+                        let chain_id = state.client_config.eth_chain_id;
+                        warn!(
+                            chain_id,
+                            elapsed = %humantime::format_duration(elapsed),
+                            "No new block observed on the Ethereum node, reconnecting"
+                        );
+                        Metrics::global().record_stale_connection_reconnect(chain_id);
+                        State {
+                            recreate_client: true,
+                            seen_logs,
+                            ..state
+                        }
+                    }
+                    Some(Heartbeat::PongTimeout(timeout)) => {
+                        // The transport makes its connection again, and the
+                        // new subscription backfills the logs missed
+                        // meanwhile. Should it fail to, the subscription
+                        // fails and the client is recreated.
+                        warn!(
+                            chain_id = state.client_config.eth_chain_id,
+                            timeout = %humantime::format_duration(timeout),
+                            "No pong from the Ethereum node, subscribing again"
+                        );
+                        State { seen_logs, ..state }
+                    }
+                }
             }
             Err(error) => {
                 error!(?error, "Failed to subscribe to logs");
@@ -127,6 +630,53 @@ impl<EP: EventProcessor<Event = CallbackRequestFilter> + Sync + Send>
             }
         }
     }
+
+    /// Process the logs emitted from [block_history::BACKFILL_OVERLAP] blocks
+    /// before the last one processed up to the head block, e.g. while the
+    /// connection to the Ethereum node was down, skipping the ones already
+    /// processed.
+    async fn backfill_logs(&self, mut state: State) -> State {
+        let head = match state.client.get_block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(error) => {
+                warn!(
+                    ?error,
+                    "Failed to get the head block, not backfilling missed events"
+                );
+                return state;
+            }
+        };
+        let from_block = state.seen_logs.backfill_from();
+        if head < from_block {
+            return state;
+        }
+        let scan = HistoryScan {
+            start_block: from_block,
+            end_block: None,
+        };
+        let logs = match replay::historical_logs(&state.client, &state.filter, scan, head).await {
+            Ok(logs) => logs,
+            Err(error) => {
+                warn!(?error, "Failed to backfill missed events");
+                return state;
+            }
+        };
+        let logs: Vec<Log> = logs
+            .into_iter()
+            .filter(|log| state.seen_logs.insert(log))
+            .collect();
+        if !logs.is_empty() {
+            let found = logs.len();
+            let processed = self.process_logs(futures::stream::iter(logs)).await;
+            info!(
+                from_block,
+                head, found, processed, "Backfilled missed events"
+            );
+        }
+        state.seen_logs.backfilled_to(head);
+        state
+    }
+
     async fn recover_block_delay(&self, state: State) -> State {
         info!(?state, "Starting to recover delay.");
         let original_state = state.clone();
@@ -0,0 +1,166 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::{anyhow, Context, Result};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Filter, Log, TransactionReceipt, H256, U256},
+};
+use tracing::{debug, info, warn};
+
+/// Maximum number of blocks whose logs are requested at once when scanning
+/// past blocks, within the limits of most Ethereum node providers.
+pub(crate) const HISTORY_SCAN_BLOCK_RANGE: u64 = 1000;
+
+/// Past blocks whose relay contract events are processed on start, before
+/// subscribing to new ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct HistoryScan {
+    pub(crate) start_block: u64,
+    /// Last block scanned, the head block if unset.
+    pub(crate) end_block: Option<u64>,
+}
+
+/// Read a file of logs to replay, as newline-delimited JSON [Log]s.
+pub(crate) fn read_logs(path: &Path) -> Result<Vec<Log>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open event replay file {}", path.display()))?;
+    parse_logs(BufReader::new(file))
+        .with_context(|| format!("Failed to read event replay file {}", path.display()))
+}
+
+/// Parse newline-delimited JSON [Log]s, skipping blank lines.
+pub(crate) fn parse_logs(reader: impl BufRead) -> Result<Vec<Log>> {
+    let mut logs = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let log = serde_json::from_str(&line)
+            .with_context(|| format!("Invalid log on line {}", index + 1))?;
+        logs.push(log);
+    }
+    Ok(logs)
+}
+
+/// Returns whether a log is in the receipt of its transaction, i.e. whether its
+/// transaction is mined in the block the log was emitted in. Logs of
+/// transactions dropped or mined in another block after a reorg are not.
+pub(crate) fn in_receipt(log: &Log, receipt: Option<&TransactionReceipt>) -> bool {
+    receipt.map_or(false, |receipt| {
+        receipt.block_hash == log.block_hash
+            && receipt.logs.iter().any(|receipt_log| {
+                receipt_log.log_index == log.log_index && receipt_log.address == log.address
+            })
+    })
+}
+
+/// Keep the logs of the relay contracts to replay, in order, skipping the ones
+/// listed more than once and the ones no longer on the canonical chain.
+pub(crate) async fn canonical_logs<M: Middleware>(
+    client: &M,
+    relay_contract_addresses: &[Address],
+    logs: Vec<Log>,
+) -> Vec<Log> {
+    let total = logs.len();
+    let mut seen = HashSet::<(Option<H256>, Option<U256>)>::new();
+    let mut replayed = Vec::new();
+    for log in logs {
+        if !relay_contract_addresses.contains(&log.address) {
+            warn!(address = ?log.address, "Skipping replayed log of another contract");
+            continue;
+        }
+        if !seen.insert((log.transaction_hash, log.log_index)) {
+            continue;
+        }
+        let Some(tx_hash) = log.transaction_hash else {
+            warn!("Skipping replayed log without a transaction hash");
+            continue;
+        };
+        match client.get_transaction_receipt(tx_hash).await {
+            Ok(receipt) if in_receipt(&log, receipt.as_ref()) => replayed.push(log),
+            Ok(_) => warn!(?tx_hash, "Skipping replayed log no longer on chain"),
+            Err(error) => warn!(
+                ?tx_hash,
+                ?error,
+                "Skipping replayed log, receipt unavailable"
+            ),
+        }
+    }
+    info!(total, replayed = replayed.len(), "Replaying logs");
+    replayed
+}
+
+/// Returns the ranges of at most `max_range` blocks covering the blocks from
+/// `from` to `to`, inclusive.
+pub(crate) fn block_ranges(from: u64, to: u64, max_range: u64) -> Vec<(u64, u64)> {
+    let max_range = max_range.max(1);
+    let mut ranges = Vec::new();
+    let mut start = from;
+    while start <= to {
+        let end = to.min(start.saturating_add(max_range - 1));
+        ranges.push((start, end));
+        if end == u64::MAX {
+            break;
+        }
+        start = end + 1;
+    }
+    ranges
+}
+
+/// Get the logs matching `filter` in the blocks of `scan`, in order, given
+/// the current `head` block. Blocks past the head block are not scanned.
+pub(crate) async fn historical_logs<M: Middleware>(
+    client: &M,
+    filter: &Filter,
+    scan: HistoryScan,
+    head: u64,
+) -> Result<Vec<Log>> {
+    let end_block = match scan.end_block {
+        Some(end_block) if end_block > head => {
+            warn!(
+                end_block,
+                head, "End block is past the head block, scanning up to the head block"
+            );
+            head
+        }
+        Some(end_block) => end_block,
+        None => head,
+    };
+    let mut logs = Vec::new();
+    for (from, to) in block_ranges(scan.start_block, end_block, HISTORY_SCAN_BLOCK_RANGE) {
+        debug!(from, to, "Scanning past blocks");
+        let range_filter = filter.clone().from_block(from).to_block(to);
+        logs.extend(
+            client
+                .get_logs(&range_filter)
+                .await
+                .map_err(|err| anyhow!("Failed to get the logs of blocks {from} to {to}: {err}"))?,
+        );
+    }
+    info!(
+        start_block = scan.start_block,
+        end_block,
+        found = logs.len(),
+        "Scanned past blocks"
+    );
+    Ok(logs)
+}
@@ -0,0 +1,69 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors returned by the public API of the relayer.
+
+use bonsai_sdk::alpha::SdkErr;
+use ethers::providers::ProviderError;
+
+use crate::{images::is_image_not_found, signer::RelaySignerError};
+
+/// An error of the relayer, e.g. returned by [crate::Relayer::run].
+#[derive(Debug, thiserror::Error)]
+pub enum RelayError {
+    #[error("Bonsai API error: {0}")]
+    BonsaiApiError(SdkErr),
+    #[error("Ethereum error: {0}")]
+    EthereumError(#[from] ProviderError),
+    #[error("Signer error: {0}")]
+    SignerError(#[from] RelaySignerError),
+    /// A SNARK proof that is malformed, or can't be encoded as the seal of a
+    /// callback.
+    #[error("{0}")]
+    ProofEncodingError(String),
+    /// An image Bonsai doesn't know of.
+    #[error("Image not found: {0}")]
+    ImageNotFound(String),
+    /// Invalid settings, e.g. a malformed private key or chains file.
+    #[error("{0}")]
+    ConfigError(String),
+    /// Every chain the relayer served exited, the last one with `error`.
+    #[error("all chains exited, the {task} of chain {chain_id} last: {error}")]
+    ChainsExited {
+        chain_id: u64,
+        task: &'static str,
+        error: String,
+    },
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    Unspecified(#[from] anyhow::Error),
+}
+
+impl RelayError {
+    /// Returns a [RelayError::ConfigError] describing `err` with its causes.
+    pub(crate) fn config(err: impl Into<anyhow::Error>) -> Self {
+        Self::ConfigError(format!("{:#}", err.into()))
+    }
+}
+
+impl From<SdkErr> for RelayError {
+    fn from(err: SdkErr) -> Self {
+        if is_image_not_found(&err) {
+            Self::ImageNotFound(err.to_string())
+        } else {
+            Self::BonsaiApiError(err)
+        }
+    }
+}
@@ -0,0 +1,642 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Transports to the Ethereum node, over a WebSocket, HTTP or, on the same
+//! host, an IPC socket.
+//!
+//! WebSocket and IPC nodes are subscribed to for new blocks and logs, while
+//! HTTP nodes, which cannot push notifications, are polled for them. Requests
+//! to WebSocket nodes can be spread over a pool of connections, and their
+//! connections pinged.
+
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{
+        Http, HttpClientError, Ipc, IpcError, JsonRpcClient, JsonRpcError, ProviderError,
+        PubsubClient, RpcError, Ws, WsClientError,
+    },
+    types::U256,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    pinging_ws::{PingingWs, PingingWsError},
+    RelayError,
+};
+
+/// Number of attempts to reconnect to the Ethereum node when its connection is
+/// lost, e.g. while it restarts.
+pub(crate) const ETH_NODE_RECONNECTS: usize = 60;
+
+/// Delay between the attempts to reopen the IPC socket of the Ethereum node,
+/// to reconnect a pinged WebSocket or to replace a closed connection of a
+/// WebSocket pool.
+pub(crate) const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default number of WebSocket connections to the Ethereum node.
+pub const DEFAULT_ETH_NODE_POOL_SIZE: usize = 1;
+
+/// Default interval between the WebSocket ping frames sent to the Ethereum
+/// node.
+pub const DEFAULT_WS_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time to wait for the pong answering a WebSocket ping frame.
+pub const DEFAULT_WS_PONG_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// WebSocket ping frames sent to the Ethereum node on each of its connections.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WsPing {
+    pub interval: Duration,
+    /// Time to wait for the pong answering a ping, after which the
+    /// connection is made again.
+    pub pong_timeout: Duration,
+}
+
+/// Supported forms of the Ethereum node endpoint, listed when it is invalid.
+const SUPPORTED_ENDPOINTS: &str =
+    "ws://, wss://, http://, https://, ipc://<path> or a bare IPC socket path";
+
+/// Endpoint of the Ethereum node, given as a WebSocket or HTTP URL, an
+/// `ipc://` URL or the path of an IPC socket.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EthNodeEndpoint {
+    Ws(String),
+    Http(String),
+    Ipc(PathBuf),
+}
+
+impl FromStr for EthNodeEndpoint {
+    type Err = RelayError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("ipc://") {
+            return Ok(Self::Ipc(PathBuf::from(path)));
+        }
+        match s.split_once("://") {
+            None if !s.is_empty() => Ok(Self::Ipc(PathBuf::from(s))),
+            Some((scheme, _)) if scheme.eq_ignore_ascii_case("ws") => Ok(Self::Ws(s.to_string())),
+            Some((scheme, _)) if scheme.eq_ignore_ascii_case("wss") => Ok(Self::Ws(s.to_string())),
+            Some((scheme, _))
+                if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") =>
+            {
+                Ok(Self::Http(s.to_string()))
+            }
+            _ => Err(RelayError::ConfigError(format!(
+                "unsupported Ethereum node endpoint {s}, expected {SUPPORTED_ENDPOINTS}"
+            ))),
+        }
+    }
+}
+
+impl EthNodeEndpoint {
+    /// Returns whether the Ethereum node is polled for new blocks and logs,
+    /// rather than subscribed to.
+    pub fn is_polling(&self) -> bool {
+        matches!(self, Self::Http(_))
+    }
+
+    /// Connect to the Ethereum node, reconnecting up to [ETH_NODE_RECONNECTS]
+    /// times when the connection is lost. WebSocket nodes are reached over
+    /// `pool_size` connections, each pinged if `ping` is set, while the other
+    /// transports ignore both.
+    pub(crate) async fn connect(
+        &self,
+        pool_size: usize,
+        ping: Option<WsPing>,
+    ) -> Result<EthNodeTransport, RelayError> {
+        match self {
+            Self::Ws(url) if pool_size > 1 => Ok(EthNodeTransport::WsPool(
+                WsPool::connect(url.clone(), pool_size, ping, ETH_NODE_RECONNECTS)
+                    .await
+                    .map_err(|err| RelayError::EthereumError(err.into()))?,
+            )),
+            Self::Ws(url) => Ok(
+                WsConnection::connect(url.clone(), ping, ETH_NODE_RECONNECTS)
+                    .await
+                    .map_err(|err| RelayError::EthereumError(err.into()))?
+                    .into(),
+            ),
+            Self::Http(url) => Ok(EthNodeTransport::Http(Http::from_str(url).map_err(
+                |err| RelayError::ConfigError(format!("invalid Ethereum node URL {url}: {err}")),
+            )?)),
+            Self::Ipc(path) => Ok(EthNodeTransport::Ipc(
+                ReconnectingIpc::connect(path.clone(), ETH_NODE_RECONNECTS)
+                    .await
+                    .map_err(|err| RelayError::EthereumError(err.into()))?,
+            )),
+        }
+    }
+}
+
+/// Transport to the Ethereum node.
+#[derive(Clone, Debug)]
+pub enum EthNodeTransport {
+    Ws(Ws),
+    /// WebSocket transport pinging its connection.
+    PingingWs(PingingWs),
+    WsPool(WsPool),
+    /// Polled transport, without subscriptions.
+    Http(Http),
+    Ipc(ReconnectingIpc),
+}
+
+impl EthNodeTransport {
+    /// Returns once a ping of a WebSocket connection is left without a pong
+    /// for the pong timeout, as the connection is made again. Never returns if
+    /// the connections are not pinged.
+    pub(crate) async fn wait_for_pong_timeout(&self) {
+        match self {
+            Self::PingingWs(ws) => ws.wait_for_pong_timeout().await,
+            Self::WsPool(pool) => pool.wait_for_pong_timeout().await,
+            Self::Ws(_) | Self::Http(_) | Self::Ipc(_) => std::future::pending().await,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EthNodeTransportError {
+    #[error(transparent)]
+    Ws(#[from] WsClientError),
+    #[error(transparent)]
+    PingingWs(#[from] PingingWsError),
+    #[error(transparent)]
+    Http(#[from] HttpClientError),
+    #[error(transparent)]
+    Ipc(#[from] IpcError),
+    #[error("subscriptions are not supported by Ethereum nodes reached over HTTP")]
+    NoSubscriptions,
+}
+
+impl RpcError for EthNodeTransportError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::Ws(err) => err.as_error_response(),
+            Self::PingingWs(err) => err.as_error_response(),
+            Self::Http(err) => err.as_error_response(),
+            Self::Ipc(err) => err.as_error_response(),
+            Self::NoSubscriptions => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Ws(err) => err.as_serde_error(),
+            Self::PingingWs(err) => err.as_serde_error(),
+            Self::Http(err) => err.as_serde_error(),
+            Self::Ipc(err) => err.as_serde_error(),
+            Self::NoSubscriptions => None,
+        }
+    }
+}
+
+impl From<EthNodeTransportError> for ProviderError {
+    fn from(src: EthNodeTransportError) -> Self {
+        match src {
+            EthNodeTransportError::Ws(err) => err.into(),
+            EthNodeTransportError::PingingWs(err) => err.into(),
+            EthNodeTransportError::Http(err) => err.into(),
+            EthNodeTransportError::Ipc(err) => err.into(),
+            EthNodeTransportError::NoSubscriptions => ProviderError::UnsupportedRPC,
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for EthNodeTransport {
+    type Error = EthNodeTransportError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Ws(ws) => Ok(ws.request(method, params).await?),
+            Self::PingingWs(ws) => Ok(ws.request(method, params).await?),
+            Self::WsPool(pool) => pool.request(method, params).await,
+            Self::Http(http) => Ok(http.request(method, params).await?),
+            Self::Ipc(ipc) => Ok(ipc.request(method, params).await?),
+        }
+    }
+}
+
+impl PubsubClient for EthNodeTransport {
+    type NotificationStream = <Ws as PubsubClient>::NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        match self {
+            Self::Ws(ws) => Ok(ws.subscribe(id)?),
+            Self::PingingWs(ws) => Ok(ws.subscribe(id)?),
+            Self::WsPool(pool) => pool.subscribe(id.into()),
+            Self::Http(_) => Err(EthNodeTransportError::NoSubscriptions),
+            Self::Ipc(ipc) => Ok(ipc.current().1.subscribe(id)?),
+        }
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        match self {
+            Self::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            Self::PingingWs(ws) => Ok(ws.unsubscribe(id)?),
+            Self::WsPool(pool) => pool.unsubscribe(id.into()),
+            Self::Http(_) => Err(EthNodeTransportError::NoSubscriptions),
+            Self::Ipc(ipc) => Ok(ipc.current().1.unsubscribe(id)?),
+        }
+    }
+}
+
+/// An [Ipc] transport reopening the socket when the Ethereum node closes it,
+/// e.g. on restart, and retrying the interrupted request. The subscriptions
+/// of the closed socket end, and are to be made again.
+#[derive(Clone, Debug)]
+pub struct ReconnectingIpc {
+    path: PathBuf,
+    reconnects: usize,
+    /// The open socket, with the number of times it was reopened.
+    ipc: Arc<RwLock<(u64, Ipc)>>,
+    /// Held while reopening the socket, so that it is reopened once.
+    reconnecting: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl ReconnectingIpc {
+    pub async fn connect(path: PathBuf, reconnects: usize) -> Result<Self, IpcError> {
+        let ipc = Ipc::connect(&path).await?;
+        Ok(Self {
+            path,
+            reconnects,
+            ipc: Arc::new(RwLock::new((0, ipc))),
+            reconnecting: Default::default(),
+        })
+    }
+
+    fn current(&self) -> (u64, Ipc) {
+        self.ipc
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, IpcError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let (generation, ipc) = self.current();
+        match ipc.request(method, &params).await {
+            Err(err) if is_disconnected(&err) => {
+                warn!(
+                    path = %self.path.display(),
+                    method,
+                    "Ethereum node IPC socket closed: {err}"
+                );
+                self.reconnect(generation).await?;
+                self.current().1.request(method, params).await
+            }
+            result => result,
+        }
+    }
+
+    /// Reopen the socket closed at the given generation, unless another
+    /// request did already.
+    async fn reconnect(&self, generation: u64) -> Result<(), IpcError> {
+        let _reconnecting = self.reconnecting.lock().await;
+        if self.current().0 != generation {
+            return Ok(());
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match Ipc::connect(&self.path).await {
+                Ok(ipc) => {
+                    info!(
+                        path = %self.path.display(),
+                        attempt,
+                        "Reopened the Ethereum node IPC socket"
+                    );
+                    *self
+                        .ipc
+                        .write()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) = (generation + 1, ipc);
+                    return Ok(());
+                }
+                Err(err) if attempt >= self.reconnects => return Err(err),
+                Err(err) => {
+                    warn!(
+                        path = %self.path.display(),
+                        attempt,
+                        "Failed to reopen the Ethereum node IPC socket: {err}"
+                    );
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                }
+            }
+        }
+    }
+}
+
+/// Returns whether the request failed because the IPC socket was closed,
+/// rather than being answered with an error.
+fn is_disconnected(err: &IpcError) -> bool {
+    matches!(
+        err,
+        IpcError::IoError(_)
+            | IpcError::ChannelError(_)
+            | IpcError::RequestCancelled(_)
+            | IpcError::ServerExit
+    )
+}
+
+/// A pool of WebSocket connections to the Ethereum node, taking the requests
+/// in turn, each pinged if the pool is. Subscriptions are served by the
+/// connection they were made on. A connection closed for good, once its own
+/// reconnects are exhausted, is replaced by a new one, and the interrupted
+/// request retried on it. The subscriptions of the closed connection end, and
+/// are to be made again.
+#[derive(Clone, Debug)]
+pub struct WsPool {
+    url: String,
+    ping: Option<WsPing>,
+    reconnects: usize,
+    connections: Arc<[PooledWs]>,
+    /// Index of the connection taking the next request.
+    next: Arc<AtomicUsize>,
+    /// Index of the connection each subscription was made on.
+    subscriptions: Arc<Mutex<HashMap<U256, usize>>>,
+}
+
+#[derive(Debug)]
+struct PooledWs {
+    /// The open connection, with the number of times it was replaced.
+    ws: RwLock<(u64, WsConnection)>,
+    /// Held while replacing the connection, so that it is replaced once.
+    replacing: tokio::sync::Mutex<()>,
+}
+
+impl WsPool {
+    /// Open `size`, at least one, connections to the node at `url`, each
+    /// pinged if `ping` is set and reconnecting up to `reconnects` times when
+    /// lost.
+    pub async fn connect(
+        url: String,
+        size: usize,
+        ping: Option<WsPing>,
+        reconnects: usize,
+    ) -> Result<Self, EthNodeTransportError> {
+        let mut connections = Vec::with_capacity(size.max(1));
+        for _ in 0..size.max(1) {
+            let ws = WsConnection::connect(url.clone(), ping, reconnects).await?;
+            connections.push(PooledWs {
+                ws: RwLock::new((0, ws)),
+                replacing: Default::default(),
+            });
+        }
+        Ok(Self {
+            url,
+            ping,
+            reconnects,
+            connections: connections.into(),
+            next: Default::default(),
+            subscriptions: Default::default(),
+        })
+    }
+
+    /// Returns the number of connections of the pool.
+    pub fn size(&self) -> usize {
+        self.connections.len()
+    }
+
+    fn current(&self, index: usize) -> (u64, WsConnection) {
+        self.connections[index]
+            .ws
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn subscriptions(&self) -> std::sync::MutexGuard<'_, HashMap<U256, usize>> {
+        self.subscriptions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns the connection taking the next request, or the one the
+    /// subscription ended by an `eth_unsubscribe` request was made on.
+    fn connection_for<T: Serialize>(&self, method: &str, params: &T) -> usize {
+        if method == "eth_unsubscribe" {
+            let index =
+                subscription_id(params).and_then(|id| self.subscriptions().get(&id).copied());
+            if let Some(index) = index {
+                return index;
+            }
+        }
+        self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len()
+    }
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, EthNodeTransportError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let index = self.connection_for(method, &params);
+        let (generation, ws) = self.current(index);
+        let response: serde_json::Value = match ws.request(method, &params).await {
+            Err(err) if is_closed(&err) => {
+                warn!(
+                    url = %self.url,
+                    connection = index,
+                    method,
+                    "Ethereum node WebSocket connection closed: {err}"
+                );
+                self.replace(index, generation).await?;
+                self.current(index).1.request(method, params).await?
+            }
+            result => result?,
+        };
+        if method == "eth_subscribe" {
+            let id: U256 = serde_json::from_value(response.clone()).map_err(WsClientError::from)?;
+            self.subscriptions().insert(id, index);
+        }
+        Ok(serde_json::from_value(response).map_err(WsClientError::from)?)
+    }
+
+    fn subscribe(
+        &self,
+        id: U256,
+    ) -> Result<<Ws as PubsubClient>::NotificationStream, EthNodeTransportError> {
+        let index = self.subscriptions().get(&id).copied();
+        let index = index.ok_or(WsClientError::UnknownSubscription(id))?;
+        self.current(index).1.subscribe(id)
+    }
+
+    fn unsubscribe(&self, id: U256) -> Result<(), EthNodeTransportError> {
+        let index = self.subscriptions().remove(&id);
+        match index {
+            Some(index) => self.current(index).1.unsubscribe(id),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns once a ping of one of the connections is left without a pong
+    /// for the pong timeout. Never returns if the connections are not pinged.
+    async fn wait_for_pong_timeout(&self) {
+        let connections: Vec<PingingWs> = (0..self.size())
+            .filter_map(|index| match self.current(index).1 {
+                WsConnection::PingingWs(ws) => Some(ws),
+                WsConnection::Ws(_) => None,
+            })
+            .collect();
+        if connections.is_empty() {
+            return std::future::pending().await;
+        }
+        futures::future::select_all(
+            connections
+                .iter()
+                .map(|ws| Box::pin(ws.wait_for_pong_timeout())),
+        )
+        .await;
+    }
+
+    /// Replace the connection closed at the given generation, unless another
+    /// request did already, forgetting its subscriptions.
+    async fn replace(&self, index: usize, generation: u64) -> Result<(), EthNodeTransportError> {
+        let _replacing = self.connections[index].replacing.lock().await;
+        if self.current(index).0 != generation {
+            return Ok(());
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match WsConnection::connect(self.url.clone(), self.ping, self.reconnects).await {
+                Ok(ws) => {
+                    info!(
+                        url = %self.url,
+                        connection = index,
+                        attempt,
+                        "Replaced the Ethereum node WebSocket connection"
+                    );
+                    *self.connections[index]
+                        .ws
+                        .write()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()) = (generation + 1, ws);
+                    self.subscriptions()
+                        .retain(|_, connection| *connection != index);
+                    return Ok(());
+                }
+                Err(err) if attempt >= self.reconnects => return Err(err),
+                Err(err) => {
+                    warn!(
+                        url = %self.url,
+                        connection = index,
+                        attempt,
+                        "Failed to replace the Ethereum node WebSocket connection: {err}"
+                    );
+                    tokio::time::sleep(RECONNECT_INTERVAL).await;
+                }
+            }
+        }
+    }
+}
+
+/// A WebSocket connection to the Ethereum node, pinged or not.
+#[derive(Clone, Debug)]
+enum WsConnection {
+    Ws(Ws),
+    PingingWs(PingingWs),
+}
+
+impl WsConnection {
+    /// Open a connection to the node at `url`, pinged if `ping` is set,
+    /// reconnecting up to `reconnects` times when lost.
+    async fn connect(
+        url: String,
+        ping: Option<WsPing>,
+        reconnects: usize,
+    ) -> Result<Self, EthNodeTransportError> {
+        match ping {
+            Some(ping) => Ok(Self::PingingWs(
+                PingingWs::connect(url, ping, reconnects).await?,
+            )),
+            None => Ok(Self::Ws(
+                Ws::connect_with_reconnects(url, reconnects).await?,
+            )),
+        }
+    }
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, EthNodeTransportError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Self::Ws(ws) => Ok(ws.request(method, params).await?),
+            Self::PingingWs(ws) => Ok(ws.request(method, params).await?),
+        }
+    }
+
+    fn subscribe(
+        &self,
+        id: U256,
+    ) -> Result<<Ws as PubsubClient>::NotificationStream, EthNodeTransportError> {
+        match self {
+            Self::Ws(ws) => Ok(ws.subscribe(id)?),
+            Self::PingingWs(ws) => Ok(ws.subscribe(id)?),
+        }
+    }
+
+    fn unsubscribe(&self, id: U256) -> Result<(), EthNodeTransportError> {
+        match self {
+            Self::Ws(ws) => Ok(ws.unsubscribe(id)?),
+            Self::PingingWs(ws) => Ok(ws.unsubscribe(id)?),
+        }
+    }
+}
+
+impl From<WsConnection> for EthNodeTransport {
+    fn from(connection: WsConnection) -> Self {
+        match connection {
+            WsConnection::Ws(ws) => Self::Ws(ws),
+            WsConnection::PingingWs(ws) => Self::PingingWs(ws),
+        }
+    }
+}
+
+/// Returns the subscription ID of the parameters of an `eth_unsubscribe`
+/// request.
+fn subscription_id<T: Serialize>(params: &T) -> Option<U256> {
+    let params = serde_json::to_value(params).ok()?;
+    serde_json::from_value(params.get(0)?.clone()).ok()
+}
+
+/// Returns whether the request failed because the WebSocket connection was
+/// closed for good, rather than being answered with an error.
+fn is_closed(err: &EthNodeTransportError) -> bool {
+    matches!(
+        err,
+        EthNodeTransportError::Ws(
+            WsClientError::UnexpectedClose
+                | WsClientError::DeadChannel
+                | WsClientError::TooManyReconnects
+        ) | EthNodeTransportError::PingingWs(PingingWsError::Closed)
+    )
+}
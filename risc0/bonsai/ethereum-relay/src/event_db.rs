@@ -0,0 +1,714 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! SQLite database of the relay events processed by the relayer, persisted
+//! across restarts so that the events whose relay transaction was sent but
+//! not confirmed are tracked again on start. The proof of each event is
+//! recorded along with it, so that its relay transaction can be resubmitted.
+//! The failed callback requests of the dead-letter list are persisted in it
+//! too, as are the gas costs of the relay transactions counted against the
+//! daily spend limit.
+
+use std::{
+    fmt,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context, Result};
+use bonsai_ethereum_contracts::i_bonsai_relay::{Callback, CallbackRequestFilter};
+use bonsai_sdk::alpha::SessionId;
+use ethers::{
+    providers::Middleware,
+    types::{Address, Log, H256, U256},
+};
+use rusqlite::{params, types::Type, Connection, OptionalExtension, Row};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::{
+    events::{self, RelayerEvent},
+    spend::{Spend, SPEND_WINDOW},
+    storage::{dead_letters::DeadLetter, AttachedRequest, ProofRequestInformation},
+};
+
+/// Interval between the removals of the completed events older than the
+/// retention period.
+pub(crate) const EVENT_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Time for which the relay transactions left unconfirmed by the previous
+/// run are followed on start.
+pub(crate) const RESUMED_TX_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Number of relayer events buffered for the event database when the relayer
+/// was not given an event channel.
+pub(crate) const EVENT_DB_EVENTS_CAPACITY: usize = 1024;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chain_id INTEGER NOT NULL,
+    relay_address TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    log_index INTEGER NOT NULL,
+    tx_hash TEXT NOT NULL,
+    image_id TEXT NOT NULL,
+    request_id TEXT NOT NULL,
+    relay_tx_hash TEXT,
+    processed_at INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    journal BLOB,
+    seal BLOB,
+    post_state_digest TEXT,
+    UNIQUE (chain_id, tx_hash, log_index)
+);
+CREATE INDEX IF NOT EXISTS events_request_id ON events (request_id);
+CREATE INDEX IF NOT EXISTS events_status ON events (status, processed_at);
+CREATE TABLE IF NOT EXISTS dead_letters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    request_id TEXT NOT NULL,
+    chain_id INTEGER NOT NULL,
+    relay_address TEXT NOT NULL,
+    account TEXT NOT NULL,
+    image_id TEXT NOT NULL,
+    input BLOB NOT NULL,
+    callback_contract TEXT NOT NULL,
+    function_selector BLOB NOT NULL,
+    gas_limit INTEGER NOT NULL,
+    stage TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    failed_at INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS dead_letters_session_id ON dead_letters (session_id);
+CREATE TABLE IF NOT EXISTS spends (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chain_id INTEGER NOT NULL,
+    tx_hash TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    spent_at INTEGER NOT NULL
+);";
+
+/// Processing status of a persisted event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum EventStatus {
+    /// The callback request was deferred at intake, e.g. while the relayer
+    /// was draining, and is to be sent to Bonsai once taken in.
+    Deferred,
+    /// The callback request was sent to Bonsai.
+    Received,
+    /// The relay transaction of the callback was sent, but not confirmed.
+    Submitted,
+    /// The relay transaction of the callback was confirmed.
+    Completed,
+    /// The callback request failed, in Bonsai or on chain.
+    Failed,
+}
+
+impl EventStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Deferred => "deferred",
+            Self::Received => "received",
+            Self::Submitted => "submitted",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+        }
+    }
+}
+
+impl fmt::Display for EventStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventStatus {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deferred" => Ok(Self::Deferred),
+            "received" => Ok(Self::Received),
+            "submitted" => Ok(Self::Submitted),
+            "completed" => Ok(Self::Completed),
+            "failed" => Ok(Self::Failed),
+            _ => Err(anyhow!("unknown event status {s}")),
+        }
+    }
+}
+
+/// Proof of a relay event, from which its callback is built.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct EventProof {
+    pub(crate) journal: Vec<u8>,
+    pub(crate) seal: Vec<u8>,
+    pub(crate) post_state_digest: H256,
+}
+
+impl EventProof {
+    /// Returns the proof a callback was built with, its payload being the
+    /// function selector, the journal and the image ID.
+    pub(crate) fn from_callback(callback: &Callback) -> Option<Self> {
+        let payload = callback.payload.as_ref();
+        let journal = payload.get(4..payload.len().checked_sub(32)?)?;
+        Some(Self {
+            journal: journal.to_vec(),
+            seal: callback.auth.seal.to_vec(),
+            post_state_digest: callback.auth.post_state_digest.into(),
+        })
+    }
+}
+
+/// A relay event recorded in the database.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PersistedEvent {
+    /// ID of the event in the database, kept when it is processed again.
+    pub(crate) id: i64,
+    pub(crate) chain_id: u64,
+    pub(crate) relay_address: Address,
+    pub(crate) block_number: u64,
+    pub(crate) log_index: u64,
+    /// Transaction which emitted the event.
+    pub(crate) tx_hash: H256,
+    pub(crate) image_id: H256,
+    /// Request ID the event was submitted to Bonsai under.
+    pub(crate) request_id: String,
+    /// Last relay transaction sent for the event, if any.
+    pub(crate) relay_tx_hash: Option<H256>,
+    /// Unix time, in milliseconds, at which the event was processed.
+    pub(crate) processed_at: u64,
+    pub(crate) status: EventStatus,
+    /// Proof of the event, once proven.
+    pub(crate) proof: Option<EventProof>,
+}
+
+impl PersistedEvent {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let relay_tx_hash: Option<String> = row.get("relay_tx_hash")?;
+        let journal: Option<Vec<u8>> = row.get("journal")?;
+        Ok(Self {
+            id: row.get("id")?,
+            chain_id: row.get::<_, i64>("chain_id")? as u64,
+            relay_address: parse_column(row, "relay_address")?,
+            block_number: row.get::<_, i64>("block_number")? as u64,
+            log_index: row.get::<_, i64>("log_index")? as u64,
+            tx_hash: parse_column(row, "tx_hash")?,
+            image_id: parse_column(row, "image_id")?,
+            request_id: row.get("request_id")?,
+            relay_tx_hash: match relay_tx_hash {
+                Some(_) => Some(parse_column(row, "relay_tx_hash")?),
+                None => None,
+            },
+            processed_at: row.get::<_, i64>("processed_at")? as u64,
+            status: parse_column(row, "status")?,
+            proof: match journal {
+                Some(journal) => Some(EventProof {
+                    journal,
+                    seal: row.get::<_, Option<Vec<u8>>>("seal")?.unwrap_or_default(),
+                    post_state_digest: parse_column(row, "post_state_digest")?,
+                }),
+                None => None,
+            },
+        })
+    }
+}
+
+/// Returns a row of the dead-letter table, with the ID of its request. The
+/// request of the row is the one of its session, without the requests
+/// attached to it, which have their own rows.
+fn dead_letter_from_row(row: &Row<'_>) -> rusqlite::Result<(String, DeadLetter)> {
+    let function_selector: Vec<u8> = row.get("function_selector")?;
+    let function_selector = function_selector.try_into().map_err(|_| {
+        let index = row
+            .as_ref()
+            .column_index("function_selector")
+            .unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            Type::Blob,
+            anyhow!("function selector is not 4 bytes long").into(),
+        )
+    })?;
+    let image_id: H256 = parse_column(row, "image_id")?;
+    let event = CallbackRequestFilter {
+        account: parse_column(row, "account")?,
+        image_id: image_id.into(),
+        input: row.get::<_, Vec<u8>>("input")?.into(),
+        callback_contract: parse_column(row, "callback_contract")?,
+        function_selector,
+        gas_limit: row.get::<_, i64>("gas_limit")? as u64,
+    };
+    Ok((
+        row.get("request_id")?,
+        DeadLetter {
+            request: ProofRequestInformation {
+                proof_request_id: SessionId::new(row.get("session_id")?),
+                chain_id: row.get::<_, i64>("chain_id")? as u64,
+                relay_contract_address: parse_column(row, "relay_address")?,
+                callback_proof_request_event: event,
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            },
+            stage: parse_column(row, "stage")?,
+            reason: row.get("reason")?,
+            failed_at: row.get::<_, i64>("failed_at")? as u64,
+        },
+    ))
+}
+
+/// Parse the text of a column.
+fn parse_column<T>(row: &Row<'_>, column: &str) -> rusqlite::Result<T>
+where
+    T: FromStr,
+    T::Err: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let text: String = row.get(column)?;
+    text.parse().map_err(|err: T::Err| {
+        let index = row.as_ref().column_index(column).unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, err.into())
+    })
+}
+
+/// Returns the position of a log on chain, if it was mined.
+pub(crate) fn log_key(log: &Log) -> Option<(H256, u64, u64)> {
+    Some((
+        log.transaction_hash?,
+        log.log_index?.as_u64(),
+        log.block_number?.as_u64(),
+    ))
+}
+
+/// Get the log of a recorded event from the receipt of its transaction, if
+/// still on chain.
+pub(crate) async fn event_log<M: Middleware>(
+    client: &M,
+    event: &PersistedEvent,
+) -> Result<Option<Log>> {
+    let receipt = client
+        .get_transaction_receipt(event.tx_hash)
+        .await
+        .map_err(|err| anyhow!("Failed to get the receipt of {:?}: {err}", event.tx_hash))?;
+    Ok(receipt.and_then(|receipt| {
+        receipt.logs.into_iter().find(|log| {
+            log.address == event.relay_address && log.log_index == Some(event.log_index.into())
+        })
+    }))
+}
+
+/// SQLite database of the processed relay events, shared by all chains.
+#[derive(Clone, Debug)]
+pub(crate) struct EventDb {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl EventDb {
+    /// Open the database at `path`, creating it if missing.
+    pub(crate) fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Failed to open event database {}", path.display()))?;
+        Self::with_connection(connection)
+            .with_context(|| format!("Failed to set up event database {}", path.display()))
+    }
+
+    /// Open a database held in memory, lost when dropped.
+    pub(crate) fn in_memory() -> Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Returns whether the event emitted at `log_index` by the transaction
+    /// `tx_hash` on the chain was recorded.
+    pub(crate) fn contains(&self, chain_id: u64, tx_hash: H256, log_index: u64) -> Result<bool> {
+        Ok(self
+            .connection()
+            .query_row(
+                "SELECT 1 FROM events WHERE chain_id = ?1 AND tx_hash = ?2 AND log_index = ?3",
+                params![chain_id as i64, format!("{tx_hash:?}"), log_index as i64],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Returns the event with the given ID, if recorded.
+    pub(crate) fn event(&self, id: i64) -> Result<Option<PersistedEvent>> {
+        Ok(self
+            .connection()
+            .query_row(
+                "SELECT * FROM events WHERE id = ?1",
+                params![id],
+                PersistedEvent::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Record an event sent to Bonsai under `request_id`, replacing any
+    /// previous record of it while keeping its ID.
+    pub(crate) fn record_received(
+        &self,
+        chain_id: u64,
+        log: &Log,
+        image_id: H256,
+        request_id: &str,
+    ) -> Result<()> {
+        self.record(chain_id, log, image_id, request_id, EventStatus::Received)
+    }
+
+    /// Record an event deferred at intake, without a request ID until it is
+    /// sent to Bonsai, so that it is processed again on the next start.
+    pub(crate) fn record_deferred(&self, chain_id: u64, log: &Log, image_id: H256) -> Result<()> {
+        self.record(chain_id, log, image_id, "", EventStatus::Deferred)
+    }
+
+    fn record(
+        &self,
+        chain_id: u64,
+        log: &Log,
+        image_id: H256,
+        request_id: &str,
+        status: EventStatus,
+    ) -> Result<()> {
+        let (tx_hash, log_index, block_number) =
+            log_key(log).ok_or_else(|| anyhow!("Log of a pending transaction"))?;
+        self.connection().execute(
+            "INSERT INTO events (chain_id, relay_address, block_number, log_index, tx_hash,
+                image_id, request_id, relay_tx_hash, processed_at, status)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, NULL, ?8, ?9)
+            ON CONFLICT (chain_id, tx_hash, log_index) DO UPDATE SET
+                relay_address = excluded.relay_address,
+                block_number = excluded.block_number,
+                image_id = excluded.image_id,
+                request_id = excluded.request_id,
+                relay_tx_hash = NULL,
+                processed_at = excluded.processed_at,
+                status = excluded.status,
+                journal = NULL,
+                seal = NULL,
+                post_state_digest = NULL",
+            params![
+                chain_id as i64,
+                format!("{:?}", log.address),
+                block_number as i64,
+                log_index as i64,
+                format!("{tx_hash:?}"),
+                format!("{image_id:?}"),
+                request_id,
+                events::now() as i64,
+                status.as_str(),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Set the status of the events of the Bonsai session `session_id`,
+    /// including the requests attached to it, whose request IDs are the
+    /// session ID followed by `-<n>`, returning the number of events updated.
+    pub(crate) fn set_session_status(
+        &self,
+        session_id: &str,
+        status: EventStatus,
+        relay_tx_hash: Option<H256>,
+    ) -> Result<usize> {
+        Ok(self.connection().execute(
+            "UPDATE events SET status = ?2, relay_tx_hash = COALESCE(?3, relay_tx_hash)
+            WHERE request_id = ?1 OR request_id LIKE ?1 || '-%'",
+            params![
+                session_id,
+                status.as_str(),
+                relay_tx_hash.map(|hash| format!("{hash:?}")),
+            ],
+        )?)
+    }
+
+    /// Record the proof of the events of the Bonsai session `session_id`,
+    /// including the requests attached to it, returning the number of events
+    /// updated.
+    pub(crate) fn set_session_proof(&self, session_id: &str, proof: &EventProof) -> Result<usize> {
+        Ok(self.connection().execute(
+            "UPDATE events SET journal = ?2, seal = ?3, post_state_digest = ?4
+            WHERE request_id = ?1 OR request_id LIKE ?1 || '-%'",
+            params![
+                session_id,
+                proof.journal,
+                proof.seal,
+                format!("{:?}", proof.post_state_digest),
+            ],
+        )?)
+    }
+
+    /// Set the status of a single event, and its relay transaction if given.
+    pub(crate) fn set_status(
+        &self,
+        event: &PersistedEvent,
+        status: EventStatus,
+        relay_tx_hash: Option<H256>,
+    ) -> Result<()> {
+        self.connection().execute(
+            "UPDATE events SET status = ?2, relay_tx_hash = COALESCE(?3, relay_tx_hash)
+            WHERE id = ?1",
+            params![
+                event.id,
+                status.as_str(),
+                relay_tx_hash.map(|hash| format!("{hash:?}")),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the events of the chain with the given status, oldest first.
+    pub(crate) fn events_with_status(
+        &self,
+        chain_id: u64,
+        status: EventStatus,
+    ) -> Result<Vec<PersistedEvent>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT * FROM events WHERE chain_id = ?1 AND status = ?2
+            ORDER BY block_number, log_index",
+        )?;
+        let rows = statement.query_map(
+            params![chain_id as i64, status.as_str()],
+            PersistedEvent::from_row,
+        )?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Remove the completed events processed more than `older_than` ago,
+    /// returning the number of events removed.
+    pub(crate) fn prune(&self, older_than: Duration) -> Result<usize> {
+        let cutoff = events::now().saturating_sub(older_than.as_millis() as u64);
+        Ok(self.connection().execute(
+            "DELETE FROM events WHERE status = ?1 AND processed_at < ?2",
+            params![EventStatus::Completed.as_str(), cutoff as i64],
+        )?)
+    }
+
+    /// Record a failed request of the dead-letter list, with the requests
+    /// attached to its session.
+    pub(crate) fn insert_dead_letter(&self, dead_letter: &DeadLetter) -> Result<()> {
+        let request = &dead_letter.request;
+        let session_id = &request.proof_request_id.uuid;
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        let events = std::iter::once((session_id, &request.callback_proof_request_event)).chain(
+            request
+                .attached_requests
+                .iter()
+                .map(|attached| (&attached.request_id, &attached.callback_proof_request_event)),
+        );
+        for (request_id, event) in events {
+            transaction.execute(
+                "INSERT INTO dead_letters (session_id, request_id, chain_id, relay_address,
+                    account, image_id, input, callback_contract, function_selector, gas_limit,
+                    stage, reason, failed_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    session_id,
+                    request_id,
+                    request.chain_id as i64,
+                    format!("{:?}", request.relay_contract_address),
+                    format!("{:?}", event.account),
+                    format!("{:?}", H256::from(event.image_id)),
+                    event.input.to_vec(),
+                    format!("{:?}", event.callback_contract),
+                    event.function_selector.to_vec(),
+                    event.gas_limit as i64,
+                    dead_letter.stage.as_str(),
+                    dead_letter.reason,
+                    dead_letter.failed_at as i64,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Remove the failed request of the Bonsai session `session_id` from the
+    /// dead-letter list, with the requests attached to it.
+    pub(crate) fn remove_dead_letter(&self, session_id: &str) -> Result<()> {
+        self.connection().execute(
+            "DELETE FROM dead_letters WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the failed requests of the dead-letter list, oldest first.
+    pub(crate) fn dead_letters(&self) -> Result<Vec<DeadLetter>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare("SELECT * FROM dead_letters ORDER BY id")?;
+        let rows = statement.query_map([], dead_letter_from_row)?;
+        let mut dead_letters: Vec<DeadLetter> = Vec::new();
+        for row in rows {
+            let (request_id, dead_letter) = row?;
+            let session_id = &dead_letter.request.proof_request_id.uuid;
+            if request_id == *session_id {
+                dead_letters.push(dead_letter);
+                continue;
+            }
+            // The row of an attached request follows the one of its session.
+            let Some(session) = dead_letters
+                .iter_mut()
+                .rev()
+                .find(|session| session.request.proof_request_id.uuid == *session_id)
+            else {
+                warn!(%request_id, "Dead letter attached to an unknown session");
+                continue;
+            };
+            session.request.attached_requests.push(AttachedRequest {
+                request_id,
+                callback_proof_request_event: dead_letter.request.callback_proof_request_event,
+            });
+        }
+        Ok(dead_letters)
+    }
+
+    /// Record the gas cost of the relay transaction `tx_hash`, and remove the
+    /// spends that left the window of the daily spend limit.
+    pub(crate) fn insert_spend(&self, tx_hash: H256, spend: &Spend) -> Result<()> {
+        let since = spend
+            .spent_at
+            .saturating_sub(SPEND_WINDOW.as_millis() as u64);
+        let mut connection = self.connection();
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO spends (chain_id, tx_hash, amount, spent_at) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                spend.chain_id as i64,
+                format!("{tx_hash:?}"),
+                spend.amount.to_string(),
+                spend.spent_at as i64,
+            ],
+        )?;
+        transaction.execute(
+            "DELETE FROM spends WHERE spent_at < ?1",
+            params![since as i64],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Returns the spends recorded since the Unix time `since`, in
+    /// milliseconds, oldest first.
+    pub(crate) fn spends_since(&self, since: u64) -> Result<Vec<Spend>> {
+        let connection = self.connection();
+        let mut statement = connection.prepare(
+            "SELECT chain_id, amount, spent_at FROM spends WHERE spent_at >= ?1 \
+             ORDER BY spent_at, id",
+        )?;
+        let rows = statement.query_map(params![since as i64], |row| {
+            let amount: String = row.get("amount")?;
+            let spent_at: i64 = row.get("spent_at")?;
+            let amount = U256::from_dec_str(&amount)
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, e.into()))?;
+            Ok(Spend {
+                chain_id: row.get::<_, i64>("chain_id")? as u64,
+                spent_at: spent_at as u64,
+                amount,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Remove all the recorded spends, e.g. when resuming the relay
+    /// transactions before the spend leaves the window.
+    pub(crate) fn clear_spends(&self) -> Result<()> {
+        self.connection().execute("DELETE FROM spends", [])?;
+        Ok(())
+    }
+
+    /// Update the status of the recorded events from a relayer event.
+    pub(crate) fn apply(&self, event: &RelayerEvent) -> Result<()> {
+        match event {
+            RelayerEvent::TxSubmitted {
+                tx_hash,
+                session_ids,
+                ..
+            } => {
+                for session_id in session_ids {
+                    self.set_session_status(session_id, EventStatus::Submitted, Some(*tx_hash))?;
+                }
+            }
+            RelayerEvent::TxConfirmed {
+                tx_hash,
+                session_ids,
+                ..
+            } => {
+                for session_id in session_ids {
+                    self.set_session_status(session_id, EventStatus::Completed, Some(*tx_hash))?;
+                }
+            }
+            RelayerEvent::RequestFailed {
+                session_id: Some(session_id),
+                ..
+            } => {
+                self.set_session_status(session_id, EventStatus::Failed, None)?;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+/// Record the status changes reported on `receiver` in the database and, if
+/// `prune_older_than` is set, remove the completed events older than it every
+/// [EVENT_PRUNE_INTERVAL], starting now.
+pub(crate) async fn record_events(
+    db: EventDb,
+    mut receiver: broadcast::Receiver<RelayerEvent>,
+    prune_older_than: Option<Duration>,
+) {
+    let mut prune = tokio::time::interval(EVENT_PRUNE_INTERVAL);
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => {
+                    if let Err(error) = db.apply(&event) {
+                        warn!(?error, "Failed to record the relayer event in the event database");
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(count)) => {
+                    warn!(count, "Relayer events dropped before being recorded");
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            _ = prune.tick(), if prune_older_than.is_some() => {
+                let older_than = prune_older_than.unwrap_or_default();
+                match db.prune(older_than) {
+                    Ok(0) => debug!("No completed events to prune"),
+                    Ok(pruned) => info!(
+                        pruned,
+                        older_than = %humantime::format_duration(older_than),
+                        "Pruned completed events"
+                    ),
+                    Err(error) => warn!(?error, "Failed to prune the event database"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lifecycle events published by the relayer to embedding applications.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ethers::core::types::{Address, H256, U256};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::broadcast,
+};
+
+use crate::{metrics::Metrics, RelayError};
+
+/// A lifecycle event of the relayer. Every event carries the Unix time, in
+/// milliseconds, at which it happened.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RelayerEvent {
+    /// A callback request was received, from the chain or the REST API.
+    RequestReceived {
+        timestamp: u64,
+        relay_address: Address,
+        image_id: H256,
+        callback_contract: Address,
+    },
+    /// A Bonsai session was created for a callback request.
+    SessionCreated {
+        timestamp: u64,
+        session_id: String,
+        image_id: H256,
+    },
+    /// The Bonsai session of a callback request completed.
+    ProofReady { timestamp: u64, session_id: String },
+    /// A relay transaction was sent on chain.
+    TxSubmitted {
+        timestamp: u64,
+        relay_address: Address,
+        tx_hash: H256,
+        session_ids: Vec<String>,
+    },
+    /// A relay transaction was confirmed on chain.
+    TxConfirmed {
+        timestamp: u64,
+        relay_address: Address,
+        tx_hash: H256,
+        session_ids: Vec<String>,
+    },
+    /// A callback request failed, in Bonsai or on chain.
+    RequestFailed {
+        timestamp: u64,
+        session_id: Option<String>,
+        error: String,
+    },
+    /// The connection to the Ethereum node was re-established.
+    EthReconnected { timestamp: u64 },
+    /// The connection to the Ethereum node of a chain could not be made again
+    /// within its reconnect attempts.
+    EthReconnectsExhausted {
+        timestamp: u64,
+        chain_id: u64,
+        error: String,
+    },
+    /// The balance of a signer of a chain dropped below the minimum, in wei,
+    /// and its relay transactions are sent by the other signers, or held back
+    /// until it is topped up.
+    LowBalance {
+        timestamp: u64,
+        chain_id: u64,
+        signer: Address,
+        balance: U256,
+        min_eth_balance: U256,
+    },
+    /// A signer of a chain failed, e.g. as its KMS key was disabled, and was
+    /// removed from rotation. Its address is unknown if it never signed.
+    SignerRemoved {
+        timestamp: u64,
+        chain_id: u64,
+        signer: Option<Address>,
+        error: String,
+        /// Signers of the chain still in rotation.
+        remaining: usize,
+    },
+    /// Relaying failed, e.g. a Bonsai request failed or a relay transaction
+    /// failed to be sent, confirmed, or reverted more than `retry_on_revert`
+    /// times.
+    RelayError {
+        timestamp: u64,
+        error: String,
+        tx_hash: Option<H256>,
+    },
+    /// The gas spent by the relay transactions of a chain over the last 24
+    /// hours reached the daily spend limit, in wei, and the relay transactions
+    /// of the chain are held back.
+    SpendLimitReached {
+        timestamp: u64,
+        chain_id: u64,
+        spent: U256,
+        max_daily_spend: U256,
+    },
+}
+
+/// Returns the current Unix time in milliseconds, as carried by a
+/// [RelayerEvent].
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Formats an error along with its chain of sources, as carried by a
+/// [RelayerEvent::RelayError].
+pub(crate) fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(&format!(": {cause}"));
+        source = cause.source();
+    }
+    message
+}
+
+/// Sending half of the channel the relayer publishes its [RelayerEvent]s on.
+///
+/// Publishing never blocks the relayer: once `capacity` events are waiting
+/// for a slow consumer, each new event drops the oldest one. The receiver then
+/// reports the number of events it missed as [broadcast::error::RecvError::Lagged],
+/// and the `bonsai_relay_events_dropped` metric counts them.
+#[derive(Clone, Debug)]
+pub struct EventSender {
+    sender: broadcast::Sender<RelayerEvent>,
+    capacity: usize,
+}
+
+/// Create a channel buffering up to `capacity` [RelayerEvent]s, rounded up to
+/// the next power of two.
+pub fn event_channel(capacity: usize) -> (EventSender, broadcast::Receiver<RelayerEvent>) {
+    let (sender, receiver) = broadcast::channel(capacity);
+    let sender = EventSender {
+        sender,
+        capacity: capacity.next_power_of_two(),
+    };
+    (sender, receiver)
+}
+
+impl EventSender {
+    /// Subscribe another receiver to the events sent from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<RelayerEvent> {
+        self.sender.subscribe()
+    }
+
+    pub(crate) fn send(&self, event: RelayerEvent) {
+        if self.sender.len() >= self.capacity {
+            Metrics::global().events_dropped.inc();
+        }
+        // Sending only fails when no receiver is left, in which case nobody
+        // is interested in the event.
+        let _ = self.sender.send(event);
+    }
+}
+
+/// Write the relayer events received on `receiver` as newline-delimited JSON,
+/// flushing each line so that the output can be followed with `tail -f`.
+pub async fn write_events(
+    mut receiver: broadcast::Receiver<RelayerEvent>,
+    mut out: impl AsyncWrite + Unpin,
+) -> Result<(), RelayError> {
+    loop {
+        let line = match receiver.recv().await {
+            Ok(event) => serde_json::to_string(&event).map_err(anyhow::Error::from)?,
+            Err(broadcast::error::RecvError::Lagged(count)) => {
+                serde_json::json!({ "event": "events_dropped", "count": count }).to_string()
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        out.write_all(format!("{line}\n").as_bytes()).await?;
+        out.flush().await?;
+    }
+}
+
+/// Publish `event` if the relayer was given an [EventSender].
+pub(crate) fn emit(events: &Option<EventSender>, event: RelayerEvent) {
+    if let Some(events) = events {
+        events.send(event);
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Periodic logging of the network fees, for operators tuning the fee cap of
+//! the relay transactions.
+
+use std::{collections::VecDeque, time::Duration};
+
+use ethers::types::U256;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::{uploader::completed_proofs::fee_cap::current_fee_per_gas, EthersClientConfig};
+
+/// Number of samples the moving average of the gas price is taken over.
+const GAS_PRICE_WINDOW: usize = 5;
+
+/// A gas price sample, in gwei, along with the moving average of the last
+/// samples and its change from the previous one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct GasPriceSample {
+    pub gas_price: f64,
+    pub moving_average: f64,
+    /// Change, in percent, from the previous sample, if any.
+    pub change_pct: Option<f64>,
+}
+
+/// The last [GAS_PRICE_WINDOW] gas price samples.
+#[derive(Debug, Default)]
+pub(crate) struct GasPriceWindow {
+    samples: VecDeque<f64>,
+}
+
+impl GasPriceWindow {
+    /// Record a gas price, in gwei, dropping the oldest sample once the
+    /// window is full.
+    pub(crate) fn record(&mut self, gas_price: f64) -> GasPriceSample {
+        let change_pct = self
+            .samples
+            .back()
+            .filter(|previous| **previous > 0.0)
+            .map(|previous| (gas_price - previous) / previous * 100.0);
+        if self.samples.len() == GAS_PRICE_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(gas_price);
+        GasPriceSample {
+            gas_price,
+            moving_average: self.samples.iter().sum::<f64>() / self.samples.len() as f64,
+            change_pct,
+        }
+    }
+}
+
+/// Log the fee per gas of the chain of `client_config` every `interval`,
+/// along with its moving average and change from the previous sample, until
+/// aborted.
+pub(crate) async fn watch_gas_price(client_config: EthersClientConfig, interval: Duration) {
+    let chain_id = client_config.eth_chain_id;
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut provider = None;
+    let mut window = GasPriceWindow::default();
+    loop {
+        ticks.tick().await;
+        if provider.is_none() {
+            match client_config.provider().await {
+                Ok(connected) => provider = Some(connected),
+                Err(err) => {
+                    warn!(
+                        chain_id,
+                        %err,
+                        "failed to connect to the Ethereum node to watch the gas price"
+                    );
+                    continue;
+                }
+            }
+        }
+        let fee = current_fee_per_gas(provider.as_ref().expect("connected provider")).await;
+        let fee = match fee {
+            Ok(fee) => fee,
+            Err(err) => {
+                warn!(chain_id, %err, "failed to get the gas price");
+                // Reconnect on the next sample.
+                provider = None;
+                continue;
+            }
+        };
+        let sample = window.record(gwei(fee));
+        info!(
+            chain_id,
+            gas_price_gwei = round(sample.gas_price),
+            moving_average_gwei = round(sample.moving_average),
+            change_pct = sample.change_pct.map(round),
+            "gas price"
+        );
+    }
+}
+
+fn gwei(wei: U256) -> f64 {
+    u128::try_from(wei).unwrap_or(u128::MAX) as f64 / 1e9
+}
+
+/// Round to two decimals, for the logs.
+fn round(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
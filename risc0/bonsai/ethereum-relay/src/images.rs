@@ -0,0 +1,103 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Guest images the relayer uploads to Bonsai at runtime, e.g. after a local
+//! Bonsai instance was wiped.
+
+use std::sync::Arc;
+
+use bonsai_sdk::alpha::SdkErr;
+use ethers::core::types::H256;
+use serde::Serialize;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::bonsai_transport::BonsaiTransport;
+
+/// The ELF binary of a guest, uploaded to Bonsai by the relayer on demand.
+#[derive(Clone, Debug)]
+pub struct GuestImage {
+    /// Name of the guest binary.
+    pub name: String,
+    /// Image ID of the guest binary.
+    pub image_id: H256,
+    /// ELF binary of the guest.
+    pub elf: Arc<[u8]>,
+}
+
+/// Result of uploading a [GuestImage] to Bonsai.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub(crate) struct ImageUploadResult {
+    /// Name of the guest binary.
+    pub name: String,
+    /// Hex-encoded image ID of the guest binary.
+    pub image_id: String,
+    /// Whether the image was uploaded, as opposed to already known by Bonsai.
+    pub uploaded: bool,
+    /// Reason the upload failed, if it did.
+    pub error: Option<String>,
+}
+
+/// Whether a Bonsai error reports that the image of a session is unknown.
+pub(crate) fn is_image_not_found(err: &SdkErr) -> bool {
+    let SdkErr::InternalServerErr(body) = err else {
+        return false;
+    };
+    let body = body.to_lowercase();
+    body.contains("image") && body.contains("not found")
+}
+
+/// Upload `image` to Bonsai. Returns whether it was uploaded, as opposed to
+/// already known by Bonsai.
+pub(crate) async fn upload_image(
+    client: &dyn BonsaiTransport,
+    image: &GuestImage,
+) -> Result<bool, SdkErr> {
+    match client
+        .put_image(hex::encode(image.image_id), image.elf.to_vec())
+        .await
+    {
+        Ok(()) => {
+            info!(name = %image.name, image_id = ?image.image_id, "uploaded image to Bonsai");
+            Ok(true)
+        }
+        Err(SdkErr::ImageIdExists) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Upload each of `images` to Bonsai, carrying on past failed uploads.
+pub(crate) async fn upload_images(
+    client: &dyn BonsaiTransport,
+    images: &[GuestImage],
+) -> Vec<ImageUploadResult> {
+    let mut results = Vec::with_capacity(images.len());
+    for image in images {
+        let result = upload_image(client, image).await;
+        if let Err(error) = &result {
+            warn!(
+                ?error,
+                name = %image.name,
+                "failed to upload image to Bonsai"
+            );
+        }
+        results.push(ImageUploadResult {
+            name: image.name.clone(),
+            image_id: hex::encode(image.image_id),
+            uploaded: matches!(result, Ok(true)),
+            error: result.err().map(|err| err.to_string()),
+        });
+    }
+    results
+}
@@ -0,0 +1,138 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Intake of new callback requests, from the relay contracts and the REST
+//! API. The intake is closed while the relayer drains before a maintenance,
+//! and capped to a maximum number of requests in flight, so that a backlog
+//! never grows unbounded. The requests taken in get their Bonsai session from
+//! the [Scheduler].
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc,
+};
+
+use ethers::types::H256;
+use tracing::info;
+
+use crate::{
+    api::error::Error,
+    scheduler::Scheduler,
+    storage::{InFlightRequests, Storage},
+};
+
+/// Reason for which a new callback request is not taken in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub(crate) enum IntakeClosed {
+    #[error("the relayer is draining for maintenance and takes no new requests")]
+    Draining,
+    #[error("{max} requests are already in flight")]
+    Full { max: usize },
+    #[error("no session is free for image {image_id:?}")]
+    Busy { image_id: H256 },
+    #[error("the daily session budget of image {image_id:?} is spent")]
+    BudgetSpent { image_id: H256 },
+}
+
+impl IntakeClosed {
+    /// Whether the intake is closed to the requests of all images, rather
+    /// than to those of a single image.
+    pub(crate) fn is_global(&self) -> bool {
+        matches!(self, IntakeClosed::Draining | IntakeClosed::Full { .. })
+    }
+}
+
+/// Whether new callback requests are taken in, shared by the event streams of
+/// all chains and the REST API.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Intake {
+    draining: Arc<AtomicBool>,
+    /// Maximum number of sessions in flight, if capped.
+    max_pending_requests: Option<usize>,
+    /// Number of on-chain events deferred at intake, to be processed once the
+    /// intake reopens.
+    deferred_events: Arc<AtomicUsize>,
+    scheduler: Scheduler,
+}
+
+impl Intake {
+    pub(crate) fn new(max_pending_requests: Option<usize>) -> Self {
+        Self {
+            max_pending_requests,
+            ..Self::default()
+        }
+    }
+
+    /// Hand out the sessions of the requests taken in with the given
+    /// scheduler.
+    pub(crate) fn with_scheduler(self, scheduler: Scheduler) -> Self {
+        Self { scheduler, ..self }
+    }
+
+    pub(crate) fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Stop taking in new requests, letting the ones in flight finish.
+    pub(crate) fn drain(&self) {
+        if !self.draining.swap(true, Ordering::SeqCst) {
+            info!("Draining: no longer taking in new callback requests");
+        }
+    }
+
+    /// Take in new requests again after a drain.
+    pub(crate) fn resume(&self) {
+        if self.draining.swap(false, Ordering::SeqCst) {
+            info!("Resuming the intake of new callback requests");
+        }
+    }
+
+    pub(crate) fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Check that a new request can be taken in: the relayer is not draining,
+    /// and fewer than the maximum number of sessions are in flight.
+    pub(crate) async fn admit<S: Storage + Sync>(&self, storage: &S) -> Result<(), Error> {
+        if self.is_draining() {
+            return Err(IntakeClosed::Draining.into());
+        }
+        if let Some(max) = self.max_pending_requests {
+            if storage.count_in_flight_requests().await?.total() >= max {
+                return Err(IntakeClosed::Full { max }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of sessions in flight, and of deferred on-chain
+    /// events.
+    pub(crate) async fn in_flight<S: Storage + Sync>(
+        &self,
+        storage: &S,
+    ) -> Result<(InFlightRequests, usize), Error> {
+        let in_flight = storage.count_in_flight_requests().await?;
+        Ok((in_flight, self.deferred_events.load(Ordering::SeqCst)))
+    }
+
+    /// Record that `count` more on-chain events are deferred.
+    pub(crate) fn add_deferred_events(&self, count: usize) {
+        self.deferred_events.fetch_add(count, Ordering::SeqCst);
+    }
+
+    /// Record that `count` deferred on-chain events were taken in, or dropped.
+    pub(crate) fn remove_deferred_events(&self, count: usize) {
+        self.deferred_events.fetch_sub(count, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON-RPC batches (EIP-1474) to the Ethereum node. The ethers transports
+//! send one request per message, and the pinned ethers-providers has no batch
+//! API, so a batch goes over a connection of its own: a POST to HTTP nodes,
+//! or a text frame to WebSocket nodes.
+
+use anyhow::{anyhow, bail, Context, Result};
+use ethers::providers::JsonRpcError;
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+/// Response to a request of a JSON-RPC batch.
+#[derive(Deserialize)]
+struct BatchResponse {
+    id: usize,
+    #[serde(default)]
+    result: Option<Value>,
+    #[serde(default)]
+    error: Option<JsonRpcError>,
+}
+
+/// Send the `requests`, as methods and their params, to the node at `url` in
+/// a single JSON-RPC batch, returning the result of each request in order. A
+/// failed request doesn't fail the others.
+pub(crate) async fn json_rpc_batch(
+    url: &str,
+    requests: &[(&str, Value)],
+) -> Result<Vec<Result<Value, JsonRpcError>>> {
+    let batch: Vec<Value> = requests
+        .iter()
+        .enumerate()
+        .map(|(id, (method, params))| {
+            json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params})
+        })
+        .collect();
+    let responses: Vec<BatchResponse> = if url.starts_with("ws://") || url.starts_with("wss://") {
+        let (mut socket, _) = connect_async(url)
+            .await
+            .context("failed to connect to the Ethereum node")?;
+        socket
+            .send(Message::Text(Value::from(batch).to_string()))
+            .await
+            .context("failed to send the JSON-RPC batch")?;
+        // Frames other than the response, e.g. pings, are skipped.
+        let response = loop {
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => break text.into_bytes(),
+                Some(Ok(Message::Binary(bytes))) => break bytes,
+                Some(Ok(_)) => continue,
+                Some(Err(err)) => return Err(err.into()),
+                None => bail!("the Ethereum node closed the connection"),
+            }
+        };
+        let _ = socket.close(None).await;
+        serde_json::from_slice(&response).context("invalid JSON-RPC batch response")?
+    } else if url.starts_with("http://") || url.starts_with("https://") {
+        reqwest::Client::new()
+            .post(url)
+            .json(&batch)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .context("failed to send the JSON-RPC batch")?
+            .json()
+            .await
+            .context("invalid JSON-RPC batch response")?
+    } else {
+        bail!("JSON-RPC batches are only sent to Ethereum nodes reached over WebSocket or HTTP");
+    };
+
+    // Responses may come in any order.
+    let mut results: Vec<Option<Result<Value, JsonRpcError>>> = std::iter::repeat_with(|| None)
+        .take(requests.len())
+        .collect();
+    for response in responses {
+        let result = match (response.result, response.error) {
+            (_, Some(error)) => Err(error),
+            (result, None) => Ok(result.unwrap_or(Value::Null)),
+        };
+        if let Some(slot) = results.get_mut(response.id) {
+            *slot = Some(result);
+        }
+    }
+    results
+        .into_iter()
+        .zip(requests)
+        .map(|(result, (method, _))| {
+            result.ok_or_else(|| anyhow!("no response to {method} in the JSON-RPC batch"))
+        })
+        .collect()
+}
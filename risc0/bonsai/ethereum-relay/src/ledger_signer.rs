@@ -0,0 +1,300 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signer of the relay transactions holding its key on a Ledger hardware
+//! wallet, on which each transaction is confirmed by hand. Ledger devices are
+//! only reached with the `ledger` feature, which links the USB HID library
+//! of the host.
+
+#[cfg(feature = "ledger")]
+use std::collections::HashMap;
+use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+#[cfg(feature = "ledger")]
+use ethers::signers::Signer;
+use ethers::types::{transaction::eip2718::TypedTransaction, Address, Signature};
+#[cfg(feature = "ledger")]
+use ethers_signers::{HDPath, Ledger, LedgerError};
+#[cfg(feature = "ledger")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "ledger")]
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::RelayError;
+
+/// Prefix of the wallet key identifiers naming the derivation path of a
+/// Ledger account, e.g. `ledger:m/44'/60'/0'/0/0`.
+pub const LEDGER_PREFIX: &str = "ledger:";
+
+/// Default time to wait for a transaction to be confirmed on the Ledger.
+pub const DEFAULT_LEDGER_SIGNING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Default time to wait on startup for the Ledger to be connected and
+/// unlocked, with the Ethereum app open.
+pub const DEFAULT_LEDGER_UNLOCK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Interval between the connection attempts to a locked Ledger.
+#[cfg(feature = "ledger")]
+const LEDGER_UNLOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Ledger accounts connected to so far, by derivation path and chain ID. The
+/// device is opened once, rather than on every batch of relay transactions.
+#[cfg(feature = "ledger")]
+static LEDGER_DEVICES: Lazy<Mutex<HashMap<(String, u64), Arc<dyn LedgerDevice>>>> =
+    Lazy::new(Default::default);
+
+/// How to use the Ledger when the wallet key identifier is a Ledger
+/// derivation path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerConfig {
+    /// Time to wait for each transaction to be confirmed on the device.
+    pub signing_timeout: Duration,
+    /// Time to wait on startup for the device to be unlocked.
+    pub unlock_timeout: Duration,
+}
+
+impl Default for LedgerConfig {
+    fn default() -> Self {
+        Self {
+            signing_timeout: DEFAULT_LEDGER_SIGNING_TIMEOUT,
+            unlock_timeout: DEFAULT_LEDGER_UNLOCK_TIMEOUT,
+        }
+    }
+}
+
+impl LedgerConfig {
+    /// Returns a signer of the Ledger account at the derivation `path`,
+    /// waiting for the device to be unlocked on first use.
+    #[cfg(feature = "ledger")]
+    pub(crate) async fn signer(
+        &self,
+        path: &str,
+        chain_id: u64,
+    ) -> Result<LedgerSigner, RelayError> {
+        let mut devices = LEDGER_DEVICES.lock().await;
+        let device = match devices.get(&(path.to_string(), chain_id)) {
+            Some(device) => device.clone(),
+            None => {
+                let device = self
+                    .connect(path, LEDGER_UNLOCK_POLL_INTERVAL, || async {
+                        let ledger = Ledger::new(HDPath::Other(path.to_string()), chain_id).await?;
+                        Ok(Arc::new(ledger) as Arc<dyn LedgerDevice>)
+                    })
+                    .await?;
+                devices.insert((path.to_string(), chain_id), device.clone());
+                device
+            }
+        };
+        Ok(self.signer_of(device, path, chain_id))
+    }
+
+    /// Fails, as Ledger devices are only reached with the `ledger` feature.
+    #[cfg(not(feature = "ledger"))]
+    pub(crate) async fn signer(
+        &self,
+        path: &str,
+        _chain_id: u64,
+    ) -> Result<LedgerSigner, RelayError> {
+        Err(RelayError::ConfigError(format!(
+            "the relayer was built without the `ledger` feature, required to use Ledger account \
+             {path}"
+        )))
+    }
+
+    /// Connect to the Ledger with `connect`, retrying every `poll_interval`
+    /// until the device is unlocked or the unlock timeout elapses.
+    #[cfg_attr(not(feature = "ledger"), allow(dead_code))]
+    pub(crate) async fn connect<F, Fut>(
+        &self,
+        path: &str,
+        poll_interval: Duration,
+        connect: F,
+    ) -> Result<Arc<dyn LedgerDevice>, RelayError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<Arc<dyn LedgerDevice>, LedgerDeviceError>>,
+    {
+        let connected = async {
+            loop {
+                match connect().await {
+                    Ok(device) => return device,
+                    Err(err) => {
+                        warn!(
+                            path,
+                            "Waiting for the Ledger to be unlocked, with the Ethereum app open: {err}"
+                        );
+                        tokio::time::sleep(poll_interval).await;
+                    }
+                }
+            }
+        };
+        let device = tokio::time::timeout(self.unlock_timeout, connected)
+            .await
+            .map_err(|_| {
+                RelayError::ConfigError(format!(
+                    "the Ledger was not unlocked within {} to use account {path}",
+                    humantime::format_duration(self.unlock_timeout)
+                ))
+            })?;
+        info!(path, address = ?device.address(), "Signing with the Ledger account");
+        Ok(device)
+    }
+
+    #[cfg_attr(not(feature = "ledger"), allow(dead_code))]
+    pub(crate) fn signer_of(
+        &self,
+        device: Arc<dyn LedgerDevice>,
+        path: &str,
+        chain_id: u64,
+    ) -> LedgerSigner {
+        LedgerSigner {
+            device,
+            path: path.to_string(),
+            chain_id,
+            signing_timeout: self.signing_timeout,
+        }
+    }
+}
+
+/// A Ledger account, abstracted over its transport so that the signer can be
+/// exercised without a device.
+#[async_trait]
+pub(crate) trait LedgerDevice: Debug + Send + Sync {
+    fn address(&self) -> Address;
+
+    async fn sign_transaction(&self, tx: &TypedTransaction)
+        -> Result<Signature, LedgerDeviceError>;
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, LedgerDeviceError>;
+}
+
+#[cfg(feature = "ledger")]
+#[async_trait]
+impl LedgerDevice for Ledger {
+    fn address(&self) -> Address {
+        Signer::address(self)
+    }
+
+    async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<Signature, LedgerDeviceError> {
+        Ok(Signer::sign_transaction(self, tx).await?)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, LedgerDeviceError> {
+        Ok(Signer::sign_message(self, message).await?)
+    }
+}
+
+/// Failure of a Ledger device, e.g. as it is locked or a signature was
+/// rejected on it.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct LedgerDeviceError(pub String);
+
+#[cfg(feature = "ledger")]
+impl From<LedgerError> for LedgerDeviceError {
+    fn from(err: LedgerError) -> Self {
+        Self(err.to_string())
+    }
+}
+
+/// Signer of a Ledger account. Each signature is confirmed on the device,
+/// within the signing timeout.
+#[derive(Clone, Debug)]
+pub struct LedgerSigner {
+    device: Arc<dyn LedgerDevice>,
+    path: String,
+    chain_id: u64,
+    signing_timeout: Duration,
+}
+
+impl LedgerSigner {
+    /// Derivation path of the account, e.g. `m/44'/60'/0'/0/0`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn address(&self) -> Address {
+        self.device.address()
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub(crate) fn with_chain_id(self, chain_id: u64) -> Self {
+        Self { chain_id, ..self }
+    }
+
+    pub(crate) fn error(&self, source: LedgerSignerError) -> crate::RelaySignerError {
+        crate::RelaySignerError::Ledger {
+            path: self.path.clone(),
+            source,
+        }
+    }
+
+    pub(crate) async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<Signature, LedgerSignerError> {
+        let mut tx = tx.clone();
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        info!(
+            path = %self.path,
+            to = ?tx.to_addr(),
+            nonce = ?tx.nonce(),
+            "Confirm the relay transaction on the Ledger"
+        );
+        self.confirmed(self.device.sign_transaction(&tx)).await
+    }
+
+    pub(crate) async fn sign_message(
+        &self,
+        message: &[u8],
+    ) -> Result<Signature, LedgerSignerError> {
+        info!(
+            path = %self.path,
+            "Confirm the message signature on the Ledger"
+        );
+        self.confirmed(self.device.sign_message(message)).await
+    }
+
+    async fn confirmed(
+        &self,
+        signature: impl Future<Output = Result<Signature, LedgerDeviceError>>,
+    ) -> Result<Signature, LedgerSignerError> {
+        match tokio::time::timeout(self.signing_timeout, signature).await {
+            Ok(signature) => Ok(signature?),
+            Err(_) => Err(LedgerSignerError::Timeout {
+                timeout: self.signing_timeout,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LedgerSignerError {
+    #[error(transparent)]
+    Device(#[from] LedgerDeviceError),
+    #[error("not confirmed on the device within {}", humantime::format_duration(*.timeout))]
+    Timeout { timeout: Duration },
+    #[error("{method} is not supported by Ledger signers")]
+    Unsupported { method: String },
+}
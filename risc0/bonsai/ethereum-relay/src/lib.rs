@@ -16,37 +16,151 @@
 
 pub mod sdk;
 
-pub use sdk::{CallbackRequest, Client, ClientError};
+pub use sdk::{CallbackRequest, Client, ClientError, ProofSubmission, SubmittedProof};
 
+mod alert_cmd;
+mod alerts;
 mod api;
+mod bonsai_transport;
+mod chains;
 mod client_config;
 mod downloader;
+mod error;
+mod eth_node;
+mod event_db;
+mod events;
+mod gas_watch;
+mod images;
+mod intake;
+mod json_rpc_batch;
+mod ledger_signer;
+mod metrics;
+mod networks;
+mod pinging_ws;
+mod proof_encoding;
+mod reload;
+mod remote_signer;
+mod resubmit;
+mod scheduler;
+mod signer;
+mod spend;
 mod storage;
 mod tests;
 mod uploader;
 
-use std::sync::Arc;
+use std::{collections::HashMap, future::Future, path::PathBuf, sync::Arc, time::Duration};
 
+pub use alert_cmd::{
+    AlertCommand, DEFAULT_ALERT_CMD_COOLDOWN, DEFAULT_BONSAI_UNREACHABLE_ALERT,
+    DEFAULT_DEAD_LETTER_ALERT_THRESHOLD,
+};
 use anyhow::{Context, Result};
-use bonsai_sdk::alpha_async::get_client_from_parts;
-pub use client_config::EthersClientConfig;
+pub use api::{
+    limits::{RateLimit, DEFAULT_MAX_REQUEST_BODY_SIZE},
+    tls::RestApiTls,
+};
+use bonsai_sdk::{alpha::responses::SnarkProof, alpha_async::get_round_robin_client_from_parts};
+pub use bonsai_transport::BonsaiTransport;
+#[cfg(feature = "grpc")]
+pub use bonsai_transport::GrpcBonsaiClient;
+pub use chains::{read_chain_configs, ChainConfig};
+pub use client_config::{
+    ChainState, EthersClientConfig, WalletKey, WalletKeyIdentifier, DEFAULT_ETH_NODE_POLL_INTERVAL,
+    DEFAULT_ETH_NODE_TIMEOUT, DEFAULT_WS_HEARTBEAT_INTERVAL, DEFAULT_WS_STALE_TIMEOUT,
+};
 use downloader::{
     proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
-    proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
+    proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream, replay::HistoryScan,
+};
+pub use error::RelayError;
+pub use eth_node::{
+    EthNodeEndpoint, EthNodeTransport, EthNodeTransportError, ReconnectingIpc, WsPing, WsPool,
+    DEFAULT_ETH_NODE_POOL_SIZE, DEFAULT_WS_PING_INTERVAL, DEFAULT_WS_PONG_TIMEOUT,
+};
+use ethers::core::types::{Address, Bytes, H256, U256};
+use event_db::EventDb;
+pub use events::{event_channel, write_events, EventSender, RelayerEvent};
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+pub use images::GuestImage;
+use intake::Intake;
+pub use ledger_signer::{
+    LedgerConfig, LedgerDeviceError, LedgerSigner, LedgerSignerError,
+    DEFAULT_LEDGER_SIGNING_TIMEOUT, DEFAULT_LEDGER_UNLOCK_TIMEOUT, LEDGER_PREFIX,
+};
+pub use networks::Network;
+pub use pinging_ws::{PingingWs, PingingWsError};
+pub use proof_encoding::CompressedSnarkProof;
+pub use remote_signer::{
+    RemoteSigner, RemoteSignerConfig, RemoteSignerError, REMOTE_SIGNER_PREFIX,
+};
+pub use resubmit::resubmit_event;
+use risc0_zkvm::sha::Digest;
+use scheduler::Scheduler;
+pub use signer::{KmsConfig, RelaySigner, RelaySignerError};
+use spend::SpendLimit;
+use storage::{dead_letters::DeadLetters, in_memory::InMemoryStorage, Storage};
+use tokio::{
+    sync::{mpsc, watch, Notify},
+    task::AbortHandle,
+};
+use tracing::{error, info, info_span, warn, Instrument};
+pub use uploader::completed_proofs::{
+    complete_proof::DryRunCallback,
+    fee_cap::{FeeCap, DEFAULT_FEE_QUEUE_DEPTH, FEE_CAP_POLL_INTERVAL},
+    gas_limit::CallbackGasLimits,
+    private_relay::{PrivateRelay, DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS},
+    relay::SafeConfig,
+    snark::{calldata_to_proof, proof_to_calldata, validate_snark_proof},
 };
-use ethers::core::types::Address;
-use storage::{in_memory::InMemoryStorage, Storage};
-use tokio::sync::Notify;
-use tracing::info;
 use uploader::{
-    completed_proofs::manager::BonsaiCompleteProofManager,
+    completed_proofs::{
+        complete_proof::relay_calldata,
+        manager::{BonsaiCompleteProofManager, PendingTransactions},
+        private_relay::TxSubmitter,
+        relay::{CallbackRelay, DirectRelay, SafeRelay},
+        signer_pool::SignerPool,
+    },
     pending_proofs::manager::BonsaiPendingProofManager,
 };
 
-use crate::api::{server::serve, state::ApiState};
+use crate::{
+    api::{
+        limits::ApiLimits,
+        server::{serve, ApiListener},
+        state::{ApiState, RelayContract, SubmissionChain, SubmissionConfig},
+    },
+    reload::{HotSettings, RelayLimits, Reloader},
+};
 
 static DEFAULT_FILTER: &str = "info";
 
+/// Default time to wait for in-flight transactions to be confirmed on
+/// shutdown.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default minimum wallet balance, in ether, required to submit relay
+/// transactions.
+pub const DEFAULT_MIN_ETH_BALANCE: &str = "0.01";
+
+/// Default time without being mined after which a relay transaction is
+/// considered stuck, and resubmitted with bumped fees.
+pub const DEFAULT_STUCK_TX_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Default percentage by which the fees of a stuck relay transaction are
+/// bumped when resubmitting it.
+pub const DEFAULT_TX_RESUBMIT_BUMP: u64 = 10;
+
+/// Default number of relay transactions in flight at once, on each chain.
+pub const DEFAULT_MAX_CONCURRENT_RELAYS: usize = 10;
+
+/// Default number of completed proofs queued for relaying, on each chain,
+/// beyond which the oldest ones are dropped.
+pub const DEFAULT_RELAY_QUEUE_DEPTH: usize = 100;
+
+/// Default number of failed requests kept in the dead-letter list, beyond
+/// which the oldest ones are evicted.
+pub const DEFAULT_MAX_DEAD_LETTERS: usize = 1000;
+
 #[derive(Clone)]
 /// A relayer to integrate Ethereum with Bonsai.
 pub struct Relayer {
@@ -54,132 +168,870 @@ pub struct Relayer {
     pub rest_api: bool,
     /// Toggle for generating real or fake receipts.
     pub dev_mode: bool,
+    /// Address the relayer REST API binds to.
+    pub rest_api_bind: String,
     /// Port serving the relayer REST API.
     pub rest_api_port: String,
+    /// Certificate and key to serve the REST API over TLS with, reloaded on
+    /// SIGHUP. The REST API is served in plaintext if unset.
+    pub rest_api_tls: Option<RestApiTls>,
     /// Bonsai API URL.
     pub bonsai_api_url: String,
+    /// URLs of further Bonsai API instances, e.g. behind a load balancer,
+    /// taking turns with `bonsai_api_url` to upload images and poll
+    /// sessions. A request failing with a 5xx status is retried on the next
+    /// instance. The other requests go to `bonsai_api_url`.
+    pub bonsai_api_urls: Vec<String>,
+    /// Send the image uploads, session creations and session polls to the
+    /// gRPC API of Bonsai, at the same URLs, instead of its REST API. Requires
+    /// the `grpc` feature.
+    pub grpc: bool,
     /// Bonsai API key.
     pub bonsai_api_key: String,
-    /// The Ethereum address of the deployed Bonsai Relay contract.
-    pub relay_contract_address: Address,
+    /// The Ethereum addresses of the deployed Bonsai Relay contracts. The
+    /// callback requests of each contract are relayed back through it, from
+    /// the same wallet.
+    pub relay_contract_addresses: Vec<Address>,
+    /// Time to wait for in-flight transactions to be confirmed on shutdown.
+    pub shutdown_timeout: Duration,
+    /// Safe multi-sig wallet to send the relay transactions through, if any.
+    pub safe: Option<SafeConfig>,
+    /// Image IDs of the callback requests to process. If empty, all callback
+    /// requests are processed.
+    pub image_ids: Vec<H256>,
+    /// Toggle to relay SNARK proofs without checking their points are valid
+    /// BN254 curve points.
+    pub skip_proof_validation: bool,
+    /// Minimum wallet balance, in wei, required to submit a relay transaction.
+    /// Below it, transactions are held back until the wallet is topped up.
+    pub min_eth_balance: U256,
+    /// Cap on the fees of the relay transactions, if any. While the network
+    /// fees exceed it, transactions are held back and the fees checked again
+    /// every [FEE_CAP_POLL_INTERVAL].
+    pub fee_cap: Option<FeeCap>,
+    /// Number of times a relay transaction reverted on chain is resubmitted,
+    /// unless its revert reason shows it would revert again.
+    pub retry_on_revert: u32,
+    /// Time without being mined after which a relay transaction is
+    /// resubmitted, with the same nonce and bumped fees.
+    pub stuck_tx_timeout: Duration,
+    /// Percentage by which the fees of a stuck relay transaction are bumped,
+    /// up to 5 times. Stuck transactions are not resubmitted if zero.
+    pub tx_resubmit_bump: u64,
+    /// Number of blocks mined on top of a relay transaction during which it
+    /// is followed, on new block headers, to check it is still on chain. If a
+    /// reorg drops it, the batch is resubmitted. Mined transactions are not
+    /// followed if zero.
+    pub reorg_depth: u64,
+    /// Number of relay transactions in flight at once, on each chain, from
+    /// being sent until confirmed. Further completed proofs are queued.
+    pub max_concurrent_relays: usize,
+    /// Number of completed proofs queued for relaying, on each chain. When
+    /// full, the oldest queued proof is dropped, failing its request.
+    pub relay_queue_depth: usize,
+    /// Limit, in wei, on the gas spent by the relay transactions of each chain
+    /// over a rolling 24-hour window, gas used times effective gas price.
+    /// Once reached, the relay transactions of the chain are held back, their
+    /// requests in the `spend_limited` state, the readiness route reports the
+    /// relayer as not ready, and an alert is posted to the `alert_webhook`, if
+    /// any. The spend is recorded in the event database, if any, across
+    /// restarts. Unlimited if unset.
+    pub max_daily_spend: Option<U256>,
+    /// Adjustment of the gas limits of the callbacks to their `eth_estimateGas`
+    /// estimate before relaying them, if any. The requests of callbacks
+    /// estimated to use more gas than they may be given fail at the
+    /// `precheck` stage, rather than running out of gas on chain. The gas
+    /// limits of the callback requests are used as is if unset.
+    pub callback_gas_limits: Option<CallbackGasLimits>,
+    /// Private relay, e.g. Flashbots Protect, to submit the relay
+    /// transactions of the chain of the client config to instead of the
+    /// public mempool, if any. Transactions not included within its fallback
+    /// blocks are broadcast to the public mempool.
+    pub private_relay: Option<PrivateRelay>,
+    /// Interval at which the fee per gas of each chain is logged, along with
+    /// its moving average over the last 5 samples and its change from the
+    /// previous one, e.g. to tune the fee cap. Not logged if unset.
+    pub watch_gas_price: Option<Duration>,
+    /// Prefixes of the journals of the proofs to relay, e.g. the 4-byte
+    /// selector of a proposal type. A proof whose journal starts with none of
+    /// them is not relayed, its request left in the `skipped` state. All
+    /// proofs are relayed if empty.
+    pub journal_filters: Vec<Vec<u8>>,
+    /// Channel to publish the lifecycle [RelayerEvent]s of the relayer on, if
+    /// any. See [event_channel].
+    pub events: Option<EventSender>,
+    /// Toggle to relay pre-generated proofs, submitted to the REST API as
+    /// [ProofSubmission]s, instead of proving the callback requests on Bonsai.
+    /// The `image_ids` then restrict the proofs accepted for submission.
+    pub submission_only: bool,
+    /// File of `CallbackRequest` logs to process on start, as if they arrived
+    /// live, e.g. to recover requests missed while disconnected from the
+    /// Ethereum node of the client config. Each line is a JSON serialized
+    /// [ethers::types::Log].
+    /// Logs whose transaction is no longer on chain, e.g. after a reorg, are
+    /// skipped.
+    pub replay_events: Option<PathBuf>,
+    /// Block from which the `CallbackRequest` events of the chain of the
+    /// client config are scanned on start, and processed before subscribing to
+    /// new events, e.g. to recover the requests emitted while the relayer was
+    /// down. Only new events are processed if unset.
+    pub start_block: Option<u64>,
+    /// Last block scanned from `start_block`, the head block if unset. The
+    /// blocks after it are not scanned.
+    pub end_block: Option<u64>,
+    /// URL to POST a JSON alert to whenever relaying fails, e.g. on a failed
+    /// relay transaction or Bonsai request, such as a Slack or PagerDuty
+    /// webhook. Failing to post an alert never stops the relayer.
+    pub alert_webhook: Option<String>,
+    /// Program run with a JSON alert on its stdin when the reconnects to an
+    /// Ethereum node are exhausted, a wallet balance drops below the minimum,
+    /// Bonsai is unreachable or the dead letters cross a threshold, e.g. to
+    /// page without Prometheus. Each kind of alert runs it at most once per
+    /// cooldown.
+    pub alert_cmd: Option<AlertCommand>,
+    /// Guest images uploaded to Bonsai again when a session fails to be
+    /// created because Bonsai no longer knows its image, or on demand through
+    /// the admin REST API.
+    pub images: Vec<GuestImage>,
+    /// Key expected in the `x-admin-key` header of the admin REST API
+    /// requests. The admin routes are not served if unset.
+    pub admin_api_key: Option<String>,
+    /// Chains served in addition to the one of the client config the relayer
+    /// is run with, each with its own Ethereum client, relay contracts, wallet
+    /// and fee policy, e.g. read with [read_chain_configs]. The Bonsai
+    /// pipeline, the REST API and the metrics are shared by all chains, and a
+    /// chain whose event stream or complete proof manager fails stops without
+    /// stopping the others.
+    pub chains: Vec<ChainConfig>,
+    /// TOML config file whose settings override the ones of the relayer and
+    /// of its client config. On SIGHUP, the file is read again, and its
+    /// image IDs, fee cap, concurrency limits, alert webhook and log level are
+    /// applied without stopping the relayer. Its other settings, e.g. the
+    /// Ethereum node or the relay contract addresses, require a restart.
+    pub config_file: Option<PathBuf>,
+    /// SQLite database recording every processed `CallbackRequest` event
+    /// with its status, created if missing. Events already recorded are not
+    /// processed again, and on start the events whose relay transaction was
+    /// sent but not confirmed are tracked again. Events are only kept in
+    /// memory if unset.
+    pub event_persistence_db: Option<PathBuf>,
+    /// Age after which the completed events are removed from the event
+    /// database, checked on start and every hour. Completed events are kept
+    /// if unset.
+    pub prune_events_older_than: Option<Duration>,
+    /// Age after which the requests of on-chain events expire if they are not
+    /// proven yet. Requests posted to the REST API carry their own deadline.
+    /// On-chain requests don't expire if unset.
+    pub request_max_age: Option<Duration>,
+    /// Stop the Bonsai session of a request whose deadline passes while it is
+    /// being proven, rather than letting it run to completion.
+    pub cancel_expired_sessions: bool,
+    /// Maximum number of Bonsai sessions in flight, checked at intake. New
+    /// on-chain events are deferred past it, until sessions complete, and
+    /// REST requests are rejected with a 429. Unbounded if unset.
+    pub max_pending_requests: Option<usize>,
+    /// Number of failed requests kept in the dead-letter list, listed and
+    /// retried through the REST API, beyond which the oldest ones are
+    /// evicted. The list is persisted in the event database, if any.
+    pub max_dead_letters: usize,
+    /// Rate limit of the REST API requests of each API key, or of each client
+    /// IP address for requests without one. Requests past it are rejected
+    /// with a 429. Requests are not rate limited if unset.
+    pub rest_api_rate_limit: Option<RateLimit>,
+    /// Maximum size, in bytes, of the body of a REST API request, e.g. of the
+    /// guest input of a callback request. Larger requests are rejected with a
+    /// 413.
+    pub max_request_body_size: usize,
+    /// Toggle to serve a Swagger UI of the REST API on `/swagger-ui`. The
+    /// OpenAPI specification it reads is always served on
+    /// `/v1/openapi.json`.
+    pub rest_api_docs: bool,
 }
 
 impl Relayer {
     /// Run a [Relayer] with an Ethereum Client.
-    pub async fn run(self, client_config: EthersClientConfig) -> Result<()> {
+    ///
+    /// On SIGINT or SIGTERM, the relayer stops accepting new events and waits
+    /// up to `shutdown_timeout` for its in-flight transactions to be confirmed
+    /// before returning.
+    pub async fn run(self, client_config: EthersClientConfig) -> Result<(), RelayError> {
+        self.run_with_shutdown(client_config, async {
+            shutdown_signal()
+                .await
+                .context("Failed to listen for shutdown signals.")
+        })
+        .await
+    }
+
+    /// Run a [Relayer] with an Ethereum Client until `shutdown` resolves,
+    /// rather than until SIGINT or SIGTERM, e.g. when embedding the relayer in
+    /// another application.
+    pub async fn run_with_shutdown(
+        mut self,
+        mut client_config: EthersClientConfig,
+        shutdown: impl Future<Output = Result<()>>,
+    ) -> Result<(), RelayError> {
+        let config_file = match &self.config_file {
+            Some(path) => reload::read_config_file(path).map_err(RelayError::config)?,
+            None => Default::default(),
+        };
+        if let Some(eth_node) = &config_file.eth_node {
+            client_config.eth_node_url = eth_node.clone();
+        }
+        if let Some(private_key) = &config_file.private_key {
+            client_config.wallet_key_identifier = private_key.clone().try_into()?;
+        }
+        if let Some(relay_addresses) = &config_file.relay_addresses {
+            self.relay_contract_addresses = relay_addresses.clone();
+        }
+        if let Some(rest_api_bind) = &config_file.rest_api_bind {
+            self.rest_api_bind = rest_api_bind.clone();
+        }
+        if let Some(rest_api_port) = config_file.rest_api_port {
+            self.rest_api_port = rest_api_port.to_string();
+        }
+
         // try to load filter from `RUST_LOG` or use reasonably verbose defaults
-        let filter = ::tracing_subscriber::EnvFilter::try_from_default_env()
-            .unwrap_or_else(|_| DEFAULT_FILTER.into());
-        let subscriber = ::tracing_subscriber::FmtSubscriber::builder()
-            .with_env_filter(filter)
+        let base_settings = HotSettings {
+            image_ids: self.image_ids.clone(),
+            fee_cap: self.fee_cap,
+            max_concurrent_relays: self.max_concurrent_relays,
+            relay_queue_depth: self.relay_queue_depth,
+            alert_webhook: self.alert_webhook.clone(),
+            log_level: std::env::var(::tracing_subscriber::EnvFilter::DEFAULT_ENV)
+                .ok()
+                .filter(|filter| ::tracing_subscriber::EnvFilter::try_new(filter).is_ok())
+                .unwrap_or_else(|| DEFAULT_FILTER.to_string()),
+        };
+        let settings = config_file
+            .hot_settings(&base_settings)
+            .map_err(RelayError::config)?;
+
+        let subscriber_builder = ::tracing_subscriber::FmtSubscriber::builder()
+            .with_env_filter(settings.log_level.as_str())
             .with_test_writer()
-            .finish();
-        let _ = ::tracing::subscriber::set_global_default(subscriber);
+            .with_filter_reloading();
+        let log_filter_handle = subscriber_builder.reload_handle();
+        let log_filter: Option<reload::LogFilterReload> =
+            ::tracing::subscriber::set_global_default(subscriber_builder.finish())
+                .ok()
+                .map(|_| -> reload::LogFilterReload {
+                    Box::new(move |filter| Ok(log_filter_handle.reload(filter)?))
+                });
 
-        let bonsai_client =
-            get_client_from_parts(self.bonsai_api_url.clone(), self.bonsai_api_key.clone())
-                .await
-                .context("Failed to create Bonsai client.")?;
+        // The chain of the client config, followed by the additional chains.
+        let chains: Vec<ChainConfig> = std::iter::once(ChainConfig {
+            client_config,
+            relay_contract_addresses: self.relay_contract_addresses,
+            safe: self.safe,
+            min_eth_balance: self.min_eth_balance,
+            fee_cap: settings.fee_cap,
+            private_relay: self.private_relay,
+        })
+        .chain(self.chains)
+        .collect();
+        for (index, chain) in chains.iter().enumerate() {
+            let chain_id = chain.client_config.eth_chain_id;
+            chain.client_config.eth_node_endpoint()?;
+            if chain.relay_contract_addresses.is_empty() {
+                return Err(RelayError::ConfigError(format!(
+                    "At least one relay contract address is required on chain {chain_id}."
+                )));
+            }
+            if chains[..index]
+                .iter()
+                .any(|other| other.client_config.eth_chain_id == chain_id)
+            {
+                return Err(RelayError::ConfigError(format!(
+                    "Chain {chain_id} is configured more than once."
+                )));
+            }
+        }
+        if let Some(CallbackGasLimits {
+            min: Some(min),
+            max: Some(max),
+            ..
+        }) = self.callback_gas_limits
+        {
+            if min > max {
+                return Err(RelayError::ConfigError(format!(
+                    "Minimum callback gas limit {min} is above the maximum {max}."
+                )));
+            }
+        }
+        if self.watch_gas_price == Some(Duration::ZERO) {
+            return Err(RelayError::ConfigError(
+                "The gas price watch interval must be positive.".to_string(),
+            ));
+        }
+        let submitters = chains
+            .iter()
+            .map(|chain| {
+                TxSubmitter::new(
+                    chain.client_config.eth_chain_id,
+                    chain.private_relay.clone(),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if self.submission_only && !self.rest_api {
+            return Err(RelayError::ConfigError(
+                "Submission-only mode requires the REST API.".to_string(),
+            ));
+        }
+        if self.grpc && !cfg!(feature = "grpc") {
+            return Err(RelayError::ConfigError(
+                "The gRPC transport requires the `grpc` feature.".to_string(),
+            ));
+        }
+        if self.submission_only && settings.image_ids.is_empty() {
+            warn!("Submission-only mode accepts proofs of any image ID.");
+        }
 
-        let storage = InMemoryStorage::new();
+        if self.prune_events_older_than.is_some() && self.event_persistence_db.is_none() {
+            return Err(RelayError::ConfigError(
+                "Pruning events requires an event database.".to_string(),
+            ));
+        }
+        let event_db = match &self.event_persistence_db {
+            Some(path) => Some(EventDb::open(path).map_err(RelayError::config)?),
+            None => None,
+        };
+        // Bind the REST API before starting anything else, rather than
+        // running without it.
+        let api_listener = match self.rest_api {
+            true => Some(
+                ApiListener::bind(
+                    &self.rest_api_bind,
+                    &self.rest_api_port,
+                    self.rest_api_tls.clone(),
+                )
+                .map_err(RelayError::config)?,
+            ),
+            false => None,
+        };
 
-        // Setup Downloader
-        let new_pending_proof_request_notifier = Arc::new(Notify::new());
-        let proxy_callback_proof_request_processor = ProxyCallbackProofRequestProcessor::new(
-            bonsai_client.clone(),
-            storage.clone(),
-            Some(new_pending_proof_request_notifier.clone()),
-        );
+        let replay_logs = match &self.replay_events {
+            Some(path) => downloader::replay::read_logs(path).map_err(RelayError::config)?,
+            None => Vec::new(),
+        };
+        if !replay_logs.is_empty() && self.submission_only {
+            warn!("Submission-only mode ignores the logs to replay.");
+        }
+        let mut history_scan = match (self.start_block, self.end_block) {
+            (Some(start_block), Some(end_block)) if end_block < start_block => {
+                return Err(RelayError::ConfigError(format!(
+                    "End block {end_block} is before start block {start_block}."
+                )));
+            }
+            (Some(start_block), end_block) => Some(HistoryScan {
+                start_block,
+                end_block,
+            }),
+            (None, Some(_)) => {
+                return Err(RelayError::ConfigError(
+                    "An end block requires a start block.".to_string(),
+                ));
+            }
+            (None, None) => None,
+        };
+        if history_scan.is_some() && self.submission_only {
+            warn!("Submission-only mode ignores the start block.");
+        }
 
-        let downloader = ProxyCallbackProofRequestStream::new(
-            client_config.clone(),
-            self.relay_contract_address,
-            proxy_callback_proof_request_processor.clone(),
-        );
+        // Check every chain and key signing the relay transactions on startup,
+        // waiting for the hardware wallets to be unlocked rather than on the
+        // first relay transaction.
+        let mut signer_pools = Vec::new();
+        for chain in &chains {
+            signer_pools.push(
+                SignerPool::connect(
+                    &chain.client_config,
+                    &chain.relay_contract_addresses,
+                    chain.min_eth_balance,
+                )
+                .await?,
+            );
+        }
 
-        // Setup Uploader
-        let new_complete_proof_notifier = Arc::new(Notify::new());
+        let relays: Vec<RelayContract> = chains
+            .iter()
+            .flat_map(|chain| {
+                chain
+                    .relay_contract_addresses
+                    .iter()
+                    .map(|address| RelayContract {
+                        chain_id: chain.client_config.eth_chain_id,
+                        address: *address,
+                    })
+            })
+            .collect();
 
+        // Alerts are derived from the relayer events, which are then published
+        // even if the embedding application doesn't consume them. A webhook
+        // may also be set when reloading the config file, and the alert
+        // command runs on some of them.
+        let alerts_enabled = settings.alert_webhook.is_some() || self.config_file.is_some();
+        let events = match &self.events {
+            None if alerts_enabled || self.alert_cmd.is_some() => {
+                Some(event_channel(alerts::ALERT_EVENTS_CAPACITY).0)
+            }
+            None if event_db.is_some() => Some(event_channel(event_db::EVENT_DB_EVENTS_CAPACITY).0),
+            events => events.clone(),
+        };
+        let (alert_webhook_sender, alert_webhook) = watch::channel(settings.alert_webhook.clone());
+        if let (true, Some(events)) = (alerts_enabled, &events) {
+            tokio::spawn(alerts::post_alerts(
+                events.subscribe(),
+                alert_webhook,
+                relays.iter().map(|relay| relay.address).collect(),
+            ));
+        }
+        // The status of the recorded events follows the relayer events.
+        if let (Some(event_db), Some(events)) = (&event_db, &events) {
+            tokio::spawn(event_db::record_events(
+                event_db.clone(),
+                events.subscribe(),
+                self.prune_events_older_than,
+            ));
+        }
+        let (image_ids_sender, image_ids) = watch::channel(settings.image_ids.clone());
+
+        let bonsai_api_urls: Vec<String> = std::iter::once(self.bonsai_api_url.clone())
+            .chain(self.bonsai_api_urls.iter().cloned())
+            .collect();
+        let bonsai_client =
+            get_round_robin_client_from_parts(bonsai_api_urls.clone(), self.bonsai_api_key.clone())
+                .await?;
+        if let (Some(alert_cmd), Some(events)) = (&self.alert_cmd, &events) {
+            tokio::spawn(alert_cmd::run_alert_command(
+                alert_cmd.clone(),
+                events.subscribe(),
+                bonsai_client.primary().clone(),
+                relays.iter().map(|relay| relay.address).collect(),
+            ));
+        }
+        let transport: Arc<dyn BonsaiTransport> = match self.grpc {
+            #[cfg(feature = "grpc")]
+            true => Arc::new(GrpcBonsaiClient::new(
+                &bonsai_api_urls,
+                &self.bonsai_api_key,
+            )?),
+            _ => Arc::new(bonsai_client.clone()),
+        };
+
+        let mut dead_letters = DeadLetters::new(self.max_dead_letters);
+        if let Some(event_db) = &event_db {
+            dead_letters = dead_letters
+                .with_event_db(event_db.clone())
+                .map_err(RelayError::config)?;
+        }
+        let storage = InMemoryStorage::new().with_dead_letters(dead_letters);
+        let spend_limit = SpendLimit::new(self.max_daily_spend)
+            .with_event_db(event_db.clone())
+            .map_err(RelayError::config)?
+            .with_events(events.clone());
+        let images: Arc<[GuestImage]> = self.images.into();
+        let intake = Intake::new(self.max_pending_requests).with_scheduler(Scheduler::new(
+            config_file.scheduler.clone().unwrap_or_default(),
+        ));
+        // In submission-only mode, the proofs don't come from Bonsai.
+        let bonsai_pipeline = !self.submission_only;
+
+        // Setup the Bonsai pipeline, shared by all chains
+        let new_pending_proof_request_notifier = Arc::new(Notify::new());
+        let new_complete_proof_notifiers: Vec<Arc<Notify>> =
+            chains.iter().map(|_| Arc::new(Notify::new())).collect();
+        let complete_proof_notifiers: HashMap<u64, Arc<Notify>> = chains
+            .iter()
+            .map(|chain| chain.client_config.eth_chain_id)
+            .zip(new_complete_proof_notifiers.iter().cloned())
+            .collect();
         let uploader_pending_proof_manager = BonsaiPendingProofManager::new(
             bonsai_client.clone(),
             storage.clone(),
             new_pending_proof_request_notifier.clone(),
-            new_complete_proof_notifier.clone(),
-        );
+            new_complete_proof_notifiers.clone(),
+            events.clone(),
+        )
+        .with_transport(transport.clone())
+        .with_cancel_expired_sessions(self.cancel_expired_sessions)
+        .with_scheduler(intake.scheduler().clone());
 
-        let send_batch_notifier = Arc::new(Notify::new());
-        let max_batch_size: usize = 3;
-        let send_batch_interval = tokio::time::interval(tokio::time::Duration::from_millis(1000));
+        // Start an event stream and a complete proof manager per chain. The
+        // tasks of a chain are stopped if either exits, without stopping the
+        // other chains.
+        let mut replay_logs = Some(replay_logs);
+        let mut running_chains = Vec::new();
+        let mut chain_exits = FuturesUnordered::new();
+        let mut submission_chains = Vec::new();
+        let mut chain_limits = Vec::new();
+        for (((chain, new_complete_proof_notifier), submitter), signer_pool) in chains
+            .into_iter()
+            .zip(new_complete_proof_notifiers)
+            .zip(submitters)
+            .zip(signer_pools)
+        {
+            let chain_id = chain.client_config.eth_chain_id;
+            let span = info_span!("chain", chain_id);
 
-        let uploader_complete_proof_manager = BonsaiCompleteProofManager::new(
-            bonsai_client.clone(),
-            self.dev_mode,
-            storage.clone(),
-            new_complete_proof_notifier.clone(),
-            send_batch_notifier.clone(),
-            max_batch_size,
-            self.relay_contract_address,
-            client_config.clone(),
-            send_batch_interval,
-        );
+            let downloader = if bonsai_pipeline {
+                let proxy_callback_proof_request_processor =
+                    ProxyCallbackProofRequestProcessor::new(
+                        chain_id,
+                        transport.clone(),
+                        storage.clone(),
+                        Some(new_pending_proof_request_notifier.clone()),
+                        events.clone(),
+                    )
+                    .with_images(images.clone())
+                    .with_request_max_age(self.request_max_age)
+                    .with_intake(intake.clone());
+                let downloader = ProxyCallbackProofRequestStream::new(
+                    chain.client_config.clone(),
+                    chain.relay_contract_addresses.clone(),
+                    image_ids.clone(),
+                    proxy_callback_proof_request_processor,
+                    events.clone(),
+                    replay_logs.take().unwrap_or_default(),
+                )
+                .with_history_scan(history_scan.take())
+                .with_intake(intake.clone())
+                .with_event_db(event_db.clone());
+                let handle = tokio::spawn(downloader.run().instrument(span.clone()));
+                let abort = handle.abort_handle();
+                chain_exits.push(
+                    async move { (chain_id, "downloader", format!("{:?}", handle.await)) }.boxed(),
+                );
+                Some(abort)
+            } else {
+                None
+            };
+
+            let (submission_sender, submission_receiver) = mpsc::unbounded_channel();
+            let send_batch_notifier = Arc::new(Notify::new());
+            let max_batch_size: usize = 3;
+            let send_batch_interval =
+                tokio::time::interval(tokio::time::Duration::from_millis(1000));
+            let shutdown_notifier = Arc::new(Notify::new());
+
+            let relay: Arc<dyn CallbackRelay> = match chain.safe {
+                Some(safe) => Arc::new(SafeRelay::new(safe)),
+                None => Arc::new(DirectRelay),
+            };
+
+            let (limits_sender, limits) = watch::channel(RelayLimits {
+                fee_cap: chain.fee_cap,
+                max_concurrent_relays: settings.max_concurrent_relays,
+                relay_queue_depth: settings.relay_queue_depth,
+            });
+            chain_limits.push(limits_sender);
+            let uploader_complete_proof_manager = BonsaiCompleteProofManager::new(
+                bonsai_client.clone(),
+                self.dev_mode,
+                self.skip_proof_validation,
+                chain.min_eth_balance,
+                limits,
+                self.retry_on_revert,
+                self.stuck_tx_timeout,
+                self.tx_resubmit_bump,
+                self.reorg_depth,
+                storage.clone(),
+                new_complete_proof_notifier,
+                submission_receiver,
+                send_batch_notifier,
+                max_batch_size,
+                relay,
+                chain.client_config.clone(),
+                send_batch_interval,
+                shutdown_notifier.clone(),
+                events.clone(),
+            )
+            .with_transport(transport.clone())
+            .with_event_db(event_db.clone())
+            .with_spend_limit(spend_limit.clone())
+            .with_callback_gas_limits(self.callback_gas_limits)
+            .with_tx_submitter(submitter)
+            .with_signer_pool(signer_pool)
+            .with_journal_filters(self.journal_filters.clone());
+            let pending_transactions = uploader_complete_proof_manager.pending_transactions();
+            let handle = tokio::spawn(uploader_complete_proof_manager.run().instrument(span));
+            let manager = handle.abort_handle();
+            chain_exits.push(
+                async move {
+                    (
+                        chain_id,
+                        "complete proof manager",
+                        format!("{:?}", handle.await),
+                    )
+                }
+                .boxed(),
+            );
+
+            let gas_price_watch = self.watch_gas_price.map(|interval| {
+                tokio::spawn(
+                    gas_watch::watch_gas_price(chain.client_config.clone(), interval)
+                        .instrument(info_span!("chain", chain_id)),
+                )
+                .abort_handle()
+            });
+
+            submission_chains.push(SubmissionChain {
+                client_config: chain.client_config,
+                sender: submission_sender,
+            });
+            running_chains.push(RunningChain {
+                chain_id,
+                downloader,
+                manager,
+                gas_price_watch,
+                shutdown_notifier,
+                pending_transactions,
+            });
+        }
 
         // Setup server API
         let state = ApiState {
             bonsai_url: self.bonsai_api_url.clone(),
+            relays: relays.clone(),
             storage: storage.clone(),
             notifier: new_pending_proof_request_notifier.clone(),
+            complete_proof_notifiers,
+            events: events.clone(),
+            submissions: self.submission_only.then(|| SubmissionConfig {
+                image_ids: image_ids.clone(),
+                dev_mode: self.dev_mode,
+                skip_proof_validation: self.skip_proof_validation,
+                chains: submission_chains,
+            }),
+            bonsai_client: transport.clone(),
+            images,
+            admin_api_key: self.admin_api_key,
+            limits: ApiLimits::new(self.rest_api_rate_limit, self.max_request_body_size),
+            rest_api_docs: self.rest_api_docs,
+            intake,
+            spend_limit,
         };
 
         // Start everything
-        let server_handle = tokio::spawn(maybe_start_publish_mode(
-            self.rest_api,
-            state,
-            self.rest_api_port,
-        ));
-        let local_bonsai_handle = tokio::spawn(maybe_start_local_bonsai(
-            self.dev_mode,
+        let mut server_handle = tokio::spawn(maybe_start_publish_mode(api_listener, state));
+        let mut local_bonsai_handle = tokio::spawn(maybe_start_local_bonsai(
+            self.dev_mode && bonsai_pipeline,
             self.bonsai_api_url.clone(),
         ));
-        let downloader_handle = tokio::spawn(downloader.run());
-        let uploader_pending_proof_manager_handle =
-            tokio::spawn(uploader_pending_proof_manager.run());
-        let uploader_complete_proof_manager_handle =
-            tokio::spawn(uploader_complete_proof_manager.run());
+        let mut uploader_pending_proof_manager_handle = tokio::spawn(maybe_run(
+            bonsai_pipeline,
+            uploader_pending_proof_manager.run(),
+        ));
 
-        info!("Relay started");
+        if let Some(path) = self.config_file {
+            tokio::spawn(reload::reload_on_sighup(Reloader {
+                path,
+                started: config_file,
+                base: base_settings,
+                current: settings,
+                image_ids: image_ids_sender,
+                alert_webhook: alert_webhook_sender,
+                chain_limits,
+                log_filter,
+            }));
+        }
 
-        tokio::select! {
-            err = server_handle, if self.rest_api => {
-                panic!("{}", format!("server API exited: {:?}", err))
-            }
-            err = local_bonsai_handle, if self.dev_mode => {
-                panic!("{}", format!("local Bonsai service exited: {:?}", err))
+        info!(?relays, "Relay started");
+
+        tokio::pin!(shutdown);
+        let mut chains_exited = None;
+        loop {
+            tokio::select! {
+                err = &mut server_handle, if self.rest_api => {
+                    panic!("{}", format!("server API exited: {:?}", err))
+                }
+                err = &mut local_bonsai_handle, if self.dev_mode && bonsai_pipeline => {
+                    panic!("{}", format!("local Bonsai service exited: {:?}", err))
+                }
+                err = &mut uploader_pending_proof_manager_handle, if bonsai_pipeline => {
+                    panic!("{}", format!("pending proof manager exited: {:?}", err))
+                }
+                Some((chain_id, task, err)) = chain_exits.next() => {
+                    // The other task of the chain was stopped along with it.
+                    let Some(index) = running_chains
+                        .iter()
+                        .position(|chain| chain.chain_id == chain_id)
+                    else {
+                        continue;
+                    };
+                    error!(
+                        chain_id,
+                        task,
+                        %err,
+                        "chain task exited, no longer relaying on the chain"
+                    );
+                    events::emit(
+                        &events,
+                        RelayerEvent::RelayError {
+                            timestamp: events::now(),
+                            error: format!("{task} of chain {chain_id} exited: {err}"),
+                            tx_hash: None,
+                        },
+                    );
+                    if running_chains.len() == 1 {
+                        // The last chain is drained like on a shutdown, before
+                        // reporting its error.
+                        chains_exited = Some(RelayError::ChainsExited {
+                            chain_id,
+                            task,
+                            error: err,
+                        });
+                        break;
+                    }
+                    running_chains.remove(index).abort();
+                }
+                res = &mut shutdown => {
+                    res?;
+                    break;
+                }
             }
-            err = downloader_handle => {
-                panic!("{}", format!("downloader exited: {:?}", err))
+        }
+
+        info!("Shutting down relay");
+
+        // Stop accepting new events.
+        server_handle.abort();
+        local_bonsai_handle.abort();
+        uploader_pending_proof_manager_handle.abort();
+        for chain in running_chains.iter() {
+            if let Some(downloader) = &chain.downloader {
+                downloader.abort();
             }
-            err = uploader_pending_proof_manager_handle => {
-                panic!("{}", format!("pending proof manager exited: {:?}", err))
+            if let Some(gas_price_watch) = &chain.gas_price_watch {
+                gas_price_watch.abort();
             }
-            err = uploader_complete_proof_manager_handle => {
-                panic!("{}", format!("complete proof manager exited: {:?}", err))
+        }
+
+        // Wait for the in-flight transactions of all chains to be confirmed.
+        for chain in running_chains.iter() {
+            chain.shutdown_notifier.notify_one();
+        }
+        let drained = tokio::time::timeout(self.shutdown_timeout, async {
+            while chain_exits.next().await.is_some() {}
+        })
+        .await;
+        match drained {
+            Ok(_) => info!("Relay shut down"),
+            Err(_) => {
+                for chain in running_chains.iter() {
+                    chain.abort();
+                    let pending_transactions = chain
+                        .pending_transactions
+                        .lock()
+                        .expect("pending transactions lock poisoned");
+                    for tx_hash in pending_transactions.iter() {
+                        warn!(
+                            chain_id = chain.chain_id,
+                            ?tx_hash,
+                            "Transaction still pending after shutdown timeout."
+                        );
+                    }
+                }
             }
         }
+
+        match chains_exited {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the calldata of the relay contract call that would invoke
+    /// `callback` with the journal, authorized by the SNARK proof, without
+    /// submitting it, e.g. to test contract upgrades, estimate gas offline or
+    /// have the call signed by a multi-sig wallet.
+    ///
+    /// The proof is checked, and the seal left empty, as the relayer does
+    /// with its `dev_mode` and `skip_proof_validation` settings. Proofs of
+    /// images outside the `image_ids`, if any, are rejected.
+    pub fn dry_run(
+        &self,
+        proof: &SnarkProof,
+        journal: &[u8],
+        image_id: Digest,
+        callback: &DryRunCallback,
+    ) -> Result<Bytes> {
+        let image = H256::from(<[u8; 32]>::from(image_id));
+        if !self.image_ids.is_empty() && !self.image_ids.contains(&image) {
+            anyhow::bail!("image ID {image:?} is not relayed");
+        }
+        relay_calldata(
+            proof,
+            journal,
+            image_id,
+            callback,
+            self.dev_mode,
+            self.skip_proof_validation,
+        )
     }
 }
 
+/// Tasks of a chain served by the relayer.
+struct RunningChain {
+    chain_id: u64,
+    /// Event stream of the chain, unless in submission-only mode.
+    downloader: Option<AbortHandle>,
+    manager: AbortHandle,
+    /// Gas price logging of the chain, if watched.
+    gas_price_watch: Option<AbortHandle>,
+    shutdown_notifier: Arc<Notify>,
+    pending_transactions: PendingTransactions,
+}
+
+impl RunningChain {
+    fn abort(&self) {
+        if let Some(downloader) = &self.downloader {
+            downloader.abort();
+        }
+        self.manager.abort();
+        if let Some(gas_price_watch) = &self.gas_price_watch {
+            gas_price_watch.abort();
+        }
+    }
+}
+
+/// Resolves once the process receives either SIGINT or SIGTERM.
+#[cfg(unix)]
+pub async fn shutdown_signal() -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+/// Resolves once the process receives Ctrl-C.
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> std::io::Result<()> {
+    tokio::signal::ctrl_c().await
+}
+
 async fn maybe_start_publish_mode<S: Storage + Sync + Send + Clone + 'static>(
-    publish_mode: bool,
+    listener: Option<ApiListener>,
     state: ApiState<S>,
-    port: String,
 ) -> anyhow::Result<()> {
-    if publish_mode {
-        return serve(state, port).await;
+    if let Some(listener) = listener {
+        return serve(state, listener).await;
     }
 
     Ok(())
 }
 
+async fn maybe_run<T>(enabled: bool, task: impl Future<Output = T>) -> Option<T> {
+    if enabled {
+        return Some(task.await);
+    }
+
+    None
+}
+
 async fn maybe_start_local_bonsai(dev_mode: bool, bonsai_url: String) -> anyhow::Result<()> {
     if dev_mode {
         let port = bonsai_url.split(':').last().context("port not defined")?;
@@ -12,32 +12,96 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
-use anyhow::Result;
-use bonsai_ethereum_relay::{EthersClientConfig, Relayer};
-use clap::Parser;
-use ethers::core::types::Address;
+use anyhow::{Context, Result};
+use bonsai_ethereum_relay::{
+    event_channel, read_chain_configs, write_events, AlertCommand, CallbackGasLimits,
+    EthersClientConfig, FeeCap, KmsConfig, LedgerConfig, Network, PrivateRelay, RateLimit,
+    RelayError, Relayer, RemoteSignerConfig, RestApiTls, SafeConfig, DEFAULT_ALERT_CMD_COOLDOWN,
+    DEFAULT_BONSAI_UNREACHABLE_ALERT, DEFAULT_DEAD_LETTER_ALERT_THRESHOLD,
+    DEFAULT_ETH_NODE_POLL_INTERVAL, DEFAULT_ETH_NODE_POOL_SIZE, DEFAULT_ETH_NODE_TIMEOUT,
+    DEFAULT_FEE_QUEUE_DEPTH, DEFAULT_LEDGER_SIGNING_TIMEOUT, DEFAULT_LEDGER_UNLOCK_TIMEOUT,
+    DEFAULT_MAX_CONCURRENT_RELAYS, DEFAULT_MAX_DEAD_LETTERS, DEFAULT_MAX_REQUEST_BODY_SIZE,
+    DEFAULT_MIN_ETH_BALANCE, DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS, DEFAULT_RELAY_QUEUE_DEPTH,
+    DEFAULT_SHUTDOWN_TIMEOUT, DEFAULT_STUCK_TX_TIMEOUT, DEFAULT_TX_RESUBMIT_BUMP,
+    DEFAULT_WS_HEARTBEAT_INTERVAL, DEFAULT_WS_PING_INTERVAL, DEFAULT_WS_PONG_TIMEOUT,
+    DEFAULT_WS_STALE_TIMEOUT,
+};
+use clap::{Parser, ValueEnum};
+use ethers::core::types::{Address, Bytes, H256, U256};
+use tokio::io::AsyncWrite;
 
 const DEFAULT_BONSAI_API_URL: &str = "http://localhost:8081";
+const DEFAULT_REST_API_BIND: &str = "127.0.0.1";
 const DEFAULT_REST_API_PORT: &str = "8080";
 
+/// Number of relayer events buffered for `--emit-events` before the oldest
+/// ones are dropped.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Endpoint the relay transactions are submitted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TxEndpoint {
+    Public,
+    Private,
+}
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
+    /// The IP address the relay REST API binds to, e.g. 0.0.0.0 or :: to
+    /// accept connections from other hosts
+    #[arg(short, long, visible_alias = "rest-api-bind", default_value_t = DEFAULT_REST_API_BIND.to_string())]
+    bind: String,
+
     /// The port of the relay REST API
     #[arg(short, long, default_value_t = DEFAULT_REST_API_PORT.to_string())]
     port: String,
 
+    /// PEM file of the certificate chain to serve the relay REST API over TLS
+    /// with, leaf certificate first. Reloaded, along with its key, on SIGHUP
+    #[arg(long, requires = "rest_api_tls_key")]
+    rest_api_tls_cert: Option<PathBuf>,
+
+    /// PEM file of the private key of --rest-api-tls-cert
+    #[arg(long, requires = "rest_api_tls_cert")]
+    rest_api_tls_key: Option<PathBuf>,
+
     /// Toggle to disable the relay REST API
     #[arg(long, default_value_t = true)]
     rest_api: bool,
 
-    /// Bonsai Relay contract address on Ethereum
+    /// Requests accepted per minute from each API key, or from each client IP
+    /// address for requests without one, by the v1 REST API routes. Requests
+    /// past it are rejected with a 429. Requests are not rate limited if unset
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    rest_api_requests_per_minute: Option<u32>,
+
+    /// Requests accepted at once from each client, within
+    /// --rest-api-requests-per-minute
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..), requires = "rest_api_requests_per_minute")]
+    rest_api_burst: u32,
+
+    /// Maximum size, in bytes, of the body of a REST API request, e.g. of the
+    /// guest input of a callback request. Larger requests are rejected with a
+    /// 413
+    #[arg(long, default_value_t = DEFAULT_MAX_REQUEST_BODY_SIZE)]
+    max_request_body_size: usize,
+
+    /// Serve a Swagger UI of the REST API on /swagger-ui. The OpenAPI
+    /// specification is always served on /v1/openapi.json
     #[arg(long)]
-    contract_address: Address,
+    rest_api_docs: bool,
 
-    /// Ethereum Node endpoint
+    /// Bonsai Relay contract address on Ethereum. Repeat the option, or
+    /// separate the addresses with commas, to relay for several contracts
+    #[arg(long, required = true, value_delimiter = ',')]
+    contract_address: Vec<Address>,
+
+    /// Ethereum Node endpoint, a ws://, wss://, http:// or https:// URL, or
+    /// the IPC socket of a node on the same host, as ipc://<path> or a bare
+    /// path. Nodes reached over HTTP are polled for new blocks and logs
     #[arg(long)]
     eth_node_url: String,
 
@@ -45,15 +109,368 @@ struct Args {
     #[arg(long, default_value_t = 5)]
     eth_chain_id: u64,
 
-    /// Wallet Key Identifier. Can be a private key as a hex string, or an AWS
-    /// KMS key identifier
-    #[arg(short, long, env)]
-    wallet_key_identifier: String,
+    /// Well-known Ethereum network, one of mainnet, goerli, sepolia or anvil,
+    /// whose chain ID is used instead of --eth-chain-id
+    #[arg(long, conflicts_with = "eth_chain_id")]
+    eth_network: Option<Network>,
+
+    /// Timeout of a single Ethereum node request
+    #[arg(long, default_value_t = DEFAULT_ETH_NODE_TIMEOUT.into())]
+    eth_node_timeout: humantime::Duration,
+
+    /// Toggle to send the chain ID, relay contract code and wallet balance
+    /// requests of the startup checks in a single JSON-RPC batch, rather than
+    /// one after the other, e.g. for a distant Ethereum node
+    #[arg(long, default_value_t = false)]
+    json_rpc_batch: bool,
+
+    /// Interval between the requests for new blocks and logs to an Ethereum
+    /// node reached over HTTP
+    #[arg(long, default_value_t = DEFAULT_ETH_NODE_POLL_INTERVAL.into())]
+    poll_interval: humantime::Duration,
+
+    /// Interval between the requests for the head block checking that the
+    /// connection to a subscribed Ethereum node is alive
+    #[arg(long, default_value_t = DEFAULT_WS_HEARTBEAT_INTERVAL.into())]
+    ws_heartbeat_interval: humantime::Duration,
+
+    /// Time without a new block after which the connection to a subscribed
+    /// Ethereum node is made again, and the events missed meanwhile
+    /// backfilled. Set to 0s to not check the connection
+    #[arg(long, default_value_t = DEFAULT_WS_STALE_TIMEOUT.into())]
+    ws_stale_timeout: humantime::Duration,
+
+    /// Interval between the WebSocket ping frames sent to the Ethereum node,
+    /// detecting connections gone silent without a close frame. Set to 0s to
+    /// not ping the node
+    #[arg(long, default_value_t = DEFAULT_WS_PING_INTERVAL.into())]
+    eth_node_ws_ping_interval: humantime::Duration,
+
+    /// Time to wait for the pong answering a WebSocket ping frame, after which
+    /// the connection to the Ethereum node is made again
+    #[arg(long, default_value_t = DEFAULT_WS_PONG_TIMEOUT.into())]
+    eth_node_ws_pong_timeout: humantime::Duration,
+
+    /// Number of WebSocket connections to the Ethereum node, taking the
+    /// subscriptions and transactions in turn. A connection closed for good
+    /// is replaced
+    #[arg(long, default_value_t = DEFAULT_ETH_NODE_POOL_SIZE)]
+    eth_node_pool_size: usize,
+
+    /// Time to wait for in-flight transactions to be confirmed on shutdown
+    #[arg(long, default_value_t = DEFAULT_SHUTDOWN_TIMEOUT.into())]
+    shutdown_timeout: humantime::Duration,
+
+    /// Wallet Key Identifier. Can be a private key as a hex string, an AWS
+    /// KMS key identifier, the URL of a remote signer prefixed with
+    /// `remote:`, e.g. remote:https://web3signer.example.com:9000, or the
+    /// derivation path of a Ledger account prefixed with `ledger:`, e.g.
+    /// ledger:m/44'/60'/0'/0/0. Repeat the option, or separate the keys with
+    /// commas, to sign the relay transactions with several keys, each
+    /// transaction being sent with the key with the fewest in flight
+    #[arg(short, long, env, required = true, value_delimiter = ',')]
+    wallet_key_identifier: Vec<String>,
+
+    /// AWS region of the KMS key, if the wallet key identifier is one.
+    /// Defaults to the region of the AWS environment
+    #[arg(long, env)]
+    kms_region: Option<String>,
+
+    /// AWS profile whose credentials, and region if --kms-region is not set,
+    /// are used to reach KMS
+    #[arg(long, env)]
+    kms_profile: Option<String>,
+
+    /// KMS endpoint overriding the one of the region, e.g.
+    /// http://localhost:4566 for localstack
+    #[arg(long, env)]
+    kms_endpoint: Option<String>,
+
+    /// Address of the wallet held by the remote signer, if the wallet key
+    /// identifier is one. Defaults to the first of its `eth_accounts`
+    #[arg(long, env)]
+    signer_address: Option<Address>,
+
+    /// PEM file of a CA certificate to trust for the remote signer
+    /// connection, in addition to the system ones
+    #[arg(long, env)]
+    signer_tls_ca_cert: Option<PathBuf>,
+
+    /// PEM file of the client certificate and private key to present to the
+    /// remote signer
+    #[arg(long, env)]
+    signer_tls_client_cert: Option<PathBuf>,
+
+    /// Time to wait for each relay transaction to be confirmed on the
+    /// Ledger, if the wallet key identifier is a Ledger account
+    #[arg(long, env, default_value_t = DEFAULT_LEDGER_SIGNING_TIMEOUT.into())]
+    ledger_signing_timeout: humantime::Duration,
+
+    /// Time to wait on startup for the Ledger to be unlocked, with its
+    /// Ethereum app open
+    #[arg(long, env, default_value_t = DEFAULT_LEDGER_UNLOCK_TIMEOUT.into())]
+    ledger_unlock_timeout: humantime::Duration,
+
+    /// Safe multi-sig wallet address on Ethereum. When set, relay
+    /// transactions are sent through the Safe `execTransaction`
+    #[arg(long)]
+    safe_address: Option<Address>,
+
+    /// Private key of a Safe owner co-signing the relay transactions. Can be
+    /// repeated, once per co-signer
+    #[arg(long = "safe-signer-key", requires = "safe_address")]
+    safe_signer_keys: Vec<String>,
+
+    /// Comma-separated image IDs of the callback requests to process. All
+    /// callback requests are processed if not set
+    #[arg(long, value_delimiter = ',')]
+    relay_event_filter_topics: Vec<H256>,
+
+    /// Toggle to relay SNARK proofs without checking their points are valid
+    /// BN254 curve points, e.g. to test deliberately malformed proofs
+    #[arg(long, default_value_t = false)]
+    skip_proof_validation: bool,
+
+    /// Minimum wallet balance, in ether, required to submit a relay
+    /// transaction. Below it, transactions are held back until the wallet is
+    /// topped up
+    #[arg(long, default_value = DEFAULT_MIN_ETH_BALANCE, value_parser = parse_ether)]
+    min_eth_balance: U256,
+
+    /// Cap, in gwei, on the fee per gas of the relay transactions: the
+    /// EIP-1559 max fee per gas, or the gas price. Above it, transactions are
+    /// held back, and the fees checked again every 30 seconds
+    #[arg(long, value_parser = parse_gwei)]
+    fee_cap: Option<U256>,
+
+    /// Number of completed proofs held back while the fees exceed the
+    /// --fee-cap. Further proofs are dropped, failing their requests
+    #[arg(long, default_value_t = DEFAULT_FEE_QUEUE_DEPTH, requires = "fee_cap")]
+    fee_queue_depth: usize,
+
+    /// Number of times a relay transaction reverted on chain is resubmitted,
+    /// with an exponential backoff. Reverts that would happen again, e.g.
+    /// ProofAlreadySubmitted, are not retried
+    #[arg(long, default_value_t = 0)]
+    retry_on_revert: u32,
+
+    /// Time without being mined after which a relay transaction is
+    /// considered stuck, and resubmitted with the same nonce and bumped fees
+    #[arg(long, default_value_t = DEFAULT_STUCK_TX_TIMEOUT.into())]
+    stuck_tx_timeout: humantime::Duration,
+
+    /// Percentage by which the fees of a stuck relay transaction are bumped
+    /// when resubmitting it, up to 5 times. Set to 0 to never resubmit
+    #[arg(long, default_value_t = DEFAULT_TX_RESUBMIT_BUMP)]
+    tx_resubmit_bump: u64,
+
+    /// Number of blocks mined on top of a relay transaction during which it
+    /// is checked to still be on chain. A transaction dropped by a reorg
+    /// meanwhile is resubmitted. Set to 0 to not follow mined transactions
+    #[arg(long, default_value_t = 0)]
+    reorg_depth: u64,
+
+    /// Number of relay transactions in flight at once, on each chain, from
+    /// being sent until confirmed. Further completed proofs are queued
+    #[arg(
+        long,
+        default_value_t = DEFAULT_MAX_CONCURRENT_RELAYS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_concurrent_relays: usize,
+
+    /// Number of completed proofs queued while --max-concurrent-relays
+    /// transactions are in flight. When full, the oldest queued proof is
+    /// dropped, failing its request
+    #[arg(
+        long,
+        default_value_t = DEFAULT_RELAY_QUEUE_DEPTH,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    relay_queue_depth: usize,
+
+    /// Limit, in ether, on the gas spent by the relay transactions of each
+    /// chain over a rolling 24-hour window. Once reached, the relay
+    /// transactions of the chain are held back and their requests reported as
+    /// spend_limited. Unlimited if unset
+    #[arg(long, value_parser = parse_ether)]
+    max_daily_spend: Option<U256>,
+
+    /// Percentage added on top of the eth_estimateGas estimate of each
+    /// callback to set its gas limit. Setting any of the callback gas options
+    /// estimates the gas of the callbacks before relaying them
+    #[arg(long)]
+    callback_gas_headroom: Option<u64>,
+
+    /// Lower bound on the estimated gas limit of the callbacks
+    #[arg(long)]
+    callback_gas_limit_min: Option<u64>,
+
+    /// Upper bound on the estimated gas limit of the callbacks. Requests of
+    /// callbacks estimated to use more fail at the precheck stage
+    #[arg(long)]
+    callback_gas_limit_max: Option<u64>,
+
+    /// Toggle to treat the gas limit of a callback request as a ceiling on
+    /// its estimated gas limit, rather than a hint. Requests of callbacks
+    /// estimated to use more fail at the precheck stage
+    #[arg(long)]
+    respect_requested_gas_limit: bool,
+
+    /// Endpoint to submit the relay transactions to: the public mempool,
+    /// through the Ethereum node, or the private relay of
+    /// --private-relay-url, e.g. Flashbots Protect
+    #[arg(long, value_enum, default_value_t = TxEndpoint::Public)]
+    tx_endpoint: TxEndpoint,
+
+    /// JSON-RPC URL of the private relay the relay transactions are sent to
+    /// with --tx-endpoint private
+    #[arg(long, required_if_eq("tx_endpoint", "private"))]
+    private_relay_url: Option<String>,
+
+    /// Number of blocks without being included after which a relay
+    /// transaction sent to the private relay is broadcast to the public
+    /// mempool
+    #[arg(long, default_value_t = DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS)]
+    private_relay_fallback_blocks: u64,
+
+    /// Interval at which to log the gas price of each chain, with its moving
+    /// average over the last 5 samples and its change from the previous one,
+    /// e.g. 30s, to tune --fee-cap
+    #[arg(long)]
+    watch_gas_price: Option<humantime::Duration>,
+
+    /// Hex prefix of the journals of the proofs to relay, e.g. the 4-byte
+    /// selector of a proposal type. Repeat to relay the proofs matching any
+    /// of them. Proofs whose journal matches none are skipped. All proofs are
+    /// relayed if not set
+    #[arg(long = "journal-filter", value_parser = parse_journal_filter)]
+    journal_filters: Vec<Bytes>,
+
+    /// Toggle to write the relayer lifecycle events, e.g. sessions created or
+    /// transactions confirmed, as newline-delimited JSON to stderr
+    #[arg(long, default_value_t = false)]
+    emit_events: bool,
+
+    /// File to append the relayer events to, instead of stderr
+    #[arg(long, requires = "emit_events")]
+    events_file: Option<PathBuf>,
+
+    /// Toggle to relay pre-generated proofs submitted to the REST API on
+    /// /v1/submissions, instead of proving callback requests on Bonsai. Only
+    /// proofs of the --relay-event-filter-topics image IDs are accepted, if set
+    #[arg(long, default_value_t = false)]
+    submission_only: bool,
+
+    /// File of previously emitted CallbackRequest logs, one JSON serialized
+    /// log per line, to process on start as if they arrived live. Logs whose
+    /// transaction is no longer on chain are skipped
+    #[arg(long)]
+    replay_events: Option<PathBuf>,
+
+    /// Block from which to scan the CallbackRequest events on start, e.g. to
+    /// recover the requests emitted while the relayer was down, before
+    /// subscribing to new events. Only new events are processed if unset
+    #[arg(long)]
+    start_block: Option<u64>,
+
+    /// Last block scanned from --start-block, the head block if unset
+    #[arg(long, requires = "start_block")]
+    end_block: Option<u64>,
+
+    /// TOML file of additional chains to relay on, as `[[chain]]` entries
+    /// with their own chain_id, eth_node, relay_addresses, private_key and
+    /// optional min_eth_balance, fee_cap, fee_queue_depth, private_relay_url
+    /// and private_relay_fallback_blocks. The Bonsai pipeline and the REST
+    /// API are shared by all chains
+    #[arg(long)]
+    chain_config: Option<PathBuf>,
+
+    /// TOML file of settings overriding the command line ones. On SIGHUP, its
+    /// image_ids, fee_cap, fee_queue_depth, max_concurrent_relays,
+    /// relay_queue_depth, alert_webhook and log_level are reloaded without
+    /// restarting, while changes to its eth_node, private_key,
+    /// relay_addresses, rest_api_bind and rest_api_port require a restart
+    #[arg(long)]
+    config_file: Option<PathBuf>,
+
+    /// SQLite database to record the processed CallbackRequest events in,
+    /// created if missing. Recorded events are not processed again, and the
+    /// events whose relay transaction was sent but not confirmed are tracked
+    /// again on start
+    #[arg(long)]
+    event_persistence_db: Option<PathBuf>,
+
+    /// Age after which completed events are removed from the
+    /// --event-persistence-db, e.g. 30d. Completed events are kept if unset
+    #[arg(long, requires = "event_persistence_db")]
+    prune_events_older_than: Option<humantime::Duration>,
+
+    /// Age after which the requests of on-chain events expire if not proven
+    /// yet, e.g. 1h. Requests posted to the REST API carry their own deadline
+    #[arg(long)]
+    request_max_age: Option<humantime::Duration>,
+
+    /// Stop the Bonsai session of a request whose deadline passes while it is
+    /// being proven
+    #[arg(long)]
+    cancel_expired_sessions: bool,
+
+    /// Maximum number of Bonsai sessions in flight. New on-chain events are
+    /// deferred past it, and REST requests rejected with a 429
+    #[arg(long, env)]
+    max_pending_requests: Option<usize>,
+
+    /// Number of failed requests kept in the dead-letter list, listed with
+    /// `GET /v1/callbacks?status=failed` and retried with
+    /// `POST /v1/callbacks/{id}/retry`. The oldest ones are evicted first
+    #[arg(long, default_value_t = DEFAULT_MAX_DEAD_LETTERS)]
+    max_dead_letter: usize,
+
+    /// URL to POST a JSON alert to whenever relaying fails, e.g. a Slack or
+    /// PagerDuty webhook
+    #[arg(long, env)]
+    alert_webhook: Option<String>,
+
+    /// Program run with a JSON alert on its stdin when the reconnects to the
+    /// Ethereum node are exhausted, the wallet balance drops below the
+    /// minimum, Bonsai is unreachable for --alert-bonsai-unreachable-after or
+    /// the dead letters reach --alert-dead-letter-threshold
+    #[arg(long, env)]
+    alert_cmd: Option<PathBuf>,
+
+    /// Minimum time between two runs of --alert-cmd for the same kind of
+    /// alert
+    #[arg(long, default_value_t = DEFAULT_ALERT_CMD_COOLDOWN.into(), requires = "alert_cmd")]
+    alert_cmd_cooldown: humantime::Duration,
+
+    /// Time for which Bonsai is to be unreachable before running --alert-cmd
+    #[arg(long, default_value_t = DEFAULT_BONSAI_UNREACHABLE_ALERT.into(), requires = "alert_cmd")]
+    alert_bonsai_unreachable_after: humantime::Duration,
+
+    /// Number of failed callback requests in the dead-letter list at which
+    /// --alert-cmd is run
+    #[arg(long, default_value_t = DEFAULT_DEAD_LETTER_ALERT_THRESHOLD, requires = "alert_cmd")]
+    alert_dead_letter_threshold: usize,
 
     /// Bonsai API URL
     #[arg(long, env, default_value_t = DEFAULT_BONSAI_API_URL.to_string())]
     bonsai_api_url: String,
 
+    /// Bonsai API URLs of several instances, e.g. behind a load balancer,
+    /// instead of --bonsai-api-url. Repeat the option, or separate the URLs
+    /// with commas. Image uploads and session polls take turns between the
+    /// instances, moving to the next one on a 5xx error, while the other
+    /// requests go to the first one
+    #[arg(long, env, value_delimiter = ',', conflicts_with = "bonsai_api_url")]
+    bonsai_api_urls: Vec<String>,
+
+    /// Send image uploads, session creations and session polls to the gRPC
+    /// API of Bonsai, at the same URLs, instead of its REST API. Requires the
+    /// `grpc` feature
+    #[arg(long, env)]
+    grpc: bool,
+
     /// Bonsai API Key
     /// Defaults to empty, providing no authentication.
     #[arg(long, env, default_value = "")]
@@ -65,28 +482,206 @@ struct Args {
     risc0_dev_mode: bool,
 }
 
+/// Parse an amount of ether into wei.
+fn parse_ether(amount: &str) -> Result<U256> {
+    ethers::utils::parse_ether(amount).with_context(|| format!("invalid ether amount: {amount}"))
+}
+
+fn parse_gwei(amount: &str) -> Result<U256> {
+    Ok(ethers::utils::parse_units(amount, "gwei")
+        .with_context(|| format!("invalid gwei amount: {amount}"))?
+        .into())
+}
+
+/// Parse a hex journal prefix, with or without a `0x` prefix.
+fn parse_journal_filter(filter: &str) -> Result<Bytes> {
+    let prefix = hex::decode(filter.strip_prefix("0x").unwrap_or(filter))
+        .with_context(|| format!("invalid journal filter: {filter}"))?;
+    if prefix.is_empty() {
+        anyhow::bail!("invalid journal filter: empty prefix");
+    }
+    Ok(prefix.into())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
-    let relayer = Relayer {
-        rest_api: args.rest_api,
-        dev_mode: args.risc0_dev_mode,
-        rest_api_port: args.port,
-        bonsai_api_url: args.bonsai_api_url,
-        bonsai_api_key: args.bonsai_api_key,
-        relay_contract_address: args.contract_address,
+    let safe = match args.safe_address {
+        Some(address) => Some(SafeConfig {
+            address,
+            signer_keys: args
+                .safe_signer_keys
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, RelayError>>()?,
+        }),
+        None => None,
+    };
+
+    let events = if args.emit_events {
+        let (sender, receiver) = event_channel(EVENTS_CAPACITY);
+        let out: Box<dyn AsyncWrite + Unpin + Send> = match args.events_file {
+            Some(path) => Box::new(
+                tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .await
+                    .with_context(|| format!("failed to open {}", path.display()))?,
+            ),
+            None => Box::new(tokio::io::stderr()),
+        };
+        tokio::spawn(async move {
+            if let Err(err) = write_events(receiver, out).await {
+                eprintln!("failed to write relayer events: {err:#}");
+            }
+        });
+        Some(sender)
+    } else {
+        None
     };
 
+    // The first key is the wallet key, and the others sign in turn with it.
+    let mut wallet_keys = args
+        .wallet_key_identifier
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<Vec<_>, RelayError>>()?;
+    let wallet_key_identifier = wallet_keys.remove(0);
+
     const WAIT_DURATION: Duration = Duration::from_secs(5);
     const MAX_RETRIES: u64 = 7 * 24 * 60 * 60 / WAIT_DURATION.as_secs(); // 1 week
     let client_config = EthersClientConfig::new(
         args.eth_node_url,
-        args.eth_chain_id,
-        args.wallet_key_identifier.try_into()?,
+        args.eth_network
+            .map_or(args.eth_chain_id, |network| network.chain_id()),
+        wallet_key_identifier,
         MAX_RETRIES,
         WAIT_DURATION,
-    );
+        args.eth_node_timeout.into(),
+    )
+    .with_json_rpc_batch(args.json_rpc_batch)
+    .with_additional_wallet_keys(wallet_keys)
+    .with_kms(KmsConfig {
+        region: args.kms_region,
+        profile: args.kms_profile,
+        endpoint: args.kms_endpoint,
+    })
+    .with_remote_signer(RemoteSignerConfig {
+        signer_address: args.signer_address,
+        tls_ca_cert: args.signer_tls_ca_cert,
+        tls_client_cert: args.signer_tls_client_cert,
+    })
+    .with_ledger(LedgerConfig {
+        signing_timeout: args.ledger_signing_timeout.into(),
+        unlock_timeout: args.ledger_unlock_timeout.into(),
+    })
+    .with_poll_interval(args.poll_interval.into())
+    .with_ws_heartbeat(
+        args.ws_heartbeat_interval.into(),
+        args.ws_stale_timeout.into(),
+    )
+    .with_ws_ping(
+        args.eth_node_ws_ping_interval.into(),
+        args.eth_node_ws_pong_timeout.into(),
+    )
+    .with_eth_node_pool_size(args.eth_node_pool_size);
+    let chains = match args.chain_config {
+        Some(path) => read_chain_configs(&path, &client_config)?,
+        None => Vec::new(),
+    };
+    let (bonsai_api_url, bonsai_api_urls) = match args.bonsai_api_urls.split_first() {
+        Some((first, rest)) => (first.clone(), rest.to_vec()),
+        None => (args.bonsai_api_url, Vec::new()),
+    };
+
+    let relayer = Relayer {
+        rest_api: args.rest_api,
+        dev_mode: args.risc0_dev_mode,
+        rest_api_bind: args.bind,
+        rest_api_port: args.port,
+        rest_api_tls: args
+            .rest_api_tls_cert
+            .zip(args.rest_api_tls_key)
+            .map(|(cert, key)| RestApiTls { cert, key }),
+        bonsai_api_url,
+        bonsai_api_urls,
+        grpc: args.grpc,
+        bonsai_api_key: args.bonsai_api_key,
+        relay_contract_addresses: args.contract_address,
+        shutdown_timeout: args.shutdown_timeout.into(),
+        safe,
+        image_ids: args.relay_event_filter_topics,
+        skip_proof_validation: args.skip_proof_validation,
+        min_eth_balance: args.min_eth_balance,
+        fee_cap: args.fee_cap.map(|max_fee_per_gas| FeeCap {
+            max_fee_per_gas,
+            queue_depth: args.fee_queue_depth,
+        }),
+        retry_on_revert: args.retry_on_revert,
+        stuck_tx_timeout: args.stuck_tx_timeout.into(),
+        tx_resubmit_bump: args.tx_resubmit_bump,
+        reorg_depth: args.reorg_depth,
+        max_concurrent_relays: args.max_concurrent_relays,
+        relay_queue_depth: args.relay_queue_depth,
+        max_daily_spend: args.max_daily_spend,
+        callback_gas_limits: (args.callback_gas_headroom.is_some()
+            || args.callback_gas_limit_min.is_some()
+            || args.callback_gas_limit_max.is_some()
+            || args.respect_requested_gas_limit)
+            .then(|| CallbackGasLimits {
+                headroom_percent: args.callback_gas_headroom.unwrap_or(0),
+                min: args.callback_gas_limit_min,
+                max: args.callback_gas_limit_max,
+                respect_requested: args.respect_requested_gas_limit,
+            }),
+        private_relay: match args.tx_endpoint {
+            TxEndpoint::Public => None,
+            TxEndpoint::Private => Some(PrivateRelay {
+                url: args
+                    .private_relay_url
+                    .expect("--private-relay-url is required with --tx-endpoint private"),
+                fallback_blocks: args.private_relay_fallback_blocks,
+            }),
+        },
+        watch_gas_price: args.watch_gas_price.map(Into::into),
+        journal_filters: args
+            .journal_filters
+            .iter()
+            .map(|filter| filter.to_vec())
+            .collect(),
+        events,
+        submission_only: args.submission_only,
+        replay_events: args.replay_events,
+        start_block: args.start_block,
+        end_block: args.end_block,
+        alert_webhook: args.alert_webhook,
+        alert_cmd: args.alert_cmd.map(|program| {
+            AlertCommand::new(program)
+                .with_cooldown(args.alert_cmd_cooldown.into())
+                .with_bonsai_unreachable_after(args.alert_bonsai_unreachable_after.into())
+                .with_dead_letter_threshold(args.alert_dead_letter_threshold)
+        }),
+        images: Vec::new(),
+        admin_api_key: None,
+        chains,
+        config_file: args.config_file,
+        event_persistence_db: args.event_persistence_db,
+        prune_events_older_than: args.prune_events_older_than.map(Into::into),
+        request_max_age: args.request_max_age.map(Into::into),
+        cancel_expired_sessions: args.cancel_expired_sessions,
+        max_pending_requests: args.max_pending_requests,
+        max_dead_letters: args.max_dead_letter,
+        rest_api_rate_limit: args
+            .rest_api_requests_per_minute
+            .map(|requests_per_minute| RateLimit {
+                requests_per_minute,
+                burst: args.rest_api_burst,
+            }),
+        max_request_body_size: args.max_request_body_size,
+        rest_api_docs: args.rest_api_docs,
+    };
 
-    relayer.run(client_config).await
+    Ok(relayer.run(client_config).await?)
 }
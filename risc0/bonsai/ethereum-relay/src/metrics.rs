@@ -0,0 +1,706 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Prometheus metrics collected by the relayer.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use bonsai_sdk::alpha::responses::SessionStats;
+use ethers::{
+    types::{Address, H256, U256},
+    utils::format_ether,
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    exponential_buckets, CounterVec, GaugeVec, HistogramOpts, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+use crate::{
+    storage::dead_letters::FailureStage, uploader::completed_proofs::private_relay::TxEndpoint,
+};
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+/// Label used to key per-image metrics.
+const IMAGE_ID_LABEL: &str = "image_id";
+
+/// Label used to key metrics by the relay contract of the requests.
+const RELAY_LABEL: &str = "relay";
+
+/// Label used to key metrics by the chain of the relay contracts.
+const CHAIN_ID_LABEL: &str = "chain_id";
+
+/// Label used to key metrics by the stage at which the requests failed.
+const STAGE_LABEL: &str = "stage";
+
+/// Label used to key metrics by the endpoint relay transactions were
+/// submitted through.
+const ENDPOINT_LABEL: &str = "endpoint";
+
+/// Label used to key metrics by the address signing the relay transactions.
+const SIGNER_LABEL: &str = "signer";
+
+/// Metrics exported by the relayer.
+pub(crate) struct Metrics {
+    registry: Registry,
+    /// Total cycles of each completed Bonsai session.
+    pub(crate) session_cycles: HistogramVec,
+    /// Number of segments of each completed Bonsai session.
+    pub(crate) session_segments: HistogramVec,
+    /// Time between the creation and the completion of a Bonsai session.
+    pub(crate) proving_seconds: HistogramVec,
+    /// Time spent converting a STARK receipt into a SNARK proof.
+    pub(crate) snark_seconds: HistogramVec,
+    /// Ethereum node requests that timed out, by JSON-RPC method.
+    pub(crate) eth_node_timeouts: IntCounterVec,
+    /// Failed remote signer requests, including retried ones, by JSON-RPC
+    /// method.
+    pub(crate) remote_signer_errors: IntCounterVec,
+    /// Relay transactions not submitted with a signer as its balance was
+    /// below the minimum, by chain and signer.
+    pub(crate) relay_skipped_low_balance: IntCounterVec,
+    /// Balance of each signer when last checked, in ether, by chain and
+    /// signer.
+    pub(crate) signer_balance_eth: GaugeVec,
+    /// Relay transactions awaiting confirmation, by chain and signer.
+    pub(crate) signer_in_flight: IntGaugeVec,
+    /// Gas spent by the relay transactions since start, in ether, by chain
+    /// and signer.
+    pub(crate) signer_spend_eth: CounterVec,
+    /// Signers removed from rotation after failing, by chain.
+    pub(crate) signers_removed: IntCounterVec,
+    /// Relay transactions held back as the network fees exceeded the fee cap,
+    /// by chain.
+    pub(crate) relay_held_fee_cap: IntCounterVec,
+    /// Completed proofs dropped as the fee cap queue was full, by chain and
+    /// relay contract.
+    pub(crate) relay_dropped_fee_cap: IntCounterVec,
+    /// Completed proofs dropped as the relay queue was full, by chain and
+    /// relay contract.
+    pub(crate) relay_dropped_queue_full: IntCounterVec,
+    /// Relay transactions reverted on chain, including resubmitted ones, by
+    /// chain and relay contract.
+    pub(crate) relay_reverted: IntCounterVec,
+    /// Relay transactions dropped from the canonical chain by a reorg while
+    /// being re-confirmed, and resubmitted, by chain and relay contract.
+    pub(crate) reorg_detected: IntCounterVec,
+    /// Relay transactions submitted, including resubmitted ones, by chain and
+    /// endpoint: the public mempool, the private relay, or the public mempool
+    /// as a fallback from the private relay.
+    pub(crate) relay_tx_submissions: IntCounterVec,
+    /// Reconnections to an Ethereum node on which no new block was observed
+    /// for the stale timeout, by chain.
+    pub(crate) stale_connection_reconnects: IntCounterVec,
+    /// WebSocket pings to an Ethereum node left without a pong for the pong
+    /// timeout, by chain.
+    pub(crate) ping_timeout: IntCounterVec,
+    /// Callback requests whose deadline passed before they were proven, by
+    /// chain and relay contract.
+    pub(crate) requests_expired: IntCounterVec,
+    /// Failed callback requests retried from the dead-letter list, by failure
+    /// stage.
+    pub(crate) dead_letter_retries: IntCounterVec,
+    /// Failed callback requests kept in the dead-letter list.
+    pub(crate) dead_letters: IntGauge,
+    /// Time since the last new block was observed on the Ethereum node, by
+    /// chain.
+    pub(crate) seconds_since_last_block: GaugeVec,
+    /// Relayer events dropped as their consumer fell behind.
+    pub(crate) events_dropped: IntCounter,
+    /// Pre-generated proofs rejected in submission-only mode.
+    pub(crate) submissions_rejected: IntCounter,
+    /// Callback requests waiting for the scheduler to hand out their session,
+    /// by image.
+    pub(crate) scheduler_queue_depth: IntGaugeVec,
+    /// Sessions handed out by the scheduler and still proving, by image.
+    pub(crate) scheduler_in_flight: IntGaugeVec,
+    /// Gas spent by the relay transactions over the last 24 hours, in ether,
+    /// by chain.
+    pub(crate) daily_spend_eth: GaugeVec,
+    /// Whether the relay transactions are held back by the daily spend limit,
+    /// by chain.
+    pub(crate) spend_limited: IntGaugeVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("bonsai_relay".to_string()), None)
+            .expect("metrics registry should be valid");
+
+        let session_cycles = HistogramVec::new(
+            HistogramOpts::new("session_cycles", "Total cycles of completed sessions")
+                .buckets(exponential_buckets(65536.0, 2.0, 16).expect("valid buckets")),
+            &[CHAIN_ID_LABEL, RELAY_LABEL, IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let session_segments = HistogramVec::new(
+            HistogramOpts::new("session_segments", "Segments of completed sessions")
+                .buckets(exponential_buckets(1.0, 2.0, 12).expect("valid buckets")),
+            &[CHAIN_ID_LABEL, RELAY_LABEL, IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let proving_seconds = HistogramVec::new(
+            HistogramOpts::new("proving_seconds", "Proving time of completed sessions")
+                .buckets(exponential_buckets(1.0, 2.0, 14).expect("valid buckets")),
+            &[CHAIN_ID_LABEL, RELAY_LABEL, IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let snark_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "snark_seconds",
+                "SNARK conversion time of completed sessions",
+            )
+            .buckets(exponential_buckets(1.0, 2.0, 12).expect("valid buckets")),
+            &[CHAIN_ID_LABEL, RELAY_LABEL, IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let eth_node_timeouts = IntCounterVec::new(
+            Opts::new("eth_node_timeouts", "Timed out Ethereum node requests"),
+            &["method"],
+        )
+        .expect("metric should be valid");
+        let remote_signer_errors = IntCounterVec::new(
+            Opts::new("remote_signer_errors", "Failed remote signer requests"),
+            &["method"],
+        )
+        .expect("metric should be valid");
+        let relay_skipped_low_balance = IntCounterVec::new(
+            Opts::new(
+                "relay_skipped_low_balance",
+                "Relay transactions skipped due to a low wallet balance",
+            ),
+            &[CHAIN_ID_LABEL, SIGNER_LABEL],
+        )
+        .expect("metric should be valid");
+        let signer_balance_eth = GaugeVec::new(
+            Opts::new("signer_balance_eth", "Balance of the signers, in ether"),
+            &[CHAIN_ID_LABEL, SIGNER_LABEL],
+        )
+        .expect("metric should be valid");
+        let signer_in_flight = IntGaugeVec::new(
+            Opts::new(
+                "signer_in_flight",
+                "Relay transactions of the signers awaiting confirmation",
+            ),
+            &[CHAIN_ID_LABEL, SIGNER_LABEL],
+        )
+        .expect("metric should be valid");
+        let signer_spend_eth = CounterVec::new(
+            Opts::new(
+                "signer_spend_eth",
+                "Gas spent by the relay transactions of the signers, in ether",
+            ),
+            &[CHAIN_ID_LABEL, SIGNER_LABEL],
+        )
+        .expect("metric should be valid");
+        let signers_removed = IntCounterVec::new(
+            Opts::new(
+                "signers_removed",
+                "Signers removed from rotation after failing",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let relay_held_fee_cap = IntCounterVec::new(
+            Opts::new(
+                "relay_held_fee_cap",
+                "Relay transactions held back due to network fees above the cap",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let relay_dropped_fee_cap = IntCounterVec::new(
+            Opts::new(
+                "relay_dropped_fee_cap",
+                "Completed proofs dropped due to a full fee cap queue",
+            ),
+            &[CHAIN_ID_LABEL, RELAY_LABEL],
+        )
+        .expect("metric should be valid");
+        let relay_dropped_queue_full = IntCounterVec::new(
+            Opts::new(
+                "relay_dropped_queue_full",
+                "Completed proofs dropped due to a full relay queue",
+            ),
+            &[CHAIN_ID_LABEL, RELAY_LABEL],
+        )
+        .expect("metric should be valid");
+        let relay_reverted = IntCounterVec::new(
+            Opts::new("relay_reverted", "Relay transactions reverted on chain"),
+            &[CHAIN_ID_LABEL, RELAY_LABEL],
+        )
+        .expect("metric should be valid");
+        let reorg_detected = IntCounterVec::new(
+            Opts::new(
+                "reorg_detected",
+                "Relay transactions dropped from the canonical chain by a reorg",
+            ),
+            &[CHAIN_ID_LABEL, RELAY_LABEL],
+        )
+        .expect("metric should be valid");
+        let relay_tx_submissions = IntCounterVec::new(
+            Opts::new(
+                "relay_tx_submissions",
+                "Relay transactions submitted, by endpoint",
+            ),
+            &[CHAIN_ID_LABEL, ENDPOINT_LABEL],
+        )
+        .expect("metric should be valid");
+        let stale_connection_reconnects = IntCounterVec::new(
+            Opts::new(
+                "stale_connection_reconnects",
+                "Reconnections to Ethereum nodes on which no new block was observed for too long",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let ping_timeout = IntCounterVec::new(
+            Opts::new(
+                "ping_timeout",
+                "WebSocket pings to Ethereum nodes left without a pong for too long",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let requests_expired = IntCounterVec::new(
+            Opts::new(
+                "requests_expired",
+                "Callback requests whose deadline passed before they were proven",
+            ),
+            &[CHAIN_ID_LABEL, RELAY_LABEL],
+        )
+        .expect("metric should be valid");
+        let dead_letter_retries = IntCounterVec::new(
+            Opts::new(
+                "dead_letter_retries",
+                "Failed callback requests retried from the dead-letter list",
+            ),
+            &[STAGE_LABEL],
+        )
+        .expect("metric should be valid");
+        let dead_letters = IntGauge::new(
+            "dead_letters",
+            "Failed callback requests kept in the dead-letter list",
+        )
+        .expect("metric should be valid");
+        let seconds_since_last_block = GaugeVec::new(
+            Opts::new(
+                "seconds_since_last_block",
+                "Time since the last new block was observed on the Ethereum node",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let events_dropped = IntCounter::new(
+            "events_dropped",
+            "Relayer events dropped due to a slow consumer",
+        )
+        .expect("metric should be valid");
+        let submissions_rejected = IntCounter::new(
+            "submissions_rejected",
+            "Pre-generated proofs rejected in submission-only mode",
+        )
+        .expect("metric should be valid");
+        let scheduler_queue_depth = IntGaugeVec::new(
+            Opts::new(
+                "scheduler_queue_depth",
+                "Callback requests waiting for a Bonsai session",
+            ),
+            &[IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let scheduler_in_flight = IntGaugeVec::new(
+            Opts::new(
+                "scheduler_in_flight",
+                "Bonsai sessions handed out by the scheduler and still proving",
+            ),
+            &[IMAGE_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let daily_spend_eth = GaugeVec::new(
+            Opts::new(
+                "daily_spend_eth",
+                "Gas spent by the relay transactions over the last 24 hours, in ether",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+        let spend_limited = IntGaugeVec::new(
+            Opts::new(
+                "spend_limited",
+                "Whether the relay transactions are held back by the daily spend limit",
+            ),
+            &[CHAIN_ID_LABEL],
+        )
+        .expect("metric should be valid");
+
+        for collector in [
+            &session_cycles,
+            &session_segments,
+            &proving_seconds,
+            &snark_seconds,
+        ] {
+            registry
+                .register(Box::new(collector.clone()))
+                .expect("metric should only be registered once");
+        }
+        for collector in [
+            &eth_node_timeouts,
+            &remote_signer_errors,
+            &relay_skipped_low_balance,
+            &signers_removed,
+            &relay_held_fee_cap,
+            &relay_dropped_fee_cap,
+            &relay_dropped_queue_full,
+            &relay_reverted,
+            &reorg_detected,
+            &relay_tx_submissions,
+            &stale_connection_reconnects,
+            &ping_timeout,
+            &requests_expired,
+            &dead_letter_retries,
+        ] {
+            registry
+                .register(Box::new(collector.clone()))
+                .expect("metric should only be registered once");
+        }
+        for collector in [&seconds_since_last_block, &signer_balance_eth] {
+            registry
+                .register(Box::new(collector.clone()))
+                .expect("metric should only be registered once");
+        }
+        registry
+            .register(Box::new(signer_in_flight.clone()))
+            .expect("metric should only be registered once");
+        registry
+            .register(Box::new(signer_spend_eth.clone()))
+            .expect("metric should only be registered once");
+        registry
+            .register(Box::new(dead_letters.clone()))
+            .expect("metric should only be registered once");
+        for collector in [&events_dropped, &submissions_rejected] {
+            registry
+                .register(Box::new(collector.clone()))
+                .expect("metric should only be registered once");
+        }
+        for collector in [&scheduler_queue_depth, &scheduler_in_flight] {
+            registry
+                .register(Box::new(collector.clone()))
+                .expect("metric should only be registered once");
+        }
+        registry
+            .register(Box::new(daily_spend_eth.clone()))
+            .expect("metric should only be registered once");
+        registry
+            .register(Box::new(spend_limited.clone()))
+            .expect("metric should only be registered once");
+
+        Self {
+            registry,
+            session_cycles,
+            session_segments,
+            proving_seconds,
+            snark_seconds,
+            eth_node_timeouts,
+            remote_signer_errors,
+            relay_skipped_low_balance,
+            signer_balance_eth,
+            signer_in_flight,
+            signer_spend_eth,
+            signers_removed,
+            relay_held_fee_cap,
+            relay_dropped_fee_cap,
+            relay_dropped_queue_full,
+            relay_reverted,
+            reorg_detected,
+            relay_tx_submissions,
+            stale_connection_reconnects,
+            ping_timeout,
+            requests_expired,
+            dead_letter_retries,
+            dead_letters,
+            seconds_since_last_block,
+            events_dropped,
+            submissions_rejected,
+            scheduler_queue_depth,
+            scheduler_in_flight,
+            daily_spend_eth,
+            spend_limited,
+        }
+    }
+
+    /// Count a relay transaction on the given chain not submitted due to a
+    /// low wallet balance.
+    pub(crate) fn record_skipped_low_balance(&self, chain_id: u64, signer: Address) {
+        self.relay_skipped_low_balance
+            .with_label_values(&[&chain_id.to_string(), &signer_label(signer)])
+            .inc();
+    }
+
+    /// Set the balance of a signer on the given chain, in wei.
+    pub(crate) fn set_signer_balance(&self, chain_id: u64, signer: Address, balance: U256) {
+        self.signer_balance_eth
+            .with_label_values(&[&chain_id.to_string(), &signer_label(signer)])
+            .set(format_ether(balance).parse().unwrap_or(f64::MAX));
+    }
+
+    /// Set the number of relay transactions of a signer on the given chain
+    /// awaiting confirmation.
+    pub(crate) fn set_signer_in_flight(&self, chain_id: u64, signer: Address, in_flight: usize) {
+        self.signer_in_flight
+            .with_label_values(&[&chain_id.to_string(), &signer_label(signer)])
+            .set(in_flight as i64);
+    }
+
+    /// Add the gas cost, in wei, of a relay transaction of a signer mined on
+    /// the given chain.
+    pub(crate) fn record_signer_spend(&self, chain_id: u64, signer: Address, amount: U256) {
+        self.signer_spend_eth
+            .with_label_values(&[&chain_id.to_string(), &signer_label(signer)])
+            .inc_by(format_ether(amount).parse().unwrap_or_default());
+    }
+
+    /// Count a signer of the given chain removed from rotation.
+    pub(crate) fn record_signer_removed(&self, chain_id: u64) {
+        self.signers_removed
+            .with_label_values(&[&chain_id.to_string()])
+            .inc();
+    }
+
+    /// Count a relay transaction on the given chain held back by the fee cap.
+    pub(crate) fn record_held_fee_cap(&self, chain_id: u64) {
+        self.relay_held_fee_cap
+            .with_label_values(&[&chain_id.to_string()])
+            .inc();
+    }
+
+    /// Count a reverted relay transaction sent to the given relay contract.
+    pub(crate) fn record_reverted(&self, chain_id: u64, relay: Address) {
+        self.relay_reverted
+            .with_label_values(&[&chain_id.to_string(), &relay_label(relay)])
+            .inc();
+    }
+
+    /// Count a relay transaction sent to the given relay contract that a reorg
+    /// dropped from the canonical chain.
+    pub(crate) fn record_reorg_detected(&self, chain_id: u64, relay: Address) {
+        self.reorg_detected
+            .with_label_values(&[&chain_id.to_string(), &relay_label(relay)])
+            .inc();
+    }
+
+    /// Count a relay transaction on the given chain submitted through the
+    /// given endpoint.
+    pub(crate) fn record_tx_submission(&self, chain_id: u64, endpoint: TxEndpoint) {
+        self.relay_tx_submissions
+            .with_label_values(&[&chain_id.to_string(), endpoint.as_str()])
+            .inc();
+    }
+
+    /// Count a reconnection to the Ethereum node of the given chain on which
+    /// no new block was observed for the stale timeout.
+    pub(crate) fn record_stale_connection_reconnect(&self, chain_id: u64) {
+        self.stale_connection_reconnects
+            .with_label_values(&[&chain_id.to_string()])
+            .inc();
+    }
+
+    /// Count a WebSocket ping to the Ethereum node of the given chain left
+    /// without a pong for the pong timeout.
+    pub(crate) fn record_ping_timeout(&self, chain_id: u64) {
+        self.ping_timeout
+            .with_label_values(&[&chain_id.to_string()])
+            .inc();
+    }
+
+    /// Set the time since the last new block was observed on the Ethereum
+    /// node of the given chain.
+    pub(crate) fn set_seconds_since_last_block(&self, chain_id: u64, elapsed: Duration) {
+        self.seconds_since_last_block
+            .with_label_values(&[&chain_id.to_string()])
+            .set(elapsed.as_secs_f64());
+    }
+
+    /// Count a completed proof for the given relay contract dropped by the
+    /// fee cap.
+    pub(crate) fn record_dropped_fee_cap(&self, chain_id: u64, relay: Address) {
+        self.relay_dropped_fee_cap
+            .with_label_values(&[&chain_id.to_string(), &relay_label(relay)])
+            .inc();
+    }
+
+    /// Count a completed proof for the given relay contract dropped by the
+    /// relay queue.
+    pub(crate) fn record_dropped_queue_full(&self, chain_id: u64, relay: Address) {
+        self.relay_dropped_queue_full
+            .with_label_values(&[&chain_id.to_string(), &relay_label(relay)])
+            .inc();
+    }
+
+    /// Count a callback request made to the given relay contract whose
+    /// deadline passed before it was proven.
+    pub(crate) fn record_expired(&self, chain_id: u64, relay: Address) {
+        self.requests_expired
+            .with_label_values(&[&chain_id.to_string(), &relay_label(relay)])
+            .inc();
+    }
+
+    /// Count a failed callback request retried from the dead-letter list.
+    pub(crate) fn record_dead_letter_retry(&self, stage: FailureStage) {
+        self.dead_letter_retries
+            .with_label_values(&[stage.as_str()])
+            .inc();
+    }
+
+    /// Set the number of requests of the given image waiting for a session,
+    /// and of its sessions still proving.
+    pub(crate) fn set_scheduled_sessions(&self, image_id: H256, queued: usize, in_flight: usize) {
+        let image_id = hex::encode(image_id);
+        self.scheduler_queue_depth
+            .with_label_values(&[&image_id])
+            .set(queued as i64);
+        self.scheduler_in_flight
+            .with_label_values(&[&image_id])
+            .set(in_flight as i64);
+    }
+
+    /// Set the gas spent by the relay transactions on the given chain over the
+    /// last 24 hours, in wei, and whether it reached the daily spend limit.
+    pub(crate) fn set_daily_spend(&self, chain_id: u64, spent: U256, limited: bool) {
+        let chain_id = chain_id.to_string();
+        self.daily_spend_eth
+            .with_label_values(&[&chain_id])
+            .set(format_ether(spent).parse().unwrap_or(f64::MAX));
+        self.spend_limited
+            .with_label_values(&[&chain_id])
+            .set(i64::from(limited));
+    }
+
+    /// Returns the process-wide [Metrics] instance.
+    pub(crate) fn global() -> &'static Metrics {
+        &METRICS
+    }
+
+    /// Record the proving statistics of a completed request for the given
+    /// chain, relay contract and image ID.
+    pub(crate) fn record_proving_stats(
+        &self,
+        chain_id: u64,
+        relay: Address,
+        image_id: &str,
+        stats: Option<&SessionStats>,
+        proving_time: Duration,
+        snark_time: Duration,
+    ) {
+        let chain_id = chain_id.to_string();
+        let relay = relay_label(relay);
+        let labels = &[chain_id.as_str(), relay.as_str(), image_id];
+        if let Some(stats) = stats {
+            self.session_cycles
+                .with_label_values(labels)
+                .observe(stats.total_cycles as f64);
+            self.session_segments
+                .with_label_values(labels)
+                .observe(stats.segments as f64);
+        }
+        self.proving_seconds
+            .with_label_values(labels)
+            .observe(proving_time.as_secs_f64());
+        self.snark_seconds
+            .with_label_values(labels)
+            .observe(snark_time.as_secs_f64());
+    }
+
+    /// Encode all metrics using the Prometheus text exposition format.
+    pub(crate) fn encode(&self) -> Result<String> {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .context("failed to encode metrics")
+    }
+}
+
+/// Value of the [RELAY_LABEL] of a relay contract: its full hex address.
+fn relay_label(relay: Address) -> String {
+    format!("{relay:?}")
+}
+
+fn signer_label(signer: Address) -> String {
+    format!("{signer:?}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bonsai_sdk::alpha::responses::SessionStats;
+    use ethers::types::Address;
+
+    use super::Metrics;
+
+    #[test]
+    fn proving_stats_are_labeled_by_chain_relay_and_image_id() {
+        let metrics = Metrics::new();
+        let stats = SessionStats {
+            segments: 3,
+            total_cycles: 3 << 20,
+            cycles: 3_000_000,
+        };
+        let relay = Address::repeat_byte(1);
+        metrics.record_proving_stats(
+            5,
+            relay,
+            "deadbeef",
+            Some(&stats),
+            Duration::from_secs(30),
+            Duration::from_secs(5),
+        );
+        metrics.record_proving_stats(5, relay, "cafebabe", None, Duration::ZERO, Duration::ZERO);
+        metrics.record_proving_stats(7, relay, "cafebabe", None, Duration::ZERO, Duration::ZERO);
+        metrics.record_reverted(5, relay);
+        metrics.record_held_fee_cap(7);
+        metrics.record_reorg_detected(5, relay);
+        metrics.record_stale_connection_reconnect(7);
+        metrics.record_ping_timeout(7);
+        metrics.set_seconds_since_last_block(7, Duration::from_secs(90));
+
+        let relay = format!("relay=\"{relay:?}\"");
+        let encoded = metrics.encode().unwrap();
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_session_cycles_count{{chain_id=\"5\",image_id=\"deadbeef\",{relay}}} 1"
+        )));
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_session_segments_sum{{chain_id=\"5\",image_id=\"deadbeef\",{relay}}} 3"
+        )));
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_proving_seconds_count{{chain_id=\"5\",image_id=\"cafebabe\",{relay}}} 1"
+        )));
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_proving_seconds_count{{chain_id=\"7\",image_id=\"cafebabe\",{relay}}} 1"
+        )));
+        assert!(!encoded
+            .contains("bonsai_relay_session_cycles_count{chain_id=\"5\",image_id=\"cafebabe\""));
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_relay_reverted{{chain_id=\"5\",{relay}}} 1"
+        )));
+        assert!(encoded.contains("bonsai_relay_relay_held_fee_cap{chain_id=\"7\"} 1"));
+        assert!(encoded.contains(&format!(
+            "bonsai_relay_reorg_detected{{chain_id=\"5\",{relay}}} 1"
+        )));
+        assert!(encoded.contains("bonsai_relay_stale_connection_reconnects{chain_id=\"7\"} 1"));
+        assert!(encoded.contains("bonsai_relay_ping_timeout{chain_id=\"7\"} 1"));
+        assert!(encoded.contains("bonsai_relay_seconds_since_last_block{chain_id=\"7\"} 90"));
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Well-known Ethereum networks, selectable by name rather than by chain ID.
+
+use std::{fmt, str::FromStr};
+
+use crate::RelayError;
+
+/// Names of the supported networks, listed when an unknown one is given.
+const SUPPORTED_NETWORKS: &str = "mainnet, goerli, sepolia, anvil";
+
+/// A well-known Ethereum network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Goerli,
+    Sepolia,
+    /// Local development node.
+    Anvil,
+}
+
+impl Network {
+    /// Returns the chain ID of the network.
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Self::Mainnet => 1,
+            Self::Goerli => 5,
+            Self::Sepolia => 11155111,
+            Self::Anvil => 31337,
+        }
+    }
+}
+
+impl TryFrom<&str> for Network {
+    type Error = RelayError;
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "mainnet" => Ok(Self::Mainnet),
+            "goerli" => Ok(Self::Goerli),
+            "sepolia" => Ok(Self::Sepolia),
+            "anvil" => Ok(Self::Anvil),
+            _ => Err(RelayError::ConfigError(format!(
+                "unknown Ethereum network {name}, expected one of {SUPPORTED_NETWORKS}"
+            ))),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = RelayError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::try_from(s)
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Mainnet => "mainnet",
+            Self::Goerli => "goerli",
+            Self::Sepolia => "sepolia",
+            Self::Anvil => "anvil",
+        })
+    }
+}
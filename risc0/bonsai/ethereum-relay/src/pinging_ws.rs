@@ -0,0 +1,494 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! JSON-RPC transport over a WebSocket connection to the Ethereum node,
+//! sending ping frames on the connection that carries the requests and the
+//! subscriptions. A WebSocket may go silent without a close frame, leaving its
+//! subscriptions open but never notified; the ethers transports do not expose
+//! their frames, so they cannot notice it.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, JsonRpcError, ProviderError, PubsubClient, RpcError},
+    types::U256,
+};
+use futures::{channel::mpsc as notification, SinkExt, StreamExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::value::RawValue;
+use tokio::{
+    net::TcpStream,
+    sync::{mpsc, oneshot, watch},
+    time::Instant,
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::eth_node::{WsPing, RECONNECT_INTERVAL};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+type NotificationStream = notification::UnboundedReceiver<Box<RawValue>>;
+
+/// Notification streams of the subscriptions, until taken by
+/// [PubsubClient::subscribe].
+type Subscriptions = Arc<Mutex<HashMap<U256, NotificationStream>>>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PingingWsError {
+    #[error("failed to connect to the Ethereum node: {0}")]
+    Connect(String),
+    #[error("the WebSocket connection to the Ethereum node is closed")]
+    Closed,
+    #[error("unknown subscription {0}")]
+    UnknownSubscription(U256),
+    #[error(transparent)]
+    JsonRpc(JsonRpcError),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+impl RpcError for PingingWsError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            Self::JsonRpc(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            Self::Serde(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<PingingWsError> for ProviderError {
+    fn from(src: PingingWsError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(src))
+    }
+}
+
+#[derive(Serialize)]
+struct Request<'a, T> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: T,
+}
+
+/// Response to a request, or notification of a subscription.
+#[derive(Deserialize)]
+struct Incoming {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    result: Option<Box<RawValue>>,
+    #[serde(default)]
+    error: Option<JsonRpcError>,
+    #[serde(default)]
+    params: Option<Notification>,
+}
+
+#[derive(Deserialize)]
+struct Notification {
+    subscription: U256,
+    result: Box<RawValue>,
+}
+
+enum Instruction {
+    Request {
+        id: u64,
+        request: String,
+        subscribe: bool,
+        sender: oneshot::Sender<Result<Box<RawValue>, PingingWsError>>,
+    },
+    Unsubscribe {
+        id: U256,
+    },
+}
+
+/// A request waiting for its response, sent again on the new connection if
+/// the connection is made again meanwhile.
+struct Pending {
+    request: String,
+    subscribe: bool,
+    sender: oneshot::Sender<Result<Box<RawValue>, PingingWsError>>,
+}
+
+/// A WebSocket transport sending a ping frame every ping interval on its
+/// connection, and making the connection again, up to a number of times, once
+/// a ping is left without a pong for the pong timeout or the connection is
+/// lost. The requests in flight are sent again on the new connection, while
+/// the subscriptions of the closed connection end, and are to be made again.
+#[derive(Clone, Debug)]
+pub struct PingingWs {
+    instructions: mpsc::UnboundedSender<Instruction>,
+    subscriptions: Subscriptions,
+    /// Number of pings left without a pong so far.
+    pong_timeouts: watch::Receiver<u64>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl PingingWs {
+    /// Connect to the node at `url`, pinging it as set by `ping` and
+    /// connecting again up to `reconnects` times. The connection attempts
+    /// time out after the pong timeout.
+    pub async fn connect(
+        url: String,
+        ping: WsPing,
+        reconnects: usize,
+    ) -> Result<Self, PingingWsError> {
+        let socket = open(&url, ping.pong_timeout).await?;
+        let (instructions, instructions_receiver) = mpsc::unbounded_channel();
+        let (pong_timeouts_sender, pong_timeouts) = watch::channel(0);
+        let subscriptions = Subscriptions::default();
+        let next_id = Arc::new(AtomicU64::new(1));
+        let backend = Backend {
+            url,
+            ping_interval: ping.interval,
+            pong_timeout: ping.pong_timeout,
+            reconnects,
+            instructions: instructions_receiver,
+            subscriptions: subscriptions.clone(),
+            pong_timeouts: pong_timeouts_sender,
+            next_id: next_id.clone(),
+            pending: BTreeMap::new(),
+            notifications: HashMap::new(),
+        };
+        tokio::spawn(backend.run(socket));
+        Ok(Self {
+            instructions,
+            subscriptions,
+            pong_timeouts,
+            next_id,
+        })
+    }
+
+    /// Returns once a ping is left without a pong for the pong timeout, as
+    /// the connection is made again. Never returns once the transport gave up
+    /// connecting again, its requests failing instead.
+    pub(crate) async fn wait_for_pong_timeout(&self) {
+        let mut pong_timeouts = self.pong_timeouts.clone();
+        pong_timeouts.borrow_and_update();
+        if pong_timeouts.changed().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for PingingWs {
+    type Error = PingingWsError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let request = serde_json::to_string(&Request {
+            jsonrpc: "2.0",
+            id,
+            method,
+            params,
+        })?;
+        let (sender, receiver) = oneshot::channel();
+        self.instructions
+            .send(Instruction::Request {
+                id,
+                request,
+                subscribe: method == "eth_subscribe",
+                sender,
+            })
+            .map_err(|_| PingingWsError::Closed)?;
+        let result = receiver.await.map_err(|_| PingingWsError::Closed)??;
+        Ok(serde_json::from_str(result.get())?)
+    }
+}
+
+impl PubsubClient for PingingWs {
+    type NotificationStream = NotificationStream;
+
+    fn subscribe<T: Into<U256>>(&self, id: T) -> Result<Self::NotificationStream, Self::Error> {
+        let id = id.into();
+        self.subscriptions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .remove(&id)
+            .ok_or(PingingWsError::UnknownSubscription(id))
+    }
+
+    fn unsubscribe<T: Into<U256>>(&self, id: T) -> Result<(), Self::Error> {
+        self.instructions
+            .send(Instruction::Unsubscribe { id: id.into() })
+            .map_err(|_| PingingWsError::Closed)
+    }
+}
+
+/// Open a WebSocket connection to the node at `url`, within `timeout`.
+async fn open(url: &str, timeout: Duration) -> Result<Socket, PingingWsError> {
+    match tokio::time::timeout(timeout, connect_async(url)).await {
+        Ok(Ok((socket, _))) => Ok(socket),
+        Ok(Err(err)) => Err(PingingWsError::Connect(err.to_string())),
+        Err(_) => Err(PingingWsError::Connect(format!(
+            "timed out after {}",
+            humantime::format_duration(timeout)
+        ))),
+    }
+}
+
+/// Why a connection stopped being served.
+enum Ended {
+    /// All the [PingingWs] handles were dropped.
+    Dropped,
+    PongTimeout,
+    Lost(String),
+}
+
+/// Serves the requests and subscriptions of the [PingingWs] handles over the
+/// current connection, pinging it.
+struct Backend {
+    url: String,
+    ping_interval: Duration,
+    pong_timeout: Duration,
+    reconnects: usize,
+    instructions: mpsc::UnboundedReceiver<Instruction>,
+    subscriptions: Subscriptions,
+    pong_timeouts: watch::Sender<u64>,
+    next_id: Arc<AtomicU64>,
+    pending: BTreeMap<u64, Pending>,
+    /// Senders of the notifications of the subscriptions of the current
+    /// connection.
+    notifications: HashMap<U256, notification::UnboundedSender<Box<RawValue>>>,
+}
+
+impl Backend {
+    async fn run(mut self, mut socket: Socket) {
+        loop {
+            match self.serve(&mut socket).await {
+                Ended::Dropped => {
+                    let _ = socket.close(None).await;
+                    return;
+                }
+                Ended::PongTimeout => {
+                    warn!(
+                        url = %self.url,
+                        timeout = %humantime::format_duration(self.pong_timeout),
+                        "Ethereum node WebSocket ping left without a pong"
+                    );
+                    self.pong_timeouts.send_modify(|count| *count += 1);
+                    let _ = tokio::time::timeout(self.pong_timeout, socket.close(None)).await;
+                }
+                Ended::Lost(error) => {
+                    warn!(url = %self.url, "Ethereum node WebSocket connection lost: {error}");
+                }
+            }
+            // The subscriptions end with the connection they were made on.
+            self.notifications.clear();
+            match self.reconnect().await {
+                Some(new_socket) => socket = new_socket,
+                None => {
+                    for (_, pending) in std::mem::take(&mut self.pending) {
+                        let _ = pending.sender.send(Err(PingingWsError::Closed));
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Connect again, sending the requests in flight again, or return `None`
+    /// once the reconnects are exhausted.
+    async fn reconnect(&mut self) -> Option<Socket> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let error = match open(&self.url, self.pong_timeout).await {
+                Ok(mut socket) => {
+                    info!(url = %self.url, attempt, "Reconnected to the Ethereum node WebSocket");
+                    let requests: Vec<String> = self
+                        .pending
+                        .values()
+                        .map(|pending| pending.request.clone())
+                        .collect();
+                    let mut resent = Ok(());
+                    for request in requests {
+                        resent = socket.send(Message::Text(request)).await;
+                        if resent.is_err() {
+                            break;
+                        }
+                    }
+                    match resent {
+                        Ok(()) => return Some(socket),
+                        Err(err) => err.to_string(),
+                    }
+                }
+                Err(err) => err.to_string(),
+            };
+            if attempt >= self.reconnects {
+                warn!(
+                    url = %self.url,
+                    attempt,
+                    "Failed to reconnect to the Ethereum node WebSocket, giving up: {error}"
+                );
+                return None;
+            }
+            warn!(
+                url = %self.url,
+                attempt,
+                "Failed to reconnect to the Ethereum node WebSocket: {error}"
+            );
+            tokio::time::sleep(RECONNECT_INTERVAL).await;
+        }
+    }
+
+    async fn serve(&mut self, socket: &mut Socket) -> Ended {
+        let mut ping =
+            tokio::time::interval_at(Instant::now() + self.ping_interval, self.ping_interval);
+        ping.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut pong_deadline: Option<Instant> = None;
+        loop {
+            let pong_timeout = async move {
+                match pong_deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+            tokio::select! {
+                instruction = self.instructions.recv() => {
+                    let Some(instruction) = instruction else {
+                        return Ended::Dropped;
+                    };
+                    if let Err(err) = self.send(socket, instruction).await {
+                        return Ended::Lost(err.to_string());
+                    }
+                }
+                message = socket.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.receive(text.as_bytes()),
+                    Some(Ok(Message::Binary(bytes))) => self.receive(&bytes),
+                    Some(Ok(Message::Pong(_))) => pong_deadline = None,
+                    Some(Ok(Message::Close(frame))) => {
+                        return Ended::Lost(format!("closed by the node: {frame:?}"));
+                    }
+                    // The pings of the node are answered by tungstenite.
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => return Ended::Lost(err.to_string()),
+                    None => return Ended::Lost("closed".to_string()),
+                },
+                _ = ping.tick(), if pong_deadline.is_none() => {
+                    if let Err(err) = socket.send(Message::Ping(Vec::new())).await {
+                        return Ended::Lost(err.to_string());
+                    }
+                    pong_deadline = Some(Instant::now() + self.pong_timeout);
+                }
+                _ = pong_timeout => return Ended::PongTimeout,
+            }
+        }
+    }
+
+    async fn send(
+        &mut self,
+        socket: &mut Socket,
+        instruction: Instruction,
+    ) -> Result<(), tokio_tungstenite::tungstenite::Error> {
+        match instruction {
+            Instruction::Request {
+                id,
+                request,
+                subscribe,
+                sender,
+            } => {
+                let message = Message::Text(request.clone());
+                self.pending.insert(
+                    id,
+                    Pending {
+                        request,
+                        subscribe,
+                        sender,
+                    },
+                );
+                socket.send(message).await
+            }
+            Instruction::Unsubscribe { id } => {
+                if self.notifications.remove(&id).is_none() {
+                    return Ok(());
+                }
+                // The response is not waited for.
+                let request = serde_json::to_string(&Request {
+                    jsonrpc: "2.0",
+                    id: self.next_id.fetch_add(1, Ordering::Relaxed),
+                    method: "eth_unsubscribe",
+                    params: [id],
+                })
+                .expect("request should serialize");
+                socket.send(Message::Text(request)).await
+            }
+        }
+    }
+
+    fn receive(&mut self, message: &[u8]) {
+        let incoming: Incoming = match serde_json::from_slice(message) {
+            Ok(incoming) => incoming,
+            Err(error) => {
+                warn!(?error, "Invalid message from the Ethereum node WebSocket");
+                return;
+            }
+        };
+        if let Some(notification) = incoming.params {
+            let id = notification.subscription;
+            let sent = self
+                .notifications
+                .get(&id)
+                .map(|sender| sender.unbounded_send(notification.result));
+            // The subscription was dropped.
+            if let Some(Err(_)) = sent {
+                self.notifications.remove(&id);
+            }
+            return;
+        }
+        let Some(pending) = incoming.id.and_then(|id| self.pending.remove(&id)) else {
+            return;
+        };
+        let result = match (incoming.error, incoming.result) {
+            (Some(error), _) => Err(PingingWsError::JsonRpc(error)),
+            (None, Some(result)) => Ok(result),
+            // A null result, e.g. of a receipt not yet mined.
+            (None, None) => {
+                Ok(RawValue::from_string("null".to_string()).expect("null should be valid JSON"))
+            }
+        };
+        if let (true, Ok(result)) = (pending.subscribe, &result) {
+            if let Ok(id) = serde_json::from_str::<U256>(result.get()) {
+                let (sender, receiver) = notification::unbounded();
+                self.notifications.insert(id, sender);
+                self.subscriptions
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .insert(id, receiver);
+            }
+        }
+        let _ = pending.sender.send(result);
+    }
+}
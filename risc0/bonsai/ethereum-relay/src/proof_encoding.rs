@@ -0,0 +1,50 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact encodings of the SNARK proofs, e.g. to store them.
+
+use std::io::Write;
+
+use bonsai_sdk::alpha::responses::SnarkProof;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+
+use crate::RelayError;
+
+/// A [SnarkProof], serialized as JSON and gzip compressed.
+///
+/// The proof coordinates are hex strings, whose repeated structure compresses
+/// well, so that the compressed form takes a fraction of the space of the JSON
+/// one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedSnarkProof(pub Vec<u8>);
+
+impl From<&SnarkProof> for CompressedSnarkProof {
+    fn from(proof: &SnarkProof) -> Self {
+        let json = serde_json::to_vec(proof).expect("SnarkProof serializes to JSON");
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        // Compressing into memory can't fail.
+        encoder.write_all(&json).expect("gzip compression failed");
+        Self(encoder.finish().expect("gzip compression failed"))
+    }
+}
+
+impl TryFrom<&CompressedSnarkProof> for SnarkProof {
+    type Error = RelayError;
+
+    fn try_from(compressed: &CompressedSnarkProof) -> Result<Self, Self::Error> {
+        serde_json::from_reader(GzDecoder::new(compressed.0.as_slice())).map_err(|err| {
+            RelayError::ProofEncodingError(format!("invalid compressed SNARK proof: {err}"))
+        })
+    }
+}
@@ -0,0 +1,330 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Settings of a relayer read from its config file, some of which are
+//! reloaded on SIGHUP without stopping the relayer.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use ethers::types::{Address, H256};
+use serde::Deserialize;
+use tokio::sync::watch;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    scheduler::SchedulerConfig,
+    uploader::completed_proofs::fee_cap::{FeeCap, DEFAULT_FEE_QUEUE_DEPTH},
+    DEFAULT_MAX_CONCURRENT_RELAYS, DEFAULT_RELAY_QUEUE_DEPTH,
+};
+
+/// Limits on the relay transactions of a chain, which its complete proof
+/// manager follows as they are reloaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct RelayLimits {
+    pub(crate) fee_cap: Option<FeeCap>,
+    pub(crate) max_concurrent_relays: usize,
+    pub(crate) relay_queue_depth: usize,
+}
+
+impl Default for RelayLimits {
+    fn default() -> Self {
+        Self {
+            fee_cap: None,
+            max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+            relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+        }
+    }
+}
+
+/// The settings of a relayer that are reloaded from its config file.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct HotSettings {
+    pub(crate) image_ids: Vec<H256>,
+    /// Fee cap of the chain of the client config the relayer is run with.
+    pub(crate) fee_cap: Option<FeeCap>,
+    pub(crate) max_concurrent_relays: usize,
+    pub(crate) relay_queue_depth: usize,
+    pub(crate) alert_webhook: Option<String>,
+    /// [EnvFilter] directives of the relayer logs.
+    pub(crate) log_level: String,
+}
+
+/// A TOML config file of a relayer. The settings it sets override the ones
+/// the relayer is run with.
+///
+/// ```toml
+/// # reloaded on SIGHUP
+/// image_ids = ["0x..."]
+/// fee_cap = "30"              # gwei
+/// fee_queue_depth = 100
+/// max_concurrent_relays = 10
+/// relay_queue_depth = 100
+/// alert_webhook = "https://hooks.example.com/..."
+/// log_level = "info"
+/// # only read on start
+/// eth_node = "wss://sepolia.example.com"
+/// private_key = "0x..."
+/// relay_addresses = ["0x5FbDB2315678afecb367f032d93F642f64180aa3"]
+/// rest_api_bind = "0.0.0.0"
+/// rest_api_port = 8080
+/// [scheduler]                 # see [SchedulerConfig]
+/// max_concurrent_sessions = 8
+/// ```
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConfigFile {
+    image_ids: Option<Vec<H256>>,
+    /// In gwei.
+    fee_cap: Option<String>,
+    fee_queue_depth: Option<usize>,
+    max_concurrent_relays: Option<usize>,
+    relay_queue_depth: Option<usize>,
+    alert_webhook: Option<String>,
+    log_level: Option<String>,
+    pub(crate) eth_node: Option<String>,
+    pub(crate) private_key: Option<String>,
+    pub(crate) relay_addresses: Option<Vec<Address>>,
+    pub(crate) rest_api_bind: Option<String>,
+    pub(crate) rest_api_port: Option<u16>,
+    pub(crate) scheduler: Option<SchedulerConfig>,
+}
+
+pub(crate) fn read_config_file(path: &Path) -> Result<ConfigFile> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    parse_config_file(&contents).with_context(|| format!("invalid config file {}", path.display()))
+}
+
+pub(crate) fn parse_config_file(contents: &str) -> Result<ConfigFile> {
+    let file: ConfigFile = toml::from_str(contents)?;
+    if let Some(scheduler) = &file.scheduler {
+        scheduler.validate()?;
+    }
+    Ok(file)
+}
+
+impl ConfigFile {
+    /// Returns the reloaded settings of the file, validated, falling back to
+    /// the `base` ones for the settings it doesn't set.
+    pub(crate) fn hot_settings(&self, base: &HotSettings) -> Result<HotSettings> {
+        let fee_cap = match (&self.fee_cap, self.fee_queue_depth) {
+            (Some(fee_cap), queue_depth) => Some(FeeCap {
+                max_fee_per_gas: ethers::utils::parse_units(fee_cap, "gwei")
+                    .with_context(|| format!("invalid gwei amount: {fee_cap}"))?
+                    .into(),
+                queue_depth: queue_depth
+                    .or(base.fee_cap.map(|fee_cap| fee_cap.queue_depth))
+                    .unwrap_or(DEFAULT_FEE_QUEUE_DEPTH),
+            }),
+            (None, Some(queue_depth)) => match base.fee_cap {
+                Some(fee_cap) => Some(FeeCap {
+                    queue_depth,
+                    ..fee_cap
+                }),
+                None => anyhow::bail!("fee_queue_depth requires fee_cap"),
+            },
+            (None, None) => base.fee_cap,
+        };
+        let max_concurrent_relays = self
+            .max_concurrent_relays
+            .unwrap_or(base.max_concurrent_relays);
+        if max_concurrent_relays == 0 {
+            anyhow::bail!("max_concurrent_relays must be at least 1");
+        }
+        if let Some(alert_webhook) = &self.alert_webhook {
+            reqwest::Url::parse(alert_webhook).context("invalid alert_webhook")?;
+        }
+        let log_level = self.log_level.as_ref().unwrap_or(&base.log_level);
+        EnvFilter::try_new(log_level).with_context(|| format!("invalid log_level: {log_level}"))?;
+
+        Ok(HotSettings {
+            image_ids: self
+                .image_ids
+                .clone()
+                .unwrap_or_else(|| base.image_ids.clone()),
+            fee_cap,
+            max_concurrent_relays,
+            relay_queue_depth: self.relay_queue_depth.unwrap_or(base.relay_queue_depth),
+            alert_webhook: self
+                .alert_webhook
+                .clone()
+                .or_else(|| base.alert_webhook.clone()),
+            log_level: log_level.clone(),
+        })
+    }
+
+    /// Returns the settings only read on start that `other` changes.
+    pub(crate) fn restart_changes(&self, other: &ConfigFile) -> Vec<&'static str> {
+        [
+            ("eth_node", self.eth_node != other.eth_node),
+            ("private_key", self.private_key != other.private_key),
+            (
+                "relay_addresses",
+                self.relay_addresses != other.relay_addresses,
+            ),
+            ("rest_api_bind", self.rest_api_bind != other.rest_api_bind),
+            ("rest_api_port", self.rest_api_port != other.rest_api_port),
+            ("scheduler", self.scheduler != other.scheduler),
+        ]
+        .into_iter()
+        .filter_map(|(setting, changed)| changed.then_some(setting))
+        .collect()
+    }
+}
+
+/// Describes the settings changed from `old` to `new`.
+pub(crate) fn settings_diff(old: &HotSettings, new: &HotSettings) -> Vec<String> {
+    let mut diff = Vec::new();
+    if old.image_ids != new.image_ids {
+        diff.push(format!(
+            "image_ids: {:?} -> {:?}",
+            old.image_ids, new.image_ids
+        ));
+    }
+    if old.fee_cap != new.fee_cap {
+        diff.push(format!(
+            "fee_cap: {} -> {}",
+            fee_cap_label(old.fee_cap),
+            fee_cap_label(new.fee_cap)
+        ));
+    }
+    if old.max_concurrent_relays != new.max_concurrent_relays {
+        diff.push(format!(
+            "max_concurrent_relays: {} -> {}",
+            old.max_concurrent_relays, new.max_concurrent_relays
+        ));
+    }
+    if old.relay_queue_depth != new.relay_queue_depth {
+        diff.push(format!(
+            "relay_queue_depth: {} -> {}",
+            old.relay_queue_depth, new.relay_queue_depth
+        ));
+    }
+    // Webhook URLs often embed a secret, e.g. for Slack.
+    if old.alert_webhook != new.alert_webhook {
+        diff.push("alert_webhook: changed".to_string());
+    }
+    if old.log_level != new.log_level {
+        diff.push(format!(
+            "log_level: {:?} -> {:?}",
+            old.log_level, new.log_level
+        ));
+    }
+    diff
+}
+
+fn fee_cap_label(fee_cap: Option<FeeCap>) -> String {
+    match fee_cap {
+        Some(fee_cap) => format!(
+            "{} gwei, queue depth {}",
+            ethers::utils::format_units(fee_cap.max_fee_per_gas, "gwei").unwrap_or_default(),
+            fee_cap.queue_depth
+        ),
+        None => "none".to_string(),
+    }
+}
+
+/// Sets the [EnvFilter] of the relayer logs.
+pub(crate) type LogFilterReload = Box<dyn Fn(EnvFilter) -> Result<()> + Send + Sync>;
+
+/// Reloads the settings of a relayer from its config file, publishing them to
+/// the tasks of the relayer.
+pub(crate) struct Reloader {
+    pub(crate) path: PathBuf,
+    /// Config file read on start, against which the settings only read on
+    /// start are compared.
+    pub(crate) started: ConfigFile,
+    /// Settings the relayer is run with, before applying the config file.
+    pub(crate) base: HotSettings,
+    pub(crate) current: HotSettings,
+    pub(crate) image_ids: watch::Sender<Vec<H256>>,
+    pub(crate) alert_webhook: watch::Sender<Option<String>>,
+    /// Relay limits of each chain, the first one being the chain of the
+    /// client config the relayer is run with.
+    pub(crate) chain_limits: Vec<watch::Sender<RelayLimits>>,
+    /// Sets the log filter, if the relayer installed its logger.
+    pub(crate) log_filter: Option<LogFilterReload>,
+}
+
+impl Reloader {
+    /// Read the config file again and apply its reloaded settings, keeping
+    /// the current ones if it is invalid.
+    pub(crate) fn reload(&mut self) -> Result<()> {
+        let file = read_config_file(&self.path)?;
+        let settings = file.hot_settings(&self.base)?;
+
+        if settings.log_level != self.current.log_level {
+            match &self.log_filter {
+                Some(set_log_filter) => set_log_filter(EnvFilter::try_new(&settings.log_level)?)?,
+                None => warn!("The relayer logger is not installed, not reloading the log level"),
+            }
+        }
+        self.image_ids.send_replace(settings.image_ids.clone());
+        self.alert_webhook
+            .send_replace(settings.alert_webhook.clone());
+        // The chains of the chains file keep their own fee caps.
+        for (index, limits) in self.chain_limits.iter().enumerate() {
+            limits.send_modify(|limits| {
+                if index == 0 {
+                    limits.fee_cap = settings.fee_cap;
+                }
+                limits.max_concurrent_relays = settings.max_concurrent_relays;
+                limits.relay_queue_depth = settings.relay_queue_depth;
+            });
+        }
+
+        for setting in self.started.restart_changes(&file) {
+            warn!(
+                setting,
+                "Changed setting requires a restart, keeping its current value"
+            );
+        }
+        let changes = settings_diff(&self.current, &settings);
+        info!(path = ?self.path, ?changes, "Reloaded config file");
+        self.current = settings;
+        Ok(())
+    }
+}
+
+/// Reload the config file on each SIGHUP.
+#[cfg(unix)]
+pub(crate) async fn reload_on_sighup(mut reloader: Reloader) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(error) => {
+            warn!(
+                ?error,
+                "Failed to listen for SIGHUP, not reloading the config file"
+            );
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        if let Err(error) = reloader.reload() {
+            tracing::error!(
+                "Failed to reload the config file, keeping the current settings: {error:#}"
+            );
+        }
+    }
+}
+
+/// Config files are only reloaded on SIGHUP, which doesn't exist here.
+#[cfg(not(unix))]
+pub(crate) async fn reload_on_sighup(_reloader: Reloader) {
+    warn!("SIGHUP is not supported, not reloading the config file");
+}
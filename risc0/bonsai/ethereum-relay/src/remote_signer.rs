@@ -0,0 +1,341 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signer of the relay transactions holding its key in a remote signer, e.g.
+//! Web3Signer, reached over JSON-RPC.
+
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use ethers::{
+    types::{transaction::eip2718::TypedTransaction, Address, Bytes, Signature},
+    utils::rlp::Rlp,
+};
+use reqwest::{Certificate, Identity, StatusCode, Url};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+
+use crate::{metrics::Metrics, RelayError};
+
+/// Prefix of the wallet key identifiers naming the URL of a remote signer,
+/// e.g. `remote:https://web3signer.example.com:9000`.
+pub const REMOTE_SIGNER_PREFIX: &str = "remote:";
+
+/// Number of times a failed remote signer request is retried, unless the
+/// remote signer rejected it.
+pub(crate) const REMOTE_SIGNER_RETRIES: u32 = 3;
+
+/// Delay before the first retry of a failed remote signer request, doubled on
+/// each retry.
+const REMOTE_SIGNER_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Upper bound on the duration of a single remote signer request.
+const REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How to reach a remote signer when the wallet key identifier is a remote
+/// signer URL.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RemoteSignerConfig {
+    /// Address of the wallet, discovered with `eth_accounts` if unset.
+    pub signer_address: Option<Address>,
+    /// PEM file of a CA certificate trusted for the remote signer connection,
+    /// in addition to the system ones.
+    pub tls_ca_cert: Option<PathBuf>,
+    /// PEM file holding the client certificate and private key presented to
+    /// the remote signer.
+    pub tls_client_cert: Option<PathBuf>,
+}
+
+impl RemoteSignerConfig {
+    fn http_client(&self) -> Result<reqwest::Client, RelayError> {
+        let mut builder = reqwest::Client::builder()
+            .use_rustls_tls()
+            .timeout(REMOTE_SIGNER_TIMEOUT);
+        if let Some(path) = &self.tls_ca_cert {
+            let cert = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))
+                .and_then(|pem| Ok(Certificate::from_pem(&pem)?))
+                .with_context(|| format!("invalid CA certificate {}", path.display()))
+                .map_err(RelayError::config)?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if let Some(path) = &self.tls_client_cert {
+            let identity = std::fs::read(path)
+                .with_context(|| format!("failed to read {}", path.display()))
+                .and_then(|pem| Ok(Identity::from_pem(&pem)?))
+                .with_context(|| format!("invalid client certificate {}", path.display()))
+                .map_err(RelayError::config)?;
+            builder = builder.identity(identity);
+        }
+        builder
+            .build()
+            .context("failed to create the remote signer HTTP client")
+            .map_err(RelayError::config)
+    }
+
+    /// Returns a signer of the wallet held by the remote signer at `url`.
+    pub(crate) async fn signer(
+        &self,
+        url: &str,
+        chain_id: u64,
+    ) -> Result<RemoteSigner, RelayError> {
+        let url = Url::parse(url)
+            .with_context(|| format!("invalid remote signer URL: {url}"))
+            .map_err(RelayError::config)?;
+        let mut signer = RemoteSigner {
+            client: self.http_client()?,
+            url,
+            address: Address::zero(),
+            chain_id,
+        };
+        signer.address = match self.signer_address {
+            Some(address) => address,
+            None => {
+                let accounts: Vec<Address> = signer
+                    .request("eth_accounts", Vec::<()>::new())
+                    .await
+                    .map_err(|source| signer.error(source))?;
+                *accounts.first().ok_or_else(|| {
+                    RelayError::ConfigError(format!(
+                        "the remote signer {} holds no account",
+                        signer.url
+                    ))
+                })?
+            }
+        };
+        Ok(signer)
+    }
+}
+
+/// Signer of a wallet whose key is held by a remote signer, signing with its
+/// `eth_signTransaction` and `eth_sign` JSON-RPC methods.
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: Url,
+    address: Address,
+    chain_id: u64,
+}
+
+impl RemoteSigner {
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    pub(crate) fn with_chain_id(self, chain_id: u64) -> Self {
+        Self { chain_id, ..self }
+    }
+
+    pub(crate) fn error(&self, source: RemoteSignerError) -> crate::RelaySignerError {
+        crate::RelaySignerError::Remote {
+            url: self.url.to_string(),
+            source,
+        }
+    }
+
+    /// Sign the transaction with `eth_signTransaction`. The returned raw
+    /// transaction must be the given one, signed by the wallet.
+    pub(crate) async fn sign_transaction(
+        &self,
+        tx: &TypedTransaction,
+    ) -> Result<Signature, RemoteSignerError> {
+        const METHOD: &str = "eth_signTransaction";
+
+        let mut tx = tx.clone();
+        tx.set_from(self.address);
+        if tx.chain_id().is_none() {
+            tx.set_chain_id(self.chain_id);
+        }
+        let raw: Bytes = self.request(METHOD, [&tx]).await?;
+        let (_, signature) = TypedTransaction::decode_signed(&Rlp::new(&raw))
+            .map_err(|err| RemoteSignerError::invalid(METHOD, err))?;
+        signature
+            .verify(tx.sighash(), self.address)
+            .map_err(|err| RemoteSignerError::invalid(METHOD, err))?;
+        Ok(signature)
+    }
+
+    /// Sign the message with `eth_sign`.
+    pub(crate) async fn sign_message(
+        &self,
+        message: &[u8],
+    ) -> Result<Signature, RemoteSignerError> {
+        const METHOD: &str = "eth_sign";
+
+        let raw: Bytes = self
+            .request(METHOD, (self.address, Bytes::from(message.to_vec())))
+            .await?;
+        let signature = Signature::try_from(raw.as_ref())
+            .map_err(|err| RemoteSignerError::invalid(METHOD, err))?;
+        signature
+            .verify(message, self.address)
+            .map_err(|err| RemoteSignerError::invalid(METHOD, err))?;
+        Ok(signature)
+    }
+
+    /// Send a JSON-RPC request to the remote signer, retrying it with an
+    /// exponential backoff unless the remote signer rejected it.
+    pub(crate) async fn request<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: P,
+    ) -> Result<R, RemoteSignerError> {
+        let mut retry = 0;
+        loop {
+            match self.request_once(method, &params).await {
+                Ok(result) => return Ok(result),
+                Err(err) => {
+                    Metrics::global()
+                        .remote_signer_errors
+                        .with_label_values(&[method])
+                        .inc();
+                    if !err.is_retryable() || retry == REMOTE_SIGNER_RETRIES {
+                        return Err(err);
+                    }
+                    retry += 1;
+                    let backoff = remote_signer_backoff(retry);
+                    warn!(
+                        url = %self.url,
+                        method,
+                        retry,
+                        ?backoff,
+                        "Remote signer request failed, retrying: {err}"
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+            }
+        }
+    }
+
+    async fn request_once<P: Serialize, R: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &P,
+    ) -> Result<R, RemoteSignerError> {
+        let response = self
+            .client
+            .post(self.url.clone())
+            .json(&JsonRpcRequest {
+                jsonrpc: "2.0",
+                id: 1,
+                method,
+                params,
+            })
+            .send()
+            .await
+            .map_err(|source| RemoteSignerError::Http {
+                method: method.to_string(),
+                source,
+            })?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(RemoteSignerError::Status {
+                method: method.to_string(),
+                status,
+            });
+        }
+        let response: JsonRpcResponse<R> =
+            response
+                .json()
+                .await
+                .map_err(|source| RemoteSignerError::Http {
+                    method: method.to_string(),
+                    source,
+                })?;
+        match response {
+            JsonRpcResponse::Success { result } => Ok(result),
+            JsonRpcResponse::Error { error } => Err(RemoteSignerError::Rpc {
+                method: method.to_string(),
+                code: error.code,
+                message: error.message,
+            }),
+        }
+    }
+}
+
+/// Delay before the given retry of a failed remote signer request.
+pub(crate) fn remote_signer_backoff(retry: u32) -> Duration {
+    REMOTE_SIGNER_BACKOFF.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+}
+
+#[derive(Serialize)]
+struct JsonRpcRequest<'a, P> {
+    jsonrpc: &'static str,
+    id: u64,
+    method: &'a str,
+    params: &'a P,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonRpcResponse<R> {
+    Success { result: R },
+    Error { error: JsonRpcErrorObject },
+}
+
+#[derive(Deserialize)]
+struct JsonRpcErrorObject {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteSignerError {
+    #[error("{method} request failed: {source}")]
+    Http {
+        method: String,
+        source: reqwest::Error,
+    },
+    #[error("{method} request failed with HTTP status {status}")]
+    Status { method: String, status: StatusCode },
+    #[error("{method} rejected ({code}): {message}")]
+    Rpc {
+        method: String,
+        code: i64,
+        message: String,
+    },
+    #[error("invalid {method} response: {message}")]
+    InvalidResponse { method: String, message: String },
+    #[error("{method} is not supported by remote signers")]
+    Unsupported { method: String },
+}
+
+impl RemoteSignerError {
+    fn invalid(method: &str, err: impl std::fmt::Display) -> Self {
+        Self::InvalidResponse {
+            method: method.to_string(),
+            message: err.to_string(),
+        }
+    }
+
+    /// Returns whether the request may succeed if sent again, i.e. whether the
+    /// remote signer was unreachable or unavailable rather than rejecting it.
+    pub(crate) fn is_retryable(&self) -> bool {
+        match self {
+            Self::Http { source, .. } => !source.is_decode(),
+            Self::Status { status, .. } => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::Rpc { .. } | Self::InvalidResponse { .. } | Self::Unsupported { .. } => false,
+        }
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Resubmission of the relay transaction of an event recorded in the event
+//! database, e.g. after it failed, without restarting the relayer.
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::anyhow;
+use bonsai_ethereum_contracts::{
+    i_bonsai_relay::{CallbackAuthorization, CallbackRequestFilter},
+    IBonsaiRelay,
+};
+use ethers::{
+    providers::{Middleware, PendingTransaction},
+    types::H256,
+};
+use tracing::info;
+
+use crate::{
+    event_db::{event_log, EventDb, EventStatus, PersistedEvent, RESUMED_TX_TIMEOUT},
+    uploader::completed_proofs::{
+        complete_proof::build_callback,
+        relay::{decode_revert_reason, is_permanent_revert, CallbackRelay, DirectRelay},
+    },
+    EthersClientConfig, RelayError,
+};
+
+/// Resubmit the relay transaction of the event with the given ID in the event
+/// database at `event_db`, returning the hash of the new transaction once
+/// mined.
+///
+/// The callback is rebuilt from the journal and seal recorded for the event,
+/// and from its `CallbackRequest` log, which must still be on chain. Events
+/// whose callback was already accepted on chain, or whose relay transaction
+/// is still pending, are not resubmitted.
+pub async fn resubmit_event(
+    client_config: &EthersClientConfig,
+    event_db: &Path,
+    event_id: i64,
+) -> Result<H256, RelayError> {
+    let db = EventDb::open(event_db).map_err(RelayError::config)?;
+    let event = db.event(event_id)?.ok_or_else(|| {
+        RelayError::ConfigError(format!(
+            "no event {event_id} in the event database {}",
+            event_db.display()
+        ))
+    })?;
+    if event.chain_id != client_config.eth_chain_id {
+        return Err(RelayError::ConfigError(format!(
+            "event {event_id} was emitted on chain {}, not on chain {}",
+            event.chain_id, client_config.eth_chain_id
+        )));
+    }
+    let client = Arc::new(client_config.get_client().await?);
+
+    check_not_accepted(client.as_ref(), &db, &event).await?;
+    let proof = event
+        .proof
+        .clone()
+        .ok_or_else(|| anyhow!("no proof was recorded for event {event_id}"))?;
+    let log = event_log(client.as_ref(), &event).await?.ok_or_else(|| {
+        anyhow!("the CallbackRequest log of event {event_id} is no longer on chain")
+    })?;
+    let request: CallbackRequestFilter = ethers::contract::parse_log(log)
+        .map_err(|err| anyhow!("invalid CallbackRequest log of event {event_id}: {err}"))?;
+    if H256::from(request.image_id) != event.image_id {
+        return Err(anyhow!(
+            "the CallbackRequest log of event {event_id} has image ID {:?}, recorded as {:?}",
+            H256::from(request.image_id),
+            event.image_id
+        )
+        .into());
+    }
+    let callback = build_callback(
+        &request,
+        &proof.journal,
+        CallbackAuthorization {
+            seal: proof.seal.into(),
+            post_state_digest: proof.post_state_digest.into(),
+        },
+    );
+
+    // Simulate the transaction first, as the relay contract may reject a
+    // proof it already accepted.
+    let relay = IBonsaiRelay::new(event.relay_address, client.clone());
+    match relay.invoke_callbacks(vec![callback.clone()]).call().await {
+        Ok(results) if results.iter().all(|invoked| *invoked) => (),
+        Ok(_) => return Err(anyhow!("the callback of event {event_id} would fail").into()),
+        Err(err) => {
+            let reason = err
+                .as_revert()
+                .map(|data| decode_revert_reason(data))
+                .unwrap_or_else(|| err.to_string());
+            if is_permanent_revert(&reason) {
+                db.set_status(&event, EventStatus::Completed, None)?;
+                return Err(anyhow!(
+                    "the proof of event {event_id} was already accepted on chain: {reason}"
+                )
+                .into());
+            }
+            return Err(anyhow!(
+                "the relay transaction of event {event_id} would revert: {reason}"
+            )
+            .into());
+        }
+    }
+
+    let tx_hash = DirectRelay
+        .invoke_callbacks(client.clone(), event.relay_address, vec![callback])
+        .await
+        .map_err(|err| anyhow!("failed to resubmit event {event_id}: {err:#}"))?;
+    db.set_status(&event, EventStatus::Submitted, Some(tx_hash))?;
+    info!(event_id, ?tx_hash, "Resubmitted the relay transaction");
+
+    let pending =
+        PendingTransaction::new(tx_hash, client.provider()).interval(client_config.poll_interval);
+    let receipt = tokio::time::timeout(RESUMED_TX_TIMEOUT, pending)
+        .await
+        .map_err(|_| anyhow!("relay transaction {tx_hash:?} still pending"))??
+        .ok_or_else(|| anyhow!("relay transaction {tx_hash:?} dropped"))?;
+    if receipt.status != Some(1.into()) {
+        db.set_status(&event, EventStatus::Failed, None)?;
+        return Err(anyhow!("relay transaction {tx_hash:?} reverted").into());
+    }
+    db.set_status(&event, EventStatus::Completed, None)?;
+    Ok(tx_hash)
+}
+
+/// Fail if the last relay transaction of the event was accepted on chain,
+/// recording it as completed, or is still pending.
+async fn check_not_accepted<M: Middleware>(
+    client: &M,
+    db: &EventDb,
+    event: &PersistedEvent,
+) -> Result<(), RelayError> {
+    let event_id = event.id;
+    if event.status == EventStatus::Completed {
+        return Err(
+            anyhow!("the callback of event {event_id} was already accepted on chain").into(),
+        );
+    }
+    let Some(relay_tx_hash) = event.relay_tx_hash else {
+        return Ok(());
+    };
+    let receipt = client
+        .get_transaction_receipt(relay_tx_hash)
+        .await
+        .map_err(|err| anyhow!("failed to get the receipt of {relay_tx_hash:?}: {err}"))?;
+    match receipt {
+        Some(receipt) if receipt.status == Some(1.into()) => {
+            db.set_status(event, EventStatus::Completed, None)?;
+            Err(anyhow!(
+                "the callback of event {event_id} was already accepted on chain, by \
+                {relay_tx_hash:?}"
+            )
+            .into())
+        }
+        Some(_) => Ok(()),
+        None => match client.get_transaction(relay_tx_hash).await {
+            Ok(Some(_)) => Err(anyhow!(
+                "the relay transaction {relay_tx_hash:?} of event {event_id} is still pending"
+            )
+            .into()),
+            _ => Ok(()),
+        },
+    }
+}
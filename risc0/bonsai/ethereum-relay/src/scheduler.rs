@@ -0,0 +1,356 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scheduler of the Bonsai sessions of new callback requests. By default, a
+//! request gets its session as soon as it is taken in, in the order the
+//! requests arrive. The config file can give each image a maximum number of
+//! sessions proving at once, a priority, and a daily budget of sessions, so
+//! that the cheap proofs of a chatty application never starve the large
+//! proofs of another one.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use ethers::types::H256;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use crate::{intake::IntakeClosed, metrics::Metrics};
+
+/// Scheduling settings of an image, from the `[scheduler.images."0x..."]`
+/// tables of the config file.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ImageSchedule {
+    /// Maximum number of sessions of the image proving at once, unlimited if
+    /// unset.
+    pub(crate) max_concurrent: Option<usize>,
+    /// The requests of the images of higher priority get the free sessions
+    /// first.
+    #[serde(default)]
+    pub(crate) priority: i32,
+    /// Maximum number of sessions of the image started per UTC day,
+    /// unlimited if unset.
+    pub(crate) daily_budget: Option<u32>,
+}
+
+/// Settings of the [Scheduler], from the `[scheduler]` table of the config
+/// file.
+///
+/// ```toml
+/// [scheduler]
+/// max_concurrent_sessions = 8
+///
+/// [scheduler.images."0x..."]
+/// max_concurrent = 2
+/// priority = 10
+/// daily_budget = 500
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SchedulerConfig {
+    /// Maximum number of sessions proving at once, across all images,
+    /// unlimited if unset.
+    pub(crate) max_concurrent_sessions: Option<usize>,
+    /// Settings of the images, by image ID. The other images are unlimited,
+    /// with priority 0.
+    #[serde(default)]
+    pub(crate) images: HashMap<H256, ImageSchedule>,
+}
+
+impl SchedulerConfig {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.max_concurrent_sessions == Some(0) {
+            bail!("scheduler.max_concurrent_sessions must be at least 1");
+        }
+        for (image_id, schedule) in &self.images {
+            if schedule.max_concurrent == Some(0) {
+                bail!("max_concurrent of image {image_id:?} must be at least 1");
+            }
+        }
+        Ok(())
+    }
+
+    fn schedule(&self, image_id: H256) -> ImageSchedule {
+        self.images.get(&image_id).cloned().unwrap_or_default()
+    }
+}
+
+/// A request waiting for a session.
+#[derive(Clone, Copy, Debug)]
+struct Waiter {
+    /// Order of arrival of the request.
+    ticket: u64,
+    image_id: H256,
+    priority: i32,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    config: SchedulerConfig,
+    /// Requests waiting for a session, in order of arrival.
+    waiting: Vec<Waiter>,
+    next_ticket: u64,
+    /// Number of sessions proving, by image.
+    in_flight: HashMap<H256, usize>,
+    /// Image of the sessions proving, by session UUID.
+    sessions: HashMap<String, H256>,
+    /// UTC day, since the Unix epoch, of the [State::started] counts.
+    day: u64,
+    /// Number of sessions started on the day, by image.
+    started: HashMap<H256, u32>,
+}
+
+impl State {
+    fn enqueue(&mut self, image_id: H256) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.waiting.push(Waiter {
+            ticket,
+            image_id,
+            priority: self.config.schedule(image_id).priority,
+        });
+        self.record_metrics(image_id);
+        ticket
+    }
+
+    fn dequeue(&mut self, ticket: u64) {
+        let Some(index) = self.waiting.iter().position(|w| w.ticket == ticket) else {
+            return;
+        };
+        let waiter = self.waiting.remove(index);
+        self.record_metrics(waiter.image_id);
+    }
+
+    fn total_in_flight(&self) -> usize {
+        self.in_flight.values().sum()
+    }
+
+    /// Whether a session of the image can start without exceeding the
+    /// concurrency limits.
+    fn has_free_session(&self, image_id: H256) -> bool {
+        let below = |count: usize, max: Option<usize>| max.map_or(true, |max| count < max);
+        below(self.total_in_flight(), self.config.max_concurrent_sessions)
+            && below(
+                self.in_flight.get(&image_id).copied().unwrap_or_default(),
+                self.config.schedule(image_id).max_concurrent,
+            )
+    }
+
+    /// Whether a session of the image can start today within its budget.
+    fn has_budget(&mut self, image_id: H256, day: u64) -> bool {
+        if self.day != day {
+            self.day = day;
+            self.started.clear();
+        }
+        let started = self.started.get(&image_id).copied().unwrap_or_default();
+        self.config
+            .schedule(image_id)
+            .daily_budget
+            .map_or(true, |budget| started < budget)
+    }
+
+    /// Ticket of the waiting request to start next: the one of highest
+    /// priority, then the oldest, among those with a free session.
+    fn next(&self) -> Option<u64> {
+        self.waiting
+            .iter()
+            .filter(|waiter| self.has_free_session(waiter.image_id))
+            .max_by_key(|waiter| (waiter.priority, Reverse(waiter.ticket)))
+            .map(|waiter| waiter.ticket)
+    }
+
+    fn start(&mut self, ticket: u64, image_id: H256) {
+        self.dequeue(ticket);
+        *self.in_flight.entry(image_id).or_default() += 1;
+        *self.started.entry(image_id).or_default() += 1;
+        self.record_metrics(image_id);
+    }
+
+    fn release(&mut self, image_id: H256) {
+        if let Some(count) = self.in_flight.get_mut(&image_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.in_flight.remove(&image_id);
+            }
+        }
+        self.record_metrics(image_id);
+    }
+
+    fn record_metrics(&self, image_id: H256) {
+        let queued = self
+            .waiting
+            .iter()
+            .filter(|waiter| waiter.image_id == image_id)
+            .count();
+        let in_flight = self.in_flight.get(&image_id).copied().unwrap_or_default();
+        Metrics::global().set_scheduled_sessions(image_id, queued, in_flight);
+    }
+}
+
+/// UTC day of the current time, since the Unix epoch.
+fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / (24 * 60 * 60)
+}
+
+/// Hands out the sessions of the new callback requests, shared by the event
+/// streams of all chains and the REST API.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Scheduler {
+    state: Arc<Mutex<State>>,
+    /// Notified whenever a session may have become free to another request.
+    changed: Arc<Notify>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(config: SchedulerConfig) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                config,
+                ..State::default()
+            })),
+            changed: Arc::new(Notify::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Wait for a session of the image to be free, behind the requests of
+    /// higher priority and the older ones of the same priority. Fails once
+    /// the daily budget of the image is spent.
+    pub(crate) async fn acquire(&self, image_id: H256) -> Result<SessionSlot, IntakeClosed> {
+        let ticket = {
+            let mut state = self.lock();
+            if !state.has_budget(image_id, today()) {
+                return Err(IntakeClosed::BudgetSpent { image_id });
+            }
+            state.enqueue(image_id)
+        };
+        // Leaves the queue if the request is given up on while waiting.
+        let _waiting = Waiting {
+            scheduler: self,
+            ticket,
+        };
+        loop {
+            let changed = self.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            {
+                let mut state = self.lock();
+                if !state.has_budget(image_id, today()) {
+                    return Err(IntakeClosed::BudgetSpent { image_id });
+                }
+                if state.next() == Some(ticket) {
+                    state.start(ticket, image_id);
+                    // The requests behind may have a free session too.
+                    self.changed.notify_waiters();
+                    return Ok(SessionSlot::new(self.clone(), image_id));
+                }
+            }
+            changed.await;
+        }
+    }
+
+    /// Take a free session of the image without waiting, failing if the
+    /// waiting requests are to get the free sessions first, or if none is
+    /// free.
+    pub(crate) fn try_acquire(&self, image_id: H256) -> Result<SessionSlot, IntakeClosed> {
+        let mut state = self.lock();
+        if !state.has_budget(image_id, today()) {
+            return Err(IntakeClosed::BudgetSpent { image_id });
+        }
+        let ticket = state.enqueue(image_id);
+        if state.next() == Some(ticket) {
+            state.start(ticket, image_id);
+            return Ok(SessionSlot::new(self.clone(), image_id));
+        }
+        state.dequeue(ticket);
+        Err(IntakeClosed::Busy { image_id })
+    }
+
+    /// Free the session of the given UUID once it is no longer proving,
+    /// whether it completed, failed or expired.
+    pub(crate) fn finish(&self, session_id: &str) {
+        let mut state = self.lock();
+        if let Some(image_id) = state.sessions.remove(session_id) {
+            state.release(image_id);
+            self.changed.notify_waiters();
+        }
+    }
+}
+
+/// Removes a waiting request from the queue when dropped.
+struct Waiting<'a> {
+    scheduler: &'a Scheduler,
+    ticket: u64,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().dequeue(self.ticket);
+        // The request may have been the next one, holding the others back.
+        self.scheduler.changed.notify_waiters();
+    }
+}
+
+/// A session of an image handed out by the [Scheduler], freed when dropped
+/// unless held by the Bonsai session created in it.
+#[derive(Debug)]
+pub(crate) struct SessionSlot {
+    scheduler: Scheduler,
+    image_id: H256,
+    held: bool,
+}
+
+impl SessionSlot {
+    fn new(scheduler: Scheduler, image_id: H256) -> Self {
+        Self {
+            scheduler,
+            image_id,
+            held: false,
+        }
+    }
+
+    /// Hold the session until the Bonsai session of the given UUID is no
+    /// longer proving, see [Scheduler::finish].
+    pub(crate) fn hold_for(mut self, session_id: &str) {
+        self.scheduler
+            .lock()
+            .sessions
+            .insert(session_id.to_string(), self.image_id);
+        self.held = true;
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        if !self.held {
+            self.scheduler.lock().release(self.image_id);
+            self.scheduler.changed.notify_waiters();
+        }
+    }
+}
@@ -15,11 +15,12 @@
 //! An asynchronous Client API.
 
 use anyhow::Context;
+use bonsai_sdk::alpha::responses::SnarkProof;
 use reqwest::{header, Client as AsyncClient, Response};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
-use crate::api::routes::CALLBACK_ROUTE;
+use crate::api::routes::{CALLBACK_ROUTE, SUBMISSION_ROUTE};
 
 /// A Bonsai CallbackRequest for Ethereum.
 #[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
@@ -34,6 +35,54 @@ pub struct CallbackRequest {
     pub function_selector: ethers::types::Selector,
     /// The gas limit.
     pub gas_limit: u64,
+    /// The relay contract to invoke the callback through. Required if the
+    /// relayer serves more than one relay contract.
+    #[schema(value_type = Option<String>)]
+    pub relay_address: Option<ethers::types::Address>,
+    /// The chain of the relay contract. Required if the relayer serves relay
+    /// contracts on more than one chain at the `relay_address`.
+    pub chain_id: Option<u64>,
+    /// The Unix time, in seconds, after which the request expires if it is
+    /// not proven yet.
+    pub deadline: Option<u64>,
+}
+
+/// A proof generated outside of Bonsai, submitted to a relayer running in
+/// submission-only mode to invoke a callback with its journal.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct ProofSubmission {
+    /// The image ID of the guest.
+    pub image_id: [u8; 32],
+    /// The journal committed by the guest.
+    pub journal: Vec<u8>,
+    /// The digest of the post-execution state of the guest.
+    pub post_state_digest: [u8; 32],
+    /// The proof of the guest execution.
+    #[schema(value_type = Object)]
+    pub proof: SubmittedProof,
+    /// The Ethereum address of the callback.
+    pub callback_contract: ethers::types::Address,
+    /// The function selector of the callback.
+    pub function_selector: ethers::types::Selector,
+    /// The gas limit.
+    pub gas_limit: u64,
+    /// The relay contract to invoke the callback through. Required if the
+    /// relayer serves more than one relay contract.
+    #[schema(value_type = Option<String>)]
+    pub relay_address: Option<ethers::types::Address>,
+    /// The chain of the relay contract. Required if the relayer serves relay
+    /// contracts on more than one chain at the `relay_address`.
+    pub chain_id: Option<u64>,
+}
+
+/// The proof of a [ProofSubmission].
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub enum SubmittedProof {
+    /// A Groth16 SNARK proof, as produced by Bonsai.
+    Snark(SnarkProof),
+    /// A bincode-serialized zkVM receipt. As the relay contract only verifies
+    /// SNARK proofs, receipts are only accepted by relayers in dev mode.
+    Receipt(Vec<u8>),
 }
 
 /// The Errors that may occur when processing a [Client] request.
@@ -127,6 +176,21 @@ impl Client {
 
         Ok(())
     }
+
+    /// Submit a pre-generated proof to a relayer running in submission-only
+    /// mode, returning the ID of the resulting callback request.
+    pub async fn submit_proof(&self, submission: ProofSubmission) -> Result<String, ClientError> {
+        let res = self
+            .client
+            .post(format!("{}{SUBMISSION_ROUTE}", self.url))
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(bincode::serialize(&submission)?)
+            .send()
+            .await?;
+        let res = error_for_status(res).await?;
+
+        Ok(res.text().await?)
+    }
 }
 
 /// Turn a response into an error if the server returned an error.
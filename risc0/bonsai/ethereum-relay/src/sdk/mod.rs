@@ -17,4 +17,4 @@
 pub mod client;
 pub mod utils;
 
-pub use client::{CallbackRequest, Client, ClientError};
+pub use client::{CallbackRequest, Client, ClientError, ProofSubmission, SubmittedProof};
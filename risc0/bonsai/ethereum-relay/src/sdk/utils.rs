@@ -14,7 +14,7 @@
 
 use std::{sync::Arc, time::Duration};
 
-use anyhow::{Context, Result};
+use anyhow::Context;
 use ethers::{
     prelude::*,
     providers::{Http, Provider, Ws},
@@ -22,7 +22,10 @@ use ethers::{
     utils::AnvilInstance,
 };
 
-use crate::{client_config::WalletKey, sdk::utils::k256::ecdsa::SigningKey, EthersClientConfig};
+use crate::{
+    client_config::WalletKeyIdentifier, sdk::utils::k256::ecdsa::SigningKey, EthersClientConfig,
+    RelayError, DEFAULT_ETH_NODE_TIMEOUT,
+};
 
 const POLL_INTERVAL: Duration = Duration::from_secs(1);
 const WAIT_DURATION: Duration = Duration::from_secs(5);
@@ -30,56 +33,77 @@ const MAX_RETRIES: u64 = 7 * 24 * 60 * 60 / WAIT_DURATION.as_secs(); // 1 week
 
 pub(crate) type Client<P> = Arc<SignerMiddleware<Provider<P>, LocalWallet>>;
 
-/// Returns a [LocalWallet] derived from either a private key as a hex string,
-/// or an AWS KMS key identifier defined by the env variable
-/// `WALLET_KEY_IDENTIFIER`. If no key is set, returns a new [LocalWallet] from
-/// the given optional `anvil` instance.
-pub fn get_wallet(anvil: Option<&AnvilInstance>) -> Result<Wallet<SigningKey>> {
-    let wallet_key_identifier = get_wallet_key_identifier(anvil)?;
-    let wallet_signing_key = SigningKey::from(wallet_key_identifier.get_key());
-    Ok(LocalWallet::from(wallet_signing_key))
+/// Returns a [LocalWallet] derived from the private key, as a hex string,
+/// defined by the env variable `WALLET_KEY_IDENTIFIER`. If no key is set,
+/// returns a new [LocalWallet] from the given optional `anvil` instance.
+pub fn get_wallet(anvil: Option<&AnvilInstance>) -> Result<Wallet<SigningKey>, RelayError> {
+    match get_wallet_key_identifier(anvil)? {
+        WalletKeyIdentifier::PrivateKey(key) => {
+            Ok(LocalWallet::from(SigningKey::from(key.get_key())))
+        }
+        WalletKeyIdentifier::Kms(key_id) => Err(RelayError::ConfigError(format!(
+            "expected a private key, got the AWS KMS key {key_id}"
+        ))),
+        WalletKeyIdentifier::Remote(url) => Err(RelayError::ConfigError(format!(
+            "expected a private key, got the remote signer {url}"
+        ))),
+        WalletKeyIdentifier::Ledger(path) => Err(RelayError::ConfigError(format!(
+            "expected a private key, got the Ledger account {path}"
+        ))),
+    }
 }
 
-/// Returns a wallet key identifier defined by the env variable
-/// `WALLET_KEY_IDENTIFIER` or from the given optional `anvil` instance.
-pub fn get_wallet_key_identifier(anvil: Option<&AnvilInstance>) -> Result<WalletKey> {
+/// Returns a wallet key identifier, either a private key as a hex string or an
+/// AWS KMS key identifier, defined by the env variable `WALLET_KEY_IDENTIFIER`
+/// or from the given optional `anvil` instance.
+pub fn get_wallet_key_identifier(
+    anvil: Option<&AnvilInstance>,
+) -> Result<WalletKeyIdentifier, RelayError> {
     match std::env::var("WALLET_KEY_IDENTIFIER") {
         Ok(wallet_key_identifier) => wallet_key_identifier.try_into(),
-        _ => {
-            let anvil = anvil.context("Anvil not instantiated.")?;
-            Ok(anvil.keys()[0].clone().into())
-        }
+        _ => Ok(instantiated(anvil)?.keys()[0].clone().into()),
     }
 }
 
-/// Returns an abstract provider for interacting with the Ethereum JSON RPC API
-/// over HTTP.
-pub fn get_http_provider(anvil: Option<&AnvilInstance>) -> Result<Provider<Http>> {
+fn instantiated(anvil: Option<&AnvilInstance>) -> Result<&AnvilInstance, RelayError> {
+    anvil.ok_or_else(|| RelayError::ConfigError("Anvil not instantiated.".to_string()))
+}
+
+/// Returns the HTTP endpoint for the Ethereum JSON RPC API.
+pub fn get_http_provider_endpoint(anvil: Option<&AnvilInstance>) -> Result<String, RelayError> {
     let endpoint = match std::env::var("ETHEREUM_HOST") {
         Ok(ethereum_host) => format!("http://{ethereum_host}"),
-        _ => anvil.context("Anvil not instantiated.")?.endpoint(),
+        _ => instantiated(anvil)?.endpoint(),
     };
+    Ok(endpoint)
+}
+
+/// Returns an abstract provider for interacting with the Ethereum JSON RPC API
+/// over HTTP.
+pub fn get_http_provider(anvil: Option<&AnvilInstance>) -> Result<Provider<Http>, RelayError> {
+    let endpoint = get_http_provider_endpoint(anvil)?;
     Ok(Provider::<Http>::try_from(endpoint)
-        .context("could not connect to {endpoint}")?
+        .context("could not connect to {endpoint}")
+        .map_err(RelayError::config)?
         .interval(POLL_INTERVAL))
 }
 
 /// Returns the Websocket endpoint for the Ethereum JSON RPC API.
-pub async fn get_ws_provider_endpoint(anvil: Option<&AnvilInstance>) -> Result<String> {
+pub async fn get_ws_provider_endpoint(anvil: Option<&AnvilInstance>) -> Result<String, RelayError> {
     let endpoint = match std::env::var("ETHEREUM_HOST") {
         Ok(ethereum_host) => format!("ws://{ethereum_host}"),
-        _ => anvil.context("Anvil not instantiated.")?.ws_endpoint(),
+        _ => instantiated(anvil)?.ws_endpoint(),
     };
     Ok(endpoint)
 }
 
 /// Returns an abstract provider for interacting with the Ethereum JSON RPC API
 /// over Websockets.
-pub async fn get_ws_provider(anvil: Option<&AnvilInstance>) -> Result<Provider<Ws>> {
+pub async fn get_ws_provider(anvil: Option<&AnvilInstance>) -> Result<Provider<Ws>, RelayError> {
     let endpoint = get_ws_provider_endpoint(anvil).await?;
     Ok(Provider::<Ws>::connect(&endpoint)
         .await
-        .context("could not connect to {endpoint}")?
+        .map_err(|err| RelayError::EthereumError(err.into()))?
         .interval(POLL_INTERVAL))
 }
 
@@ -87,12 +111,8 @@ pub async fn get_ws_provider(anvil: Option<&AnvilInstance>) -> Result<Provider<W
 pub async fn get_ethers_client<P: JsonRpcClient>(
     provider: Provider<P>,
     wallet: LocalWallet,
-) -> Result<Client<P>> {
-    let chain_id = provider
-        .get_chainid()
-        .await
-        .context("could not get chain id")?
-        .as_u64();
+) -> Result<Client<P>, RelayError> {
+    let chain_id = provider.get_chainid().await?.as_u64();
     Ok(Arc::new(SignerMiddleware::new(
         provider,
         wallet.with_chain_id(chain_id),
@@ -100,7 +120,9 @@ pub async fn get_ethers_client<P: JsonRpcClient>(
 }
 
 /// Returns an Ethereum Client Configuration struct.
-pub async fn get_ethers_client_config(anvil: Option<&AnvilInstance>) -> Result<EthersClientConfig> {
+pub async fn get_ethers_client_config(
+    anvil: Option<&AnvilInstance>,
+) -> Result<EthersClientConfig, RelayError> {
     let provider = get_ws_provider(anvil).await.unwrap();
     let eth_node_url = get_ws_provider_endpoint(anvil).await.unwrap();
     let eth_chain_id = provider.get_chainid().await.unwrap().as_u64();
@@ -111,6 +133,7 @@ pub async fn get_ethers_client_config(anvil: Option<&AnvilInstance>) -> Result<E
         wallet_key_identifier,
         MAX_RETRIES,
         WAIT_DURATION,
+        DEFAULT_ETH_NODE_TIMEOUT,
     );
     Ok(ethers_client_config)
 }
@@ -0,0 +1,310 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signers of the relay transactions, holding their key locally, in AWS KMS,
+//! in a remote signer or on a Ledger.
+
+use std::{fmt::Debug, str::FromStr};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use ethers::{
+    signers::{LocalWallet, Signer, WalletError},
+    types::{
+        transaction::{eip2718::TypedTransaction, eip712::Eip712},
+        Address, Signature,
+    },
+};
+use ethers_signers::{AwsSigner, AwsSignerError};
+use rusoto_core::{
+    credential::{DefaultCredentialsProvider, ProfileProvider},
+    HttpClient, Region, RusotoError,
+};
+use rusoto_kms::{DescribeKeyRequest, Kms, KmsClient};
+
+use crate::{
+    ledger_signer::{LedgerSigner, LedgerSignerError},
+    remote_signer::{RemoteSigner, RemoteSignerError},
+    RelayError,
+};
+
+/// How to reach AWS KMS when the wallet key identifier is a KMS key. The
+/// default AWS environment is used for the settings left unset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KmsConfig {
+    /// AWS region of the key, e.g. `us-east-1`.
+    pub region: Option<String>,
+    /// Profile of the AWS config and credentials files to use.
+    pub profile: Option<String>,
+    /// KMS endpoint overriding the one of the region, e.g. the URL of a
+    /// localstack instance.
+    pub endpoint: Option<String>,
+}
+
+impl KmsConfig {
+    pub(crate) fn region(&self) -> Result<Region, RelayError> {
+        let region = match (&self.region, &self.profile) {
+            (Some(region), _) => Some(region.clone()),
+            (None, Some(profile)) => profile_provider(profile)?
+                .region_from_profile()
+                .with_context(|| format!("failed to read the region of AWS profile {profile}"))
+                .map_err(RelayError::config)?,
+            (None, None) => None,
+        };
+        match (region, &self.endpoint) {
+            (region, Some(endpoint)) => Ok(Region::Custom {
+                name: region.unwrap_or_else(|| Region::default().name().to_string()),
+                endpoint: endpoint.clone(),
+            }),
+            (Some(region), None) => Region::from_str(&region)
+                .with_context(|| format!("invalid AWS region: {region}"))
+                .map_err(RelayError::config),
+            (None, None) => Ok(Region::default()),
+        }
+    }
+
+    /// Returns a client of the KMS of the configured region or endpoint.
+    pub fn client(&self) -> Result<KmsClient, RelayError> {
+        let region = self.region()?;
+        let http_client = HttpClient::new()
+            .context("failed to create the AWS HTTP client")
+            .map_err(RelayError::config)?;
+        Ok(match &self.profile {
+            Some(profile) => KmsClient::new_with(http_client, profile_provider(profile)?, region),
+            None => KmsClient::new_with(
+                http_client,
+                DefaultCredentialsProvider::new()
+                    .context("failed to load the AWS credentials")
+                    .map_err(RelayError::config)?,
+                region,
+            ),
+        })
+    }
+
+    /// Returns a signer of the KMS key `key_id`, given as a key ID, key ARN,
+    /// alias name or alias ARN.
+    pub(crate) async fn signer(
+        &self,
+        key_id: &str,
+        chain_id: u64,
+    ) -> Result<RelaySigner, RelayError> {
+        let kms = self.client()?;
+        let key_arn = kms
+            .describe_key(DescribeKeyRequest {
+                key_id: key_id.to_string(),
+                grant_tokens: None,
+            })
+            .await
+            .map_err(|err| RelaySignerError::KmsKey {
+                key_id: key_id.to_string(),
+                code: aws_error_code(&err),
+                message: err.to_string(),
+            })?
+            .key_metadata
+            .and_then(|metadata| metadata.arn)
+            .ok_or_else(|| RelaySignerError::KmsKey {
+                key_id: key_id.to_string(),
+                code: "none".to_string(),
+                message: "the key has no ARN".to_string(),
+            })?;
+        let signer = AwsSigner::new(kms, &key_arn, chain_id)
+            .await
+            .map_err(|source| RelaySignerError::kms(&key_arn, source))?;
+        Ok(RelaySigner::Kms { signer, key_arn })
+    }
+}
+
+fn profile_provider(profile: &str) -> Result<ProfileProvider, RelayError> {
+    let mut provider = ProfileProvider::new()
+        .context("failed to locate the AWS config files")
+        .map_err(RelayError::config)?;
+    provider.set_profile(profile);
+    Ok(provider)
+}
+
+/// Signer of the relay transactions of a wallet.
+#[derive(Clone, Debug)]
+pub enum RelaySigner {
+    /// Signs with a private key held by the relayer.
+    Local(LocalWallet),
+    /// Signs with a key held by AWS KMS.
+    Kms { signer: AwsSigner, key_arn: String },
+    /// Signs with a key held by a remote signer, e.g. Web3Signer.
+    Remote(RemoteSigner),
+    /// Signs with a key held by a Ledger hardware wallet.
+    Ledger(LedgerSigner),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RelaySignerError {
+    #[error(transparent)]
+    Local(#[from] WalletError),
+    #[error("KMS signing with key {key_arn} failed ({code}): {source}")]
+    Kms {
+        key_arn: String,
+        /// AWS error code of the failed KMS request, e.g.
+        /// `DisabledException`.
+        code: String,
+        source: AwsSignerError,
+    },
+    #[error("failed to look up KMS key {key_id} ({code}): {message}")]
+    KmsKey {
+        key_id: String,
+        code: String,
+        message: String,
+    },
+    #[error("remote signing with {url} failed: {source}")]
+    Remote {
+        url: String,
+        source: RemoteSignerError,
+    },
+    #[error("Ledger signing with account {path} failed: {source}")]
+    Ledger {
+        path: String,
+        source: LedgerSignerError,
+    },
+}
+
+impl RelaySignerError {
+    pub(crate) fn kms(key_arn: &str, source: AwsSignerError) -> Self {
+        let code = match &source {
+            AwsSignerError::SignError(err) => aws_error_code(err),
+            AwsSignerError::GetPublicKeyError(err) => aws_error_code(err),
+            _ => "none".to_string(),
+        };
+        Self::Kms {
+            key_arn: key_arn.to_string(),
+            code,
+            source,
+        }
+    }
+}
+
+/// Returns the AWS error code of a failed KMS request, e.g.
+/// `NotFoundException`.
+pub(crate) fn aws_error_code<E: Debug>(err: &RusotoError<E>) -> String {
+    match err {
+        // The service errors are named after their AWS error code, without
+        // its `Exception` suffix.
+        RusotoError::Service(err) => {
+            let name = format!("{err:?}");
+            format!("{}Exception", name.split('(').next().unwrap_or_default())
+        }
+        RusotoError::Unknown(response) => rusoto_core::proto::json::Error::parse(response)
+            .map(|err| err.typ)
+            .unwrap_or_else(|| format!("HTTP {}", response.status)),
+        RusotoError::HttpDispatch(_) => "HttpDispatchError".to_string(),
+        RusotoError::Credentials(_) => "CredentialsError".to_string(),
+        RusotoError::Validation(_) => "ValidationError".to_string(),
+        RusotoError::ParseError(_) => "ParseError".to_string(),
+        RusotoError::Blocking => "BlockingError".to_string(),
+    }
+}
+
+#[async_trait]
+impl Signer for RelaySigner {
+    type Error = RelaySignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_message(message).await?),
+            Self::Kms { signer, key_arn } => signer
+                .sign_message(message)
+                .await
+                .map_err(|source| RelaySignerError::kms(key_arn, source)),
+            Self::Remote(signer) => signer
+                .sign_message(message.as_ref())
+                .await
+                .map_err(|source| signer.error(source)),
+            Self::Ledger(signer) => signer
+                .sign_message(message.as_ref())
+                .await
+                .map_err(|source| signer.error(source)),
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_transaction(tx).await?),
+            Self::Kms { signer, key_arn } => signer
+                .sign_transaction(tx)
+                .await
+                .map_err(|source| RelaySignerError::kms(key_arn, source)),
+            Self::Remote(signer) => signer
+                .sign_transaction(tx)
+                .await
+                .map_err(|source| signer.error(source)),
+            Self::Ledger(signer) => signer
+                .sign_transaction(tx)
+                .await
+                .map_err(|source| signer.error(source)),
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => Ok(wallet.sign_typed_data(payload).await?),
+            Self::Kms { signer, key_arn } => signer
+                .sign_typed_data(payload)
+                .await
+                .map_err(|source| RelaySignerError::kms(key_arn, source)),
+            // Remote signers sign typed data given as JSON, which an [Eip712]
+            // payload can't be serialized to.
+            Self::Remote(signer) => Err(signer.error(RemoteSignerError::Unsupported {
+                method: "eth_signTypedData".to_string(),
+            })),
+            // The relayer never signs typed data, which the Ledger would have
+            // to be given as its [Eip712] type rather than a payload.
+            Self::Ledger(signer) => Err(signer.error(LedgerSignerError::Unsupported {
+                method: "eth_signTypedData".to_string(),
+            })),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            Self::Local(wallet) => wallet.address(),
+            Self::Kms { signer, .. } => signer.address(),
+            Self::Remote(signer) => signer.address(),
+            Self::Ledger(signer) => signer.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Self::Local(wallet) => wallet.chain_id(),
+            Self::Kms { signer, .. } => signer.chain_id(),
+            Self::Remote(signer) => signer.chain_id(),
+            Self::Ledger(signer) => signer.chain_id(),
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Self::Local(wallet) => Self::Local(wallet.with_chain_id(chain_id)),
+            Self::Kms { signer, key_arn } => Self::Kms {
+                signer: signer.with_chain_id(chain_id),
+                key_arn,
+            },
+            Self::Remote(signer) => Self::Remote(signer.with_chain_id(chain_id.into())),
+            Self::Ledger(signer) => Self::Ledger(signer.with_chain_id(chain_id.into())),
+        }
+    }
+}
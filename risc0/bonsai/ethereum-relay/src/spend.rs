@@ -0,0 +1,269 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Daily spend limit of the relayer wallet. The gas cost of the relay
+//! transactions mined on each chain is summed over a rolling 24-hour window,
+//! and once it reaches the limit the relay transactions of the chain are held
+//! back until enough of the spend leaves the window, the limit is raised, or
+//! an admin resumes early. The spends are recorded in the event database, if
+//! any, so that the accounting survives restarts.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use anyhow::Result;
+use ethers::{
+    types::{Address, TransactionReceipt, H256, U256},
+    utils::format_ether,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    event_db::EventDb,
+    events::{self, EventSender, RelayerEvent},
+    metrics::Metrics,
+};
+
+/// Window over which the spends are summed against the limit.
+pub(crate) const SPEND_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Gas cost of a mined relay transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Spend {
+    /// Chain on which the transaction was mined.
+    pub(crate) chain_id: u64,
+    /// Unix time, in milliseconds, at which the transaction was confirmed.
+    pub(crate) spent_at: u64,
+    /// Gas used times the effective gas price, in wei.
+    pub(crate) amount: U256,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    max_daily_spend: Option<U256>,
+    chains: BTreeMap<u64, ChainSpends>,
+}
+
+/// Spends of the window on a chain.
+#[derive(Debug, Default)]
+struct ChainSpends {
+    /// Spends of the window, oldest first.
+    spends: VecDeque<Spend>,
+    limited: bool,
+}
+
+/// Daily spend limit of the relayer wallet, shared by the complete proof
+/// managers of all chains and the REST API. The limit applies to the spend of
+/// each chain on its own. Unlimited by default.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SpendLimit {
+    state: Arc<Mutex<State>>,
+    event_db: Option<EventDb>,
+    events: Option<EventSender>,
+}
+
+impl SpendLimit {
+    pub(crate) fn new(max_daily_spend: Option<U256>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                max_daily_spend,
+                ..State::default()
+            })),
+            ..Self::default()
+        }
+    }
+
+    /// Record the spends in the given database, starting from the ones of the
+    /// window recorded by the previous runs.
+    pub(crate) fn with_event_db(self, event_db: Option<EventDb>) -> Result<Self> {
+        if let Some(db) = &event_db {
+            let since = events::now().saturating_sub(SPEND_WINDOW.as_millis() as u64);
+            let mut state = self.lock();
+            for spend in db.spends_since(since)? {
+                let chain = state.chains.entry(spend.chain_id).or_default();
+                chain.spends.push_back(spend);
+            }
+        }
+        Ok(Self { event_db, ..self })
+    }
+
+    /// Publish a [RelayerEvent::SpendLimitReached] on the given channel when
+    /// the limit is reached.
+    pub(crate) fn with_events(self, events: Option<EventSender>) -> Self {
+        Self { events, ..self }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Record the gas cost of a relay transaction of `signer` mined on the
+    /// chain. The daily spend limit applies to all the signers of the chain
+    /// together.
+    pub(crate) fn record(&self, chain_id: u64, signer: Address, tx_hash: H256, amount: U256) {
+        Metrics::global().record_signer_spend(chain_id, signer, amount);
+        let spend = Spend {
+            chain_id,
+            spent_at: events::now(),
+            amount,
+        };
+        if let Some(db) = &self.event_db {
+            if let Err(error) = db.insert_spend(tx_hash, &spend) {
+                warn!(?error, "Failed to record the spend in the event database");
+            }
+        }
+        let mut state = self.lock();
+        let chain = state.chains.entry(chain_id).or_default();
+        chain.spends.push_back(spend);
+        self.update(&mut state, chain_id);
+    }
+
+    /// Whether the relay transactions of the chain are held back, the spend of
+    /// the window on the chain having reached the limit.
+    pub(crate) fn is_limited(&self, chain_id: u64) -> bool {
+        self.update(&mut self.lock(), chain_id)
+    }
+
+    /// Returns the spend of the window on the chain, in wei.
+    pub(crate) fn spent(&self, chain_id: u64) -> U256 {
+        let mut state = self.lock();
+        self.update(&mut state, chain_id);
+        total(&state.chains[&chain_id].spends)
+    }
+
+    /// Returns the chains whose spend is accounted for, in the order of their
+    /// IDs.
+    pub(crate) fn chain_ids(&self) -> Vec<u64> {
+        self.lock().chains.keys().copied().collect()
+    }
+
+    /// Returns the chains whose relay transactions are held back, in the order
+    /// of their IDs.
+    pub(crate) fn limited_chain_ids(&self) -> Vec<u64> {
+        self.chain_ids()
+            .into_iter()
+            .filter(|chain_id| self.is_limited(*chain_id))
+            .collect()
+    }
+
+    pub(crate) fn max_daily_spend(&self) -> Option<U256> {
+        self.lock().max_daily_spend
+    }
+
+    /// Change the limit, e.g. to raise it, until the next restart. The spend
+    /// is unlimited if `None`.
+    pub(crate) fn set_max_daily_spend(&self, max_daily_spend: Option<U256>) {
+        let mut state = self.lock();
+        info!(
+            max_daily_spend = ?max_daily_spend.map(format_ether),
+            "Daily spend limit changed"
+        );
+        state.max_daily_spend = max_daily_spend;
+        self.update_all(&mut state);
+    }
+
+    /// Send the relay transactions again before the spend leaves the window,
+    /// by forgetting the spends recorded so far.
+    pub(crate) fn resume(&self) {
+        if let Some(db) = &self.event_db {
+            if let Err(error) = db.clear_spends() {
+                warn!(?error, "Failed to clear the spends of the event database");
+            }
+        }
+        let mut state = self.lock();
+        for (chain_id, chain) in state.chains.iter_mut() {
+            info!(
+                chain_id,
+                spent = format_ether(total(&chain.spends)),
+                "Resuming the relay transactions, forgetting the spends of the last 24 hours"
+            );
+            chain.spends.clear();
+        }
+        self.update_all(&mut state);
+    }
+
+    fn update_all(&self, state: &mut State) {
+        let chain_ids: Vec<u64> = state.chains.keys().copied().collect();
+        for chain_id in chain_ids {
+            self.update(state, chain_id);
+        }
+    }
+
+    /// Drop the spends of the chain that left the window, and hold back or
+    /// resume its relay transactions as the remaining spend reaches the limit
+    /// or not.
+    fn update(&self, state: &mut State, chain_id: u64) -> bool {
+        let since = events::now().saturating_sub(SPEND_WINDOW.as_millis() as u64);
+        let max_daily_spend = state.max_daily_spend;
+        let chain = state.chains.entry(chain_id).or_default();
+        while chain
+            .spends
+            .front()
+            .is_some_and(|spend| spend.spent_at < since)
+        {
+            chain.spends.pop_front();
+        }
+        let spent = total(&chain.spends);
+        let limited = max_daily_spend.is_some_and(|max| spent >= max);
+        if limited != chain.limited {
+            chain.limited = limited;
+            let max_daily_spend = max_daily_spend.unwrap_or_default();
+            if limited {
+                error!(
+                    chain_id,
+                    spent = format_ether(spent),
+                    max_daily_spend = format_ether(max_daily_spend),
+                    "DAILY SPEND LIMIT REACHED: holding back the relay transactions"
+                );
+                events::emit(
+                    &self.events,
+                    RelayerEvent::SpendLimitReached {
+                        timestamp: events::now(),
+                        chain_id,
+                        spent,
+                        max_daily_spend,
+                    },
+                );
+            } else {
+                info!(
+                    chain_id,
+                    spent = format_ether(spent),
+                    "Daily spend below the limit, sending the relay transactions again"
+                );
+            }
+        }
+        Metrics::global().set_daily_spend(chain_id, spent, limited);
+        limited
+    }
+}
+
+/// Returns the gas cost of a mined transaction, in wei: its gas used times its
+/// effective gas price.
+pub(crate) fn receipt_cost(receipt: &TransactionReceipt) -> U256 {
+    receipt
+        .gas_used
+        .unwrap_or_default()
+        .saturating_mul(receipt.effective_gas_price.unwrap_or_default())
+}
+
+fn total(spends: &VecDeque<Spend>) -> U256 {
+    spends.iter().fold(U256::zero(), |total, spend| {
+        total.saturating_add(spend.amount)
+    })
+}
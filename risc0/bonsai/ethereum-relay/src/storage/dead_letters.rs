@@ -0,0 +1,208 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bounded list of the callback requests that failed, kept with the stage and
+//! reason of their failure so that they can be retried.
+
+use std::{
+    collections::VecDeque,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{anyhow, Result};
+use tracing::warn;
+
+use crate::{
+    event_db::EventDb, events, metrics::Metrics, storage::ProofRequestInformation,
+    DEFAULT_MAX_DEAD_LETTERS,
+};
+
+/// Stage at which a callback request failed, from which it is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum FailureStage {
+    /// Bonsai failed to prove the request, which is proven again.
+    Proving,
+    /// The request was proven, but its relay transaction failed or its proof
+    /// was dropped before being sent, and the proof is relayed again.
+    Relaying,
+    /// The request was proven, but the gas estimate of its callback exceeded
+    /// the gas limit it may be given, and the proof is relayed again.
+    Precheck,
+}
+
+impl FailureStage {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Proving => "proving",
+            Self::Relaying => "relaying",
+            Self::Precheck => "precheck",
+        }
+    }
+}
+
+impl fmt::Display for FailureStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for FailureStage {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "proving" => Ok(Self::Proving),
+            "relaying" => Ok(Self::Relaying),
+            "precheck" => Ok(Self::Precheck),
+            _ => Err(anyhow!("unknown failure stage {s}")),
+        }
+    }
+}
+
+/// A failed callback request, with the requests attached to its Bonsai
+/// session.
+#[derive(Clone, Debug)]
+pub(crate) struct DeadLetter {
+    pub request: ProofRequestInformation,
+    pub stage: FailureStage,
+    pub reason: String,
+    /// Unix time, in milliseconds, at which the request failed.
+    pub failed_at: u64,
+}
+
+impl DeadLetter {
+    pub(crate) fn new(
+        request: ProofRequestInformation,
+        stage: FailureStage,
+        reason: String,
+    ) -> Self {
+        Self {
+            request,
+            stage,
+            reason,
+            failed_at: events::now(),
+        }
+    }
+
+    /// Returns whether `request_id` is the ID of the request, or of one of
+    /// the requests attached to its session.
+    pub(crate) fn holds(&self, request_id: &str) -> bool {
+        self.request.proof_request_id.uuid == request_id
+            || self
+                .request
+                .attached_requests
+                .iter()
+                .any(|attached| attached.request_id == request_id)
+    }
+}
+
+/// The dead-letter list, oldest first, evicting the oldest requests beyond
+/// its capacity. It is persisted in the event database, if any.
+#[derive(Clone, Debug)]
+pub(crate) struct DeadLetters {
+    max_dead_letters: usize,
+    letters: Arc<Mutex<VecDeque<DeadLetter>>>,
+    event_db: Option<EventDb>,
+}
+
+impl Default for DeadLetters {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DEAD_LETTERS)
+    }
+}
+
+impl DeadLetters {
+    pub(crate) fn new(max_dead_letters: usize) -> Self {
+        Self {
+            max_dead_letters,
+            letters: Default::default(),
+            event_db: None,
+        }
+    }
+
+    /// Persist the list in the event database, starting from the failed
+    /// requests it recorded.
+    pub(crate) fn with_event_db(self, event_db: EventDb) -> Result<Self> {
+        let letters: VecDeque<DeadLetter> = event_db.dead_letters()?.into();
+        let dead_letters = Self {
+            letters: Arc::new(Mutex::new(letters)),
+            event_db: Some(event_db),
+            ..self
+        };
+        dead_letters.evict(&mut dead_letters.lock());
+        Ok(dead_letters)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<DeadLetter>> {
+        self.letters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Add a failed request, evicting the oldest ones if the list is full.
+    pub(crate) fn push(&self, dead_letter: DeadLetter) {
+        let mut letters = self.lock();
+        if let Some(db) = &self.event_db {
+            if let Err(error) = db.insert_dead_letter(&dead_letter) {
+                warn!(
+                    ?error,
+                    "Failed to record the dead letter in the event database"
+                );
+            }
+        }
+        letters.push_back(dead_letter);
+        self.evict(&mut letters);
+    }
+
+    fn evict(&self, letters: &mut VecDeque<DeadLetter>) {
+        while letters.len() > self.max_dead_letters {
+            let Some(evicted) = letters.pop_front() else {
+                break;
+            };
+            self.forget(&evicted);
+        }
+        Metrics::global().dead_letters.set(letters.len() as i64);
+    }
+
+    fn forget(&self, dead_letter: &DeadLetter) {
+        if let Some(db) = &self.event_db {
+            let session_id = &dead_letter.request.proof_request_id.uuid;
+            if let Err(error) = db.remove_dead_letter(session_id) {
+                warn!(
+                    ?error,
+                    "Failed to remove the dead letter from the event database"
+                );
+            }
+        }
+    }
+
+    /// Returns the failed requests, oldest first.
+    pub(crate) fn list(&self) -> Vec<DeadLetter> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// Take the failed request with the given ID, or the request whose
+    /// session it is attached to, out of the list.
+    pub(crate) fn take(&self, request_id: &str) -> Option<DeadLetter> {
+        let mut letters = self.lock();
+        let index = letters
+            .iter()
+            .position(|dead_letter| dead_letter.holds(request_id))?;
+        let dead_letter = letters.remove(index)?;
+        self.forget(&dead_letter);
+        Metrics::global().dead_letters.set(letters.len() as i64);
+        Some(dead_letter)
+    }
+}
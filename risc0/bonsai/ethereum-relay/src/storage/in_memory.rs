@@ -15,9 +15,20 @@
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
+    time::Instant,
 };
 
-use crate::storage::{Error, ProofID, ProofRequestInformation, ProofRequestState, Storage};
+use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+use ethers::types::{Address, H256};
+use tokio::sync::broadcast;
+
+use crate::storage::{
+    dead_letters::{DeadLetter, DeadLetters, FailureStage},
+    request_digest,
+    transitions::{StateTransition, TRANSITIONS_CAPACITY},
+    AttachedRequest, Error, InFlightRequests, ProofID, ProofRequestInformation, ProofRequestState,
+    Storage,
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct InMemoryStorage {
@@ -26,6 +37,18 @@ pub(crate) struct InMemoryStorage {
     pending_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
     completed_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
     preparing_onchain_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    /// Requests held back by the daily spend limit, still queued for relaying.
+    spend_limited_proofs: Arc<RwLock<HashMap<String, ProofRequestInformation>>>,
+    /// Session of each callback request, by request ID.
+    request_sessions: Arc<RwLock<HashMap<String, String>>>,
+    /// Sessions still being proven, by digest of their image ID and input.
+    proving_sessions: Arc<RwLock<HashMap<H256, String>>>,
+    /// Transaction of the sessions whose callbacks were completed on chain.
+    completed_onchain: Arc<RwLock<HashMap<String, H256>>>,
+    /// Failed requests, kept to be retried.
+    dead_letters: DeadLetters,
+    /// State transitions of the requests, streamed to the REST API clients.
+    transitions: broadcast::Sender<StateTransition>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -53,9 +76,29 @@ impl InMemoryStorage {
             pending_proofs: Arc::new(RwLock::new(HashMap::new())),
             completed_proofs: Arc::new(RwLock::new(HashMap::new())),
             preparing_onchain_proofs: Arc::new(RwLock::new(HashMap::new())),
+            spend_limited_proofs: Arc::new(RwLock::new(HashMap::new())),
+            request_sessions: Arc::new(RwLock::new(HashMap::new())),
+            proving_sessions: Arc::new(RwLock::new(HashMap::new())),
+            completed_onchain: Arc::new(RwLock::new(HashMap::new())),
+            dead_letters: DeadLetters::default(),
+            transitions: broadcast::channel(TRANSITIONS_CAPACITY).0,
         }
     }
 
+    /// Keep the failed requests in the given dead-letter list.
+    pub(crate) fn with_dead_letters(self, dead_letters: DeadLetters) -> Self {
+        Self {
+            dead_letters,
+            ..self
+        }
+    }
+
+    /// Publish the transition of `proof` to `state`. Sending only fails when
+    /// nobody is subscribed.
+    fn publish(&self, proof: &ProofRequestInformation, state: ProofRequestState) {
+        let _ = self.transitions.send(StateTransition::new(proof, state));
+    }
+
     fn get_proof_request_set_for_state(
         &self,
         state: ProofRequestState,
@@ -66,7 +109,10 @@ impl InMemoryStorage {
             ProofRequestState::Failed => Arc::new(RwLock::new(HashMap::new())),
             ProofRequestState::Completed => self.completed_proofs.clone(),
             ProofRequestState::PreparingOnchain => self.preparing_onchain_proofs.clone(),
+            ProofRequestState::SpendLimited => self.spend_limited_proofs.clone(),
             ProofRequestState::CompletedOnchain(_) => Arc::new(RwLock::new(HashMap::new())),
+            ProofRequestState::Expired => Arc::new(RwLock::new(HashMap::new())),
+            ProofRequestState::Skipped => Arc::new(RwLock::new(HashMap::new())),
         }
     }
 }
@@ -80,6 +126,19 @@ impl Storage for InMemoryStorage {
         self.proof_states
             .write()?
             .insert(proof.proof_request_id.uuid.clone(), ProofRequestState::New);
+        self.request_sessions.write()?.insert(
+            proof.proof_request_id.uuid.clone(),
+            proof.proof_request_id.uuid.clone(),
+        );
+        self.proving_sessions.write()?.insert(
+            request_digest(
+                proof.chain_id,
+                proof.relay_contract_address,
+                &proof.callback_proof_request_event,
+            ),
+            proof.proof_request_id.uuid.clone(),
+        );
+        self.publish(&proof, ProofRequestState::New);
         self.new_proofs
             .write()?
             .insert(proof.proof_request_id.uuid.clone(), proof);
@@ -87,6 +146,47 @@ impl Storage for InMemoryStorage {
         Ok(())
     }
 
+    async fn add_submitted_proof_request(
+        &self,
+        proof: ProofRequestInformation,
+    ) -> Result<(), Error> {
+        self.proof_states.write()?.insert(
+            proof.proof_request_id.uuid.clone(),
+            ProofRequestState::PreparingOnchain,
+        );
+        self.request_sessions.write()?.insert(
+            proof.proof_request_id.uuid.clone(),
+            proof.proof_request_id.uuid.clone(),
+        );
+        self.publish(&proof, ProofRequestState::PreparingOnchain);
+        self.preparing_onchain_proofs
+            .write()?
+            .insert(proof.proof_request_id.uuid.clone(), proof);
+
+        Ok(())
+    }
+
+    async fn add_completed_bonsai_proof_request(
+        &self,
+        proof: ProofRequestInformation,
+    ) -> Result<(), Error> {
+        let uuid = proof.proof_request_id.uuid.clone();
+        self.proof_states
+            .write()?
+            .insert(uuid.clone(), ProofRequestState::Completed);
+        {
+            let mut request_sessions_locked = self.request_sessions.write()?;
+            request_sessions_locked.insert(uuid.clone(), uuid.clone());
+            for attached in proof.attached_requests.iter() {
+                request_sessions_locked.insert(attached.request_id.clone(), uuid.clone());
+            }
+        }
+        self.publish(&proof, ProofRequestState::Completed);
+        self.completed_proofs.write()?.insert(uuid, proof);
+
+        Ok(())
+    }
+
     async fn fetch_new_bonsai_requests(
         &self,
         _limit: Option<u64>,
@@ -110,8 +210,13 @@ impl Storage for InMemoryStorage {
         _limit: Option<u64>,
     ) -> Result<Vec<ProofRequestInformation>, Error> {
         let hashmap = self.preparing_onchain_proofs.read()?;
+        let spend_limited = self.spend_limited_proofs.read()?;
 
-        Ok(hashmap.values().cloned().collect())
+        Ok(hashmap
+            .values()
+            .chain(spend_limited.values())
+            .cloned()
+            .collect())
     }
 
     async fn get_proof_request_state(&self, proof_id: ProofID) -> Result<ProofRequestState, Error> {
@@ -158,9 +263,31 @@ impl Storage for InMemoryStorage {
             proof
         };
 
-        if let ProofRequestState::CompletedOnchain(_) = new_state {
-            // We don't need to store onchain transactions in memory
+        // Identical requests can only be attached while the session is being
+        // proven.
+        if !matches!(
+            new_state,
+            ProofRequestState::New | ProofRequestState::Pending
+        ) {
+            let digest = request_digest(
+                proof.chain_id,
+                proof.relay_contract_address,
+                &proof.callback_proof_request_event,
+            );
+            let mut proving_sessions_locked = self.proving_sessions.write()?;
+            if proving_sessions_locked.get(&digest) == Some(&proof_id.uuid) {
+                proving_sessions_locked.remove(&digest);
+            }
+        }
+
+        self.publish(&proof, new_state);
+        if let ProofRequestState::CompletedOnchain(tx_hash) = new_state {
+            // We don't need to store onchain transactions in memory, only
+            // their hash for the status of the callback requests.
             proof_states_locked.remove(&proof_id.uuid);
+            self.completed_onchain
+                .write()?
+                .insert(proof_id.uuid, tx_hash);
             return Ok(());
         };
 
@@ -170,4 +297,124 @@ impl Storage for InMemoryStorage {
 
         Ok(())
     }
+
+    async fn fail_proof_request(
+        &self,
+        proof_id: ProofID,
+        stage: FailureStage,
+        reason: String,
+    ) -> Result<(), Error> {
+        let state = self.get_proof_request_state(proof_id.clone()).await?;
+        let Some(proof) = self
+            .get_proof_request_set_for_state(state)
+            .read()?
+            .get(&proof_id.uuid)
+            .cloned()
+        else {
+            return Err(Error::ProofNotFound { id: proof_id });
+        };
+        self.transition_proof_request(proof_id, ProofRequestState::Failed)
+            .await?;
+        self.dead_letters
+            .push(DeadLetter::new(proof, stage, reason));
+
+        Ok(())
+    }
+
+    async fn add_dead_letter(&self, dead_letter: DeadLetter) -> Result<(), Error> {
+        self.dead_letters.push(dead_letter);
+
+        Ok(())
+    }
+
+    async fn fetch_dead_letters(&self) -> Result<Vec<DeadLetter>, Error> {
+        Ok(self.dead_letters.list())
+    }
+
+    async fn take_dead_letter(&self, request_id: &str) -> Result<DeadLetter, Error> {
+        self.dead_letters
+            .take(request_id)
+            .ok_or_else(|| Error::DeadLetterNotFound {
+                request_id: request_id.to_string(),
+            })
+    }
+
+    async fn attach_to_proving_request(
+        &self,
+        chain_id: u64,
+        relay_contract_address: Address,
+        event: CallbackRequestFilter,
+        deadline: Option<Instant>,
+    ) -> Result<Option<(ProofID, String)>, Error> {
+        // Hold the states lock so that the session can't leave the proving
+        // stage before the request is attached.
+        let proof_states_locked = self.proof_states.write()?;
+
+        let digest = request_digest(chain_id, relay_contract_address, &event);
+        let Some(uuid) = self.proving_sessions.read()?.get(&digest).cloned() else {
+            return Ok(None);
+        };
+        let Some(state) = proof_states_locked.get(&uuid).copied() else {
+            return Ok(None);
+        };
+        let set = self.get_proof_request_set_for_state(state);
+        let mut set_locked = set.write()?;
+        let Some(proof) = set_locked.get_mut(&uuid) else {
+            return Ok(None);
+        };
+        // The session would expire before the request does.
+        let expires_first = match (proof.deadline, deadline) {
+            (None, _) => false,
+            (Some(_), None) => true,
+            (Some(session_deadline), Some(deadline)) => session_deadline < deadline,
+        };
+        if expires_first {
+            return Ok(None);
+        }
+
+        let request_id = format!("{uuid}-{}", proof.attached_requests.len() + 1);
+        proof.attached_requests.push(AttachedRequest {
+            request_id: request_id.clone(),
+            callback_proof_request_event: event,
+        });
+        self.request_sessions
+            .write()?
+            .insert(request_id.clone(), uuid.clone());
+
+        Ok(Some((ProofID::new(uuid), request_id)))
+    }
+
+    async fn get_request_state(
+        &self,
+        request_id: &str,
+    ) -> Result<(ProofID, ProofRequestState), Error> {
+        let Some(uuid) = self.request_sessions.read()?.get(request_id).cloned() else {
+            return Err(Error::RequestNotFound {
+                request_id: request_id.to_string(),
+            });
+        };
+        if let Some(tx_hash) = self.completed_onchain.read()?.get(&uuid) {
+            return Ok((
+                ProofID::new(uuid),
+                ProofRequestState::CompletedOnchain(*tx_hash),
+            ));
+        }
+
+        let proof_id = ProofID::new(uuid);
+        let state = self.get_proof_request_state(proof_id.clone()).await?;
+        Ok((proof_id, state))
+    }
+
+    fn subscribe_transitions(&self) -> broadcast::Receiver<StateTransition> {
+        self.transitions.subscribe()
+    }
+
+    async fn count_in_flight_requests(&self) -> Result<InFlightRequests, Error> {
+        Ok(InFlightRequests {
+            proving: self.new_proofs.read()?.len() + self.pending_proofs.read()?.len(),
+            relaying: self.completed_proofs.read()?.len()
+                + self.preparing_onchain_proofs.read()?.len()
+                + self.spend_limited_proofs.read()?.len(),
+        })
+    }
 }
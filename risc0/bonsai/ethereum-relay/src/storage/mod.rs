@@ -12,16 +12,27 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::PoisonError;
+use std::{sync::PoisonError, time::Instant};
 
 use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-use ethers::types::H256;
+use ethers::{
+    types::{Address, H256},
+    utils::keccak256,
+};
 
+pub(crate) mod dead_letters;
 pub(crate) mod in_memory;
+pub(crate) mod transitions;
 
 use bonsai_sdk::alpha::SessionId;
 
-use self::in_memory::InMemoryStorageError;
+use tokio::sync::broadcast;
+
+use self::{
+    dead_letters::{DeadLetter, FailureStage},
+    in_memory::InMemoryStorageError,
+    transitions::StateTransition,
+};
 
 pub(crate) type ProofID = SessionId;
 
@@ -33,6 +44,10 @@ pub(crate) enum Error {
     TransitionProofRequest(#[from] InMemoryStorageError),
     #[error("Proof not found")]
     ProofNotFound { id: ProofID },
+    #[error("Callback request not found")]
+    RequestNotFound { request_id: String },
+    #[error("Failed callback request not found")]
+    DeadLetterNotFound { request_id: String },
     // TODO: We lose the underlying error here. We should probably wrap it in a
     // custom error type (?).
     #[error("Storage is poisoned")]
@@ -48,9 +63,64 @@ impl<T> From<PoisonError<T>> for Error {
 #[derive(Debug, Clone)]
 pub(crate) struct ProofRequestInformation {
     pub proof_request_id: ProofID,
+    /// Chain of the relay contract the request was made to.
+    pub chain_id: u64,
+    /// Relay contract the request was made to, through which its callback is
+    /// invoked.
+    pub relay_contract_address: Address,
+    pub callback_proof_request_event: CallbackRequestFilter,
+    /// Time at which the request was submitted to Bonsai.
+    pub created_at: Instant,
+    /// Identical requests attached to the session while it was being proven,
+    /// whose callbacks are fanned out along with this request's one.
+    pub attached_requests: Vec<AttachedRequest>,
+    /// Time after which the request is no longer proven, and expires instead.
+    pub deadline: Option<Instant>,
+}
+
+/// A callback request sharing the Bonsai session of an identical request.
+#[derive(Debug, Clone)]
+pub(crate) struct AttachedRequest {
+    pub request_id: String,
     pub callback_proof_request_event: CallbackRequestFilter,
 }
 
+/// Number of Bonsai sessions in flight, by stage. Identical requests sharing a
+/// session are counted once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct InFlightRequests {
+    /// Sessions new or being proven by Bonsai.
+    pub proving: usize,
+    /// Sessions proven, waiting for or being relayed on chain.
+    pub relaying: usize,
+}
+
+impl InFlightRequests {
+    pub(crate) fn total(&self) -> usize {
+        self.proving + self.relaying
+    }
+}
+
+/// Digest of the chain, relay contract, image ID and input of a callback
+/// request. Requests with the same digest produce the same proof, and can
+/// share a Bonsai session and a relay transaction.
+pub(crate) fn request_digest(
+    chain_id: u64,
+    relay_contract_address: Address,
+    event: &CallbackRequestFilter,
+) -> H256 {
+    keccak256(
+        [
+            chain_id.to_be_bytes().as_slice(),
+            relay_contract_address.as_bytes(),
+            event.image_id.as_slice(),
+            event.input.as_ref(),
+        ]
+        .concat(),
+    )
+    .into()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub(crate) enum ProofRequestState {
     New,
@@ -59,19 +129,57 @@ pub(crate) enum ProofRequestState {
     Completed,
     Failed,
     PreparingOnchain,
+    // Held back before being sent on chain by the daily spend limit
+    SpendLimited,
     CompletedOnchain(H256),
+    // Past its deadline before being proven
+    Expired,
+    // Proven, not relayed as its journal matches none of the journal filters
+    Skipped,
 }
 
 impl ProofRequestState {
+    /// Transaction that invoked the callback, once completed on chain.
+    pub(crate) fn tx_hash(&self) -> Option<H256> {
+        match self {
+            ProofRequestState::CompletedOnchain(tx_hash) => Some(*tx_hash),
+            _ => None,
+        }
+    }
+
+    /// Whether the request leaves no further state, short of being retried.
+    pub(crate) fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ProofRequestState::Failed
+                | ProofRequestState::CompletedOnchain(_)
+                | ProofRequestState::Expired
+                | ProofRequestState::Skipped
+        )
+    }
+
     fn is_valid_state_transition(self, new_state: Self) -> bool {
         match (self, new_state) {
             (ProofRequestState::New, ProofRequestState::Pending)
             | (ProofRequestState::Pending, ProofRequestState::Completed)
             | (ProofRequestState::Pending, ProofRequestState::Failed)
+            | (ProofRequestState::New, ProofRequestState::Expired)
+            | (ProofRequestState::Pending, ProofRequestState::Expired)
             | (ProofRequestState::Completed, ProofRequestState::PreparingOnchain)
             // Allow a revert from PreparingOnchain to Completed. This is useful if the service
             // crashes while preparing a request for sending on chain.
             | (ProofRequestState::PreparingOnchain, ProofRequestState::Completed)
+            // Allow failing a request whose proof is dropped before being sent on chain, e.g.
+            // when too many proofs are held back by the fee cap.
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::Failed)
+            // Skip a request whose journal matches none of the journal filters.
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::Skipped)
+            // Hold back a request while the daily spend limit is reached, until it is sent on
+            // chain, dropped from the relay queue, or reverted on restart.
+            | (ProofRequestState::PreparingOnchain, ProofRequestState::SpendLimited)
+            | (ProofRequestState::SpendLimited, ProofRequestState::PreparingOnchain)
+            | (ProofRequestState::SpendLimited, ProofRequestState::Failed)
+            | (ProofRequestState::SpendLimited, ProofRequestState::Completed)
             | (ProofRequestState::PreparingOnchain, ProofRequestState::CompletedOnchain(_)) => true,
             _ => false,
         }
@@ -81,6 +189,15 @@ impl ProofRequestState {
 #[async_trait::async_trait]
 pub(crate) trait Storage {
     async fn add_new_bonsai_proof_request(&self, proof: ProofRequestInformation) -> Result<()>;
+    /// Add a request whose proof was submitted pre-generated, skipping Bonsai
+    /// straight to being prepared on chain.
+    async fn add_submitted_proof_request(&self, proof: ProofRequestInformation) -> Result<()>;
+    /// Add a request already proven by Bonsai, e.g. a failed one to relay
+    /// again, straight to being completed.
+    async fn add_completed_bonsai_proof_request(
+        &self,
+        proof: ProofRequestInformation,
+    ) -> Result<()>;
     async fn fetch_new_bonsai_requests(
         &self,
         limit: Option<u64>,
@@ -89,6 +206,8 @@ pub(crate) trait Storage {
         &self,
         limit: Option<u64>,
     ) -> Result<Vec<ProofRequestInformation>>;
+    /// Returns the requests being relayed on chain, including the ones held
+    /// back by the daily spend limit.
     async fn fetch_preparing_onchain_proof_requests(
         &self,
         limit: Option<u64>,
@@ -99,4 +218,40 @@ pub(crate) trait Storage {
         new_state: ProofRequestState,
     ) -> Result<()>;
     async fn get_proof_request_state(&self, proof_id: ProofID) -> Result<ProofRequestState>;
+    /// Fail a request, keeping it in the dead-letter list with the stage and
+    /// reason of its failure.
+    async fn fail_proof_request(
+        &self,
+        proof_id: ProofID,
+        stage: FailureStage,
+        reason: String,
+    ) -> Result<()>;
+    /// Add a failed request to the dead-letter list, e.g. back after failing
+    /// to retry it.
+    async fn add_dead_letter(&self, dead_letter: DeadLetter) -> Result<()>;
+    /// Returns the requests of the dead-letter list, oldest first.
+    async fn fetch_dead_letters(&self) -> Result<Vec<DeadLetter>>;
+    /// Take a failed request out of the dead-letter list to retry it, by its
+    /// request ID or the one of a request attached to its session.
+    async fn take_dead_letter(&self, request_id: &str) -> Result<DeadLetter>;
+    /// Attach a callback request to the session of an identical request made
+    /// to the same relay contract, on the same chain, that is still being
+    /// proven, returning the session and the ID of the attached request, or
+    /// `None` if there is no such session. A request is only attached to a
+    /// session expiring no earlier than its own deadline.
+    async fn attach_to_proving_request(
+        &self,
+        chain_id: u64,
+        relay_contract_address: Address,
+        event: CallbackRequestFilter,
+        deadline: Option<Instant>,
+    ) -> Result<Option<(ProofID, String)>>;
+    /// Returns the session and state of a callback request, by request ID. The
+    /// ID of a request that created its own session is the session UUID.
+    async fn get_request_state(&self, request_id: &str) -> Result<(ProofID, ProofRequestState)>;
+    /// Subscribe to the state transitions of the callback requests made from
+    /// now on. A subscriber lagging behind misses the oldest ones.
+    fn subscribe_transitions(&self) -> broadcast::Receiver<StateTransition>;
+    /// Returns the number of sessions not in a terminal state yet.
+    async fn count_in_flight_requests(&self) -> Result<InFlightRequests>;
 }
@@ -0,0 +1,108 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State transitions of the callback requests, streamed to the REST API
+//! clients waiting on them.
+
+use std::collections::VecDeque;
+
+use crate::{
+    events,
+    storage::{ProofRequestInformation, ProofRequestState},
+};
+
+/// Number of state transitions buffered for each subscriber before the oldest
+/// ones are dropped.
+pub(crate) const TRANSITIONS_CAPACITY: usize = 1024;
+
+/// Number of state transitions waiting to be sent to a slow client beyond
+/// which the oldest ones are dropped, intermediate ones first.
+pub(crate) const MAX_PENDING_TRANSITIONS: usize = 256;
+
+/// A callback request, along with the requests attached to its Bonsai
+/// session, entered a new state.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StateTransition {
+    pub session_id: String,
+    /// Requests sharing the session: the one that created it, then the
+    /// attached ones.
+    pub request_ids: Vec<String>,
+    pub state: ProofRequestState,
+    /// Unix time, in milliseconds, of the transition.
+    pub timestamp: u64,
+}
+
+impl StateTransition {
+    pub(crate) fn new(proof: &ProofRequestInformation, state: ProofRequestState) -> Self {
+        let session_id = proof.proof_request_id.uuid.clone();
+        let request_ids = std::iter::once(session_id.clone())
+            .chain(
+                proof
+                    .attached_requests
+                    .iter()
+                    .map(|attached| attached.request_id.clone()),
+            )
+            .collect();
+        Self {
+            session_id,
+            request_ids,
+            state,
+            timestamp: events::now(),
+        }
+    }
+}
+
+/// State transitions waiting to be sent to a client. Publishing never waits
+/// for the client: a newer transition of a session replaces its pending
+/// intermediate one, and once [MAX_PENDING_TRANSITIONS] are pending the
+/// oldest ones are dropped, terminal transitions last.
+#[derive(Debug, Default)]
+pub(crate) struct PendingTransitions {
+    queue: VecDeque<StateTransition>,
+    /// Number of transitions dropped since the last one was taken.
+    dropped: u64,
+}
+
+impl PendingTransitions {
+    pub(crate) fn push(&mut self, transition: StateTransition) {
+        if let Some(index) = self.queue.iter().position(|pending| {
+            pending.session_id == transition.session_id && !pending.state.is_terminal()
+        }) {
+            self.queue.remove(index);
+            self.dropped += 1;
+        }
+        self.queue.push_back(transition);
+        while self.queue.len() > MAX_PENDING_TRANSITIONS {
+            let index = self
+                .queue
+                .iter()
+                .position(|pending| !pending.state.is_terminal())
+                .unwrap_or(0);
+            self.queue.remove(index);
+            self.dropped += 1;
+        }
+    }
+
+    /// Record transitions dropped before reaching the queue.
+    pub(crate) fn record_dropped(&mut self, count: u64) {
+        self.dropped += count;
+    }
+
+    /// Take the oldest pending transition, along with the number of
+    /// transitions dropped before it.
+    pub(crate) fn pop(&mut self) -> Option<(StateTransition, u64)> {
+        let transition = self.queue.pop_front()?;
+        Some((transition, std::mem::take(&mut self.dropped)))
+    }
+}
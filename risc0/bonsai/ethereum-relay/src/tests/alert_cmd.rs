@@ -0,0 +1,187 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, Instant},
+    };
+
+    use ethers::{
+        types::{Address, U256},
+        utils::parse_ether,
+    };
+    use serde_json::json;
+
+    use crate::{
+        alert_cmd::{AlertCommand, AlertConditions, AlertKind},
+        events::RelayerEvent,
+    };
+
+    fn conditions() -> AlertConditions {
+        let config = AlertCommand::new(PathBuf::from("/usr/local/bin/page"))
+            .with_cooldown(Duration::from_secs(60))
+            .with_bonsai_unreachable_after(Duration::from_secs(300))
+            .with_dead_letter_threshold(3);
+        AlertConditions::new(config, vec![Address::repeat_byte(1)])
+    }
+
+    #[test]
+    fn alert_command_runs_on_exhausted_reconnects_and_low_balance() {
+        let conditions = conditions();
+        let alert = conditions
+            .on_event(&RelayerEvent::EthReconnectsExhausted {
+                timestamp: 1_690_891_200_000,
+                chain_id: 5,
+                error: "Failed to create client.".to_string(),
+            })
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(alert).unwrap(),
+            json!({
+                "kind": "eth_reconnects_exhausted",
+                "message": "Failed to reconnect to the Ethereum node of chain 5: \
+                    Failed to create client.",
+                "chain_id": 5,
+                "relay_addresses": [Address::repeat_byte(1)],
+                "timestamp": "2023-08-01T12:00:00.000Z",
+            })
+        );
+
+        let alert = conditions
+            .on_event(&RelayerEvent::LowBalance {
+                timestamp: 1_690_891_200_000,
+                chain_id: 5,
+                signer: Address::repeat_byte(2),
+                balance: parse_ether("0.01").unwrap(),
+                min_eth_balance: parse_ether("0.1").unwrap(),
+            })
+            .unwrap();
+        assert_eq!(alert.kind, AlertKind::LowBalance);
+        assert!(
+            alert.message.contains("0.010000000000000000 ETH"),
+            "{}",
+            alert.message
+        );
+
+        let alert = conditions
+            .on_event(&RelayerEvent::SignerRemoved {
+                timestamp: 1_690_891_200_000,
+                chain_id: 5,
+                signer: None,
+                error: "KMS key disabled".to_string(),
+                remaining: 1,
+            })
+            .unwrap();
+        assert_eq!(alert.kind, AlertKind::SignerRemoved);
+        assert_eq!(
+            alert.message,
+            "A signer of chain 5 failed and was removed from rotation, 1 left: KMS key disabled"
+        );
+
+        let reconnected = RelayerEvent::EthReconnected { timestamp: 0 };
+        assert_eq!(conditions.on_event(&reconnected), None);
+        let spend_limit = RelayerEvent::SpendLimitReached {
+            timestamp: 0,
+            chain_id: 5,
+            spent: U256::one(),
+            max_daily_spend: U256::one(),
+        };
+        assert_eq!(conditions.on_event(&spend_limit), None);
+    }
+
+    #[test]
+    fn bonsai_outages_are_alerted_once_past_the_delay() {
+        let mut conditions = conditions();
+        let start = Instant::now();
+        assert_eq!(conditions.on_bonsai_check(false, start), None);
+        let later = start + Duration::from_secs(299);
+        assert_eq!(conditions.on_bonsai_check(false, later), None);
+
+        let later = start + Duration::from_secs(300);
+        let alert = conditions.on_bonsai_check(false, later).unwrap();
+        assert_eq!(alert.kind, AlertKind::BonsaiUnreachable);
+        assert_eq!(alert.message, "Bonsai unreachable for 5m");
+        assert_eq!(alert.chain_id, None);
+        let later = start + Duration::from_secs(900);
+        assert_eq!(conditions.on_bonsai_check(false, later), None);
+
+        // A new outage is alerted again.
+        assert_eq!(conditions.on_bonsai_check(true, later), None);
+        assert_eq!(conditions.on_bonsai_check(false, later), None);
+        let later = later + Duration::from_secs(300);
+        assert!(conditions.on_bonsai_check(false, later).is_some());
+    }
+
+    #[test]
+    fn dead_letters_are_alerted_when_crossing_the_threshold() {
+        let mut conditions = conditions();
+        assert_eq!(conditions.on_dead_letters(2), None);
+        let alert = conditions.on_dead_letters(3).unwrap();
+        assert_eq!(alert.kind, AlertKind::DeadLetters);
+        assert_eq!(
+            alert.message,
+            "3 failed callback requests in the dead-letter list, over the threshold of 3"
+        );
+        assert_eq!(conditions.on_dead_letters(4), None);
+        assert_eq!(conditions.on_dead_letters(1), None);
+        assert!(conditions.on_dead_letters(5).is_some());
+    }
+
+    #[test]
+    fn each_kind_of_alert_has_its_own_cooldown() {
+        let mut conditions = conditions();
+        let start = Instant::now();
+        assert!(conditions.should_run(AlertKind::LowBalance, start));
+        assert!(conditions.should_run(AlertKind::DeadLetters, start));
+        let later = start + Duration::from_secs(59);
+        assert!(!conditions.should_run(AlertKind::LowBalance, later));
+        let later = start + Duration::from_secs(60);
+        assert!(conditions.should_run(AlertKind::LowBalance, later));
+        assert!(!conditions.should_run(AlertKind::LowBalance, later));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn alert_program_reads_the_alert_on_stdin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::alert_cmd::run_program;
+
+        let dir = std::env::temp_dir().join(format!("relay-alert-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let output = dir.join("alert.json");
+        let script = |name: &str, body: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        let alert = conditions().on_dead_letters(3).unwrap();
+        let page = script("page", format!("cat > {}", output.display()));
+        let status = run_program(&page, &alert).await.unwrap();
+        assert!(status.success());
+        let written: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&output).unwrap()).unwrap();
+        assert_eq!(written, serde_json::to_value(&alert).unwrap());
+
+        let failing = script("failing", "exit 3".to_string());
+        let status = run_program(&failing, &alert).await.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert!(run_program(&dir.join("missing"), &alert).await.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
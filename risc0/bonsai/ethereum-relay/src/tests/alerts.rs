@@ -0,0 +1,123 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+    use serde_json::json;
+    use tokio::sync::watch;
+    use wiremock::{
+        matchers::{body_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        alerts::{post_alerts, Alert},
+        events::{emit, event_channel, RelayerEvent},
+    };
+
+    fn webhook(url: String) -> watch::Receiver<Option<String>> {
+        watch::channel(Some(url)).1
+    }
+
+    fn relay_error() -> RelayerEvent {
+        RelayerEvent::RelayError {
+            timestamp: 1_690_891_200_000,
+            error: "Transaction reverted".to_string(),
+            tx_hash: Some(H256::repeat_byte(0xab)),
+        }
+    }
+
+    fn expected_alert() -> serde_json::Value {
+        json!({
+            "level": "error",
+            "message": "Transaction reverted",
+            "relay_addresses": [Address::repeat_byte(1)],
+            "tx_hash": H256::repeat_byte(0xab),
+            "timestamp": "2023-08-01T12:00:00.000Z",
+        })
+    }
+
+    #[test]
+    fn alerts_report_relay_errors_only() {
+        let relay_addresses = [Address::repeat_byte(1)];
+        let alert = Alert::from_event(&relay_error(), &relay_addresses).unwrap();
+        assert_eq!(serde_json::to_value(alert).unwrap(), expected_alert());
+
+        let reconnected = RelayerEvent::EthReconnected { timestamp: 0 };
+        assert_eq!(Alert::from_event(&reconnected, &relay_addresses), None);
+    }
+
+    #[tokio::test]
+    async fn alerts_are_posted_to_webhook() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_json(expected_alert()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (sender, receiver) = event_channel(8);
+        let events = Some(sender);
+        emit(&events, RelayerEvent::EthReconnected { timestamp: 0 });
+        emit(&events, relay_error());
+        drop(events);
+
+        post_alerts(
+            receiver,
+            webhook(server.uri()),
+            vec![Address::repeat_byte(1)],
+        )
+        .await;
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn failed_alerts_are_dropped() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let (sender, receiver) = event_channel(8);
+        let events = Some(sender);
+        emit(&events, relay_error());
+        emit(&events, relay_error());
+        drop(events);
+
+        // Both alerts are attempted, and the failures don't end the task early.
+        post_alerts(
+            receiver,
+            webhook(server.uri()),
+            vec![Address::repeat_byte(1)],
+        )
+        .await;
+        server.verify().await;
+
+        // Nor does an unreachable webhook.
+        let (sender, receiver) = event_channel(8);
+        let events = Some(sender);
+        emit(&events, relay_error());
+        drop(events);
+        post_alerts(
+            receiver,
+            webhook("http://127.0.0.1:1".to_string()),
+            vec![Address::repeat_byte(1)],
+        )
+        .await;
+    }
+}
@@ -14,7 +14,9 @@
 
 #[cfg(test)]
 mod tests {
-    use bonsai_sdk::alpha_async::get_client_from_parts;
+    use std::sync::Arc;
+
+    use bonsai_sdk::alpha_async::{get_client_from_parts, RoundRobinBonsaiClient};
 
     use crate::{
         tests::utils::tests::get_test_bonsai_server,
@@ -29,7 +31,10 @@ mod tests {
         let bonsai_client = get_client_from_parts(server.uri(), String::default())
             .await
             .unwrap();
-        let pending_proof_request = PendingProofRequest::new(bonsai_client, proof_id.clone());
+        let pending_proof_request = PendingProofRequest::new(
+            Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            proof_id.clone(),
+        );
         let completed_proof_response = pending_proof_request.await;
         assert!(completed_proof_response.is_ok());
 
@@ -0,0 +1,223 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use bonsai_sdk::alpha_async::get_round_robin_client_from_parts;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_bytes, body_json, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::bonsai_transport::BonsaiTransport;
+
+    const KNOWN_IMAGE_ID: &str = "known";
+    const SESSION_ID: &str = "session";
+
+    #[tokio::test]
+    async fn rest_transport_uploads_the_input_of_a_session() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/inputs/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "url": format!("{}/upload/input", server.uri()),
+                "uuid": "input",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/upload/input"))
+            .and(body_bytes(b"input".to_vec()))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/sessions/create"))
+            .and(body_json(
+                json!({ "img": KNOWN_IMAGE_ID, "input": "input" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "uuid": SESSION_ID })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = get_round_robin_client_from_parts(vec![server.uri()], String::default())
+            .await
+            .unwrap();
+
+        let session =
+            BonsaiTransport::create_session(&client, KNOWN_IMAGE_ID.to_string(), b"input".to_vec())
+                .await
+                .unwrap();
+        assert_eq!(session.uuid, SESSION_ID);
+        server.verify().await;
+    }
+}
+
+#[cfg(all(test, feature = "grpc"))]
+mod grpc_tests {
+    use bonsai_sdk::alpha::{SdkErr, SessionId};
+    use tokio::net::TcpListener;
+    use tonic::{transport::Server, Code, Request, Response, Status};
+
+    use crate::bonsai_transport::{
+        grpc::proto::{
+            bonsai_server::{Bonsai, BonsaiServer},
+            CreateSessionRequest, CreateSessionResponse, GetSessionRequest, GetSessionResponse,
+            PutImageRequest, PutImageResponse,
+        },
+        BonsaiTransport, GrpcBonsaiClient,
+    };
+
+    const API_KEY: &str = "test-key";
+    const KNOWN_IMAGE_ID: &str = "known";
+    const SESSION_ID: &str = "session";
+
+    /// Bonsai gRPC API knowing a single image and session.
+    struct MockBonsai;
+
+    fn check_api_key<T>(request: &Request<T>) -> Result<(), Status> {
+        match request.metadata().get("x-api-key") {
+            Some(key) if key.as_bytes() == API_KEY.as_bytes() => Ok(()),
+            _ => Err(Status::unauthenticated("invalid API key")),
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Bonsai for MockBonsai {
+        async fn put_image(
+            &self,
+            request: Request<PutImageRequest>,
+        ) -> Result<Response<PutImageResponse>, Status> {
+            check_api_key(&request)?;
+            if request.get_ref().image_id == KNOWN_IMAGE_ID {
+                return Err(Status::already_exists("image exists"));
+            }
+            Ok(Response::new(PutImageResponse {}))
+        }
+
+        async fn create_session(
+            &self,
+            request: Request<CreateSessionRequest>,
+        ) -> Result<Response<CreateSessionResponse>, Status> {
+            check_api_key(&request)?;
+            let request = request.into_inner();
+            if request.image_id != KNOWN_IMAGE_ID {
+                return Err(Status::not_found(format!(
+                    "Image {} not found",
+                    request.image_id
+                )));
+            }
+            assert_eq!(request.input, b"input");
+            Ok(Response::new(CreateSessionResponse {
+                session_id: SESSION_ID.to_string(),
+            }))
+        }
+
+        async fn get_session(
+            &self,
+            request: Request<GetSessionRequest>,
+        ) -> Result<Response<GetSessionResponse>, Status> {
+            check_api_key(&request)?;
+            if request.get_ref().session_id != SESSION_ID {
+                return Err(Status::new(Code::NotFound, "session not found"));
+            }
+            Ok(Response::new(GetSessionResponse {
+                status: "SUCCEEDED".to_string(),
+                receipt_url: Some("http://localhost/receipt".to_string()),
+                error_msg: None,
+                state: None,
+            }))
+        }
+    }
+
+    /// Serve [MockBonsai] on a free port, returning its URL.
+    async fn serve_mock_bonsai() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let incoming = futures::stream::unfold(listener, |listener| async move {
+            let connection = listener.accept().await.map(|(stream, _)| stream);
+            Some((connection, listener))
+        });
+        tokio::spawn(
+            Server::builder()
+                .add_service(BonsaiServer::new(MockBonsai))
+                .serve_with_incoming(incoming),
+        );
+        url
+    }
+
+    #[tokio::test]
+    async fn grpc_transport_follows_the_rest_errors() {
+        let client = GrpcBonsaiClient::new(&[serve_mock_bonsai().await], API_KEY).unwrap();
+
+        client
+            .put_image("new".to_string(), b"elf".to_vec())
+            .await
+            .unwrap();
+        assert!(matches!(
+            client
+                .put_image(KNOWN_IMAGE_ID.to_string(), b"elf".to_vec())
+                .await,
+            Err(SdkErr::ImageIdExists)
+        ));
+
+        let session = client
+            .create_session(KNOWN_IMAGE_ID.to_string(), b"input".to_vec())
+            .await
+            .unwrap();
+        assert_eq!(session.uuid, SESSION_ID);
+        let err = client
+            .create_session("missing".to_string(), b"input".to_vec())
+            .await
+            .unwrap_err();
+        // Missing images are told apart by the message, as in the REST API.
+        assert!(crate::images::is_image_not_found(&err), "{err}");
+
+        let status = client.get_session(session).await.unwrap();
+        assert_eq!(status.status, "SUCCEEDED");
+        assert_eq!(
+            status.receipt_url.as_deref(),
+            Some("http://localhost/receipt")
+        );
+        assert!(matches!(
+            client
+                .get_session(SessionId::new("unknown".to_string()))
+                .await,
+            Err(SdkErr::SessionNotFound(uuid)) if uuid == "unknown"
+        ));
+    }
+
+    #[tokio::test]
+    async fn grpc_transport_sends_the_api_key() {
+        let url = serve_mock_bonsai().await;
+        let client = GrpcBonsaiClient::new(&[url], "wrong-key").unwrap();
+        let err = client
+            .put_image("new".to_string(), b"elf".to_vec())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, SdkErr::InternalServerErr(message) if message == "invalid API key"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn invalid_grpc_urls_are_config_errors() {
+        assert!(GrpcBonsaiClient::new(&[], API_KEY).is_err());
+        assert!(GrpcBonsaiClient::new(&["not a url".to_string()], API_KEY).is_err());
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        storage::dead_letters::FailureStage,
+        uploader::completed_proofs::gas_limit::{CallbackGasLimits, GasPrecheckError},
+    };
+
+    const REQUESTED: u64 = 100_000;
+    const ESTIMATE: u64 = 80_000;
+
+    fn limits(
+        headroom_percent: u64,
+        min: Option<u64>,
+        max: Option<u64>,
+        respect_requested: bool,
+    ) -> CallbackGasLimits {
+        CallbackGasLimits {
+            headroom_percent,
+            min,
+            max,
+            respect_requested,
+        }
+    }
+
+    #[test]
+    fn gas_limit_of_each_flag_combination() {
+        // (headroom, min, max, respect requested, gas limit)
+        let cases = [
+            (0, None, None, false, 80_000),
+            (20, None, None, false, 96_000),
+            (50, None, None, false, 120_000),
+            (0, Some(90_000), None, false, 90_000),
+            (20, Some(90_000), None, false, 96_000),
+            (0, None, Some(85_000), false, 80_000),
+            (50, None, Some(85_000), false, 85_000),
+            (50, Some(90_000), Some(110_000), false, 110_000),
+            (0, None, None, true, 80_000),
+            (20, None, None, true, 96_000),
+            (50, None, None, true, REQUESTED),
+            (0, Some(90_000), None, true, 90_000),
+            (0, Some(150_000), None, true, REQUESTED),
+            (0, None, Some(85_000), true, 80_000),
+            (20, None, Some(90_000), true, 90_000),
+            (20, Some(90_000), Some(95_000), true, 95_000),
+        ];
+        for (headroom, min, max, respect, expected) in cases {
+            let limits = limits(headroom, min, max, respect);
+            assert_eq!(
+                limits.gas_limit(REQUESTED, ESTIMATE),
+                Ok(expected),
+                "{limits:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn headroom_rounds_up() {
+        assert_eq!(
+            limits(10, None, None, false).gas_limit(0, 33_333),
+            Ok(36_667)
+        );
+        assert_eq!(
+            limits(10, None, None, false).gas_limit(0, u64::MAX),
+            Ok(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn estimate_above_the_requested_gas_limit() {
+        let estimate = 120_000;
+        // As a hint, the requested gas limit is replaced by the estimate.
+        assert_eq!(
+            limits(0, None, None, false).gas_limit(REQUESTED, estimate),
+            Ok(estimate)
+        );
+        // As a ceiling, the request fails, whatever the other flags.
+        for limits in [
+            limits(0, None, None, true),
+            limits(20, Some(50_000), None, true),
+            limits(20, None, Some(150_000), true),
+        ] {
+            let err = limits.gas_limit(REQUESTED, estimate).unwrap_err();
+            assert_eq!(
+                err,
+                GasPrecheckError::Requested {
+                    estimate,
+                    requested: REQUESTED
+                }
+            );
+            let reason = err.to_string();
+            assert!(
+                reason.contains("120000") && reason.contains("100000"),
+                "{reason}"
+            );
+        }
+    }
+
+    #[test]
+    fn estimate_above_the_maximum_gas_limit() {
+        for respect in [false, true] {
+            assert_eq!(
+                limits(0, None, Some(70_000), respect).gas_limit(REQUESTED, ESTIMATE),
+                Err(GasPrecheckError::Max {
+                    estimate: ESTIMATE,
+                    max: 70_000
+                })
+            );
+        }
+        // The requested gas limit is checked first.
+        assert!(matches!(
+            limits(0, None, Some(70_000), true).gas_limit(REQUESTED, 120_000),
+            Err(GasPrecheckError::Requested { .. })
+        ));
+    }
+
+    #[test]
+    fn precheck_failure_stage() {
+        assert_eq!(FailureStage::Precheck.to_string(), "precheck");
+        assert_eq!(
+            "precheck".parse::<FailureStage>().unwrap(),
+            FailureStage::Precheck
+        );
+    }
+}
@@ -0,0 +1,154 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::types::{Address, U256};
+
+    use crate::{
+        chains::parse_chain_configs, EthersClientConfig, PrivateRelay, WalletKeyIdentifier,
+        DEFAULT_ETH_NODE_TIMEOUT, DEFAULT_FEE_QUEUE_DEPTH, DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS,
+    };
+
+    const PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const SECOND_PRIVATE_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn defaults() -> EthersClientConfig {
+        EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            11155111,
+            PRIVATE_KEY.parse().unwrap(),
+            7,
+            Duration::from_secs(5),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        )
+    }
+
+    #[test]
+    fn chains_are_read_with_their_own_fee_policy() {
+        let chains = parse_chain_configs(
+            &format!(
+                r#"
+                [[chain]]
+                chain_id = 84532
+                eth_node = "wss://base-sepolia.example.com"
+                relay_addresses = ["0x0101010101010101010101010101010101010101"]
+                private_key = "0x{PRIVATE_KEY}"
+                min_eth_balance = "0.5"
+                fee_cap = "2"
+                private_relay_url = "https://rpc.flashbots.net"
+
+                [[chain]]
+                chain_id = 421614
+                eth_node = "wss://arbitrum-sepolia.example.com"
+                relay_addresses = [
+                    "0x0202020202020202020202020202020202020202",
+                    "0x0303030303030303030303030303030303030303",
+                ]
+                private_key = "{PRIVATE_KEY}"
+                additional_private_keys = ["{SECOND_PRIVATE_KEY}"]
+                "#
+            ),
+            &defaults(),
+        )
+        .unwrap();
+
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].client_config.eth_chain_id, 84532);
+        assert_eq!(
+            chains[0].client_config.eth_node_url,
+            "wss://base-sepolia.example.com"
+        );
+        assert_eq!(chains[0].client_config.retries, 7);
+        assert_eq!(
+            chains[0].relay_contract_addresses,
+            vec![Address::repeat_byte(1)]
+        );
+        assert_eq!(chains[0].min_eth_balance, U256::exp10(17) * 5);
+        let fee_cap = chains[0].fee_cap.expect("fee cap should be set");
+        assert_eq!(fee_cap.max_fee_per_gas, U256::exp10(9) * 2);
+        assert_eq!(fee_cap.queue_depth, DEFAULT_FEE_QUEUE_DEPTH);
+
+        assert_eq!(chains[1].client_config.eth_chain_id, 421614);
+        assert_eq!(chains[1].relay_contract_addresses.len(), 2);
+        assert_eq!(chains[1].min_eth_balance, U256::exp10(16));
+        assert!(chains[1].fee_cap.is_none());
+        assert_eq!(
+            chains[0].private_relay,
+            Some(PrivateRelay {
+                url: "https://rpc.flashbots.net".to_string(),
+                fallback_blocks: DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS,
+            })
+        );
+        assert!(chains[1].private_relay.is_none());
+        assert!(chains[0].client_config.additional_wallet_keys.is_empty());
+        assert_eq!(
+            chains[1].client_config.additional_wallet_keys,
+            vec![SECOND_PRIVATE_KEY.parse::<WalletKeyIdentifier>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn invalid_chains_are_rejected() {
+        let chain = |extra: &str| {
+            format!(
+                r#"
+                [[chain]]
+                chain_id = 84532
+                eth_node = "wss://base-sepolia.example.com"
+                private_key = "{PRIVATE_KEY}"
+                {extra}
+                "#
+            )
+        };
+
+        let err = parse_chain_configs(&chain("relay_addresses = []"), &defaults()).unwrap_err();
+        assert!(format!("{err:#}").contains("at least one relay address"));
+
+        let err = parse_chain_configs(
+            &chain(
+                r#"relay_addresses = ["0x0101010101010101010101010101010101010101"]
+                fee_queue_depth = 10"#,
+            ),
+            &defaults(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("fee_queue_depth requires fee_cap"));
+
+        let err = parse_chain_configs(
+            &chain(
+                r#"relay_addresses = ["0x0101010101010101010101010101010101010101"]
+                private_relay_fallback_blocks = 10"#,
+            ),
+            &defaults(),
+        )
+        .unwrap_err();
+        assert!(
+            format!("{err:#}").contains("private_relay_fallback_blocks requires private_relay_url")
+        );
+
+        let err = parse_chain_configs(
+            &chain(
+                r#"relay_addresses = ["0x0101010101010101010101010101010101010101"]
+                gas_price = "1""#,
+            ),
+            &defaults(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("gas_price"));
+    }
+}
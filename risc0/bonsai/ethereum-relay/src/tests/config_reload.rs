@@ -0,0 +1,198 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{H256, U256};
+    use tokio::sync::watch;
+
+    use crate::{
+        reload::{parse_config_file, settings_diff, HotSettings, RelayLimits, Reloader},
+        FeeCap, DEFAULT_FEE_QUEUE_DEPTH, DEFAULT_MAX_CONCURRENT_RELAYS, DEFAULT_RELAY_QUEUE_DEPTH,
+    };
+
+    fn base() -> HotSettings {
+        HotSettings {
+            image_ids: vec![H256::repeat_byte(0x11)],
+            fee_cap: None,
+            max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+            relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+            alert_webhook: None,
+            log_level: "info".to_string(),
+        }
+    }
+
+    #[test]
+    fn config_file_overrides_the_settings_it_sets() {
+        let file = parse_config_file(
+            r#"
+            image_ids = ["0x2222222222222222222222222222222222222222222222222222222222222222"]
+            fee_cap = "30"
+            max_concurrent_relays = 4
+            log_level = "debug"
+            "#,
+        )
+        .unwrap();
+        let settings = file.hot_settings(&base()).unwrap();
+
+        assert_eq!(settings.image_ids, vec![H256::repeat_byte(0x22)]);
+        assert_eq!(
+            settings.fee_cap,
+            Some(FeeCap {
+                max_fee_per_gas: U256::exp10(9) * 30,
+                queue_depth: DEFAULT_FEE_QUEUE_DEPTH,
+            })
+        );
+        assert_eq!(settings.max_concurrent_relays, 4);
+        assert_eq!(settings.relay_queue_depth, DEFAULT_RELAY_QUEUE_DEPTH);
+        assert_eq!(settings.alert_webhook, None);
+        assert_eq!(settings.log_level, "debug");
+
+        // An empty file keeps the settings the relayer is run with.
+        let settings = parse_config_file("")
+            .unwrap()
+            .hot_settings(&base())
+            .unwrap();
+        assert_eq!(settings, base());
+    }
+
+    #[test]
+    fn invalid_config_files_are_rejected() {
+        for (contents, error) in [
+            ("max_concurrent_relays = 0", "max_concurrent_relays"),
+            ("fee_queue_depth = 10", "fee_queue_depth requires fee_cap"),
+            ("fee_cap = \"thirty\"", "invalid gwei amount"),
+            ("alert_webhook = \"not a url\"", "invalid alert_webhook"),
+            ("log_level = \"relay=loud\"", "invalid log_level"),
+        ] {
+            let err = parse_config_file(contents)
+                .unwrap()
+                .hot_settings(&base())
+                .expect_err(contents);
+            assert!(format!("{err:#}").contains(error), "{err:#}");
+        }
+        assert!(parse_config_file("eth_chain_id = 1").is_err());
+    }
+
+    #[test]
+    fn settings_read_on_start_are_reported_as_changed() {
+        let started = parse_config_file(
+            r#"
+            eth_node = "wss://sepolia.example.com"
+            rest_api_port = 8080
+            log_level = "info"
+            "#,
+        )
+        .unwrap();
+        let reloaded = parse_config_file(
+            r#"
+            eth_node = "wss://other.example.com"
+            rest_api_port = 8080
+            log_level = "debug"
+            relay_addresses = ["0x0101010101010101010101010101010101010101"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            started.restart_changes(&reloaded),
+            vec!["eth_node", "relay_addresses"]
+        );
+        assert!(started.restart_changes(&started).is_empty());
+    }
+
+    #[test]
+    fn settings_diff_does_not_log_the_webhook() {
+        let new = HotSettings {
+            alert_webhook: Some("https://hooks.example.com/secret".to_string()),
+            relay_queue_depth: 5,
+            ..base()
+        };
+        let diff = settings_diff(&base(), &new);
+        assert_eq!(
+            diff,
+            vec![
+                format!("relay_queue_depth: {DEFAULT_RELAY_QUEUE_DEPTH} -> 5"),
+                "alert_webhook: changed".to_string(),
+            ]
+        );
+        assert!(settings_diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn reload_publishes_the_new_settings() {
+        let path = std::env::temp_dir().join(format!(
+            "bonsai-relay-config-reload-{}.toml",
+            std::process::id()
+        ));
+        let (image_ids_sender, image_ids) = watch::channel(base().image_ids);
+        let (alert_webhook_sender, alert_webhook) = watch::channel(None);
+        let (primary_sender, primary) = watch::channel(RelayLimits::default());
+        let other_fee_cap = Some(FeeCap {
+            max_fee_per_gas: U256::one(),
+            queue_depth: 1,
+        });
+        let (other_sender, other) = watch::channel(RelayLimits {
+            fee_cap: other_fee_cap,
+            ..Default::default()
+        });
+        let mut reloader = Reloader {
+            path: path.clone(),
+            started: Default::default(),
+            base: base(),
+            current: base(),
+            image_ids: image_ids_sender,
+            alert_webhook: alert_webhook_sender,
+            chain_limits: vec![primary_sender, other_sender],
+            log_filter: None,
+        };
+
+        std::fs::write(
+            &path,
+            r#"
+            image_ids = ["0x3333333333333333333333333333333333333333333333333333333333333333"]
+            fee_cap = "1"
+            relay_queue_depth = 7
+            alert_webhook = "https://hooks.example.com/alerts"
+            "#,
+        )
+        .unwrap();
+        reloader.reload().unwrap();
+
+        assert_eq!(*image_ids.borrow(), vec![H256::repeat_byte(0x33)]);
+        assert_eq!(
+            *alert_webhook.borrow(),
+            Some("https://hooks.example.com/alerts".to_string())
+        );
+        assert_eq!(primary.borrow().relay_queue_depth, 7);
+        assert_eq!(
+            primary
+                .borrow()
+                .fee_cap
+                .map(|fee_cap| fee_cap.max_fee_per_gas),
+            Some(U256::exp10(9))
+        );
+        // The chains of the chains file keep their own fee caps.
+        assert_eq!(other.borrow().relay_queue_depth, 7);
+        assert_eq!(other.borrow().fee_cap, other_fee_cap);
+
+        // An invalid file keeps the current settings.
+        std::fs::write(&path, "max_concurrent_relays = 0").unwrap();
+        assert!(reloader.reload().is_err());
+        assert_eq!(*image_ids.borrow(), vec![H256::repeat_byte(0x33)]);
+        assert_eq!(reloader.current.relay_queue_depth, 7);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Instant};
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{
+        alpha::{responses::SessionStatusRes, SessionId},
+        alpha_async::get_client_from_parts,
+    };
+    use ethers::types::{Address, Bytes, H256};
+    use tokio::sync::Notify;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        event_db::EventDb,
+        storage::{
+            dead_letters::{DeadLetter, DeadLetters, FailureStage},
+            in_memory::InMemoryStorage,
+            AttachedRequest, Error as StorageError, ProofRequestInformation, ProofRequestState,
+            Storage,
+        },
+        uploader::pending_proofs::manager::BonsaiPendingProofManager,
+    };
+
+    const CHAIN_ID: u64 = 31337;
+
+    fn proof_request(uuid: &str) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id: SessionId::new(uuid.to_string()),
+            chain_id: CHAIN_ID,
+            relay_contract_address: Address::repeat_byte(1),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(2),
+                image_id: H256::repeat_byte(3).into(),
+                input: Bytes::from(vec![4, 5, 6]),
+                callback_contract: Address::repeat_byte(2),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            created_at: Instant::now(),
+            attached_requests: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    /// Add a request to the storage, and fail it at the given stage.
+    async fn fail(storage: &InMemoryStorage, uuid: &str, stage: FailureStage) {
+        let proof_id = SessionId::new(uuid.to_string());
+        storage
+            .add_new_bonsai_proof_request(proof_request(uuid))
+            .await
+            .unwrap();
+        let states: &[ProofRequestState] = match stage {
+            FailureStage::Proving => &[ProofRequestState::Pending],
+            FailureStage::Relaying | FailureStage::Precheck => &[
+                ProofRequestState::Pending,
+                ProofRequestState::Completed,
+                ProofRequestState::PreparingOnchain,
+            ],
+        };
+        for state in states {
+            storage
+                .transition_proof_request(proof_id.clone(), *state)
+                .await
+                .unwrap();
+        }
+        storage
+            .fail_proof_request(proof_id, stage, format!("{uuid} failed"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn oldest_failed_requests_are_evicted() {
+        let storage = InMemoryStorage::new().with_dead_letters(DeadLetters::new(2));
+        for uuid in ["first", "second", "third"] {
+            fail(&storage, uuid, FailureStage::Proving).await;
+        }
+
+        let dead_letters = storage.fetch_dead_letters().await.unwrap();
+        let uuids: Vec<&str> = dead_letters
+            .iter()
+            .map(|dead_letter| dead_letter.request.proof_request_id.uuid.as_str())
+            .collect();
+        assert_eq!(uuids, ["second", "third"]);
+        assert_eq!(dead_letters[0].stage, FailureStage::Proving);
+        assert_eq!(dead_letters[0].reason, "second failed");
+        assert_eq!(
+            storage
+                .get_proof_request_state(SessionId::new("first".to_string()))
+                .await
+                .unwrap(),
+            ProofRequestState::Failed
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_proofs_are_relayed_again() {
+        let storage = InMemoryStorage::new();
+        fail(&storage, "relaying", FailureStage::Relaying).await;
+        assert!(matches!(
+            storage.take_dead_letter("unknown").await,
+            Err(StorageError::DeadLetterNotFound { .. })
+        ));
+
+        let dead_letter = storage.take_dead_letter("relaying").await.unwrap();
+        assert_eq!(dead_letter.stage, FailureStage::Relaying);
+        assert!(storage.fetch_dead_letters().await.unwrap().is_empty());
+
+        storage
+            .add_completed_bonsai_proof_request(dead_letter.request)
+            .await
+            .unwrap();
+        let (_, state) = storage.get_request_state("relaying").await.unwrap();
+        assert_eq!(state, ProofRequestState::Completed);
+        let completed = storage.fetch_completed_bonsai_requests(None).await.unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].proof_request_id.uuid, "relaying");
+    }
+
+    #[tokio::test]
+    async fn failed_sessions_are_kept_to_be_proven_again() {
+        let server = MockServer::start().await;
+        let proof_id = SessionId::new("failing".to_string());
+        Mock::given(method("GET"))
+            .and(path(format!("sessions/status/{}", proof_id.uuid)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SessionStatusRes {
+                status: "FAILED".to_string(),
+                receipt_url: None,
+                error_msg: None,
+                state: None,
+                stats: None,
+            }))
+            .mount(&server)
+            .await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let notifier = Arc::new(Notify::new());
+        let mut manager = BonsaiPendingProofManager::new(
+            bonsai_client.into(),
+            storage.clone(),
+            notifier.clone(),
+            vec![Arc::new(Notify::new())],
+            None,
+        );
+        storage
+            .add_new_bonsai_proof_request(proof_request(&proof_id.uuid))
+            .await
+            .unwrap();
+
+        notifier.notify_one();
+        manager.step().await.unwrap();
+        manager.step().await.unwrap();
+
+        let dead_letters = storage.fetch_dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].request.proof_request_id, proof_id);
+        assert_eq!(dead_letters[0].stage, FailureStage::Proving);
+        assert!(
+            dead_letters[0].reason.contains("FAILED"),
+            "{}",
+            dead_letters[0].reason
+        );
+    }
+
+    #[test]
+    fn dead_letters_are_kept_across_restarts() {
+        let db = EventDb::in_memory().unwrap();
+        let dead_letters = DeadLetters::new(10).with_event_db(db.clone()).unwrap();
+        let mut request = proof_request("session");
+        request.attached_requests.push(AttachedRequest {
+            request_id: "session-1".to_string(),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(7),
+                ..request.callback_proof_request_event.clone()
+            },
+        });
+        dead_letters.push(DeadLetter::new(
+            request.clone(),
+            FailureStage::Relaying,
+            "reverted".to_string(),
+        ));
+        dead_letters.push(DeadLetter::new(
+            proof_request("other"),
+            FailureStage::Proving,
+            "failed".to_string(),
+        ));
+
+        // A restarted relayer finds the failed requests, and their attached
+        // requests, in the event database.
+        let restarted = DeadLetters::new(10).with_event_db(db.clone()).unwrap();
+        let kept = restarted.list();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[0].request.proof_request_id.uuid, "session");
+        assert_eq!(kept[0].stage, FailureStage::Relaying);
+        assert_eq!(kept[0].reason, "reverted");
+        assert_eq!(
+            kept[0].request.callback_proof_request_event,
+            request.callback_proof_request_event
+        );
+        assert_eq!(kept[0].request.attached_requests.len(), 1);
+        assert_eq!(kept[0].request.attached_requests[0].request_id, "session-1");
+        assert_eq!(
+            kept[0].request.attached_requests[0].callback_proof_request_event,
+            request.attached_requests[0].callback_proof_request_event
+        );
+        assert_eq!(kept[1].request.proof_request_id.uuid, "other");
+
+        // Requests taken out of the list to be retried are no longer kept,
+        // even when taken by the ID of an attached request.
+        assert!(restarted.take("session-1").is_some());
+        let restarted = DeadLetters::new(1).with_event_db(db).unwrap();
+        let kept = restarted.list();
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].request.proof_request_id.uuid, "other");
+    }
+}
@@ -0,0 +1,225 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Instant};
+
+    use axum::{
+        body::{Body, HttpBody},
+        http::{header, Request, StatusCode},
+        response::{IntoResponse, Response},
+        Router,
+    };
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{
+        alpha::SessionId,
+        alpha_async::{get_client_from_parts, RoundRobinBonsaiClient},
+    };
+    use ethers::types::{Address, Bytes, H256};
+    use hyper::service::Service;
+    use serde_json::Value;
+    use tokio::sync::Notify;
+
+    use crate::{
+        api::{
+            admin::ADMIN_KEY_HEADER,
+            error::Error,
+            limits::{ApiLimits, DEFAULT_MAX_REQUEST_BODY_SIZE},
+            routes::{ADMIN_DRAIN_ROUTE, ADMIN_RESUME_ROUTE, CALLBACK_ROUTE},
+            server::app,
+            state::{ApiState, RelayContract},
+        },
+        intake::{Intake, IntakeClosed},
+        sdk::client::CallbackRequest,
+        spend::SpendLimit,
+        storage::{
+            in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState, Storage,
+        },
+    };
+
+    const ADMIN_KEY: &str = "secret";
+
+    async fn test_app(storage: InMemoryStorage, intake: Intake) -> Router {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        app(ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: vec![RelayContract {
+                chain_id: 1,
+                address: Address::repeat_byte(1),
+            }],
+            storage,
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: Some(ADMIN_KEY.to_string()),
+            limits: ApiLimits::new(None, DEFAULT_MAX_REQUEST_BODY_SIZE),
+            rest_api_docs: false,
+            intake,
+            spend_limit: SpendLimit::default(),
+        })
+    }
+
+    fn proof_request(uuid: &str) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id: SessionId::new(uuid.to_string()),
+            chain_id: 1,
+            relay_contract_address: Address::repeat_byte(1),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(2),
+                image_id: H256::repeat_byte(3).into(),
+                input: Bytes::from(uuid.as_bytes().to_vec()),
+                callback_contract: Address::repeat_byte(2),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            created_at: Instant::now(),
+            attached_requests: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    async fn admin_request(app: &mut Router, method: &str, route: &str) -> Value {
+        let request = Request::builder()
+            .method(method)
+            .uri(route)
+            .header(ADMIN_KEY_HEADER, ADMIN_KEY)
+            .body(Body::empty())
+            .unwrap();
+        let response = app.call(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        serde_json::from_slice(&response.into_body().data().await.unwrap().unwrap()).unwrap()
+    }
+
+    async fn post_callback_request(app: &mut Router) -> Response {
+        let body = bincode::serialize(&CallbackRequest {
+            image_id: [3; 32],
+            input: vec![1, 2, 3],
+            callback_contract: Address::repeat_byte(2),
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+            relay_address: None,
+            chain_id: None,
+            deadline: None,
+        })
+        .unwrap();
+        let request = Request::post(CALLBACK_ROUTE)
+            .header("x-api-key", "key")
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(body))
+            .unwrap();
+        app.call(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn draining_relayer_refuses_new_requests_until_resumed() {
+        let storage = InMemoryStorage::new();
+        storage
+            .add_new_bonsai_proof_request(proof_request("session"))
+            .await
+            .unwrap();
+        let mut app = test_app(storage.clone(), Intake::default()).await;
+
+        let status = admin_request(&mut app, "POST", ADMIN_DRAIN_ROUTE).await;
+        assert_eq!(status["draining"], true);
+        assert_eq!(status["proving"], 1);
+        // The session in flight is left to finish.
+        assert_eq!(status["drained"], false);
+
+        let response = post_callback_request(&mut app).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = response.into_body().data().await.unwrap().unwrap();
+        assert!(
+            String::from_utf8_lossy(&body).contains("draining"),
+            "{body:?}"
+        );
+
+        storage
+            .transition_proof_request(
+                SessionId::new("session".to_string()),
+                ProofRequestState::Expired,
+            )
+            .await
+            .unwrap();
+        let status = admin_request(&mut app, "GET", ADMIN_DRAIN_ROUTE).await;
+        assert_eq!(status["proving"], 0);
+        assert_eq!(status["drained"], true);
+
+        let status = admin_request(&mut app, "POST", ADMIN_RESUME_ROUTE).await;
+        assert_eq!(status["draining"], false);
+        assert_eq!(status["drained"], false);
+        // The request is taken in again, failing later on the unreachable
+        // Bonsai instance.
+        let response = post_callback_request(&mut app).await;
+        assert_ne!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn drain_routes_require_the_admin_key() {
+        let mut app = test_app(InMemoryStorage::new(), Intake::default()).await;
+        for (method, route) in [
+            ("GET", ADMIN_DRAIN_ROUTE),
+            ("POST", ADMIN_DRAIN_ROUTE),
+            ("POST", ADMIN_RESUME_ROUTE),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri(route)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.call(request).await.unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_past_the_maximum_in_flight_are_refused() {
+        let storage = InMemoryStorage::new();
+        let intake = Intake::new(Some(2));
+        storage
+            .add_new_bonsai_proof_request(proof_request("first"))
+            .await
+            .unwrap();
+        intake.admit(&storage).await.unwrap();
+        storage
+            .add_new_bonsai_proof_request(proof_request("second"))
+            .await
+            .unwrap();
+        let err = intake.admit(&storage).await.unwrap_err();
+        assert!(
+            matches!(err, Error::IntakeClosed(IntakeClosed::Full { max: 2 })),
+            "{err}"
+        );
+        assert_eq!(err.into_response().status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let mut app = test_app(storage.clone(), intake.clone()).await;
+        let response = post_callback_request(&mut app).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        // Sessions leaving the pipeline make room for new requests.
+        storage
+            .transition_proof_request(
+                SessionId::new("first".to_string()),
+                ProofRequestState::Expired,
+            )
+            .await
+            .unwrap();
+        intake.admit(&storage).await.unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use crate::{eth_node::EthNodeEndpoint, RelayError};
+
+    #[test]
+    fn eth_node_endpoints() {
+        assert_eq!(
+            "wss://sepolia.example.com"
+                .parse::<EthNodeEndpoint>()
+                .unwrap(),
+            EthNodeEndpoint::Ws("wss://sepolia.example.com".to_string())
+        );
+        assert_eq!(
+            "ws://localhost:8545".parse::<EthNodeEndpoint>().unwrap(),
+            EthNodeEndpoint::Ws("ws://localhost:8545".to_string())
+        );
+        assert_eq!(
+            "https://sepolia.example.com"
+                .parse::<EthNodeEndpoint>()
+                .unwrap(),
+            EthNodeEndpoint::Http("https://sepolia.example.com".to_string())
+        );
+        assert_eq!(
+            "http://localhost:8545".parse::<EthNodeEndpoint>().unwrap(),
+            EthNodeEndpoint::Http("http://localhost:8545".to_string())
+        );
+        assert_eq!(
+            "ipc:///var/run/geth.ipc"
+                .parse::<EthNodeEndpoint>()
+                .unwrap(),
+            EthNodeEndpoint::Ipc(PathBuf::from("/var/run/geth.ipc"))
+        );
+        assert_eq!(
+            "/var/run/geth.ipc".parse::<EthNodeEndpoint>().unwrap(),
+            EthNodeEndpoint::Ipc(PathBuf::from("/var/run/geth.ipc"))
+        );
+    }
+
+    #[test]
+    fn unsupported_eth_node_endpoints_list_the_supported_ones() {
+        for endpoint in ["ftp://example.com", "grpc://localhost:8545", ""] {
+            let err = endpoint.parse::<EthNodeEndpoint>().unwrap_err();
+            let RelayError::ConfigError(message) = err else {
+                panic!("expected a config error, got {err}");
+            };
+            assert!(
+                message.contains("ws://, wss://, http://, https://, ipc://"),
+                "{message}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn ws_pool_spreads_requests_and_keeps_subscriptions() {
+        use ethers::providers::{Middleware, Provider, StreamExt};
+        use serde_json::Value;
+
+        use crate::{
+            eth_node::{EthNodeTransport, WsPing, WsPool, ETH_NODE_RECONNECTS},
+            sdk::utils,
+        };
+
+        let anvil = utils::get_anvil();
+        let url = utils::get_ws_provider_endpoint(anvil.as_ref())
+            .await
+            .unwrap();
+        let ping = WsPing {
+            interval: Duration::from_millis(100),
+            pong_timeout: Duration::from_secs(1),
+        };
+        for ping in [None, Some(ping)] {
+            let pool = WsPool::connect(url.clone(), 3, ping, ETH_NODE_RECONNECTS)
+                .await
+                .unwrap();
+            assert_eq!(pool.size(), 3);
+            let provider = Provider::new(EthNodeTransport::WsPool(pool));
+
+            // Each subscription is served by the connection it was made on,
+            // whichever connection takes the next requests.
+            let mut subscriptions = Vec::new();
+            for _ in 0..3 {
+                subscriptions.push(provider.subscribe_blocks().await.unwrap());
+            }
+            let block = provider.get_block_number().await.unwrap();
+            provider.request::<_, Value>("evm_mine", ()).await.unwrap();
+            for subscription in &mut subscriptions {
+                let mined = tokio::time::timeout(Duration::from_secs(5), subscription.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(mined.number, Some(block + 1));
+            }
+            for subscription in subscriptions {
+                subscription.unsubscribe().await.unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn pinging_ws_fails_its_requests_once_the_node_is_gone() {
+        use ethers::providers::JsonRpcClient;
+        use serde_json::Value;
+        use tokio::net::TcpListener;
+
+        use crate::{
+            eth_node::WsPing,
+            pinging_ws::{PingingWs, PingingWsError},
+        };
+
+        let ping = WsPing {
+            interval: Duration::from_millis(100),
+            pong_timeout: Duration::from_millis(300),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let node = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio_tungstenite::accept_async(stream).await.unwrap()
+        });
+        let ws = PingingWs::connect(url.clone(), ping, 2).await.unwrap();
+        // Closes the connection, the listener being dropped already.
+        drop(node.await.unwrap());
+
+        // Failing to connect again is not a pong timeout.
+        let err = ws
+            .request::<_, Value>("eth_blockNumber", ())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PingingWsError::Closed), "{err}");
+        let pong_timeout =
+            tokio::time::timeout(Duration::from_millis(500), ws.wait_for_pong_timeout()).await;
+        assert!(pong_timeout.is_err(), "no pong timeout expected");
+
+        let err = PingingWs::connect(url, ping, 2).await.unwrap_err();
+        assert!(matches!(err, PingingWsError::Connect(_)), "{err}");
+    }
+
+    #[cfg(unix)]
+    mod ipc {
+        use std::{path::Path, time::Duration};
+
+        use ethers::providers::{Middleware, Provider};
+        use serde_json::{json, Value};
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::UnixListener,
+            task::JoinHandle,
+        };
+
+        use crate::eth_node::{EthNodeTransport, ReconnectingIpc};
+
+        /// Serves `eth_blockNumber` on the IPC socket at `path`, one
+        /// connection at a time, until aborted, answering `block_number`.
+        /// Aborting closes the open connection, as a node restart does.
+        fn serve_ipc(path: &Path, block_number: u64) -> JoinHandle<()> {
+            let listener = UnixListener::bind(path).unwrap();
+            tokio::spawn(async move {
+                loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let mut buf = vec![0u8; 4096];
+                    loop {
+                        let n = match stream.read(&mut buf).await {
+                            Ok(0) | Err(_) => break,
+                            Ok(n) => n,
+                        };
+                        let request: Value = serde_json::from_slice(&buf[..n]).unwrap();
+                        let response = json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "result": format!("{block_number:#x}"),
+                        });
+                        let response = serde_json::to_vec(&response).unwrap();
+                        if stream.write_all(&response).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            })
+        }
+
+        #[tokio::test]
+        async fn ipc_socket_is_reopened_after_a_node_restart() {
+            let path = std::env::temp_dir().join(format!("relay-{}.ipc", uuid::Uuid::new_v4()));
+            let node = serve_ipc(&path, 1);
+
+            let provider = Provider::new(EthNodeTransport::Ipc(
+                ReconnectingIpc::connect(path.clone(), 10).await.unwrap(),
+            ));
+            assert_eq!(provider.get_block_number().await.unwrap(), 1.into());
+
+            // The node restarts: its socket disappears, then comes back.
+            node.abort();
+            let _ = node.await;
+            std::fs::remove_file(&path).unwrap();
+            let restarted = {
+                let path = path.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    serve_ipc(&path, 2)
+                })
+            };
+
+            assert_eq!(provider.get_block_number().await.unwrap(), 2.into());
+            restarted.await.unwrap().abort();
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
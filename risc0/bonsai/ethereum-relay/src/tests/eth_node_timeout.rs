@@ -0,0 +1,73 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, time::Duration};
+
+    use ethers::providers::{Http, Middleware, Provider};
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{client_config::TimeoutClient, metrics::Metrics};
+
+    const ETH_NODE_TIMEOUT: Duration = Duration::from_millis(200);
+
+    async fn get_test_eth_node(rpc_method: &str, delay: Duration) -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "method": rpc_method })))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": "0x2a" }))
+                    .set_delay(delay),
+            )
+            .mount(&server)
+            .await;
+        server
+    }
+
+    #[tokio::test]
+    async fn integration_test_eth_node_request_within_timeout() {
+        let server = get_test_eth_node("eth_chainId", Duration::ZERO).await;
+        let provider = Provider::new(TimeoutClient::new(
+            Http::from_str(&server.uri()).unwrap(),
+            ETH_NODE_TIMEOUT,
+        ));
+
+        let chain_id = provider.get_chainid().await.unwrap();
+        assert_eq!(chain_id, 42.into());
+    }
+
+    #[tokio::test]
+    async fn integration_test_eth_node_request_times_out() {
+        let server = get_test_eth_node("eth_blockNumber", ETH_NODE_TIMEOUT * 10).await;
+        let provider = Provider::new(TimeoutClient::new(
+            Http::from_str(&server.uri()).unwrap(),
+            ETH_NODE_TIMEOUT,
+        ));
+
+        let timeouts = Metrics::global()
+            .eth_node_timeouts
+            .with_label_values(&["eth_blockNumber"]);
+        let timeouts_before = timeouts.get();
+
+        let err = provider.get_block_number().await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert_eq!(timeouts.get(), timeouts_before + 1);
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::{CallbackAuthorization, CallbackRequestFilter};
+    use ethers::{
+        contract::abigen,
+        providers::Middleware,
+        types::{Address, Bytes, Log, TransactionReceipt, TransactionRequest, H256},
+    };
+    use tokio::sync::{mpsc, watch};
+
+    use crate::{
+        downloader::{
+            event_processor::EventProcessor,
+            proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
+            replay::HistoryScan,
+        },
+        event_db::{EventDb, EventProof, EventStatus},
+        events::RelayerEvent,
+        sdk::utils,
+        uploader::completed_proofs::{complete_proof::build_callback, relay::EthersClient},
+        EthersClientConfig,
+    };
+
+    abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+    /// Forwards the processed events to a channel, as requests named after
+    /// their image ID.
+    struct ForwardingProcessor(mpsc::UnboundedSender<CallbackRequestFilter>);
+
+    #[async_trait::async_trait]
+    impl EventProcessor for ForwardingProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            _address: Address,
+            event: CallbackRequestFilter,
+        ) -> Result<String, crate::api::error::Error> {
+            let request_id = format!("request-{}", event.image_id[0]);
+            self.0.send(event).unwrap();
+            Ok(request_id)
+        }
+    }
+
+    fn log(tx_hash: H256, log_index: u64) -> Log {
+        Log {
+            address: Address::repeat_byte(1),
+            transaction_hash: Some(tx_hash),
+            log_index: Some(log_index.into()),
+            block_number: Some(10.into()),
+            ..Default::default()
+        }
+    }
+
+    fn submitted(tx_hash: H256, session_ids: &[&str]) -> RelayerEvent {
+        RelayerEvent::TxSubmitted {
+            timestamp: 0,
+            relay_address: Address::repeat_byte(1),
+            tx_hash,
+            session_ids: session_ids.iter().map(|id| id.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn event_status_follows_the_relayer_events() {
+        let db = EventDb::in_memory().unwrap();
+        let (first, attached, other) = (H256::random(), H256::random(), H256::random());
+        let image_id = H256::repeat_byte(7);
+        db.record_received(1, &log(first, 0), image_id, "session")
+            .unwrap();
+        db.record_received(1, &log(attached, 3), image_id, "session-1")
+            .unwrap();
+        db.record_received(1, &log(other, 0), image_id, "other")
+            .unwrap();
+        assert!(db.contains(1, first, 0).unwrap());
+        assert!(!db.contains(1, first, 1).unwrap());
+        assert!(!db.contains(2, first, 0).unwrap());
+
+        // The requests attached to a session follow it.
+        let relay_tx_hash = H256::random();
+        db.apply(&submitted(relay_tx_hash, &["session"])).unwrap();
+        let events = db.events_with_status(1, EventStatus::Submitted).unwrap();
+        assert_eq!(events.len(), 2);
+        for event in &events {
+            assert_eq!(event.relay_tx_hash, Some(relay_tx_hash));
+            assert_eq!(event.image_id, image_id);
+            assert_eq!(event.block_number, 10);
+        }
+        assert_eq!(events[0].tx_hash, first);
+        assert_eq!(events[1].tx_hash, attached);
+        assert_eq!(events[1].log_index, 3);
+        assert_eq!(events[1].request_id, "session-1");
+
+        db.apply(&RelayerEvent::TxConfirmed {
+            timestamp: 0,
+            relay_address: Address::repeat_byte(1),
+            tx_hash: relay_tx_hash,
+            session_ids: vec!["session".to_string()],
+        })
+        .unwrap();
+        db.apply(&RelayerEvent::RequestFailed {
+            timestamp: 0,
+            session_id: Some("other".to_string()),
+            error: "reverted".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            db.events_with_status(1, EventStatus::Completed)
+                .unwrap()
+                .len(),
+            2
+        );
+        let failed = db.events_with_status(1, EventStatus::Failed).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].tx_hash, other);
+    }
+
+    #[test]
+    fn only_old_completed_events_are_pruned() {
+        let db = EventDb::in_memory().unwrap();
+        let (completed, submitted_tx) = (H256::random(), H256::random());
+        db.record_received(1, &log(completed, 0), H256::zero(), "done")
+            .unwrap();
+        db.record_received(1, &log(submitted_tx, 0), H256::zero(), "pending")
+            .unwrap();
+        db.apply(&submitted(H256::random(), &["done", "pending"]))
+            .unwrap();
+        db.set_session_status("done", EventStatus::Completed, None)
+            .unwrap();
+
+        assert_eq!(db.prune(Duration::from_secs(60 * 60)).unwrap(), 0);
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(db.prune(Duration::from_millis(1)).unwrap(), 1);
+        assert!(!db.contains(1, completed, 0).unwrap());
+        assert!(db.contains(1, submitted_tx, 0).unwrap());
+    }
+
+    #[test]
+    fn proofs_are_recorded_until_the_event_is_processed_again() {
+        let db = EventDb::in_memory().unwrap();
+        let tx_hash = H256::random();
+        db.record_received(1, &log(tx_hash, 0), H256::repeat_byte(7), "session")
+            .unwrap();
+        let id = db.events_with_status(1, EventStatus::Received).unwrap()[0].id;
+
+        let callback = build_callback(
+            &CallbackRequestFilter {
+                account: Address::default(),
+                image_id: [7; 32],
+                input: Bytes::default(),
+                callback_contract: Address::repeat_byte(2),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            b"journal",
+            CallbackAuthorization {
+                seal: Bytes::from(b"seal".to_vec()),
+                post_state_digest: [3; 32],
+            },
+        );
+        let proof = EventProof::from_callback(&callback).unwrap();
+        assert_eq!(proof.journal, b"journal");
+        assert_eq!(proof.seal, b"seal");
+        assert_eq!(proof.post_state_digest, H256::repeat_byte(3));
+        assert_eq!(db.set_session_proof("session", &proof).unwrap(), 1);
+        assert_eq!(db.event(id).unwrap().unwrap().proof, Some(proof));
+
+        // Processing the event again keeps its ID, but not its previous proof.
+        db.record_received(1, &log(tx_hash, 0), H256::repeat_byte(7), "other")
+            .unwrap();
+        let event = db.event(id).unwrap().unwrap();
+        assert_eq!(event.request_id, "other");
+        assert_eq!(event.proof, None);
+        assert!(db.event(id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn events_are_kept_across_restarts() {
+        let path = std::env::temp_dir().join(format!("relay-events-{}.db", uuid::Uuid::new_v4()));
+        let tx_hash = H256::random();
+        let relay_tx_hash = H256::random();
+        {
+            let db = EventDb::open(&path).unwrap();
+            db.record_received(5, &log(tx_hash, 2), H256::zero(), "session")
+                .unwrap();
+            db.apply(&submitted(relay_tx_hash, &["session"])).unwrap();
+        }
+        let db = EventDb::open(&path).unwrap();
+        let events = db.events_with_status(5, EventStatus::Submitted).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].tx_hash, tx_hash);
+        assert_eq!(events[0].log_index, 2);
+        assert_eq!(events[0].relay_tx_hash, Some(relay_tx_hash));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    async fn deploy_proxy(client_config: &EthersClientConfig) -> Proxy<EthersClient> {
+        let client = Arc::new(client_config.get_client().await.unwrap());
+        Proxy::deploy(client, ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed")
+    }
+
+    /// Emits a callback request for the image ID `image_id`, returning the
+    /// receipt of its transaction.
+    async fn request_callback<M: Middleware + 'static>(
+        proxy: &Proxy<M>,
+        image_id: u8,
+    ) -> TransactionReceipt {
+        proxy
+            .request_callback(
+                [image_id; 32],
+                Bytes::from(b"hello world".to_vec()),
+                Address::random(),
+                [0xab, 0xcd, 0xef, 0xab],
+                3_000_000,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("callback request should be mined")
+    }
+
+    #[tokio::test]
+    async fn unfinished_events_are_resumed_on_start() {
+        let anvil = utils::get_anvil();
+        let client_config = EthersClientConfig {
+            eth_node_url: utils::get_http_provider_endpoint(anvil.as_ref()).unwrap(),
+            ..utils::get_ethers_client_config(anvil.as_ref())
+                .await
+                .unwrap()
+        }
+        .with_poll_interval(Duration::from_millis(100));
+        let chain_id = client_config.eth_chain_id;
+        let client = client_config.get_client().await.unwrap();
+        let proxy = deploy_proxy(&client_config).await;
+
+        // The relay transaction of the first request was mined while the
+        // relayer was down, the one of the second request was dropped.
+        let confirmed = request_callback(&proxy, 1).await;
+        let dropped = request_callback(&proxy, 2).await;
+        let relay_tx_hash = client
+            .send_transaction(TransactionRequest::pay(Address::random(), 1), None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("transfer should be mined")
+            .transaction_hash;
+        let db = EventDb::in_memory().unwrap();
+        db.record_received(chain_id, &confirmed.logs[0], H256::repeat_byte(1), "first")
+            .unwrap();
+        db.record_received(chain_id, &dropped.logs[0], H256::repeat_byte(2), "second")
+            .unwrap();
+        db.apply(&submitted(relay_tx_hash, &["first"])).unwrap();
+        db.apply(&submitted(H256::random(), &["second"])).unwrap();
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stream = ProxyCallbackProofRequestStream::new(
+            client_config,
+            vec![proxy.address()],
+            watch::channel(vec![]).1,
+            ForwardingProcessor(sender),
+            None,
+            vec![],
+        )
+        .with_history_scan(Some(HistoryScan {
+            start_block: 0,
+            end_block: None,
+        }))
+        .with_event_db(Some(db.clone()));
+        let stream = tokio::spawn(stream.run());
+
+        // Only the request whose relay transaction was dropped is processed
+        // again, once, although the scanned blocks hold both.
+        let event = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("dropped request should be processed again")
+            .unwrap();
+        assert_eq!(H256::from(event.image_id), H256::repeat_byte(2));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+        stream.abort();
+
+        let completed = db
+            .events_with_status(chain_id, EventStatus::Completed)
+            .unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].request_id, "first");
+        let received = db
+            .events_with_status(chain_id, EventStatus::Received)
+            .unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].request_id, "request-2");
+        assert_eq!(Some(received[0].tx_hash), dropped.logs[0].transaction_hash);
+    }
+}
@@ -0,0 +1,45 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use ethers::types::{Address, Bytes, H256};
+
+    use crate::downloader::proxy_callback_proof_request_stream::matches_image_ids;
+
+    fn callback_request(image_id: [u8; 32]) -> CallbackRequestFilter {
+        CallbackRequestFilter {
+            account: Address::zero(),
+            image_id,
+            input: Bytes::default(),
+            callback_contract: Address::zero(),
+            function_selector: [0; 4],
+            gas_limit: 3000000,
+        }
+    }
+
+    #[test]
+    fn empty_filter_matches_all_events() {
+        assert!(matches_image_ids(&[], &callback_request([1; 32])));
+    }
+
+    #[test]
+    fn filter_matches_listed_image_ids() {
+        let image_ids = [H256::from([1; 32]), H256::from([2; 32])];
+        assert!(matches_image_ids(&image_ids, &callback_request([1; 32])));
+        assert!(matches_image_ids(&image_ids, &callback_request([2; 32])));
+        assert!(!matches_image_ids(&image_ids, &callback_request([3; 32])));
+    }
+}
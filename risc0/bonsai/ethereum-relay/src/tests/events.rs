@@ -0,0 +1,71 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+
+    use crate::{
+        events::{emit, event_channel, write_events, RelayerEvent},
+        metrics::Metrics,
+    };
+
+    fn tx_confirmed(timestamp: u64) -> RelayerEvent {
+        RelayerEvent::TxConfirmed {
+            timestamp,
+            relay_address: Address::repeat_byte(1),
+            tx_hash: H256::repeat_byte(0xab),
+            session_ids: vec!["session".to_string()],
+        }
+    }
+
+    #[test]
+    fn events_are_tagged_by_name() {
+        let json = serde_json::to_value(tx_confirmed(42)).unwrap();
+        assert_eq!(json["event"], "tx_confirmed");
+        assert_eq!(json["timestamp"], 42);
+        assert_eq!(json["session_ids"][0], "session");
+        assert_eq!(
+            json["relay_address"],
+            serde_json::to_value(Address::repeat_byte(1)).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn slow_consumer_drops_oldest_events() {
+        let dropped = Metrics::global().events_dropped.get();
+        let (sender, receiver) = event_channel(2);
+
+        // Sending never waits for the consumer.
+        let events = Some(sender);
+        for timestamp in 0..4 {
+            emit(&events, tx_confirmed(timestamp));
+        }
+        drop(events);
+
+        let mut out = Vec::new();
+        write_events(receiver, &mut out).await.unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["event"], "events_dropped");
+        assert_eq!(lines[0]["count"], 2);
+        assert_eq!(lines[1]["timestamp"], 2);
+        assert_eq!(lines[2]["timestamp"], 3);
+        assert!(Metrics::global().events_dropped.get() >= dropped + 2);
+    }
+}
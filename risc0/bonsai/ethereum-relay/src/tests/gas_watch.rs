@@ -0,0 +1,54 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use crate::gas_watch::{GasPriceSample, GasPriceWindow};
+
+    #[test]
+    fn first_sample_has_no_change() {
+        let mut window = GasPriceWindow::default();
+        assert_eq!(
+            window.record(20.0),
+            GasPriceSample {
+                gas_price: 20.0,
+                moving_average: 20.0,
+                change_pct: None,
+            }
+        );
+        let sample = window.record(30.0);
+        assert_eq!(sample.moving_average, 25.0);
+        assert_eq!(sample.change_pct, Some(50.0));
+        assert_eq!(window.record(15.0).change_pct, Some(-50.0));
+    }
+
+    #[test]
+    fn moving_average_is_taken_over_the_last_five_samples() {
+        let mut window = GasPriceWindow::default();
+        for gas_price in [100.0, 10.0, 10.0, 10.0, 10.0] {
+            window.record(gas_price);
+        }
+        // The first sample leaves the window.
+        let sample = window.record(20.0);
+        assert_eq!(sample.moving_average, 12.0);
+        assert_eq!(sample.change_pct, Some(100.0));
+    }
+
+    #[test]
+    fn change_from_a_zero_gas_price_is_not_reported() {
+        let mut window = GasPriceWindow::default();
+        window.record(0.0);
+        assert_eq!(window.record(1.0).change_pct, None);
+    }
+}
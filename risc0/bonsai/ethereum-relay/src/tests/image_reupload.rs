@@ -0,0 +1,215 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{alpha::SdkErr, alpha_async::get_round_robin_client_from_parts};
+    use ethers::types::{Address, Bytes, H256};
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        api::admin::{is_admin_key, select_images},
+        downloader::proxy_callback_proof_processor::ProxyCallbackProofRequestProcessor,
+        images::{is_image_not_found, upload_images, GuestImage},
+        storage::in_memory::InMemoryStorage,
+        RelayError,
+    };
+
+    fn image(name: &str, byte: u8) -> GuestImage {
+        GuestImage {
+            name: name.to_string(),
+            image_id: H256::repeat_byte(byte),
+            elf: Arc::from(vec![byte; 4]),
+        }
+    }
+
+    fn callback_request() -> CallbackRequestFilter {
+        CallbackRequestFilter {
+            account: Address::default(),
+            image_id: H256::repeat_byte(0x11).into(),
+            input: Bytes::from(b"input".to_vec()),
+            callback_contract: Address::repeat_byte(1),
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+        }
+    }
+
+    /// Mock the Bonsai routes uploading an input and an image.
+    async fn mock_uploads(server: &MockServer, image_uploads: u64) {
+        Mock::given(method("GET"))
+            .and(path("/inputs/upload"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "url": format!("{}/put/input", server.uri()),
+                "uuid": "input",
+            })))
+            .mount(server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/images/upload/{}",
+                hex::encode(H256::repeat_byte(0x11))
+            )))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "url": format!("{}/put/image", server.uri()),
+            })))
+            .expect(image_uploads)
+            .mount(server)
+            .await;
+        Mock::given(method("PUT"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn image_not_found_errors() {
+        assert!(is_image_not_found(&SdkErr::InternalServerErr(
+            "Image 1111 not found".to_string()
+        )));
+        assert!(!is_image_not_found(&SdkErr::InternalServerErr(
+            "Input 1111 not found".to_string()
+        )));
+        assert!(!is_image_not_found(&SdkErr::ImageIdExists));
+    }
+
+    #[test]
+    fn image_not_found_errors_are_relay_errors() {
+        let err = RelayError::from(SdkErr::InternalServerErr(
+            "Image 1111 not found".to_string(),
+        ));
+        assert!(matches!(err, RelayError::ImageNotFound(_)), "{err:?}");
+        let err = RelayError::from(SdkErr::ImageIdExists);
+        assert!(
+            matches!(err, RelayError::BonsaiApiError(SdkErr::ImageIdExists)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn admin_key_and_image_selection() {
+        assert!(is_admin_key("secret", Some("secret")));
+        assert!(!is_admin_key("secret", Some("secreT")));
+        assert!(!is_admin_key("secret", Some("secret2")));
+        assert!(!is_admin_key("secret", None));
+
+        let images = [image("echo", 0x11), image("finalize_votes", 0x22)];
+        assert_eq!(select_images(&images, &[]).unwrap().len(), 2);
+        let selected = select_images(
+            &images,
+            &["ECHO".to_string(), format!("0x{}", hex::encode([0x22; 32]))],
+        )
+        .unwrap();
+        let names: Vec<_> = selected.iter().map(|image| image.name.as_str()).collect();
+        assert_eq!(names, ["echo", "finalize_votes"]);
+        assert!(select_images(&images, &["unknown".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn images_are_uploaded_on_demand() {
+        let server = MockServer::start().await;
+        mock_uploads(&server, 1).await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/images/upload/{}",
+                hex::encode(H256::repeat_byte(0x22))
+            )))
+            .respond_with(ResponseTemplate::new(204))
+            .mount(&server)
+            .await;
+        let client = get_round_robin_client_from_parts(vec![server.uri()], String::default())
+            .await
+            .unwrap();
+
+        let results = upload_images(&client, &[image("echo", 0x11), image("other", 0x22)]).await;
+        assert!(results[0].uploaded);
+        assert_eq!(results[0].error, None);
+        // Images already known by Bonsai are not uploaded again.
+        assert!(!results[1].uploaded);
+        assert_eq!(results[1].error, None);
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn missing_image_is_uploaded_again() {
+        let server = MockServer::start().await;
+        mock_uploads(&server, 1).await;
+        Mock::given(method("POST"))
+            .and(path("/sessions/create"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(format!(
+                "Image {} not found",
+                hex::encode(H256::repeat_byte(0x11))
+            )))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/sessions/create"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "uuid": "session" })))
+            .mount(&server)
+            .await;
+        let client = get_round_robin_client_from_parts(vec![server.uri()], String::default())
+            .await
+            .unwrap();
+
+        let processor = ProxyCallbackProofRequestProcessor::new(
+            1,
+            Arc::new(client),
+            InMemoryStorage::new(),
+            None,
+            None,
+        )
+        .with_images(Arc::new([image("echo", 0x11)]));
+        let request_id = processor
+            .submit(Address::default(), callback_request(), None)
+            .await
+            .unwrap();
+        assert_eq!(request_id, "session");
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn unknown_missing_image_fails_the_request() {
+        let server = MockServer::start().await;
+        mock_uploads(&server, 0).await;
+        Mock::given(method("POST"))
+            .and(path("/sessions/create"))
+            .respond_with(ResponseTemplate::new(404).set_body_string("Image not found"))
+            .mount(&server)
+            .await;
+        let client = get_round_robin_client_from_parts(vec![server.uri()], String::default())
+            .await
+            .unwrap();
+
+        // Without the image to upload, the session is not retried.
+        let processor = ProxyCallbackProofRequestProcessor::new(
+            1,
+            Arc::new(client),
+            InMemoryStorage::new(),
+            None,
+            None,
+        );
+        assert!(processor
+            .submit(Address::default(), callback_request(), None)
+            .await
+            .is_err());
+        server.verify().await;
+    }
+}
@@ -0,0 +1,83 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::{providers::Middleware, signers::Signer, types::U256};
+    use serde_json::{json, Value};
+
+    use crate::{json_rpc_batch::json_rpc_batch, sdk::utils};
+
+    #[tokio::test]
+    async fn integration_test_json_rpc_batch() {
+        let anvil = utils::get_anvil();
+        let address = utils::get_wallet(anvil.as_ref()).unwrap().address();
+        let node_chain_id = utils::get_http_provider(anvil.as_ref())
+            .unwrap()
+            .get_chainid()
+            .await
+            .unwrap();
+        let endpoints = [
+            utils::get_ws_provider_endpoint(anvil.as_ref())
+                .await
+                .unwrap(),
+            utils::get_http_provider(anvil.as_ref())
+                .unwrap()
+                .url()
+                .to_string(),
+        ];
+        for endpoint in endpoints {
+            let requests = [
+                ("eth_chainId", json!([])),
+                ("eth_getBalance", json!([address, "latest"])),
+                ("eth_unknownMethod", json!([])),
+                ("eth_getCode", json!([address, "latest"])),
+            ];
+            let results = json_rpc_batch(&endpoint, &requests).await.unwrap();
+            assert_eq!(results.len(), 4, "{endpoint}");
+            let chain_id: U256 = serde_json::from_value(results[0].clone().unwrap()).unwrap();
+            assert_eq!(chain_id, node_chain_id);
+            let balance: U256 = serde_json::from_value(results[1].clone().unwrap()).unwrap();
+            assert!(!balance.is_zero());
+            // A failed request doesn't fail the others.
+            assert!(results[2].is_err(), "{endpoint}");
+            assert_eq!(results[3].clone().unwrap(), Value::from("0x"));
+        }
+
+        assert!(json_rpc_batch("/var/run/geth.ipc", &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn integration_test_batched_chain_state() {
+        let anvil = utils::get_anvil();
+        let address = utils::get_wallet(anvil.as_ref()).unwrap().address();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap();
+
+        let sequential = client_config
+            .chain_state(&[address], &[address])
+            .await
+            .unwrap();
+        let batched = client_config
+            .with_json_rpc_batch(true)
+            .chain_state(&[address], &[address])
+            .await
+            .unwrap();
+        assert_eq!(batched.chain_id, sequential.chain_id);
+        assert_eq!(batched.codes, sequential.codes);
+        assert_eq!(batched.balances, sequential.balances);
+        assert!(!batched.balances[0].as_ref().unwrap().is_zero());
+    }
+}
@@ -0,0 +1,101 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers_signers::AwsSignerError;
+    use rusoto_core::{credential::CredentialsError, Region, RusotoError};
+    use rusoto_kms::SignError;
+
+    use crate::{
+        signer::{aws_error_code, KmsConfig, RelaySignerError},
+        WalletKeyIdentifier,
+    };
+
+    const PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    #[test]
+    fn wallet_key_identifiers_are_private_keys_or_kms_keys() {
+        for key in [PRIVATE_KEY.to_string(), format!("0x{PRIVATE_KEY}")] {
+            assert!(matches!(
+                key.parse::<WalletKeyIdentifier>().unwrap(),
+                WalletKeyIdentifier::PrivateKey(_)
+            ));
+        }
+        for key_id in [
+            "1234abcd-12ab-34cd-56ef-1234567890ab",
+            "mrk-1234abcd12ab34cd56ef1234567890ab",
+            "arn:aws:kms:us-east-2:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab",
+            "alias/relayer",
+            "arn:aws:kms:us-east-2:111122223333:alias/relayer",
+        ] {
+            assert_eq!(
+                key_id.parse::<WalletKeyIdentifier>().unwrap(),
+                WalletKeyIdentifier::Kms(key_id.to_string())
+            );
+        }
+        assert!("relayer".parse::<WalletKeyIdentifier>().is_err());
+        assert!("0x1234".parse::<WalletKeyIdentifier>().is_err());
+    }
+
+    #[test]
+    fn kms_region_and_endpoint_are_honored() {
+        let config = KmsConfig {
+            region: Some("eu-west-1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(config.region().unwrap(), Region::EuWest1);
+
+        let config = KmsConfig {
+            region: Some("eu-west-1".to_string()),
+            endpoint: Some("http://localhost:4566".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.region().unwrap(),
+            Region::Custom {
+                name: "eu-west-1".to_string(),
+                endpoint: "http://localhost:4566".to_string(),
+            }
+        );
+
+        let config = KmsConfig {
+            region: Some("mars-north-1".to_string()),
+            ..Default::default()
+        };
+        let err = config.region().unwrap_err();
+        assert!(format!("{err:#}").contains("invalid AWS region"), "{err:#}");
+    }
+
+    #[test]
+    fn kms_signing_errors_name_the_key_and_the_aws_error_code() {
+        let key_arn = "arn:aws:kms:us-east-2:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab";
+        let err = RelaySignerError::kms(
+            key_arn,
+            AwsSignerError::SignError(RusotoError::Service(SignError::Disabled(
+                "key is disabled".to_string(),
+            ))),
+        );
+        let message = err.to_string();
+        assert!(message.contains(key_arn), "{message}");
+        assert!(message.contains("DisabledException"), "{message}");
+
+        assert_eq!(
+            aws_error_code::<SignError>(&RusotoError::Credentials(CredentialsError::new(
+                "no credentials"
+            ))),
+            "CredentialsError"
+        );
+    }
+}
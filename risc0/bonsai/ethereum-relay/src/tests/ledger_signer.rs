@@ -0,0 +1,195 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip2718::TypedTransaction, Address, Signature, TransactionRequest},
+    };
+
+    use crate::{
+        ledger_signer::{LedgerConfig, LedgerDevice, LedgerDeviceError, LedgerSignerError},
+        uploader::completed_proofs::manager::relay_concurrency,
+        EthersClientConfig, RelayError, RelaySigner, WalletKeyIdentifier,
+    };
+
+    const PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const PATH: &str = "m/44'/60'/0'/0/0";
+
+    /// A Ledger transport answering with a local wallet, after the given
+    /// confirmation delay.
+    #[derive(Debug)]
+    struct MockLedger {
+        wallet: LocalWallet,
+        confirmation_delay: Duration,
+    }
+
+    #[async_trait]
+    impl LedgerDevice for MockLedger {
+        fn address(&self) -> Address {
+            self.wallet.address()
+        }
+
+        async fn sign_transaction(
+            &self,
+            tx: &TypedTransaction,
+        ) -> Result<Signature, LedgerDeviceError> {
+            tokio::time::sleep(self.confirmation_delay).await;
+            Ok(self.wallet.sign_transaction_sync(tx).unwrap())
+        }
+
+        async fn sign_message(&self, message: &[u8]) -> Result<Signature, LedgerDeviceError> {
+            tokio::time::sleep(self.confirmation_delay).await;
+            Ok(self.wallet.sign_message(message).await.unwrap())
+        }
+    }
+
+    fn mock_ledger(confirmation_delay: Duration) -> Arc<dyn LedgerDevice> {
+        Arc::new(MockLedger {
+            wallet: PRIVATE_KEY
+                .parse::<LocalWallet>()
+                .unwrap()
+                .with_chain_id(5u64),
+            confirmation_delay,
+        })
+    }
+
+    fn tx() -> TypedTransaction {
+        TransactionRequest::pay(Address::repeat_byte(1), 1000)
+            .nonce(3)
+            .gas(21000)
+            .gas_price(10)
+            .into()
+    }
+
+    fn client_config(wallet_key_identifier: &str) -> EthersClientConfig {
+        EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            5,
+            wallet_key_identifier.parse().unwrap(),
+            1,
+            Duration::from_millis(10),
+            Duration::from_secs(1),
+        )
+    }
+
+    #[test]
+    fn wallet_key_identifiers_can_be_ledger_accounts() {
+        let identifier = format!("ledger:{PATH}")
+            .parse::<WalletKeyIdentifier>()
+            .unwrap();
+        assert_eq!(identifier, WalletKeyIdentifier::Ledger(PATH.to_string()));
+        assert!(identifier.is_hardware_wallet());
+        assert!(!PRIVATE_KEY
+            .parse::<WalletKeyIdentifier>()
+            .unwrap()
+            .is_hardware_wallet());
+        assert!("ledger:".parse::<WalletKeyIdentifier>().is_err());
+        assert!("ledger:44'/60'/0'/0/0"
+            .parse::<WalletKeyIdentifier>()
+            .is_err());
+    }
+
+    #[test]
+    fn ledger_relays_are_sent_one_at_a_time() {
+        assert_eq!(relay_concurrency(&client_config(PRIVATE_KEY), 4), 4);
+        assert_eq!(
+            relay_concurrency(&client_config(&format!("ledger:{PATH}")), 4),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn ledger_signer_waits_for_the_device_to_be_unlocked() {
+        let attempts = AtomicUsize::new(0);
+        let config = LedgerConfig::default();
+        let device = config
+            .connect(PATH, Duration::from_millis(10), || async {
+                // The device is locked on the first two attempts.
+                if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(LedgerDeviceError("locked".to_string()))
+                } else {
+                    Ok(mock_ledger(Duration::ZERO))
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+        let signer = RelaySigner::Ledger(config.signer_of(device, PATH, 5));
+        assert_eq!(signer.address(), wallet.address());
+        assert_eq!(signer.chain_id(), 5);
+        let signature = signer.sign_transaction(&tx()).await.unwrap();
+        let mut expected_tx = tx();
+        expected_tx.set_chain_id(5);
+        assert_eq!(
+            signature,
+            wallet
+                .with_chain_id(5u64)
+                .sign_transaction_sync(&expected_tx)
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn ledger_signer_fails_if_never_unlocked() {
+        let config = LedgerConfig {
+            unlock_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let err = config
+            .connect(PATH, Duration::from_millis(10), || async {
+                Err(LedgerDeviceError("locked".to_string()))
+            })
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, RelayError::ConfigError(message) if message.contains(PATH)),
+            "{err}"
+        );
+    }
+
+    #[tokio::test]
+    async fn ledger_signatures_time_out_unless_confirmed() {
+        let config = LedgerConfig {
+            signing_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let signer =
+            RelaySigner::Ledger(config.signer_of(mock_ledger(Duration::from_secs(5)), PATH, 5));
+        let err = signer.sign_transaction(&tx()).await.unwrap_err();
+        assert!(
+            matches!(
+                &err,
+                crate::RelaySignerError::Ledger {
+                    source: LedgerSignerError::Timeout { .. },
+                    ..
+                }
+            ),
+            "{err}"
+        );
+        assert!(err.to_string().contains(PATH), "{err}");
+    }
+}
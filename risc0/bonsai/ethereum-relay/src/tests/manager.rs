@@ -14,24 +14,36 @@
 
 #[cfg(test)]
 pub(crate) mod tests {
-    use std::sync::Arc;
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
 
     use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
-    use bonsai_sdk::alpha_async::get_client_from_parts;
-    use ethers::types::{Address, Bytes, H256};
-    use tokio::sync::Notify;
+    use bonsai_sdk::{alpha::SessionId, alpha_async::get_client_from_parts};
+    use ethers::types::{Address, Bytes, H256, U256};
+    use tokio::sync::{mpsc, watch, Notify};
 
     use crate::{
+        metrics::Metrics,
+        reload::RelayLimits,
         sdk::utils,
+        spend::SpendLimit,
         storage::{
             in_memory::InMemoryStorage, Error as StorageError, ProofRequestInformation,
             ProofRequestState, Storage,
         },
         tests::utils::tests::get_test_bonsai_server,
         uploader::{
-            completed_proofs::manager::BonsaiCompleteProofManager,
+            completed_proofs::{
+                complete_proof::{journal_matches, CompleteProof},
+                manager::BonsaiCompleteProofManager,
+                relay::DirectRelay,
+            },
             pending_proofs::manager::BonsaiPendingProofManager,
         },
+        EthersClientConfig, FeeCap, DEFAULT_ETH_NODE_TIMEOUT, DEFAULT_FEE_QUEUE_DEPTH,
+        DEFAULT_STUCK_TX_TIMEOUT, DEFAULT_TX_RESUBMIT_BUMP,
     };
 
     #[tokio::test]
@@ -47,16 +59,19 @@ pub(crate) mod tests {
         let done_notifer = Arc::new(Notify::new());
 
         let mut manager = BonsaiPendingProofManager::new(
-            bonsai_client,
+            bonsai_client.into(),
             storage.clone(),
             notifier.clone(),
-            done_notifer.clone(),
+            vec![done_notifer.clone()],
+            None,
         );
 
         // add a pending proof request to storage
         storage
             .add_new_bonsai_proof_request(ProofRequestInformation {
                 proof_request_id: proof_id,
+                chain_id: 31337,
+                relay_contract_address: Address::default(),
                 callback_proof_request_event: CallbackRequestFilter {
                     account: Address::default(),
                     image_id: H256::default().into(),
@@ -65,6 +80,9 @@ pub(crate) mod tests {
                     function_selector: [0xab, 0xcd, 0xef, 0xab],
                     gas_limit: 3000000,
                 },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
             })
             .await
             .expect("storage should succeed");
@@ -84,6 +102,772 @@ pub(crate) mod tests {
         done_notifer.notified().await;
     }
 
+    #[tokio::test]
+    async fn completed_proof_manager_stops_on_shutdown() {
+        // Mock API server
+        let (_, server) = get_test_bonsai_server().await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let ethers_client_config = EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            31337,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap(),
+            1,
+            Duration::from_secs(1),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        );
+        let shutdown_notifier = Arc::new(Notify::new());
+
+        let manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            InMemoryStorage::new(),
+            Arc::new(Notify::new()),
+            mpsc::unbounded_channel().1,
+            Arc::new(Notify::new()),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config,
+            tokio::time::interval(Duration::from_secs(1000)),
+            shutdown_notifier.clone(),
+            None,
+        );
+        let pending_transactions = manager.pending_transactions();
+
+        // the manager should return once notified, with no transaction left
+        // pending
+        shutdown_notifier.notify_one();
+        tokio::time::timeout(Duration::from_secs(10), manager.run())
+            .await
+            .expect("manager should stop on shutdown")
+            .expect("manager should shut down cleanly");
+        assert!(pending_transactions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_only_takes_proofs_of_its_chain() {
+        // Mock API server
+        let (proof_id, server) = get_test_bonsai_server().await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let ethers_client_config = EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            31337,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap(),
+            1,
+            Duration::from_secs(1),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        );
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            mpsc::unbounded_channel().1,
+            Arc::new(Notify::new()),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config,
+            tokio::time::interval(Duration::from_secs(1000)),
+            Arc::new(Notify::new()),
+            None,
+        );
+
+        // one completed proof on the chain of the manager, and one on another
+        // chain
+        let other_proof_id = SessionId::new("other-chain".to_string());
+        for (proof_id, chain_id) in [(proof_id.clone(), 31337), (other_proof_id.clone(), 84532)] {
+            storage
+                .add_new_bonsai_proof_request(ProofRequestInformation {
+                    proof_request_id: proof_id.clone(),
+                    chain_id,
+                    relay_contract_address: Address::default(),
+                    callback_proof_request_event: CallbackRequestFilter {
+                        account: Address::default(),
+                        image_id: H256::default().into(),
+                        input: Bytes::default(),
+                        callback_contract: Address::default(),
+                        function_selector: [0xab, 0xcd, 0xef, 0xab],
+                        gas_limit: 3000000,
+                    },
+                    created_at: Instant::now(),
+                    attached_requests: Vec::new(),
+                    deadline: None,
+                })
+                .await
+                .expect("storage should succeed");
+            storage
+                .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+                .await
+                .expect("should transition to pending");
+            storage
+                .transition_proof_request(proof_id, ProofRequestState::Completed)
+                .await
+                .expect("should transition to completed");
+        }
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+
+        let state = storage.get_proof_request_state(proof_id).await.unwrap();
+        assert_eq!(state, ProofRequestState::PreparingOnchain);
+        let state = storage
+            .get_proof_request_state(other_proof_id)
+            .await
+            .unwrap();
+        assert_eq!(state, ProofRequestState::Completed);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_drops_oldest_proof_when_queue_full() {
+        // Mock API server
+        let (_, server) = get_test_bonsai_server().await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let ethers_client_config = EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            31337,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap(),
+            1,
+            Duration::from_secs(1),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        );
+        let storage = InMemoryStorage::new();
+
+        // a single proof can be queued
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits {
+                relay_queue_depth: 1,
+                ..Default::default()
+            })
+            .1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            Arc::new(Notify::new()),
+            mpsc::unbounded_channel().1,
+            Arc::new(Notify::new()),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config,
+            tokio::time::interval(Duration::from_secs(1000)),
+            Arc::new(Notify::new()),
+            None,
+        );
+
+        let relay = Address::repeat_byte(0x44);
+        let dropped = Metrics::global()
+            .relay_dropped_queue_full
+            .with_label_values(&["31337", &format!("{relay:?}")]);
+        let dropped_before = dropped.get();
+
+        let oldest = SessionId::new("oldest".to_string());
+        let newest = SessionId::new("newest".to_string());
+        for proof_id in [oldest.clone(), newest.clone()] {
+            storage
+                .add_submitted_proof_request(ProofRequestInformation {
+                    proof_request_id: proof_id.clone(),
+                    chain_id: 31337,
+                    relay_contract_address: relay,
+                    callback_proof_request_event: CallbackRequestFilter {
+                        account: Address::default(),
+                        image_id: H256::default().into(),
+                        input: Bytes::default(),
+                        callback_contract: Address::default(),
+                        function_selector: [0xab, 0xcd, 0xef, 0xab],
+                        gas_limit: 3000000,
+                    },
+                    created_at: Instant::now(),
+                    attached_requests: Vec::new(),
+                    deadline: None,
+                })
+                .await
+                .expect("storage should succeed");
+            manager
+                .handle_complete_proof_result(Ok(CompleteProof {
+                    bonsai_proof_id: proof_id,
+                    relay_contract_address: relay,
+                    ethereum_callbacks: Vec::new(),
+                    journal: Vec::new(),
+                }))
+                .await
+                .expect("proof should be queued");
+        }
+
+        // the oldest proof is dropped, failing its request
+        assert_eq!(dropped.get(), dropped_before + 1);
+        let state = storage.get_proof_request_state(oldest).await.unwrap();
+        assert_eq!(state, ProofRequestState::Failed);
+        let state = storage.get_proof_request_state(newest).await.unwrap();
+        assert_eq!(state, ProofRequestState::PreparingOnchain);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_skips_proofs_matching_no_journal_filter() {
+        // Mock API server
+        let (_, server) = get_test_bonsai_server().await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let ethers_client_config = EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            31337,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap(),
+            1,
+            Duration::from_secs(1),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        );
+        let storage = InMemoryStorage::new();
+
+        let filters = vec![vec![0x12, 0x34, 0x56, 0x78], vec![0xca, 0xfe]];
+        assert!(journal_matches(&[], &[0x00]));
+        assert!(!journal_matches(&filters, &[0x12, 0x34]));
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            Arc::new(Notify::new()),
+            mpsc::unbounded_channel().1,
+            Arc::new(Notify::new()),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config,
+            tokio::time::interval(Duration::from_secs(1000)),
+            Arc::new(Notify::new()),
+            None,
+        )
+        .with_journal_filters(filters);
+
+        let relay = Address::repeat_byte(0x44);
+        let proofs = [
+            (
+                SessionId::new("selected".to_string()),
+                vec![0xca, 0xfe, 0x01],
+            ),
+            (
+                SessionId::new("other".to_string()),
+                vec![0x12, 0x34, 0x56, 0x00],
+            ),
+        ];
+        for (proof_id, journal) in proofs.clone() {
+            storage
+                .add_submitted_proof_request(ProofRequestInformation {
+                    proof_request_id: proof_id.clone(),
+                    chain_id: 31337,
+                    relay_contract_address: relay,
+                    callback_proof_request_event: CallbackRequestFilter {
+                        account: Address::default(),
+                        image_id: H256::default().into(),
+                        input: Bytes::default(),
+                        callback_contract: Address::default(),
+                        function_selector: [0xab, 0xcd, 0xef, 0xab],
+                        gas_limit: 3000000,
+                    },
+                    created_at: Instant::now(),
+                    attached_requests: Vec::new(),
+                    deadline: None,
+                })
+                .await
+                .expect("storage should succeed");
+            manager
+                .handle_complete_proof_result(Ok(CompleteProof {
+                    bonsai_proof_id: proof_id,
+                    relay_contract_address: relay,
+                    ethereum_callbacks: Vec::new(),
+                    journal,
+                }))
+                .await
+                .expect("proof should be handled");
+        }
+
+        // only the proof whose journal starts with a filter is queued
+        let [(selected, _), (other, _)] = proofs;
+        let state = storage.get_proof_request_state(selected).await.unwrap();
+        assert_eq!(state, ProofRequestState::PreparingOnchain);
+        let state = storage.get_proof_request_state(other).await.unwrap();
+        assert_eq!(state, ProofRequestState::Skipped);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_follows_reloaded_limits() {
+        // Mock API server
+        let (_, server) = get_test_bonsai_server().await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let ethers_client_config = EthersClientConfig::new(
+            "ws://localhost:8545".to_string(),
+            31337,
+            "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+                .parse()
+                .unwrap(),
+            1,
+            Duration::from_secs(1),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        );
+        let storage = InMemoryStorage::new();
+        let (limits_sender, limits) = watch::channel(RelayLimits::default());
+        let mut send_batch_interval = tokio::time::interval(Duration::from_secs(1000));
+        send_batch_interval.tick().await;
+
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            limits,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            Arc::new(Notify::new()),
+            mpsc::unbounded_channel().1,
+            Arc::new(Notify::new()),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config,
+            send_batch_interval,
+            Arc::new(Notify::new()),
+            None,
+        );
+
+        // the relay queue is reloaded down to a single proof
+        limits_sender.send_modify(|limits| limits.relay_queue_depth = 1);
+        manager.step().await.expect("step should succeed");
+
+        let relay = Address::repeat_byte(0x45);
+        let oldest = SessionId::new("reloaded-oldest".to_string());
+        let newest = SessionId::new("reloaded-newest".to_string());
+        for proof_id in [oldest.clone(), newest.clone()] {
+            storage
+                .add_submitted_proof_request(ProofRequestInformation {
+                    proof_request_id: proof_id.clone(),
+                    chain_id: 31337,
+                    relay_contract_address: relay,
+                    callback_proof_request_event: CallbackRequestFilter {
+                        account: Address::default(),
+                        image_id: H256::default().into(),
+                        input: Bytes::default(),
+                        callback_contract: Address::default(),
+                        function_selector: [0xab, 0xcd, 0xef, 0xab],
+                        gas_limit: 3000000,
+                    },
+                    created_at: Instant::now(),
+                    attached_requests: Vec::new(),
+                    deadline: None,
+                })
+                .await
+                .expect("storage should succeed");
+            manager
+                .handle_complete_proof_result(Ok(CompleteProof {
+                    bonsai_proof_id: proof_id,
+                    relay_contract_address: relay,
+                    ethereum_callbacks: Vec::new(),
+                    journal: Vec::new(),
+                }))
+                .await
+                .expect("proof should be queued");
+        }
+
+        let state = storage.get_proof_request_state(oldest).await.unwrap();
+        assert_eq!(state, ProofRequestState::Failed);
+        let state = storage.get_proof_request_state(newest).await.unwrap();
+        assert_eq!(state, ProofRequestState::PreparingOnchain);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_holds_batch_on_low_balance() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+        use bonsai_ethereum_contracts::i_bonsai_relay;
+        use ethers::prelude::*;
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Counter contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        // Mock API server
+        let (proof_id, server) = get_test_bonsai_server().await;
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let send_batch_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        // no wallet can hold the minimum balance
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::MAX,
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            mpsc::unbounded_channel().1,
+            send_batch_notifier.clone(),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config.clone(),
+            send_batch_interval,
+            Arc::new(Notify::new()),
+            None,
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                chain_id: ethers_client_config.eth_chain_id,
+                relay_contract_address: proxy.address(),
+                callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to pending to completed");
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+
+        let skipped = Metrics::global()
+            .relay_skipped_low_balance
+            .with_label_values(&[
+                &ethers_client_config.eth_chain_id.to_string(),
+                &format!("{:?}", ethers_client.address()),
+            ]);
+        let skipped_before = skipped.get();
+        send_batch_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        assert!(skipped.get() > skipped_before);
+
+        // the batch is held back rather than sent or dropped
+        assert!(manager.pending_transactions().lock().unwrap().is_empty());
+        let logs = ethers_client
+            .get_logs(&Filter::new().address(proxy.address()))
+            .await
+            .expect("logs should be present");
+        assert!(logs.is_empty());
+        let request_state = storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .expect("proof should exist");
+        assert_eq!(request_state, ProofRequestState::PreparingOnchain);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_holds_batch_above_fee_cap() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+        use bonsai_ethereum_contracts::i_bonsai_relay;
+        use ethers::prelude::*;
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Counter contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        // Mock API server
+        let (proof_id, server) = get_test_bonsai_server().await;
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let send_batch_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        // no network fee is below the cap
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits {
+                fee_cap: Some(FeeCap {
+                    max_fee_per_gas: U256::zero(),
+                    queue_depth: DEFAULT_FEE_QUEUE_DEPTH,
+                }),
+                ..Default::default()
+            })
+            .1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            mpsc::unbounded_channel().1,
+            send_batch_notifier.clone(),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config.clone(),
+            send_batch_interval,
+            Arc::new(Notify::new()),
+            None,
+        );
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                chain_id: ethers_client_config.eth_chain_id,
+                relay_contract_address: proxy.address(),
+                callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to pending to completed");
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+
+        let held = Metrics::global()
+            .relay_held_fee_cap
+            .with_label_values(&[&ethers_client_config.eth_chain_id.to_string()]);
+        let held_before = held.get();
+        send_batch_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        assert!(held.get() > held_before);
+
+        // the batch is held back rather than sent or dropped
+        assert!(manager.pending_transactions().lock().unwrap().is_empty());
+        let logs = ethers_client
+            .get_logs(&Filter::new().address(proxy.address()))
+            .await
+            .expect("logs should be present");
+        assert!(logs.is_empty());
+        let request_state = storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .expect("proof should exist");
+        assert_eq!(request_state, ProofRequestState::PreparingOnchain);
+    }
+
+    #[tokio::test]
+    async fn completed_proof_manager_holds_batch_over_spend_limit() {
+        abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+        use bonsai_ethereum_contracts::i_bonsai_relay;
+        use ethers::prelude::*;
+
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("could not get client"),
+        );
+        let proxy = Proxy::deploy(ethers_client.clone(), ())
+            .expect("should be able to deploy the Counter contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        // Mock API server
+        let (proof_id, server) = get_test_bonsai_server().await;
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let new_complete_proofs_notifier = Arc::new(Notify::new());
+        let send_batch_notifier = Arc::new(Notify::new());
+        let mut send_batch_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(10000000000));
+        send_batch_interval.tick().await;
+
+        // no spend is below the limit
+        let spend_limit = SpendLimit::new(Some(U256::zero()));
+        let mut manager = BonsaiCompleteProofManager::new(
+            bonsai_client.into(),
+            true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
+            storage.clone(),
+            new_complete_proofs_notifier.clone(),
+            mpsc::unbounded_channel().1,
+            send_batch_notifier.clone(),
+            3,
+            Arc::new(DirectRelay),
+            ethers_client_config.clone(),
+            send_batch_interval,
+            Arc::new(Notify::new()),
+            None,
+        )
+        .with_spend_limit(spend_limit.clone());
+
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                chain_id: ethers_client_config.eth_chain_id,
+                relay_contract_address: proxy.address(),
+                callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::default().into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::default(),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .expect("storage should succeed");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Pending)
+            .await
+            .expect("should transition to pending");
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .expect("should transition to pending to completed");
+
+        new_complete_proofs_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        manager.step().await.expect("step should succeed");
+
+        send_batch_notifier.notify_one();
+        manager.step().await.expect("step should succeed");
+        // only the chain of the manager is held back
+        assert_eq!(
+            spend_limit.limited_chain_ids(),
+            vec![ethers_client_config.eth_chain_id]
+        );
+
+        // the batch is held back rather than sent or dropped
+        assert!(manager.pending_transactions().lock().unwrap().is_empty());
+        let logs = ethers_client
+            .get_logs(&Filter::new().address(proxy.address()))
+            .await
+            .expect("logs should be present");
+        assert!(logs.is_empty());
+        let request_state = storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .expect("proof should exist");
+        assert_eq!(request_state, ProofRequestState::SpendLimited);
+    }
+
     #[tokio::test]
     async fn integration_test_completed_proof_manager() {
         abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
@@ -131,21 +915,33 @@ pub(crate) mod tests {
         send_batch_interval.tick().await;
 
         let mut manager = BonsaiCompleteProofManager::new(
-            bonsai_client,
+            bonsai_client.into(),
             true,
+            false,
+            U256::zero(),
+            watch::channel(RelayLimits::default()).1,
+            0,
+            DEFAULT_STUCK_TX_TIMEOUT,
+            DEFAULT_TX_RESUBMIT_BUMP,
+            0,
             storage.clone(),
             new_complete_proofs_notifier.clone(),
+            mpsc::unbounded_channel().1,
             send_batch_notifier.clone(),
             max_batch_size,
-            proxy.address(),
+            Arc::new(DirectRelay),
             ethers_client_config.clone(),
             send_batch_interval,
+            Arc::new(Notify::new()),
+            None,
         );
 
         // add a complete proof request to storage
         storage
             .add_new_bonsai_proof_request(ProofRequestInformation {
                 proof_request_id: proof_id.clone(),
+                chain_id: ethers_client_config.eth_chain_id,
+                relay_contract_address: proxy.address(),
                 callback_proof_request_event: i_bonsai_relay::CallbackRequestFilter {
                     account: Address::default(),
                     image_id: H256::default().into(),
@@ -154,6 +950,9 @@ pub(crate) mod tests {
                     function_selector: [0xab, 0xcd, 0xef, 0xab],
                     gas_limit: 3000000,
                 },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
             })
             .await
             .expect("storage should succeed");
@@ -192,6 +991,10 @@ pub(crate) mod tests {
         // third step should actually send the batch to the ethereum network
         manager.step().await.expect("step should succeed");
 
+        // fourth step should wait for the transaction to be confirmed
+        manager.step().await.expect("step should succeed");
+        assert!(manager.pending_transactions().lock().unwrap().is_empty());
+
         // check that the event was emitted
         let filter = &Filter::new().address(proxy.address());
         let logs = ethers_client_config
@@ -12,6 +12,49 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod alert_cmd;
+mod alerts;
 mod bonsai_pending_proof_requests;
+mod bonsai_transport;
+mod callback_gas_limit;
+mod chain_config;
+mod config_reload;
+mod dead_letters;
+mod drain;
+mod eth_node;
+mod eth_node_timeout;
+mod event_db;
+mod event_filter;
+mod events;
+mod json_rpc_batch;
+mod gas_watch;
+mod image_reupload;
+mod kms_signer;
+mod ledger_signer;
 mod manager;
+mod networks;
+mod openapi;
+mod polling;
+mod proof_calldata;
+mod proof_encoding;
+mod private_relay;
+mod replay;
+mod relay_selection;
+mod remote_signer;
+mod reorg;
+mod request_deadline;
+mod request_dedup;
+mod request_events;
+mod rest_api_limits;
+mod rest_api_tls;
+mod resubmit;
+mod revert_retry;
+mod safe_relay;
+mod scheduler;
+mod signer_pool;
+mod snark_validation;
+mod spend_limit;
+mod stuck_tx;
+mod submission;
 mod utils;
+mod ws_heartbeat;
@@ -0,0 +1,47 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use crate::{Network, RelayError};
+
+    #[test]
+    fn networks_map_to_their_chain_ids() {
+        for (name, network, chain_id) in [
+            ("mainnet", Network::Mainnet, 1),
+            ("goerli", Network::Goerli, 5),
+            ("sepolia", Network::Sepolia, 11155111),
+            ("anvil", Network::Anvil, 31337),
+        ] {
+            assert_eq!(Network::try_from(name).unwrap(), network);
+            assert_eq!(network.chain_id(), chain_id);
+            assert_eq!(network.to_string(), name);
+        }
+        assert_eq!("Sepolia".parse::<Network>().unwrap(), Network::Sepolia);
+    }
+
+    #[test]
+    fn unknown_networks_list_the_supported_ones() {
+        for name in ["holesky", "localhost", ""] {
+            let err = Network::try_from(name).unwrap_err();
+            let RelayError::ConfigError(message) = err else {
+                panic!("expected a config error, got {err}");
+            };
+            assert!(
+                message.contains("mainnet, goerli, sepolia, anvil"),
+                "{message}"
+            );
+        }
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use axum::{
+        body::{Body, HttpBody},
+        http::{Request, StatusCode},
+        Router,
+    };
+    use bonsai_sdk::alpha_async::{get_client_from_parts, RoundRobinBonsaiClient};
+    use hyper::service::Service;
+    use serde_json::{json, Value};
+    use tokio::sync::Notify;
+    use utoipa::OpenApi;
+
+    use crate::{
+        api::{
+            callback_request::CallbackRequestState,
+            limits::{ApiLimits, DEFAULT_MAX_REQUEST_BODY_SIZE},
+            openapi::ApiDoc,
+            routes::{
+                ADMIN_DRAIN_ROUTE, ADMIN_RESUME_ROUTE, ADMIN_SPEND_LIMIT_RESUME_ROUTE,
+                ADMIN_SPEND_LIMIT_ROUTE, ADMIN_UPLOAD_IMAGES_ROUTE, CALLBACK_EVENTS_ROUTE,
+                CALLBACK_RETRY_ROUTE, CALLBACK_ROUTE, CALLBACK_STATUS_ROUTE, EVENTS_ROUTE,
+                METRICS_ROUTE, OPENAPI_ROUTE, READY_ROUTE, SUBMISSION_ROUTE, SWAGGER_UI_ROUTE,
+            },
+            server::app,
+            state::ApiState,
+        },
+        intake::Intake,
+        spend::SpendLimit,
+        storage::{in_memory::InMemoryStorage, ProofRequestState},
+    };
+
+    async fn test_app(rest_api_docs: bool) -> Router {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        app(ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: Vec::new(),
+            storage: InMemoryStorage::default(),
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: None,
+            limits: ApiLimits::new(None, DEFAULT_MAX_REQUEST_BODY_SIZE),
+            rest_api_docs,
+            intake: Intake::default(),
+            spend_limit: SpendLimit::default(),
+        })
+    }
+
+    /// Collects the `$ref` targets found in the value.
+    fn references<'a>(value: &'a Value, found: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(target)) => found.push(target),
+                        _ => references(value, found),
+                    }
+                }
+            }
+            Value::Array(values) => values.iter().for_each(|value| references(value, found)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn spec_is_a_valid_openapi_3_0_document() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        // Deserializing checks the document against the object model of the
+        // OpenAPI 3.0 specification: required fields, and the type of every
+        // object, down to the schemas.
+        let document: openapiv3::OpenAPI = serde_json::from_value(spec.clone()).unwrap();
+        assert!(document.openapi.starts_with("3.0."), "{}", document.openapi);
+
+        let mut targets = Vec::new();
+        references(&spec, &mut targets);
+        assert!(!targets.is_empty());
+        for target in targets {
+            let name = target
+                .strip_prefix("#/components/schemas/")
+                .unwrap_or_else(|| panic!("unexpected reference {target}"));
+            assert!(
+                spec["components"]["schemas"].get(name).is_some(),
+                "{target} does not resolve"
+            );
+        }
+
+        let components = document.components.unwrap();
+        for (path, item) in document.paths.paths {
+            let item = item.into_item().unwrap();
+            for (method, operation) in item.iter() {
+                assert!(!operation.responses.responses.is_empty(), "{method} {path}");
+                for requirement in operation.security.iter().flatten() {
+                    for scheme in requirement.keys() {
+                        assert!(
+                            components.security_schemes.contains_key(scheme),
+                            "{method} {path} requires the undeclared {scheme}"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spec_documents_every_route() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        for route in [
+            CALLBACK_ROUTE,
+            CALLBACK_STATUS_ROUTE,
+            CALLBACK_RETRY_ROUTE,
+            CALLBACK_EVENTS_ROUTE,
+            EVENTS_ROUTE,
+            SUBMISSION_ROUTE,
+            ADMIN_UPLOAD_IMAGES_ROUTE,
+            ADMIN_DRAIN_ROUTE,
+            ADMIN_RESUME_ROUTE,
+            ADMIN_SPEND_LIMIT_ROUTE,
+            ADMIN_SPEND_LIMIT_RESUME_ROUTE,
+            OPENAPI_ROUTE,
+            METRICS_ROUTE,
+            READY_ROUTE,
+        ] {
+            let path = route.replace(":request_id", "{request_id}");
+            assert!(
+                spec["paths"].get(&path).is_some(),
+                "{path} is not documented"
+            );
+        }
+
+        // The lifecycle states are the ones the handlers serialize.
+        let states = [
+            ProofRequestState::New,
+            ProofRequestState::Pending,
+            ProofRequestState::Completed,
+            ProofRequestState::Failed,
+            ProofRequestState::PreparingOnchain,
+            ProofRequestState::SpendLimited,
+            ProofRequestState::CompletedOnchain(Default::default()),
+            ProofRequestState::Expired,
+            ProofRequestState::Skipped,
+        ]
+        .iter()
+        .map(|state| serde_json::to_value(CallbackRequestState::from(state)).unwrap())
+        .collect::<Vec<_>>();
+        assert_eq!(
+            spec["components"]["schemas"]["CallbackRequestState"]["enum"],
+            json!(states)
+        );
+
+        // Errors are plain text messages, and the requests authenticated with
+        // an API key.
+        let status = &spec["paths"]["/v1/callbacks/{request_id}"]["get"];
+        assert_eq!(status["security"], json!([{"api_key": []}]));
+        for code in ["401", "404", "429"] {
+            assert_eq!(
+                status["responses"][code]["content"]["text/plain"]["schema"]["type"], "string",
+                "{code}"
+            );
+        }
+        assert!(status["responses"]["429"]["headers"]["Retry-After"].is_object());
+        assert_eq!(
+            spec["components"]["securitySchemes"]["api_key"],
+            json!({
+                "type": "apiKey",
+                "in": "header",
+                "name": "x-api-key",
+                "description": "API key of the client, also used as its Bonsai API key",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn spec_is_served_and_swagger_ui_on_demand() {
+        let mut app = test_app(false).await;
+        // The specification needs no API key.
+        let response = app
+            .call(Request::get(OPENAPI_ROUTE).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = Vec::new();
+        let mut response = response.into_body();
+        while let Some(chunk) = response.data().await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        let served: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(served, serde_json::to_value(ApiDoc::openapi()).unwrap());
+
+        let response = app
+            .call(Request::get(SWAGGER_UI_ROUTE).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let mut app = test_app(true).await;
+        let response = app
+            .call(Request::get(SWAGGER_UI_ROUTE).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_redirection(), "{}", response.status());
+    }
+}
@@ -0,0 +1,278 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc, time::Duration};
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use ethers::{
+        contract::abigen,
+        providers::{Http, Middleware, Provider},
+        types::{Address, Bytes, Filter, TransactionRequest, H256},
+    };
+    use serde_json::Value;
+    use tokio::sync::{mpsc, watch};
+
+    use crate::{
+        downloader::{
+            event_processor::EventProcessor, log_poller::LogPoller,
+            proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
+        },
+        eth_node::EthNodeTransport,
+        intake::{Intake, IntakeClosed},
+        sdk::utils,
+        uploader::completed_proofs::relay::{reconfirm_receipt, EthersClient},
+        EthersClientConfig,
+    };
+
+    abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+    const EVENT_NAME: &str = "CallbackRequest(address,bytes32,bytes,address,bytes4,uint64)";
+
+    /// Forwards the processed events to a channel.
+    struct ForwardingProcessor(mpsc::UnboundedSender<CallbackRequestFilter>);
+
+    #[async_trait::async_trait]
+    impl EventProcessor for ForwardingProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            _address: Address,
+            event: CallbackRequestFilter,
+        ) -> Result<String, crate::api::error::Error> {
+            self.0.send(event).unwrap();
+            Ok(String::new())
+        }
+    }
+
+    /// Forwards the processed events to a channel, while the intake is open.
+    struct IntakeProcessor {
+        intake: Intake,
+        sender: mpsc::UnboundedSender<CallbackRequestFilter>,
+    }
+
+    #[async_trait::async_trait]
+    impl EventProcessor for IntakeProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            _address: Address,
+            event: CallbackRequestFilter,
+        ) -> Result<String, crate::api::error::Error> {
+            if self.intake.is_draining() {
+                return Err(IntakeClosed::Draining.into());
+            }
+            self.sender.send(event).unwrap();
+            Ok(String::new())
+        }
+    }
+
+    async fn deploy_proxy(client_config: &EthersClientConfig) -> Proxy<EthersClient> {
+        let client = Arc::new(client_config.get_client().await.unwrap());
+        Proxy::deploy(client, ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed")
+    }
+
+    /// Emits a callback request for the image ID `image_id`.
+    async fn request_callback<M: Middleware + 'static>(proxy: &Proxy<M>, image_id: u8) {
+        proxy
+            .request_callback(
+                [image_id; 32],
+                Bytes::from(b"hello world".to_vec()),
+                Address::random(),
+                [0xab, 0xcd, 0xef, 0xab],
+                3_000_000,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    async fn mine(provider: &Provider<Http>, blocks: usize) {
+        for _ in 0..blocks {
+            provider.request::<_, Value>("evm_mine", ()).await.unwrap();
+        }
+    }
+
+    /// Runs the event stream of a deployed proxy, emits callback requests and
+    /// checks that each of them is processed once.
+    async fn callback_requests_are_processed_once(client_config: EthersClientConfig) {
+        let proxy = deploy_proxy(&client_config).await;
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stream = ProxyCallbackProofRequestStream::new(
+            client_config,
+            vec![proxy.address()],
+            watch::channel(vec![]).1,
+            ForwardingProcessor(sender),
+            None,
+            vec![],
+        );
+        let stream = tokio::spawn(stream.run());
+        // Let the logs be subscribed to, or polled, before emitting events.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        for image_id in 1..=3 {
+            request_callback(&proxy, image_id).await;
+        }
+        for image_id in 1..=3 {
+            let event = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+                .await
+                .expect("callback request should be processed")
+                .unwrap();
+            assert_eq!(H256::from(event.image_id), H256::from([image_id; 32]));
+        }
+        // Polling the last blocks again does not process their events twice.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+        stream.abort();
+    }
+
+    #[tokio::test]
+    async fn callback_requests_are_subscribed_to_over_websocket() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap();
+        assert!(!client_config.polls());
+        callback_requests_are_processed_once(client_config).await;
+    }
+
+    #[tokio::test]
+    async fn callback_requests_are_polled_for_over_http() {
+        let anvil = utils::get_anvil();
+        let client_config = EthersClientConfig {
+            eth_node_url: utils::get_http_provider_endpoint(anvil.as_ref()).unwrap(),
+            ..utils::get_ethers_client_config(anvil.as_ref())
+                .await
+                .unwrap()
+        }
+        .with_poll_interval(Duration::from_millis(100));
+        assert!(client_config.polls());
+        callback_requests_are_processed_once(client_config).await;
+    }
+
+    #[tokio::test]
+    async fn deferred_events_are_processed_once_the_intake_reopens() {
+        let anvil = utils::get_anvil();
+        let client_config = EthersClientConfig {
+            eth_node_url: utils::get_http_provider_endpoint(anvil.as_ref()).unwrap(),
+            ..utils::get_ethers_client_config(anvil.as_ref())
+                .await
+                .unwrap()
+        }
+        .with_poll_interval(Duration::from_millis(100));
+        let proxy = deploy_proxy(&client_config).await;
+        let intake = Intake::default();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stream = ProxyCallbackProofRequestStream::new(
+            client_config,
+            vec![proxy.address()],
+            watch::channel(vec![]).1,
+            IntakeProcessor {
+                intake: intake.clone(),
+                sender,
+            },
+            None,
+            vec![],
+        )
+        .with_intake(intake.clone());
+        let stream = tokio::spawn(stream.run());
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        intake.drain();
+        request_callback(&proxy, 1).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+
+        intake.resume();
+        let event = tokio::time::timeout(Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("deferred callback request should be processed")
+            .unwrap();
+        assert_eq!(H256::from(event.image_id), H256::from([1; 32]));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+        stream.abort();
+    }
+
+    #[tokio::test]
+    async fn log_poller_skips_the_logs_of_blocks_polled_again() {
+        let anvil = utils::get_anvil();
+        let client_config = EthersClientConfig {
+            eth_node_url: utils::get_http_provider_endpoint(anvil.as_ref()).unwrap(),
+            ..utils::get_ethers_client_config(anvil.as_ref())
+                .await
+                .unwrap()
+        };
+        let provider = utils::get_http_provider(anvil.as_ref()).unwrap();
+        let proxy = deploy_proxy(&client_config).await;
+        let filter = Filter::new().address(proxy.address()).event(EVENT_NAME);
+        let start_block = provider.get_block_number().await.unwrap().as_u64() + 1;
+        let mut poller = LogPoller::new(filter, start_block);
+
+        request_callback(&proxy, 1).await;
+        assert_eq!(poller.poll(&provider).await.unwrap().len(), 1);
+        mine(&provider, 1).await;
+        assert!(poller.poll(&provider).await.unwrap().is_empty());
+        request_callback(&proxy, 2).await;
+        let logs = poller.poll(&provider).await.unwrap();
+        assert_eq!(logs.len(), 1);
+        let event: CallbackRequestFilter = ethers::contract::parse_log(logs[0].clone()).unwrap();
+        assert_eq!(H256::from(event.image_id), H256::from([2; 32]));
+    }
+
+    #[tokio::test]
+    async fn transaction_is_reconfirmed_by_polling() {
+        let anvil = utils::get_anvil();
+        let http = utils::get_http_provider(anvil.as_ref()).unwrap();
+        let client =
+            utils::get_ethers_client(http.clone(), utils::get_wallet(anvil.as_ref()).unwrap())
+                .await
+                .unwrap();
+        let receipt = client
+            .send_transaction(TransactionRequest::pay(Address::random(), 1), None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("transfer should be mined");
+        let tx_hash = receipt.transaction_hash;
+
+        let provider = Provider::new(EthNodeTransport::Http(
+            Http::from_str(&utils::get_http_provider_endpoint(anvil.as_ref()).unwrap()).unwrap(),
+        ))
+        .interval(Duration::from_millis(100));
+        let reconfirmed =
+            tokio::spawn(async move { reconfirm_receipt(&provider, receipt, 2, true).await });
+        mine(&http, 2).await;
+
+        let reconfirmed = tokio::time::timeout(Duration::from_secs(10), reconfirmed)
+            .await
+            .expect("transaction should be reconfirmed")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            reconfirmed.map(|receipt| receipt.transaction_hash),
+            Some(tx_hash)
+        );
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::Middleware,
+        types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest, H256, U256},
+    };
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        metrics::Metrics,
+        sdk::utils,
+        uploader::completed_proofs::{
+            private_relay::{PrivateRelay, TxEndpoint, TxSubmitter},
+            relay::EthersClient,
+        },
+        RelayError,
+    };
+
+    const CHAIN_ID: u64 = 31337;
+
+    /// Serves `eth_sendRawTransaction` as a private relay that never
+    /// includes the transactions it accepts.
+    async fn private_relay_server() -> MockServer {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "method": "eth_sendRawTransaction" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": format!("{:?}", H256::zero()),
+            })))
+            .mount(&server)
+            .await;
+        server
+    }
+
+    fn transfer(client: &EthersClient) -> TypedTransaction {
+        TransactionRequest::pay(Address::random(), 1)
+            .from(client.address())
+            .into()
+    }
+
+    fn submissions(endpoint: TxEndpoint) -> u64 {
+        Metrics::global()
+            .relay_tx_submissions
+            .with_label_values(&[&CHAIN_ID.to_string(), endpoint.as_str()])
+            .get()
+    }
+
+    #[tokio::test]
+    async fn private_transaction_falls_back_to_the_public_mempool() {
+        let anvil = utils::get_anvil();
+        let client = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .get_client()
+            .await
+            .unwrap();
+        let server = private_relay_server().await;
+        let submitter = TxSubmitter::new(
+            CHAIN_ID,
+            Some(PrivateRelay {
+                url: server.uri(),
+                fallback_blocks: 2,
+            }),
+        )
+        .unwrap();
+        let private_before = submissions(TxEndpoint::Private);
+        let fallback_before = submissions(TxEndpoint::Fallback);
+
+        let tx_hash = submitter.submit(&client, transfer(&client)).await.unwrap();
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+        assert!(submissions(TxEndpoint::Private) > private_before);
+
+        // The node never sees the transaction, which is still followed.
+        let provider = client.provider();
+        assert!(provider.get_transaction(tx_hash).await.unwrap().is_none());
+        assert!(submitter
+            .pending_transaction(&client, tx_hash)
+            .await
+            .unwrap()
+            .is_some());
+        submitter.fall_back(&client, tx_hash).await.unwrap();
+        assert!(provider.get_transaction(tx_hash).await.unwrap().is_none());
+
+        for _ in 0..2 {
+            provider.request::<_, Value>("evm_mine", ()).await.unwrap();
+        }
+        submitter.fall_back(&client, tx_hash).await.unwrap();
+        assert!(submissions(TxEndpoint::Fallback) > fallback_before);
+        let receipt = provider.get_transaction_receipt(tx_hash).await.unwrap();
+        assert_eq!(
+            receipt.map(|receipt| receipt.transaction_hash),
+            Some(tx_hash)
+        );
+        assert!(submitter
+            .pending_transaction(&client, tx_hash)
+            .await
+            .unwrap()
+            .is_none());
+        submitter.forget(&[tx_hash]);
+    }
+
+    #[tokio::test]
+    async fn private_transactions_get_consecutive_nonces() {
+        let anvil = utils::get_anvil();
+        let client = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .get_client()
+            .await
+            .unwrap();
+        let server = private_relay_server().await;
+        let submitter = TxSubmitter::new(
+            CHAIN_ID,
+            Some(PrivateRelay {
+                url: server.uri(),
+                fallback_blocks: 25,
+            }),
+        )
+        .unwrap();
+
+        let mut nonces = Vec::new();
+        for _ in 0..2 {
+            let tx_hash = submitter.submit(&client, transfer(&client)).await.unwrap();
+            let tx = submitter
+                .pending_transaction(&client, tx_hash)
+                .await
+                .unwrap()
+                .unwrap();
+            nonces.push(*tx.nonce().unwrap());
+        }
+        assert_eq!(nonces[1], nonces[0] + U256::one());
+    }
+
+    #[test]
+    fn invalid_private_relay_url_is_rejected() {
+        let err = TxSubmitter::new(
+            CHAIN_ID,
+            Some(PrivateRelay {
+                url: "not a url".to_string(),
+                fallback_blocks: 25,
+            }),
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(err, RelayError::ConfigError(_)), "{err}");
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use bonsai_ethereum_contracts::i_bonsai_relay::InvokeCallbacksCall;
+    use bonsai_sdk::alpha::responses::SnarkProof;
+    use ethers::{
+        abi::{self, AbiDecode},
+        types::{Address, U256},
+    };
+    use risc0_zkvm::sha::Digest;
+
+    use crate::{
+        uploader::completed_proofs::{
+            complete_proof::{relay_calldata, DryRunCallback},
+            snark::{calldata_to_proof, proof_to_calldata, tokenize_snark_proof},
+        },
+        RelayError,
+    };
+
+    fn proof() -> SnarkProof {
+        let coordinate = |value: u64| format!("{:x}", U256::from(value));
+        SnarkProof {
+            a: vec![coordinate(1), coordinate(2)],
+            b: vec![
+                vec![coordinate(3), coordinate(4)],
+                vec![coordinate(5), coordinate(6)],
+            ],
+            c: vec![coordinate(7), coordinate(8)],
+            public: vec![],
+        }
+    }
+
+    #[test]
+    fn calldata_matches_tokenized_proof() {
+        let proof = proof();
+        let calldata = proof_to_calldata(&proof).unwrap();
+        assert_eq!(
+            calldata.to_vec(),
+            abi::encode(&[tokenize_snark_proof(&proof).unwrap()])
+        );
+        assert_eq!(calldata.len(), 8 * 32);
+    }
+
+    #[test]
+    fn calldata_round_trip() {
+        let mut proof = proof();
+        proof.c[1] = format!("{:x}", U256::MAX);
+        let calldata = proof_to_calldata(&proof).unwrap();
+        assert_eq!(calldata_to_proof(&calldata).unwrap(), proof);
+    }
+
+    #[test]
+    fn malformed_proof() {
+        let mut proof = proof();
+        proof.b.pop();
+        assert!(proof_to_calldata(&proof).is_err());
+    }
+
+    #[test]
+    fn dry_run_calldata_invokes_the_callback() {
+        let proof = proof();
+        let callback = DryRunCallback {
+            callback_contract: Address::repeat_byte(1),
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+            post_state_digest: Digest::from([2; 32]),
+        };
+        let image_id = Digest::from([3; 32]);
+        let calldata =
+            relay_calldata(&proof, b"journal", image_id, &callback, false, true).unwrap();
+
+        let call = InvokeCallbacksCall::decode(&calldata).unwrap();
+        let [invoked] = call.callbacks.as_slice() else {
+            panic!("expected a single callback, got {:?}", call.callbacks);
+        };
+        assert_eq!(invoked.callback_contract, Address::repeat_byte(1));
+        assert_eq!(invoked.gas_limit, 3000000);
+        assert_eq!(
+            invoked.payload.to_vec(),
+            [&[0xab, 0xcd, 0xef, 0xab], b"journal".as_slice(), &[3; 32]].concat()
+        );
+        assert_eq!(invoked.auth.seal, proof_to_calldata(&proof).unwrap());
+        assert_eq!(invoked.auth.post_state_digest, [2; 32]);
+
+        // In dev mode, the seal is left empty.
+        let calldata =
+            relay_calldata(&proof, b"journal", image_id, &callback, true, false).unwrap();
+        let call = InvokeCallbacksCall::decode(&calldata).unwrap();
+        assert!(call.callbacks[0].auth.seal.is_empty());
+    }
+
+    #[test]
+    fn truncated_calldata() {
+        let calldata = proof_to_calldata(&proof()).unwrap();
+        let err = calldata_to_proof(&calldata[..calldata.len() - 1]).unwrap_err();
+        assert!(matches!(err, RelayError::ProofEncodingError(_)), "{err:?}");
+        assert_eq!(
+            err.to_string(),
+            "encoded proof is 255 bytes long, expected 256"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use bonsai_sdk::alpha::responses::SnarkProof;
+    use ethers::utils::keccak256;
+
+    use crate::{proof_encoding::CompressedSnarkProof, RelayError};
+
+    /// A proof with coordinates of the length of those returned by Bonsai.
+    fn proof() -> SnarkProof {
+        let coordinate = |index: u8| format!("0x{}", hex::encode(keccak256([index])));
+        SnarkProof {
+            a: vec![coordinate(0), coordinate(1)],
+            b: vec![
+                vec![coordinate(2), coordinate(3)],
+                vec![coordinate(4), coordinate(5)],
+            ],
+            c: vec![coordinate(6), coordinate(7)],
+            public: (8..12).map(coordinate).collect(),
+        }
+    }
+
+    #[test]
+    fn compressed_proof_round_trip() {
+        let proof = proof();
+        let compressed = CompressedSnarkProof::from(&proof);
+        assert_eq!(SnarkProof::try_from(&compressed).unwrap(), proof);
+    }
+
+    #[test]
+    fn compressed_proof_is_smaller_than_json() {
+        let proof = proof();
+        let json = serde_json::to_vec(&proof).unwrap();
+        let compressed = CompressedSnarkProof::from(&proof);
+        assert!(
+            compressed.0.len() < json.len(),
+            "{} compressed bytes, {} JSON bytes",
+            compressed.0.len(),
+            json.len()
+        );
+    }
+
+    #[test]
+    fn corrupted_compressed_proof() {
+        let mut compressed = CompressedSnarkProof::from(&proof());
+        compressed.0.truncate(compressed.0.len() / 2);
+        let err = SnarkProof::try_from(&compressed).unwrap_err();
+        assert!(matches!(err, RelayError::ProofEncodingError(_)), "{err}");
+
+        let err = SnarkProof::try_from(&CompressedSnarkProof(b"{}".to_vec())).unwrap_err();
+        assert!(matches!(err, RelayError::ProofEncodingError(_)), "{err}");
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use axum::http::StatusCode;
+    use ethers::types::Address;
+
+    use crate::api::state::{select_relay, RelayContract};
+
+    fn relay(chain_id: u64, byte: u8) -> RelayContract {
+        RelayContract {
+            chain_id,
+            address: Address::repeat_byte(byte),
+        }
+    }
+
+    #[test]
+    fn single_relay_is_selected_by_default() {
+        let relays = [relay(1, 1)];
+        assert_eq!(select_relay(&relays, None, None).unwrap(), relays[0]);
+        assert_eq!(
+            select_relay(&relays, None, Some(relays[0].address)).unwrap(),
+            relays[0]
+        );
+        assert_eq!(select_relay(&relays, Some(1), None).unwrap(), relays[0]);
+    }
+
+    #[test]
+    fn several_relays_require_a_selection() {
+        let relays = [relay(1, 1), relay(1, 2)];
+        assert_eq!(
+            select_relay(&relays, None, Some(relays[1].address)).unwrap(),
+            relays[1]
+        );
+
+        let err = select_relay(&relays, None, None).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("select one with relay_address"));
+    }
+
+    #[test]
+    fn relays_at_the_same_address_require_a_chain() {
+        let relays = [relay(11155111, 1), relay(84532, 1), relay(84532, 2)];
+        assert_eq!(
+            select_relay(&relays, Some(84532), Some(relays[1].address)).unwrap(),
+            relays[1]
+        );
+        assert_eq!(
+            select_relay(&relays, None, Some(relays[2].address)).unwrap(),
+            relays[2]
+        );
+
+        let err = select_relay(&relays, None, Some(relays[0].address)).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("select one with chain_id"));
+    }
+
+    #[test]
+    fn unknown_relay_is_rejected() {
+        let relays = [relay(1, 1)];
+        let err = select_relay(&relays, None, Some(Address::repeat_byte(3))).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("is not served by this relayer"));
+
+        let err = select_relay(&relays, Some(5), None).unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+        assert!(err.to_string().contains("chain 5 is not served"));
+    }
+}
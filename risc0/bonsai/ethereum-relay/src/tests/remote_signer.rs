@@ -0,0 +1,185 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{transaction::eip2718::TypedTransaction, Address, TransactionRequest},
+    };
+    use serde_json::json;
+    use wiremock::{
+        matchers::{body_partial_json, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        metrics::Metrics,
+        remote_signer::{remote_signer_backoff, RemoteSignerConfig, RemoteSignerError},
+        RelaySignerError, WalletKeyIdentifier,
+    };
+
+    const PRIVATE_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const OTHER_PRIVATE_KEY: &str =
+        "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn tx() -> TypedTransaction {
+        TransactionRequest::pay(Address::repeat_byte(1), 1000)
+            .nonce(3)
+            .gas(21000)
+            .gas_price(10)
+            .chain_id(5)
+            .into()
+    }
+
+    /// Raw transaction as signed by the remote signer holding `key`.
+    fn signed_tx(key: &str) -> String {
+        let wallet = key.parse::<LocalWallet>().unwrap().with_chain_id(5u64);
+        let signature = wallet.sign_transaction_sync(&tx()).unwrap();
+        format!("0x{}", hex::encode(tx().rlp_signed(&signature)))
+    }
+
+    fn rpc_result(result: serde_json::Value) -> ResponseTemplate {
+        ResponseTemplate::new(200)
+            .set_body_json(json!({ "jsonrpc": "2.0", "id": 1, "result": result }))
+    }
+
+    async fn mock_rpc(server: &MockServer, rpc_method: &str, response: ResponseTemplate) {
+        Mock::given(method("POST"))
+            .and(body_partial_json(json!({ "method": rpc_method })))
+            .respond_with(response)
+            .mount(server)
+            .await;
+    }
+
+    #[test]
+    fn wallet_key_identifiers_can_be_remote_signers() {
+        assert_eq!(
+            "remote:https://web3signer.example.com:9000"
+                .parse::<WalletKeyIdentifier>()
+                .unwrap(),
+            WalletKeyIdentifier::Remote("https://web3signer.example.com:9000".to_string())
+        );
+        assert!("remote:web3signer".parse::<WalletKeyIdentifier>().is_err());
+    }
+
+    #[test]
+    fn backoff_doubles_on_each_retry() {
+        assert_eq!(remote_signer_backoff(1), Duration::from_millis(500));
+        assert_eq!(remote_signer_backoff(2), Duration::from_secs(1));
+        assert_eq!(remote_signer_backoff(3), Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn remote_signer_signs_transactions_of_its_account() {
+        let wallet = PRIVATE_KEY.parse::<LocalWallet>().unwrap();
+        let server = MockServer::start().await;
+        mock_rpc(
+            &server,
+            "eth_accounts",
+            rpc_result(json!([wallet.address()])),
+        )
+        .await;
+        // The remote signer is unavailable once, and the request retried.
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "method": "eth_signTransaction" }),
+            ))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        mock_rpc(
+            &server,
+            "eth_signTransaction",
+            rpc_result(json!(signed_tx(PRIVATE_KEY))),
+        )
+        .await;
+
+        let errors = Metrics::global()
+            .remote_signer_errors
+            .with_label_values(&["eth_signTransaction"]);
+        let errors_before = errors.get();
+
+        let signer = RemoteSignerConfig::default()
+            .signer(&server.uri(), 5)
+            .await
+            .unwrap();
+        assert_eq!(signer.address(), wallet.address());
+        let signature = signer.sign_transaction(&tx()).await.unwrap();
+        assert_eq!(
+            signature,
+            wallet
+                .with_chain_id(5u64)
+                .sign_transaction_sync(&tx())
+                .unwrap()
+        );
+        assert!(errors.get() > errors_before);
+    }
+
+    #[tokio::test]
+    async fn remote_signer_rejections_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(body_partial_json(
+                json!({ "method": "eth_signTransaction" }),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "error": { "code": -32000, "message": "Signer not found" },
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let config = RemoteSignerConfig {
+            signer_address: Some(Address::repeat_byte(2)),
+            ..Default::default()
+        };
+        let signer = config.signer(&server.uri(), 5).await.unwrap();
+        let err = signer.sign_transaction(&tx()).await.unwrap_err();
+        assert!(!err.is_retryable());
+        assert!(
+            matches!(&err, RemoteSignerError::Rpc { code: -32000, .. }),
+            "{err}"
+        );
+        let err: RelaySignerError = signer.error(err);
+        assert!(err.to_string().contains(&server.uri()), "{err}");
+    }
+
+    #[tokio::test]
+    async fn remote_signatures_of_another_account_are_rejected() {
+        let server = MockServer::start().await;
+        mock_rpc(
+            &server,
+            "eth_signTransaction",
+            rpc_result(json!(signed_tx(OTHER_PRIVATE_KEY))),
+        )
+        .await;
+
+        let config = RemoteSignerConfig {
+            signer_address: Some(PRIVATE_KEY.parse::<LocalWallet>().unwrap().address()),
+            ..Default::default()
+        };
+        let signer = config.signer(&server.uri(), 5).await.unwrap();
+        let err = signer.sign_transaction(&tx()).await.unwrap_err();
+        assert!(
+            matches!(err, RemoteSignerError::InvalidResponse { .. }),
+            "{err}"
+        );
+    }
+}
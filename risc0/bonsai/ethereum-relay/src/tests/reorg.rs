@@ -0,0 +1,98 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::{
+        providers::{Middleware, Provider, Ws},
+        signers::LocalWallet,
+        types::{Address, TransactionReceipt, TransactionRequest, U256},
+    };
+    use serde_json::Value;
+
+    use crate::{sdk::utils, uploader::completed_proofs::relay::reconfirm_receipt};
+
+    /// Sends a transfer from the first Anvil account, and returns its receipt.
+    async fn transfer(provider: &Provider<Ws>, wallet: LocalWallet) -> TransactionReceipt {
+        let client = utils::get_ethers_client(provider.clone(), wallet)
+            .await
+            .unwrap();
+        client
+            .send_transaction(TransactionRequest::pay(Address::random(), 1), None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("transfer should be mined")
+    }
+
+    async fn mine(provider: &Provider<Ws>, blocks: usize) {
+        for _ in 0..blocks {
+            provider.request::<_, Value>("evm_mine", ()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn transaction_is_reconfirmed_after_reorg_depth_blocks() {
+        let anvil = utils::get_anvil();
+        let provider = utils::get_ws_provider(anvil.as_ref()).await.unwrap();
+        let receipt = transfer(&provider, utils::get_wallet(anvil.as_ref()).unwrap()).await;
+        let tx_hash = receipt.transaction_hash;
+
+        let reconfirmed = tokio::spawn({
+            let provider = provider.clone();
+            async move { reconfirm_receipt(&provider, receipt, 2, false).await }
+        });
+        // Let the block headers be subscribed to before mining.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        mine(&provider, 2).await;
+
+        let reconfirmed = tokio::time::timeout(Duration::from_secs(10), reconfirmed)
+            .await
+            .expect("transaction should be reconfirmed")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            reconfirmed.map(|receipt| receipt.transaction_hash),
+            Some(tx_hash)
+        );
+    }
+
+    #[tokio::test]
+    async fn transaction_dropped_by_a_reorg_is_detected() {
+        let anvil = utils::get_anvil();
+        let provider = utils::get_ws_provider(anvil.as_ref()).await.unwrap();
+        let snapshot: U256 = provider.request("evm_snapshot", ()).await.unwrap();
+        let receipt = transfer(&provider, utils::get_wallet(anvil.as_ref()).unwrap()).await;
+
+        let reconfirmed = tokio::spawn({
+            let provider = provider.clone();
+            async move { reconfirm_receipt(&provider, receipt, 3, false).await }
+        });
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        // Replace the block of the transaction with an empty one.
+        let reverted: bool = provider.request("evm_revert", [snapshot]).await.unwrap();
+        assert!(reverted);
+        mine(&provider, 1).await;
+
+        let reconfirmed = tokio::time::timeout(Duration::from_secs(10), reconfirmed)
+            .await
+            .expect("reorg should be detected")
+            .unwrap()
+            .unwrap();
+        assert!(reconfirmed.is_none());
+    }
+}
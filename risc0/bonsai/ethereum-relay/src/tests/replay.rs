@@ -0,0 +1,126 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::Provider,
+        types::{Address, Filter, Log, TransactionReceipt, H256, U256, U64},
+    };
+
+    use crate::downloader::replay::{
+        block_ranges, historical_logs, in_receipt, parse_logs, HistoryScan,
+    };
+
+    fn log(block_hash: H256, log_index: u64) -> Log {
+        Log {
+            address: Address::repeat_byte(1),
+            block_hash: Some(block_hash),
+            transaction_hash: Some(H256::repeat_byte(2)),
+            log_index: Some(U256::from(log_index)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_logs_skips_blank_lines() -> anyhow::Result<()> {
+        let first = log(H256::repeat_byte(3), 0);
+        let second = log(H256::repeat_byte(3), 1);
+        let file = format!(
+            "{}\n\n{}\n",
+            serde_json::to_string(&first)?,
+            serde_json::to_string(&second)?
+        );
+        assert_eq!(parse_logs(file.as_bytes())?, vec![first, second]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_logs_reports_invalid_line() -> anyhow::Result<()> {
+        let file = format!(
+            "{}\nnot a log\n",
+            serde_json::to_string(&log(H256::zero(), 0))?
+        );
+        let err = parse_logs(file.as_bytes()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid log on line 2");
+        Ok(())
+    }
+
+    #[test]
+    fn in_receipt_of_transaction_mined_in_log_block() {
+        let block_hash = H256::repeat_byte(3);
+        let replayed = log(block_hash, 1);
+        let receipt = TransactionReceipt {
+            block_hash: Some(block_hash),
+            logs: vec![log(block_hash, 0), log(block_hash, 1)],
+            ..Default::default()
+        };
+        assert!(in_receipt(&replayed, Some(&receipt)));
+
+        // The transaction was dropped.
+        assert!(!in_receipt(&replayed, None));
+
+        // The transaction was mined in another block after a reorg.
+        let reorged = TransactionReceipt {
+            block_hash: Some(H256::repeat_byte(4)),
+            ..receipt.clone()
+        };
+        assert!(!in_receipt(&replayed, Some(&reorged)));
+
+        // The receipt has no such log.
+        let other_logs = TransactionReceipt {
+            logs: vec![log(block_hash, 0)],
+            ..receipt
+        };
+        assert!(!in_receipt(&replayed, Some(&other_logs)));
+    }
+
+    #[test]
+    fn block_ranges_cover_the_blocks() {
+        assert_eq!(
+            block_ranges(10, 2509, 1000),
+            vec![(10, 1009), (1010, 2009), (2010, 2509)]
+        );
+        assert_eq!(block_ranges(7, 7, 1000), vec![(7, 7)]);
+        assert!(block_ranges(8, 7, 1000).is_empty());
+    }
+
+    #[tokio::test]
+    async fn historical_logs_stop_at_the_head_block() -> anyhow::Result<()> {
+        let (provider, mock) = Provider::mocked();
+        let filter = Filter::new().address(Address::repeat_byte(1));
+        let first = Log {
+            block_number: Some(U64::from(5)),
+            ..log(H256::repeat_byte(3), 0)
+        };
+        let second = Log {
+            block_number: Some(U64::from(1200)),
+            ..log(H256::repeat_byte(4), 0)
+        };
+        // The mocked responses are returned last pushed first.
+        mock.push::<Vec<Log>, _>(vec![second.clone()])?;
+        mock.push::<Vec<Log>, _>(vec![first.clone()])?;
+
+        let scan = HistoryScan {
+            start_block: 0,
+            end_block: Some(5000),
+        };
+        let logs = historical_logs(&provider, &filter, scan, 1500).await?;
+
+        assert_eq!(logs, vec![first, second]);
+        mock.assert_request("eth_getLogs", [filter.clone().from_block(0).to_block(999)])?;
+        mock.assert_request("eth_getLogs", [filter.from_block(1000).to_block(1500)])?;
+        Ok(())
+    }
+}
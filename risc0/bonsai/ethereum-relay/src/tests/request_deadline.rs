@@ -0,0 +1,204 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{
+        alpha::{responses::SessionStatusRes, SessionId},
+        alpha_async::get_client_from_parts,
+    };
+    use ethers::types::{Address, Bytes, H256};
+    use tokio::sync::Notify;
+    use wiremock::{
+        matchers::{method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        storage::{
+            in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState, Storage,
+        },
+        tests::utils::tests::get_test_bonsai_server,
+        uploader::pending_proofs::manager::BonsaiPendingProofManager,
+    };
+
+    const CHAIN_ID: u64 = 31337;
+
+    fn proof_request(
+        proof_request_id: SessionId,
+        deadline: Option<Instant>,
+    ) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id,
+            chain_id: CHAIN_ID,
+            relay_contract_address: Address::default(),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::default(),
+                image_id: H256::default().into(),
+                input: Bytes::default(),
+                callback_contract: Address::default(),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            created_at: Instant::now(),
+            attached_requests: Vec::new(),
+            deadline,
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_past_their_deadline_expire_unproven() {
+        let (proof_id, server) = get_test_bonsai_server().await;
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let notifier = Arc::new(Notify::new());
+        let done_notifier = Arc::new(Notify::new());
+        let mut manager = BonsaiPendingProofManager::new(
+            bonsai_client.into(),
+            storage.clone(),
+            notifier.clone(),
+            vec![done_notifier.clone()],
+            None,
+        );
+
+        let expired_id = SessionId::new("expired".to_string());
+        storage
+            .add_new_bonsai_proof_request(proof_request(expired_id.clone(), Some(Instant::now())))
+            .await
+            .unwrap();
+        storage
+            .add_new_bonsai_proof_request(proof_request(
+                proof_id.clone(),
+                Some(Instant::now() + Duration::from_secs(60)),
+            ))
+            .await
+            .unwrap();
+
+        // The request past its deadline expires without being proven, while
+        // the other one is.
+        notifier.notify_one();
+        manager.step().await.unwrap();
+        assert_eq!(
+            storage.get_proof_request_state(expired_id).await.unwrap(),
+            ProofRequestState::Expired
+        );
+        manager.step().await.unwrap();
+        done_notifier.notified().await;
+        assert_eq!(
+            storage.get_proof_request_state(proof_id).await.unwrap(),
+            ProofRequestState::Completed
+        );
+    }
+
+    #[tokio::test]
+    async fn sessions_expiring_while_proven_are_stopped() {
+        let server = MockServer::start().await;
+        let proof_id = SessionId::new("running".to_string());
+        Mock::given(method("GET"))
+            .and(path(format!("sessions/status/{}", proof_id.uuid)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(SessionStatusRes {
+                status: "RUNNING".to_string(),
+                receipt_url: None,
+                error_msg: None,
+                state: None,
+                stats: None,
+            }))
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path(format!("sessions/{}", proof_id.uuid)))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let bonsai_client = get_client_from_parts(server.uri(), String::default())
+            .await
+            .unwrap();
+        let storage = InMemoryStorage::new();
+        let notifier = Arc::new(Notify::new());
+        let mut manager = BonsaiPendingProofManager::new(
+            bonsai_client.into(),
+            storage.clone(),
+            notifier.clone(),
+            vec![Arc::new(Notify::new())],
+            None,
+        )
+        .with_cancel_expired_sessions(true);
+
+        storage
+            .add_new_bonsai_proof_request(proof_request(
+                proof_id.clone(),
+                Some(Instant::now() + Duration::from_millis(500)),
+            ))
+            .await
+            .unwrap();
+
+        notifier.notify_one();
+        manager.step().await.unwrap();
+        assert_eq!(
+            storage
+                .get_proof_request_state(proof_id.clone())
+                .await
+                .unwrap(),
+            ProofRequestState::Pending
+        );
+
+        // The session is stopped once the deadline passes.
+        tokio::time::timeout(Duration::from_secs(10), manager.step())
+            .await
+            .expect("request should expire")
+            .unwrap();
+        assert_eq!(
+            storage.get_proof_request_state(proof_id).await.unwrap(),
+            ProofRequestState::Expired
+        );
+        server.verify().await;
+    }
+
+    #[tokio::test]
+    async fn requests_only_share_sessions_expiring_no_earlier() {
+        let storage = InMemoryStorage::new();
+        let deadline = Instant::now() + Duration::from_secs(60);
+        let session_id = SessionId::new("session".to_string());
+        let request = proof_request(session_id.clone(), Some(deadline));
+        let event = request.callback_proof_request_event.clone();
+        storage.add_new_bonsai_proof_request(request).await.unwrap();
+
+        // A request without a deadline, or with a later one, would expire
+        // along with the session.
+        for later in [None, Some(deadline + Duration::from_secs(1))] {
+            let attached = storage
+                .attach_to_proving_request(CHAIN_ID, Address::default(), event.clone(), later)
+                .await
+                .unwrap();
+            assert!(attached.is_none());
+        }
+
+        let (attached_session, _) = storage
+            .attach_to_proving_request(CHAIN_ID, Address::default(), event, Some(deadline))
+            .await
+            .unwrap()
+            .expect("request should be attached");
+        assert_eq!(attached_session, session_id);
+    }
+}
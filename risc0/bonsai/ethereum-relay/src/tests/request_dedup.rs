@@ -0,0 +1,173 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::alpha::SessionId;
+    use ethers::types::{Address, Bytes, H256};
+
+    use crate::storage::{
+        in_memory::InMemoryStorage, Error as StorageError, ProofRequestInformation,
+        ProofRequestState, Storage,
+    };
+
+    const CHAIN_ID: u64 = 11155111;
+
+    fn relay() -> Address {
+        Address::repeat_byte(0x44)
+    }
+
+    fn callback_request(input: &str, callback_contract: Address) -> CallbackRequestFilter {
+        CallbackRequestFilter {
+            account: Address::default(),
+            image_id: H256::repeat_byte(0x11).into(),
+            input: Bytes::from(input.as_bytes().to_vec()),
+            callback_contract,
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+        }
+    }
+
+    #[tokio::test]
+    async fn identical_requests_share_a_session() {
+        let storage = InMemoryStorage::new();
+        let session_id = SessionId::new("session".to_string());
+        storage
+            .add_new_bonsai_proof_request(ProofRequestInformation {
+                proof_request_id: session_id.clone(),
+                chain_id: CHAIN_ID,
+                relay_contract_address: relay(),
+                callback_proof_request_event: callback_request("input", Address::repeat_byte(1)),
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .unwrap();
+
+        // A different input needs its own session.
+        let attached = storage
+            .attach_to_proving_request(
+                CHAIN_ID,
+                relay(),
+                callback_request("other", Address::repeat_byte(2)),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(attached.is_none());
+
+        storage
+            .transition_proof_request(session_id.clone(), ProofRequestState::Pending)
+            .await
+            .unwrap();
+
+        // The same request made to another relay contract is relayed through
+        // it, so it needs its own session.
+        let attached = storage
+            .attach_to_proving_request(
+                CHAIN_ID,
+                Address::repeat_byte(0x55),
+                callback_request("input", Address::repeat_byte(2)),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(attached.is_none());
+
+        // So does the same request made to a relay contract at the same
+        // address on another chain.
+        let attached = storage
+            .attach_to_proving_request(
+                84532,
+                relay(),
+                callback_request("input", Address::repeat_byte(2)),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(attached.is_none());
+
+        let (attached_session, request_id) = storage
+            .attach_to_proving_request(
+                CHAIN_ID,
+                relay(),
+                callback_request("input", Address::repeat_byte(2)),
+                None,
+            )
+            .await
+            .unwrap()
+            .expect("request should be attached");
+        assert_eq!(attached_session, session_id);
+        assert_eq!(request_id, "session-1");
+
+        // Once proven, the session no longer accepts identical requests.
+        storage
+            .transition_proof_request(session_id.clone(), ProofRequestState::Completed)
+            .await
+            .unwrap();
+        let attached = storage
+            .attach_to_proving_request(
+                CHAIN_ID,
+                relay(),
+                callback_request("input", Address::repeat_byte(3)),
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(attached.is_none());
+
+        // The attached callback is fanned out along with the original one.
+        let completed = storage.fetch_completed_bonsai_requests(None).await.unwrap();
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].attached_requests.len(), 1);
+        assert_eq!(completed[0].attached_requests[0].request_id, "session-1");
+        assert_eq!(
+            completed[0].attached_requests[0]
+                .callback_proof_request_event
+                .callback_contract,
+            Address::repeat_byte(2)
+        );
+
+        // Each request reports its own status.
+        for request_id in ["session", "session-1"] {
+            let (request_session, state) = storage.get_request_state(request_id).await.unwrap();
+            assert_eq!(request_session, session_id);
+            assert_eq!(state, ProofRequestState::Completed);
+        }
+
+        let tx_hash = H256::repeat_byte(0xab);
+        storage
+            .transition_proof_request(session_id.clone(), ProofRequestState::PreparingOnchain)
+            .await
+            .unwrap();
+        storage
+            .transition_proof_request(
+                session_id.clone(),
+                ProofRequestState::CompletedOnchain(tx_hash),
+            )
+            .await
+            .unwrap();
+        let (_, state) = storage.get_request_state("session-1").await.unwrap();
+        assert_eq!(state, ProofRequestState::CompletedOnchain(tx_hash));
+
+        assert!(matches!(
+            storage.get_request_state("unknown").await,
+            Err(StorageError::RequestNotFound { .. })
+        ));
+    }
+}
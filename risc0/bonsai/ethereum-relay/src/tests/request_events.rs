@@ -0,0 +1,275 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{SocketAddr, TcpListener},
+        sync::Arc,
+        time::Instant,
+    };
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{
+        alpha::SessionId,
+        alpha_async::{get_client_from_parts, RoundRobinBonsaiClient},
+    };
+    use ethers::types::{Address, Bytes, H256};
+    use futures::StreamExt;
+    use serde_json::Value;
+    use tokio::{net::TcpStream, sync::Notify};
+    use tokio_tungstenite::{
+        connect_async,
+        tungstenite::{client::IntoClientRequest, Message},
+        MaybeTlsStream, WebSocketStream,
+    };
+
+    use crate::{
+        api::{
+            limits::{ApiLimits, DEFAULT_MAX_REQUEST_BODY_SIZE},
+            server::app,
+            state::ApiState,
+        },
+        intake::Intake,
+        spend::SpendLimit,
+        storage::{
+            in_memory::InMemoryStorage,
+            transitions::{PendingTransitions, StateTransition, MAX_PENDING_TRANSITIONS},
+            ProofRequestInformation, ProofRequestState, Storage,
+        },
+    };
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    fn proof_request(uuid: &str) -> ProofRequestInformation {
+        ProofRequestInformation {
+            proof_request_id: SessionId::new(uuid.to_string()),
+            chain_id: 31337,
+            relay_contract_address: Address::repeat_byte(1),
+            callback_proof_request_event: CallbackRequestFilter {
+                account: Address::repeat_byte(2),
+                image_id: H256::repeat_byte(3).into(),
+                input: Bytes::from(vec![4, 5, 6]),
+                callback_contract: Address::repeat_byte(2),
+                function_selector: [0xab, 0xcd, 0xef, 0xab],
+                gas_limit: 3000000,
+            },
+            created_at: Instant::now(),
+            attached_requests: Vec::new(),
+            deadline: None,
+        }
+    }
+
+    fn transition(session_id: &str, state: ProofRequestState) -> StateTransition {
+        StateTransition {
+            session_id: session_id.to_string(),
+            request_ids: vec![session_id.to_string()],
+            state,
+            timestamp: 0,
+        }
+    }
+
+    /// Serve the REST API over the storage on a local port, returning its
+    /// address.
+    async fn serve(storage: InMemoryStorage) -> SocketAddr {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        let state = ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: Vec::new(),
+            storage,
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: None,
+            limits: ApiLimits::new(None, DEFAULT_MAX_REQUEST_BODY_SIZE),
+            rest_api_docs: false,
+            intake: Intake::default(),
+            spend_limit: SpendLimit::default(),
+        };
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(app(state).into_make_service_with_connect_info::<SocketAddr>()),
+        );
+        addr
+    }
+
+    async fn connect(addr: SocketAddr, path: &str) -> Client {
+        let mut request = format!("ws://{addr}{path}").into_client_request().unwrap();
+        request
+            .headers_mut()
+            .insert("x-api-key", "key".parse().unwrap());
+        connect_async(request).await.unwrap().0
+    }
+
+    /// Receive the next event, or `None` once the server closed the socket.
+    async fn next_event(client: &mut Client) -> Option<Value> {
+        match client.next().await? {
+            Ok(Message::Text(text)) => Some(serde_json::from_str(&text).unwrap()),
+            Ok(Message::Close(_)) | Err(_) => None,
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn request_transitions_are_streamed_until_a_terminal_state() {
+        let storage = InMemoryStorage::default();
+        storage
+            .add_new_bonsai_proof_request(proof_request("session"))
+            .await
+            .unwrap();
+        let addr = serve(storage.clone()).await;
+        let mut client = connect(addr, "/v1/callbacks/session/events").await;
+
+        // The current state comes first.
+        let event = next_event(&mut client).await.unwrap();
+        assert_eq!(event["state"], "new");
+        assert_eq!(event["request_ids"], serde_json::json!(["session"]));
+        assert_eq!(event["session_id"], "session");
+        assert!(event.get("dropped").is_none());
+
+        let tx_hash = H256::repeat_byte(9);
+        for (state, name) in [
+            (ProofRequestState::Pending, "pending"),
+            (ProofRequestState::Completed, "completed"),
+            (ProofRequestState::PreparingOnchain, "preparing_onchain"),
+            (
+                ProofRequestState::CompletedOnchain(tx_hash),
+                "completed_onchain",
+            ),
+        ] {
+            storage
+                .transition_proof_request(SessionId::new("session".to_string()), state)
+                .await
+                .unwrap();
+            let event = next_event(&mut client).await.unwrap();
+            assert_eq!(event["state"], name);
+        }
+        assert!(next_event(&mut client).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn terminal_requests_are_streamed_as_server_sent_events() {
+        let storage = InMemoryStorage::default();
+        storage
+            .add_new_bonsai_proof_request(proof_request("session"))
+            .await
+            .unwrap();
+        storage
+            .transition_proof_request(
+                SessionId::new("session".to_string()),
+                ProofRequestState::Expired,
+            )
+            .await
+            .unwrap();
+        let addr = serve(storage).await;
+        let client = reqwest::Client::new();
+
+        let response = client
+            .get(format!("http://{addr}/v1/callbacks/session/events"))
+            .header("x-api-key", "key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[reqwest::header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+        // The stream ends after the terminal state.
+        let body = response.text().await.unwrap();
+        assert!(body.starts_with("data: {"), "{body}");
+        assert!(body.contains(r#""state":"expired""#), "{body}");
+
+        let response = client
+            .get(format!("http://{addr}/v1/callbacks/unknown/events"))
+            .header("x-api-key", "key")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NOT_FOUND);
+        let response = client
+            .get(format!("http://{addr}/v1/events"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn all_transitions_are_streamed_to_dashboards() {
+        let storage = InMemoryStorage::default();
+        let addr = serve(storage.clone()).await;
+        let mut client = connect(addr, "/v1/events").await;
+
+        for uuid in ["first", "second"] {
+            storage
+                .add_new_bonsai_proof_request(proof_request(uuid))
+                .await
+                .unwrap();
+            let event = next_event(&mut client).await.unwrap();
+            assert_eq!(event["session_id"], uuid);
+            assert_eq!(event["state"], "new");
+        }
+        storage
+            .transition_proof_request(
+                SessionId::new("first".to_string()),
+                ProofRequestState::Expired,
+            )
+            .await
+            .unwrap();
+        let event = next_event(&mut client).await.unwrap();
+        assert_eq!(event["session_id"], "first");
+        assert_eq!(event["state"], "expired");
+    }
+
+    #[test]
+    fn slow_clients_get_the_latest_and_terminal_transitions() {
+        let mut pending = PendingTransitions::default();
+        pending.push(transition("a", ProofRequestState::New));
+        pending.push(transition("b", ProofRequestState::New));
+        // A newer transition of a session replaces its pending one.
+        pending.push(transition("a", ProofRequestState::Pending));
+        assert_eq!(
+            pending.pop(),
+            Some((transition("b", ProofRequestState::New), 1))
+        );
+        assert_eq!(
+            pending.pop(),
+            Some((transition("a", ProofRequestState::Pending), 0))
+        );
+        assert_eq!(pending.pop(), None);
+
+        // Terminal transitions are kept over the intermediate ones.
+        pending.push(transition("a", ProofRequestState::Failed));
+        for i in 0..MAX_PENDING_TRANSITIONS {
+            pending.push(transition(&i.to_string(), ProofRequestState::New));
+        }
+        assert_eq!(
+            pending.pop(),
+            Some((transition("a", ProofRequestState::Failed), 1))
+        );
+        assert_eq!(
+            pending.pop(),
+            Some((transition("1", ProofRequestState::New), 0))
+        );
+    }
+}
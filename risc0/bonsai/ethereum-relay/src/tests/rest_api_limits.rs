@@ -0,0 +1,182 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use axum::{
+        body::{Body, HttpBody},
+        http::{header, Request, StatusCode},
+        response::Response,
+        Router,
+    };
+    use bonsai_sdk::alpha_async::{get_client_from_parts, RoundRobinBonsaiClient};
+    use hyper::service::Service;
+    use tokio::sync::Notify;
+
+    use crate::{
+        api::{
+            admin::ADMIN_KEY_HEADER,
+            limits::{ApiLimits, RateLimit, RateLimiter},
+            routes::ADMIN_UPLOAD_IMAGES_ROUTE,
+            server::app,
+            state::ApiState,
+        },
+        intake::Intake,
+        spend::SpendLimit,
+        storage::in_memory::InMemoryStorage,
+    };
+
+    const MAX_BODY_SIZE: usize = 64;
+
+    async fn test_app(rate_limit: Option<RateLimit>) -> Router {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        app(ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: Vec::new(),
+            storage: InMemoryStorage::default(),
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: Some("secret".to_string()),
+            limits: ApiLimits::new(rate_limit, MAX_BODY_SIZE),
+            rest_api_docs: false,
+            intake: Intake::default(),
+            spend_limit: SpendLimit::default(),
+        })
+    }
+
+    /// Upload the images named `{"images": ["<name>"]}`, padding the name to
+    /// make a body of `size` bytes. No image is known to the app, so requests
+    /// within the limits are answered with a 404.
+    async fn upload_images(app: &mut Router, size: usize) -> Response {
+        let body = format!(
+            r#"{{"images": ["{}"]}}"#,
+            "x".repeat(size - r#"{"images": [""]}"#.len())
+        );
+        assert_eq!(body.len(), size);
+        post(app, body).await
+    }
+
+    /// Post `body` to the app, declaring its size if it is known, as clients
+    /// do.
+    async fn post(app: &mut Router, body: impl Into<Body>) -> Response {
+        let body = body.into();
+        let mut request = Request::post(ADMIN_UPLOAD_IMAGES_ROUTE)
+            .header(ADMIN_KEY_HEADER, "secret")
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(size) = body.size_hint().exact() {
+            request = request.header(header::CONTENT_LENGTH, size);
+        }
+        app.call(request.body(body).unwrap()).await.unwrap()
+    }
+
+    async fn body_text(response: Response) -> String {
+        let body = response.into_body().data().await.unwrap().unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn rate_limiter_accepts_bursts_then_refills() {
+        let limiter = RateLimiter::new(RateLimit {
+            requests_per_minute: 60,
+            burst: 3,
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire("key:a", start), Ok(()));
+        }
+        assert_eq!(limiter.acquire("key:a", start), Err(Duration::from_secs(1)));
+        // Other clients have their own bucket.
+        assert_eq!(limiter.acquire("key:b", start), Ok(()));
+
+        // One request is refilled every second, not before.
+        let half = start + Duration::from_millis(500);
+        assert_eq!(
+            limiter.acquire("key:a", half),
+            Err(Duration::from_millis(500))
+        );
+        let refilled = start + Duration::from_secs(1);
+        assert_eq!(limiter.acquire("key:a", refilled), Ok(()));
+        assert!(limiter.acquire("key:a", refilled).is_err());
+
+        // The bucket refills up to the burst only.
+        let idle = refilled + Duration::from_secs(3600);
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire("key:a", idle), Ok(()));
+        }
+        assert!(limiter.acquire("key:a", idle).is_err());
+    }
+
+    #[tokio::test]
+    async fn rate_limited_requests_are_told_when_to_retry() {
+        let mut app = test_app(Some(RateLimit {
+            requests_per_minute: 1,
+            burst: 2,
+        }))
+        .await;
+        for _ in 0..2 {
+            let response = upload_images(&mut app, MAX_BODY_SIZE).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        }
+        let response = upload_images(&mut app, MAX_BODY_SIZE).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = response.headers()[header::RETRY_AFTER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((59..=60).contains(&retry_after), "{retry_after}");
+    }
+
+    #[tokio::test]
+    async fn request_bodies_are_limited_in_size() {
+        let mut app = test_app(None).await;
+        let response = upload_images(&mut app, MAX_BODY_SIZE).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = upload_images(&mut app, MAX_BODY_SIZE + 1).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Bodies without a declared size are limited as they are read.
+        let chunks: Vec<Result<_, std::io::Error>> =
+            vec![Ok(vec![b' '; MAX_BODY_SIZE]), Ok(b"{}".to_vec())];
+        let response = post(&mut app, Body::wrap_stream(futures::stream::iter(chunks))).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn malformed_json_is_a_bad_request() {
+        let mut app = test_app(None).await;
+        let response = post(&mut app, r#"{"images": ["echo""#).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let message = body_text(response).await;
+        assert!(message.contains("line 1 column 18"), "{message}");
+
+        // Well-formed JSON of the wrong shape is rejected the same way.
+        let response = post(&mut app, r#"{"images": "echo"}"#).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let message = body_text(response).await;
+        assert!(message.contains("line 1 column"), "{message}");
+    }
+}
@@ -0,0 +1,197 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv6Addr, SocketAddr},
+        path::PathBuf,
+        sync::Arc,
+    };
+
+    use bonsai_sdk::alpha_async::{get_client_from_parts, RoundRobinBonsaiClient};
+    use reqwest::{Certificate, StatusCode};
+    use tokio::sync::Notify;
+
+    use crate::{
+        api::{
+            limits::{ApiLimits, DEFAULT_MAX_REQUEST_BODY_SIZE},
+            routes::METRICS_ROUTE,
+            server::{serve, ApiListener},
+            state::ApiState,
+            tls::RestApiTls,
+        },
+        intake::Intake,
+        spend::SpendLimit,
+        storage::in_memory::InMemoryStorage,
+    };
+
+    const FIRST_CERT: &[u8] = include_bytes!("certs/first.pem");
+    const FIRST_KEY: &[u8] = include_bytes!("certs/first-key.pem");
+    const SECOND_CERT: &[u8] = include_bytes!("certs/second.pem");
+    const SECOND_KEY: &[u8] = include_bytes!("certs/second-key.pem");
+
+    /// Write the certificate and key to a fresh directory, to be replaced as
+    /// on a renewal.
+    fn write_tls_files(cert: &[u8], key: &[u8]) -> RestApiTls {
+        let dir = std::env::temp_dir().join(format!("relay-tls-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let files = RestApiTls {
+            cert: dir.join("cert.pem"),
+            key: dir.join("key.pem"),
+        };
+        std::fs::write(&files.cert, cert).unwrap();
+        std::fs::write(&files.key, key).unwrap();
+        files
+    }
+
+    async fn test_state() -> ApiState<InMemoryStorage> {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: Vec::new(),
+            storage: InMemoryStorage::default(),
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: None,
+            limits: ApiLimits::new(None, DEFAULT_MAX_REQUEST_BODY_SIZE),
+            rest_api_docs: false,
+            intake: Intake::default(),
+            spend_limit: SpendLimit::default(),
+        }
+    }
+
+    /// GET the metrics of the REST API on `address`, as `localhost`, trusting
+    /// only `cert`.
+    async fn get_metrics(address: SocketAddr, cert: &[u8]) -> reqwest::Result<StatusCode> {
+        let client = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(Certificate::from_pem(cert).unwrap())
+            .resolve("localhost", address)
+            .build()
+            .unwrap();
+        let response = client
+            .get(format!(
+                "https://localhost:{}{METRICS_ROUTE}",
+                address.port()
+            ))
+            .send()
+            .await?;
+        Ok(response.status())
+    }
+
+    #[tokio::test]
+    async fn rest_api_is_served_over_tls_with_the_reloaded_certificate() {
+        let files = write_tls_files(FIRST_CERT, FIRST_KEY);
+        let listener = ApiListener::bind("127.0.0.1", "0", Some(files.clone())).unwrap();
+        let address = listener.listener.local_addr().unwrap();
+        let tls = listener.tls.clone().unwrap();
+        let server = tokio::spawn(serve(test_state().await, listener));
+
+        assert_eq!(
+            get_metrics(address, FIRST_CERT).await.unwrap(),
+            StatusCode::OK
+        );
+        assert!(get_metrics(address, SECOND_CERT).await.is_err());
+
+        // Invalid files are not loaded, keeping the current certificate.
+        std::fs::write(&files.key, b"not a key").unwrap();
+        assert!(tls.reload().is_err());
+        assert_eq!(
+            get_metrics(address, FIRST_CERT).await.unwrap(),
+            StatusCode::OK
+        );
+
+        // The renewed certificate is served to new connections.
+        std::fs::write(&files.cert, SECOND_CERT).unwrap();
+        std::fs::write(&files.key, SECOND_KEY).unwrap();
+        tls.reload().unwrap();
+        assert_eq!(
+            get_metrics(address, SECOND_CERT).await.unwrap(),
+            StatusCode::OK
+        );
+        assert!(get_metrics(address, FIRST_CERT).await.is_err());
+
+        server.abort();
+        let _ = std::fs::remove_dir_all(files.cert.parent().unwrap());
+    }
+
+    #[test]
+    fn missing_tls_files_are_reported_on_bind() {
+        let files = RestApiTls {
+            cert: PathBuf::from("/nonexistent/cert.pem"),
+            key: PathBuf::from("/nonexistent/key.pem"),
+        };
+        let err = ApiListener::bind("127.0.0.1", "0", Some(files))
+            .err()
+            .unwrap();
+        assert!(
+            format!("{err:#}").contains("/nonexistent/cert.pem"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn bind_failures_are_reported_on_start() {
+        let listener = ApiListener::bind("127.0.0.1", "0", None).unwrap();
+        let port = listener.listener.local_addr().unwrap().port().to_string();
+        let err = ApiListener::bind("127.0.0.1", &port, None).err().unwrap();
+        assert!(
+            format!("{err:#}").contains("failed to bind the REST API"),
+            "{err:#}"
+        );
+
+        let err = ApiListener::bind("localhost:", "8080", None).err().unwrap();
+        assert!(
+            format!("{err:#}").contains("invalid REST API address"),
+            "{err:#}"
+        );
+        let err = ApiListener::bind("127.0.0.1", "http", None).err().unwrap();
+        assert!(
+            format!("{err:#}").contains("invalid REST API port"),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn rest_api_binds_to_ipv6_addresses() {
+        let listener = ApiListener::bind("::1", "0", None).unwrap();
+        let address = listener.listener.local_addr().unwrap();
+        assert_eq!(address.ip(), Ipv6Addr::LOCALHOST);
+    }
+
+    #[tokio::test]
+    async fn rest_api_is_served_over_tls_on_ipv6() {
+        let files = write_tls_files(FIRST_CERT, FIRST_KEY);
+        let listener = ApiListener::bind("::1", "0", Some(files.clone())).unwrap();
+        let address = listener.listener.local_addr().unwrap();
+        assert_eq!(address.ip(), Ipv6Addr::LOCALHOST);
+        let server = tokio::spawn(serve(test_state().await, listener));
+
+        assert_eq!(
+            get_metrics(address, FIRST_CERT).await.unwrap(),
+            StatusCode::OK
+        );
+
+        server.abort();
+        let _ = std::fs::remove_dir_all(files.cert.parent().unwrap());
+    }
+}
@@ -0,0 +1,104 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::{
+        contract::abigen,
+        providers::Middleware,
+        types::{Address, Bytes, H256},
+    };
+
+    use crate::{
+        event_db::{EventDb, EventProof, EventStatus},
+        events::RelayerEvent,
+        resubmit_event,
+        sdk::utils,
+    };
+
+    abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+    #[tokio::test]
+    async fn failed_events_are_resubmitted_once() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap();
+        let chain_id = client_config.eth_chain_id;
+        let client = Arc::new(client_config.get_client().await.unwrap());
+        let proxy = Proxy::deploy(client.clone(), ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+        let receipt = proxy
+            .request_callback(
+                [1; 32],
+                Bytes::from(b"hello world".to_vec()),
+                Address::random(),
+                [0xab, 0xcd, 0xef, 0xab],
+                3_000_000,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .expect("callback request should be mined");
+
+        // The relay transaction of the event failed after it was proven.
+        let path = std::env::temp_dir().join(format!("relay-events-{}.db", uuid::Uuid::new_v4()));
+        let db = EventDb::open(&path).unwrap();
+        db.record_received(chain_id, &receipt.logs[0], H256::repeat_byte(1), "session")
+            .unwrap();
+        db.set_session_proof(
+            "session",
+            &EventProof {
+                journal: b"journal".to_vec(),
+                seal: Vec::new(),
+                post_state_digest: H256::zero(),
+            },
+        )
+        .unwrap();
+        db.apply(&RelayerEvent::RequestFailed {
+            timestamp: 0,
+            session_id: Some("session".to_string()),
+            error: "reverted".to_string(),
+        })
+        .unwrap();
+        let id = db
+            .events_with_status(chain_id, EventStatus::Failed)
+            .unwrap()[0]
+            .id;
+
+        let tx_hash = resubmit_event(&client_config, &path, id).await.unwrap();
+        let relay_receipt = client
+            .get_transaction_receipt(tx_hash)
+            .await
+            .unwrap()
+            .expect("relay transaction should be mined");
+        assert_eq!(relay_receipt.to, Some(proxy.address()));
+        let event = db.event(id).unwrap().unwrap();
+        assert_eq!(event.status, EventStatus::Completed);
+        assert_eq!(event.relay_tx_hash, Some(tx_hash));
+
+        // The callback was accepted, so it is not resubmitted again.
+        let err = resubmit_event(&client_config, &path, id).await.unwrap_err();
+        assert!(err.to_string().contains("already accepted"), "{err}");
+        assert!(resubmit_event(&client_config, &path, id + 1).await.is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::abi::AbiEncode;
+
+    use crate::uploader::completed_proofs::relay::{
+        decode_revert_reason, is_permanent_revert, revert_retry_backoff,
+    };
+
+    fn error_string(reason: &str) -> Vec<u8> {
+        let mut data = vec![0x08, 0xc3, 0x79, 0xa0];
+        data.extend(reason.to_string().encode());
+        data
+    }
+
+    #[test]
+    fn decodes_revert_reason() {
+        assert_eq!(
+            decode_revert_reason(&error_string("stale oracle reading")),
+            "stale oracle reading"
+        );
+        // Custom errors are not decoded.
+        assert_eq!(
+            decode_revert_reason(&[0xde, 0xad, 0xbe, 0xef]),
+            "0xdeadbeef"
+        );
+    }
+
+    #[test]
+    fn permanent_reverts_are_not_retried() {
+        let reason = decode_revert_reason(&error_string("ProofAlreadySubmitted"));
+        assert!(is_permanent_revert(&reason));
+        assert!(!is_permanent_revert("stale oracle reading"));
+    }
+
+    #[test]
+    fn backoff_doubles_on_each_retry() {
+        assert_eq!(revert_retry_backoff(1), Duration::from_secs(5));
+        assert_eq!(revert_retry_backoff(2), Duration::from_secs(10));
+        assert_eq!(revert_retry_backoff(4), Duration::from_secs(40));
+    }
+}
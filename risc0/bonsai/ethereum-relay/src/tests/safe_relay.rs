@@ -0,0 +1,63 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::{Signature, H256},
+    };
+
+    use crate::uploader::completed_proofs::relay::sign_safe_transaction;
+
+    fn wallets(count: usize) -> Vec<LocalWallet> {
+        (1..=count)
+            .map(|key| format!("{key:064x}").parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn safe_signatures_are_sorted_by_owner() {
+        let owners = wallets(3);
+        let safe_tx_hash = H256::random();
+
+        let signatures = sign_safe_transaction(&owners, safe_tx_hash, 3).unwrap();
+        assert_eq!(signatures.len(), 3 * 65);
+
+        let signers: Vec<_> = signatures
+            .chunks(65)
+            .map(|signature| {
+                Signature::try_from(signature)
+                    .unwrap()
+                    .recover(safe_tx_hash)
+                    .unwrap()
+            })
+            .collect();
+        let mut expected: Vec<_> = owners.iter().map(|owner| owner.address()).collect();
+        expected.sort();
+        assert_eq!(signers, expected);
+    }
+
+    #[test]
+    fn safe_signatures_stop_at_threshold() {
+        let mut owners = wallets(3);
+        // duplicated owners only sign once
+        owners.push(owners[0].clone());
+
+        let signatures = sign_safe_transaction(&owners, H256::random(), 2).unwrap();
+        assert_eq!(signatures.len(), 2 * 65);
+
+        assert!(sign_safe_transaction(&owners, H256::random(), 4).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ethers::types::H256;
+    use tokio::sync::mpsc;
+
+    use crate::{
+        intake::IntakeClosed,
+        metrics::Metrics,
+        reload::parse_config_file,
+        scheduler::{ImageSchedule, Scheduler, SchedulerConfig, SessionSlot},
+    };
+
+    /// Requests of the image, waiting for their session in the background,
+    /// whose sessions are sent to `started` as they are handed out.
+    fn request(
+        scheduler: &Scheduler,
+        image_id: H256,
+        name: &'static str,
+        started: &mpsc::UnboundedSender<(&'static str, SessionSlot)>,
+    ) {
+        let (scheduler, started) = (scheduler.clone(), started.clone());
+        tokio::spawn(async move {
+            let slot = scheduler.acquire(image_id).await.unwrap();
+            started.send((name, slot)).unwrap();
+        });
+    }
+
+    /// Let the spawned requests run until they all wait.
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn gauge(name: &str, image_id: H256) -> String {
+        let label = format!(
+            "bonsai_relay_{name}{{image_id=\"{}\"}}",
+            hex::encode(image_id)
+        );
+        Metrics::global()
+            .encode()
+            .unwrap()
+            .lines()
+            .find_map(|line| {
+                line.strip_prefix(&label)
+                    .map(|value| value.trim().to_string())
+            })
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn sessions_are_handed_out_by_priority_within_the_limits() {
+        // A chatty image of cheap proofs, an important image of large proofs,
+        // and an image on a budget, sharing two sessions.
+        let chatty = H256::repeat_byte(0xa1);
+        let important = H256::repeat_byte(0xa2);
+        let budgeted = H256::repeat_byte(0xa3);
+        let scheduler = Scheduler::new(SchedulerConfig {
+            max_concurrent_sessions: Some(2),
+            images: HashMap::from([
+                (
+                    chatty,
+                    ImageSchedule {
+                        max_concurrent: Some(1),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    important,
+                    ImageSchedule {
+                        max_concurrent: Some(2),
+                        priority: 10,
+                        daily_budget: None,
+                    },
+                ),
+                (
+                    budgeted,
+                    ImageSchedule {
+                        max_concurrent: None,
+                        priority: 5,
+                        daily_budget: Some(2),
+                    },
+                ),
+            ]),
+        });
+        let (sender, mut started) = mpsc::unbounded_channel();
+
+        request(&scheduler, chatty, "chatty 1", &sender);
+        request(&scheduler, important, "important 1", &sender);
+        settle().await;
+        let (_, chatty_1) = started.try_recv().unwrap();
+        let (_, important_1) = started.try_recv().unwrap();
+
+        // Both sessions are taken: the next requests wait, in this order.
+        request(&scheduler, chatty, "chatty 2", &sender);
+        request(&scheduler, budgeted, "budgeted 1", &sender);
+        request(&scheduler, important, "important 2", &sender);
+        request(&scheduler, budgeted, "budgeted 2", &sender);
+        settle().await;
+        assert!(started.try_recv().is_err());
+        assert_eq!(gauge("scheduler_queue_depth", chatty), "1");
+        assert_eq!(gauge("scheduler_queue_depth", budgeted), "2");
+        assert_eq!(gauge("scheduler_in_flight", important), "1");
+
+        // The highest priority drains first, then the oldest request.
+        drop(chatty_1);
+        settle().await;
+        let (name, important_2) = started.try_recv().unwrap();
+        assert_eq!(name, "important 2");
+        assert!(started.try_recv().is_err());
+
+        drop(important_1);
+        settle().await;
+        let (name, budgeted_1) = started.try_recv().unwrap();
+        assert_eq!(name, "budgeted 1");
+
+        // Sessions held by a Bonsai session are freed once it finishes.
+        important_2.hold_for("session");
+        settle().await;
+        assert!(started.try_recv().is_err());
+        scheduler.finish("session");
+        settle().await;
+        let (name, budgeted_2) = started.try_recv().unwrap();
+        assert_eq!(name, "budgeted 2");
+
+        // The budget of the day is spent, and the chatty image waits on.
+        assert_eq!(
+            scheduler.acquire(budgeted).await.unwrap_err(),
+            IntakeClosed::BudgetSpent { image_id: budgeted }
+        );
+        assert!(started.try_recv().is_err());
+
+        drop(budgeted_1);
+        settle().await;
+        let (name, chatty_2) = started.try_recv().unwrap();
+        assert_eq!(name, "chatty 2");
+
+        // The chatty image is at its own limit, though a session is free.
+        drop(budgeted_2);
+        assert_eq!(
+            scheduler.try_acquire(chatty).unwrap_err(),
+            IntakeClosed::Busy { image_id: chatty }
+        );
+        let important_3 = scheduler.try_acquire(important).unwrap();
+        assert_eq!(gauge("scheduler_in_flight", chatty), "1");
+        assert_eq!(gauge("scheduler_in_flight", important), "1");
+        assert_eq!(gauge("scheduler_queue_depth", chatty), "0");
+        drop((chatty_2, important_3));
+    }
+
+    #[tokio::test]
+    async fn requests_are_not_held_back_by_default() {
+        let scheduler = Scheduler::default();
+        let image_id = H256::repeat_byte(0xa4);
+        let slots: Vec<_> = (0..100)
+            .map(|_| scheduler.try_acquire(image_id).unwrap())
+            .collect();
+        assert_eq!(gauge("scheduler_in_flight", image_id), "100");
+        drop(slots);
+        assert_eq!(gauge("scheduler_in_flight", image_id), "0");
+    }
+
+    #[tokio::test]
+    async fn requests_given_up_on_leave_the_queue() {
+        let image_id = H256::repeat_byte(0xa5);
+        let scheduler = Scheduler::new(SchedulerConfig {
+            max_concurrent_sessions: Some(1),
+            images: HashMap::new(),
+        });
+        let slot = scheduler.try_acquire(image_id).unwrap();
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.acquire(image_id).await }
+        });
+        settle().await;
+        assert_eq!(gauge("scheduler_queue_depth", image_id), "1");
+        waiting.abort();
+        settle().await;
+        assert_eq!(gauge("scheduler_queue_depth", image_id), "0");
+
+        drop(slot);
+        scheduler.try_acquire(image_id).unwrap();
+    }
+
+    #[test]
+    fn scheduler_settings_are_read_from_the_config_file() {
+        let file = parse_config_file(
+            r#"
+            [scheduler]
+            max_concurrent_sessions = 8
+
+            [scheduler.images."0x2222222222222222222222222222222222222222222222222222222222222222"]
+            max_concurrent = 2
+            priority = 10
+            daily_budget = 500
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.scheduler,
+            Some(SchedulerConfig {
+                max_concurrent_sessions: Some(8),
+                images: HashMap::from([(
+                    H256::repeat_byte(0x22),
+                    ImageSchedule {
+                        max_concurrent: Some(2),
+                        priority: 10,
+                        daily_budget: Some(500),
+                    },
+                )]),
+            })
+        );
+
+        for contents in [
+            "[scheduler]\nmax_concurrent_sessions = 0",
+            "[scheduler.images.\"0x2222222222222222222222222222222222222222222222222222222222222222\"]\nmax_concurrent = 0",
+            "[scheduler.images.\"0x22\"]\npriority = 1",
+            "[scheduler]\nqueue = \"fifo\"",
+        ] {
+            assert!(parse_config_file(contents).is_err(), "{contents}");
+        }
+    }
+}
@@ -0,0 +1,168 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ethers::types::U256;
+    use serde_json::json;
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use crate::{
+        event_channel,
+        events::RelayerEvent,
+        sdk::utils,
+        uploader::completed_proofs::signer_pool::{SignerClientError, SignerPool},
+        EthersClientConfig, RelayError, WalletKeyIdentifier, DEFAULT_ETH_NODE_TIMEOUT,
+    };
+
+    /// Not an Anvil account key.
+    const PRIVATE_KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    /// Key of the tenth Anvil account.
+    const ANVIL_KEY: &str = "2a871d0798f97d79848a013d4936a73bf4cc922c825d33c1cf7073dff6d409c6";
+
+    fn client_config(chain_id: u64, keys: &[&str]) -> EthersClientConfig {
+        EthersClientConfig::new(
+            "ws://127.0.0.1:9".to_string(),
+            chain_id,
+            keys[0].parse().unwrap(),
+            1,
+            Duration::from_millis(10),
+            DEFAULT_ETH_NODE_TIMEOUT,
+        )
+        .with_additional_wallet_keys(keys[1..].iter().map(|key| key.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn keys_are_assigned_the_fewest_transactions_in_flight_in_turn() {
+        let pool = SignerPool::new(&client_config(5, &[PRIVATE_KEY, ANVIL_KEY, PRIVATE_KEY]));
+        let first = pool.assign(&[]).unwrap();
+        let second = pool.assign(&[]).unwrap();
+        let third = pool.assign(&[]).unwrap();
+        assert_eq!([first.index(), second.index(), third.index()], [0, 1, 2]);
+        assert_eq!(pool.in_flight(), vec![1, 1, 1]);
+
+        // A key whose transaction is confirmed is assigned the next one.
+        drop(second);
+        let next = pool.assign(&[]).unwrap();
+        assert_eq!(next.index(), 1);
+        drop(next);
+        assert_eq!(pool.in_flight(), vec![1, 0, 1]);
+
+        // Skipped keys, e.g. with a low balance, are not assigned any.
+        let next = pool.assign(&[1]).unwrap();
+        assert_eq!(next.index(), 2);
+        assert!(pool.assign(&[0, 1, 2]).is_none());
+    }
+
+    #[tokio::test]
+    async fn anvil_keys_are_refused_outside_of_anvil() {
+        assert!(WalletKeyIdentifier::try_from(ANVIL_KEY.to_string())
+            .unwrap()
+            .is_anvil_key());
+        assert!(!WalletKeyIdentifier::try_from(PRIVATE_KEY.to_string())
+            .unwrap()
+            .is_anvil_key());
+        assert!(!WalletKeyIdentifier::Kms("alias/relayer".to_string()).is_anvil_key());
+
+        // Every key is checked, not only the wallet key.
+        let err = SignerPool::connect(
+            &client_config(1, &[PRIVATE_KEY, ANVIL_KEY]),
+            &[],
+            U256::zero(),
+        )
+        .await
+        .err()
+        .unwrap();
+        let RelayError::ConfigError(message) = err else {
+            panic!("expected a config error, got {err}");
+        };
+        assert!(message.contains("Key 1 of chain 1"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn failing_signers_are_removed_from_rotation() {
+        let anvil = utils::get_anvil();
+        let node_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap();
+
+        // A remote signer holding no account can't sign.
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "result": [],
+            })))
+            .mount(&server)
+            .await;
+        let client_config = node_config
+            .with_additional_wallet_keys(vec![WalletKeyIdentifier::Remote(server.uri())]);
+        let pool = SignerPool::connect(&client_config, &[], U256::zero())
+            .await
+            .unwrap();
+        let (events, mut receiver) = event_channel(8);
+
+        let first = pool.assign(&[]).unwrap();
+        assert_eq!(first.index(), 0);
+        first.client().await.unwrap();
+        let second = pool.assign(&[]).unwrap();
+        assert_eq!(second.index(), 1);
+        let Err(SignerClientError::Key(err)) = second.client().await else {
+            panic!("expected the remote signer to fail");
+        };
+        second.remove(&err, &Some(events));
+        drop(second);
+        assert_eq!(pool.active(), 1);
+        match receiver.recv().await.unwrap() {
+            RelayerEvent::SignerRemoved {
+                chain_id,
+                signer,
+                remaining,
+                ..
+            } => {
+                assert_eq!(chain_id, client_config.eth_chain_id);
+                assert_eq!(signer, None);
+                assert_eq!(remaining, 1);
+            }
+            event => panic!("unexpected event {event:?}"),
+        }
+
+        // The remaining key is assigned all the transactions.
+        assert_eq!(pool.assign(&[]).unwrap().index(), 0);
+        assert!(pool.assign(&[0]).is_none());
+    }
+
+    #[tokio::test]
+    async fn nodes_of_another_chain_are_refused() {
+        let anvil = utils::get_anvil();
+        let mut client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_json_rpc_batch(true);
+        client_config.eth_chain_id = 1;
+        client_config.wallet_key_identifier = PRIVATE_KEY.parse().unwrap();
+
+        let err = SignerPool::connect(&client_config, &[], U256::zero())
+            .await
+            .err()
+            .unwrap();
+        let RelayError::ConfigError(message) = err else {
+            panic!("expected a config error, got {err}");
+        };
+        assert!(message.contains("not the configured chain 1"), "{message}");
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+    use ark_ec::AffineRepr;
+    use ark_ff::{Field, PrimeField};
+    use bonsai_sdk::alpha::responses::SnarkProof;
+    use ethers::types::U256;
+
+    use crate::uploader::completed_proofs::snark::validate_snark_proof;
+
+    fn to_hex(value: Fq) -> String {
+        format!("{:x}", U256(value.into_bigint().0))
+    }
+
+    fn g1(point: G1Affine) -> Vec<String> {
+        vec![to_hex(point.x), to_hex(point.y)]
+    }
+
+    fn fq2(value: Fq2) -> Vec<String> {
+        vec![to_hex(value.c1), to_hex(value.c0)]
+    }
+
+    fn proof(a: G1Affine, b: G2Affine, c: G1Affine) -> SnarkProof {
+        SnarkProof {
+            a: g1(a),
+            b: vec![fq2(b.x), fq2(b.y)],
+            c: g1(c),
+            public: vec![],
+        }
+    }
+
+    fn validation_error(proof: &SnarkProof) -> String {
+        validate_snark_proof(proof)
+            .expect_err("proof should be invalid")
+            .to_string()
+    }
+
+    #[test]
+    fn valid_proof_points() {
+        let g1 = G1Affine::generator();
+        let g2 = G2Affine::generator();
+        validate_snark_proof(&proof(g1, g2, g1)).unwrap();
+    }
+
+    #[test]
+    fn truncated_coordinate() {
+        let g1 = G1Affine::generator();
+        let mut proof = proof(g1, G2Affine::generator(), g1);
+        proof.c[1].pop();
+        assert_eq!(
+            validation_error(&proof),
+            "proof point c is not on the BN254 curve"
+        );
+    }
+
+    #[test]
+    fn coordinate_above_modulus() {
+        let g1 = G1Affine::generator();
+        let mut proof = proof(g1, G2Affine::generator(), g1);
+        proof.a[0] = format!("{:x}", U256::MAX);
+        assert_eq!(
+            validation_error(&proof),
+            "proof component a.x is not below the BN254 base field modulus"
+        );
+        proof.a[0] = "not hex".to_string();
+        assert!(validation_error(&proof).starts_with("proof component a.x is not a valid uint256"));
+    }
+
+    #[test]
+    fn g2_point_off_curve() {
+        let g1 = G1Affine::generator();
+        let g2 = G2Affine::generator();
+        let off_curve = G2Affine::new_unchecked(g2.x, g2.y.double());
+        assert_eq!(
+            validation_error(&proof(g1, off_curve, g1)),
+            "proof point b is not on the BN254 twist curve"
+        );
+    }
+
+    #[test]
+    fn g2_point_outside_subgroup() {
+        // Points of the twist are almost never in the G2 subgroup, given its
+        // large cofactor.
+        let outside_subgroup = (1u64..)
+            .map(|x| Fq2::new(Fq::from(x), Fq::ONE))
+            .find_map(|x| G2Affine::get_point_from_x_unchecked(x, false))
+            .unwrap();
+        assert!(!outside_subgroup.is_in_correct_subgroup_assuming_on_curve());
+
+        let g1 = G1Affine::generator();
+        assert_eq!(
+            validation_error(&proof(g1, outside_subgroup, g1)),
+            "proof point b is not in the BN254 G2 subgroup"
+        );
+    }
+}
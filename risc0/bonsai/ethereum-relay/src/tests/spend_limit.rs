@@ -0,0 +1,355 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Instant};
+
+    use axum::{
+        body::{Body, HttpBody},
+        http::{Request, StatusCode},
+        Router,
+    };
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::{
+        alpha::SessionId,
+        alpha_async::{get_client_from_parts, RoundRobinBonsaiClient},
+    };
+    use ethers::{
+        types::{Address, Bytes, TransactionReceipt, H256, U256},
+        utils::parse_ether,
+    };
+    use hyper::service::Service;
+    use serde_json::{json, Value};
+    use tokio::sync::Notify;
+
+    use crate::{
+        alerts::Alert,
+        api::{
+            admin::ADMIN_KEY_HEADER,
+            callback_request::CallbackRequestState,
+            limits::{ApiLimits, DEFAULT_MAX_REQUEST_BODY_SIZE},
+            routes::{ADMIN_SPEND_LIMIT_RESUME_ROUTE, ADMIN_SPEND_LIMIT_ROUTE, READY_ROUTE},
+            server::app,
+            state::ApiState,
+        },
+        event_db::EventDb,
+        events::{self, event_channel, RelayerEvent},
+        intake::Intake,
+        spend::{receipt_cost, Spend, SpendLimit, SPEND_WINDOW},
+        storage::{
+            in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState, Storage,
+        },
+    };
+
+    const ADMIN_KEY: &str = "secret";
+
+    fn ether(amount: &str) -> U256 {
+        parse_ether(amount).unwrap()
+    }
+
+    async fn test_app(intake: Intake, spend_limit: SpendLimit) -> Router {
+        let bonsai_client = get_client_from_parts("http://127.0.0.1:9".to_string(), String::new())
+            .await
+            .unwrap();
+        app(ApiState {
+            bonsai_url: "http://127.0.0.1:9".to_string(),
+            relays: Vec::new(),
+            storage: InMemoryStorage::default(),
+            notifier: Arc::new(Notify::new()),
+            complete_proof_notifiers: HashMap::new(),
+            events: None,
+            submissions: None,
+            bonsai_client: Arc::new(RoundRobinBonsaiClient::from(bonsai_client)),
+            images: Arc::from(Vec::new()),
+            admin_api_key: Some(ADMIN_KEY.to_string()),
+            limits: ApiLimits::new(None, DEFAULT_MAX_REQUEST_BODY_SIZE),
+            rest_api_docs: false,
+            intake,
+            spend_limit,
+        })
+    }
+
+    async fn request(
+        app: &mut Router,
+        method: &str,
+        route: &str,
+        body: Body,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(route)
+            .header(ADMIN_KEY_HEADER, ADMIN_KEY)
+            .header("content-type", "application/json")
+            .body(body)
+            .unwrap();
+        let response = app.call(request).await.unwrap();
+        let status = response.status();
+        let mut body = Vec::new();
+        let mut response = response.into_body();
+        while let Some(chunk) = response.data().await {
+            body.extend_from_slice(&chunk.unwrap());
+        }
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[test]
+    fn spend_limit_holds_back_once_reached() {
+        let (sender, mut receiver) = event_channel(8);
+        let spend_limit = SpendLimit::new(Some(ether("1"))).with_events(Some(sender));
+        spend_limit.record(1, Address::zero(), H256::repeat_byte(1), ether("0.6"));
+        assert!(!spend_limit.is_limited(1));
+        assert!(receiver.try_recv().is_err());
+
+        // The spends of other chains do not count.
+        spend_limit.record(2, Address::zero(), H256::repeat_byte(2), ether("0.6"));
+        assert!(!spend_limit.is_limited(1));
+        assert_eq!(spend_limit.spent(1), ether("0.6"));
+
+        spend_limit.record(1, Address::zero(), H256::repeat_byte(3), ether("0.4"));
+        assert!(spend_limit.is_limited(1));
+        assert!(!spend_limit.is_limited(2));
+        assert_eq!(spend_limit.spent(1), ether("1"));
+        assert_eq!(spend_limit.limited_chain_ids(), vec![1]);
+        let RelayerEvent::SpendLimitReached {
+            chain_id,
+            spent,
+            max_daily_spend,
+            ..
+        } = receiver.try_recv().unwrap()
+        else {
+            panic!("expected the spend limit to be reported");
+        };
+        assert_eq!(
+            (chain_id, spent, max_daily_spend),
+            (1, ether("1"), ether("1"))
+        );
+        // Reported once, until the spend drops below the limit.
+        assert!(spend_limit.is_limited(1));
+        assert!(receiver.try_recv().is_err());
+
+        spend_limit.set_max_daily_spend(Some(ether("2")));
+        assert!(!spend_limit.is_limited(1));
+        spend_limit.set_max_daily_spend(Some(ether("0.5")));
+        assert_eq!(spend_limit.limited_chain_ids(), vec![1, 2]);
+        spend_limit.set_max_daily_spend(None);
+        assert!(spend_limit.limited_chain_ids().is_empty());
+
+        spend_limit.set_max_daily_spend(Some(ether("1")));
+        assert!(spend_limit.is_limited(1));
+        spend_limit.resume();
+        assert!(!spend_limit.is_limited(1));
+        assert_eq!(spend_limit.spent(1), U256::zero());
+        assert!(!SpendLimit::default().is_limited(1));
+    }
+
+    #[test]
+    fn spend_is_the_gas_used_times_the_effective_gas_price() {
+        let receipt = TransactionReceipt {
+            gas_used: Some(21_000.into()),
+            effective_gas_price: Some(30_000_000_000u64.into()),
+            ..Default::default()
+        };
+        assert_eq!(receipt_cost(&receipt), ether("0.00063"));
+        assert_eq!(receipt_cost(&TransactionReceipt::default()), U256::zero());
+    }
+
+    #[test]
+    fn spends_of_the_window_survive_restarts() {
+        let db = EventDb::in_memory().unwrap();
+        let spend_limit = SpendLimit::new(Some(ether("1")))
+            .with_event_db(Some(db.clone()))
+            .unwrap();
+        spend_limit.record(1, Address::zero(), H256::repeat_byte(1), ether("1"));
+        spend_limit.record(2, Address::zero(), H256::repeat_byte(2), ether("0.5"));
+        assert!(spend_limit.is_limited(1));
+        // A spend older than the window no longer counts.
+        db.insert_spend(
+            H256::repeat_byte(3),
+            &Spend {
+                chain_id: 1,
+                spent_at: events::now() - SPEND_WINDOW.as_millis() as u64 - 1,
+                amount: ether("5"),
+            },
+        )
+        .unwrap();
+
+        let restarted = SpendLimit::new(Some(ether("1")))
+            .with_event_db(Some(db.clone()))
+            .unwrap();
+        assert_eq!(restarted.spent(1), ether("1"));
+        assert_eq!(restarted.spent(2), ether("0.5"));
+        assert_eq!(restarted.limited_chain_ids(), vec![1]);
+
+        // Resuming early forgets the recorded spends.
+        restarted.resume();
+        let restarted = SpendLimit::new(Some(ether("1")))
+            .with_event_db(Some(db))
+            .unwrap();
+        assert_eq!(restarted.spent(1), U256::zero());
+    }
+
+    #[test]
+    fn alerts_report_the_spend_limit() {
+        let event = RelayerEvent::SpendLimitReached {
+            timestamp: 1_690_891_200_000,
+            chain_id: 5,
+            spent: ether("1.05"),
+            max_daily_spend: ether("1"),
+        };
+        let alert = Alert::from_event(&event, &[Address::repeat_byte(1)]).unwrap();
+        assert_eq!(
+            serde_json::to_value(alert).unwrap(),
+            json!({
+                "level": "error",
+                "message": "Daily spend limit of 1.000000000000000000 ETH reached on chain 5, \
+                    1.050000000000000000 ETH spent over the last 24 hours: relay transactions \
+                    are held back",
+                "relay_addresses": [Address::repeat_byte(1)],
+                "tx_hash": null,
+                "timestamp": "2023-08-01T12:00:00.000Z",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn readiness_and_admin_routes_follow_the_spend_limit() {
+        let intake = Intake::default();
+        let spend_limit = SpendLimit::new(Some(ether("1")));
+        let mut app = test_app(intake.clone(), spend_limit.clone()).await;
+
+        let (status, ready) = request(&mut app, "GET", READY_ROUTE, Body::empty()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            ready,
+            json!({"ready": true, "draining": false, "spend_limited": []})
+        );
+
+        spend_limit.record(1, Address::zero(), H256::repeat_byte(1), ether("1.5"));
+        spend_limit.record(2, Address::zero(), H256::repeat_byte(2), ether("0.5"));
+        let (status, ready) = request(&mut app, "GET", READY_ROUTE, Body::empty()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            ready,
+            json!({"ready": false, "draining": false, "spend_limited": [1]})
+        );
+        let (status, limit) =
+            request(&mut app, "GET", ADMIN_SPEND_LIMIT_ROUTE, Body::empty()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            limit,
+            json!({
+                "max_daily_spend": "1.000000000000000000",
+                "chains": [
+                    {"chain_id": 1, "spent": "1.500000000000000000", "limited": true},
+                    {"chain_id": 2, "spent": "0.500000000000000000", "limited": false},
+                ],
+            })
+        );
+
+        // Raising the limit above the spend resumes the relay transactions.
+        let (status, limit) = request(
+            &mut app,
+            "POST",
+            ADMIN_SPEND_LIMIT_ROUTE,
+            Body::from(r#"{"max_daily_spend": "2"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(limit["chains"][0]["limited"], false);
+        assert!(!spend_limit.is_limited(1));
+        let (status, _) = request(
+            &mut app,
+            "POST",
+            ADMIN_SPEND_LIMIT_ROUTE,
+            Body::from(r#"{"max_daily_spend": "two"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        spend_limit.set_max_daily_spend(Some(ether("1")));
+        let (status, limit) = request(
+            &mut app,
+            "POST",
+            ADMIN_SPEND_LIMIT_RESUME_ROUTE,
+            Body::empty(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(limit["chains"][0]["spent"], "0.000000000000000000");
+        assert_eq!(limit["chains"][0]["limited"], false);
+
+        // Draining makes the relayer not ready either.
+        intake.drain();
+        let (status, ready) = request(&mut app, "GET", READY_ROUTE, Body::empty()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(ready["draining"], true);
+    }
+
+    #[tokio::test]
+    async fn spend_limited_requests_stay_queued_for_relaying() {
+        let storage = InMemoryStorage::default();
+        let proof_id = SessionId::new("session".to_string());
+        storage
+            .add_submitted_proof_request(ProofRequestInformation {
+                proof_request_id: proof_id.clone(),
+                chain_id: 1,
+                relay_contract_address: Address::repeat_byte(1),
+                callback_proof_request_event: CallbackRequestFilter {
+                    account: Address::repeat_byte(2),
+                    image_id: H256::repeat_byte(3).into(),
+                    input: Bytes::default(),
+                    callback_contract: Address::repeat_byte(2),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .unwrap();
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::SpendLimited)
+            .await
+            .unwrap();
+
+        let state = storage
+            .get_proof_request_state(proof_id.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            serde_json::to_value(CallbackRequestState::from(&state)).unwrap(),
+            "spend_limited"
+        );
+        assert_eq!(
+            storage.count_in_flight_requests().await.unwrap().relaying,
+            1
+        );
+        // Held back requests are queued again on restart.
+        let preparing = storage
+            .fetch_preparing_onchain_proof_requests(None)
+            .await
+            .unwrap();
+        assert_eq!(preparing.len(), 1);
+        storage
+            .transition_proof_request(proof_id.clone(), ProofRequestState::Completed)
+            .await
+            .unwrap();
+        assert!(storage
+            .transition_proof_request(proof_id, ProofRequestState::SpendLimited)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{transaction::eip2718::TypedTransaction, Transaction, U256, U64};
+
+    use crate::uploader::completed_proofs::relay::{bump_fee, bumped_replacement};
+
+    #[test]
+    fn bump_fee_rounds_up() {
+        assert_eq!(bump_fee(U256::from(100), 10), U256::from(110));
+        assert_eq!(bump_fee(U256::from(101), 10), U256::from(112));
+        assert_eq!(bump_fee(U256::from(100), 0), U256::from(100));
+    }
+
+    #[test]
+    fn replacement_keeps_nonce_and_bumps_fees() {
+        let tx = Transaction {
+            nonce: U256::from(7),
+            transaction_type: Some(U64::from(2)),
+            max_fee_per_gas: Some(U256::from(1000)),
+            max_priority_fee_per_gas: Some(U256::from(100)),
+            ..Default::default()
+        };
+        let TypedTransaction::Eip1559(replacement) = bumped_replacement(&tx, 10) else {
+            panic!("expected an EIP-1559 transaction");
+        };
+        assert_eq!(replacement.nonce, Some(U256::from(7)));
+        assert_eq!(replacement.max_fee_per_gas, Some(U256::from(1100)));
+        assert_eq!(replacement.max_priority_fee_per_gas, Some(U256::from(110)));
+
+        let legacy = Transaction {
+            nonce: U256::from(3),
+            gas_price: Some(U256::from(50)),
+            ..Default::default()
+        };
+        let replacement = bumped_replacement(&legacy, 20);
+        assert_eq!(replacement.nonce(), Some(&U256::from(3)));
+        assert_eq!(replacement.gas_price(), Some(U256::from(60)));
+    }
+}
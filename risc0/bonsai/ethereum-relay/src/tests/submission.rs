@@ -0,0 +1,157 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use ark_bn254::{Fq, G1Affine, G2Affine};
+    use ark_ec::AffineRepr;
+    use ark_ff::PrimeField;
+    use axum::http::StatusCode;
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use bonsai_sdk::alpha::{responses::SnarkProof, SessionId};
+    use ethers::types::{Address, H256, U256};
+
+    use crate::{
+        api::{error::Error, submission::validate_submission},
+        sdk::client::{ProofSubmission, SubmittedProof},
+        storage::{
+            in_memory::InMemoryStorage, ProofRequestInformation, ProofRequestState, Storage,
+        },
+    };
+
+    fn to_hex(value: Fq) -> String {
+        format!("{:x}", U256(value.into_bigint().0))
+    }
+
+    fn snark_proof() -> SnarkProof {
+        let g1 = G1Affine::generator();
+        let g2 = G2Affine::generator();
+        SnarkProof {
+            a: vec![to_hex(g1.x), to_hex(g1.y)],
+            b: vec![
+                vec![to_hex(g2.x.c1), to_hex(g2.x.c0)],
+                vec![to_hex(g2.y.c1), to_hex(g2.y.c0)],
+            ],
+            c: vec![to_hex(g1.x), to_hex(g1.y)],
+            public: vec![],
+        }
+    }
+
+    fn submission(proof: SubmittedProof) -> ProofSubmission {
+        ProofSubmission {
+            image_id: H256::repeat_byte(0x11).into(),
+            journal: b"journal".to_vec(),
+            post_state_digest: H256::repeat_byte(0x22).into(),
+            proof,
+            callback_contract: Address::repeat_byte(1),
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+            relay_address: None,
+            chain_id: None,
+        }
+    }
+
+    fn rejection(result: Result<impl std::fmt::Debug, Error>) -> String {
+        let err = result.expect_err("submission should be rejected");
+        assert_eq!(err.status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        err.to_string()
+    }
+
+    #[test]
+    fn accepts_allowed_snark_proof() {
+        let submission = submission(SubmittedProof::Snark(snark_proof()));
+        let auth =
+            validate_submission(&submission, &[H256::repeat_byte(0x11)], false, false).unwrap();
+        assert!(!auth.seal.is_empty());
+        assert_eq!(auth.post_state_digest, submission.post_state_digest);
+    }
+
+    #[test]
+    fn rejects_image_id_not_allowed() {
+        let submission = submission(SubmittedProof::Snark(snark_proof()));
+        let err = rejection(validate_submission(
+            &submission,
+            &[H256::repeat_byte(0x33)],
+            false,
+            false,
+        ));
+        assert!(err.contains("is not allowed"), "{err}");
+    }
+
+    #[test]
+    fn rejects_invalid_snark_proof() {
+        let mut proof = snark_proof();
+        proof.a.swap(0, 1);
+        let submission = submission(SubmittedProof::Snark(proof));
+        let err = rejection(validate_submission(&submission, &[], false, false));
+        assert!(
+            err.contains("proof point a is not on the BN254 curve"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn rejects_receipt_outside_dev_mode() {
+        let submission = submission(SubmittedProof::Receipt(vec![]));
+        let err = rejection(validate_submission(&submission, &[], false, false));
+        assert!(err.contains("only accepted in dev mode"), "{err}");
+
+        let err = rejection(validate_submission(&submission, &[], true, false));
+        assert!(err.contains("invalid receipt"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn submitted_request_is_preparing_onchain() {
+        let storage = InMemoryStorage::new();
+        let session_id = SessionId::new("submission".to_string());
+        storage
+            .add_submitted_proof_request(ProofRequestInformation {
+                proof_request_id: session_id.clone(),
+                chain_id: 11155111,
+                relay_contract_address: Address::repeat_byte(0x44),
+                callback_proof_request_event: CallbackRequestFilter {
+                    account: Address::default(),
+                    image_id: H256::repeat_byte(0x11).into(),
+                    input: Default::default(),
+                    callback_contract: Address::repeat_byte(1),
+                    function_selector: [0xab, 0xcd, 0xef, 0xab],
+                    gas_limit: 3000000,
+                },
+                created_at: Instant::now(),
+                attached_requests: Vec::new(),
+                deadline: None,
+            })
+            .await
+            .unwrap();
+
+        // Submitted proofs never go through Bonsai.
+        assert!(storage
+            .fetch_completed_bonsai_requests(None)
+            .await
+            .unwrap()
+            .is_empty());
+        let (_, state) = storage.get_request_state("submission").await.unwrap();
+        assert_eq!(state, ProofRequestState::PreparingOnchain);
+
+        let tx_hash = H256::repeat_byte(0xab);
+        storage
+            .transition_proof_request(session_id, ProofRequestState::CompletedOnchain(tx_hash))
+            .await
+            .unwrap();
+        let (_, state) = storage.get_request_state("submission").await.unwrap();
+        assert_eq!(state, ProofRequestState::CompletedOnchain(tx_hash));
+    }
+}
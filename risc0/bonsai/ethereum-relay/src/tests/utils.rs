@@ -41,6 +41,7 @@ pub(crate) mod tests {
             receipt_url: Some(format!("{}/fake/receipt/path", server.uri())),
             error_msg: None,
             state: None,
+            stats: None,
         };
 
         let receipt_data_response = Receipt {
@@ -120,8 +121,9 @@ pub(crate) mod tests {
 
         async fn process_event(
             &self,
+            _address: Address,
             event: CallbackRequestFilter,
-        ) -> Result<(), crate::api::error::Error> {
+        ) -> Result<String, crate::api::error::Error> {
             assert_eq!(event.account, self.expected_account);
             assert_eq!(H256::from(event.image_id), self.expected_image_id);
             assert_eq!(event.input, self.expected_input);
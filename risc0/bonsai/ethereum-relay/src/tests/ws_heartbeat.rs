@@ -0,0 +1,371 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use bonsai_ethereum_contracts::i_bonsai_relay::CallbackRequestFilter;
+    use ethers::{
+        contract::abigen,
+        providers::Middleware,
+        types::{Address, Bytes, H256},
+    };
+    use serde_json::Value;
+    use tokio::{
+        net::{TcpListener, TcpStream},
+        sync::{mpsc, watch},
+        task::{JoinHandle, JoinSet},
+    };
+
+    use crate::{
+        client_config::Heartbeat,
+        downloader::{
+            event_processor::EventProcessor,
+            proxy_callback_proof_request_stream::ProxyCallbackProofRequestStream,
+        },
+        metrics::Metrics,
+        sdk::utils,
+        EthNodeTransport, EthersClientConfig,
+    };
+
+    abigen!(Proxy, "../ethereum/out/ProxyTest.sol/Proxy.json");
+
+    /// Forwards the processed events to a channel.
+    struct ForwardingProcessor(mpsc::UnboundedSender<CallbackRequestFilter>);
+
+    #[async_trait::async_trait]
+    impl EventProcessor for ForwardingProcessor {
+        type Event = CallbackRequestFilter;
+
+        async fn process_event(
+            &self,
+            _address: Address,
+            event: CallbackRequestFilter,
+        ) -> Result<String, crate::api::error::Error> {
+            self.0.send(event).unwrap();
+            Ok(String::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn connection_without_new_blocks_is_stale() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_heartbeat(Duration::from_millis(100), Duration::from_millis(500));
+        let client = client_config.get_client().await.unwrap();
+
+        // Anvil only mines blocks on new transactions.
+        let heartbeat = tokio::time::timeout(
+            Duration::from_secs(5),
+            client_config.wait_for_stale_connection(&client),
+        )
+        .await
+        .expect("connection should be stale");
+        let Heartbeat::Stale(elapsed) = heartbeat else {
+            panic!("expected a stale connection, got {heartbeat:?}");
+        };
+        assert!(elapsed >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn connection_with_new_blocks_is_not_stale() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_heartbeat(Duration::from_millis(100), Duration::from_millis(500));
+        let client = client_config.get_client().await.unwrap();
+        let provider = utils::get_ws_provider(anvil.as_ref()).await.unwrap();
+        let miner = tokio::spawn(async move {
+            loop {
+                provider.request::<_, Value>("evm_mine", ()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        });
+
+        let stale = tokio::time::timeout(
+            Duration::from_secs(2),
+            client_config.wait_for_stale_connection(&client),
+        )
+        .await;
+        miner.abort();
+        assert!(stale.is_err(), "connection should not be stale");
+    }
+
+    #[tokio::test]
+    async fn zero_stale_timeout_disables_the_check() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_heartbeat(Duration::from_millis(100), Duration::ZERO);
+        let client = client_config.get_client().await.unwrap();
+
+        let stale = tokio::time::timeout(
+            Duration::from_secs(1),
+            client_config.wait_for_stale_connection(&client),
+        )
+        .await;
+        assert!(stale.is_err(), "connection should not be checked");
+    }
+
+    #[tokio::test]
+    async fn pings_without_pong_time_out() {
+        let anvil = utils::get_anvil();
+        let mut client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_ping(Duration::from_millis(100), Duration::from_millis(300));
+        // A node that completes the handshake and then never reads, so that
+        // its pongs are never sent.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        client_config.eth_node_url = format!("ws://{}", listener.local_addr().unwrap());
+        client_config.eth_chain_id = 98_002;
+        let silent_node = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let _socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        let ping_timeouts = || {
+            Metrics::global()
+                .ping_timeout
+                .with_label_values(&["98002"])
+                .get()
+        };
+        let ping_timeouts_before = ping_timeouts();
+        let provider = client_config.provider().await.unwrap();
+
+        let heartbeat = tokio::time::timeout(
+            Duration::from_secs(5),
+            client_config.wait_for_pong_timeout(&provider),
+        )
+        .await
+        .expect("pings should time out");
+        silent_node.abort();
+        assert_eq!(
+            heartbeat,
+            Heartbeat::PongTimeout(Duration::from_millis(300))
+        );
+        assert_eq!(ping_timeouts(), ping_timeouts_before + 1);
+    }
+
+    #[tokio::test]
+    async fn answered_pings_do_not_time_out() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_ping(Duration::from_millis(100), Duration::from_millis(300));
+        let provider = client_config.provider().await.unwrap();
+        assert!(matches!(
+            provider.as_ref().inner(),
+            EthNodeTransport::PingingWs(_)
+        ));
+
+        let timeout = tokio::time::timeout(
+            Duration::from_secs(1),
+            client_config.wait_for_pong_timeout(&provider),
+        )
+        .await;
+        assert!(timeout.is_err(), "pings should be answered");
+        // The pinged connection still serves the requests.
+        provider.get_block_number().await.unwrap();
+
+        // Zero disables the pings.
+        let client_config = client_config.with_ws_ping(Duration::ZERO, Duration::ZERO);
+        let provider = client_config.provider().await.unwrap();
+        assert!(matches!(provider.as_ref().inner(), EthNodeTransport::Ws(_)));
+        let timeout = tokio::time::timeout(
+            Duration::from_millis(300),
+            client_config.wait_for_pong_timeout(&provider),
+        )
+        .await;
+        assert!(timeout.is_err(), "pings should be disabled");
+    }
+
+    #[tokio::test]
+    async fn events_are_backfilled_once_across_stale_reconnects() {
+        let anvil = utils::get_anvil();
+        let client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap()
+            .with_ws_heartbeat(Duration::from_millis(100), Duration::from_millis(500));
+        let chain_id = client_config.eth_chain_id.to_string();
+        let reconnects = || {
+            Metrics::global()
+                .stale_connection_reconnects
+                .with_label_values(&[&chain_id])
+                .get()
+        };
+        let reconnects_before = reconnects();
+        let client = Arc::new(client_config.get_client().await.unwrap());
+        let proxy = Proxy::deploy(client, ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stream = ProxyCallbackProofRequestStream::new(
+            client_config,
+            vec![proxy.address()],
+            watch::channel(vec![]).1,
+            ForwardingProcessor(sender),
+            None,
+            vec![],
+        );
+        let stream = tokio::spawn(stream.run());
+        // The event is emitted before the logs are subscribed to, and is
+        // backfilled once subscribed.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        proxy
+            .request_callback(
+                [7; 32],
+                Bytes::from(b"hello world".to_vec()),
+                Address::random(),
+                [0xab, 0xcd, 0xef, 0xab],
+                3_000_000,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+
+        let event = tokio::time::timeout(Duration::from_secs(15), receiver.recv())
+            .await
+            .expect("callback request should be backfilled")
+            .unwrap();
+        assert_eq!(H256::from(event.image_id), H256::from([7; 32]));
+
+        // No new block is mined, so the connection is made again, without
+        // backfilling the event twice.
+        tokio::time::timeout(Duration::from_secs(15), async {
+            while reconnects() < reconnects_before + 2 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .expect("stale connection should be made again");
+        assert!(receiver.try_recv().is_err());
+        stream.abort();
+    }
+
+    /// Forwards the TCP connections made to `listener` to `target` until
+    /// aborted, which closes them as a dropped connection does.
+    fn forward(listener: TcpListener, target: String) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut connections = JoinSet::new();
+            loop {
+                let (mut inbound, _) = listener.accept().await.unwrap();
+                let target = target.clone();
+                connections.spawn(async move {
+                    let mut outbound = TcpStream::connect(target).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+            }
+        })
+    }
+
+    /// Emits a callback request for the image ID `image_id`.
+    async fn request_callback<M: Middleware + 'static>(proxy: &Proxy<M>, image_id: u8) {
+        proxy
+            .request_callback(
+                [image_id; 32],
+                Bytes::from(b"hello world".to_vec()),
+                Address::random(),
+                [0xab, 0xcd, 0xef, 0xab],
+                3_000_000,
+            )
+            .send()
+            .await
+            .unwrap()
+            .await
+            .unwrap();
+    }
+
+    /// Waits for the next processed callback request, returning its image ID.
+    async fn next_image_id(receiver: &mut mpsc::UnboundedReceiver<CallbackRequestFilter>) -> H256 {
+        let event = tokio::time::timeout(Duration::from_secs(30), receiver.recv())
+            .await
+            .expect("callback request should be processed")
+            .unwrap();
+        H256::from(event.image_id)
+    }
+
+    #[tokio::test]
+    async fn events_emitted_while_disconnected_are_processed_once() {
+        let anvil = utils::get_anvil();
+        let node_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .unwrap();
+        let node = utils::get_ws_provider_endpoint(anvil.as_ref())
+            .await
+            .unwrap()
+            .trim_start_matches("ws://")
+            .to_string();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut forwarder = forward(listener, node.clone());
+        // Anvil only mines blocks on new transactions, so the connection is
+        // not to be found stale.
+        let client_config = EthersClientConfig {
+            eth_node_url: format!("ws://{address}"),
+            ..node_config.clone()
+        }
+        .with_ws_heartbeat(Duration::from_millis(100), Duration::from_secs(60));
+
+        // The callback requests are emitted straight to the node.
+        let client = Arc::new(node_config.get_client().await.unwrap());
+        let proxy = Proxy::deploy(client, ())
+            .expect("should be able to deploy the Proxy contract")
+            .send()
+            .await
+            .expect("deployment should succeed");
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let stream = ProxyCallbackProofRequestStream::new(
+            client_config,
+            vec![proxy.address()],
+            watch::channel(vec![]).1,
+            ForwardingProcessor(sender),
+            None,
+            vec![],
+        );
+        let stream = tokio::spawn(stream.run());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        request_callback(&proxy, 1).await;
+        assert_eq!(next_image_id(&mut receiver).await, H256::from([1; 32]));
+
+        // The connection drops, and the request emitted meanwhile is
+        // backfilled once the connection is made again.
+        forwarder.abort();
+        let _ = forwarder.await;
+        request_callback(&proxy, 2).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        forwarder = forward(TcpListener::bind(address).await.unwrap(), node);
+        assert_eq!(next_image_id(&mut receiver).await, H256::from([2; 32]));
+
+        // New requests are still received, and none is processed twice.
+        request_callback(&proxy, 3).await;
+        assert_eq!(next_image_id(&mut receiver).await, H256::from([3; 32]));
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(receiver.try_recv().is_err());
+        stream.abort();
+        forwarder.abort();
+    }
+}
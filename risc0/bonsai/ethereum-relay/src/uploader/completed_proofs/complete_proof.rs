@@ -12,32 +12,132 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{sync::Arc, time::Instant};
+
+use anyhow::Context;
 use bonsai_ethereum_contracts::i_bonsai_relay::{
-    Callback, CallbackAuthorization, CallbackRequestFilter,
+    Callback, CallbackAuthorization, CallbackRequestFilter, InvokeCallbacksCall,
 };
 use bonsai_sdk::{
-    alpha::{Client, SessionId},
-    alpha_async::{download, session_status},
+    alpha::{responses::SnarkProof, SessionId},
+    alpha_async::{download, RoundRobinBonsaiClient},
+};
+use ethers::{
+    abi::AbiEncode,
+    types::{Address, Bytes, Selector},
 };
-use ethers::abi;
-use risc0_zkvm::Receipt;
+use risc0_zkvm::{sha::Digest, Receipt};
 
-use super::snark::tokenize_snark_proof;
-use crate::{api, uploader::completed_proofs::error::CompleteProofError};
+use super::snark::{proof_to_calldata, validate_snark_proof};
+use crate::{
+    api, bonsai_transport::BonsaiTransport, metrics::Metrics, storage::AttachedRequest,
+    uploader::completed_proofs::error::CompleteProofError,
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct CompleteProof {
     pub bonsai_proof_id: SessionId,
-    pub ethereum_callback: Callback,
+    /// Relay contract the callbacks are invoked through.
+    pub relay_contract_address: Address,
+    /// Callback of the request that created the session, followed by the
+    /// callbacks of the identical requests attached to it.
+    pub ethereum_callbacks: Vec<Callback>,
+    /// Journal of the proof, passed to each callback.
+    pub journal: Vec<u8>,
+}
+
+/// Whether `journal` starts with any of `filters`, e.g. the selector of a
+/// proposal type. Every journal matches if there are no filters.
+pub(crate) fn journal_matches(filters: &[Vec<u8>], journal: &[u8]) -> bool {
+    filters.is_empty() || filters.iter().any(|filter| journal.starts_with(filter))
+}
+
+/// Builds the callback invoking `callback_request` with the given journal.
+pub(crate) fn build_callback(
+    callback_request: &CallbackRequestFilter,
+    journal: &[u8],
+    auth: CallbackAuthorization,
+) -> Callback {
+    let payload = [
+        callback_request.function_selector.as_slice(),
+        journal,
+        callback_request.image_id.as_slice(),
+    ]
+    .concat();
+
+    Callback {
+        auth,
+        payload: payload.into(),
+        gas_limit: callback_request.gas_limit,
+        callback_contract: callback_request.callback_contract,
+    }
+}
+
+/// Callback invoked by the relay transaction of a [crate::Relayer::dry_run].
+#[derive(Clone, Debug)]
+pub struct DryRunCallback {
+    /// The Ethereum address of the callback.
+    pub callback_contract: Address,
+    /// The function selector of the callback.
+    pub function_selector: Selector,
+    /// The gas limit of the callback.
+    pub gas_limit: u64,
+    /// Digest of the post-state of the guest execution, from the metadata of
+    /// its receipt.
+    pub post_state_digest: Digest,
 }
 
+/// Encodes the `invokeCallbacks` call of the relay contract invoking
+/// `callback` with the journal, authorized by the SNARK proof, as the relay
+/// transaction does. In dev mode, the seal is left empty.
+pub(crate) fn relay_calldata(
+    proof: &SnarkProof,
+    journal: &[u8],
+    image_id: Digest,
+    callback: &DryRunCallback,
+    dev_mode: bool,
+    skip_proof_validation: bool,
+) -> anyhow::Result<Bytes> {
+    if !dev_mode && !skip_proof_validation {
+        validate_snark_proof(proof).context("invalid SNARK proof")?;
+    }
+    let seal = match dev_mode {
+        true => Bytes::new(),
+        false => proof_to_calldata(proof).context("invalid SNARK proof")?,
+    };
+    let callback_request = CallbackRequestFilter {
+        account: callback.callback_contract,
+        image_id: image_id.into(),
+        input: Default::default(),
+        callback_contract: callback.callback_contract,
+        function_selector: callback.function_selector,
+        gas_limit: callback.gas_limit,
+    };
+    let auth = CallbackAuthorization {
+        seal,
+        post_state_digest: callback.post_state_digest.into(),
+    };
+    let call = InvokeCallbacksCall {
+        callbacks: vec![build_callback(&callback_request, journal, auth)],
+    };
+    Ok(call.encode().into())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn get_complete_proof(
-    bonsai_client: Client,
+    bonsai_client: RoundRobinBonsaiClient,
+    transport: Arc<dyn BonsaiTransport>,
     dev_mode: bool,
+    skip_proof_validation: bool,
     bonsai_proof_id: SessionId,
+    chain_id: u64,
+    relay_contract_address: Address,
     callback_request: CallbackRequestFilter,
+    attached_requests: Vec<AttachedRequest>,
+    created_at: Instant,
 ) -> Result<CompleteProof, CompleteProofError> {
-    let bonsai_response = session_status(bonsai_client.clone(), bonsai_proof_id.clone())
+    let bonsai_response = transport
+        .get_session(bonsai_proof_id.clone())
         .await
         .map_err(|err| CompleteProofError::ClientAPI {
             source: api::error::Error::Bonsai(err),
@@ -53,6 +153,8 @@ pub(crate) async fn get_complete_proof(
 
     let receipt_url = receipt_url_result?;
 
+    // Receipts and SNARKs are requested from the primary instance.
+    let bonsai_client = bonsai_client.primary().clone();
     let receipt_buf = download(bonsai_client.clone(), receipt_url)
         .await
         .map_err(|err| CompleteProofError::ClientAPI {
@@ -60,18 +162,34 @@ pub(crate) async fn get_complete_proof(
             id: bonsai_proof_id.clone(),
         })?;
 
+    let proving_time = created_at.elapsed();
+    let snark_start = Instant::now();
     let snark_id =
         super::snark::get_snark_id(bonsai_client.clone(), bonsai_proof_id.clone()).await?;
     let snark_proof =
         super::snark::get_snark_proof(bonsai_client.clone(), snark_id, bonsai_proof_id.clone())
             .await?;
+    Metrics::global().record_proving_stats(
+        chain_id,
+        relay_contract_address,
+        &hex::encode(callback_request.image_id),
+        bonsai_response.stats.as_ref(),
+        proving_time,
+        snark_start.elapsed(),
+    );
+    if !dev_mode && !skip_proof_validation {
+        validate_snark_proof(&snark_proof).map_err(|err| CompleteProofError::InvalidSnark {
+            id: bonsai_proof_id.clone(),
+            reason: format!("{err:#}"),
+        })?;
+    }
     let seal = match dev_mode {
         true => vec![],
-        false => abi::encode(&[tokenize_snark_proof(&snark_proof).map_err(|_| {
-            CompleteProofError::SnarkFailed {
+        false => proof_to_calldata(&snark_proof)
+            .map_err(|_| CompleteProofError::SnarkFailed {
                 id: bonsai_proof_id.clone(),
-            }
-        })?]),
+            })?
+            .to_vec(),
     };
 
     let receipt: Receipt =
@@ -91,27 +209,25 @@ pub(crate) async fn get_complete_proof(
         true => [0u8; 32],
     };
 
-    let payload = [
-        callback_request.function_selector.as_slice(),
-        receipt.journal.as_slice(),
-        callback_request.image_id.as_slice(),
-    ]
-    .concat();
-    let gas_limit = callback_request.gas_limit;
-
     let auth = CallbackAuthorization {
         seal: seal.into(),
         post_state_digest,
     };
-    let ethereum_callback = Callback {
-        auth,
-        payload: payload.into(),
-        gas_limit,
-        callback_contract: callback_request.callback_contract,
-    };
+    // Each request keeps its own callback target, so that a failing callback
+    // doesn't affect the others sharing the proof.
+    let ethereum_callbacks = std::iter::once(&callback_request)
+        .chain(
+            attached_requests
+                .iter()
+                .map(|request| &request.callback_proof_request_event),
+        )
+        .map(|request| build_callback(request, &receipt.journal, auth.clone()))
+        .collect();
 
     Ok(CompleteProof {
         bonsai_proof_id,
-        ethereum_callback,
+        relay_contract_address,
+        ethereum_callbacks,
+        journal: receipt.journal,
     })
 }
@@ -31,6 +31,13 @@ pub(crate) enum BonsaiCompleteProofManagerError {
     },
     #[error("Ethers Client failed")]
     EthersClient(#[from] anyhow::Error),
+    /// The key signing the relay transactions failed, e.g. as its KMS key was
+    /// disabled, rather than the Ethereum node.
+    #[error("Signer failed")]
+    Signer {
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
     #[error("Failed to operate on storage")]
     Storage {
         #[source]
@@ -41,12 +48,16 @@ pub(crate) enum BonsaiCompleteProofManagerError {
     CompleteProof(#[from] CompleteProofError),
     #[error("Join Error")]
     JoinHandle(#[from] JoinError),
+    #[error("Failed to send transaction through Safe: {0}")]
+    Safe(String),
     #[error("Failed to confirm transaction on chain")]
     Confirmation {
         #[source]
         source: ProviderError,
         tx_hash: H256,
     },
+    #[error("Transaction {tx_hash:?} reverted: {reason}")]
+    Reverted { tx_hash: H256, reason: String },
 }
 
 impl BonsaiCompleteProofManagerError {
@@ -57,6 +68,13 @@ impl BonsaiCompleteProofManagerError {
             _ => None,
         }
     }
+
+    pub(crate) fn tx_hash(&self) -> Option<H256> {
+        match self {
+            Self::Confirmation { tx_hash, .. } | Self::Reverted { tx_hash, .. } => Some(*tx_hash),
+            _ => None,
+        }
+    }
 }
 
 // Cannot use async functions that return snafu errors with tokio::spawn cleanly
@@ -78,6 +96,8 @@ pub(crate) enum CompleteProofError {
     SnarkUnknown { id: ProofID },
     /// invalid receipt
     InvalidReceipt { id: ProofID },
+    /// bonsai snark proof is invalid: {reason}
+    InvalidSnark { id: ProofID, reason: String },
 }
 
 impl CompleteProofError {
@@ -89,6 +109,7 @@ impl CompleteProofError {
             | CompleteProofError::SnarkUnknown { id }
             | CompleteProofError::ReceiptNotFound { id }
             | CompleteProofError::InvalidReceipt { id }
+            | CompleteProofError::InvalidSnark { id, .. }
             | CompleteProofError::ClientAPI { id, .. } => id,
         }
     }
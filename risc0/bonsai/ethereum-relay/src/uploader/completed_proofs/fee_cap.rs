@@ -0,0 +1,45 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use ethers::{providers::Middleware, types::U256};
+
+/// Time between two checks of the network fees while relay transactions are
+/// held back by a [FeeCap].
+pub const FEE_CAP_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default number of completed proofs held back while the network fees
+/// exceed a [FeeCap].
+pub const DEFAULT_FEE_QUEUE_DEPTH: usize = 100;
+
+/// Cap on the fees of the relay transactions. While the network fees exceed
+/// it, completed proofs are held back rather than relayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FeeCap {
+    /// Maximum fee per gas, in wei.
+    pub max_fee_per_gas: U256,
+    /// Number of completed proofs held back while the fees exceed the cap.
+    /// Further proofs are dropped, failing their requests.
+    pub queue_depth: usize,
+}
+
+/// Current fee per gas of the network: the EIP-1559 max fee per gas, or the
+/// gas price on networks without EIP-1559.
+pub(crate) async fn current_fee_per_gas<M: Middleware>(client: &M) -> Result<U256, M::Error> {
+    match client.estimate_eip1559_fees(None).await {
+        Ok((max_fee_per_gas, _)) => Ok(max_fee_per_gas),
+        Err(_) => client.get_gas_price().await,
+    }
+}
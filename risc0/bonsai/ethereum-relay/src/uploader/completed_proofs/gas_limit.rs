@@ -0,0 +1,99 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bonsai_ethereum_contracts::i_bonsai_relay::Callback;
+use ethers::{
+    providers::Middleware,
+    types::{Address, TransactionRequest},
+};
+use thiserror::Error;
+
+/// Gas of the transaction itself, included in the `eth_estimateGas` estimate
+/// of a callback but not spent by the relay contract calling it.
+const TX_BASE_GAS: u64 = 21000;
+
+/// Adjustment of the gas limits of the callbacks to their `eth_estimateGas`
+/// estimate, e.g. for callback contracts using more gas than requested after
+/// an upgrade.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CallbackGasLimits {
+    /// Percentage added on top of the gas estimate of each callback.
+    pub headroom_percent: u64,
+    /// Lower bound on the gas limit of the callbacks, if any.
+    pub min: Option<u64>,
+    /// Upper bound on the gas limit of the callbacks, if any. Callbacks
+    /// estimated to use more fail their request rather than being relayed.
+    pub max: Option<u64>,
+    /// Toggle to treat the gas limit of the callback request as a ceiling on
+    /// the adjusted one, rather than a hint replaced by it. Callbacks
+    /// estimated to use more than requested then fail their request rather
+    /// than running out of gas on chain.
+    pub respect_requested: bool,
+}
+
+/// Gas estimate of a callback exceeding the gas limit it may be given.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum GasPrecheckError {
+    #[error("callback gas estimate {estimate} exceeds the requested gas limit {requested}")]
+    Requested { estimate: u64, requested: u64 },
+    #[error("callback gas estimate {estimate} exceeds the maximum callback gas limit {max}")]
+    Max { estimate: u64, max: u64 },
+}
+
+impl CallbackGasLimits {
+    /// Gas limit of a callback requested with `requested` gas, and estimated
+    /// to use `estimate`: the estimate plus the headroom, clamped between the
+    /// bounds, and at most the requested one if it is respected.
+    pub(crate) fn gas_limit(&self, requested: u64, estimate: u64) -> Result<u64, GasPrecheckError> {
+        if self.respect_requested && estimate > requested {
+            return Err(GasPrecheckError::Requested {
+                estimate,
+                requested,
+            });
+        }
+        if let Some(max) = self.max.filter(|max| estimate > *max) {
+            return Err(GasPrecheckError::Max { estimate, max });
+        }
+
+        let padded = (u128::from(estimate) * (100 + u128::from(self.headroom_percent)) + 99) / 100;
+        let mut gas_limit = u64::try_from(padded).unwrap_or(u64::MAX);
+        if let Some(min) = self.min {
+            gas_limit = gas_limit.max(min);
+        }
+        if let Some(max) = self.max {
+            gas_limit = gas_limit.min(max);
+        }
+        if self.respect_requested {
+            gas_limit = gas_limit.min(requested);
+        }
+        Ok(gas_limit)
+    }
+}
+
+/// Estimates the gas used by `callback` when called by the relay contract at
+/// `relay_contract_address`, without the gas of the transaction itself.
+pub(crate) async fn estimate_callback_gas<M: Middleware>(
+    client: &M,
+    relay_contract_address: Address,
+    callback: &Callback,
+) -> Result<u64, M::Error> {
+    let tx = TransactionRequest::new()
+        .from(relay_contract_address)
+        .to(callback.callback_contract)
+        .data(callback.payload.clone());
+    let estimate = client.estimate_gas(&tx.into(), None).await?;
+    Ok(u64::try_from(estimate)
+        .unwrap_or(u64::MAX)
+        .saturating_sub(TX_BASE_GAS))
+}
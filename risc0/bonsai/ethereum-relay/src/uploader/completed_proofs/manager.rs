@@ -12,119 +12,781 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use bonsai_ethereum_contracts::{i_bonsai_relay::Callback, IBonsaiRelay};
-use bonsai_sdk::alpha::Client;
-use ethers::prelude::{k256::ecdsa::SigningKey, *};
+use bonsai_ethereum_contracts::i_bonsai_relay::Callback;
+use bonsai_sdk::alpha_async::RoundRobinBonsaiClient;
+use ethers::prelude::*;
 use futures::{stream::FuturesUnordered, StreamExt};
-use tokio::{sync::Notify, task::JoinHandle};
-use tracing::info;
+use tokio::{
+    sync::{mpsc, watch, Notify, OwnedSemaphorePermit, Semaphore},
+    task::JoinHandle,
+};
+use tracing::{debug, error, info, warn};
 
 use crate::{
-    storage::{ProofRequestState, Storage},
+    bonsai_transport::BonsaiTransport,
+    event_db::{EventDb, EventProof},
+    events::{self, EventSender, RelayerEvent},
+    metrics::Metrics,
+    reload::RelayLimits,
+    spend::{receipt_cost, SpendLimit},
+    storage::{dead_letters::FailureStage, ProofID, ProofRequestState, Storage},
     uploader::completed_proofs::{
-        complete_proof::{get_complete_proof, CompleteProof},
+        complete_proof::{get_complete_proof, journal_matches, CompleteProof},
         error::*,
+        fee_cap::{current_fee_per_gas, FeeCap, FEE_CAP_POLL_INTERVAL},
+        gas_limit::{estimate_callback_gas, CallbackGasLimits, GasPrecheckError},
+        private_relay::TxSubmitter,
+        relay::{
+            ethers_error, get_revert_reason, is_permanent_revert, reconfirm_receipt,
+            revert_retry_backoff, wait_for_receipt, CallbackRelay, EthersClient,
+        },
+        signer_pool::{Signer, SignerClientError, SignerPool},
     },
     EthersClientConfig,
 };
 
-const BONSAI_RELAY_GAS_LIMIT: u64 = 3000000;
+/// Hashes of the transactions sent on chain that are still awaiting
+/// confirmation.
+pub(crate) type PendingTransactions = Arc<Mutex<HashSet<H256>>>;
 
 pub(crate) struct BonsaiCompleteProofManager<S: Storage> {
-    client: Client,
+    client: RoundRobinBonsaiClient,
+    /// Transport fetching the status of the sessions, the REST API of
+    /// `client` by default.
+    transport: Arc<dyn BonsaiTransport>,
     dev_mode: bool,
+    skip_proof_validation: bool,
+    min_eth_balance: U256,
+    fee_cap: Option<FeeCap>,
+    /// Time of the next fee check, while the fees exceed the fee cap.
+    fee_held_until: Option<Instant>,
+    retry_on_revert: u32,
+    stuck_tx_timeout: Duration,
+    tx_resubmit_bump: u64,
+    /// Number of blocks mined on top of a relay transaction during which it
+    /// is checked to still be on chain, and resubmitted if a reorg drops it.
+    reorg_depth: u64,
+    /// Reloaded fee cap, concurrency and queue limits.
+    limits: watch::Receiver<RelayLimits>,
+    /// Permits of the relay transactions in flight, each held from sending
+    /// the transaction until it is confirmed.
+    relay_permits: Arc<Semaphore>,
+    max_concurrent_relays: usize,
+    /// Number of completed proofs queued for relaying, beyond which the
+    /// oldest ones are dropped.
+    relay_queue_depth: usize,
     storage: S,
     new_complete_proofs_notifier: Arc<Notify>,
+    /// Proofs submitted pre-generated, in submission-only mode.
+    submissions: mpsc::UnboundedReceiver<CompleteProof>,
     ready_to_send_batch: Vec<CompleteProof>,
     max_batch_size: usize,
-    proxy_contract_address: Address,
+    relay: Arc<dyn CallbackRelay>,
     ethers_client_config: EthersClientConfig,
+    /// Keys signing the relay transactions, each sent with the least-loaded
+    /// one.
+    signers: SignerPool,
     send_batch_notifier: Arc<Notify>,
     send_batch_interval: tokio::time::Interval,
     futures_set: FuturesUnordered<JoinHandle<Result<CompleteProof, CompleteProofError>>>,
+    shutdown_notifier: Arc<Notify>,
+    shutdown: bool,
+    pending_transactions: PendingTransactions,
+    transactions_set: FuturesUnordered<JoinHandle<Result<(), BonsaiCompleteProofManagerError>>>,
+    events: Option<EventSender>,
+    /// Database in which the proofs of the relay events are recorded, so that
+    /// their relay transactions can be resubmitted.
+    event_db: Option<EventDb>,
+    /// Daily spend limit of the wallet, shared with the other chains.
+    spend_limit: SpendLimit,
+    /// Whether the queued proofs are held back by the daily spend limit.
+    spend_held: bool,
+    /// Adjustment of the gas limits of the callbacks to their estimate, if
+    /// any.
+    callback_gas_limits: Option<CallbackGasLimits>,
+    /// Submits the relay transactions to the public mempool, or to a private
+    /// relay.
+    submitter: TxSubmitter,
+    /// Prefixes of the journals of the proofs to relay. Proofs whose journal
+    /// starts with none of them are skipped. All proofs are relayed if empty.
+    journal_filters: Vec<Vec<u8>>,
 }
 
-impl<S: Storage> BonsaiCompleteProofManager<S> {
+impl<S: Storage + Sync + Send + Clone + 'static> BonsaiCompleteProofManager<S> {
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
-        client: Client,
+        client: RoundRobinBonsaiClient,
         dev_mode: bool,
+        skip_proof_validation: bool,
+        min_eth_balance: U256,
+        mut limits: watch::Receiver<RelayLimits>,
+        retry_on_revert: u32,
+        stuck_tx_timeout: Duration,
+        tx_resubmit_bump: u64,
+        reorg_depth: u64,
         storage: S,
         new_complete_proofs_notifier: Arc<Notify>,
+        submissions: mpsc::UnboundedReceiver<CompleteProof>,
         send_batch_notifier: Arc<Notify>,
         max_batch_size: usize,
-        proxy_contract_address: Address,
+        relay: Arc<dyn CallbackRelay>,
         ethers_client_config: EthersClientConfig,
         send_batch_interval: tokio::time::Interval,
+        shutdown_notifier: Arc<Notify>,
+        events: Option<EventSender>,
     ) -> Self {
+        let RelayLimits {
+            fee_cap,
+            max_concurrent_relays,
+            relay_queue_depth,
+        } = *limits.borrow_and_update();
+        let max_concurrent_relays = relay_concurrency(&ethers_client_config, max_concurrent_relays);
+        let submitter = TxSubmitter::public(ethers_client_config.eth_chain_id);
+        let signers = SignerPool::new(&ethers_client_config);
         Self {
+            transport: Arc::new(client.clone()),
             client,
             dev_mode,
+            skip_proof_validation,
+            min_eth_balance,
+            fee_cap,
+            fee_held_until: None,
+            retry_on_revert,
+            stuck_tx_timeout,
+            tx_resubmit_bump,
+            reorg_depth,
+            limits,
+            relay_permits: Arc::new(Semaphore::new(max_concurrent_relays)),
+            max_concurrent_relays,
+            relay_queue_depth,
             storage,
             new_complete_proofs_notifier,
+            submissions,
             ready_to_send_batch: Vec::new(),
             max_batch_size,
-            proxy_contract_address,
+            relay,
             ethers_client_config,
+            signers,
             send_batch_notifier,
             send_batch_interval,
             futures_set: FuturesUnordered::new(),
+            shutdown_notifier,
+            shutdown: false,
+            pending_transactions: Arc::new(Mutex::new(HashSet::new())),
+            transactions_set: FuturesUnordered::new(),
+            events,
+            event_db: None,
+            spend_limit: SpendLimit::default(),
+            spend_held: false,
+            callback_gas_limits: None,
+            submitter,
+            journal_filters: Vec::new(),
+        }
+    }
+
+    /// Fetch the status of the sessions through `transport`, e.g. the gRPC
+    /// API of Bonsai.
+    pub(crate) fn with_transport(self, transport: Arc<dyn BonsaiTransport>) -> Self {
+        Self { transport, ..self }
+    }
+
+    /// Record the proofs of the relay events in the given database.
+    pub(crate) fn with_event_db(self, event_db: Option<EventDb>) -> Self {
+        Self { event_db, ..self }
+    }
+
+    /// Hold back the relay transactions while the gas they spent over the last
+    /// 24 hours reaches the given limit, recording their spend in it.
+    pub(crate) fn with_spend_limit(self, spend_limit: SpendLimit) -> Self {
+        Self {
+            spend_limit,
+            ..self
+        }
+    }
+
+    /// Adjust the gas limits of the callbacks to their estimate before sending
+    /// them, failing the requests whose callbacks would run out of gas.
+    pub(crate) fn with_callback_gas_limits(
+        self,
+        callback_gas_limits: Option<CallbackGasLimits>,
+    ) -> Self {
+        Self {
+            callback_gas_limits,
+            ..self
+        }
+    }
+
+    /// Submit the relay transactions through `submitter`, e.g. to a private
+    /// relay.
+    pub(crate) fn with_tx_submitter(self, submitter: TxSubmitter) -> Self {
+        Self { submitter, ..self }
+    }
+
+    /// Sign the relay transactions with the keys of `signers`, e.g. checked
+    /// on startup.
+    pub(crate) fn with_signer_pool(self, signers: SignerPool) -> Self {
+        Self { signers, ..self }
+    }
+
+    /// Only relay the proofs whose journal starts with one of
+    /// `journal_filters`, skipping the others.
+    pub(crate) fn with_journal_filters(self, journal_filters: Vec<Vec<u8>>) -> Self {
+        Self {
+            journal_filters,
+            ..self
         }
     }
 
+    /// Chain the relay transactions of the manager are sent on.
+    fn chain_id(&self) -> u64 {
+        self.ethers_client_config.eth_chain_id
+    }
+
+    /// Returns a handle on the hashes of the transactions that are still
+    /// awaiting confirmation.
+    pub(crate) fn pending_transactions(&self) -> PendingTransactions {
+        self.pending_transactions.clone()
+    }
+
     async fn send_batch(&mut self) -> Result<(), BonsaiCompleteProofManagerError> {
         if self.ready_to_send_batch.is_empty() {
             return Ok(());
         }
-        let contract_call = {
-            let ethers_client = self.ethers_client_config.get_client().await?;
-            let bonsay_relay =
-                IBonsaiRelay::<SignerMiddleware<Provider<Ws>, Wallet<SigningKey>>>::new(
-                    self.proxy_contract_address,
-                    Arc::new(ethers_client),
+        if self
+            .fee_held_until
+            .is_some_and(|held_until| Instant::now() < held_until)
+        {
+            return Ok(());
+        }
+        // Keep the batch until the spend of the last 24 hours drops below the
+        // daily spend limit, the limit is raised, or an admin resumes.
+        if self.spend_limit.is_limited(self.chain_id()) {
+            return self.hold_spend_limited().await;
+        }
+        self.release_spend_limited().await?;
+        // Keep the batch queued until a relay transaction in flight is
+        // confirmed.
+        if self.relay_permits.available_permits() == 0 {
+            return Ok(());
+        }
+        // Each relay contract gets its own transaction, signed by the key with
+        // the fewest transactions in flight.
+        let mut batches: Vec<(Address, Vec<CompleteProof>)> = Vec::new();
+        for complete_proof in std::mem::take(&mut self.ready_to_send_batch) {
+            let relay_contract_address = complete_proof.relay_contract_address;
+            match batches
+                .iter_mut()
+                .find(|(relay, _)| *relay == relay_contract_address)
+            {
+                Some((_, batch)) => batch.push(complete_proof),
+                None => batches.push((relay_contract_address, vec![complete_proof])),
+            }
+        }
+        let mut index = 0;
+        while index < batches.len() {
+            let relay_contract_address = batches[index].0;
+            let Ok(permit) = self.relay_permits.clone().try_acquire_owned() else {
+                warn!(
+                    chain_id = self.chain_id(),
+                    queued = batches[index..].len(),
+                    "max concurrent relays reached, queuing batches"
                 );
-            let proof_batch: Vec<Callback> = self
-                .ready_to_send_batch
-                .clone()
-                .into_iter()
-                .map(|complete_proof| complete_proof.ethereum_callback)
-                .collect();
-
-            info!("sending batch");
-            bonsay_relay
-                .invoke_callbacks(proof_batch)
-                .gas(BONSAI_RELAY_GAS_LIMIT)
-        };
+                self.requeue(&batches[index..]);
+                return Ok(());
+            };
+            let assigned = match self.assign_signer().await {
+                Ok(assigned) => assigned,
+                Err(err) => {
+                    self.requeue(&batches[index..]);
+                    return Err(err);
+                }
+            };
+            let Some((signer, ethers_client)) = assigned else {
+                self.requeue(&batches[index..]);
+                return Ok(());
+            };
+            if index == 0 {
+                match self.fees_above_cap(&ethers_client).await {
+                    Ok(false) => {}
+                    held => {
+                        self.requeue(&batches[index..]);
+                        return held.map(|_| ());
+                    }
+                }
+            }
+            let (batch, failed) = self
+                .check_gas_limits(&ethers_client, relay_contract_address, &batches[index].1)
+                .await;
+            batches[index].1.retain(|complete_proof| {
+                !failed.iter().any(|(proof_request_id, _)| {
+                    *proof_request_id == complete_proof.bonsai_proof_id
+                })
+            });
+            let sent = async {
+                for (proof_request_id, error) in failed {
+                    self.fail_queued_proof(
+                        proof_request_id,
+                        FailureStage::Precheck,
+                        error.to_string(),
+                    )
+                    .await?;
+                }
+                if !batch.is_empty() {
+                    self.send_relay_batch(
+                        signer,
+                        ethers_client.clone(),
+                        relay_contract_address,
+                        batch,
+                        permit,
+                    )
+                    .await?;
+                }
+                Ok::<_, BonsaiCompleteProofManagerError>(())
+            }
+            .await;
+            match sent {
+                Ok(()) => index += 1,
+                // The key failed to sign the batch and was removed from
+                // rotation: the batch is sent with the next key.
+                Err(BonsaiCompleteProofManagerError::Signer { .. }) => {}
+                Err(err) => {
+                    // Keep the batches not sent yet for the next attempt.
+                    self.requeue(&batches[index..]);
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Queue the batches not sent yet again, for the next attempt.
+    fn requeue(&mut self, batches: &[(Address, Vec<CompleteProof>)]) {
+        self.ready_to_send_batch = batches
+            .iter()
+            .flat_map(|(_, batch)| batch.clone())
+            .collect();
+    }
 
-        let pending_tx =
-            contract_call
-                .send()
+    /// Assign the next relay transaction to the key of the pool with the
+    /// fewest transactions in flight whose balance is above the minimum,
+    /// returning it along with its client. Keys failing to sign are removed
+    /// from rotation.
+    ///
+    /// Returns `None` if no key can send the transaction, the batches being
+    /// kept until a key is topped up, rather than running out of funds
+    /// mid-transaction.
+    async fn assign_signer(
+        &mut self,
+    ) -> Result<Option<(Signer, Arc<EthersClient>)>, BonsaiCompleteProofManagerError> {
+        let chain_id = self.chain_id();
+        let mut skipped = Vec::new();
+        while let Some(signer) = self.signers.assign(&skipped) {
+            skipped.push(signer.index());
+            let ethers_client = match signer.client().await {
+                Ok(ethers_client) => Arc::new(ethers_client),
+                Err(SignerClientError::Key(err)) => {
+                    signer.remove(&err, &self.events);
+                    continue;
+                }
+                Err(SignerClientError::Node(err)) => return Err(anyhow::Error::from(err).into()),
+            };
+            let address = ethers_client.address();
+            let balance = ethers_client
+                .get_balance(address, None)
                 .await
-                .map_err(|e| BonsaiCompleteProofManagerError::Ethers {
-                    source: Box::new(e),
-                })?;
-        let tx_hash = pending_tx.tx_hash();
+                .map_err(ethers_error)?;
+            Metrics::global().set_signer_balance(chain_id, address, balance);
+            if balance < self.min_eth_balance {
+                warn!(
+                    chain_id,
+                    signer = ?address,
+                    %balance,
+                    min_eth_balance = %self.min_eth_balance,
+                    "signer balance too low, not sending batch with it"
+                );
+                Metrics::global().record_skipped_low_balance(chain_id, address);
+                if signer.set_low_balance(true) {
+                    events::emit(
+                        &self.events,
+                        RelayerEvent::LowBalance {
+                            timestamp: events::now(),
+                            chain_id,
+                            signer: address,
+                            balance,
+                            min_eth_balance: self.min_eth_balance,
+                        },
+                    );
+                }
+                continue;
+            }
+            signer.set_low_balance(false);
+            return Ok(Some((signer, ethers_client)));
+        }
+        if self.signers.active() == 0 {
+            error!(chain_id, "no signer left in rotation, not sending batch");
+        } else {
+            error!(chain_id, "signer balances too low, not sending batch");
+        }
+        Ok(None)
+    }
 
-        pending_tx
+    /// Returns whether the network fees exceed the fee cap, if any, holding
+    /// the batches until they drop below it, checking them again every
+    /// [FEE_CAP_POLL_INTERVAL].
+    async fn fees_above_cap(
+        &mut self,
+        ethers_client: &EthersClient,
+    ) -> Result<bool, BonsaiCompleteProofManagerError> {
+        let Some(fee_cap) = self.fee_cap else {
+            return Ok(false);
+        };
+        let fee = current_fee_per_gas(ethers_client)
             .await
-            .map_err(|e| BonsaiCompleteProofManagerError::Confirmation { source: e, tx_hash })?;
+            .map_err(ethers_error)?;
+        if fee > fee_cap.max_fee_per_gas {
+            warn!(
+                chain_id = self.chain_id(),
+                %fee,
+                fee_cap = %fee_cap.max_fee_per_gas,
+                held = self.ready_to_send_batch.len(),
+                "network fees above the cap, holding batch"
+            );
+            Metrics::global().record_held_fee_cap(self.chain_id());
+            self.fee_held_until = Some(Instant::now() + FEE_CAP_POLL_INTERVAL);
+            return Ok(true);
+        }
+        self.fee_held_until = None;
+        Ok(false)
+    }
 
-        for completed_proof in self.ready_to_send_batch.clone().into_iter() {
-            self.storage
-                .transition_proof_request(
-                    completed_proof.bonsai_proof_id.clone(),
-                    ProofRequestState::CompletedOnchain(tx_hash),
+    /// Adjust the gas limits of the callbacks of the batch, if set, returning
+    /// the adjusted batch, without the completed proofs whose callbacks are
+    /// estimated to use more gas than they may be given.
+    ///
+    /// Callbacks whose gas can't be estimated, e.g. as they revert, keep their
+    /// requested gas limit.
+    async fn check_gas_limits(
+        &self,
+        ethers_client: &EthersClient,
+        relay_contract_address: Address,
+        batch: &[CompleteProof],
+    ) -> (Vec<CompleteProof>, Vec<(ProofID, GasPrecheckError)>) {
+        let Some(limits) = self.callback_gas_limits else {
+            return (batch.to_vec(), Vec::new());
+        };
+        let mut checked = Vec::with_capacity(batch.len());
+        let mut failed = Vec::new();
+        'proofs: for complete_proof in batch {
+            let mut complete_proof = complete_proof.clone();
+            for callback in complete_proof.ethereum_callbacks.iter_mut() {
+                let estimate = match estimate_callback_gas(
+                    ethers_client.provider(),
+                    relay_contract_address,
+                    callback,
                 )
                 .await
-                .map_err(|e| BonsaiCompleteProofManagerError::Storage {
-                    source: e,
-                    id: Some(completed_proof.bonsai_proof_id.clone()),
-                })?;
+                {
+                    Ok(estimate) => estimate,
+                    Err(err) => {
+                        warn!(
+                            chain_id = self.chain_id(),
+                            callback = ?callback.callback_contract,
+                            ?err,
+                            "failed to estimate callback gas, keeping the requested gas limit"
+                        );
+                        continue;
+                    }
+                };
+                match limits.gas_limit(callback.gas_limit, estimate) {
+                    Ok(gas_limit) => callback.gas_limit = gas_limit,
+                    Err(error) => {
+                        warn!(
+                            chain_id = self.chain_id(),
+                            proof_request_id = ?complete_proof.bonsai_proof_id,
+                            %error,
+                            "callback gas precheck failed"
+                        );
+                        failed.push((complete_proof.bonsai_proof_id.clone(), error));
+                        continue 'proofs;
+                    }
+                }
+            }
+            checked.push(complete_proof);
         }
+        (checked, failed)
+    }
 
-        self.ready_to_send_batch.clear();
+    /// Send the batch of completed proofs of a relay contract in a single
+    /// transaction signed by `signer`, and track its confirmation, holding the
+    /// relay `permit` until then. The signer is removed from rotation if it
+    /// fails to sign.
+    async fn send_relay_batch(
+        &mut self,
+        signer: Signer,
+        ethers_client: Arc<EthersClient>,
+        relay_contract_address: Address,
+        batch: Vec<CompleteProof>,
+        permit: OwnedSemaphorePermit,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let proof_batch: Vec<Callback> = batch
+            .iter()
+            .flat_map(|complete_proof| complete_proof.ethereum_callbacks.clone())
+            .collect();
+        let session_ids: Vec<String> = batch
+            .iter()
+            .map(|complete_proof| complete_proof.bonsai_proof_id.uuid.clone())
+            .collect();
+
+        let signer_address = ethers_client.address();
+        info!(
+            chain_id = self.chain_id(),
+            relay = ?relay_contract_address,
+            signer = ?signer_address,
+            "sending batch"
+        );
+        let submitted = {
+            let _send = signer.send_lock().lock().await;
+            let tx = self
+                .relay
+                .callbacks_transaction(
+                    ethers_client.clone(),
+                    relay_contract_address,
+                    proof_batch.clone(),
+                )
+                .await?;
+            self.submitter.submit(&ethers_client, tx).await
+        };
+        let mut tx_hash = match submitted {
+            Err(BonsaiCompleteProofManagerError::Signer { source }) => {
+                signer.remove(&*source, &self.events);
+                return Err(BonsaiCompleteProofManagerError::Signer { source });
+            }
+            submitted => submitted?,
+        };
+        info!(
+            chain_id = self.chain_id(),
+            relay = ?relay_contract_address,
+            signer = ?signer_address,
+            ?tx_hash,
+            "batch sent"
+        );
+        events::emit(
+            &self.events,
+            RelayerEvent::TxSubmitted {
+                timestamp: events::now(),
+                relay_address: relay_contract_address,
+                tx_hash,
+                session_ids: session_ids.clone(),
+            },
+        );
+
+        // Wait for the confirmation in a separate task so that the transaction
+        // can be tracked, and drained, on shutdown.
+        self.pending_transactions
+            .lock()
+            .expect("pending transactions lock poisoned")
+            .insert(tx_hash);
+        let storage = self.storage.clone();
+        let pending_transactions = self.pending_transactions.clone();
+        let spend_limit = self.spend_limit.clone();
+        let relay = self.relay.clone();
+        let submitter = self.submitter.clone();
+        let chain_id = self.chain_id();
+        let retry_on_revert = self.retry_on_revert;
+        let stuck_tx_timeout = self.stuck_tx_timeout;
+        let tx_resubmit_bump = self.tx_resubmit_bump;
+        let reorg_depth = self.reorg_depth;
+        let polling = self.ethers_client_config.polls();
+        let events = self.events.clone();
+        self.transactions_set.push(tokio::spawn(async move {
+            let _permit = permit;
+            let mut retries = 0;
+            loop {
+                let mut submitted = vec![tx_hash];
+                let confirmation = wait_for_receipt(
+                    &ethers_client,
+                    &submitter,
+                    tx_hash,
+                    stuck_tx_timeout,
+                    tx_resubmit_bump,
+                    |replacement| {
+                        pending_transactions
+                            .lock()
+                            .expect("pending transactions lock poisoned")
+                            .insert(replacement);
+                        events::emit(
+                            &events,
+                            RelayerEvent::TxSubmitted {
+                                timestamp: events::now(),
+                                relay_address: relay_contract_address,
+                                tx_hash: replacement,
+                                session_ids: session_ids.clone(),
+                            },
+                        );
+                        submitted.push(replacement);
+                    },
+                )
+                .await;
+                {
+                    let mut pending_transactions = pending_transactions
+                        .lock()
+                        .expect("pending transactions lock poisoned");
+                    for tx_hash in submitted.iter() {
+                        pending_transactions.remove(tx_hash);
+                    }
+                }
+                let receipt = confirmation.map_err(|e| {
+                    BonsaiCompleteProofManagerError::Confirmation { source: e, tx_hash }
+                })?;
+                // A fee-bumped replacement may have been mined instead. Mined
+                // transactions spend gas, reverted or not.
+                if let Some(receipt) = receipt.as_ref() {
+                    tx_hash = receipt.transaction_hash;
+                    spend_limit.record(chain_id, signer_address, tx_hash, receipt_cost(receipt));
+                }
+                let reverted_at = receipt
+                    .as_ref()
+                    .filter(|receipt| receipt.status == Some(0.into()))
+                    .and_then(|receipt| receipt.block_number);
+                if let Some(block_number) = reverted_at {
+                    let reason = get_revert_reason(&ethers_client, tx_hash, block_number)
+                        .await
+                        .unwrap_or_else(|| "unknown".to_string());
+                    Metrics::global().record_reverted(chain_id, relay_contract_address);
+                    if retries >= retry_on_revert || is_permanent_revert(&reason) {
+                        // Keep the requests in the dead-letter list, to be
+                        // relayed again.
+                        for completed_proof in batch.iter() {
+                            storage
+                                .fail_proof_request(
+                                    completed_proof.bonsai_proof_id.clone(),
+                                    FailureStage::Relaying,
+                                    format!("transaction {tx_hash:?} reverted: {reason}"),
+                                )
+                                .await
+                                .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                                    source: e,
+                                    id: Some(completed_proof.bonsai_proof_id.clone()),
+                                })?;
+                        }
+                        for session_id in session_ids {
+                            events::emit(
+                                &events,
+                                RelayerEvent::RequestFailed {
+                                    timestamp: events::now(),
+                                    session_id: Some(session_id),
+                                    error: format!("transaction {tx_hash:?} reverted: {reason}"),
+                                },
+                            );
+                        }
+                        return Err(BonsaiCompleteProofManagerError::Reverted { tx_hash, reason });
+                    }
+                    retries += 1;
+                    let backoff = revert_retry_backoff(retries);
+                    warn!(
+                        chain_id,
+                        relay = ?relay_contract_address,
+                        ?tx_hash,
+                        %reason,
+                        retries,
+                        ?backoff,
+                        "batch reverted, resubmitting"
+                    );
+                    tokio::time::sleep(backoff).await;
+                } else {
+                    // Follow the mined transaction for `reorg_depth` more
+                    // blocks, resubmitting the batch if a reorg drops it.
+                    let Some(receipt) = receipt.filter(|_| reorg_depth > 0) else {
+                        break;
+                    };
+                    let reconfirmed =
+                        reconfirm_receipt(ethers_client.provider(), receipt, reorg_depth, polling)
+                            .await
+                            .map_err(|e| BonsaiCompleteProofManagerError::Confirmation {
+                                source: e,
+                                tx_hash,
+                            })?;
+                    if let Some(receipt) = reconfirmed {
+                        tx_hash = receipt.transaction_hash;
+                        break;
+                    }
+                    Metrics::global().record_reorg_detected(chain_id, relay_contract_address);
+                    warn!(
+                        chain_id,
+                        relay = ?relay_contract_address,
+                        ?tx_hash,
+                        reorg_depth,
+                        "batch dropped from the chain by a reorg, resubmitting"
+                    );
+                }
+
+                let resent = {
+                    let _send = signer.send_lock().lock().await;
+                    let tx = relay
+                        .callbacks_transaction(
+                            ethers_client.clone(),
+                            relay_contract_address,
+                            proof_batch.clone(),
+                        )
+                        .await?;
+                    submitter.submit(&ethers_client, tx).await
+                };
+                tx_hash = match resent {
+                    // The key is no longer used for new batches, while this
+                    // one fails.
+                    Err(BonsaiCompleteProofManagerError::Signer { source }) => {
+                        signer.remove(&*source, &events);
+                        return Err(BonsaiCompleteProofManagerError::Signer { source });
+                    }
+                    resent => resent?,
+                };
+                info!(chain_id, relay = ?relay_contract_address, ?tx_hash, "batch resent");
+                events::emit(
+                    &events,
+                    RelayerEvent::TxSubmitted {
+                        timestamp: events::now(),
+                        relay_address: relay_contract_address,
+                        tx_hash,
+                        session_ids: session_ids.clone(),
+                    },
+                );
+                pending_transactions
+                    .lock()
+                    .expect("pending transactions lock poisoned")
+                    .insert(tx_hash);
+            }
+            events::emit(
+                &events,
+                RelayerEvent::TxConfirmed {
+                    timestamp: events::now(),
+                    relay_address: relay_contract_address,
+                    tx_hash,
+                    session_ids,
+                },
+            );
+
+            for completed_proof in batch.into_iter() {
+                storage
+                    .transition_proof_request(
+                        completed_proof.bonsai_proof_id.clone(),
+                        ProofRequestState::CompletedOnchain(tx_hash),
+                    )
+                    .await
+                    .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                        source: e,
+                        id: Some(completed_proof.bonsai_proof_id.clone()),
+                    })?;
+            }
+
+            Ok(())
+        }));
 
         Ok(())
     }
@@ -140,12 +802,22 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 source: e,
                 id: None,
             })?;
-        for request in completed_proof_requests.into_iter() {
+        // The storage is shared with the managers of the other chains.
+        for request in completed_proof_requests
+            .into_iter()
+            .filter(|request| request.chain_id == self.chain_id())
+        {
             let completed_proof_request_handler = tokio::spawn(get_complete_proof(
                 self.client.clone(),
+                self.transport.clone(),
                 self.dev_mode,
+                self.skip_proof_validation,
                 request.proof_request_id.clone(),
+                request.chain_id,
+                request.relay_contract_address,
                 request.callback_proof_request_event,
+                request.attached_requests,
+                request.created_at,
             ));
             self.futures_set.push(completed_proof_request_handler);
 
@@ -160,7 +832,12 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                     id: Some(request.proof_request_id.clone()),
                 })?;
 
-            info!(?request.proof_request_id, "processing compeleted proof");
+            info!(
+                chain_id = request.chain_id,
+                relay = ?request.relay_contract_address,
+                ?request.proof_request_id,
+                "processing compeleted proof"
+            );
         }
 
         Ok(())
@@ -171,16 +848,237 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
         completed_proof_result: Result<CompleteProof, CompleteProofError>,
     ) -> Result<(), BonsaiCompleteProofManagerError> {
         let completed_proof = completed_proof_result?;
+        if !journal_matches(&self.journal_filters, &completed_proof.journal) {
+            return self.skip_proof(completed_proof).await;
+        }
+        self.record_proof(&completed_proof);
 
+        // Only hold back a bounded number of proofs while the fees exceed the
+        // cap.
+        if let Some(fee_cap) = self.fee_cap {
+            if self.fee_held_until.is_some()
+                && self.ready_to_send_batch.len() >= fee_cap.queue_depth
+            {
+                self.drop_held_proof(completed_proof, fee_cap.queue_depth)
+                    .await?;
+                return Ok(());
+            }
+        }
+
+        if self.spend_held {
+            self.transition_queued_proof(&completed_proof, ProofRequestState::SpendLimited)
+                .await?;
+        }
         self.ready_to_send_batch.push(completed_proof.clone());
         if self.ready_to_send_batch.len() >= self.max_batch_size {
             self.send_batch_notifier.notify_one();
         }
-
         info!(?completed_proof, "proof added to batch");
+
+        // Bound the proofs queued while the relay transactions in flight are
+        // at their maximum, dropping the oldest ones.
+        if self.ready_to_send_batch.len() > self.relay_queue_depth {
+            let oldest = self.ready_to_send_batch.remove(0);
+            self.drop_queued_proof(oldest).await?;
+        }
+        Ok(())
+    }
+
+    /// Record the proof of the events of a completed proof in the event
+    /// database, if any. The callbacks of the events share the proof.
+    fn record_proof(&self, completed_proof: &CompleteProof) {
+        let Some(db) = &self.event_db else {
+            return;
+        };
+        let Some(proof) = completed_proof
+            .ethereum_callbacks
+            .first()
+            .and_then(EventProof::from_callback)
+        else {
+            return;
+        };
+        if let Err(error) = db.set_session_proof(&completed_proof.bonsai_proof_id.uuid, &proof) {
+            warn!(?error, "Failed to record the proof in the event database");
+        }
+    }
+
+    /// Mark the queued proofs as held back by the daily spend limit, once it
+    /// is reached.
+    async fn hold_spend_limited(&mut self) -> Result<(), BonsaiCompleteProofManagerError> {
+        if self.spend_held {
+            return Ok(());
+        }
+        warn!(
+            chain_id = self.chain_id(),
+            held = self.ready_to_send_batch.len(),
+            "daily spend limit reached, holding batch"
+        );
+        for completed_proof in self.ready_to_send_batch.clone() {
+            self.transition_queued_proof(&completed_proof, ProofRequestState::SpendLimited)
+                .await?;
+        }
+        self.spend_held = true;
+        Ok(())
+    }
+
+    /// Mark the proofs held back by the daily spend limit as being relayed
+    /// again, once the spend is below the limit.
+    async fn release_spend_limited(&mut self) -> Result<(), BonsaiCompleteProofManagerError> {
+        if !self.spend_held {
+            return Ok(());
+        }
+        info!(
+            chain_id = self.chain_id(),
+            held = self.ready_to_send_batch.len(),
+            "daily spend below the limit, releasing batch"
+        );
+        for completed_proof in self.ready_to_send_batch.clone() {
+            self.transition_queued_proof(&completed_proof, ProofRequestState::PreparingOnchain)
+                .await?;
+        }
+        self.spend_held = false;
+        Ok(())
+    }
+
+    async fn transition_queued_proof(
+        &self,
+        completed_proof: &CompleteProof,
+        state: ProofRequestState,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let proof_request_id = completed_proof.bonsai_proof_id.clone();
+        self.storage
+            .transition_proof_request(proof_request_id.clone(), state)
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: Some(proof_request_id),
+            })
+    }
+
+    /// Skip the request of a completed proof whose journal matches none of the
+    /// journal filters, without relaying it.
+    async fn skip_proof(
+        &mut self,
+        completed_proof: CompleteProof,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        debug!(
+            chain_id = self.chain_id(),
+            relay = ?completed_proof.relay_contract_address,
+            proof_request_id = ?completed_proof.bonsai_proof_id,
+            journal = hex::encode(&completed_proof.journal),
+            "journal matches no journal filter, skipping completed proof"
+        );
+        self.transition_queued_proof(&completed_proof, ProofRequestState::Skipped)
+            .await
+    }
+
+    /// Fail the request of a completed proof that the fee queue has no room
+    /// for.
+    async fn drop_held_proof(
+        &mut self,
+        completed_proof: CompleteProof,
+        queue_depth: usize,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let relay_contract_address = completed_proof.relay_contract_address;
+        let proof_request_id = completed_proof.bonsai_proof_id;
+        warn!(
+            chain_id = self.chain_id(),
+            relay = ?relay_contract_address,
+            ?proof_request_id,
+            queue_depth,
+            "fee queue full, dropping completed proof"
+        );
+        Metrics::global().record_dropped_fee_cap(self.chain_id(), relay_contract_address);
+        self.fail_queued_proof(
+            proof_request_id,
+            FailureStage::Relaying,
+            format!("dropped as {queue_depth} proofs are already held back by the fee cap"),
+        )
+        .await
+    }
+
+    /// Fail the request of the oldest completed proof queued for relaying,
+    /// when the relay queue is full.
+    async fn drop_queued_proof(
+        &mut self,
+        completed_proof: CompleteProof,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        let relay_contract_address = completed_proof.relay_contract_address;
+        let proof_request_id = completed_proof.bonsai_proof_id;
+        let queue_depth = self.relay_queue_depth;
+        warn!(
+            chain_id = self.chain_id(),
+            relay = ?relay_contract_address,
+            ?proof_request_id,
+            queue_depth,
+            "relay queue full, dropping oldest completed proof"
+        );
+        Metrics::global().record_dropped_queue_full(self.chain_id(), relay_contract_address);
+        self.fail_queued_proof(
+            proof_request_id,
+            FailureStage::Relaying,
+            format!("dropped as {queue_depth} newer proofs are queued for relaying"),
+        )
+        .await
+    }
+
+    /// Fail the request of a completed proof dropped before being sent on
+    /// chain, keeping it in the dead-letter list to be relayed again.
+    async fn fail_queued_proof(
+        &mut self,
+        proof_request_id: ProofID,
+        stage: FailureStage,
+        error: String,
+    ) -> Result<(), BonsaiCompleteProofManagerError> {
+        self.storage
+            .fail_proof_request(proof_request_id.clone(), stage, error.clone())
+            .await
+            .map_err(|e| BonsaiCompleteProofManagerError::Storage {
+                source: e,
+                id: None,
+            })?;
+        events::emit(
+            &self.events,
+            RelayerEvent::RequestFailed {
+                timestamp: events::now(),
+                session_id: Some(proof_request_id.uuid),
+                error,
+            },
+        );
         Ok(())
     }
 
+    /// Follow the reloaded limits. A lower concurrency limit takes effect as
+    /// the relay transactions in flight are confirmed.
+    fn update_limits(&mut self) {
+        let limits = *self.limits.borrow_and_update();
+        self.fee_cap = limits.fee_cap;
+        if self.fee_cap.is_none() {
+            self.fee_held_until = None;
+        }
+        self.relay_queue_depth = limits.relay_queue_depth;
+        let max_concurrent_relays =
+            relay_concurrency(&self.ethers_client_config, limits.max_concurrent_relays);
+        if max_concurrent_relays > self.max_concurrent_relays {
+            self.relay_permits
+                .add_permits(max_concurrent_relays - self.max_concurrent_relays);
+        } else if max_concurrent_relays < self.max_concurrent_relays {
+            let permits = self.relay_permits.clone();
+            let removed = (self.max_concurrent_relays - max_concurrent_relays) as u32;
+            tokio::spawn(async move {
+                if let Ok(permits) = permits.acquire_many_owned(removed).await {
+                    permits.forget();
+                }
+            });
+        }
+        self.max_concurrent_relays = max_concurrent_relays;
+        info!(chain_id = self.chain_id(), ?limits, "relay limits updated");
+
+        if !self.ready_to_send_batch.is_empty() {
+            self.send_batch_notifier.notify_one();
+        }
+    }
+
     async fn reset_inflight_proof_requests(
         &mut self,
     ) -> Result<(), BonsaiCompleteProofManagerError> {
@@ -193,7 +1091,10 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 id: None,
             })?;
 
-        for request in inflight_requests.into_iter() {
+        for request in inflight_requests
+            .into_iter()
+            .filter(|request| request.chain_id == self.chain_id())
+        {
             self.storage
                 .transition_proof_request(
                     request.proof_request_id.clone(),
@@ -218,6 +1119,9 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
             _ = self.new_complete_proofs_notifier.notified() => {
                 self.process_new_complete_proof_requests().await?
             }
+            Some(submission) = self.submissions.recv() => {
+                self.handle_complete_proof_result(Ok(submission)).await?
+            }
 
             _ = self.send_batch_interval.tick() => {
                 self.send_batch_notifier.notify_one();
@@ -226,6 +1130,23 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
             _ = self.send_batch_notifier.notified() => {
                 self.send_batch().await?
             }
+
+            Some(transaction_handle) = self.transactions_set.next() => {
+                // The relay permit of the transaction was released.
+                if !self.ready_to_send_batch.is_empty() {
+                    self.send_batch_notifier.notify_one();
+                }
+                transaction_handle??
+            }
+
+            Ok(()) = self.limits.changed() => {
+                self.update_limits()
+            }
+
+            _ = self.shutdown_notifier.notified() => {
+                info!("shutting down, no longer accepting completed proofs");
+                self.shutdown = true;
+            }
         }
 
         Ok(())
@@ -235,7 +1156,7 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
         self.reset_inflight_proof_requests().await?;
         self.process_new_complete_proof_requests().await?;
 
-        loop {
+        while !self.shutdown {
             match self.step().await {
                 e @ Err(BonsaiCompleteProofManagerError::JoinHandle(..)) => {
                     // if a task panics, just fail
@@ -244,6 +1165,14 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 Err(err) => {
                     // An error occurred processing the completed proof.
                     println!("error occurred managing pending proof requests: {:?}", err);
+                    events::emit(
+                        &self.events,
+                        RelayerEvent::RelayError {
+                            timestamp: events::now(),
+                            error: events::error_chain(&err),
+                            tx_hash: err.tx_hash(),
+                        },
+                    );
                     if let Some(proof_request_id) = err.get_proof_request_id() {
                         // Store the proof as new so that it can be retried.
                         //
@@ -260,5 +1189,41 @@ impl<S: Storage> BonsaiCompleteProofManager<S> {
                 _ => (),
             }
         }
+
+        self.drain_pending_transactions().await;
+        Ok(())
+    }
+
+    /// Wait for all the transactions sent on chain to be confirmed.
+    async fn drain_pending_transactions(&mut self) {
+        info!(
+            pending = self.transactions_set.len(),
+            "waiting for in-flight transactions"
+        );
+        while let Some(transaction_handle) = self.transactions_set.next().await {
+            match transaction_handle {
+                Ok(Ok(())) => (),
+                Ok(Err(err)) => error!(?err, "in-flight transaction failed"),
+                Err(err) => error!(?err, "in-flight transaction task failed"),
+            }
+        }
+    }
+}
+
+/// Returns the number of relay transactions allowed in flight at once. A
+/// hardware wallet signs one transaction at a time, each confirmed on the
+/// device, so its transactions are sent one by one.
+pub(crate) fn relay_concurrency(
+    ethers_client_config: &EthersClientConfig,
+    max_concurrent_relays: usize,
+) -> usize {
+    if ethers_client_config
+        .signer_configs()
+        .iter()
+        .any(|config| config.wallet_key_identifier.is_hardware_wallet())
+    {
+        1
+    } else {
+        max_concurrent_relays
     }
 }
@@ -12,7 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod complete_proof;
+pub(crate) mod complete_proof;
 mod error;
+pub(crate) mod fee_cap;
+pub(crate) mod gas_limit;
 pub(crate) mod manager;
-mod snark;
+pub(crate) mod private_relay;
+pub(crate) mod relay;
+pub(crate) mod signer_pool;
+pub(crate) mod snark;
@@ -0,0 +1,279 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Submission of the relay transactions to a private relay, e.g. Flashbots
+//! Protect, keeping them out of the public mempool until they are included.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction, utils::keccak256};
+use tracing::warn;
+
+use crate::{
+    metrics::Metrics,
+    uploader::completed_proofs::{
+        error::BonsaiCompleteProofManagerError,
+        relay::{ethers_error, send_error, EthersClient},
+    },
+    RelayError,
+};
+
+/// Default number of blocks within which a relay transaction sent to a private
+/// relay is expected to be included, before it is broadcast to the public
+/// mempool.
+pub const DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS: u64 = 25;
+
+/// A private relay, e.g. Flashbots Protect, accepting signed transactions on
+/// `eth_sendRawTransaction` without broadcasting them to the public mempool.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrivateRelay {
+    /// JSON-RPC URL of the relay, e.g. `https://rpc.flashbots.net`.
+    pub url: String,
+    /// Number of blocks without being included after which a transaction sent
+    /// to the relay is broadcast to the public mempool through the Ethereum
+    /// node.
+    pub fallback_blocks: u64,
+}
+
+/// Endpoint through which a relay transaction was submitted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum TxEndpoint {
+    /// The public mempool, through the Ethereum node.
+    Public,
+    /// The private relay.
+    Private,
+    /// The public mempool, after not being included through the private
+    /// relay within the fallback blocks.
+    Fallback,
+}
+
+impl TxEndpoint {
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Private => "private",
+            Self::Fallback => "fallback",
+        }
+    }
+}
+
+/// A transaction sent to the private relay, unknown to the Ethereum node until
+/// included or broadcast to the public mempool.
+struct PrivateTx {
+    tx: TypedTransaction,
+    raw: Bytes,
+    /// Block number of the Ethereum node when the transaction was sent.
+    sent_at: U64,
+    /// Whether the transaction was broadcast to the public mempool as well.
+    broadcast: bool,
+}
+
+/// Submits the relay transactions of a chain to the public mempool, through
+/// the Ethereum node, or to a private relay.
+///
+/// The private relay does not expose its pending transactions, so the ones
+/// sent to it are kept until they are confirmed, to be resubmitted or
+/// broadcast to the public mempool.
+#[derive(Clone)]
+pub(crate) struct TxSubmitter {
+    chain_id: u64,
+    private_relay: Option<(PrivateRelay, Provider<Http>)>,
+    private_txs: Arc<Mutex<HashMap<H256, PrivateTx>>>,
+}
+
+impl TxSubmitter {
+    /// Submit the transactions of the given chain to the public mempool.
+    pub(crate) fn public(chain_id: u64) -> Self {
+        Self {
+            chain_id,
+            private_relay: None,
+            private_txs: Default::default(),
+        }
+    }
+
+    /// Submit the transactions of the given chain to the private relay, if
+    /// any, or else to the public mempool.
+    pub(crate) fn new(
+        chain_id: u64,
+        private_relay: Option<PrivateRelay>,
+    ) -> Result<Self, RelayError> {
+        let private_relay = match private_relay {
+            Some(private_relay) => {
+                let provider =
+                    Provider::<Http>::try_from(private_relay.url.as_str()).map_err(|err| {
+                        RelayError::ConfigError(format!(
+                            "Invalid private relay URL {}: {err}",
+                            private_relay.url
+                        ))
+                    })?;
+                Some((private_relay, provider))
+            }
+            None => None,
+        };
+        Ok(Self {
+            private_relay,
+            ..Self::public(chain_id)
+        })
+    }
+
+    /// Submit a new transaction, returning its hash.
+    pub(crate) async fn submit(
+        &self,
+        client: &EthersClient,
+        mut tx: TypedTransaction,
+    ) -> Result<H256, BonsaiCompleteProofManagerError> {
+        if self.private_relay.is_some() {
+            // The Ethereum node does not count the transactions pending on the
+            // private relay in the nonce of the wallet. Each signer has its
+            // own nonces.
+            let next_nonce = self
+                .lock()
+                .values()
+                .filter(|private_tx| private_tx.tx.from() == Some(&client.address()))
+                .filter_map(|private_tx| private_tx.tx.nonce())
+                .max()
+                .map(|nonce| *nonce + 1);
+            if let Some(next_nonce) = next_nonce {
+                if tx.nonce().map_or(true, |nonce| *nonce < next_nonce) {
+                    tx.set_nonce(next_nonce);
+                }
+            }
+        }
+        self.resubmit(client, tx).await
+    }
+
+    /// Submit a transaction replacing a submitted one of the same nonce, e.g.
+    /// with bumped fees, returning its hash.
+    pub(crate) async fn resubmit(
+        &self,
+        client: &EthersClient,
+        mut tx: TypedTransaction,
+    ) -> Result<H256, BonsaiCompleteProofManagerError> {
+        let Some((_, private_relay)) = &self.private_relay else {
+            let pending_tx = client
+                .send_transaction(tx, None)
+                .await
+                .map_err(send_error)?;
+            Metrics::global().record_tx_submission(self.chain_id, TxEndpoint::Public);
+            return Ok(pending_tx.tx_hash());
+        };
+
+        client
+            .fill_transaction(&mut tx, None)
+            .await
+            .map_err(ethers_error)?;
+        let signature = client
+            .sign_transaction(&tx, client.address())
+            .await
+            .map_err(send_error)?;
+        let raw = tx.rlp_signed(&signature);
+        let sent_at = client.get_block_number().await.map_err(ethers_error)?;
+        private_relay
+            .send_raw_transaction(raw.clone())
+            .await
+            .map_err(ethers_error)?;
+        Metrics::global().record_tx_submission(self.chain_id, TxEndpoint::Private);
+
+        let tx_hash = H256(keccak256(&raw));
+        self.lock().insert(
+            tx_hash,
+            PrivateTx {
+                tx,
+                raw,
+                sent_at,
+                broadcast: false,
+            },
+        );
+        Ok(tx_hash)
+    }
+
+    /// Returns the submitted transaction `tx_hash`, unless it was dropped: as
+    /// sent to the private relay, until a transaction of the same nonce is
+    /// mined, or else as known by the Ethereum node.
+    pub(crate) async fn pending_transaction(
+        &self,
+        client: &EthersClient,
+        tx_hash: H256,
+    ) -> Result<Option<TypedTransaction>, ProviderError> {
+        let private_tx = self
+            .lock()
+            .get(&tx_hash)
+            .map(|private_tx| private_tx.tx.clone());
+        let Some(tx) = private_tx else {
+            let tx = client.provider().get_transaction(tx_hash).await?;
+            return Ok(tx.as_ref().map(TypedTransaction::from));
+        };
+        let nonce = client
+            .provider()
+            .get_transaction_count(client.address(), Some(BlockNumber::Latest.into()))
+            .await?;
+        let pending = tx.nonce().map_or(false, |tx_nonce| *tx_nonce >= nonce);
+        Ok(pending.then_some(tx))
+    }
+
+    /// Broadcast the transaction `tx_hash` to the public mempool if it was
+    /// sent to the private relay, and not included within the fallback
+    /// blocks.
+    pub(crate) async fn fall_back(
+        &self,
+        client: &EthersClient,
+        tx_hash: H256,
+    ) -> Result<(), ProviderError> {
+        let Some((private_relay, _)) = &self.private_relay else {
+            return Ok(());
+        };
+        let due = self
+            .lock()
+            .get(&tx_hash)
+            .filter(|private_tx| !private_tx.broadcast)
+            .map(|private_tx| (private_tx.raw.clone(), private_tx.sent_at));
+        let Some((raw, sent_at)) = due else {
+            return Ok(());
+        };
+        let block_number = client.provider().get_block_number().await?;
+        if block_number < sent_at + private_relay.fallback_blocks {
+            return Ok(());
+        }
+
+        warn!(
+            chain_id = self.chain_id,
+            ?tx_hash,
+            fallback_blocks = private_relay.fallback_blocks,
+            "transaction not included through the private relay, broadcasting it"
+        );
+        client.provider().send_raw_transaction(raw).await?;
+        Metrics::global().record_tx_submission(self.chain_id, TxEndpoint::Fallback);
+        if let Some(private_tx) = self.lock().get_mut(&tx_hash) {
+            private_tx.broadcast = true;
+        }
+        Ok(())
+    }
+
+    /// Stop following the given transactions, once confirmed or dropped.
+    pub(crate) fn forget(&self, tx_hashes: &[H256]) {
+        let mut private_txs = self.lock();
+        for tx_hash in tx_hashes {
+            private_txs.remove(tx_hash);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<H256, PrivateTx>> {
+        self.private_txs
+            .lock()
+            .expect("private transactions lock poisoned")
+    }
+}
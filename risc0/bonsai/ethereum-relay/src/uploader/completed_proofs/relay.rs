@@ -0,0 +1,474 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use bonsai_ethereum_contracts::{i_bonsai_relay::Callback, IBonsaiRelay};
+use ethers::{
+    abi::AbiDecode,
+    prelude::{k256::ecdsa::SigningKey, *},
+    providers::{RpcError, StreamExt},
+    types::transaction::eip2718::TypedTransaction,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    client_config::{EthersProvider, WalletKey},
+    signer::RelaySigner,
+    uploader::completed_proofs::{
+        error::BonsaiCompleteProofManagerError, private_relay::TxSubmitter,
+    },
+};
+
+const BONSAI_RELAY_GAS_LIMIT: u64 = 3000000;
+
+/// Additional gas consumed by the Safe to verify the signatures and forward the
+/// call to the Bonsai Relay contract.
+const SAFE_EXEC_GAS_OVERHEAD: u64 = 200000;
+
+/// Selector of the `Error(string)` revert reason.
+const ERROR_STRING_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+
+/// Revert reasons of relay transactions that would revert again if resubmitted.
+const PERMANENT_REVERT_REASONS: &[&str] = &["ProofAlreadySubmitted"];
+
+/// Delay before resubmitting a reverted transaction for the first time, doubled
+/// on each subsequent retry.
+const REVERT_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Maximum number of times a stuck transaction is resubmitted with bumped fees,
+/// before it is left for manual intervention.
+pub(crate) const MAX_TX_RESUBMIT_BUMPS: u32 = 5;
+
+pub(crate) type EthersClient = SignerMiddleware<EthersProvider, RelaySigner>;
+
+abigen!(
+    GnosisSafe,
+    r#"[
+        function nonce() external view returns (uint256)
+        function getThreshold() external view returns (uint256)
+        function isOwner(address owner) external view returns (bool)
+        function getTransactionHash(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, uint256 _nonce) external view returns (bytes32)
+        function execTransaction(address to, uint256 value, bytes data, uint8 operation, uint256 safeTxGas, uint256 baseGas, uint256 gasPrice, address gasToken, address refundReceiver, bytes signatures) external payable returns (bool success)
+    ]"#
+);
+
+/// Configuration of a Safe multi-sig wallet through which the relay
+/// transactions are sent.
+#[derive(Clone, Debug)]
+pub struct SafeConfig {
+    /// The Ethereum address of the Safe.
+    pub address: Address,
+    /// Keys of the Safe owners co-signing the relay transactions.
+    pub signer_keys: Vec<WalletKey>,
+}
+
+/// Sends batches of callbacks to the Bonsai Relay contracts.
+#[async_trait]
+pub(crate) trait CallbackRelay: Send + Sync {
+    /// Builds a transaction invoking the given callbacks through the relay
+    /// contract at `relay_contract_address`, to be submitted by the relayer
+    /// wallet.
+    async fn callbacks_transaction(
+        &self,
+        client: Arc<EthersClient>,
+        relay_contract_address: Address,
+        callbacks: Vec<Callback>,
+    ) -> Result<TypedTransaction, BonsaiCompleteProofManagerError>;
+
+    /// Submits a transaction invoking the given callbacks through the relay
+    /// contract at `relay_contract_address` to the public mempool, returning
+    /// its hash without waiting for it to be confirmed.
+    async fn invoke_callbacks(
+        &self,
+        client: Arc<EthersClient>,
+        relay_contract_address: Address,
+        callbacks: Vec<Callback>,
+    ) -> Result<H256, BonsaiCompleteProofManagerError> {
+        let tx = self
+            .callbacks_transaction(client.clone(), relay_contract_address, callbacks)
+            .await?;
+        let pending_tx = client
+            .send_transaction(tx, None)
+            .await
+            .map_err(ethers_error)?;
+        Ok(pending_tx.tx_hash())
+    }
+}
+
+/// Invokes the callbacks with transactions signed by the relayer wallet.
+pub(crate) struct DirectRelay;
+
+#[async_trait]
+impl CallbackRelay for DirectRelay {
+    async fn callbacks_transaction(
+        &self,
+        client: Arc<EthersClient>,
+        relay_contract_address: Address,
+        callbacks: Vec<Callback>,
+    ) -> Result<TypedTransaction, BonsaiCompleteProofManagerError> {
+        let nonce = next_nonce(&client).await?;
+        let bonsai_relay = IBonsaiRelay::new(relay_contract_address, client);
+        let contract_call = bonsai_relay
+            .invoke_callbacks(callbacks)
+            .gas(BONSAI_RELAY_GAS_LIMIT)
+            .nonce(nonce);
+        Ok(contract_call.tx)
+    }
+}
+
+/// Invokes the callbacks through a Safe `execTransaction` call, co-signed by
+/// enough of the Safe owners to meet its threshold.
+pub(crate) struct SafeRelay {
+    safe_address: Address,
+    signers: Vec<LocalWallet>,
+}
+
+impl SafeRelay {
+    pub(crate) fn new(config: SafeConfig) -> Self {
+        let signers = config
+            .signer_keys
+            .into_iter()
+            .map(|key| LocalWallet::from(SigningKey::from(key.get_key())))
+            .collect();
+        Self {
+            safe_address: config.address,
+            signers,
+        }
+    }
+}
+
+#[async_trait]
+impl CallbackRelay for SafeRelay {
+    async fn callbacks_transaction(
+        &self,
+        client: Arc<EthersClient>,
+        relay_contract_address: Address,
+        callbacks: Vec<Callback>,
+    ) -> Result<TypedTransaction, BonsaiCompleteProofManagerError> {
+        let data = IBonsaiRelay::new(relay_contract_address, client.clone())
+            .invoke_callbacks(callbacks)
+            .calldata()
+            .ok_or_else(|| {
+                BonsaiCompleteProofManagerError::Safe("missing callbacks calldata".to_string())
+            })?;
+
+        let safe = GnosisSafe::new(self.safe_address, client.clone());
+        let nonce = safe.nonce().call().await.map_err(ethers_error)?;
+        let threshold = safe.get_threshold().call().await.map_err(ethers_error)?;
+        let safe_tx_hash = safe
+            .get_transaction_hash(
+                relay_contract_address,
+                U256::zero(),
+                data.clone(),
+                0,
+                U256::zero(),
+                U256::zero(),
+                U256::zero(),
+                Address::zero(),
+                Address::zero(),
+                nonce,
+            )
+            .call()
+            .await
+            .map_err(ethers_error)?;
+
+        // The relayer wallet can co-sign as well if it is one of the owners.
+        let mut owners = Vec::new();
+        for signer in self.signers.iter().chain([client.signer()]) {
+            if safe
+                .is_owner(signer.address())
+                .call()
+                .await
+                .map_err(ethers_error)?
+            {
+                owners.push(signer.clone());
+            } else {
+                warn!(signer = ?signer.address(), "Signer is not an owner of the Safe, skipping.");
+            }
+        }
+        let signatures = sign_safe_transaction(&owners, safe_tx_hash.into(), threshold.as_usize())?;
+
+        info!(
+            safe = ?self.safe_address,
+            relay = ?relay_contract_address,
+            ?nonce,
+            "sending batch through Safe"
+        );
+        let contract_call = safe
+            .exec_transaction(
+                relay_contract_address,
+                U256::zero(),
+                data,
+                0,
+                U256::zero(),
+                U256::zero(),
+                U256::zero(),
+                Address::zero(),
+                Address::zero(),
+                signatures,
+            )
+            .gas(BONSAI_RELAY_GAS_LIMIT + SAFE_EXEC_GAS_OVERHEAD)
+            .nonce(next_nonce(&client).await?);
+        Ok(contract_call.tx)
+    }
+}
+
+/// Signs `safe_tx_hash` with `threshold` of the given owners, returning the
+/// concatenated signatures in the ascending owner address order expected by
+/// the Safe.
+pub(crate) fn sign_safe_transaction(
+    owners: &[LocalWallet],
+    safe_tx_hash: H256,
+    threshold: usize,
+) -> Result<Bytes, BonsaiCompleteProofManagerError> {
+    let owners: BTreeMap<Address, &LocalWallet> = owners
+        .iter()
+        .map(|owner| (owner.address(), owner))
+        .collect();
+    if owners.len() < threshold {
+        return Err(BonsaiCompleteProofManagerError::Safe(format!(
+            "{} owner signatures available, {threshold} required",
+            owners.len()
+        )));
+    }
+
+    let mut signatures = Vec::with_capacity(threshold * 65);
+    for owner in owners.values().take(threshold) {
+        let signature =
+            owner
+                .sign_hash(safe_tx_hash)
+                .map_err(|e| BonsaiCompleteProofManagerError::Ethers {
+                    source: Box::new(e),
+                })?;
+        signatures.extend(signature.to_vec());
+    }
+    Ok(signatures.into())
+}
+
+/// Decodes the `Error(string)` reason from the revert data, or else returns the
+/// hex-encoded data, e.g. for custom errors.
+pub(crate) fn decode_revert_reason(data: &[u8]) -> String {
+    data.strip_prefix(&ERROR_STRING_SELECTOR)
+        .and_then(|data| String::decode(data).ok())
+        .unwrap_or_else(|| format!("0x{}", hex::encode(data)))
+}
+
+/// Returns whether the transaction would revert again if resubmitted.
+pub(crate) fn is_permanent_revert(reason: &str) -> bool {
+    PERMANENT_REVERT_REASONS
+        .iter()
+        .any(|permanent| reason.contains(permanent))
+}
+
+/// Delay before the given retry of a reverted transaction.
+pub(crate) fn revert_retry_backoff(retry: u32) -> Duration {
+    REVERT_RETRY_BACKOFF.saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+}
+
+/// Replays a reverted transaction on top of the block preceding the one it was
+/// mined in, to recover its revert reason.
+pub(crate) async fn get_revert_reason(
+    client: &EthersClient,
+    tx_hash: H256,
+    block_number: U64,
+) -> Option<String> {
+    let tx = client.provider().get_transaction(tx_hash).await.ok()??;
+    let block = BlockId::from(block_number.saturating_sub(1.into()));
+    let err = client
+        .provider()
+        .call(&(&tx).into(), Some(block))
+        .await
+        .err()?;
+    Some(decode_revert_reason(
+        &err.as_error_response()?.as_revert_data()?,
+    ))
+}
+
+/// Increases `fee` by `bump_percent`, rounding up.
+pub(crate) fn bump_fee(fee: U256, bump_percent: u64) -> U256 {
+    (fee * (100 + bump_percent) + 99) / 100
+}
+
+/// Returns a copy of `tx`, with the same nonce and its fees increased by
+/// `bump_percent`, to replace it in the mempool.
+pub(crate) fn bumped_replacement(
+    tx: impl Into<TypedTransaction>,
+    bump_percent: u64,
+) -> TypedTransaction {
+    let mut replacement = tx.into();
+    if let TypedTransaction::Eip1559(request) = &mut replacement {
+        // Nodes only accept the replacement if both fees are bumped.
+        request.max_fee_per_gas = request
+            .max_fee_per_gas
+            .map(|fee| bump_fee(fee, bump_percent));
+        request.max_priority_fee_per_gas = request
+            .max_priority_fee_per_gas
+            .map(|fee| bump_fee(fee, bump_percent));
+    } else if let Some(gas_price) = replacement.gas_price() {
+        replacement.set_gas_price(bump_fee(gas_price, bump_percent));
+    }
+    replacement
+}
+
+/// Waits for `tx_hash`, or one of its fee-bumped replacements, to be mined.
+///
+/// Whenever none of them is mined within `stuck_tx_timeout`, the latest one is
+/// resubmitted with its fees bumped by `bump_percent`, at most
+/// [MAX_TX_RESUBMIT_BUMPS] times, and `on_resubmit` is called with the hash of
+/// the replacement. Transactions sent to a private relay are broadcast to the
+/// public mempool once not included within its fallback blocks. Returns `None`
+/// if the latest transaction was dropped from the mempool.
+pub(crate) async fn wait_for_receipt(
+    client: &EthersClient,
+    submitter: &TxSubmitter,
+    tx_hash: H256,
+    stuck_tx_timeout: Duration,
+    bump_percent: u64,
+    on_resubmit: impl FnMut(H256),
+) -> Result<Option<TransactionReceipt>, ProviderError> {
+    let mut submitted = vec![tx_hash];
+    let receipt = follow_submitted(
+        client,
+        submitter,
+        &mut submitted,
+        stuck_tx_timeout,
+        bump_percent,
+        on_resubmit,
+    )
+    .await;
+    submitter.forget(&submitted);
+    receipt
+}
+
+async fn follow_submitted(
+    client: &EthersClient,
+    submitter: &TxSubmitter,
+    submitted: &mut Vec<H256>,
+    stuck_tx_timeout: Duration,
+    bump_percent: u64,
+    mut on_resubmit: impl FnMut(H256),
+) -> Result<Option<TransactionReceipt>, ProviderError> {
+    let provider = client.provider();
+    let mut bumps = 0;
+    let mut gave_up = false;
+    loop {
+        let deadline = tokio::time::Instant::now() + stuck_tx_timeout;
+        while tokio::time::Instant::now() < deadline {
+            for tx_hash in submitted.iter() {
+                if let Some(receipt) = provider.get_transaction_receipt(*tx_hash).await? {
+                    return Ok(Some(receipt));
+                }
+            }
+            let latest = *submitted.last().expect("a transaction was submitted");
+            submitter.fall_back(client, latest).await?;
+            tokio::time::sleep(provider.get_interval()).await;
+        }
+
+        let latest = *submitted.last().expect("a transaction was submitted");
+        let Some(tx) = submitter.pending_transaction(client, latest).await? else {
+            return Ok(None);
+        };
+        if bump_percent > 0 && bumps < MAX_TX_RESUBMIT_BUMPS {
+            bumps += 1;
+            match submitter
+                .resubmit(client, bumped_replacement(tx, bump_percent))
+                .await
+            {
+                Ok(replacement) => {
+                    warn!(tx_hash = ?latest, ?replacement, bumps, "transaction stuck, resubmitted with bumped fees");
+                    submitted.push(replacement);
+                    on_resubmit(replacement);
+                }
+                Err(err) => warn!(?err, tx_hash = ?latest, "failed to resubmit stuck transaction"),
+            }
+        } else if !gave_up {
+            error!(tx_hash = ?latest, bumps, "transaction still stuck, manual intervention required");
+            gave_up = true;
+        }
+    }
+}
+
+/// Follows the new block headers until `reorg_depth` blocks are mined on top
+/// of the block of `receipt`, checking on each of them that the transaction is
+/// still part of the canonical chain. When `polling`, the head block is polled
+/// at the interval of the provider instead of being subscribed to.
+///
+/// Returns the receipt of the re-confirmed transaction, whose block may have
+/// changed if the transaction was mined again after a reorg, or `None` if the
+/// transaction fell out of the canonical chain.
+pub(crate) async fn reconfirm_receipt<P: PubsubClient>(
+    provider: &Provider<P>,
+    mut receipt: TransactionReceipt,
+    reorg_depth: u64,
+    polling: bool,
+) -> Result<Option<TransactionReceipt>, ProviderError> {
+    let tx_hash = receipt.transaction_hash;
+    let mut blocks = match polling {
+        true => None,
+        false => Some(provider.subscribe_blocks().await?),
+    };
+    loop {
+        match provider.get_transaction_receipt(tx_hash).await? {
+            Some(current) if current.block_number.is_some() => receipt = current,
+            _ => return Ok(None),
+        }
+        let mined_at = receipt.block_number.expect("mined receipt").as_u64();
+        if provider.get_block_number().await?.as_u64() >= mined_at + reorg_depth {
+            return Ok(Some(receipt));
+        }
+        match blocks.as_mut() {
+            Some(blocks) => {
+                if blocks.next().await.is_none() {
+                    return Err(ProviderError::CustomError(
+                        "block header subscription ended".to_string(),
+                    ));
+                }
+            }
+            None => tokio::time::sleep(provider.get_interval()).await,
+        }
+    }
+}
+
+/// Nonce of the next transaction of the relayer wallet, counting its pending
+/// transactions, so that relay transactions sent before the previous ones are
+/// mined, e.g. to another relay contract, don't reuse their nonce.
+async fn next_nonce(client: &EthersClient) -> Result<U256, BonsaiCompleteProofManagerError> {
+    client
+        .get_transaction_count(client.address(), Some(BlockNumber::Pending.into()))
+        .await
+        .map_err(ethers_error)
+}
+
+pub(crate) fn ethers_error(
+    error: impl std::error::Error + Send + Sync + 'static,
+) -> BonsaiCompleteProofManagerError {
+    BonsaiCompleteProofManagerError::Ethers {
+        source: Box::new(error),
+    }
+}
+
+/// Wraps an error of the client sending a transaction, telling the failures
+/// of its signer apart from the ones of the Ethereum node.
+pub(crate) fn send_error(
+    error: SignerMiddlewareError<EthersProvider, RelaySigner>,
+) -> BonsaiCompleteProofManagerError {
+    match error {
+        SignerMiddlewareError::SignerError(source) => BonsaiCompleteProofManagerError::Signer {
+            source: Box::new(source),
+        },
+        error => ethers_error(error),
+    }
+}
@@ -0,0 +1,362 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Pool of the keys signing the relay transactions of a chain. Each relay
+//! transaction is signed by the key with the fewest transactions in flight,
+//! so that the sequential nonces of a single key don't cap the number of
+//! relay transactions sent at once, and a key that fails is removed from
+//! rotation rather than failing the requests assigned to it.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ethers::{prelude::*, utils::format_ether};
+use tracing::{error, info, warn};
+
+use crate::{
+    client_config::EthersClientConfig,
+    events::{self, EventSender, RelayerEvent},
+    metrics::Metrics,
+    networks::Network,
+    uploader::completed_proofs::relay::EthersClient,
+    RelayError,
+};
+
+/// A key of the pool.
+struct PooledKey {
+    config: EthersClientConfig,
+    /// Address of the key, once it signed.
+    address: Option<Address>,
+    /// Relay transactions sent with the key awaiting confirmation.
+    in_flight: usize,
+    /// Held while sending a relay transaction with the key, so that its
+    /// transactions get consecutive nonces.
+    send_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Whether the balance of the key was below the minimum when last
+    /// checked.
+    low_balance: bool,
+    /// Whether the key failed and was removed from rotation.
+    removed: bool,
+}
+
+impl PooledKey {
+    fn record_in_flight(&self, chain_id: u64) {
+        if let Some(address) = self.address {
+            Metrics::global().set_signer_in_flight(chain_id, address, self.in_flight);
+        }
+    }
+}
+
+struct PoolState {
+    keys: Vec<PooledKey>,
+    /// Index of the key after the one last assigned a transaction, from
+    /// which the ties between the least-loaded keys are broken.
+    next: usize,
+}
+
+/// The keys signing the relay transactions of a chain, shared by the relay
+/// transactions in flight.
+#[derive(Clone)]
+pub(crate) struct SignerPool {
+    chain_id: u64,
+    state: Arc<Mutex<PoolState>>,
+}
+
+/// Failure to create the client of a key of the pool.
+#[derive(Debug)]
+pub(crate) enum SignerClientError {
+    /// The Ethereum node could not be reached.
+    Node(RelayError),
+    /// The key could not sign, e.g. as its KMS key is disabled.
+    Key(RelayError),
+}
+
+/// A key of the pool assigned a relay transaction, counted in flight until
+/// dropped.
+pub(crate) struct Signer {
+    pool: SignerPool,
+    index: usize,
+    config: EthersClientConfig,
+    send_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SignerPool {
+    /// A pool of the wallet key and the additional wallet keys of the client
+    /// config.
+    pub(crate) fn new(client_config: &EthersClientConfig) -> Self {
+        let keys = client_config
+            .signer_configs()
+            .into_iter()
+            .map(|config| PooledKey {
+                config,
+                address: None,
+                in_flight: 0,
+                send_lock: Default::default(),
+                low_balance: false,
+                removed: false,
+            })
+            .collect();
+        Self {
+            chain_id: client_config.eth_chain_id,
+            state: Arc::new(Mutex::new(PoolState { keys, next: 0 })),
+        }
+    }
+
+    /// A pool of the keys of the client config, checked on startup: none of
+    /// them may be an Anvil key outside of Anvil, hardware wallets must be
+    /// unlocked, and the Ethereum node must serve the chain. The relay
+    /// contracts without code and the keys whose balance is below
+    /// `min_eth_balance` are reported. Keys that can't sign yet are checked
+    /// again on their first relay transaction.
+    pub(crate) async fn connect(
+        client_config: &EthersClientConfig,
+        relay_contract_addresses: &[Address],
+        min_eth_balance: U256,
+    ) -> Result<Self, RelayError> {
+        let pool = Self::new(client_config);
+        let chain_id = pool.chain_id;
+        let mut addresses = Vec::new();
+        for (index, config) in client_config.signer_configs().iter().enumerate() {
+            let key = &config.wallet_key_identifier;
+            if chain_id != Network::Anvil.chain_id() && key.is_anvil_key() {
+                return Err(RelayError::ConfigError(format!(
+                    "Key {index} of chain {chain_id} is an Anvil account key, which anyone can \
+                     sign with."
+                )));
+            }
+            let client = match config.get_client().await {
+                Ok(client) => client,
+                // Hardware wallets are unlocked on startup rather than on the
+                // first relay transaction.
+                Err(err) if key.is_hardware_wallet() => return Err(err),
+                Err(error) => {
+                    warn!(
+                        chain_id,
+                        key = index,
+                        %error,
+                        "Failed to check the signer, checking it again on its first relay \
+                         transaction"
+                    );
+                    continue;
+                }
+            };
+            let address = client.address();
+            pool.set_address(index, address);
+            addresses.push(address);
+            if key.is_hardware_wallet() {
+                info!(
+                    chain_id,
+                    signer = ?address,
+                    "Relaying one transaction at a time with the hardware wallet"
+                );
+            }
+        }
+
+        // A single JSON-RPC batch with `--json-rpc-batch`. A node that can't be
+        // reached is reached again by the tasks of the chain.
+        let state = match client_config
+            .chain_state(relay_contract_addresses, &addresses)
+            .await
+        {
+            Ok(state) => state,
+            Err(error) => {
+                warn!(chain_id, %error, "Failed to check the Ethereum node on startup");
+                return Ok(pool);
+            }
+        };
+        if state.chain_id != chain_id.into() {
+            return Err(RelayError::ConfigError(format!(
+                "The Ethereum node serves chain {}, not the configured chain {chain_id}.",
+                state.chain_id
+            )));
+        }
+        for (relay_contract_address, code) in relay_contract_addresses.iter().zip(state.codes) {
+            match code {
+                Ok(code) if code.is_empty() => warn!(
+                    chain_id,
+                    ?relay_contract_address,
+                    "No contract deployed at the relay contract address"
+                ),
+                Ok(_) => {}
+                Err(error) => warn!(
+                    chain_id,
+                    ?relay_contract_address,
+                    %error,
+                    "Failed to read the relay contract code"
+                ),
+            }
+        }
+        for (address, balance) in addresses.into_iter().zip(state.balances) {
+            match balance {
+                Ok(balance) => {
+                    Metrics::global().set_signer_balance(chain_id, address, balance);
+                    if balance < min_eth_balance {
+                        warn!(
+                            chain_id,
+                            signer = ?address,
+                            balance = %format_ether(balance),
+                            min_eth_balance = %format_ether(min_eth_balance),
+                            "Signer balance below the minimum, not sending relay transactions \
+                             with it until topped up"
+                        );
+                    }
+                }
+                Err(error) => warn!(
+                    chain_id,
+                    signer = ?address,
+                    %error,
+                    "Failed to check the signer balance"
+                ),
+            }
+        }
+        Ok(pool)
+    }
+
+    /// Assign a relay transaction to the key in rotation with the fewest
+    /// transactions in flight, other than the `skipped` ones. Ties go to the
+    /// keys in turn.
+    pub(crate) fn assign(&self, skipped: &[usize]) -> Option<Signer> {
+        let mut state = self.lock();
+        let count = state.keys.len();
+        let index = (0..count)
+            .map(|offset| (state.next + offset) % count)
+            .filter(|index| !state.keys[*index].removed && !skipped.contains(index))
+            .min_by_key(|index| state.keys[*index].in_flight)?;
+        state.next = (index + 1) % count;
+        let key = &mut state.keys[index];
+        key.in_flight += 1;
+        key.record_in_flight(self.chain_id);
+        Some(Signer {
+            pool: self.clone(),
+            index,
+            config: key.config.clone(),
+            send_lock: key.send_lock.clone(),
+        })
+    }
+
+    /// Returns the number of keys in rotation.
+    pub(crate) fn active(&self) -> usize {
+        self.lock().keys.iter().filter(|key| !key.removed).count()
+    }
+
+    /// Returns the number of relay transactions in flight, by key.
+    pub(crate) fn in_flight(&self) -> Vec<usize> {
+        self.lock().keys.iter().map(|key| key.in_flight).collect()
+    }
+
+    fn set_address(&self, index: usize, address: Address) {
+        let mut state = self.lock();
+        let key = &mut state.keys[index];
+        key.address = Some(address);
+        key.record_in_flight(self.chain_id);
+    }
+
+    fn release(&self, index: usize) {
+        let mut state = self.lock();
+        let key = &mut state.keys[index];
+        key.in_flight = key.in_flight.saturating_sub(1);
+        key.record_in_flight(self.chain_id);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Signer {
+    /// Index of the key in the pool.
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Held while sending a relay transaction with the key.
+    pub(crate) fn send_lock(&self) -> &tokio::sync::Mutex<()> {
+        &self.send_lock
+    }
+
+    /// Returns a client signing with the key.
+    pub(crate) async fn client(&self) -> Result<EthersClient, SignerClientError> {
+        let provider = self
+            .config
+            .provider()
+            .await
+            .map_err(SignerClientError::Node)?;
+        let signer = self
+            .config
+            .get_signer()
+            .await
+            .map_err(SignerClientError::Key)?;
+        let client = SignerMiddleware::new(provider, signer);
+        self.pool.set_address(self.index, client.address());
+        Ok(client)
+    }
+
+    /// Record whether the balance of the key is below the minimum, returning
+    /// whether it changed.
+    pub(crate) fn set_low_balance(&self, low_balance: bool) -> bool {
+        let mut state = self.pool.lock();
+        let key = &mut state.keys[self.index];
+        let changed = key.low_balance != low_balance;
+        key.low_balance = low_balance;
+        changed
+    }
+
+    /// Remove the key from rotation after it failed with `error`, alerting
+    /// on it. The relay transactions already assigned to it are kept.
+    pub(crate) fn remove(
+        &self,
+        error: &(dyn std::error::Error + 'static),
+        events: &Option<EventSender>,
+    ) {
+        let chain_id = self.pool.chain_id;
+        let (address, remaining) = {
+            let mut state = self.pool.lock();
+            let key = &mut state.keys[self.index];
+            if key.removed {
+                return;
+            }
+            key.removed = true;
+            let address = key.address;
+            let remaining = state.keys.iter().filter(|key| !key.removed).count();
+            (address, remaining)
+        };
+        let error = events::error_chain(error);
+        error!(
+            chain_id,
+            key = self.index,
+            signer = ?address,
+            %error,
+            remaining,
+            "signer failed, removed from rotation"
+        );
+        Metrics::global().record_signer_removed(chain_id);
+        events::emit(
+            events,
+            RelayerEvent::SignerRemoved {
+                timestamp: events::now(),
+                chain_id,
+                signer: address,
+                error,
+                remaining,
+            },
+        );
+    }
+}
+
+impl Drop for Signer {
+    fn drop(&mut self) {
+        self.pool.release(self.index);
+    }
+}
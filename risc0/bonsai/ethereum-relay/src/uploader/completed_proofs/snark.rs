@@ -14,17 +14,20 @@
 
 use std::time::Duration;
 
+use anyhow::{bail, Context};
+use ark_bn254::{Fq, Fq2, G1Affine, G2Affine};
+use ark_ff::{BigInt, PrimeField};
 use bonsai_sdk::{
     alpha::{responses::SnarkProof, Client, SessionId, SnarkId},
     alpha_async::{create_snark, snark_status},
 };
 use ethers::{
-    abi::{Token, Tokenizable},
-    types::U256,
+    abi::{self, ParamType, Token, Tokenizable},
+    types::{Bytes, U256},
 };
 
 use super::error::CompleteProofError;
-use crate::api;
+use crate::{api, RelayError};
 
 pub(crate) async fn get_snark_id(
     client: Client,
@@ -79,7 +82,8 @@ pub(crate) fn parse_to_tokens(slice: &[String]) -> anyhow::Result<Token> {
     ))
 }
 
-pub(crate) fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token> {
+/// Check that the proof holds two coordinates for each of its points.
+fn check_proof_shape(proof: &SnarkProof) -> anyhow::Result<()> {
     if proof.b.len() != 2 {
         anyhow::bail!("hex-strings encoded proof is not well formed");
     }
@@ -88,6 +92,11 @@ pub(crate) fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token>
             anyhow::bail!("hex-strings encoded proof is not well formed");
         }
     }
+    Ok(())
+}
+
+pub(crate) fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token> {
+    check_proof_shape(proof)?;
     Ok(Token::FixedArray(vec![
         parse_to_tokens(&proof.a)?,
         Token::FixedArray(vec![
@@ -97,3 +106,108 @@ pub(crate) fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token>
         parse_to_tokens(&proof.c)?,
     ]))
 }
+
+/// Number of uint256 coordinates of the proof points `a`, `b` and `c`.
+const PROOF_COORDINATES: usize = 8;
+
+/// ABI encode the proof points as the seal of a relay contract callback.
+pub fn proof_to_calldata(proof: &SnarkProof) -> Result<Bytes, RelayError> {
+    let token = tokenize_snark_proof(proof).map_err(proof_encoding_error)?;
+    Ok(abi::encode(&[token]).into())
+}
+
+/// Decode a seal encoded by [proof_to_calldata] back into the proof points,
+/// as lowercase hex strings. The public inputs are not part of the seal, and
+/// are left empty.
+pub fn calldata_to_proof(bytes: &[u8]) -> Result<SnarkProof, RelayError> {
+    decode_proof_points(bytes).map_err(proof_encoding_error)
+}
+
+fn proof_encoding_error(err: anyhow::Error) -> RelayError {
+    RelayError::ProofEncodingError(format!("{err:#}"))
+}
+
+fn decode_proof_points(bytes: &[u8]) -> anyhow::Result<SnarkProof> {
+    if bytes.len() != PROOF_COORDINATES * 32 {
+        bail!(
+            "encoded proof is {} bytes long, expected {}",
+            bytes.len(),
+            PROOF_COORDINATES * 32
+        );
+    }
+    let tokens = abi::decode(
+        &[ParamType::FixedArray(
+            Box::new(ParamType::Uint(256)),
+            PROOF_COORDINATES,
+        )],
+        bytes,
+    )?;
+    let coordinates = tokens
+        .into_iter()
+        .next()
+        .and_then(Token::into_fixed_array)
+        .context("encoded proof is not an array of uint256")?
+        .into_iter()
+        .map(|token| {
+            token
+                .into_uint()
+                .map(|value| format!("{value:x}"))
+                .context("encoded proof is not an array of uint256")
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(SnarkProof {
+        a: coordinates[0..2].to_vec(),
+        b: vec![coordinates[2..4].to_vec(), coordinates[4..6].to_vec()],
+        c: coordinates[6..8].to_vec(),
+        public: vec![],
+    })
+}
+
+/// Parse a proof coordinate as an element of the BN254 base field.
+fn parse_field_element(component: &str, value: &str) -> anyhow::Result<Fq> {
+    let value = U256::from_str_radix(value, 16)
+        .with_context(|| format!("proof component {component} is not a valid uint256: {value}"))?;
+    Fq::from_bigint(BigInt::new(value.0)).with_context(|| {
+        format!("proof component {component} is not below the BN254 base field modulus")
+    })
+}
+
+/// Parse a pair of proof coordinates as an element of the BN254 quadratic
+/// extension field. Following EIP-197, the imaginary part comes first.
+fn parse_extension_field_element(component: &str, pair: &[String]) -> anyhow::Result<Fq2> {
+    Ok(Fq2::new(
+        parse_field_element(&format!("{component}.c0"), &pair[1])?,
+        parse_field_element(&format!("{component}.c1"), &pair[0])?,
+    ))
+}
+
+/// Validate that the proof points `a` and `c` are on the BN254 curve, and that
+/// the proof point `b` is on its twist and in the G2 subgroup, so that a
+/// malformed proof fails here rather than in the on-chain pairing check.
+pub fn validate_snark_proof(proof: &SnarkProof) -> Result<(), RelayError> {
+    check_proof_points(proof).map_err(proof_encoding_error)
+}
+
+fn check_proof_points(proof: &SnarkProof) -> anyhow::Result<()> {
+    check_proof_shape(proof)?;
+    for (name, point) in [("a", &proof.a), ("c", &proof.c)] {
+        let point = G1Affine::new_unchecked(
+            parse_field_element(&format!("{name}.x"), &point[0])?,
+            parse_field_element(&format!("{name}.y"), &point[1])?,
+        );
+        if !point.is_on_curve() {
+            bail!("proof point {name} is not on the BN254 curve");
+        }
+    }
+    let b = G2Affine::new_unchecked(
+        parse_extension_field_element("b.x", &proof.b[0])?,
+        parse_extension_field_element("b.y", &proof.b[1])?,
+    );
+    if !b.is_on_curve() {
+        bail!("proof point b is not on the BN254 twist curve");
+    }
+    if !b.is_in_correct_subgroup_assuming_on_curve() {
+        bail!("proof point b is not in the BN254 G2 subgroup");
+    }
+    Ok(())
+}
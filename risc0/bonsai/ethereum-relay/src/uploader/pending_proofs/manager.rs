@@ -12,18 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Instant};
 
-use bonsai_sdk::alpha::Client;
+use bonsai_sdk::alpha_async::{stop_session, RoundRobinBonsaiClient};
+use ethers::types::Address;
 use futures::{stream::FuturesUnordered, StreamExt};
 use tokio::{
     sync::Notify,
     task::{JoinError, JoinHandle},
 };
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    storage::{Error as StorageError, ProofRequestState, Storage},
+    bonsai_transport::BonsaiTransport,
+    events::{self, EventSender, RelayerEvent},
+    metrics::Metrics,
+    scheduler::Scheduler,
+    storage::{dead_letters::FailureStage, Error as StorageError, ProofRequestState, Storage},
     uploader::pending_proofs::pending_proof_request_future::{
         Error as PendingProofError, PendingProofRequest, ProofRequestID,
     },
@@ -40,38 +45,105 @@ pub(crate) enum BonsaiPendingProofManagerError {
 }
 
 pub(crate) struct BonsaiPendingProofManager<S: Storage> {
-    client: Client,
+    client: RoundRobinBonsaiClient,
+    /// Transport polling the sessions, the REST API of `client` by default.
+    transport: Arc<dyn BonsaiTransport>,
     storage: S,
     new_pending_proof_request_notifier: Arc<Notify>,
-    complete_proof_manager_notifier: Arc<Notify>,
+    /// Notifiers of the complete proof managers, one per chain, each picking
+    /// the completed proofs of its chain.
+    complete_proof_manager_notifiers: Vec<Arc<Notify>>,
     futures_set: FuturesUnordered<JoinHandle<Result<ProofRequestID, PendingProofError>>>,
+    events: Option<EventSender>,
+    /// Stop the Bonsai session of a request whose deadline passes while it is
+    /// being proven.
+    cancel_expired_sessions: bool,
+    /// Chain and relay contract of the requests being proven with a deadline,
+    /// by session UUID.
+    expiring: HashMap<String, (u64, Address)>,
+    /// Scheduler of the sessions, told when they are no longer proving.
+    scheduler: Scheduler,
 }
 
 impl<S: Storage> BonsaiPendingProofManager<S> {
     pub(crate) fn new(
-        client: Client,
+        client: RoundRobinBonsaiClient,
         storage: S,
         new_pending_proof_request_notifier: Arc<Notify>,
-        complete_proof_manager_notifier: Arc<Notify>,
+        complete_proof_manager_notifiers: Vec<Arc<Notify>>,
+        events: Option<EventSender>,
     ) -> Self {
         Self {
+            transport: Arc::new(client.clone()),
             client,
             storage,
             new_pending_proof_request_notifier,
-            complete_proof_manager_notifier,
+            complete_proof_manager_notifiers,
             futures_set: FuturesUnordered::new(),
+            events,
+            cancel_expired_sessions: false,
+            expiring: HashMap::new(),
+            scheduler: Scheduler::default(),
+        }
+    }
+
+    /// Free the sessions handed out by the given scheduler once they are no
+    /// longer proving.
+    pub(crate) fn with_scheduler(self, scheduler: Scheduler) -> Self {
+        Self { scheduler, ..self }
+    }
+
+    /// Poll the sessions through `transport`, e.g. the gRPC API of Bonsai.
+    pub(crate) fn with_transport(self, transport: Arc<dyn BonsaiTransport>) -> Self {
+        Self { transport, ..self }
+    }
+
+    /// Stop the Bonsai session of a request whose deadline passes before it
+    /// is proven, rather than letting it run to completion.
+    pub(crate) fn with_cancel_expired_sessions(self, cancel_expired_sessions: bool) -> Self {
+        Self {
+            cancel_expired_sessions,
+            ..self
         }
     }
 
     async fn process_new_pending_proof_requests(
         &mut self,
     ) -> Result<(), BonsaiPendingProofManagerError> {
-        let pending_proof_requests = self.storage.fetch_new_bonsai_requests(None).await?;
+        let mut pending_proof_requests = self.storage.fetch_new_bonsai_requests(None).await?;
+        // Pick the requests by earliest deadline, those without one last.
+        pending_proof_requests
+            .sort_by_key(|request| (request.deadline.is_none(), request.deadline));
 
+        let now = Instant::now();
         for request in pending_proof_requests.into_iter() {
+            if matches!(request.deadline, Some(deadline) if deadline <= now) {
+                self.expire_request(
+                    request.proof_request_id,
+                    request.chain_id,
+                    request.relay_contract_address,
+                )
+                .await?;
+                continue;
+            }
+
             let pending_proof_request =
-                PendingProofRequest::new(self.client.clone(), request.proof_request_id.clone());
-            let pending_proof_request_handler = tokio::spawn(pending_proof_request);
+                PendingProofRequest::new(self.transport.clone(), request.proof_request_id.clone());
+            let pending_proof_request_handler = match request.deadline {
+                Some(deadline) => {
+                    self.expiring.insert(
+                        request.proof_request_id.uuid.clone(),
+                        (request.chain_id, request.relay_contract_address),
+                    );
+                    let id = request.proof_request_id.clone();
+                    tokio::spawn(async move {
+                        tokio::time::timeout_at(deadline.into(), pending_proof_request)
+                            .await
+                            .unwrap_or(Err(PendingProofError::Expired { id }))
+                    })
+                }
+                None => tokio::spawn(pending_proof_request),
+            };
             self.futures_set.push(pending_proof_request_handler);
 
             self.storage
@@ -88,26 +160,128 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
         Ok(())
     }
 
-    pub(crate) async fn handle_pending_proof_result(
+    /// Expire a request whose deadline passed before it was proven, stopping
+    /// its Bonsai session if expired sessions are cancelled.
+    async fn expire_request(
         &self,
+        proof_request_id: ProofRequestID,
+        chain_id: u64,
+        relay_contract_address: Address,
+    ) -> Result<(), BonsaiPendingProofManagerError> {
+        self.storage
+            .transition_proof_request(proof_request_id.clone(), ProofRequestState::Expired)
+            .await?;
+        self.scheduler.finish(&proof_request_id.uuid);
+        Metrics::global().record_expired(chain_id, relay_contract_address);
+
+        if self.cancel_expired_sessions {
+            if let Err(err) =
+                stop_session(self.client.primary().clone(), proof_request_id.clone()).await
+            {
+                warn!(
+                    ?proof_request_id,
+                    "failed to stop the Bonsai session of an expired request: {err}"
+                );
+            }
+        }
+
+        events::emit(
+            &self.events,
+            RelayerEvent::RequestFailed {
+                timestamp: events::now(),
+                session_id: Some(proof_request_id.uuid.clone()),
+                error: "deadline passed before the request was proven".to_string(),
+            },
+        );
+        let log_id = proof_request_id;
+        info!(?log_id, chain_id, relay = ?relay_contract_address, "proof request expired");
+        Ok(())
+    }
+
+    pub(crate) async fn handle_pending_proof_result(
+        &mut self,
         pending_proof_result: Result<ProofRequestID, PendingProofError>,
     ) -> Result<(), BonsaiPendingProofManagerError> {
-        let (completed_proof_id, state) = match pending_proof_result {
-            Ok(session_id) => (session_id, ProofRequestState::Completed),
-            Err(err) => (err.get_proof_request_id(), ProofRequestState::Failed),
+        let expiring = match &pending_proof_result {
+            Ok(id) => self.expiring.remove(&id.uuid),
+            Err(err) => self.expiring.remove(&err.id().uuid),
         };
+        if let (Err(PendingProofError::Expired { id }), Some((chain_id, relay))) =
+            (&pending_proof_result, expiring)
+        {
+            return self.expire_request(id.clone(), chain_id, relay).await;
+        }
 
-        self.storage
-            .transition_proof_request(completed_proof_id.clone(), ProofRequestState::Completed)
-            .await?;
+        let (completed_proof_id, state, error) = match pending_proof_result {
+            Ok(session_id) => (session_id, ProofRequestState::Completed, None),
+            Err(err) => {
+                let error = err.to_string();
+                (
+                    err.get_proof_request_id(),
+                    ProofRequestState::Failed,
+                    Some(error),
+                )
+            }
+        };
+
+        self.scheduler.finish(&completed_proof_id.uuid);
+
+        // Failed requests are kept in the dead-letter list, to be proven again.
+        match &error {
+            None => {
+                self.storage
+                    .transition_proof_request(completed_proof_id.clone(), state)
+                    .await?
+            }
+            Some(error) => {
+                self.storage
+                    .fail_proof_request(
+                        completed_proof_id.clone(),
+                        FailureStage::Proving,
+                        error.clone(),
+                    )
+                    .await?
+            }
+        }
 
         let log_id = completed_proof_id.clone();
         match state {
             ProofRequestState::Completed => {
-                self.complete_proof_manager_notifier.notify_one();
+                for notifier in self.complete_proof_manager_notifiers.iter() {
+                    notifier.notify_one();
+                }
+                events::emit(
+                    &self.events,
+                    RelayerEvent::ProofReady {
+                        timestamp: events::now(),
+                        session_id: completed_proof_id.uuid,
+                    },
+                );
                 info!(?log_id, "pending proof done");
             }
-            _ => info!(?log_id, "pending proof failed"),
+            _ => {
+                let error = error.unwrap_or_default();
+                events::emit(
+                    &self.events,
+                    RelayerEvent::RelayError {
+                        timestamp: events::now(),
+                        error: format!(
+                            "Bonsai session {} failed: {error}",
+                            completed_proof_id.uuid
+                        ),
+                        tx_hash: None,
+                    },
+                );
+                events::emit(
+                    &self.events,
+                    RelayerEvent::RequestFailed {
+                        timestamp: events::now(),
+                        session_id: Some(completed_proof_id.uuid),
+                        error,
+                    },
+                );
+                info!(?log_id, "pending proof failed")
+            }
         }
 
         Ok(())
@@ -142,6 +316,14 @@ impl<S: Storage> BonsaiPendingProofManager<S> {
                         "error occurred managing pending proof requests: {:?}",
                         source
                     );
+                    events::emit(
+                        &self.events,
+                        RelayerEvent::RelayError {
+                            timestamp: events::now(),
+                            error: events::error_chain(&source),
+                            tx_hash: None,
+                        },
+                    );
                     // Store the proof as new so that it can be retried.
                     //
                     // TODO: What do we do if this call to storage fails?
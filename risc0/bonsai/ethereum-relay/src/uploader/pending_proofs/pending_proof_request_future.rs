@@ -12,19 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::pin::Pin;
+use std::{pin::Pin, sync::Arc};
 
-use bonsai_sdk::{
-    alpha::{responses::SessionStatusRes, Client, SessionId},
-    alpha_async::session_status,
-};
+use bonsai_sdk::alpha::{responses::SessionStatusRes, SessionId};
 use futures::{
     task::{Context, Poll},
     Future,
 };
 use pin_project::pin_project;
 
-use crate::{api, api::error::Error as BonsaiError};
+use crate::{api, api::error::Error as BonsaiError, bonsai_transport::BonsaiTransport};
 
 pub type ProofRequestID = SessionId;
 
@@ -38,13 +35,24 @@ pub(crate) enum Error {
     },
     #[error("Proof Request {:?} Failed: {}", id, status)]
     ProofRequestError { status: String, id: ProofRequestID },
+    #[error("Proof Request {:?} expired before being proven", id)]
+    Expired { id: ProofRequestID },
 }
 
 impl Error {
+    pub(crate) fn id(&self) -> &ProofRequestID {
+        match self {
+            Error::ClientAPI { source: _, id } => id,
+            Error::ProofRequestError { status: _, id } => id,
+            Error::Expired { id } => id,
+        }
+    }
+
     pub(crate) fn get_proof_request_id(self) -> ProofRequestID {
         match self {
             Error::ClientAPI { source: _, id } => id,
             Error::ProofRequestError { status: _, id } => id,
+            Error::Expired { id } => id,
         }
     }
 }
@@ -61,13 +69,13 @@ enum PendingProofRequestState {
 
 #[pin_project]
 pub(crate) struct PendingProofRequest {
-    bonsai_client: Client,
+    bonsai_client: Arc<dyn BonsaiTransport>,
     pending_proof_id: ProofRequestID,
     state: PendingProofRequestState,
 }
 
 impl PendingProofRequest {
-    pub fn new(bonsai_client: Client, pending_proof_id: ProofRequestID) -> Self {
+    pub fn new(bonsai_client: Arc<dyn BonsaiTransport>, pending_proof_id: ProofRequestID) -> Self {
         Self {
             bonsai_client,
             pending_proof_id,
@@ -130,10 +138,11 @@ impl Future for PendingProofRequest {
 // future still needing it. Moving the function outside and not taking &self as
 // a parameter fixes the issue
 async fn get_receipt_info(
-    bonsai_client: Client,
+    bonsai_client: Arc<dyn BonsaiTransport>,
     session: SessionId,
 ) -> Result<SessionStatusRes, Error> {
-    session_status(bonsai_client, session.clone())
+    bonsai_client
+        .get_session(session.clone())
         .await
         .map_err(|e| Error::ClientAPI {
             source: api::error::Error::Bonsai(e),
@@ -24,10 +24,13 @@ mod tests {
     use bonsai_ethereum_contracts::{BonsaiRelay, BonsaiTestRelay, RiscZeroGroth16Verifier};
     use bonsai_ethereum_relay::{
         sdk::{
-            client::{CallbackRequest, Client},
+            client::{CallbackRequest, Client, ProofSubmission, SubmittedProof},
             utils,
         },
-        Relayer,
+        EthersClientConfig, KmsConfig, RelaySigner, Relayer, WalletKeyIdentifier,
+        DEFAULT_MAX_CONCURRENT_RELAYS, DEFAULT_MAX_DEAD_LETTERS, DEFAULT_MAX_REQUEST_BODY_SIZE,
+        DEFAULT_RELAY_QUEUE_DEPTH, DEFAULT_SHUTDOWN_TIMEOUT, DEFAULT_STUCK_TX_TIMEOUT,
+        DEFAULT_TX_RESUBMIT_BUMP,
     };
     use bonsai_sdk::{
         alpha::{Client as BonsaiClient, SdkErr},
@@ -36,9 +39,11 @@ mod tests {
     use ethers::types::{Bytes, H256 as ethers_H256, U256};
     use risc0_zkvm::{MemoryImage, Program, MEM_SIZE, PAGE_SIZE};
     use risc0_zkvm_methods::{SLICE_IO_ELF, SLICE_IO_ID};
+    use rusoto_kms::{CreateKeyRequest, Kms};
     use tokio::time::{sleep, Duration};
 
     const BONSAI_API_URI: &str = "http://localhost:8081";
+    const LOCALSTACK_ENDPOINT: &str = "http://localhost:4566";
 
     /// Check for the RISC0_DEV_MODE environment variable:
     /// * Return true if the value is "true" or it is not set.
@@ -144,10 +149,51 @@ mod tests {
         let relayer = Relayer {
             rest_api: false,
             dev_mode: dev_mode().unwrap(),
+            rest_api_bind: "0.0.0.0".to_string(),
             rest_api_port: "8080".to_string(),
+            rest_api_tls: None,
             bonsai_api_url: get_bonsai_url(),
+            bonsai_api_urls: Vec::new(),
+            grpc: false,
             bonsai_api_key: get_api_key(),
-            relay_contract_address: bonsai_relay_contract,
+            relay_contract_addresses: vec![bonsai_relay_contract],
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            safe: None,
+            image_ids: vec![],
+            skip_proof_validation: false,
+            min_eth_balance: U256::zero(),
+            fee_cap: None,
+            retry_on_revert: 0,
+            stuck_tx_timeout: DEFAULT_STUCK_TX_TIMEOUT,
+            tx_resubmit_bump: DEFAULT_TX_RESUBMIT_BUMP,
+            reorg_depth: 0,
+            max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+            relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+            max_daily_spend: None,
+            callback_gas_limits: None,
+            private_relay: None,
+            watch_gas_price: None,
+            journal_filters: Vec::new(),
+            events: None,
+            submission_only: false,
+            replay_events: None,
+            start_block: None,
+            end_block: None,
+            alert_webhook: None,
+            alert_cmd: None,
+            images: Vec::new(),
+            admin_api_key: None,
+            chains: Vec::new(),
+            config_file: None,
+            event_persistence_db: None,
+            prune_events_older_than: None,
+            request_max_age: None,
+            cancel_expired_sessions: false,
+            max_pending_requests: None,
+            max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+            rest_api_rate_limit: None,
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            rest_api_docs: false,
         };
 
         dbg!("starting bonsai relayer");
@@ -293,10 +339,51 @@ mod tests {
         let relayer = Relayer {
             rest_api: true,
             dev_mode: dev_mode().unwrap(),
+            rest_api_bind: "0.0.0.0".to_string(),
             rest_api_port: "8080".to_string(),
+            rest_api_tls: None,
             bonsai_api_url: get_bonsai_url(),
+            bonsai_api_urls: Vec::new(),
+            grpc: false,
             bonsai_api_key: get_api_key(),
-            relay_contract_address: bonsai_relay_contract,
+            relay_contract_addresses: vec![bonsai_relay_contract],
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            safe: None,
+            image_ids: vec![],
+            skip_proof_validation: false,
+            min_eth_balance: U256::zero(),
+            fee_cap: None,
+            retry_on_revert: 0,
+            stuck_tx_timeout: DEFAULT_STUCK_TX_TIMEOUT,
+            tx_resubmit_bump: DEFAULT_TX_RESUBMIT_BUMP,
+            reorg_depth: 0,
+            max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+            relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+            max_daily_spend: None,
+            callback_gas_limits: None,
+            private_relay: None,
+            watch_gas_price: None,
+            journal_filters: Vec::new(),
+            events: None,
+            submission_only: false,
+            replay_events: None,
+            start_block: None,
+            end_block: None,
+            alert_webhook: None,
+            alert_cmd: None,
+            images: Vec::new(),
+            admin_api_key: None,
+            chains: Vec::new(),
+            config_file: None,
+            event_persistence_db: None,
+            prune_events_older_than: None,
+            request_max_age: None,
+            cancel_expired_sessions: false,
+            max_pending_requests: None,
+            max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+            rest_api_rate_limit: None,
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            rest_api_docs: false,
         };
 
         dbg!("starting bonsai relayer");
@@ -334,6 +421,9 @@ mod tests {
             gas_limit,
             image_id,
             input,
+            relay_address: None,
+            chain_id: None,
+            deadline: None,
         };
 
         let relay_client =
@@ -375,4 +465,219 @@ mod tests {
         }
         assert_eq!(value, expected_value)
     }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    #[ignore]
+    async fn e2e_test_reload_image_ids_on_sighup() {
+        // Get Anvil
+        let anvil = utils::get_anvil();
+        // Get client config
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let ethers_client = Arc::new(
+            ethers_client_config
+                .get_client()
+                .await
+                .expect("Failed to get ethers client"),
+        );
+        let bonsai_relay_contract =
+            BonsaiTestRelay::deploy(ethers_client.clone(), ethers_client.signer().chain_id())
+                .expect("should be able to deploy the BonsaiTestRelay contract")
+                .send()
+                .await
+                .expect("deployment should succeed")
+                .address();
+
+        // only the first image ID is allowed on start
+        let allowed = ethers_H256::repeat_byte(0x11);
+        let reloaded = ethers_H256::repeat_byte(0x22);
+        let config_file = std::env::temp_dir().join(format!(
+            "bonsai-relay-e2e-reload-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(&config_file, format!("image_ids = [\"{allowed:?}\"]\n"))
+            .expect("config file should be written");
+
+        // run the bonsai relayer, relaying submitted proofs
+        let relayer = Relayer {
+            rest_api: true,
+            dev_mode: dev_mode().unwrap(),
+            rest_api_bind: "0.0.0.0".to_string(),
+            rest_api_port: "8082".to_string(),
+            rest_api_tls: None,
+            bonsai_api_url: get_bonsai_url(),
+            bonsai_api_urls: Vec::new(),
+            grpc: false,
+            bonsai_api_key: get_api_key(),
+            relay_contract_addresses: vec![bonsai_relay_contract],
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            safe: None,
+            image_ids: vec![],
+            skip_proof_validation: false,
+            min_eth_balance: U256::zero(),
+            fee_cap: None,
+            retry_on_revert: 0,
+            stuck_tx_timeout: DEFAULT_STUCK_TX_TIMEOUT,
+            tx_resubmit_bump: DEFAULT_TX_RESUBMIT_BUMP,
+            reorg_depth: 0,
+            max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+            relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+            max_daily_spend: None,
+            callback_gas_limits: None,
+            private_relay: None,
+            watch_gas_price: None,
+            journal_filters: Vec::new(),
+            events: None,
+            submission_only: true,
+            replay_events: None,
+            start_block: None,
+            end_block: None,
+            alert_webhook: None,
+            alert_cmd: None,
+            images: Vec::new(),
+            admin_api_key: None,
+            chains: Vec::new(),
+            config_file: Some(config_file.clone()),
+            event_persistence_db: None,
+            prune_events_older_than: None,
+            request_max_age: None,
+            cancel_expired_sessions: false,
+            max_pending_requests: None,
+            max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+            rest_api_rate_limit: None,
+            max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+            rest_api_docs: false,
+        };
+        tokio::spawn(relayer.run(ethers_client_config.clone()));
+
+        // wait for relay to start
+        sleep(Duration::from_secs(2)).await;
+
+        let relay_client =
+            Client::from_parts("http://localhost:8082".to_string(), get_api_key()).unwrap();
+        let submission = ProofSubmission {
+            image_id: reloaded.0,
+            journal: vec![],
+            post_state_digest: [0; 32],
+            proof: SubmittedProof::Receipt(vec![]),
+            callback_contract: Address::repeat_byte(1),
+            function_selector: [0xab, 0xcd, 0xef, 0xab],
+            gas_limit: 3000000,
+            relay_address: None,
+            chain_id: None,
+        };
+        let err = relay_client
+            .submit_proof(submission.clone())
+            .await
+            .expect_err("the image ID should not be allowed");
+        assert!(err.to_string().contains("is not allowed"), "{err}");
+
+        // allow the other image ID, and signal the relayer to reload
+        std::fs::write(&config_file, format!("image_ids = [\"{reloaded:?}\"]\n"))
+            .expect("config file should be written");
+        let status = std::process::Command::new("kill")
+            .args(["-HUP", &std::process::id().to_string()])
+            .status()
+            .expect("kill should run");
+        assert!(status.success());
+        sleep(Duration::from_secs(1)).await;
+
+        // the proof now gets past the image ID check, to be rejected as invalid
+        let err = relay_client
+            .submit_proof(submission)
+            .await
+            .expect_err("the empty receipt should be rejected");
+        assert!(!err.to_string().contains("is not allowed"), "{err}");
+
+        std::fs::remove_file(&config_file).expect("config file should be removed");
+    }
+
+    /// Signs and sends a transaction on Anvil with a secp256k1 key of a
+    /// localstack KMS, at `LOCALSTACK_ENDPOINT` or http://localhost:4566.
+    /// The AWS credentials are read from the environment, e.g.
+    /// `AWS_ACCESS_KEY_ID=test AWS_SECRET_ACCESS_KEY=test`.
+    #[tokio::test]
+    #[ignore]
+    async fn e2e_test_kms_signer() {
+        let anvil = utils::get_anvil();
+        let ethers_client_config = utils::get_ethers_client_config(anvil.as_ref())
+            .await
+            .expect("Failed to get ethers client config");
+        let funder = ethers_client_config
+            .get_client()
+            .await
+            .expect("Failed to get ethers client");
+
+        let kms = KmsConfig {
+            region: Some("us-east-1".to_string()),
+            profile: None,
+            endpoint: Some(
+                std::env::var("LOCALSTACK_ENDPOINT")
+                    .unwrap_or_else(|_| LOCALSTACK_ENDPOINT.to_string()),
+            ),
+        };
+        let key_arn = kms
+            .client()
+            .expect("KMS client should be created")
+            .create_key(CreateKeyRequest {
+                customer_master_key_spec: Some("ECC_SECG_P256K1".to_string()),
+                key_usage: Some("SIGN_VERIFY".to_string()),
+                ..Default::default()
+            })
+            .await
+            .expect("KMS key should be created")
+            .key_metadata
+            .and_then(|metadata| metadata.arn)
+            .expect("KMS key should have an ARN");
+        let kms_client_config = EthersClientConfig {
+            wallet_key_identifier: WalletKeyIdentifier::Kms(key_arn.clone()),
+            ..ethers_client_config.clone()
+        }
+        .with_kms(kms.clone());
+        let kms_client = kms_client_config
+            .get_client()
+            .await
+            .expect("KMS signer should be created");
+        assert!(
+            matches!(kms_client.signer(), RelaySigner::Kms { key_arn: arn, .. } if *arn == key_arn)
+        );
+
+        // fund the KMS wallet, and pay a new address from it
+        funder
+            .send_transaction(
+                TransactionRequest::pay(kms_client.address(), U256::exp10(18)),
+                None,
+            )
+            .await
+            .expect("funding transaction should be sent")
+            .await
+            .expect("funding transaction should be mined");
+        let recipient = Address::random();
+        let receipt = kms_client
+            .send_transaction(TransactionRequest::pay(recipient, 1000), None)
+            .await
+            .expect("KMS signed transaction should be sent")
+            .await
+            .expect("KMS signed transaction should be mined")
+            .expect("KMS signed transaction should not be dropped");
+        assert_eq!(receipt.from, kms_client.address());
+        assert_eq!(receipt.status, Some(1.into()));
+        assert_eq!(
+            kms_client.get_balance(recipient, None).await.unwrap(),
+            U256::from(1000)
+        );
+
+        // an unknown key reports the AWS error code
+        let err = EthersClientConfig {
+            wallet_key_identifier: WalletKeyIdentifier::Kms("alias/missing".to_string()),
+            ..ethers_client_config
+        }
+        .with_kms(kms)
+        .get_client()
+        .await
+        .expect_err("the KMS key should not be found");
+        assert!(format!("{err:#}").contains("NotFoundException"), "{err:#}");
+    }
 }
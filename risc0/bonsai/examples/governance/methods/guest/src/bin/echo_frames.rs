@@ -0,0 +1,28 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use risc0_zkvm::guest::env;
+risc0_zkvm::guest::entry!(main);
+
+/// Reads three separately written frames from the host and commits them back,
+/// in the order they were read, to the journal.
+fn main() {
+    let first: Vec<u8> = env::read();
+    let second: Vec<u8> = env::read();
+    let third: Vec<u8> = env::read();
+
+    env::commit(&(first, second, third));
+}
@@ -0,0 +1,32 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use std::io::Write;
+
+use risc0_zkvm::guest::env;
+risc0_zkvm::guest::entry!(main);
+
+/// Writes a line to its stdout and one to its stderr, then pauses if it reads
+/// a non-zero word from the host. A paused guest doesn't halt with exit code
+/// 0, as a stand-in for a failing guest in the tests of the guest output.
+fn main() {
+    let pause: u32 = env::read();
+    writeln!(env::stdout(), "guest stdout line").unwrap();
+    writeln!(env::stderr(), "guest stderr line").unwrap();
+    if pause != 0 {
+        env::pause();
+    }
+}
@@ -0,0 +1,33 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use risc0_zkvm::guest::env;
+risc0_zkvm::guest::entry!(main);
+
+/// Reads a number of rounds from the host and mixes a counter for that many
+/// rounds before committing it to the journal. Given `u64::MAX` rounds, it
+/// runs practically for ever, as a stand-in for a buggy guest in the tests of
+/// the execution limits.
+fn main() {
+    let rounds: u64 = env::read();
+    let mut state = 0u64;
+    for round in 0..rounds {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(round);
+    }
+    env::commit(&state);
+}
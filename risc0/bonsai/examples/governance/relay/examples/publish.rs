@@ -51,6 +51,11 @@ async fn main() {
         gas_limit: 3000000,
         image_id: image_id.into(),
         input,
+        // The relay contract, if the relayer serves more than one.
+        relay_address: None,
+        chain_id: None,
+        // The Unix time after which the request expires if not proven yet.
+        deadline: None,
     };
 
     // Send the callback request to the Bonsai Relay.
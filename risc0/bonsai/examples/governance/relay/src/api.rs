@@ -0,0 +1,1406 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Programmatic API behind the relay CLI commands, returning typed results
+//! rather than printing them.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::Infallible,
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bonsai_ethereum_contracts::{
+    i_bonsai_relay::{Callback, CallbackRequestFilter, IBonsaiRelayCalls},
+    IBonsaiRelay,
+};
+use bonsai_ethereum_relay::{
+    AlertCommand, CallbackGasLimits, ChainConfig, EthersClientConfig, EventSender, FeeCap,
+    GuestImage, PrivateRelay, RateLimit, RelayError, Relayer, RestApiTls, SafeConfig,
+};
+use bonsai_sdk::{
+    alpha::responses::SnarkProof,
+    alpha_async::{get_client_from_parts_with_config, health},
+};
+use clap::ValueEnum;
+use ethers::{
+    abi::{AbiDecode, Hash, Token, Tokenizable},
+    providers::Middleware,
+    signers::Signer,
+    types::{Address, TransactionReceipt, TransactionRequest, H256, U256, U64},
+    utils::format_ether,
+};
+use hex::FromHex;
+use risc0_build::GuestListEntry;
+use risc0_zkvm::{sha::Digest, Receipt};
+use serde::Serialize;
+use sha2::{Digest as _, Sha256};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    compute_image_id, resolve_guest_entry, resolve_image_output, upload_images,
+    wait_for_bonsai_ready, BonsaiClientOpts, CliError, ExecutionLimits, GuestEnv, Output, PollOpts,
+    ProofCache, Stats, DEFAULT_BONSAI_READY_TIMEOUT,
+};
+
+/// A guest binary, selected either by name or by image ID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GuestSelector {
+    /// Name of the guest binary, e.g. `finalize_votes`, in any case.
+    Name(String),
+    /// Image ID of the guest binary.
+    ImageId(Digest),
+}
+
+impl GuestSelector {
+    /// Find the selected guest in the guest list.
+    pub fn resolve<'a>(&self, guest_list: &[GuestListEntry<'a>]) -> Result<GuestListEntry<'a>> {
+        match self {
+            Self::Name(name) => resolve_guest_entry(guest_list, name),
+            Self::ImageId(image_id) => {
+                resolve_guest_entry(guest_list, &hex::encode(image_id.as_bytes()))
+            }
+        }
+    }
+}
+
+impl FromStr for GuestSelector {
+    type Err = Infallible;
+
+    /// Parse a hex-encoded image ID, or else a guest binary name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match Digest::from_hex(s.trim_start_matches("0x")) {
+            Ok(image_id) => Self::ImageId(image_id),
+            Err(_) => Self::Name(s.to_string()),
+        })
+    }
+}
+
+/// Layout of the SNARK proof in the output of [query].
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum SealFormat {
+    /// The journal, the post-state digest, and the proof encoded as a fixed
+    /// array of uint256s.
+    Uint256Array,
+    /// The journal, the journal digest, and the proof encoded as a `bytes`
+    /// seal prefixed with a 4-byte verifier selector, as expected by
+    /// `IRiscZeroVerifier.verify`.
+    Groth16Selector,
+}
+
+/// Prover of [query] outside of dev mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProverKind {
+    /// The Bonsai proving service.
+    #[default]
+    Bonsai,
+    /// The default prover of the zkVM, on this machine. Only produces STARK
+    /// receipts.
+    Local,
+}
+
+/// Kind of receipt [query] proves the guest into on Bonsai.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReceiptKind {
+    /// The STARK receipt alone, for off-chain verification, skipping the
+    /// slow SNARK conversion.
+    Stark,
+    /// The STARK receipt converted into a SNARK proof, verifiable on chain.
+    #[default]
+    Snark,
+}
+
+/// Options of [query].
+#[derive(Clone, Debug)]
+pub struct QueryOpts {
+    /// Guests to select from, e.g. [methods::GUEST_LIST].
+    pub guest_list: &'static [GuestListEntry<'static>],
+    /// Execute the guest locally instead of proving it on Bonsai.
+    pub dev_mode: bool,
+    /// Prove the guest on Bonsai or on this machine. Ignored in dev mode.
+    pub prover: ProverKind,
+    /// Print the guest's stdout and stderr, along with its exit code, to
+    /// stderr even if the execution succeeds.
+    pub show_guest_output: bool,
+    /// Layout of the SNARK proof in the output.
+    pub seal_format: SealFormat,
+    /// Verifier selector prefixed to the seal in the `Groth16Selector` seal
+    /// format.
+    pub seal_selector: [u8; 4],
+    /// Skip checking that the SNARK proof points are valid BN254 curve points.
+    pub skip_proof_validation: bool,
+    /// Write the full receipt produced by Bonsai, or by the local prover, to
+    /// this path, as JSON.
+    pub receipt_out: Option<PathBuf>,
+    /// Kind of receipt to prove the guest into. Ignored in dev mode.
+    pub receipt_kind: ReceiptKind,
+    /// Polling of the Bonsai session. Ignored in dev mode.
+    pub poll: PollOpts,
+    /// Connection settings of the Bonsai client. Ignored in dev mode.
+    pub bonsai_client: BonsaiClientOpts,
+    /// Custom metadata attached to the Bonsai session, e.g. for billing or
+    /// audit. Ignored in dev mode.
+    pub session_metadata: HashMap<String, String>,
+    /// Cache of the SNARK proofs, reused instead of proving the same input
+    /// again. Ignored in dev mode and for STARK receipts.
+    pub proof_cache: Option<ProofCache>,
+    /// Limits of the execution of the guest.
+    pub limits: ExecutionLimits,
+    /// Environment variables of the guest. Only supported by local
+    /// executions, in dev mode or with the local prover.
+    pub guest_env: GuestEnv,
+}
+
+/// Result of [query].
+#[derive(Debug)]
+pub struct QueryResult {
+    /// Output tokens: the image ID if no input was given, else the journal,
+    /// followed by the proof if it was proven on Bonsai into a SNARK, or by
+    /// the post-state digest alone for a STARK receipt.
+    pub tokens: Vec<Token>,
+    /// Statistics of the execution or proving session, if an input was given.
+    pub stats: Option<Stats>,
+    /// Full receipt, if the input was proven on Bonsai or locally.
+    pub receipt: Option<Box<Receipt>>,
+}
+
+impl QueryResult {
+    /// Ethereum ABI encode the output tokens.
+    pub fn abi_encode(&self) -> Vec<u8> {
+        ethers::abi::encode(&self.tokens)
+    }
+}
+
+/// Parse a slice of strings as a fixed array of uint256 tokens.
+fn parse_to_tokens(slice: &[String]) -> Result<Token> {
+    Ok(Token::FixedArray(
+        slice
+            .iter()
+            .map(|s| -> Result<_> { Ok(U256::from_str_radix(s, 16)?.into_token()) })
+            .collect::<Result<Vec<_>, _>>()?,
+    ))
+}
+
+/// Tokenize the proof points as a fixed array of uint256 pairs.
+pub fn tokenize_snark_proof(proof: &SnarkProof) -> Result<Token> {
+    let malformed =
+        || CliError::ProofEncoding(anyhow!("hex-strings encoded proof is not well formed"));
+    if proof.b.len() != 2 {
+        return Err(malformed().into());
+    }
+    for pair in [&proof.a, &proof.c].into_iter().chain(proof.b.iter()) {
+        if pair.len() != 2 {
+            return Err(malformed().into());
+        }
+    }
+    let tokenize = |pair: &[String]| parse_to_tokens(pair).map_err(CliError::ProofEncoding);
+    Ok(Token::FixedArray(vec![
+        tokenize(&proof.a)?,
+        Token::FixedArray(vec![tokenize(&proof.b[0])?, tokenize(&proof.b[1])?]),
+        tokenize(&proof.c)?,
+    ]))
+}
+
+/// Encode the proof as a seal prefixed with the 4-byte verifier selector.
+pub fn encode_groth16_seal(proof: &SnarkProof, selector: [u8; 4]) -> Result<Vec<u8>> {
+    let mut seal = selector.to_vec();
+    seal.extend(ethers::abi::encode(&[tokenize_snark_proof(proof)?]));
+    Ok(seal)
+}
+
+/// Execute or prove the guest on the given input, or, if no input is given,
+/// return its image ID.
+///
+/// ```no_run
+/// use bonsai_ethereum_relay_cli::{
+///     query, BonsaiClientOpts, PollOpts, QueryOpts, ReceiptKind, SealFormat,
+/// };
+/// use methods::GUEST_LIST;
+///
+/// # async fn example(input: Vec<u8>) -> anyhow::Result<()> {
+/// let result = query(
+///     "finalize_votes".parse()?,
+///     Some(input),
+///     QueryOpts {
+///         guest_list: GUEST_LIST,
+///         dev_mode: true,
+///         prover: Default::default(),
+///         show_guest_output: false,
+///         seal_format: SealFormat::Uint256Array,
+///         seal_selector: [0x31, 0x0f, 0xe5, 0x98],
+///         skip_proof_validation: false,
+///         receipt_out: None,
+///         receipt_kind: ReceiptKind::Snark,
+///         poll: PollOpts::default(),
+///         bonsai_client: BonsaiClientOpts::default(),
+///         session_metadata: Default::default(),
+///         proof_cache: None,
+///         limits: Default::default(),
+///         guest_env: Default::default(),
+///     },
+/// )
+/// .await?;
+/// println!("{}", hex::encode(result.abi_encode()));
+/// # Ok(())
+/// # }
+/// ```
+pub async fn query(
+    guest: GuestSelector,
+    input: Option<Vec<u8>>,
+    opts: QueryOpts,
+) -> Result<QueryResult> {
+    let guest_entry = guest
+        .resolve(opts.guest_list)
+        .context("failed to resolve guest entry")?;
+
+    // No input. Return the Ethereum ABI encoded bytes32 image ID.
+    let Some(input) = input else {
+        return Ok(QueryResult {
+            tokens: vec![
+                Hash::from(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id)).into_token(),
+            ],
+            stats: None,
+            receipt: None,
+        });
+    };
+
+    // Input provided. Return the Ethereum ABI encoded journal and, if proven,
+    // the proof, read from the proof cache if it was proven before.
+    let proof_cache = opts
+        .proof_cache
+        .filter(|_| !opts.dev_mode && opts.receipt_kind == ReceiptKind::Snark)
+        .map(|cache| (ProofCache::key(guest_entry.image_id, &input), cache));
+    let cached = match &proof_cache {
+        Some((key, cache)) => cache.get(key)?,
+        None => None,
+    };
+    let cache_miss = cached.is_none();
+    let output = match cached {
+        Some(output) => output,
+        None => resolve_image_output(
+            input,
+            &guest_entry,
+            opts.dev_mode,
+            opts.prover,
+            opts.show_guest_output,
+            opts.receipt_kind,
+            opts.poll,
+            opts.bonsai_client,
+            opts.session_metadata,
+            opts.limits,
+            opts.guest_env,
+        )
+        .await
+        .context("failed to resolve image output")?,
+    };
+    match (opts.dev_mode, output) {
+        (true, Output::Execution { journal, stats }) => Ok(QueryResult {
+            tokens: vec![Token::Bytes(journal)],
+            stats: Some(stats),
+            receipt: None,
+        }),
+        (
+            false,
+            Output::Bonsai {
+                journal,
+                receipt_metadata,
+                receipt,
+                snark_proof,
+                stats,
+            },
+        ) => {
+            if let Some(path) = opts.receipt_out {
+                write_receipt(&path, &receipt)?;
+            }
+            if !opts.skip_proof_validation {
+                bonsai_ethereum_relay::validate_snark_proof(&snark_proof)
+                    .context("Bonsai returned an invalid SNARK proof")
+                    .map_err(CliError::ProofEncoding)?;
+            }
+            if let Some((key, cache)) = proof_cache.filter(|_| cache_miss) {
+                cache.put(
+                    &key,
+                    &journal,
+                    &receipt_metadata,
+                    &receipt,
+                    &snark_proof,
+                    &stats,
+                )?;
+            }
+            let tokens = match opts.seal_format {
+                SealFormat::Uint256Array => vec![
+                    Token::Bytes(journal),
+                    Hash::from(<[u8; 32]>::from(receipt_metadata.post.digest())).into_token(),
+                    Token::Bytes(ethers::abi::encode(&[tokenize_snark_proof(&snark_proof)?])),
+                ],
+                SealFormat::Groth16Selector => {
+                    let journal_digest = Sha256::digest(&journal);
+                    vec![
+                        Token::Bytes(journal),
+                        Hash::from_slice(&journal_digest).into_token(),
+                        Token::Bytes(encode_groth16_seal(&snark_proof, opts.seal_selector)?),
+                    ]
+                }
+            };
+            Ok(QueryResult {
+                tokens,
+                stats: Some(stats),
+                receipt: Some(receipt),
+            })
+        }
+        (
+            false,
+            Output::Stark {
+                journal,
+                receipt_metadata,
+                receipt,
+                stats,
+            },
+        ) => {
+            if let Some(path) = opts.receipt_out {
+                write_receipt(&path, &receipt)?;
+            }
+            Ok(QueryResult {
+                tokens: vec![
+                    Token::Bytes(journal),
+                    Hash::from(<[u8; 32]>::from(receipt_metadata.post.digest())).into_token(),
+                ],
+                stats: Some(stats),
+                receipt: Some(receipt),
+            })
+        }
+        (dev_mode, _) => bail!("invalid dev mode and output combination: {:?}", dev_mode),
+    }
+}
+
+/// Write the receipt produced by Bonsai to the given path, as JSON.
+fn write_receipt(path: &Path, receipt: &Receipt) -> Result<()> {
+    std::fs::write(path, serde_json::to_vec(receipt)?)
+        .with_context(|| format!("failed to write receipt {}", path.display()))
+}
+
+/// Result of uploading a guest image to Bonsai.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UploadResult {
+    /// Name of the guest binary.
+    pub name: String,
+    /// Image ID of the guest binary.
+    pub image_id: Digest,
+    /// Whether the image was uploaded, as opposed to already known by Bonsai.
+    pub uploaded: bool,
+}
+
+/// Upload the selected guest, or, if `guest` is None, all guests of the guest
+/// list, to Bonsai. If checksums are given, the SHA-256 of each ELF is verified
+/// against them before uploading anything. Each upload fails if it takes longer
+/// than `upload_timeout`, e.g. [DEFAULT_UPLOAD_TIMEOUT](crate::DEFAULT_UPLOAD_TIMEOUT),
+/// and the Bonsai clients connect as set by `client_opts`.
+pub async fn upload(
+    guest_list: &[GuestListEntry<'_>],
+    guest: Option<GuestSelector>,
+    checksums: Option<HashMap<String, String>>,
+    bonsai_api_url: &str,
+    bonsai_api_key: &str,
+    upload_timeout: Duration,
+    client_opts: &BonsaiClientOpts,
+) -> Result<Vec<UploadResult>> {
+    upload_images(
+        guest_list,
+        guest,
+        checksums,
+        bonsai_api_url,
+        bonsai_api_key,
+        upload_timeout,
+        client_opts,
+    )
+    .await
+}
+
+/// Image ID of a guest binary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageIdResult {
+    /// Name of the guest binary.
+    pub name: String,
+    /// Image ID of the guest binary.
+    pub image_id: Digest,
+}
+
+/// Image IDs of the selected guest, or, if `guest` is None, of all guests of
+/// the guest list. The image IDs are computed offline, without contacting
+/// Bonsai.
+pub fn image_ids(
+    guest_list: &[GuestListEntry<'_>],
+    guest: Option<GuestSelector>,
+) -> Result<Vec<ImageIdResult>> {
+    let guest_entries = match guest {
+        Some(guest) => vec![guest.resolve(guest_list)?],
+        None => guest_list.to_vec(),
+    };
+    Ok(guest_entries
+        .into_iter()
+        .map(|entry| ImageIdResult {
+            name: entry.name.to_string(),
+            image_id: entry.image_id.into(),
+        })
+        .collect())
+}
+
+/// Compute the image ID of the ELF file at `path`, named after its uppercased
+/// file stem, as for the guests loaded with `--elf-dir`.
+pub fn elf_image_id(path: &Path) -> Result<ImageIdResult> {
+    let elf = std::fs::read(path)
+        .with_context(|| format!("Failed to read ELF {}", path.display()))
+        .map_err(CliError::Input)?;
+    let image_id = compute_image_id(&elf)
+        .with_context(|| format!("Failed to compute the image ID of {}", path.display()))
+        .map_err(CliError::Input)?;
+    let name = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_uppercase())
+        .unwrap_or_default();
+    Ok(ImageIdResult { name, image_id })
+}
+
+/// Location of the image ID a contract expects for a guest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ImageIdSource {
+    /// Getter without arguments returning the image ID as a bytes32, e.g.
+    /// `imageId()`.
+    Getter(String),
+    /// Storage slot holding the image ID. Immutable variables, like the
+    /// `imageId` of the Bonsai Governor, are not in storage and must be read
+    /// with their getter.
+    StorageSlot(H256),
+}
+
+impl FromStr for ImageIdSource {
+    type Err = anyhow::Error;
+
+    /// Parse a getter signature, e.g. `imageId()`, or a storage slot, either
+    /// as a `0x` prefixed hex string or as a decimal number.
+    fn from_str(source: &str) -> Result<Self> {
+        if source.contains('(') {
+            if !source.ends_with("()") {
+                bail!("image ID getter {source} must not take arguments");
+            }
+            return Ok(Self::Getter(source.to_string()));
+        }
+        let slot = match source.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).map_err(anyhow::Error::from),
+            None => U256::from_dec_str(source).map_err(anyhow::Error::from),
+        }
+        .with_context(|| format!("invalid image ID getter or storage slot: {source}"))?;
+        let mut bytes = [0u8; 32];
+        slot.to_big_endian(&mut bytes);
+        Ok(Self::StorageSlot(H256(bytes)))
+    }
+}
+
+/// Load a JSON file mapping guest names to the [ImageIdSource] of their
+/// expected image ID, e.g. `{"finalize_votes": "imageId()"}`.
+pub fn load_image_id_sources(path: &Path) -> Result<Vec<(String, ImageIdSource)>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read image ID mapping {}", path.display()))
+        .map_err(CliError::Input)?;
+    let mapping: BTreeMap<String, String> = serde_json::from_str(&contents)
+        .with_context(|| format!("Invalid image ID mapping {}", path.display()))
+        .map_err(CliError::Input)?;
+    mapping
+        .into_iter()
+        .map(|(name, source)| {
+            let source = source
+                .parse()
+                .with_context(|| format!("Invalid image ID source of guest {name}"))
+                .map_err(CliError::Input)?;
+            Ok((name, source))
+        })
+        .collect()
+}
+
+/// Read the image ID expected by the contract at `contract`.
+pub async fn read_image_id<M: Middleware>(
+    client: &M,
+    contract: Address,
+    source: &ImageIdSource,
+) -> Result<[u8; 32]> {
+    match source {
+        ImageIdSource::Getter(getter) => {
+            let call = TransactionRequest::new()
+                .to(contract)
+                .data(ethers::utils::id(getter).to_vec());
+            let output = client
+                .call(&call.into(), None)
+                .await
+                .map_err(|err| anyhow!("{err}"))
+                .with_context(|| format!("Failed to call {getter} on {contract:?}"))
+                .map_err(CliError::Contract)?;
+            let image_id: [u8; 32] = output
+                .get(..32)
+                .and_then(|word| word.try_into().ok())
+                .ok_or_else(|| {
+                    CliError::Contract(anyhow!(
+                        "{getter} on {contract:?} returned {} bytes, expected a bytes32",
+                        output.len()
+                    ))
+                })?;
+            Ok(image_id)
+        }
+        ImageIdSource::StorageSlot(slot) => {
+            let value = client
+                .get_storage_at(contract, *slot, None)
+                .await
+                .map_err(|err| anyhow!("{err}"))
+                .with_context(|| format!("Failed to read storage slot {slot:?} of {contract:?}"))
+                .map_err(CliError::EthereumConnection)?;
+            Ok(value.0)
+        }
+    }
+}
+
+/// Comparison of the image ID of a local guest with the one a contract
+/// expects.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ImageIdDiff {
+    /// Name of the guest binary.
+    pub name: String,
+    /// Hex-encoded image ID of the local guest.
+    pub local: String,
+    /// Hex-encoded image ID expected by the contract.
+    pub on_chain: String,
+    /// Whether both image IDs are the same.
+    pub matches: bool,
+}
+
+/// Compare the image IDs of the guests of the guest list with the ones the
+/// contract at `contract` expects, read from the given sources.
+pub async fn diff_image_ids<M: Middleware>(
+    client: &M,
+    contract: Address,
+    guest_list: &[GuestListEntry<'_>],
+    sources: &[(String, ImageIdSource)],
+) -> Result<Vec<ImageIdDiff>> {
+    let mut diffs = Vec::new();
+    for (name, source) in sources {
+        let entry = GuestSelector::Name(name.clone()).resolve(guest_list)?;
+        let local = bytemuck::cast::<[u32; 8], [u8; 32]>(entry.image_id);
+        let on_chain = read_image_id(client, contract, source)
+            .await
+            .with_context(|| format!("Failed to read the image ID of guest {name}"))?;
+        diffs.push(ImageIdDiff {
+            name: entry.name.to_string(),
+            local: hex::encode(local),
+            on_chain: hex::encode(on_chain),
+            matches: local == on_chain,
+        });
+    }
+    Ok(diffs)
+}
+
+/// A callback request to submit to the relay contract with
+/// [request_callback].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallbackRequest {
+    /// Image ID of the guest to execute.
+    pub image_id: Digest,
+    /// Input to provide to the guest.
+    pub input: Vec<u8>,
+    /// Contract receiving the callback.
+    pub callback_contract: Address,
+    /// Selector of the callback function.
+    pub function_selector: [u8; 4],
+    /// Maximum amount of gas the callback function may use.
+    pub gas_limit: u64,
+}
+
+impl CallbackRequest {
+    /// Whether `callback` answers this request: it targets the same contract
+    /// and its payload, made of the function selector, the journal and the
+    /// image ID, starts with the selector and ends with the image ID.
+    pub fn is_answered_by(&self, callback: &Callback) -> bool {
+        callback.callback_contract == self.callback_contract
+            && callback.payload.starts_with(&self.function_selector)
+            && callback.payload.ends_with(self.image_id.as_bytes())
+    }
+}
+
+/// Identifier of a callback request. The relay contract assigns none, so a
+/// request is identified by the `CallbackRequest` log it emitted, as the
+/// relay does when replaying events.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct CallbackRequestId {
+    /// Hash of the transaction submitting the request.
+    pub tx_hash: H256,
+    /// Index of the `CallbackRequest` log in its block.
+    pub log_index: U256,
+}
+
+impl fmt::Display for CallbackRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}:{}", self.tx_hash, self.log_index)
+    }
+}
+
+/// Find the `CallbackRequest` log emitted by the relay contract at
+/// `relay_address` in the receipt of a request transaction.
+pub fn callback_request_id(
+    receipt: &TransactionReceipt,
+    relay_address: Address,
+) -> Result<CallbackRequestId> {
+    receipt
+        .logs
+        .iter()
+        .filter(|log| log.address == relay_address)
+        .find(|log| ethers::contract::parse_log::<CallbackRequestFilter>((*log).clone()).is_ok())
+        .map(|log| CallbackRequestId {
+            tx_hash: receipt.transaction_hash,
+            log_index: log.log_index.unwrap_or_default(),
+        })
+        .ok_or_else(|| {
+            CliError::Contract(anyhow!(
+                "transaction {:?} emitted no CallbackRequest event from {relay_address:?}",
+                receipt.transaction_hash
+            ))
+            .into()
+        })
+}
+
+/// Submit `request` to the relay contract at `relay_address`, and wait for
+/// the transaction to be mined. Returns the identifier of the request and the
+/// number of the block including it.
+pub async fn request_callback<M: Middleware + 'static>(
+    client: Arc<M>,
+    relay_address: Address,
+    request: &CallbackRequest,
+) -> Result<(CallbackRequestId, U64)> {
+    let relay = IBonsaiRelay::new(relay_address, client);
+    let call = relay.request_callback(
+        bytemuck::cast::<_, [u8; 32]>(request.image_id),
+        request.input.clone().into(),
+        request.callback_contract,
+        request.function_selector,
+        request.gas_limit,
+    );
+    let receipt = async {
+        call.send()
+            .await
+            .map_err(|err| anyhow!("{err}"))?
+            .await?
+            .context("callback request transaction was dropped")
+    }
+    .await
+    .map_err(CliError::Contract)?;
+    if receipt.status != Some(1.into()) {
+        return Err(CliError::Contract(anyhow!(
+            "callback request transaction {:?} failed",
+            receipt.transaction_hash
+        ))
+        .into());
+    }
+    let id = callback_request_id(&receipt, relay_address)?;
+    Ok((id, receipt.block_number.unwrap_or_default()))
+}
+
+/// Wait for the relay to invoke a callback answering `request` on the relay
+/// contract at `relay_address`, scanning the blocks from `from_block` on.
+/// Returns the hash of the callback transaction.
+///
+/// Callbacks relayed through a Safe `execTransaction` are not recognized, as
+/// the relay contract is not the target of their transaction.
+pub async fn wait_for_callback<M: Middleware>(
+    client: &M,
+    relay_address: Address,
+    request: &CallbackRequest,
+    from_block: U64,
+    poll_interval: Duration,
+) -> Result<H256> {
+    let mut next_block = from_block;
+    loop {
+        let latest = client
+            .get_block_number()
+            .await
+            .map_err(|err| anyhow!("{err}"))
+            .map_err(CliError::EthereumConnection)?;
+        while next_block <= latest {
+            let block = client
+                .get_block_with_txs(next_block)
+                .await
+                .map_err(|err| anyhow!("{err}"))
+                .with_context(|| format!("Failed to fetch block {next_block}"))
+                .map_err(CliError::EthereumConnection)?;
+            let Some(block) = block else {
+                break;
+            };
+            for tx in block.transactions {
+                if tx.to != Some(relay_address) {
+                    continue;
+                }
+                let callbacks = match IBonsaiRelayCalls::decode(&tx.input) {
+                    Ok(IBonsaiRelayCalls::InvokeCallback(call)) => vec![call.callback],
+                    Ok(IBonsaiRelayCalls::InvokeCallbacks(call)) => call.callbacks,
+                    _ => continue,
+                };
+                if callbacks
+                    .iter()
+                    .any(|callback| request.is_answered_by(callback))
+                {
+                    return Ok(tx.hash);
+                }
+            }
+            next_block += U64::one();
+        }
+        tokio::time::sleep(poll_interval).await;
+    }
+}
+
+/// Configuration of a relay started by [run_relayer].
+#[derive(Clone, Debug)]
+pub struct RelayerConfig {
+    /// Guests to relay callbacks for, all uploaded to Bonsai on start.
+    pub guest_list: &'static [GuestListEntry<'static>],
+    /// Toggle to enable the relay REST API.
+    pub rest_api: bool,
+    /// Address the relay REST API binds to.
+    pub rest_api_bind: String,
+    /// Port serving the relay REST API.
+    pub rest_api_port: String,
+    /// Certificate and key to serve the relay REST API over TLS with.
+    pub rest_api_tls: Option<RestApiTls>,
+    /// Toggle to execute the guests on a local Bonsai mock, without proving.
+    pub dev_mode: bool,
+    /// Bonsai API URL.
+    pub bonsai_api_url: String,
+    /// URLs of further Bonsai API instances, taking turns with
+    /// `bonsai_api_url` to upload images and poll sessions.
+    pub bonsai_api_urls: Vec<String>,
+    /// Send the image uploads, session creations and session polls of the
+    /// relay to the gRPC API of Bonsai instead of its REST API.
+    pub grpc: bool,
+    /// Bonsai API key.
+    pub bonsai_api_key: String,
+    /// Bonsai Relay contract addresses on Ethereum. The callback requests of
+    /// each contract are relayed back through it.
+    pub relay_addresses: Vec<Address>,
+    /// Time to wait for in-flight transactions to be confirmed on shutdown.
+    pub shutdown_timeout: Duration,
+    /// Safe multi-sig wallet to send the relay transactions through, if any.
+    pub safe: Option<SafeConfig>,
+    /// Guests of the callback requests to process. If empty, all callback
+    /// requests are processed.
+    pub event_filter: Vec<GuestSelector>,
+    /// Relay SNARK proofs without checking that their points are valid BN254
+    /// curve points.
+    pub skip_proof_validation: bool,
+    /// Minimum wallet balance, in wei, required to submit a relay transaction.
+    pub min_eth_balance: U256,
+    /// Cap on the fee per gas of the relay transactions, above which they are
+    /// held back, if any.
+    pub fee_cap: Option<FeeCap>,
+    /// Number of times a relay transaction reverted on chain is resubmitted.
+    pub retry_on_revert: u32,
+    /// Time without being mined after which a relay transaction is
+    /// resubmitted with bumped fees.
+    pub stuck_tx_timeout: Duration,
+    /// Percentage by which the fees of a stuck relay transaction are bumped.
+    pub tx_resubmit_bump: u64,
+    /// Number of blocks during which a mined relay transaction is checked to
+    /// still be on chain, and resubmitted if a reorg drops it.
+    pub reorg_depth: u64,
+    /// Number of relay transactions in flight at once, on each chain.
+    pub max_concurrent_relays: usize,
+    /// Number of completed proofs queued for relaying, on each chain, beyond
+    /// which the oldest ones are dropped.
+    pub relay_queue_depth: usize,
+    /// Maximum wei spent on gas by the relay transactions of each chain over a
+    /// rolling 24 hours, beyond which they are held back. Unlimited if unset.
+    pub max_daily_spend: Option<U256>,
+    /// Adjustment of the gas limits of the callbacks to their estimate, if
+    /// any. The gas limits of the callback requests are used as is if unset.
+    pub callback_gas_limits: Option<CallbackGasLimits>,
+    /// Private relay to submit the relay transactions to instead of the
+    /// public mempool, if any.
+    pub private_relay: Option<PrivateRelay>,
+    /// Interval at which to log the gas price of each chain, if any.
+    pub watch_gas_price: Option<Duration>,
+    /// Prefixes of the journals of the proofs to relay. Proofs whose journal
+    /// starts with none of them are skipped. All proofs are relayed if empty.
+    pub journal_filters: Vec<Vec<u8>>,
+    /// Channel to publish the relay lifecycle events on, if any.
+    pub events: Option<EventSender>,
+    /// Relay pre-generated proofs submitted to the REST API, instead of
+    /// proving callback requests on Bonsai. Only proofs of the `event_filter`
+    /// guests are accepted, if set.
+    pub submission_only: bool,
+    /// File of `CallbackRequest` logs, one JSON serialized log per line, to
+    /// process on start as if they arrived live.
+    pub replay_events: Option<PathBuf>,
+    /// Block from which to scan the `CallbackRequest` events on start, before
+    /// subscribing to new ones. Only new events are processed if unset.
+    pub start_block: Option<u64>,
+    /// Last block scanned from `start_block`, the head block if unset.
+    pub end_block: Option<u64>,
+    /// URL to POST a JSON alert to whenever relaying fails, if any.
+    pub alert_webhook: Option<String>,
+    /// Program run with a JSON alert on its stdin on persistent failures,
+    /// e.g. Bonsai being unreachable, if any.
+    pub alert_cmd: Option<AlertCommand>,
+    /// Key of the admin REST API, e.g. to upload the guests to Bonsai again
+    /// at runtime. The admin routes are not served if unset.
+    pub admin_api_key: Option<String>,
+    /// Chains to relay on in addition to the one of the client config, e.g.
+    /// read with [bonsai_ethereum_relay::read_chain_configs].
+    pub chains: Vec<ChainConfig>,
+    /// TOML file of settings overriding the ones of the config, some of which
+    /// are reloaded on SIGHUP. See [bonsai_ethereum_relay::Relayer].
+    pub config_file: Option<PathBuf>,
+    /// SQLite database recording the processed `CallbackRequest` events, if
+    /// any. See [bonsai_ethereum_relay::Relayer].
+    pub event_persistence_db: Option<PathBuf>,
+    /// Age after which completed events are removed from the event database.
+    pub prune_events_older_than: Option<Duration>,
+    /// Age after which the requests of on-chain events expire if not proven
+    /// yet. See [bonsai_ethereum_relay::Relayer].
+    pub request_max_age: Option<Duration>,
+    /// Stop the Bonsai session of a request whose deadline passes while it is
+    /// being proven.
+    pub cancel_expired_sessions: bool,
+    /// Maximum number of Bonsai sessions in flight, past which new requests
+    /// are deferred or rejected. See [bonsai_ethereum_relay::Relayer].
+    pub max_pending_requests: Option<usize>,
+    /// Number of failed requests kept in the dead-letter list, to be retried
+    /// through the REST API.
+    pub max_dead_letters: usize,
+    /// Rate limit of the REST API requests of each client, if any.
+    pub rest_api_rate_limit: Option<RateLimit>,
+    /// Maximum size, in bytes, of the body of a REST API request.
+    pub max_request_body_size: usize,
+    /// Toggle to serve a Swagger UI of the REST API.
+    pub rest_api_docs: bool,
+    /// Time to wait for the upload of each guest to Bonsai on start.
+    pub upload_timeout: Duration,
+    /// Toggle to upload all guests to Bonsai on start. Guests already known
+    /// by Bonsai, e.g. on a restart, need not be uploaded again.
+    pub upload_images: bool,
+    /// Connection settings of the Bonsai client uploading the guests.
+    pub bonsai_client: BonsaiClientOpts,
+}
+
+/// Handle on a relay started by [run_relayer].
+pub struct RelayerHandle {
+    task: JoinHandle<Result<(), RelayError>>,
+    shutdown: CancellationToken,
+}
+
+impl RelayerHandle {
+    /// Wait for the relay to stop, once its shutdown token is cancelled.
+    pub async fn join(self) -> Result<()> {
+        Ok(self.task.await.context("relay task failed")??)
+    }
+
+    /// Cancel the shutdown token, and wait for the relay to stop after its
+    /// in-flight transactions are confirmed.
+    pub async fn shutdown(self) -> Result<()> {
+        self.shutdown.cancel();
+        self.join().await
+    }
+}
+
+/// Start a relay in the background and upload all guests to Bonsai, unless in
+/// submission-only mode or `upload_images` is unset. The relay runs until `shutdown` is cancelled.
+///
+/// The relay does not listen for SIGINT or SIGTERM, leaving it to the
+/// embedding application to decide when to stop it:
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use bonsai_ethereum_relay::{
+///     EthersClientConfig, DEFAULT_ETH_NODE_TIMEOUT, DEFAULT_MAX_CONCURRENT_RELAYS,
+///     DEFAULT_MAX_DEAD_LETTERS, DEFAULT_MAX_REQUEST_BODY_SIZE, DEFAULT_RELAY_QUEUE_DEPTH,
+///     DEFAULT_STUCK_TX_TIMEOUT, DEFAULT_TX_RESUBMIT_BUMP,
+/// };
+/// use bonsai_ethereum_relay_cli::{
+///     run_relayer, BonsaiClientOpts, RelayerConfig, DEFAULT_UPLOAD_TIMEOUT,
+/// };
+/// use ethers::types::U256;
+/// use methods::GUEST_LIST;
+/// use tokio_util::sync::CancellationToken;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let config = RelayerConfig {
+///         guest_list: GUEST_LIST,
+///         rest_api: false,
+///         rest_api_bind: "127.0.0.1".to_string(),
+///         rest_api_port: "8080".to_string(),
+///         rest_api_tls: None,
+///         dev_mode: true,
+///         bonsai_api_url: "http://localhost:8081".to_string(),
+///         bonsai_api_urls: Vec::new(),
+///         grpc: false,
+///         bonsai_api_key: String::new(),
+///         relay_addresses: vec!["0x5FbDB2315678afecb367f032d93F642f64180aa3".parse()?],
+///         shutdown_timeout: Duration::from_secs(60),
+///         safe: None,
+///         event_filter: vec!["finalize_votes".parse()?],
+///         skip_proof_validation: false,
+///         min_eth_balance: U256::zero(),
+///         fee_cap: None,
+///         retry_on_revert: 0,
+///         stuck_tx_timeout: DEFAULT_STUCK_TX_TIMEOUT,
+///         tx_resubmit_bump: DEFAULT_TX_RESUBMIT_BUMP,
+///         reorg_depth: 0,
+///         max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+///         relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+///         max_daily_spend: None,
+///         callback_gas_limits: None,
+///         private_relay: None,
+///         watch_gas_price: None,
+///         journal_filters: Vec::new(),
+///         events: None,
+///         submission_only: false,
+///         replay_events: None,
+///         start_block: None,
+///         end_block: None,
+///         alert_webhook: None,
+///         alert_cmd: None,
+///         admin_api_key: None,
+///         chains: Vec::new(),
+///         config_file: None,
+///         event_persistence_db: None,
+///         prune_events_older_than: None,
+///         request_max_age: None,
+///         cancel_expired_sessions: false,
+///         max_pending_requests: None,
+///         max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+///         rest_api_rate_limit: None,
+///         max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+///         rest_api_docs: false,
+///         upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+///         upload_images: true,
+///         bonsai_client: BonsaiClientOpts::default(),
+///     };
+///     let client_config = EthersClientConfig::new(
+///         "ws://localhost:8545".to_string(),
+///         31337,
+///         std::env::var("PRIVATE_KEY")?.try_into()?,
+///         60,
+///         Duration::from_secs(5),
+///         DEFAULT_ETH_NODE_TIMEOUT,
+///     );
+///
+///     let shutdown = CancellationToken::new();
+///     let relayer = run_relayer(config, client_config, shutdown.clone()).await?;
+///
+///     // Run the rest of the application, then stop the relay along with it.
+///     tokio::signal::ctrl_c().await?;
+///     relayer.shutdown().await
+/// }
+/// ```
+pub async fn run_relayer(
+    config: RelayerConfig,
+    client_config: EthersClientConfig,
+    shutdown: CancellationToken,
+) -> Result<RelayerHandle> {
+    let image_ids = config
+        .event_filter
+        .iter()
+        .map(|guest| {
+            let guest_entry = guest.resolve(config.guest_list)?;
+            Ok(Hash::from(bytemuck::cast::<_, [u8; 32]>(
+                guest_entry.image_id,
+            )))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let relayer = Relayer {
+        rest_api: config.rest_api,
+        dev_mode: config.dev_mode,
+        rest_api_bind: config.rest_api_bind,
+        rest_api_port: config.rest_api_port,
+        rest_api_tls: config.rest_api_tls,
+        bonsai_api_url: config.bonsai_api_url.clone(),
+        bonsai_api_urls: config.bonsai_api_urls,
+        grpc: config.grpc,
+        bonsai_api_key: config.bonsai_api_key.clone(),
+        relay_contract_addresses: config.relay_addresses,
+        shutdown_timeout: config.shutdown_timeout,
+        safe: config.safe,
+        image_ids,
+        skip_proof_validation: config.skip_proof_validation,
+        min_eth_balance: config.min_eth_balance,
+        fee_cap: config.fee_cap,
+        retry_on_revert: config.retry_on_revert,
+        stuck_tx_timeout: config.stuck_tx_timeout,
+        tx_resubmit_bump: config.tx_resubmit_bump,
+        reorg_depth: config.reorg_depth,
+        max_concurrent_relays: config.max_concurrent_relays,
+        relay_queue_depth: config.relay_queue_depth,
+        max_daily_spend: config.max_daily_spend,
+        callback_gas_limits: config.callback_gas_limits,
+        private_relay: config.private_relay,
+        watch_gas_price: config.watch_gas_price,
+        journal_filters: config.journal_filters,
+        events: config.events,
+        submission_only: config.submission_only,
+        replay_events: config.replay_events,
+        start_block: config.start_block,
+        end_block: config.end_block,
+        alert_webhook: config.alert_webhook,
+        alert_cmd: config.alert_cmd,
+        images: config
+            .guest_list
+            .iter()
+            .map(|guest_entry| GuestImage {
+                name: guest_entry.name.to_string(),
+                image_id: H256(bytemuck::cast(guest_entry.image_id)),
+                elf: guest_entry.elf.into(),
+            })
+            .collect(),
+        admin_api_key: config.admin_api_key,
+        chains: config.chains,
+        config_file: config.config_file,
+        event_persistence_db: config.event_persistence_db,
+        prune_events_older_than: config.prune_events_older_than,
+        request_max_age: config.request_max_age,
+        cancel_expired_sessions: config.cancel_expired_sessions,
+        max_pending_requests: config.max_pending_requests,
+        max_dead_letters: config.max_dead_letters,
+        rest_api_rate_limit: config.rest_api_rate_limit,
+        max_request_body_size: config.max_request_body_size,
+        rest_api_docs: config.rest_api_docs,
+    };
+    let cancelled = shutdown.clone();
+    let task = tokio::spawn(relayer.run_with_shutdown(client_config, async move {
+        cancelled.cancelled().await;
+        Ok(())
+    }));
+
+    // Without Bonsai, there is nothing to upload the guests to, and with
+    // --no-upload they are expected to be on Bonsai already.
+    if config.submission_only || !config.upload_images {
+        return Ok(RelayerHandle { task, shutdown });
+    }
+
+    // A local Bonsai is started alongside the relay, wait for it to serve
+    // requests before uploading to it.
+    if config.dev_mode {
+        let ready = async {
+            let client = get_client_from_parts_with_config(
+                config.bonsai_api_url.clone(),
+                config.bonsai_api_key.clone(),
+                config.bonsai_client.client_config()?,
+            )
+            .await?;
+            wait_for_bonsai_ready(&client, DEFAULT_BONSAI_READY_TIMEOUT).await
+        };
+        if let Err(err) = ready.await {
+            shutdown.cancel();
+            return Err(err);
+        }
+    }
+
+    // Upload all locally defined images.
+    if let Err(err) = upload(
+        config.guest_list,
+        None,
+        None,
+        &config.bonsai_api_url,
+        &config.bonsai_api_key,
+        config.upload_timeout,
+        &config.bonsai_client,
+    )
+    .await
+    {
+        shutdown.cancel();
+        return Err(err);
+    }
+
+    Ok(RelayerHandle { task, shutdown })
+}
+
+/// Outcome of a check of [validate_config].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Fail,
+    /// Not applicable to the configuration, e.g. the Bonsai API in
+    /// submission-only mode, or not checked after an earlier failure.
+    Skip,
+}
+
+impl fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Self::Pass => "PASS",
+            Self::Fail => "FAIL",
+            Self::Skip => "SKIP",
+        })
+    }
+}
+
+/// A check of [validate_config], e.g. of a relay contract.
+#[derive(Clone, Debug)]
+pub struct ConfigCheck {
+    /// What was checked, e.g. `Relay contract 0x5fbd…0aa3 (chain 1)`.
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found, e.g. the wallet balance, or why the check failed.
+    pub detail: String,
+}
+
+impl ConfigCheck {
+    pub fn pass(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Pass, detail)
+    }
+
+    pub fn fail(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Fail, detail)
+    }
+
+    pub fn skip(name: impl Into<String>, detail: impl Into<String>) -> Self {
+        Self::new(name, CheckStatus::Skip, detail)
+    }
+
+    fn new(name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Checks of [validate_config], displayed as a `CHECK | STATUS | DETAIL`
+/// table.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigReport {
+    pub checks: Vec<ConfigCheck>,
+}
+
+impl ConfigReport {
+    /// Returns the number of failed checks.
+    pub fn failed(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fail)
+            .count()
+    }
+}
+
+impl From<ConfigCheck> for ConfigReport {
+    fn from(check: ConfigCheck) -> Self {
+        Self {
+            checks: vec![check],
+        }
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.chars().count())
+            .fold("CHECK".len(), usize::max);
+        writeln!(f, "{:width$} | STATUS | DETAIL", "CHECK")?;
+        for check in &self.checks {
+            writeln!(
+                f,
+                "{:width$} | {:6} | {}",
+                check.name, check.status, check.detail
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the configuration of a relay without running it: the guest ELFs to
+/// relay callbacks for are present and match their image IDs, the Bonsai API
+/// instances answer, and on each chain, the Ethereum node is reachable with
+/// the configured chain ID, the relay contracts are deployed and the wallets
+/// hold the minimum balance.
+pub async fn validate_config(
+    config: &RelayerConfig,
+    client_config: &EthersClientConfig,
+) -> ConfigReport {
+    let mut checks = Vec::new();
+
+    // Without an event filter, callbacks are relayed for all the guests.
+    if config.event_filter.is_empty() {
+        checks.extend(config.guest_list.iter().map(check_elf));
+    }
+    for guest in &config.event_filter {
+        checks.push(match guest.resolve(config.guest_list) {
+            Ok(guest_entry) => check_elf(&guest_entry),
+            Err(err) => ConfigCheck::fail(format!("ELF {guest:?}"), format!("{err:#}")),
+        });
+    }
+
+    if config.submission_only {
+        checks.push(ConfigCheck::skip("Bonsai API", "submission-only mode"));
+    } else if config.dev_mode {
+        checks.push(ConfigCheck::skip(
+            "Bonsai API",
+            "dev mode, a local Bonsai is started with the relay",
+        ));
+    } else {
+        for url in std::iter::once(&config.bonsai_api_url).chain(&config.bonsai_api_urls) {
+            checks.push(check_bonsai(url, config).await);
+        }
+    }
+
+    checks.extend(
+        check_chain(
+            client_config,
+            &config.relay_addresses,
+            config.min_eth_balance,
+        )
+        .await,
+    );
+    for chain in &config.chains {
+        checks.extend(
+            check_chain(
+                &chain.client_config,
+                &chain.relay_contract_addresses,
+                chain.min_eth_balance,
+            )
+            .await,
+        );
+    }
+    ConfigReport { checks }
+}
+
+fn check_elf(guest_entry: &GuestListEntry<'_>) -> ConfigCheck {
+    let name = format!("ELF {}", guest_entry.name);
+    if guest_entry.elf.is_empty() {
+        return ConfigCheck::fail(name, "empty ELF");
+    }
+    match compute_image_id(guest_entry.elf) {
+        Ok(image_id) if image_id == Digest::from(guest_entry.image_id) => {
+            ConfigCheck::pass(name, format!("image ID {image_id}"))
+        }
+        Ok(image_id) => ConfigCheck::fail(
+            name,
+            format!(
+                "image ID {image_id}, expected {}",
+                Digest::from(guest_entry.image_id)
+            ),
+        ),
+        Err(err) => ConfigCheck::fail(name, format!("invalid ELF: {err:#}")),
+    }
+}
+
+async fn check_bonsai(url: &str, config: &RelayerConfig) -> ConfigCheck {
+    let name = "Bonsai API";
+    let client = match config.bonsai_client.client_config() {
+        Ok(client_config) => {
+            get_client_from_parts_with_config(
+                url.to_string(),
+                config.bonsai_api_key.clone(),
+                client_config,
+            )
+            .await
+        }
+        Err(err) => return ConfigCheck::fail(name, format!("{err:#}")),
+    };
+    let result = match client {
+        Ok(client) => health(client).await,
+        Err(err) => Err(err),
+    };
+    match result {
+        Ok(()) => ConfigCheck::pass(name, format!("{url} is up")),
+        Err(err) => ConfigCheck::fail(name, format!("{url}: {err}")),
+    }
+}
+
+/// Check the Ethereum node, relay contracts and wallets of a chain.
+async fn check_chain(
+    client_config: &EthersClientConfig,
+    relay_addresses: &[Address],
+    min_eth_balance: U256,
+) -> Vec<ConfigCheck> {
+    let chain_id = client_config.eth_chain_id;
+    let mut key_checks = Vec::new();
+    let mut wallets = Vec::new();
+    for (index, signer_config) in client_config.signer_configs().iter().enumerate() {
+        match signer_config.get_signer().await {
+            Ok(signer) => wallets.push(signer.address()),
+            Err(err) => key_checks.push(ConfigCheck::fail(
+                format!("Wallet key {index} (chain {chain_id})"),
+                err.to_string(),
+            )),
+        }
+    }
+
+    // Read in a single JSON-RPC batch with `--json-rpc-batch`.
+    let node = format!("Ethereum node (chain {chain_id})");
+    let state = match client_config.chain_state(relay_addresses, &wallets).await {
+        Ok(state) => state,
+        Err(err) => return unreachable_chain(ConfigCheck::fail(node, err.to_string()), chain_id),
+    };
+    if state.chain_id != chain_id.into() {
+        return unreachable_chain(
+            ConfigCheck::fail(node, format!("serves chain {}", state.chain_id)),
+            chain_id,
+        );
+    }
+    let mut checks = vec![ConfigCheck::pass(node, "reachable")];
+
+    for (address, code) in relay_addresses.iter().zip(state.codes) {
+        let name = format!("Relay contract {address:?} (chain {chain_id})");
+        checks.push(match code {
+            Ok(code) if code.is_empty() => ConfigCheck::fail(name, "no contract deployed"),
+            Ok(code) => ConfigCheck::pass(name, format!("{} bytes of code", code.len())),
+            Err(err) => ConfigCheck::fail(name, err),
+        });
+    }
+
+    checks.extend(key_checks);
+    for (address, balance) in wallets.into_iter().zip(state.balances) {
+        let name = format!("Wallet {address:?} (chain {chain_id})");
+        checks.push(match balance {
+            Ok(balance) => {
+                let detail = format!(
+                    "{} ETH, minimum {} ETH",
+                    format_ether(balance),
+                    format_ether(min_eth_balance)
+                );
+                if balance < min_eth_balance {
+                    ConfigCheck::fail(name, detail)
+                } else {
+                    ConfigCheck::pass(name, detail)
+                }
+            }
+            Err(err) => ConfigCheck::fail(name, err),
+        });
+    }
+    checks
+}
+
+/// The failed check of the Ethereum node of a chain, followed by the skipped
+/// checks of its contracts and wallets.
+fn unreachable_chain(node: ConfigCheck, chain_id: u64) -> Vec<ConfigCheck> {
+    vec![
+        node,
+        ConfigCheck::skip(
+            format!("Relay contracts and wallets (chain {chain_id})"),
+            "Ethereum node unavailable",
+        ),
+    ]
+}
@@ -0,0 +1,120 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Typed errors of the relay CLI, mapped to distinct process exit codes so
+//! that scripts can tell failures apart without matching error messages.
+//!
+//! | Code | Error                                    |
+//! |------|------------------------------------------|
+//! | 1    | Any other error                          |
+//! | 2    | Invalid usage                            |
+//! | 3    | Guest not found                          |
+//! | 4    | Invalid input                            |
+//! | 5    | Guest execution failed, e.g. over limits |
+//! | 10   | Bonsai unreachable or request failed     |
+//! | 11   | Bonsai proving session failed            |
+//! | 12   | Invalid proof                            |
+//! | 20   | Ethereum node unreachable or failed      |
+//! | 21   | Contract call or transaction failed      |
+
+use serde_json::json;
+
+/// Error of a relay CLI command. Functions returning [anyhow::Result] carry
+/// it as the root of the error chain, below any added context.
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    /// Invalid combination of arguments.
+    #[error("{0:#}")]
+    Usage(anyhow::Error),
+    /// The guest binary is not in the guest list.
+    #[error("{0:#}")]
+    GuestResolution(anyhow::Error),
+    /// The guest input could not be parsed.
+    #[error("{0:#}")]
+    Input(anyhow::Error),
+    /// The local execution of the guest failed, e.g. as it exceeded its
+    /// cycle limit.
+    #[error("{0:#}")]
+    ExecutionFailed(anyhow::Error),
+    /// A request to Bonsai failed, e.g. as Bonsai is unreachable.
+    #[error("{0:#}")]
+    BonsaiTransport(anyhow::Error),
+    /// A Bonsai proving session, or its SNARK conversion, did not succeed.
+    #[error("{0:#}")]
+    SessionFailed(anyhow::Error),
+    /// The proof returned by Bonsai could not be validated or encoded.
+    #[error("{0:#}")]
+    ProofEncoding(anyhow::Error),
+    /// A request to the Ethereum node failed, e.g. as it is unreachable.
+    #[error("{0:#}")]
+    EthereumConnection(anyhow::Error),
+    /// A contract call or transaction failed or reverted.
+    #[error("{0:#}")]
+    Contract(anyhow::Error),
+}
+
+impl CliError {
+    /// Process exit code of the error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Usage(_) => 2,
+            Self::GuestResolution(_) => 3,
+            Self::Input(_) => 4,
+            Self::ExecutionFailed(_) => 5,
+            Self::BonsaiTransport(_) => 10,
+            Self::SessionFailed(_) => 11,
+            Self::ProofEncoding(_) => 12,
+            Self::EthereumConnection(_) => 20,
+            Self::Contract(_) => 21,
+        }
+    }
+
+    /// Name of the error kind, as reported in JSON errors.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Usage(_) => "usage",
+            Self::GuestResolution(_) => "guest_resolution",
+            Self::Input(_) => "input",
+            Self::ExecutionFailed(_) => "execution_failed",
+            Self::BonsaiTransport(_) => "bonsai_transport",
+            Self::SessionFailed(_) => "session_failed",
+            Self::ProofEncoding(_) => "proof_encoding",
+            Self::EthereumConnection(_) => "ethereum_connection",
+            Self::Contract(_) => "contract",
+        }
+    }
+
+    /// Find the [CliError] in the chain of the given error, if any.
+    pub fn find(err: &anyhow::Error) -> Option<&CliError> {
+        err.chain()
+            .find_map(|cause| cause.downcast_ref::<CliError>())
+    }
+}
+
+/// Process exit code of the given error, 1 if it carries no [CliError].
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    CliError::find(err).map_or(1, CliError::exit_code)
+}
+
+/// Structured error object reported on stdout when the output format is JSON.
+pub fn error_json(err: &anyhow::Error) -> serde_json::Value {
+    let cli_error = CliError::find(err);
+    json!({
+        "error": {
+            "kind": cli_error.map_or("other", CliError::kind),
+            "code": exit_code(err),
+            "message": format!("{err:#}"),
+        }
+    })
+}
@@ -12,22 +12,61 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::{collections::HashMap, io::Write, path::PathBuf, str::FromStr};
 
 use anyhow::Context;
-use bonsai_ethereum_relay::{EthersClientConfig, Relayer};
-use bonsai_ethereum_relay_cli::{resolve_guest_entry, resolve_image_output, Output};
-use bonsai_sdk::{
-    alpha::{responses::SnarkProof, SdkErr},
-    alpha_async::{get_client_from_parts, put_image},
+use bonsai_ethereum_contracts::BonsaiTestRelay;
+use bonsai_ethereum_relay::{
+    event_channel, read_chain_configs, shutdown_signal, write_events, AlertCommand,
+    CallbackGasLimits, EthersClientConfig, EventSender, FeeCap, KmsConfig, LedgerConfig,
+    PrivateRelay, RateLimit, RelayError, RemoteSignerConfig, RestApiTls, SafeConfig,
+    DEFAULT_ALERT_CMD_COOLDOWN, DEFAULT_BONSAI_UNREACHABLE_ALERT,
+    DEFAULT_DEAD_LETTER_ALERT_THRESHOLD, DEFAULT_ETH_NODE_POLL_INTERVAL,
+    DEFAULT_ETH_NODE_POOL_SIZE, DEFAULT_ETH_NODE_TIMEOUT, DEFAULT_FEE_QUEUE_DEPTH,
+    DEFAULT_LEDGER_SIGNING_TIMEOUT, DEFAULT_LEDGER_UNLOCK_TIMEOUT, DEFAULT_MAX_CONCURRENT_RELAYS,
+    DEFAULT_MAX_DEAD_LETTERS, DEFAULT_MAX_REQUEST_BODY_SIZE, DEFAULT_MIN_ETH_BALANCE,
+    DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS, DEFAULT_RELAY_QUEUE_DEPTH, DEFAULT_SHUTDOWN_TIMEOUT,
+    DEFAULT_STUCK_TX_TIMEOUT, DEFAULT_TX_RESUBMIT_BUMP, DEFAULT_WS_HEARTBEAT_INTERVAL,
+    DEFAULT_WS_PING_INTERVAL, DEFAULT_WS_PONG_TIMEOUT, DEFAULT_WS_STALE_TIMEOUT,
 };
-use clap::{Args, Parser, Subcommand};
+use bonsai_ethereum_relay_cli::{
+    assert_unique_image_ids, cancel_session, decode_input, decode_journal, diff_image_ids,
+    elf_image_id, encode_abi_input, encode_frames, error_json, exit_code, fetch_session_receipt,
+    hex_diff, image_ids, journal_from_query_output, load_checksums, load_elf_dir, load_elf_list,
+    load_image_id_sources, load_journal_abi, load_receipt, merge_guest_lists, parse_frame, query,
+    read_key_file, request_callback, resolve_guest_entry, resolve_image_output, run_relayer,
+    transferable_balance, upload, validate_config, validate_guest_env_key, verify_receipt,
+    wait_for_callback, write_key_file, BenchmarkReport, BenchmarkRun, BonsaiClientOpts,
+    CallbackRequest, CancelOutcome, CliError, ConfigCheck, ConfigReport, ExecutionLimits, GuestEnv,
+    GuestSelector, ImageIdSource, InputEncoding, IpfsConfig, Output, PollOpts, ProofCache,
+    ProverKind, QueryOpts, ReceiptKind, RelayerConfig, SealFormat, DEFAULT_BONSAI_CONNECT_TIMEOUT,
+    DEFAULT_BONSAI_TIMEOUT, DEFAULT_IPFS_GATEWAY, DEFAULT_PROOF_CACHE_MAX_AGE,
+    DEFAULT_UPLOAD_TIMEOUT, TRANSFER_GAS,
+};
+use bonsai_sdk::alpha::Client;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use ethers::{
     abi::{Hash, Token, Tokenizable},
-    types::{Address, U256},
+    middleware::SignerMiddleware,
+    prelude::abigen,
+    providers::{Middleware, Provider, Ws},
+    signers::{LocalWallet, Signer},
+    types::{Address, Bytes, Filter, TransactionRequest, U256},
+    utils::Anvil,
 };
+use hex::FromHex;
 use methods::GUEST_LIST;
+use risc0_build::GuestListEntry;
+use risc0_zkp::{MAX_CYCLES_PO2, MIN_CYCLES_PO2};
 use risc0_zkvm::sha::Digest;
+use tokio_util::sync::CancellationToken;
+
+abigen!(
+    GnosisSafe,
+    r#"[
+        function isOwner(address owner) external view returns (bool)
+    ]"#
+);
 
 /// Index 0 private key generated by default in Anvil.
 const ANVIL_DEFAULT_KEY: &'static str =
@@ -39,6 +78,62 @@ const CONNECTION_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from
 /// Number of connection attempts.
 const CONNECTION_RETRY_ATTEMPTS: u64 = 60;
 
+/// Address the relay REST API binds to.
+const REST_API_BIND: &str = "127.0.0.1";
+
+/// Port serving the relay REST API.
+const REST_API_PORT: &str = "8080";
+
+/// Creation code of the `selftest` callback contract, which logs the calldata
+/// of every call as an anonymous event.
+const JOURNAL_SINK_BYTECODE: &str = "600b600c600039600b6000f3366000600037366000a000";
+
+/// Gas limit of the `selftest` callback.
+const SELFTEST_GAS_LIMIT: u64 = 3000000;
+
+/// Verifier selector prefixed to `groth16-selector` seals by default, routing
+/// them to the Groth16 verifier of a RISC Zero verifier router.
+const DEFAULT_SEAL_SELECTOR: &str = "0x310fe598";
+
+/// Output format of the `Query` command.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum OutputFormat {
+    /// Hex-encoded Ethereum ABI output.
+    Hex,
+    /// JSON object holding the hex-encoded output along with proving
+    /// statistics.
+    Json,
+}
+
+/// Output format of the `ImageId` command.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ImageIdFormat {
+    /// Hex-encoded Ethereum ABI array of the bytes32 image IDs, as output by
+    /// `upload`.
+    Hex,
+    /// JSON object holding the hex-encoded output along with the name and
+    /// image ID of each guest.
+    Json,
+}
+
+/// Endpoint the `Run` command submits the relay transactions to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum TxEndpoint {
+    /// The public mempool, through the Ethereum node.
+    Public,
+    /// The private relay of `--private-relay-url`, e.g. Flashbots Protect.
+    Private,
+}
+
+/// Output format of the `Bench` and `DiffImageIds` commands.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum ReportFormat {
+    /// Human-readable table.
+    Text,
+    /// JSON object holding the report.
+    Json,
+}
+
 #[derive(Subcommand)]
 enum Command {
     /// Runs the RISC-V ELF binary.
@@ -48,19 +143,487 @@ enum Command {
 
         /// The input to provide to the guest binary
         input: Option<String>,
+
+        /// Encoding of the input
+        #[arg(long, value_enum, default_value_t = InputEncoding::Hex, requires = "input")]
+        input_encoding: InputEncoding,
+
+        /// An input frame to provide to the guest binary, either as a hex
+        /// string or as `@<path>` to read a file. Can be repeated, each frame
+        /// being read by a separate `env::read()` call in the guest.
+        #[arg(long = "frame", value_name = "HEX|@FILE", conflicts_with = "input")]
+        frames: Vec<String>,
+
+        /// Ethereum ABI signature of the input to provide to the guest binary,
+        /// e.g. `(uint256,address,bytes32[])`, encoded from the `--arg` values.
+        #[arg(long, conflicts_with_all = ["input", "frames"])]
+        abi: Option<String>,
+
+        /// An argument of the `--abi` signature. Can be repeated, once per
+        /// component of the signature.
+        #[arg(long = "arg", requires = "abi", allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+        format: OutputFormat,
+
+        /// Print the guest's stdout and stderr, along with its exit code, to
+        /// stderr even if the execution succeeds.
+        #[arg(long, default_value_t = false)]
+        show_guest_output: bool,
+
+        /// Layout of the SNARK proof in the output
+        #[arg(long, value_enum, default_value_t = SealFormat::Uint256Array)]
+        seal_format: SealFormat,
+
+        /// Verifier selector prefixed to the seal in the `groth16-selector`
+        /// seal format, as a 4-byte hex string.
+        #[arg(long, default_value = DEFAULT_SEAL_SELECTOR, value_parser = parse_seal_selector)]
+        seal_selector: [u8; 4],
+
+        /// Skip checking that the SNARK proof points are valid BN254 curve
+        /// points, e.g. to test deliberately malformed proofs.
+        #[arg(long, default_value_t = false)]
+        skip_proof_validation: bool,
+
+        /// Write the full receipt produced by Bonsai to this path, as JSON.
+        #[arg(long)]
+        receipt_out: Option<PathBuf>,
+
+        /// Kind of receipt to prove the guest into on Bonsai. With `stark`,
+        /// the SNARK conversion is skipped, the receipt is written to
+        /// `--receipt-out`, and the output holds the journal and the
+        /// post-state digest only.
+        #[arg(long, value_enum, default_value_t = ReceiptKind::Snark)]
+        receipt_kind: ReceiptKind,
+
+        /// Prover of the guest outside of dev mode. The `local` prover runs on
+        /// this machine instead of Bonsai, and only supports `--receipt-kind
+        /// stark`.
+        #[arg(long, value_enum, default_value_t = ProverKind::Bonsai)]
+        prover: ProverKind,
+
+        /// Delay between two status requests of the Bonsai session.
+        #[arg(long, default_value = "4s")]
+        poll_interval: humantime::Duration,
+
+        /// Give up on the Bonsai session if it has not completed, including
+        /// its SNARK conversion, within this duration. No deadline if unset.
+        #[arg(long)]
+        poll_timeout: Option<humantime::Duration>,
+
+        /// How long to retry Bonsai transport errors and server errors while
+        /// polling, with backoff, before giving up on the session. The session
+        /// UUID is printed on giving up, as the session may still complete.
+        #[arg(long, default_value = "1m")]
+        poll_retry_duration: humantime::Duration,
+
+        /// Directory to cache the SNARK proofs in, by image ID and input. A
+        /// proof already in the cache is printed without proving the input
+        /// on Bonsai again.
+        #[arg(long)]
+        proof_cache_dir: Option<PathBuf>,
+
+        /// Time the proofs written to `--proof-cache-dir` are reused for.
+        #[arg(long, default_value_t = DEFAULT_PROOF_CACHE_MAX_AGE.into(), requires = "proof_cache_dir")]
+        proof_cache_max_age: humantime::Duration,
+
+        /// Custom metadata to attach to the Bonsai session, e.g. for billing or
+        /// audit, as `key=value`. Can be repeated, once per key.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_session_metadata)]
+        session_metadata: Vec<(String, String)>,
+
+        /// Environment variable of the guest, read with `env::var`, as
+        /// `KEY=VALUE`. Can be repeated, once per key. Only supported in dev
+        /// mode and with `--prover local`.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_guest_env)]
+        guest_env: Vec<(String, String)>,
+
+        /// Like `--guest-env`, for a secret value, which is never printed.
+        // Parsed after clap, whose parse errors echo the value.
+        #[arg(long, value_name = "KEY=VALUE")]
+        guest_env_secret: Vec<String>,
+
+        /// Number of cycles past which the execution of the guest fails, e.g.
+        /// to stop a guest stuck in a loop. Applied to the executor in dev
+        /// mode, and sent to Bonsai with the proving session.
+        #[arg(long)]
+        max_cycles: Option<u64>,
+
+        /// Maximum size of the segments of the execution, as a power of two
+        /// of cycles. Only applied in dev mode.
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(MIN_CYCLES_PO2 as i64..=MAX_CYCLES_PO2 as i64)
+        )]
+        segment_po2: Option<u32>,
+    },
+    /// Verifies a receipt offline, without contacting Bonsai, and prints its
+    /// journal and post-state digest.
+    VerifyReceipt {
+        /// Path to the receipt, either as JSON or bincode.
+        receipt: PathBuf,
+
+        /// The expected image ID, as a hex string.
+        #[arg(
+            long,
+            conflicts_with = "guest_binary",
+            required_unless_present = "guest_binary"
+        )]
+        image_id: Option<String>,
+
+        /// The name of the guest binary whose image ID is expected.
+        #[arg(long)]
+        guest_binary: Option<String>,
+    },
+    /// Compares the journals and the post-state digests of the receipts of
+    /// two Bonsai sessions, e.g. to debug non-deterministic guests. Exits with
+    /// a non-zero status if the journals differ.
+    Diff {
+        /// UUID of the first Bonsai session.
+        #[arg(long)]
+        session_a: String,
+
+        /// UUID of the second Bonsai session.
+        #[arg(long)]
+        session_b: String,
+
+        /// Ethereum ABI signature of the journals, e.g. `(bytes32,uint256)`,
+        /// to also compare their decoded components.
+        #[arg(long)]
+        abi: Option<String>,
+    },
+    /// Stops a running Bonsai proving session, e.g. one proving the wrong
+    /// input. Sessions that already completed are left as they are.
+    CancelSession {
+        /// UUID of the Bonsai session.
+        #[arg(long)]
+        session_id: String,
+    },
+    /// Compares the image IDs of the local guests with the ones a contract
+    /// expects, e.g. to catch a contract left behind after a guest rebuild.
+    /// Exits with a non-zero status if any image ID differs.
+    DiffImageIds {
+        /// Ethereum Node endpoint.
+        #[arg(long, env, default_value = "ws://localhost:8545")]
+        eth_node: String,
+
+        /// Address of the contract holding the expected image IDs.
+        #[arg(long)]
+        contract: Address,
+
+        /// The name of the guest binary whose image ID is read with
+        /// `--getter` or `--storage-slot`.
+        #[arg(long, default_value = "finalize_votes")]
+        guest_binary: String,
+
+        /// Getter of the expected image ID, without arguments and returning a
+        /// bytes32, e.g. `imageId()`.
+        #[arg(long, conflicts_with_all = ["storage_slot", "mapping_file"])]
+        getter: Option<String>,
+
+        /// Storage slot of the expected image ID, as hex or decimal.
+        #[arg(long, conflicts_with = "mapping_file")]
+        storage_slot: Option<String>,
+
+        /// JSON file mapping guest names to the getter signature or storage
+        /// slot of their expected image ID, e.g.
+        /// `{"finalize_votes": "imageId()"}`.
+        #[arg(long, required_unless_present_any = ["getter", "storage_slot"])]
+        mapping_file: Option<PathBuf>,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Measures the end-to-end latency of proving the guest binary over
+    /// repeated runs, per phase: execution, proving, SNARK conversion and
+    /// total wall time, along with the cycle counts. In dev mode, only the
+    /// executor is measured.
+    #[command(alias = "benchmark")]
+    Bench {
+        /// The name of the guest binary
+        guest_binary: String,
+
+        /// The input to provide to the guest binary
+        #[arg(required_unless_present_any = ["frames", "abi"])]
+        input: Option<String>,
+
+        /// Encoding of the input
+        #[arg(long, value_enum, default_value_t = InputEncoding::Hex, requires = "input")]
+        input_encoding: InputEncoding,
+
+        /// An input frame to provide to the guest binary, either as a hex
+        /// string or as `@<path>` to read a file. Can be repeated, each frame
+        /// being read by a separate `env::read()` call in the guest.
+        #[arg(long = "frame", value_name = "HEX|@FILE", conflicts_with = "input")]
+        frames: Vec<String>,
+
+        /// Ethereum ABI signature of the input to provide to the guest binary,
+        /// e.g. `(uint256,address,bytes32[])`, encoded from the `--arg` values.
+        #[arg(long, conflicts_with_all = ["input", "frames"])]
+        abi: Option<String>,
+
+        /// An argument of the `--abi` signature. Can be repeated, once per
+        /// component of the signature.
+        #[arg(long = "arg", requires = "abi", allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// Number of measured runs.
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+
+        /// Number of initial runs discarded from the measurements.
+        #[arg(long, default_value_t = 0)]
+        warmup: u32,
+
+        /// Number of measured runs in flight at once, each in its own
+        /// proving session.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        concurrency: u32,
+
+        /// Kind of receipt to prove the guest into on Bonsai. With `stark`,
+        /// the SNARK conversion is skipped.
+        #[arg(long, value_enum, default_value_t = ReceiptKind::Snark)]
+        receipt_kind: ReceiptKind,
+
+        /// Delay between two status requests of the Bonsai sessions.
+        #[arg(long, default_value = "4s")]
+        poll_interval: humantime::Duration,
+
+        /// Give up on a Bonsai session if it has not completed, including its
+        /// SNARK conversion, within this duration. No deadline if unset.
+        #[arg(long)]
+        poll_timeout: Option<humantime::Duration>,
+
+        /// How long to retry Bonsai transport errors and server errors while
+        /// polling, with backoff, before giving up on a session.
+        #[arg(long, default_value = "1m")]
+        poll_retry_duration: humantime::Duration,
+
+        /// Custom metadata to attach to the Bonsai sessions, as `key=value`.
+        /// Can be repeated, once per key.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_session_metadata)]
+        session_metadata: Vec<(String, String)>,
+
+        /// Number of cycles past which the execution of the guest fails.
+        /// Applied to the executor in dev mode, and sent to Bonsai with the
+        /// proving sessions.
+        #[arg(long)]
+        max_cycles: Option<u64>,
+
+        /// Maximum size of the segments of the execution, as a power of two
+        /// of cycles. Only applied in dev mode.
+        #[arg(
+            long,
+            value_parser = clap::value_parser!(u32).range(MIN_CYCLES_PO2 as i64..=MAX_CYCLES_PO2 as i64)
+        )]
+        segment_po2: Option<u32>,
+
+        /// Output format
+        #[arg(long, alias = "output-format", value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Migrates relay signing to a new private key, transferring the ETH held
+    /// by the old key to the new key's address.
+    RotateKey {
+        /// Ethereum Node endpoint.
+        #[arg(long, env, default_value = "ws://localhost:8545")]
+        eth_node: String,
+
+        /// Ethereum chain ID
+        #[arg(long, default_value_t = 31337)]
+        eth_chain_id: u64,
+
+        /// Private key currently used by the relay, as a hex string.
+        #[arg(long)]
+        old_key: String,
+
+        /// Private key to use from now on, as a hex string.
+        #[arg(long)]
+        new_key: String,
+
+        /// Safe multi-sig wallet the relay transactions are sent through.
+        /// When set, both keys must be owners of the Safe.
+        #[arg(long, env)]
+        safe_address: Option<Address>,
+
+        /// Keystore file holding the relay private key, updated with the new
+        /// key once the transfer is verified.
+        #[arg(long, env)]
+        keystore: Option<PathBuf>,
+
+        /// Transfer without asking for confirmation.
+        #[arg(long, short, default_value_t = false)]
+        yes: bool,
+    },
+    /// Ethereum ABI encodes a guest input and prints it as a hex string.
+    EncodeInput {
+        /// Ethereum ABI signature of the input, e.g.
+        /// `(uint256,address,bytes32[])`.
+        #[arg(long)]
+        abi: String,
+
+        /// An argument of the `--abi` signature. Can be repeated, once per
+        /// component of the signature.
+        #[arg(long = "arg", allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Ethereum ABI decodes a guest journal and prints its fields.
+    DecodeJournal {
+        /// The name of the guest binary whose journal ABI is declared in the
+        /// `--journal-abi-file` manifest
+        #[arg(requires = "journal_abi_file")]
+        guest_binary: Option<String>,
+
+        /// Ethereum ABI signature of the journal, e.g. `(bytes32,uint256)`.
+        #[arg(
+            long,
+            conflicts_with = "guest_binary",
+            required_unless_present = "guest_binary"
+        )]
+        abi: Option<String>,
+
+        /// JSON manifest mapping guest names to the ABI signature of their
+        /// journal.
+        #[arg(long, env)]
+        journal_abi_file: Option<PathBuf>,
+
+        /// The journal, either as a hex string or as `@<path>` to read a file.
+        #[arg(
+            long,
+            value_name = "HEX|@FILE",
+            conflicts_with = "query_output",
+            required_unless_present = "query_output"
+        )]
+        journal: Option<String>,
+
+        /// JSON output of a prior `query --format json` to read the journal
+        /// from, or `-` to read it from stdin.
+        #[arg(long)]
+        query_output: Option<PathBuf>,
     },
     /// Upload the RISC-V ELF binary to Bonsai.
     Upload {
         /// The name of the guest binary
         /// If not provided, all defined guests will be uploaded.
         guest_binary: Option<String>,
+
+        /// Expected SHA-256 of the guest ELF, checked before uploading.
+        #[arg(long, value_name = "SHA256_HEX", requires = "guest_binary")]
+        checksum: Option<String>,
+
+        /// Manifest of the expected SHA-256 of each guest ELF, with one
+        /// `<name>=<hash>` entry per line, checked before uploading all guests.
+        #[arg(long, conflicts_with = "guest_binary")]
+        checksum_file: Option<PathBuf>,
+
+        /// Time to wait for the upload of each guest ELF.
+        #[arg(long, env, default_value_t = DEFAULT_UPLOAD_TIMEOUT.into())]
+        upload_timeout: humantime::Duration,
+    },
+    /// Print the image IDs of the RISC-V ELF binaries, computed offline.
+    ImageId {
+        /// The name or image ID of the guest binary
+        /// If not provided, the image IDs of all defined guests are printed.
+        guest_binary: Option<String>,
+
+        /// ELF file to compute the image ID of, instead of a defined guest.
+        #[arg(long, conflicts_with = "guest_binary")]
+        elf: Option<PathBuf>,
+
+        /// Output format.
+        #[arg(long, value_enum, default_value_t = ImageIdFormat::Hex)]
+        format: ImageIdFormat,
     },
     /// Upload the RISC-V ELF binary to Bonsai.
-    Run {
+    Run(RunArgs),
+    /// Checks the configuration of `run`, taking the same options, without
+    /// running the relay: the Ethereum nodes are reachable on the configured
+    /// chains, the relay contracts are deployed, the Bonsai API answers, the
+    /// wallets hold the minimum balance and the guest ELFs are present.
+    /// Prints a `CHECK | STATUS | DETAIL` table, and exits with an error if
+    /// any check fails.
+    ValidateConfig(RunArgs),
+    /// Runs the full relay loop end to end in dev mode: deploys a test relay
+    /// contract, starts the relay, requests a callback, and checks that the
+    /// journal reaches the callback contract.
+    Selftest {
+        /// The name of the guest binary to request a callback for.
+        #[arg(default_value = "echo_frames")]
+        guest_binary: String,
+
+        /// The input to provide to the guest binary, as a hex string.
+        /// Defaults to three frames, as read by `echo_frames`.
+        input: Option<String>,
+
+        /// Ethereum Node endpoint to attach to. If not set, a local Anvil
+        /// instance is spawned for the duration of the test.
+        #[arg(long, env)]
+        eth_node: Option<String>,
+
+        /// Ethereum chain ID of the `--eth-node`.
+        #[arg(long, default_value_t = 31337, requires = "eth_node")]
+        eth_chain_id: u64,
+
+        /// Private key of the wallet deploying the contracts and relaying the
+        /// callback. Defaults to the first private key of a default Anvil
+        /// instance.
+        #[arg(short, long, env, default_value = ANVIL_DEFAULT_KEY)]
+        private_key: String,
+
+        /// Time to wait for the callback to land on chain.
+        #[arg(long, default_value = "2m")]
+        timeout: humantime::Duration,
+    },
+    /// Submits a callback request to the Bonsai Relay contract, and prints the
+    /// hash of its transaction and the request identifier.
+    RequestCallback {
         /// Bonsai Relay contract address on Ethereum
         #[arg(long, env)]
         relay_address: Address,
 
+        /// Contract receiving the callback.
+        #[arg(long)]
+        callback_contract: Address,
+
+        /// Selector of the callback function, either as a 4-byte hex string
+        /// or as a function signature, e.g. `settle(bytes)`.
+        #[arg(long, value_parser = parse_function_selector)]
+        function_selector: [u8; 4],
+
+        /// Maximum amount of gas the callback function may use.
+        #[arg(long, default_value_t = 3000000)]
+        gas_limit: u64,
+
+        /// The name or the hex-encoded image ID of the guest binary. Image IDs
+        /// of guests not known locally are requested as is.
+        guest_binary: GuestSelector,
+
+        /// The input to provide to the guest binary
+        input: Option<String>,
+
+        /// Encoding of the input
+        #[arg(long, value_enum, default_value_t = InputEncoding::Hex, requires = "input")]
+        input_encoding: InputEncoding,
+
+        /// An input frame to provide to the guest binary, either as a hex
+        /// string or as `@<path>` to read a file. Can be repeated, each frame
+        /// being read by a separate `env::read()` call in the guest.
+        #[arg(long = "frame", value_name = "HEX|@FILE", conflicts_with = "input")]
+        frames: Vec<String>,
+
+        /// Ethereum ABI signature of the input to provide to the guest binary,
+        /// e.g. `(uint256,address,bytes32[])`, encoded from the `--arg` values.
+        #[arg(long, conflicts_with_all = ["input", "frames"])]
+        abi: Option<String>,
+
+        /// An argument of the `--abi` signature. Can be repeated, once per
+        /// component of the signature.
+        #[arg(long = "arg", requires = "abi", allow_hyphen_values = true)]
+        args: Vec<String>,
+
         /// Ethereum Node endpoint.
         #[arg(long, env, default_value = "ws://localhost:8545")]
         eth_node: String,
@@ -69,245 +632,3765 @@ enum Command {
         #[arg(long, default_value_t = 31337)]
         eth_chain_id: u64,
 
-        /// Wallet Key Identifier.
-        /// Can be a private key as a hex string, or an AWS KMS key identifier.
-        /// Defaults to the first private key of a deafult Anvil instance.
-        #[arg(
-            short,
-            long,
-            env,
-            default_value = ANVIL_DEFAULT_KEY
-        )]
+        /// Private key of the wallet sending the request, as a hex string.
+        /// Defaults to the first private key of a default Anvil instance.
+        #[arg(short, long, env, default_value = ANVIL_DEFAULT_KEY)]
         private_key: String,
 
-        /// Number of connection attempts.
-        #[arg(short = 'a', long, env, default_value_t = CONNECTION_RETRY_ATTEMPTS)]
-        connection_retry_attempts: u64,
+        /// Keystore file holding the hex-encoded private key of the wallet,
+        /// as maintained by `rotate-key`.
+        #[arg(long, env, conflicts_with = "private_key")]
+        keystore: Option<PathBuf>,
 
-        /// Interval between connection attempts.
-        #[arg(short = 'i', long, env, default_value_t = CONNECTION_RETRY_INTERVAL.into(), value_parser = humantime::parse_duration)]
-        connection_retry_interval: humantime::Duration,
+        /// Wait for the relay to invoke the callback, and print the hash of
+        /// the callback transaction.
+        #[arg(long, default_value_t = false)]
+        wait: bool,
+
+        /// Time to wait for the callback with `--wait`.
+        #[arg(long, default_value = "10m", requires = "wait")]
+        timeout: humantime::Duration,
     },
-}
+    /// Resubmits the relay transaction of an event recorded in the event
+    /// database of a relay, e.g. after it failed, and prints the hash of the
+    /// new transaction once mined. Events whose callback was already accepted
+    /// on chain are not resubmitted.
+    Resubmit {
+        /// Event database of the relay, as given to `run
+        /// --event-persistence-db`.
+        #[arg(long, env)]
+        event_db: PathBuf,
 
-#[derive(Debug, Args)]
-struct GlobalOpts {
-    /// Bonsai API URL
-    #[arg(long, env, global = true, default_value = "http://localhost:8081")]
-    bonsai_api_url: String,
+        /// ID of the event in the event database.
+        #[arg(long)]
+        event_id: i64,
 
-    /// Bonsai API key
-    /// Defaults to empty, providing no authentication.
-    #[arg(long, env, global = true, default_value = "")]
-    bonsai_api_key: String,
+        /// Ethereum Node endpoint.
+        #[arg(long, env, default_value = "ws://localhost:8545")]
+        eth_node: String,
 
-    /// Toggle to enable dev_mode: only a local executor runs your
-    /// zkVM program and no proof is generated.
-    #[arg(long, env, global = true, default_value_t = false)]
-    risc0_dev_mode: bool,
+        /// Ethereum chain ID
+        #[arg(long, default_value_t = 31337)]
+        eth_chain_id: u64,
+
+        /// Private key of the wallet sending the relay transaction, as a hex
+        /// string. Defaults to the first private key of a default Anvil
+        /// instance.
+        #[arg(short, long, env, default_value = ANVIL_DEFAULT_KEY)]
+        private_key: String,
+
+        /// Keystore file holding the hex-encoded private key of the wallet,
+        /// as maintained by `rotate-key`.
+        #[arg(long, env, conflicts_with = "private_key")]
+        keystore: Option<PathBuf>,
+    },
 }
 
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-pub struct App {
-    #[clap(flatten)]
-    global_opts: GlobalOpts,
+/// Stand-in for a secret argument in the [Display](std::fmt::Display) of a
+/// [Command].
+struct Redacted;
 
-    #[command(subcommand)]
-    command: Command,
+impl std::fmt::Debug for Redacted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[REDACTED]")
+    }
 }
 
-/// Parse a slice of strings as a fixed array of uint256 tokens.
-fn parse_to_tokens(slice: &[String]) -> anyhow::Result<Token> {
-    Ok(Token::FixedArray(
-        slice
-            .iter()
-            .map(|s| -> anyhow::Result<_> { Ok(U256::from_str_radix(s, 16)?.into_token()) })
-            .collect::<Result<Vec<_>, _>>()?,
-    ))
+/// Substitutes `[REDACTED]` for a secret, e.g. a private key.
+fn redacted<T: ?Sized>(_secret: &T) -> Redacted {
+    Redacted
 }
 
-fn tokenize_snark_proof(proof: &SnarkProof) -> anyhow::Result<Token> {
-    if proof.b.len() != 2 {
-        anyhow::bail!("hex-strings encoded proof is not well formed");
-    }
-    for pair in [&proof.a, &proof.c].into_iter().chain(proof.b.iter()) {
-        if pair.len() != 2 {
-            anyhow::bail!("hex-strings encoded proof is not well formed");
+/// Name and key arguments of the command, e.g. for structured log messages.
+/// Secrets, such as private keys, are redacted, and Ethereum node URLs, which
+/// may embed an API key, are left out.
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::Query {
+                guest_binary,
+                receipt_kind,
+                prover,
+                format,
+                ..
+            } => f
+                .debug_struct("Query")
+                .field("guest_binary", guest_binary)
+                .field("receipt_kind", receipt_kind)
+                .field("prover", prover)
+                .field("format", format)
+                .finish(),
+            Command::VerifyReceipt {
+                receipt,
+                image_id,
+                guest_binary,
+            } => f
+                .debug_struct("VerifyReceipt")
+                .field("receipt", receipt)
+                .field("image_id", image_id)
+                .field("guest_binary", guest_binary)
+                .finish(),
+            Command::Diff {
+                session_a,
+                session_b,
+                ..
+            } => f
+                .debug_struct("Diff")
+                .field("session_a", session_a)
+                .field("session_b", session_b)
+                .finish(),
+            Command::CancelSession { session_id } => f
+                .debug_struct("CancelSession")
+                .field("session_id", session_id)
+                .finish(),
+            Command::DiffImageIds {
+                contract,
+                guest_binary,
+                ..
+            } => f
+                .debug_struct("DiffImageIds")
+                .field("contract", contract)
+                .field("guest_binary", guest_binary)
+                .finish(),
+            Command::Bench {
+                guest_binary,
+                iterations,
+                warmup,
+                concurrency,
+                receipt_kind,
+                ..
+            } => f
+                .debug_struct("Bench")
+                .field("guest_binary", guest_binary)
+                .field("iterations", iterations)
+                .field("warmup", warmup)
+                .field("concurrency", concurrency)
+                .field("receipt_kind", receipt_kind)
+                .finish(),
+            Command::RotateKey {
+                eth_chain_id,
+                old_key,
+                new_key,
+                safe_address,
+                keystore,
+                ..
+            } => f
+                .debug_struct("RotateKey")
+                .field("eth_chain_id", eth_chain_id)
+                .field("old_key", &redacted(old_key))
+                .field("new_key", &redacted(new_key))
+                .field("safe_address", safe_address)
+                .field("keystore", keystore)
+                .finish(),
+            Command::EncodeInput { abi, .. } => {
+                f.debug_struct("EncodeInput").field("abi", abi).finish()
+            }
+            Command::DecodeJournal {
+                guest_binary, abi, ..
+            } => f
+                .debug_struct("DecodeJournal")
+                .field("guest_binary", guest_binary)
+                .field("abi", abi)
+                .finish(),
+            Command::Upload { guest_binary, .. } => f
+                .debug_struct("Upload")
+                .field("guest_binary", guest_binary)
+                .finish(),
+            Command::ImageId {
+                guest_binary, elf, ..
+            } => f
+                .debug_struct("ImageId")
+                .field("guest_binary", guest_binary)
+                .field("elf", elf)
+                .finish(),
+            Command::Run(run_args) => run_args.fmt_redacted("Run", f),
+            Command::ValidateConfig(run_args) => run_args.fmt_redacted("ValidateConfig", f),
+            Command::Selftest {
+                guest_binary,
+                eth_chain_id,
+                private_key,
+                ..
+            } => f
+                .debug_struct("Selftest")
+                .field("guest_binary", guest_binary)
+                .field("eth_chain_id", eth_chain_id)
+                .field("private_key", &redacted(private_key))
+                .finish(),
+            Command::RequestCallback {
+                relay_address,
+                callback_contract,
+                function_selector,
+                guest_binary,
+                eth_chain_id,
+                private_key,
+                keystore,
+                wait,
+                ..
+            } => f
+                .debug_struct("RequestCallback")
+                .field("relay_address", relay_address)
+                .field("callback_contract", callback_contract)
+                .field(
+                    "function_selector",
+                    &format_args!("0x{}", hex::encode(function_selector)),
+                )
+                .field("guest_binary", guest_binary)
+                .field("eth_chain_id", eth_chain_id)
+                .field("private_key", &redacted(private_key))
+                .field("keystore", keystore)
+                .field("wait", wait)
+                .finish(),
+            Command::Resubmit {
+                event_db,
+                event_id,
+                eth_chain_id,
+                private_key,
+                keystore,
+                ..
+            } => f
+                .debug_struct("Resubmit")
+                .field("event_db", event_db)
+                .field("event_id", event_id)
+                .field("eth_chain_id", eth_chain_id)
+                .field("private_key", &redacted(private_key))
+                .field("keystore", keystore)
+                .finish(),
         }
     }
-    Ok(Token::FixedArray(vec![
-        parse_to_tokens(&proof.a)?,
-        Token::FixedArray(vec![
-            parse_to_tokens(&proof.b[0])?,
-            parse_to_tokens(&proof.b[1])?,
-        ]),
-        parse_to_tokens(&proof.c)?,
-    ]))
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = App::parse();
-    let dev_mode = args.global_opts.risc0_dev_mode;
+/// Options of the `run` and `validate-config` commands.
+#[derive(Args)]
+struct RunArgs {
+    /// Bonsai Relay contract address on Ethereum. Repeat the option, or
+    /// separate the addresses with commas, to relay for several
+    /// contracts from the same wallet.
+    #[arg(long, env, required = true, value_delimiter = ',')]
+    relay_address: Vec<Address>,
 
-    match args.command {
-        Command::Query {
-            guest_binary,
-            input,
-        } => {
-            // Search list for requested binary name
-            let guest_entry = resolve_guest_entry(GUEST_LIST, &guest_binary)
-                .context("failed to resolve guest entry")?;
+    /// Ethereum Node endpoint, a ws://, wss://, http:// or https:// URL,
+    /// or the IPC socket of a node on the same host, as ipc://<path> or a
+    /// bare path. Nodes reached over HTTP are polled for new blocks and
+    /// logs.
+    #[arg(long, env, default_value = "ws://localhost:8545")]
+    eth_node: String,
 
-            // Execute or return image id
-            let output_tokens = match &input {
-                // Input provided. Return the Ethereum ABI encoded journal and
-                Some(input) => {
-                    let output = resolve_image_output(input, &guest_entry, dev_mode)
-                        .await
-                        .context("failed to resolve image output")?;
-                    match (dev_mode, output) {
-                        (true, Output::Execution { journal }) => {
-                            vec![Token::Bytes(journal)]
-                        }
-                        (
-                            false,
-                            Output::Bonsai {
-                                journal,
-                                receipt_metadata,
-                                snark_proof,
-                            },
-                        ) => {
-                            vec![
-                                Token::Bytes(journal),
-                                Hash::from(<[u8; 32]>::from(receipt_metadata.post.digest()))
-                                    .into_token(),
-                                Token::Bytes(ethers::abi::encode(&[tokenize_snark_proof(
-                                    &snark_proof,
-                                )?])),
-                            ]
-                        }
-                        _ => {
-                            anyhow::bail!("invalid dev mode and output combination: {:?}", dev_mode)
-                        }
-                    }
-                }
-                // No input. Return the Ethereum ABI encoded bytes32 image ID.
-                None => vec![
-                    Hash::from(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id)).into_token(),
-                ],
-            };
+    /// Ethereum chain ID
+    #[arg(long, default_value_t = 31337)]
+    eth_chain_id: u64,
 
-            let output = hex::encode(ethers::abi::encode(&output_tokens));
-            print!("{output}");
-            std::io::stdout()
-                .flush()
-                .context("failed to flush stdout buffer")?;
-        }
-        Command::Upload { guest_binary } => {
-            let image_ids = upload_images(
-                guest_binary,
-                &args.global_opts.bonsai_api_url,
-                &args.global_opts.bonsai_api_key,
-            )
-            .await?;
+    /// Wallet Key Identifier.
+    /// Can be a private key as a hex string, an AWS KMS key identifier,
+    /// the URL of a remote signer prefixed with `remote:`, e.g.
+    /// remote:https://web3signer.example.com:9000, or the derivation
+    /// path of a Ledger account prefixed with `ledger:`, e.g.
+    /// ledger:m/44'/60'/0'/0/0.
+    /// Repeat it, or separate the keys with commas, to spread the relay
+    /// transactions across several keys, each with its own nonces.
+    /// Defaults to the first private key of a deafult Anvil instance.
+    #[arg(
+        short,
+        long,
+        env,
+        default_value = ANVIL_DEFAULT_KEY,
+        value_delimiter = ','
+    )]
+    private_key: Vec<String>,
 
-            let output = hex::encode(ethers::abi::encode(&[Token::Array(
-                image_ids
-                    .into_iter()
-                    .map(|image_id| {
-                        Hash::from(bytemuck::cast::<_, [u8; 32]>(image_id)).into_token()
-                    })
-                    .collect(),
-            )]));
-            print!("{output}");
-            std::io::stdout()
-                .flush()
-                .context("failed to flush stdout buffer")?;
-        }
-        Command::Run {
-            relay_address,
-            eth_node,
-            eth_chain_id,
-            private_key,
-            connection_retry_attempts,
-            connection_retry_interval,
-        } => {
-            let relayer = Relayer {
-                rest_api: true,
-                dev_mode: dev_mode,
-                rest_api_port: "8080".to_string(),
-                bonsai_api_url: args.global_opts.bonsai_api_url.clone(),
-                bonsai_api_key: args.global_opts.bonsai_api_key.clone(),
-                relay_contract_address: relay_address,
-            };
-            let client_config = EthersClientConfig::new(
-                eth_node,
-                eth_chain_id,
-                private_key.try_into()?,
-                connection_retry_attempts,
-                connection_retry_interval.into(),
-            );
-            let server_handle = tokio::spawn(relayer.run(client_config));
+    /// Keystore file holding the hex-encoded private key of the wallet,
+    /// as maintained by `rotate-key`.
+    #[arg(long, env, conflicts_with = "private_key")]
+    keystore: Option<PathBuf>,
 
-            // HACK: Wait 1 second to give local Bonsai a chance to start.
-            std::thread::sleep(std::time::Duration::from_secs(1));
+    /// AWS region of the KMS key, if the private key is a KMS key
+    /// identifier. Defaults to the region of the AWS environment.
+    #[arg(long, env)]
+    kms_region: Option<String>,
 
-            // Upload all locally defined images.
-            upload_images(
-                None,
-                &args.global_opts.bonsai_api_url,
-                &args.global_opts.bonsai_api_key,
-            )
-            .await?;
+    /// AWS profile whose credentials, and region if --kms-region is not
+    /// set, are used to reach KMS.
+    #[arg(long, env)]
+    kms_profile: Option<String>,
 
-            // Wait for the server to exit.
-            let _ = server_handle.await;
-        }
-    }
-    Ok(())
-}
+    /// KMS endpoint overriding the one of the region, e.g.
+    /// http://localhost:4566 for localstack.
+    #[arg(long, env)]
+    kms_endpoint: Option<String>,
 
-/// Upload a single specified image, or, if guest_binary is None, upload all
-/// images in the GUEST_LIST. Returns a list of uploaded image IDs.
-async fn upload_images(
-    guest_binary: Option<String>,
-    bonsai_api_url: &str,
-    bonsai_api_key: &str,
-) -> anyhow::Result<Vec<Digest>> {
-    // Create a list of either the single binary name to upload or all guests.
-    let guest_entries = guest_binary.map_or_else(
-        || Ok::<_, anyhow::Error>(GUEST_LIST.iter().cloned().collect::<Vec<_>>()),
-        |name| Ok(vec![resolve_guest_entry(&GUEST_LIST, &name)?]),
-    )?;
-
-    // Upload each guest binary.
-    let mut image_ids = Vec::<Digest>::new();
-    for guest_entry in guest_entries.iter() {
-        // Search list for requested binary name
-        let image_id = hex::encode(Vec::from(bytemuck::cast::<[u32; 8], [u8; 32]>(
-            guest_entry.image_id,
-        )));
-
-        // upload binary to Bonsai
-        let bonsai_client =
-            get_client_from_parts(bonsai_api_url.to_string(), bonsai_api_key.to_string()).await?;
-        let img_id = image_id.clone();
-
-        match put_image(
-            bonsai_client.clone(),
-            img_id.clone(),
-            guest_entry.elf.to_vec(),
-        )
-        .await
-        {
-            Ok(()) | Err(SdkErr::ImageIdExists) => Ok::<_, anyhow::Error>(()),
-            Err(err) => Err(err.into()),
-        }?;
+    /// Address of the wallet held by the remote signer, if the private
+    /// key is a remote signer URL. Defaults to the first of its
+    /// `eth_accounts`.
+    #[arg(long, env)]
+    signer_address: Option<Address>,
 
-        image_ids.push(guest_entry.image_id.into());
-    }
+    /// PEM file of a CA certificate to trust for the remote signer
+    /// connection, in addition to the system ones.
+    #[arg(long, env)]
+    signer_tls_ca_cert: Option<PathBuf>,
+
+    /// PEM file of the client certificate and private key to present to
+    /// the remote signer.
+    #[arg(long, env)]
+    signer_tls_client_cert: Option<PathBuf>,
+
+    /// Time to wait for each relay transaction to be confirmed on the
+    /// Ledger, if the private key is a Ledger account. Relay
+    /// transactions are then sent one at a time.
+    #[arg(long, env, default_value_t = DEFAULT_LEDGER_SIGNING_TIMEOUT.into())]
+    ledger_signing_timeout: humantime::Duration,
+
+    /// Time to wait on startup for the Ledger to be unlocked, with its
+    /// Ethereum app open.
+    #[arg(long, env, default_value_t = DEFAULT_LEDGER_UNLOCK_TIMEOUT.into())]
+    ledger_unlock_timeout: humantime::Duration,
+
+    /// Safe multi-sig wallet address on Ethereum.
+    /// When set, relay transactions are sent through the Safe
+    /// `execTransaction`.
+    #[arg(long, env)]
+    safe_address: Option<Address>,
+
+    /// Private key of a Safe owner co-signing the relay transactions.
+    /// Can be repeated, once per co-signer.
+    #[arg(long = "safe-signer-key", requires = "safe_address")]
+    safe_signer_keys: Vec<String>,
+
+    /// Number of connection attempts.
+    #[arg(short = 'a', long, env, default_value_t = CONNECTION_RETRY_ATTEMPTS)]
+    connection_retry_attempts: u64,
+
+    /// Interval between connection attempts.
+    #[arg(short = 'i', long, env, default_value_t = CONNECTION_RETRY_INTERVAL.into())]
+    connection_retry_interval: humantime::Duration,
+
+    /// Timeout of a single Ethereum node request.
+    #[arg(long, env, default_value_t = DEFAULT_ETH_NODE_TIMEOUT.into())]
+    eth_node_timeout: humantime::Duration,
+
+    /// Send the chain ID, relay contract code and wallet balance requests
+    /// of the startup and configuration checks of each chain in a single
+    /// JSON-RPC batch, rather than one after the other, e.g. for a distant
+    /// Ethereum node.
+    #[arg(long, env)]
+    json_rpc_batch: bool,
+
+    /// Interval between the requests for new blocks and logs to an
+    /// Ethereum node reached over HTTP.
+    #[arg(long, env, default_value_t = DEFAULT_ETH_NODE_POLL_INTERVAL.into())]
+    poll_interval: humantime::Duration,
+
+    /// Interval between the requests for the head block checking that the
+    /// connection to a subscribed Ethereum node is alive.
+    #[arg(long, env, default_value_t = DEFAULT_WS_HEARTBEAT_INTERVAL.into())]
+    ws_heartbeat_interval: humantime::Duration,
+
+    /// Time without a new block after which the connection to a
+    /// subscribed Ethereum node is made again, and the events missed
+    /// meanwhile backfilled. Set to 0s to not check the connection.
+    #[arg(long, env, default_value_t = DEFAULT_WS_STALE_TIMEOUT.into())]
+    ws_stale_timeout: humantime::Duration,
+
+    /// Interval between the WebSocket ping frames sent to the Ethereum
+    /// node, detecting connections gone silent without a close frame. Set
+    /// to 0s to not ping the node.
+    #[arg(long, env, default_value_t = DEFAULT_WS_PING_INTERVAL.into())]
+    eth_node_ws_ping_interval: humantime::Duration,
 
-    Ok(image_ids)
+    /// Time to wait for the pong answering a WebSocket ping frame, after
+    /// which the connection to the Ethereum node is made again.
+    #[arg(long, env, default_value_t = DEFAULT_WS_PONG_TIMEOUT.into())]
+    eth_node_ws_pong_timeout: humantime::Duration,
+
+    /// Number of WebSocket connections to the Ethereum node, taking the
+    /// subscriptions and transactions in turn. A connection closed for
+    /// good is replaced.
+    #[arg(long, env, default_value_t = DEFAULT_ETH_NODE_POOL_SIZE)]
+    eth_node_pool_size: usize,
+
+    /// Time to wait for in-flight transactions to be confirmed on shutdown.
+    #[arg(long, env, default_value_t = DEFAULT_SHUTDOWN_TIMEOUT.into())]
+    shutdown_timeout: humantime::Duration,
+
+    /// IP address the relay REST API binds to, either IPv4 or IPv6.
+    #[arg(long, env, default_value = REST_API_BIND)]
+    rest_api_bind: String,
+
+    /// Port serving the relay REST API.
+    #[arg(long, env, default_value = REST_API_PORT)]
+    rest_api_port: String,
+
+    /// PEM certificate chain to serve the relay REST API over TLS with,
+    /// reloaded on SIGHUP.
+    #[arg(long, env, requires = "rest_api_tls_key")]
+    rest_api_tls_cert: Option<PathBuf>,
+
+    /// PEM private key of --rest-api-tls-cert.
+    #[arg(long, env, requires = "rest_api_tls_cert")]
+    rest_api_tls_key: Option<PathBuf>,
+
+    /// Comma-separated guest binary names or image IDs of the callback
+    /// requests to process. All callback requests are processed if not set.
+    #[arg(long, env, value_delimiter = ',')]
+    relay_event_filter_topics: Vec<String>,
+
+    /// Relay SNARK proofs without checking that their points are valid
+    /// BN254 curve points, e.g. to test deliberately malformed proofs.
+    #[arg(long, env, default_value_t = false)]
+    skip_proof_validation: bool,
+
+    /// Minimum wallet balance, in ether, required to submit a relay
+    /// transaction. Below it, transactions are held back until the wallet
+    /// is topped up.
+    #[arg(long, env, default_value = DEFAULT_MIN_ETH_BALANCE, value_parser = parse_ether)]
+    min_eth_balance: U256,
+
+    /// Cap, in gwei, on the fee per gas of the relay transactions. Above
+    /// it, transactions are held back, and the fees checked again every
+    /// 30 seconds.
+    #[arg(long, env, value_parser = parse_gwei)]
+    fee_cap: Option<U256>,
+
+    /// Number of completed proofs held back while the fees exceed the
+    /// --fee-cap. Further proofs are dropped, failing their requests.
+    #[arg(long, env, default_value_t = DEFAULT_FEE_QUEUE_DEPTH, requires = "fee_cap")]
+    fee_queue_depth: usize,
+
+    /// Number of times a relay transaction reverted on chain is
+    /// resubmitted, with an exponential backoff. Reverts that would happen
+    /// again, e.g. ProofAlreadySubmitted, are not retried.
+    #[arg(long, env, default_value_t = 0)]
+    retry_on_revert: u32,
+
+    /// Time without being mined after which a relay transaction is
+    /// considered stuck, and resubmitted with the same nonce and bumped
+    /// fees.
+    #[arg(long, env, default_value_t = DEFAULT_STUCK_TX_TIMEOUT.into())]
+    stuck_tx_timeout: humantime::Duration,
+
+    /// Percentage by which the fees of a stuck relay transaction are
+    /// bumped when resubmitting it, up to 5 times. Set to 0 to never
+    /// resubmit.
+    #[arg(long, env, default_value_t = DEFAULT_TX_RESUBMIT_BUMP)]
+    tx_resubmit_bump: u64,
+
+    /// Number of blocks mined on top of a relay transaction during which
+    /// it is checked to still be on chain. A transaction dropped by a
+    /// reorg meanwhile is resubmitted. Set to 0 to not follow mined
+    /// transactions.
+    #[arg(long, env, default_value_t = 0)]
+    reorg_depth: u64,
+
+    /// Number of relay transactions in flight at once, on each chain,
+    /// from being sent until confirmed. Further completed proofs are
+    /// queued.
+    #[arg(
+        long,
+        env,
+        default_value_t = DEFAULT_MAX_CONCURRENT_RELAYS,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    max_concurrent_relays: usize,
+
+    /// Number of completed proofs queued while --max-concurrent-relays
+    /// transactions are in flight. When full, the oldest queued proof is
+    /// dropped, failing its request.
+    #[arg(
+        long,
+        env,
+        default_value_t = DEFAULT_RELAY_QUEUE_DEPTH,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    relay_queue_depth: usize,
+
+    /// Maximum ether spent on gas by the relay transactions of each chain
+    /// over a rolling 24 hours. Once reached, the transactions of the chain
+    /// are held back until the spend leaves the window, or an admin raises
+    /// the limit or resumes.
+    #[arg(long, env, value_parser = parse_ether)]
+    max_daily_spend: Option<U256>,
+
+    /// Percentage added on top of the gas estimate of each callback to
+    /// set its gas limit. Any of the callback gas options estimates the
+    /// gas of the callbacks before relaying them.
+    #[arg(long, env)]
+    callback_gas_headroom: Option<u64>,
+
+    /// Lower bound on the estimated gas limit of the callbacks.
+    #[arg(long, env)]
+    callback_gas_limit_min: Option<u64>,
+
+    /// Upper bound on the estimated gas limit of the callbacks. Requests
+    /// of callbacks estimated to use more fail their gas precheck.
+    #[arg(long, env)]
+    callback_gas_limit_max: Option<u64>,
+
+    /// Treat the gas limit of a callback request as a ceiling on its
+    /// estimated gas limit, rather than a hint. Requests of callbacks
+    /// estimated to use more fail their gas precheck.
+    #[arg(long, env)]
+    respect_requested_gas_limit: bool,
+
+    /// Submit the relay transactions to the public mempool, or to the
+    /// private relay of --private-relay-url.
+    #[arg(long, env, value_enum, default_value_t = TxEndpoint::Public)]
+    tx_endpoint: TxEndpoint,
+
+    /// JSON-RPC URL of the private relay, e.g. Flashbots Protect, the
+    /// relay transactions are sent to with --tx-endpoint private.
+    #[arg(long, env, required_if_eq("tx_endpoint", "private"))]
+    private_relay_url: Option<String>,
+
+    /// Blocks without being included after which a relay transaction
+    /// sent to the private relay is broadcast to the public mempool.
+    #[arg(long, env, default_value_t = DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS)]
+    private_relay_fallback_blocks: u64,
+
+    /// Log the gas price of each chain on this interval, e.g. 30s, with
+    /// its moving average over the last 5 samples and its change from
+    /// the previous one, to tune --fee-cap. Stops on shutdown.
+    #[arg(long, env)]
+    watch_gas_price: Option<humantime::Duration>,
+
+    /// Hex prefix of the journals of the proofs to relay, e.g. the 4-byte
+    /// selector of a proposal type. Repeat to relay the proofs matching any
+    /// of them. Other proofs are logged at DEBUG level and skipped.
+    #[arg(long, env, value_delimiter = ',', value_parser = parse_journal_filter)]
+    journal_filter: Vec<Bytes>,
+
+    /// Write the relay lifecycle events, e.g. sessions created or
+    /// transactions confirmed, as newline-delimited JSON to stderr.
+    #[arg(long, env, default_value_t = false)]
+    emit_events: bool,
+
+    /// File to append the relay events to, instead of stderr.
+    #[arg(long, env, requires = "emit_events")]
+    events_file: Option<PathBuf>,
+
+    /// Relay pre-generated proofs submitted to the REST API on
+    /// /v1/submissions, instead of proving callback requests on Bonsai.
+    /// Only proofs of the --relay-event-filter-topics guests are accepted,
+    /// if set.
+    #[arg(long, env, default_value_t = false)]
+    submission_only: bool,
+
+    /// File of previously emitted CallbackRequest logs, one JSON serialized
+    /// log per line, to process on start as if they arrived live, e.g. to
+    /// recover requests missed during a reorg. Logs whose transaction is
+    /// no longer on chain are skipped.
+    #[arg(long, env)]
+    replay_events: Option<PathBuf>,
+
+    /// Block from which to scan the CallbackRequest events on start,
+    /// e.g. to recover the requests emitted while the relay was down,
+    /// before subscribing to new events. Defaults to the head block, only
+    /// processing new events.
+    #[arg(long, env)]
+    start_block: Option<u64>,
+
+    /// Last block scanned from --start-block, e.g. for a bounded scan of
+    /// past events. Defaults to the head block.
+    #[arg(long, env, requires = "start_block")]
+    end_block: Option<u64>,
+
+    /// URL to POST a JSON alert to whenever relaying fails, e.g. a Slack
+    /// or PagerDuty webhook.
+    #[arg(long, env)]
+    alert_webhook: Option<String>,
+
+    /// Program run with a JSON alert on its stdin when the reconnects to
+    /// the Ethereum node are exhausted, the wallet balance drops below
+    /// the minimum, Bonsai is unreachable for
+    /// --alert-bonsai-unreachable-after or the dead letters reach
+    /// --alert-dead-letter-threshold.
+    #[arg(long, env)]
+    alert_cmd: Option<PathBuf>,
+
+    /// Minimum time between two runs of --alert-cmd for the same kind of
+    /// alert.
+    #[arg(long, env, default_value_t = DEFAULT_ALERT_CMD_COOLDOWN.into(), requires = "alert_cmd")]
+    alert_cmd_cooldown: humantime::Duration,
+
+    /// Time for which Bonsai is to be unreachable before running
+    /// --alert-cmd.
+    #[arg(long, env, default_value_t = DEFAULT_BONSAI_UNREACHABLE_ALERT.into(), requires = "alert_cmd")]
+    alert_bonsai_unreachable_after: humantime::Duration,
+
+    /// Number of failed callback requests in the dead-letter list at
+    /// which --alert-cmd is run.
+    #[arg(long, env, default_value_t = DEFAULT_DEAD_LETTER_ALERT_THRESHOLD, requires = "alert_cmd")]
+    alert_dead_letter_threshold: usize,
+
+    /// TOML file of additional chains to relay on, as `[[chain]]` entries
+    /// with their own chain_id, eth_node, relay_addresses, private_key and
+    /// optional min_eth_balance, fee_cap and fee_queue_depth. The Bonsai
+    /// pipeline and the REST API are shared by all chains.
+    #[arg(long, env)]
+    chain_config: Option<PathBuf>,
+
+    /// TOML file of settings overriding the command line ones. On SIGHUP,
+    /// its image_ids (as hex image IDs), fee_cap, fee_queue_depth,
+    /// max_concurrent_relays, relay_queue_depth, alert_webhook and
+    /// log_level are reloaded without restarting the relay, while changes
+    /// to its eth_node, private_key, relay_addresses, rest_api_bind and
+    /// rest_api_port require a restart.
+    #[arg(long, env)]
+    config_file: Option<PathBuf>,
+
+    /// SQLite database to record the processed CallbackRequest events in,
+    /// created if missing. Recorded events are not processed again, and
+    /// the events whose relay transaction was sent but not confirmed are
+    /// tracked again on start.
+    #[arg(long, env)]
+    event_persistence_db: Option<PathBuf>,
+
+    /// Age after which completed events are removed from the
+    /// --event-persistence-db, e.g. 30d. Completed events are kept if
+    /// unset.
+    #[arg(long, env, requires = "event_persistence_db")]
+    prune_events_older_than: Option<humantime::Duration>,
+
+    /// Age after which the requests of on-chain events expire if not
+    /// proven yet, e.g. 1h. Requests posted to the REST API carry their
+    /// own deadline.
+    #[arg(long, env)]
+    request_max_age: Option<humantime::Duration>,
+
+    /// Stop the Bonsai session of a request whose deadline passes while
+    /// it is being proven.
+    #[arg(long, env)]
+    cancel_expired_sessions: bool,
+
+    /// Maximum number of Bonsai sessions in flight. New on-chain events
+    /// are deferred past it, and REST requests rejected with a 429.
+    #[arg(long, env)]
+    max_pending_requests: Option<usize>,
+
+    /// Number of failed requests kept in the dead-letter list, listed
+    /// with `GET /v1/callbacks?status=failed` and retried with
+    /// `POST /v1/callbacks/{id}/retry`. The oldest ones are evicted first.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_DEAD_LETTERS)]
+    max_dead_letter: usize,
+
+    /// Requests accepted per minute from each API key, or from each
+    /// client IP address for requests without one, by the v1 REST API
+    /// routes. Requests past it are rejected with a 429. Requests are not
+    /// rate limited if unset.
+    #[arg(long, env, value_parser = clap::value_parser!(u32).range(1..))]
+    rest_api_requests_per_minute: Option<u32>,
+
+    /// Requests accepted at once from each client, within
+    /// `--rest-api-requests-per-minute`.
+    #[arg(long, env, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..), requires = "rest_api_requests_per_minute")]
+    rest_api_burst: u32,
+
+    /// Maximum size, in bytes, of the body of a REST API request, e.g. of
+    /// the guest input of a callback request. Larger requests are
+    /// rejected with a 413.
+    #[arg(long, env, default_value_t = DEFAULT_MAX_REQUEST_BODY_SIZE)]
+    max_request_body_size: usize,
+
+    /// Serve a Swagger UI of the REST API on `/swagger-ui`. The OpenAPI
+    /// specification is always served on `/v1/openapi.json`.
+    #[arg(long, env)]
+    rest_api_docs: bool,
+
+    /// Key expected in the `x-admin-key` header of the admin REST API
+    /// requests, e.g. `POST /v1/admin/upload-images` to upload the guests
+    /// to Bonsai again. The admin routes are not served if unset.
+    #[arg(long, env)]
+    admin_api_key: Option<String>,
+
+    /// Time to wait for the upload of each guest ELF to Bonsai on start.
+    #[arg(long, env, default_value_t = DEFAULT_UPLOAD_TIMEOUT.into())]
+    upload_timeout: humantime::Duration,
+
+    /// Skip the upload of the guest ELFs to Bonsai on start, e.g. on a
+    /// restart once they are already uploaded.
+    #[arg(long, env, conflicts_with = "submission_only")]
+    no_upload: bool,
+
+    /// Upload the guest ELFs to Bonsai and print their image IDs, as the
+    /// upload command does, then exit without starting the relay.
+    #[arg(long, env, conflicts_with_all = ["no_upload", "submission_only"])]
+    upload_only: bool,
+
+    /// URLs of further Bonsai API instances, e.g. behind a load balancer.
+    /// Image uploads and session polls of the relay take turns between
+    /// them and --bonsai-api-url, moving to the next instance on a 5xx
+    /// error. Repeat the option, or separate the URLs with commas.
+    #[arg(long, env, value_delimiter = ',')]
+    bonsai_api_urls: Vec<String>,
+
+    /// Send the image uploads, session creations and session polls of the
+    /// relay to the gRPC API of Bonsai, at the same URLs. Requires the
+    /// `grpc` feature.
+    #[arg(long, env)]
+    grpc: bool,
+}
+
+impl RunArgs {
+    /// Format the key arguments under `name`, as [Command] does.
+    fn fmt_redacted(&self, name: &str, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let RunArgs {
+            relay_address,
+            eth_chain_id,
+            private_key,
+            keystore,
+            kms_region,
+            kms_profile,
+            kms_endpoint,
+            signer_address,
+            signer_tls_ca_cert,
+            signer_tls_client_cert,
+            safe_address,
+            safe_signer_keys,
+            rest_api_bind,
+            rest_api_port,
+            rest_api_tls_cert,
+            rest_api_tls_key,
+            relay_event_filter_topics,
+            submission_only,
+            start_block,
+            end_block,
+            alert_webhook,
+            chain_config,
+            config_file,
+            admin_api_key,
+            no_upload,
+            upload_only,
+            ..
+        } = self;
+        f.debug_struct(name)
+            .field("relay_address", relay_address)
+            .field("eth_chain_id", eth_chain_id)
+            .field("private_key", &redacted(private_key))
+            .field("keystore", keystore)
+            .field("kms_region", kms_region)
+            .field("kms_profile", kms_profile)
+            .field("kms_endpoint", kms_endpoint)
+            .field("signer_address", signer_address)
+            .field("signer_tls_ca_cert", signer_tls_ca_cert)
+            .field("signer_tls_client_cert", signer_tls_client_cert)
+            .field("safe_address", safe_address)
+            .field(
+                "safe_signer_keys",
+                &safe_signer_keys.iter().map(redacted).collect::<Vec<_>>(),
+            )
+            .field("rest_api_bind", rest_api_bind)
+            .field("rest_api_port", rest_api_port)
+            .field("rest_api_tls_cert", rest_api_tls_cert)
+            .field("rest_api_tls_key", rest_api_tls_key)
+            .field("relay_event_filter_topics", relay_event_filter_topics)
+            .field("submission_only", submission_only)
+            .field("start_block", start_block)
+            .field("end_block", end_block)
+            // Webhook URLs, e.g. of Slack, hold their own credentials.
+            .field("alert_webhook", &alert_webhook.as_ref().map(redacted))
+            .field("chain_config", chain_config)
+            .field("config_file", config_file)
+            .field("admin_api_key", &admin_api_key.as_ref().map(redacted))
+            .field("no_upload", no_upload)
+            .field("upload_only", upload_only)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Args)]
+struct GlobalOpts {
+    /// Bonsai API URL
+    #[arg(long, env, global = true, default_value = "http://localhost:8081")]
+    bonsai_api_url: String,
+
+    /// Bonsai API key
+    /// Defaults to empty, providing no authentication.
+    #[arg(long, env, global = true, default_value = "")]
+    bonsai_api_key: String,
+
+    /// Timeout of each request to Bonsai, from connecting to reading the
+    /// response. Image uploads may also take up to `--upload-timeout`.
+    #[arg(long, env, global = true, default_value_t = DEFAULT_BONSAI_TIMEOUT.into())]
+    bonsai_timeout: humantime::Duration,
+
+    /// Timeout to connect to Bonsai.
+    #[arg(long, env, global = true, default_value_t = DEFAULT_BONSAI_CONNECT_TIMEOUT.into())]
+    bonsai_connect_timeout: humantime::Duration,
+
+    /// Proxy URL to send the requests to Bonsai through. Defaults to the
+    /// proxy of the `HTTPS_PROXY` environment variable, if set.
+    #[arg(long, env, global = true)]
+    bonsai_proxy: Option<String>,
+
+    /// PEM file of a CA certificate to trust for Bonsai and its proxy, in
+    /// addition to the system ones, e.g. the private CA of a corporate proxy.
+    #[arg(long, env, global = true)]
+    bonsai_ca_cert: Option<PathBuf>,
+
+    /// Accept invalid TLS certificates from Bonsai and its proxy. This
+    /// disables their authentication: only use it in lab environments.
+    #[arg(long, env, global = true, default_value_t = false)]
+    bonsai_insecure_tls: bool,
+
+    /// PEM file of the TLS client certificate to authenticate to Bonsai with,
+    /// for deployments requiring mutual TLS. Requires `--bonsai-tls-key`.
+    #[arg(long, env, global = true, requires = "bonsai_tls_key")]
+    bonsai_tls_cert: Option<PathBuf>,
+
+    /// PEM file of the PKCS#8 private key of `--bonsai-tls-cert`.
+    #[arg(long, env, global = true, requires = "bonsai_tls_cert")]
+    bonsai_tls_key: Option<PathBuf>,
+
+    /// Sign the requests to Bonsai with HMAC-SHA256, as `X-Bonsai-Signature`
+    /// headers. Requires `--hmac-secret`.
+    #[arg(
+        long,
+        env,
+        global = true,
+        default_value_t = false,
+        requires = "hmac_secret"
+    )]
+    use_hmac: bool,
+
+    /// Hex encoded shared secret to sign the requests to Bonsai with, when
+    /// `--use-hmac` is set.
+    #[arg(long, env, global = true)]
+    hmac_secret: Option<HmacSecret>,
+
+    /// Toggle to enable dev_mode: only a local executor runs your
+    /// zkVM program and no proof is generated.
+    #[arg(long, env, global = true, default_value_t = false)]
+    risc0_dev_mode: bool,
+
+    /// Directory of guest `*.elf` files, named after the guest binary, to
+    /// load in addition to the compiled-in guests. Guests whose image ID is
+    /// already compiled in are skipped.
+    #[arg(long, env, global = true)]
+    elf_dir: Option<PathBuf>,
+
+    /// File listing guest ELFs to load in addition to the compiled-in guests,
+    /// one `[<name>=]<source>` entry per line, the source being an
+    /// `ipfs://<cid>` URI or the path of a local ELF file. Guests whose image
+    /// ID is already loaded are skipped.
+    #[arg(long, env, global = true)]
+    elf_list_file: Option<PathBuf>,
+
+    /// IPFS gateway the ELFs listed as `ipfs://` URIs are downloaded from.
+    #[arg(long, env, global = true, default_value = DEFAULT_IPFS_GATEWAY)]
+    ipfs_gateway: String,
+
+    /// Directory the ELFs downloaded from IPFS are cached in, a
+    /// `bonsai-relay-elf-cache` directory of the system temporary directory
+    /// by default.
+    #[arg(long, env, global = true)]
+    elf_cache_dir: Option<PathBuf>,
+}
+
+impl GlobalOpts {
+    fn bonsai_client(&self) -> BonsaiClientOpts {
+        BonsaiClientOpts {
+            connect_timeout: self.bonsai_connect_timeout.into(),
+            timeout: self.bonsai_timeout.into(),
+            proxy: self.bonsai_proxy.clone(),
+            ca_cert: self.bonsai_ca_cert.clone(),
+            insecure_tls: self.bonsai_insecure_tls,
+            hmac_secret: self
+                .hmac_secret
+                .as_ref()
+                .filter(|_| self.use_hmac)
+                .map(|secret| secret.0.clone()),
+            tls_identity: self
+                .bonsai_tls_cert
+                .clone()
+                .zip(self.bonsai_tls_key.clone()),
+        }
+    }
+}
+
+/// Hex-encoded HMAC secret, redacted from debug output.
+#[derive(Clone, PartialEq, Eq)]
+struct HmacSecret(Vec<u8>);
+
+impl std::fmt::Debug for HmacSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HmacSecret(..)")
+    }
+}
+
+impl FromStr for HmacSecret {
+    type Err = anyhow::Error;
+
+    fn from_str(secret: &str) -> anyhow::Result<Self> {
+        let secret = Vec::from_hex(secret.trim_start_matches("0x"))?;
+        anyhow::ensure!(!secret.is_empty(), "HMAC secret must not be empty");
+        Ok(Self(secret))
+    }
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+pub struct App {
+    #[clap(flatten)]
+    global_opts: GlobalOpts,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Parse a hex-encoded 4-byte verifier selector.
+fn parse_seal_selector(selector: &str) -> anyhow::Result<[u8; 4]> {
+    <[u8; 4]>::from_hex(selector.trim_start_matches("0x"))
+        .with_context(|| format!("invalid seal selector, expected 4 hex-encoded bytes: {selector}"))
+}
+
+/// Parse a hex-encoded journal prefix.
+fn parse_journal_filter(filter: &str) -> anyhow::Result<Bytes> {
+    let prefix = hex::decode(filter.trim_start_matches("0x"))
+        .with_context(|| format!("invalid journal filter: {filter}"))?;
+    anyhow::ensure!(!prefix.is_empty(), "invalid journal filter: empty prefix");
+    Ok(prefix.into())
+}
+
+/// Parse a `key=value` pair of session metadata.
+fn parse_session_metadata(pair: &str) -> anyhow::Result<(String, String)> {
+    match pair.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => anyhow::bail!("invalid session metadata, expected key=value: {pair}"),
+    }
+}
+
+/// Parse a `KEY=VALUE` guest environment variable.
+fn parse_guest_env(pair: &str) -> anyhow::Result<(String, String)> {
+    let Some((key, value)) = pair.split_once('=') else {
+        anyhow::bail!("invalid guest environment variable, expected KEY=VALUE: {pair}");
+    };
+    validate_guest_env_key(key)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Parse a `KEY=VALUE` secret guest environment variable, without printing
+/// the value in the errors.
+fn parse_guest_env_secret(pair: &str) -> anyhow::Result<(String, String)> {
+    let Some((key, value)) = pair.split_once('=') else {
+        anyhow::bail!("invalid secret guest environment variable, expected KEY=VALUE");
+    };
+    validate_guest_env_key(key)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Hex-encoded Ethereum ABI array of the image IDs, as bytes32.
+/// Parse a function selector, either as a 4-byte hex string or as the
+/// signature of the function.
+fn parse_function_selector(selector: &str) -> anyhow::Result<[u8; 4]> {
+    if selector.contains('(') {
+        return Ok(ethers::utils::id(selector));
+    }
+    <[u8; 4]>::from_hex(selector.trim_start_matches("0x"))
+        .with_context(|| format!("invalid function selector: {selector}"))
+}
+
+/// Resolve the guest input given by the `query` and `request-callback` input
+/// options, if any.
+fn resolve_input(
+    input: Option<String>,
+    input_encoding: InputEncoding,
+    frames: &[String],
+    abi: Option<String>,
+    args: &[String],
+) -> anyhow::Result<Option<Vec<u8>>> {
+    if let Some(abi) = abi {
+        Ok(Some(encode_abi_input(&abi, args)?))
+    } else if frames.is_empty() {
+        input
+            .map(|input| decode_input(&input, input_encoding))
+            .transpose()
+    } else {
+        let frames = frames
+            .iter()
+            .map(|frame| parse_frame(frame))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Some(encode_frames(&frames)?))
+    }
+}
+
+fn encode_image_ids(image_ids: impl IntoIterator<Item = Digest>) -> String {
+    hex::encode(ethers::abi::encode(&[Token::Array(
+        image_ids
+            .into_iter()
+            .map(|image_id| Hash::from(<[u8; 32]>::from(image_id)).into_token())
+            .collect(),
+    )]))
+}
+
+/// Parse an amount of ether into wei.
+fn parse_ether(amount: &str) -> anyhow::Result<U256> {
+    ethers::utils::parse_ether(amount).with_context(|| format!("invalid ether amount: {amount}"))
+}
+
+fn parse_gwei(amount: &str) -> anyhow::Result<U256> {
+    Ok(ethers::utils::parse_units(amount, "gwei")
+        .with_context(|| format!("invalid gwei amount: {amount}"))?
+        .into())
+}
+
+/// Number of relay events buffered for `--emit-events` before the oldest ones
+/// are dropped.
+const EVENTS_CAPACITY: usize = 1024;
+
+/// Write the relay events as newline-delimited JSON to `events_file`, or to
+/// stderr if not set, and return the sender to publish them on.
+async fn spawn_event_writer(events_file: Option<PathBuf>) -> anyhow::Result<EventSender> {
+    let (sender, receiver) = event_channel(EVENTS_CAPACITY);
+    let out: Box<dyn tokio::io::AsyncWrite + Unpin + Send> = match events_file {
+        Some(path) => Box::new(
+            tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await
+                .with_context(|| format!("failed to open {}", path.display()))
+                .map_err(CliError::Input)?,
+        ),
+        None => Box::new(tokio::io::stderr()),
+    };
+    tokio::spawn(async move {
+        if let Err(err) = write_events(receiver, out).await {
+            eprintln!("failed to write relay events: {err:#}");
+        }
+    });
+    Ok(sender)
+}
+
+#[tokio::main]
+async fn main() {
+    let args = App::parse();
+    // Errors of JSON formatted output are also reported as JSON on stdout.
+    let json_errors = matches!(
+        args.command,
+        Command::Query {
+            format: OutputFormat::Json,
+            ..
+        } | Command::ImageId {
+            format: ImageIdFormat::Json,
+            ..
+        }
+    );
+    if let Err(err) = run(args).await {
+        if json_errors {
+            println!("{}", error_json(&err));
+        }
+        eprintln!("Error: {err:#}");
+        std::process::exit(exit_code(&err));
+    }
+}
+
+async fn run(args: App) -> anyhow::Result<()> {
+    let dev_mode = args.global_opts.risc0_dev_mode;
+    if args.global_opts.bonsai_insecure_tls {
+        eprintln!(
+            "WARNING: --bonsai-insecure-tls disables the TLS certificate checks of Bonsai and \
+             its proxy. Anyone on the network path can impersonate them, and read or tamper \
+             with the guests, inputs and proofs. Only use it in lab environments."
+        );
+    }
+    // Guests loaded at runtime, kept for the image ID collision check of the
+    // relay, as merging drops the entries of known image IDs.
+    let mut loaded_guests = Vec::new();
+    let guest_list: &'static [GuestListEntry<'static>] = match &args.global_opts.elf_dir {
+        Some(elf_dir) => {
+            let entries = load_elf_dir(elf_dir)?;
+            loaded_guests.extend(entries.iter().cloned());
+            merge_guest_lists(GUEST_LIST, entries).leak()
+        }
+        None => GUEST_LIST,
+    };
+    let guest_list: &'static [GuestListEntry<'static>] = match &args.global_opts.elf_list_file {
+        Some(elf_list_file) => {
+            let elf_list_file = elf_list_file.clone();
+            let ipfs = IpfsConfig {
+                gateway: args.global_opts.ipfs_gateway.clone(),
+                cache_dir: args
+                    .global_opts
+                    .elf_cache_dir
+                    .clone()
+                    .unwrap_or_else(IpfsConfig::default_cache_dir),
+            };
+            // IPFS downloads are blocking.
+            let entries =
+                tokio::task::spawn_blocking(move || load_elf_list(&elf_list_file, &ipfs)).await??;
+            loaded_guests.extend(entries.iter().cloned());
+            merge_guest_lists(guest_list, entries).leak()
+        }
+        None => guest_list,
+    };
+
+    match args.command {
+        Command::Query {
+            guest_binary,
+            input,
+            input_encoding,
+            frames,
+            abi,
+            args,
+            format,
+            show_guest_output,
+            seal_format,
+            seal_selector,
+            skip_proof_validation,
+            receipt_out,
+            receipt_kind,
+            prover,
+            poll_interval,
+            poll_timeout,
+            poll_retry_duration,
+            proof_cache_dir,
+            proof_cache_max_age,
+            session_metadata,
+            guest_env,
+            guest_env_secret,
+            max_cycles,
+            segment_po2,
+        } => {
+            let input = resolve_input(input, input_encoding, &frames, abi, &args)?;
+            let guest_env_secret = guest_env_secret
+                .iter()
+                .map(|pair| parse_guest_env_secret(pair))
+                .collect::<anyhow::Result<_>>()
+                .map_err(CliError::Usage)?;
+            let guest_env = GuestEnv::new(guest_env, guest_env_secret)?;
+
+            if receipt_out.is_some() && (dev_mode || input.is_none()) {
+                return Err(CliError::Usage(anyhow::anyhow!(
+                    "a receipt is only produced when proving an input on Bonsai or locally"
+                ))
+                .into());
+            }
+            // The STARK receipt is the only proof of a STARK query.
+            if receipt_kind == ReceiptKind::Stark
+                && !dev_mode
+                && input.is_some()
+                && receipt_out.is_none()
+            {
+                return Err(CliError::Usage(anyhow::anyhow!(
+                    "--receipt-kind stark requires --receipt-out to write the receipt to"
+                ))
+                .into());
+            }
+
+            let result = query(
+                guest_binary.parse()?,
+                input,
+                QueryOpts {
+                    guest_list,
+                    dev_mode,
+                    prover,
+                    show_guest_output,
+                    seal_format,
+                    seal_selector,
+                    skip_proof_validation,
+                    receipt_out,
+                    receipt_kind,
+                    poll: PollOpts {
+                        interval: poll_interval.into(),
+                        timeout: poll_timeout.map(Into::into),
+                        retry_duration: poll_retry_duration.into(),
+                    },
+                    bonsai_client: args.global_opts.bonsai_client(),
+                    session_metadata: session_metadata.into_iter().collect(),
+                    proof_cache: proof_cache_dir.map(|dir| ProofCache {
+                        dir,
+                        max_age: proof_cache_max_age.into(),
+                    }),
+                    limits: ExecutionLimits {
+                        max_cycles,
+                        segment_po2,
+                    },
+                    guest_env,
+                },
+            )
+            .await?;
+
+            let output = hex::encode(result.abi_encode());
+            match format {
+                OutputFormat::Hex => print!("{output}"),
+                OutputFormat::Json => print!(
+                    "{}",
+                    serde_json::json!({ "output": output, "stats": result.stats })
+                ),
+            }
+            std::io::stdout()
+                .flush()
+                .context("failed to flush stdout buffer")?;
+        }
+        Command::VerifyReceipt {
+            receipt,
+            image_id,
+            guest_binary,
+        } => {
+            let image_id = match (image_id, guest_binary) {
+                (Some(image_id), _) => Digest::from_hex(image_id.trim_start_matches("0x"))
+                    .with_context(|| format!("invalid image ID: {image_id}"))?,
+                (None, Some(guest_binary)) => resolve_guest_entry(guest_list, &guest_binary)?
+                    .image_id
+                    .into(),
+                (None, None) => {
+                    return Err(CliError::Usage(anyhow::anyhow!(
+                        "either an image ID or a guest binary is required"
+                    ))
+                    .into())
+                }
+            };
+            let receipt = load_receipt(
+                &std::fs::read(&receipt)
+                    .with_context(|| format!("failed to read receipt {}", receipt.display()))?,
+            )?;
+            let metadata = verify_receipt(&receipt, image_id)?;
+            println!("journal: {}", hex::encode(&receipt.journal));
+            println!("post-state digest: {}", metadata.post.digest());
+        }
+        Command::Diff {
+            session_a,
+            session_b,
+            abi,
+        } => {
+            let bonsai_api_url = args.global_opts.bonsai_api_url.clone();
+            let bonsai_api_key = args.global_opts.bonsai_api_key.clone();
+            let client_config = args.global_opts.bonsai_client().client_config()?;
+            let (receipt_a, receipt_b) = tokio::task::spawn_blocking({
+                let (session_a, session_b) = (session_a.clone(), session_b.clone());
+                move || {
+                    let client = Client::from_parts_with_config(
+                        bonsai_api_url,
+                        bonsai_api_key,
+                        &client_config,
+                    )
+                    .map_err(|err| CliError::BonsaiTransport(err.into()))?;
+                    Ok::<_, anyhow::Error>((
+                        fetch_session_receipt(&client, &session_a)?,
+                        fetch_session_receipt(&client, &session_b)?,
+                    ))
+                }
+            })
+            .await
+            .context("failed to run the download sub-task")??;
+
+            let post_a = receipt_a.get_metadata()?.post.digest();
+            let post_b = receipt_b.get_metadata()?.post.digest();
+            if post_a == post_b {
+                println!("post-state digests match: {post_a}");
+            } else {
+                println!("post-state digests differ:\n  a: {post_a}\n  b: {post_b}");
+            }
+
+            let (journal_a, journal_b) = (&receipt_a.journal, &receipt_b.journal);
+            if journal_a == journal_b {
+                println!("journals match");
+                return Ok(());
+            }
+            println!(
+                "journals differ ({} and {} bytes):",
+                journal_a.len(),
+                journal_b.len()
+            );
+            print!("{}", hex_diff(journal_a, journal_b));
+            if let Some(abi) = abi {
+                let decoded_a = decode_journal(&abi, journal_a)
+                    .with_context(|| format!("failed to decode the journal of {session_a}"))?;
+                let decoded_b = decode_journal(&abi, journal_b)
+                    .with_context(|| format!("failed to decode the journal of {session_b}"))?;
+                for (index, ((param, token_a), (_, token_b))) in
+                    decoded_a.iter().zip(decoded_b.iter()).enumerate()
+                {
+                    if token_a != token_b {
+                        println!("{index}: {param}\n  a: {token_a}\n  b: {token_b}");
+                    }
+                }
+            }
+            anyhow::bail!("the journals of sessions {session_a} and {session_b} differ");
+        }
+        Command::CancelSession { session_id } => {
+            let bonsai_api_url = args.global_opts.bonsai_api_url.clone();
+            let bonsai_api_key = args.global_opts.bonsai_api_key.clone();
+            let client_config = args.global_opts.bonsai_client().client_config()?;
+            let outcome = tokio::task::spawn_blocking({
+                let session_id = session_id.clone();
+                move || {
+                    let client = Client::from_parts_with_config(
+                        bonsai_api_url,
+                        bonsai_api_key,
+                        &client_config,
+                    )
+                    .map_err(|err| CliError::BonsaiTransport(err.into()))?;
+                    cancel_session(&client, &session_id)
+                }
+            })
+            .await
+            .context("failed to run the cancel sub-task")??;
+            match outcome {
+                CancelOutcome::Cancelled => println!("Cancelled session {session_id}"),
+                CancelOutcome::AlreadyComplete(status) => println!(
+                    "Session {session_id} already completed with status {status}, nothing to cancel"
+                ),
+            }
+        }
+        Command::DiffImageIds {
+            eth_node,
+            contract,
+            guest_binary,
+            getter,
+            storage_slot,
+            mapping_file,
+            format,
+        } => {
+            let sources = match (getter, storage_slot, mapping_file) {
+                (_, _, Some(mapping_file)) => load_image_id_sources(&mapping_file)?,
+                (getter, storage_slot, None) => {
+                    let source: ImageIdSource = getter
+                        .or(storage_slot)
+                        .context("missing --getter, --storage-slot or --mapping-file")
+                        .map_err(CliError::Usage)?
+                        .parse()
+                        .map_err(CliError::Input)?;
+                    vec![(guest_binary, source)]
+                }
+            };
+            let provider = Provider::<Ws>::connect(&eth_node)
+                .await
+                .context("failed to connect to the Ethereum node")
+                .map_err(CliError::EthereumConnection)?;
+            let diffs = diff_image_ids(&provider, contract, guest_list, &sources).await?;
+
+            match format {
+                ReportFormat::Text => {
+                    println!("{:<20} {:<64} {:<64} status", "guest", "local", "on-chain");
+                    for diff in &diffs {
+                        println!(
+                            "{:<20} {} {} {}",
+                            diff.name,
+                            diff.local,
+                            diff.on_chain,
+                            if diff.matches { "match" } else { "MISMATCH" }
+                        );
+                    }
+                }
+                ReportFormat::Json => println!("{}", serde_json::json!({ "guests": diffs })),
+            }
+            let mismatches = diffs.iter().filter(|diff| !diff.matches).count();
+            if mismatches > 0 {
+                anyhow::bail!(
+                    "{mismatches} image IDs differ from the ones of contract {contract:?}"
+                );
+            }
+        }
+        Command::Bench {
+            guest_binary,
+            input,
+            input_encoding,
+            frames,
+            abi,
+            args: abi_args,
+            iterations,
+            warmup,
+            concurrency,
+            receipt_kind,
+            poll_interval,
+            poll_timeout,
+            poll_retry_duration,
+            session_metadata,
+            max_cycles,
+            segment_po2,
+            format,
+        } => {
+            let guest_entry = resolve_guest_entry(guest_list, &guest_binary)
+                .context("failed to resolve guest entry")?;
+            let input =
+                resolve_input(input, input_encoding, &frames, abi, &abi_args)?.unwrap_or_default();
+            let poll = PollOpts {
+                interval: poll_interval.into(),
+                timeout: poll_timeout.map(Into::into),
+                retry_duration: poll_retry_duration.into(),
+            };
+            let session_metadata: HashMap<_, _> = session_metadata.into_iter().collect();
+            let limits = ExecutionLimits {
+                max_cycles,
+                segment_po2,
+            };
+
+            // Each run creates a new proving session.
+            let bench_run = |run: u32| {
+                let input = input.clone();
+                let guest_entry = guest_entry.clone();
+                let poll = poll.clone();
+                let client_opts = args.global_opts.bonsai_client();
+                let session_metadata = session_metadata.clone();
+                async move {
+                    let start = std::time::Instant::now();
+                    let output = resolve_image_output(
+                        input,
+                        &guest_entry,
+                        dev_mode,
+                        ProverKind::Bonsai,
+                        false,
+                        receipt_kind,
+                        poll,
+                        client_opts,
+                        session_metadata,
+                        limits,
+                        GuestEnv::default(),
+                    )
+                    .await;
+                    let run_result = output.map(|output| BenchmarkRun {
+                        total: start.elapsed(),
+                        stats: output.stats().clone(),
+                    });
+                    (run, run_result)
+                }
+            };
+
+            for run in 0..warmup {
+                match bench_run(run).await {
+                    (_, Ok(result)) => {
+                        eprintln!("warmup run {run}: {} ms", result.total.as_millis())
+                    }
+                    (_, Err(err)) => eprintln!("warmup run {run} failed: {err:#}"),
+                }
+            }
+
+            // Failed runs are reported and left out of the statistics, rather
+            // than aborting the benchmark.
+            let mut runs = Vec::with_capacity(iterations as usize);
+            let mut pending = tokio::task::JoinSet::new();
+            let mut next_run = 0;
+            while next_run < iterations || !pending.is_empty() {
+                while next_run < iterations && pending.len() < concurrency as usize {
+                    pending.spawn(bench_run(next_run));
+                    next_run += 1;
+                }
+                match pending.join_next().await {
+                    Some(Ok((run, Ok(result)))) => {
+                        eprintln!("run {run}: {} ms", result.total.as_millis());
+                        runs.push(result);
+                    }
+                    Some(Ok((run, Err(err)))) => eprintln!("run {run} failed: {err:#}"),
+                    Some(Err(err)) => eprintln!("run failed: {err}"),
+                    None => break,
+                }
+            }
+
+            if runs.is_empty() {
+                anyhow::bail!("all {iterations} benchmark runs of {guest_binary} failed");
+            }
+            let report = BenchmarkReport::new(&guest_binary, iterations as usize, &runs)?;
+            match format {
+                ReportFormat::Text => print!("{report}"),
+                ReportFormat::Json => println!("{}", serde_json::to_string(&report)?),
+            }
+        }
+        Command::RotateKey {
+            eth_node,
+            eth_chain_id,
+            old_key,
+            new_key,
+            safe_address,
+            keystore,
+            yes,
+        } => {
+            rotate_key(
+                &eth_node,
+                eth_chain_id,
+                &old_key,
+                &new_key,
+                safe_address,
+                keystore,
+                yes,
+            )
+            .await?
+        }
+        Command::EncodeInput { abi, args } => {
+            print!("{}", hex::encode(encode_abi_input(&abi, &args)?));
+            std::io::stdout()
+                .flush()
+                .context("failed to flush stdout buffer")?;
+        }
+        Command::DecodeJournal {
+            guest_binary,
+            abi,
+            journal_abi_file,
+            journal,
+            query_output,
+        } => {
+            let signature = match (abi, guest_binary, journal_abi_file) {
+                (Some(abi), _, _) => abi,
+                (None, Some(guest_binary), Some(journal_abi_file)) => {
+                    load_journal_abi(&journal_abi_file, &guest_binary)?
+                }
+                _ => {
+                    return Err(CliError::Usage(anyhow::anyhow!(
+                        "either a guest binary or an ABI signature is required"
+                    ))
+                    .into())
+                }
+            };
+            let journal = match (journal, query_output) {
+                (Some(journal), _) => parse_frame(&journal)?,
+                (None, Some(path)) => {
+                    let query_output = if path.as_os_str() == "-" {
+                        std::io::read_to_string(std::io::stdin())
+                            .context("failed to read query output from stdin")?
+                    } else {
+                        std::fs::read_to_string(&path).with_context(|| {
+                            format!("failed to read query output {}", path.display())
+                        })?
+                    };
+                    journal_from_query_output(&query_output)?
+                }
+                (None, None) => {
+                    return Err(CliError::Usage(anyhow::anyhow!(
+                        "either a journal or a query output is required"
+                    ))
+                    .into())
+                }
+            };
+
+            for (index, (param, token)) in decode_journal(&signature, &journal)?
+                .into_iter()
+                .enumerate()
+            {
+                println!("{index}: {param} = {token}");
+            }
+        }
+        Command::Upload {
+            guest_binary,
+            checksum,
+            checksum_file,
+            upload_timeout,
+        } => {
+            let checksums = match (&guest_binary, checksum, checksum_file) {
+                (Some(guest_binary), Some(checksum), _) => {
+                    Some(HashMap::from([(guest_binary.to_uppercase(), checksum)]))
+                }
+                (_, _, Some(checksum_file)) => Some(load_checksums(&checksum_file)?),
+                _ => None,
+            };
+            let results = upload(
+                guest_list,
+                guest_binary
+                    .map(|guest_binary| guest_binary.parse())
+                    .transpose()?,
+                checksums,
+                &args.global_opts.bonsai_api_url,
+                &args.global_opts.bonsai_api_key,
+                upload_timeout.into(),
+                &args.global_opts.bonsai_client(),
+            )
+            .await?;
+
+            let output = encode_image_ids(results.into_iter().map(|result| result.image_id));
+            print!("{output}");
+            std::io::stdout()
+                .flush()
+                .context("failed to flush stdout buffer")?;
+        }
+        Command::ImageId {
+            guest_binary,
+            elf,
+            format,
+        } => {
+            let results = match elf {
+                Some(elf) => vec![elf_image_id(&elf)?],
+                None => image_ids(
+                    guest_list,
+                    guest_binary
+                        .map(|guest_binary| guest_binary.parse())
+                        .transpose()?,
+                )?,
+            };
+
+            let output = encode_image_ids(results.iter().map(|result| result.image_id));
+            match format {
+                ImageIdFormat::Hex => print!("{output}"),
+                ImageIdFormat::Json => print!(
+                    "{}",
+                    serde_json::json!({
+                        "output": output,
+                        "guests": results
+                            .iter()
+                            .map(|result| serde_json::json!({
+                                "name": result.name,
+                                "image_id": hex::encode(result.image_id),
+                            }))
+                            .collect::<Vec<_>>(),
+                    })
+                ),
+            }
+            std::io::stdout()
+                .flush()
+                .context("failed to flush stdout buffer")?;
+        }
+        Command::Run(run_args) => {
+            assert_unique_image_ids(GUEST_LIST.iter().chain(&loaded_guests))?;
+            if run_args.upload_only {
+                let results = upload(
+                    guest_list,
+                    None,
+                    None,
+                    &args.global_opts.bonsai_api_url,
+                    &args.global_opts.bonsai_api_key,
+                    run_args.upload_timeout.into(),
+                    &args.global_opts.bonsai_client(),
+                )
+                .await?;
+
+                let output = encode_image_ids(results.into_iter().map(|result| result.image_id));
+                print!("{output}");
+                std::io::stdout()
+                    .flush()
+                    .context("failed to flush stdout buffer")?;
+                return Ok(());
+            }
+            let events = if run_args.emit_events {
+                Some(spawn_event_writer(run_args.events_file.clone()).await?)
+            } else {
+                None
+            };
+            let (config, client_config) =
+                relayer_config(run_args, &args.global_opts, guest_list, dev_mode, events)?;
+
+            // Shut down the relay on SIGINT or SIGTERM.
+            let shutdown = CancellationToken::new();
+            tokio::spawn({
+                let shutdown = shutdown.clone();
+                async move {
+                    let _ = shutdown_signal().await;
+                    shutdown.cancel();
+                }
+            });
+            let relayer = run_relayer(config, client_config, shutdown).await?;
+
+            // Wait for the relay to exit.
+            let _ = relayer.join().await;
+        }
+        Command::ValidateConfig(run_args) => {
+            let report =
+                match relayer_config(run_args, &args.global_opts, guest_list, dev_mode, None) {
+                    Ok((config, client_config)) => validate_config(&config, &client_config).await,
+                    Err(err) => {
+                        ConfigReport::from(ConfigCheck::fail("Options", format!("{err:#}")))
+                    }
+                };
+            print!("{report}");
+            std::io::stdout()
+                .flush()
+                .context("failed to flush stdout buffer")?;
+            let failed = report.failed();
+            if failed > 0 {
+                anyhow::bail!(
+                    "{failed} of {} configuration checks failed",
+                    report.checks.len()
+                );
+            }
+        }
+        Command::Selftest {
+            guest_binary,
+            input,
+            eth_node,
+            eth_chain_id,
+            private_key,
+            timeout,
+        } => {
+            let result = selftest(
+                guest_list,
+                &guest_binary,
+                input,
+                eth_node.map(|eth_node| (eth_node, eth_chain_id)),
+                private_key,
+                timeout.into(),
+                &args.global_opts.bonsai_api_url,
+            )
+            .await;
+            // Exit only once selftest has torn down Anvil and the relay.
+            match result {
+                Ok(()) => eprintln!("selftest passed"),
+                Err(err) => {
+                    eprintln!("selftest failed: {err:#}");
+                    std::process::exit(exit_code(&err));
+                }
+            }
+        }
+        Command::RequestCallback {
+            relay_address,
+            callback_contract,
+            function_selector,
+            gas_limit,
+            guest_binary,
+            input,
+            input_encoding,
+            frames,
+            abi,
+            args,
+            eth_node,
+            eth_chain_id,
+            private_key,
+            keystore,
+            wait,
+            timeout,
+        } => {
+            let image_id = match guest_binary {
+                GuestSelector::ImageId(image_id) => image_id,
+                guest @ GuestSelector::Name(_) => guest.resolve(guest_list)?.image_id.into(),
+            };
+            let request = CallbackRequest {
+                image_id,
+                input: resolve_input(input, input_encoding, &frames, abi, &args)?
+                    .unwrap_or_default(),
+                callback_contract,
+                function_selector,
+                gas_limit,
+            };
+            let private_key = match keystore {
+                Some(keystore) => read_key_file(&keystore)?,
+                None => private_key,
+            };
+            let wallet = private_key
+                .trim_start_matches("0x")
+                .parse::<LocalWallet>()
+                .context("invalid private key")?
+                .with_chain_id(eth_chain_id);
+            let provider = Provider::<Ws>::connect(&eth_node)
+                .await
+                .context("failed to connect to the Ethereum node")
+                .map_err(CliError::EthereumConnection)?;
+            let client = std::sync::Arc::new(SignerMiddleware::new(provider, wallet));
+
+            let (id, block_number) =
+                request_callback(client.clone(), relay_address, &request).await?;
+            println!("tx_hash: {:?}", id.tx_hash);
+            println!("request_id: {id}");
+            if wait {
+                let callback_tx = tokio::time::timeout(
+                    timeout.into(),
+                    wait_for_callback(
+                        client.as_ref(),
+                        relay_address,
+                        &request,
+                        block_number,
+                        std::time::Duration::from_secs(1),
+                    ),
+                )
+                .await
+                .with_context(|| format!("no callback landed within {timeout}"))??;
+                println!("callback_tx_hash: {callback_tx:?}");
+            }
+        }
+        Command::Resubmit {
+            event_db,
+            event_id,
+            eth_node,
+            eth_chain_id,
+            private_key,
+            keystore,
+        } => {
+            let client_config = EthersClientConfig::new(
+                eth_node,
+                eth_chain_id,
+                match keystore {
+                    Some(keystore) => read_key_file(&keystore)?,
+                    None => private_key,
+                }
+                .try_into()?,
+                CONNECTION_RETRY_ATTEMPTS,
+                CONNECTION_RETRY_INTERVAL,
+                DEFAULT_ETH_NODE_TIMEOUT,
+            );
+            let tx_hash =
+                bonsai_ethereum_relay::resubmit_event(&client_config, &event_db, event_id)
+                    .await
+                    .map_err(|err| CliError::Contract(err.into()))?;
+            println!("tx_hash: {tx_hash:?}");
+        }
+    }
+    Ok(())
+}
+
+/// Run the relay loop end to end in dev mode against `eth_node`, or against a
+/// spawned Anvil instance, and check that the journal of the guest reaches the
+/// callback contract. Everything started here is torn down on return.
+/// Relayer and Ethereum client configs of the `run` options, the relayer
+/// emitting its events to `events`, if set.
+fn relayer_config(
+    run_args: RunArgs,
+    global_opts: &GlobalOpts,
+    guest_list: &'static [GuestListEntry<'static>],
+    dev_mode: bool,
+    events: Option<EventSender>,
+) -> anyhow::Result<(RelayerConfig, EthersClientConfig)> {
+    let RunArgs {
+        relay_address,
+        eth_node,
+        eth_chain_id,
+        private_key,
+        keystore,
+        kms_region,
+        kms_profile,
+        kms_endpoint,
+        signer_address,
+        signer_tls_ca_cert,
+        signer_tls_client_cert,
+        ledger_signing_timeout,
+        ledger_unlock_timeout,
+        safe_address,
+        safe_signer_keys,
+        connection_retry_attempts,
+        connection_retry_interval,
+        eth_node_timeout,
+        json_rpc_batch,
+        poll_interval,
+        ws_heartbeat_interval,
+        ws_stale_timeout,
+        eth_node_ws_ping_interval,
+        eth_node_ws_pong_timeout,
+        eth_node_pool_size,
+        shutdown_timeout,
+        rest_api_bind,
+        rest_api_port,
+        rest_api_tls_cert,
+        rest_api_tls_key,
+        relay_event_filter_topics,
+        skip_proof_validation,
+        min_eth_balance,
+        fee_cap,
+        fee_queue_depth,
+        retry_on_revert,
+        stuck_tx_timeout,
+        tx_resubmit_bump,
+        reorg_depth,
+        max_concurrent_relays,
+        relay_queue_depth,
+        max_daily_spend,
+        callback_gas_headroom,
+        callback_gas_limit_min,
+        callback_gas_limit_max,
+        respect_requested_gas_limit,
+        tx_endpoint,
+        private_relay_url,
+        private_relay_fallback_blocks,
+        watch_gas_price,
+        journal_filter,
+        submission_only,
+        replay_events,
+        start_block,
+        end_block,
+        alert_webhook,
+        alert_cmd,
+        alert_cmd_cooldown,
+        alert_bonsai_unreachable_after,
+        alert_dead_letter_threshold,
+        chain_config,
+        config_file,
+        event_persistence_db,
+        prune_events_older_than,
+        request_max_age,
+        cancel_expired_sessions,
+        max_pending_requests,
+        max_dead_letter,
+        rest_api_requests_per_minute,
+        rest_api_burst,
+        max_request_body_size,
+        rest_api_docs,
+        admin_api_key,
+        upload_timeout,
+        no_upload,
+        bonsai_api_urls,
+        grpc,
+        ..
+    } = run_args;
+    let safe = match safe_address {
+        Some(address) => Some(SafeConfig {
+            address,
+            signer_keys: safe_signer_keys
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, RelayError>>()?,
+        }),
+        None => None,
+    };
+    let mut private_keys = private_key.into_iter();
+    let wallet_key = match keystore {
+        Some(keystore) => read_key_file(&keystore)?,
+        None => private_keys
+            .next()
+            .context("at least one private key is required")?,
+    };
+    let client_config = EthersClientConfig::new(
+        eth_node,
+        eth_chain_id,
+        wallet_key.try_into()?,
+        connection_retry_attempts,
+        connection_retry_interval.into(),
+        eth_node_timeout.into(),
+    )
+    .with_json_rpc_batch(json_rpc_batch)
+    .with_additional_wallet_keys(
+        private_keys
+            .map(TryInto::try_into)
+            .collect::<Result<_, RelayError>>()?,
+    )
+    .with_kms(KmsConfig {
+        region: kms_region,
+        profile: kms_profile,
+        endpoint: kms_endpoint,
+    })
+    .with_remote_signer(RemoteSignerConfig {
+        signer_address,
+        tls_ca_cert: signer_tls_ca_cert,
+        tls_client_cert: signer_tls_client_cert,
+    })
+    .with_ledger(LedgerConfig {
+        signing_timeout: ledger_signing_timeout.into(),
+        unlock_timeout: ledger_unlock_timeout.into(),
+    })
+    .with_poll_interval(poll_interval.into())
+    .with_ws_heartbeat(ws_heartbeat_interval.into(), ws_stale_timeout.into())
+    .with_ws_ping(
+        eth_node_ws_ping_interval.into(),
+        eth_node_ws_pong_timeout.into(),
+    )
+    .with_eth_node_pool_size(eth_node_pool_size);
+    let chains = match chain_config {
+        Some(path) => read_chain_configs(&path, &client_config)?,
+        None => Vec::new(),
+    };
+    let config = RelayerConfig {
+        guest_list,
+        rest_api: true,
+        rest_api_bind,
+        rest_api_port,
+        rest_api_tls: rest_api_tls_cert
+            .zip(rest_api_tls_key)
+            .map(|(cert, key)| RestApiTls { cert, key }),
+        dev_mode,
+        bonsai_api_url: global_opts.bonsai_api_url.clone(),
+        bonsai_api_urls,
+        grpc,
+        bonsai_api_key: global_opts.bonsai_api_key.clone(),
+        relay_addresses: relay_address,
+        shutdown_timeout: shutdown_timeout.into(),
+        safe,
+        event_filter: relay_event_filter_topics
+            .iter()
+            .map(|guest_binary| guest_binary.parse())
+            .collect::<Result<_, _>>()?,
+        skip_proof_validation,
+        min_eth_balance,
+        fee_cap: fee_cap.map(|max_fee_per_gas| FeeCap {
+            max_fee_per_gas,
+            queue_depth: fee_queue_depth,
+        }),
+        retry_on_revert,
+        stuck_tx_timeout: stuck_tx_timeout.into(),
+        tx_resubmit_bump,
+        reorg_depth,
+        max_concurrent_relays,
+        relay_queue_depth,
+        max_daily_spend,
+        callback_gas_limits: (callback_gas_headroom.is_some()
+            || callback_gas_limit_min.is_some()
+            || callback_gas_limit_max.is_some()
+            || respect_requested_gas_limit)
+            .then(|| CallbackGasLimits {
+                headroom_percent: callback_gas_headroom.unwrap_or(0),
+                min: callback_gas_limit_min,
+                max: callback_gas_limit_max,
+                respect_requested: respect_requested_gas_limit,
+            }),
+        private_relay: match tx_endpoint {
+            TxEndpoint::Public => None,
+            TxEndpoint::Private => Some(PrivateRelay {
+                url: private_relay_url
+                    .context("--private-relay-url is required with --tx-endpoint private")?,
+                fallback_blocks: private_relay_fallback_blocks,
+            }),
+        },
+        watch_gas_price: watch_gas_price.map(Into::into),
+        journal_filters: journal_filter
+            .iter()
+            .map(|filter| filter.to_vec())
+            .collect(),
+        events,
+        submission_only,
+        replay_events,
+        start_block,
+        end_block,
+        alert_webhook,
+        alert_cmd: alert_cmd.map(|program| {
+            AlertCommand::new(program)
+                .with_cooldown(alert_cmd_cooldown.into())
+                .with_bonsai_unreachable_after(alert_bonsai_unreachable_after.into())
+                .with_dead_letter_threshold(alert_dead_letter_threshold)
+        }),
+        admin_api_key,
+        chains,
+        config_file,
+        event_persistence_db,
+        prune_events_older_than: prune_events_older_than.map(Into::into),
+        request_max_age: request_max_age.map(Into::into),
+        cancel_expired_sessions,
+        max_pending_requests,
+        max_dead_letters: max_dead_letter,
+        rest_api_rate_limit: rest_api_requests_per_minute.map(|requests_per_minute| RateLimit {
+            requests_per_minute,
+            burst: rest_api_burst,
+        }),
+        max_request_body_size,
+        rest_api_docs,
+        upload_timeout: upload_timeout.into(),
+        upload_images: !no_upload,
+        bonsai_client: global_opts.bonsai_client(),
+    };
+    Ok((config, client_config))
+}
+
+async fn selftest(
+    guest_list: &'static [GuestListEntry<'static>],
+    guest_binary: &str,
+    input: Option<String>,
+    eth_node: Option<(String, u64)>,
+    private_key: String,
+    timeout: std::time::Duration,
+    bonsai_api_url: &str,
+) -> anyhow::Result<()> {
+    let guest_entry = resolve_guest_entry(guest_list, guest_binary)?;
+    let input = match input {
+        Some(input) => decode_input(&input, InputEncoding::Hex)?,
+        None => encode_frames(&[b"self".to_vec(), b"test".to_vec(), b"ok".to_vec()])?,
+    };
+    let Output::Execution { journal, .. } = resolve_image_output(
+        input.clone(),
+        &guest_entry,
+        true,
+        ProverKind::Bonsai,
+        false,
+        ReceiptKind::Snark,
+        PollOpts::default(),
+        BonsaiClientOpts::default(),
+        HashMap::new(),
+        ExecutionLimits::default(),
+        GuestEnv::default(),
+    )
+    .await?
+    else {
+        anyhow::bail!("expected a local execution output");
+    };
+    eprintln!(
+        "Executed {guest_binary} locally: {} byte journal",
+        journal.len()
+    );
+
+    // Kept alive until return, when dropping it stops Anvil.
+    let anvil;
+    let (eth_node, eth_chain_id) = match eth_node {
+        Some(eth_node) => eth_node,
+        None => {
+            std::process::Command::new("anvil")
+                .arg("--version")
+                .output()
+                .context("failed to find `anvil`; install Foundry or pass --eth-node")?;
+            anvil = Anvil::new().spawn();
+            eprintln!("Spawned Anvil at {}", anvil.ws_endpoint());
+            (anvil.ws_endpoint(), anvil.chain_id())
+        }
+    };
+    let wallet = private_key
+        .trim_start_matches("0x")
+        .parse::<LocalWallet>()
+        .context("invalid private key")?
+        .with_chain_id(eth_chain_id);
+    let provider = Provider::<Ws>::connect(&eth_node)
+        .await
+        .context("failed to connect to the Ethereum node")
+        .map_err(CliError::EthereumConnection)?;
+    let client = std::sync::Arc::new(SignerMiddleware::new(provider, wallet));
+
+    let relay = BonsaiTestRelay::deploy(client.clone(), U256::from(eth_chain_id))?
+        .send()
+        .await
+        .context("failed to deploy BonsaiTestRelay")
+        .map_err(CliError::Contract)?;
+    let receipt = async {
+        client
+            .send_transaction(
+                TransactionRequest::new().data(JOURNAL_SINK_BYTECODE.parse::<Bytes>()?),
+                None,
+            )
+            .await?
+            .await?
+            .context("callback contract deployment was dropped")
+    }
+    .await
+    .map_err(CliError::Contract)?;
+    let sink = receipt
+        .contract_address
+        .context("callback contract deployment created no contract")?;
+    eprintln!(
+        "Deployed BonsaiTestRelay at {:?} and the callback contract at {sink:?}",
+        relay.address()
+    );
+
+    let config = RelayerConfig {
+        guest_list,
+        rest_api: false,
+        rest_api_bind: REST_API_BIND.to_string(),
+        rest_api_port: REST_API_PORT.to_string(),
+        rest_api_tls: None,
+        dev_mode: true,
+        bonsai_api_url: bonsai_api_url.to_string(),
+        bonsai_api_urls: Vec::new(),
+        grpc: false,
+        bonsai_api_key: String::new(),
+        relay_addresses: vec![relay.address()],
+        shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+        safe: None,
+        event_filter: vec![GuestSelector::ImageId(guest_entry.image_id.into())],
+        skip_proof_validation: false,
+        min_eth_balance: U256::zero(),
+        fee_cap: None,
+        retry_on_revert: 0,
+        stuck_tx_timeout: DEFAULT_STUCK_TX_TIMEOUT,
+        tx_resubmit_bump: DEFAULT_TX_RESUBMIT_BUMP,
+        reorg_depth: 0,
+        max_concurrent_relays: DEFAULT_MAX_CONCURRENT_RELAYS,
+        relay_queue_depth: DEFAULT_RELAY_QUEUE_DEPTH,
+        max_daily_spend: None,
+        callback_gas_limits: None,
+        private_relay: None,
+        watch_gas_price: None,
+        journal_filters: Vec::new(),
+        events: None,
+        submission_only: false,
+        replay_events: None,
+        start_block: None,
+        end_block: None,
+        alert_webhook: None,
+        alert_cmd: None,
+        admin_api_key: None,
+        chains: Vec::new(),
+        config_file: None,
+        event_persistence_db: None,
+        prune_events_older_than: None,
+        request_max_age: None,
+        cancel_expired_sessions: false,
+        max_pending_requests: None,
+        max_dead_letters: DEFAULT_MAX_DEAD_LETTERS,
+        rest_api_rate_limit: None,
+        max_request_body_size: DEFAULT_MAX_REQUEST_BODY_SIZE,
+        rest_api_docs: false,
+        upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+        upload_images: true,
+        bonsai_client: BonsaiClientOpts::default(),
+    };
+    let client_config = EthersClientConfig::new(
+        eth_node,
+        eth_chain_id,
+        private_key.try_into()?,
+        CONNECTION_RETRY_ATTEMPTS,
+        CONNECTION_RETRY_INTERVAL,
+        DEFAULT_ETH_NODE_TIMEOUT,
+    );
+    let relayer = run_relayer(config, client_config, CancellationToken::new()).await?;
+    eprintln!("Started the relay in dev mode");
+
+    let image_id = Hash::from(bytemuck::cast::<_, [u8; 32]>(guest_entry.image_id));
+    let result: anyhow::Result<()> = async {
+        let selector = ethers::utils::id("selftest(bytes)");
+        async {
+            relay
+                .request_callback(
+                    image_id.into(),
+                    input.into(),
+                    sink,
+                    selector,
+                    SELFTEST_GAS_LIMIT,
+                )
+                .send()
+                .await?
+                .await?
+                .context("callback request transaction was dropped")
+        }
+        .await
+        .map_err(CliError::Contract)?;
+        eprintln!("Requested a callback for {guest_binary}");
+
+        // The relay calls back with the selector, the journal and the image ID.
+        let expected = [selector.as_slice(), &journal, image_id.as_bytes()].concat();
+        let filter = Filter::new().address(sink);
+        let payload = tokio::time::timeout(timeout, async {
+            loop {
+                let logs = client.get_logs(&filter).await?;
+                if let Some(log) = logs.first() {
+                    return Ok::<_, anyhow::Error>(log.data.to_vec());
+                }
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            }
+        })
+        .await
+        .with_context(|| {
+            format!(
+                "no callback landed within {}",
+                humantime::format_duration(timeout)
+            )
+        })??;
+        if payload != expected {
+            anyhow::bail!(
+                "callback payload mismatch: expected 0x{}, got 0x{}",
+                hex::encode(&expected),
+                hex::encode(&payload)
+            );
+        }
+        eprintln!("Callback landed with the expected journal");
+        Ok(())
+    }
+    .await;
+
+    relayer.shutdown().await?;
+    result
+}
+
+/// Ask the user for confirmation on stdin.
+fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    eprint!("{prompt} [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Transfer the ETH held by the old key to the new key's address, verify the
+/// transfer with `eth_getBalance`, and replace the old key in the keystore.
+async fn rotate_key(
+    eth_node: &str,
+    eth_chain_id: u64,
+    old_key: &str,
+    new_key: &str,
+    safe_address: Option<Address>,
+    keystore: Option<PathBuf>,
+    yes: bool,
+) -> anyhow::Result<()> {
+    let old_wallet = old_key
+        .trim_start_matches("0x")
+        .parse::<LocalWallet>()
+        .context("invalid old key")?
+        .with_chain_id(eth_chain_id);
+    let new_wallet = new_key
+        .trim_start_matches("0x")
+        .parse::<LocalWallet>()
+        .context("invalid new key")?;
+    let (old_address, new_address) = (old_wallet.address(), new_wallet.address());
+
+    let provider = Provider::<Ws>::connect(eth_node)
+        .await
+        .context("failed to connect to the Ethereum node")
+        .map_err(CliError::EthereumConnection)?;
+    let client = std::sync::Arc::new(SignerMiddleware::new(provider, old_wallet));
+
+    // The relay contract accepts callbacks from any sender, so the only
+    // permission to check is the Safe ownership when relaying through a Safe.
+    if let Some(safe_address) = safe_address {
+        let safe = GnosisSafe::new(safe_address, client.clone());
+        for (name, address) in [("old", old_address), ("new", new_address)] {
+            let is_owner = safe
+                .is_owner(address)
+                .call()
+                .await
+                .with_context(|| format!("failed to check the owners of Safe {safe_address:?}"))
+                .map_err(CliError::Contract)?;
+            if !is_owner {
+                anyhow::bail!("{name} key {address:?} is not an owner of Safe {safe_address:?}");
+            }
+        }
+    }
+
+    let (balance, gas_price) = async {
+        Ok::<_, anyhow::Error>((
+            client.get_balance(old_address, None).await?,
+            client.get_gas_price().await?,
+        ))
+    }
+    .await
+    .map_err(CliError::EthereumConnection)?;
+    match transferable_balance(balance, gas_price) {
+        Some(amount) => {
+            if !yes
+                && !confirm(&format!(
+                    "Transfer {amount} wei from {old_address:?} to {new_address:?}?"
+                ))?
+            {
+                anyhow::bail!("key rotation aborted");
+            }
+
+            let new_balance = client.get_balance(new_address, None).await?;
+            let tx = TransactionRequest::pay(new_address, amount)
+                .gas(TRANSFER_GAS)
+                .gas_price(gas_price);
+            let receipt = async {
+                client
+                    .send_transaction(tx, None)
+                    .await?
+                    .await?
+                    .context("transfer transaction was dropped")
+            }
+            .await
+            .map_err(CliError::Contract)?;
+            if receipt.status != Some(1.into()) {
+                return Err(CliError::Contract(anyhow::anyhow!(
+                    "transfer transaction {:?} failed",
+                    receipt.transaction_hash
+                ))
+                .into());
+            }
+
+            let expected = new_balance + amount;
+            let actual = client.get_balance(new_address, None).await?;
+            if actual < expected {
+                anyhow::bail!(
+                    "transfer not reflected in the balance of {new_address:?}: expected at least {expected} wei, got {actual} wei"
+                );
+            }
+            eprintln!("Transferred {amount} wei to {new_address:?}");
+        }
+        None => eprintln!("No ETH to transfer from {old_address:?}"),
+    }
+
+    if let Some(keystore) = keystore {
+        write_key_file(&keystore, new_key)?;
+        eprintln!("Replaced the old key in {}", keystore.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use bonsai_ethereum_relay::WalletKeyIdentifier;
+    use bonsai_ethereum_relay_cli::{encode_groth16_seal, tokenize_snark_proof};
+    use bonsai_sdk::alpha::responses::SnarkProof;
+    use clap::{CommandFactory, Parser};
+    use ethers::{
+        abi::{ParamType, Token},
+        types::{Address, Bytes},
+    };
+    use risc0_zkvm::sha::Digest;
+
+    use super::{
+        encode_image_ids, parse_guest_env_secret, parse_seal_selector, App, Command, GuestSelector,
+        ProverKind, ReceiptKind, ReportFormat, RunArgs, TxEndpoint, DEFAULT_LEDGER_UNLOCK_TIMEOUT,
+        DEFAULT_STUCK_TX_TIMEOUT,
+    };
+
+    #[test]
+    fn verify_app() {
+        App::command().debug_assert();
+    }
+
+    #[test]
+    fn query_frames_conflict_with_input() {
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "--frame",
+            "0x01",
+            "--frame",
+            "",
+            "--frame",
+            "@input.bin",
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "0x01",
+            "--frame",
+            "0x01"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn query_input_encoding() {
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "aGVsbG8=",
+            "--input-encoding",
+            "base64",
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "--input-encoding",
+            "utf8",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn query_receipt_kind() -> anyhow::Result<()> {
+        let app = App::try_parse_from(["relay", "query", "finalize_votes", "00"])?;
+        let Command::Query { receipt_kind, .. } = app.command else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(receipt_kind, ReceiptKind::Snark);
+
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--receipt-kind",
+            "stark",
+            "--receipt-out",
+            "receipt.json",
+        ])?;
+        let Command::Query { receipt_kind, .. } = app.command else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(receipt_kind, ReceiptKind::Stark);
+        Ok(())
+    }
+
+    #[test]
+    fn query_prover() -> anyhow::Result<()> {
+        let app = App::try_parse_from(["relay", "query", "finalize_votes", "00"])?;
+        let Command::Query { prover, .. } = app.command else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(prover, ProverKind::Bonsai);
+
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--prover",
+            "local",
+            "--receipt-kind",
+            "stark",
+            "--receipt-out",
+            "receipt.json",
+        ])?;
+        let Command::Query { prover, .. } = app.command else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(prover, ProverKind::Local);
+        Ok(())
+    }
+
+    #[test]
+    fn query_guest_env() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--guest-env",
+            "NETWORK=sepolia",
+            "--guest-env",
+            "EMPTY=",
+            "--guest-env-secret",
+            "API_TOKEN=a=b",
+        ])?;
+        let Command::Query {
+            guest_env,
+            guest_env_secret,
+            ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the query command");
+        };
+        let pair = |key: &str, value: &str| (key.to_string(), value.to_string());
+        assert_eq!(
+            guest_env,
+            vec![pair("NETWORK", "sepolia"), pair("EMPTY", "")]
+        );
+        assert_eq!(guest_env_secret, vec!["API_TOKEN=a=b"]);
+        assert_eq!(
+            parse_guest_env_secret(&guest_env_secret[0])?,
+            pair("API_TOKEN", "a=b")
+        );
+
+        for pair in ["NETWORK", "=sepolia", "NET\0WORK=sepolia"] {
+            let result = App::try_parse_from([
+                "relay",
+                "query",
+                "finalize_votes",
+                "00",
+                "--guest-env",
+                pair,
+            ]);
+            assert!(result.is_err(), "{pair:?}");
+            assert!(parse_guest_env_secret(pair).is_err(), "{pair:?}");
+        }
+        // The value of a secret is not echoed by the parse errors.
+        let Err(err) = parse_guest_env_secret("hunter2") else {
+            anyhow::bail!("expected a secret without a key to be rejected");
+        };
+        assert!(!err.to_string().contains("hunter2"));
+        Ok(())
+    }
+
+    #[test]
+    fn query_poll_options() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--poll-interval",
+            "500ms",
+            "--poll-timeout",
+            "10m",
+        ])?;
+        let Command::Query {
+            poll_interval,
+            poll_timeout,
+            poll_retry_duration,
+            ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(*poll_interval, std::time::Duration::from_millis(500));
+        assert_eq!(
+            poll_timeout.map(Into::into),
+            Some(std::time::Duration::from_secs(600))
+        );
+        assert_eq!(*poll_retry_duration, std::time::Duration::from_secs(60));
+        Ok(())
+    }
+
+    #[test]
+    fn query_execution_limits() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "spin",
+            "00",
+            "--max-cycles",
+            "1000000",
+            "--segment-po2",
+            "20",
+        ])?;
+        let Command::Query {
+            max_cycles,
+            segment_po2,
+            ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(max_cycles, Some(1_000_000));
+        assert_eq!(segment_po2, Some(20));
+        for segment_po2 in ["12", "25"] {
+            assert!(
+                App::try_parse_from(["relay", "query", "spin", "--segment-po2", segment_po2])
+                    .is_err()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn query_session_metadata() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--session-metadata",
+            "governance_proposal_id=42",
+            "--session-metadata",
+            "note=a=b",
+        ])?;
+        let Command::Query {
+            session_metadata, ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the query command");
+        };
+        assert_eq!(
+            session_metadata,
+            [
+                ("governance_proposal_id".to_string(), "42".to_string()),
+                ("note".to_string(), "a=b".to_string()),
+            ]
+        );
+
+        for pair in ["environment", "=staging"] {
+            assert!(App::try_parse_from([
+                "relay",
+                "query",
+                "finalize_votes",
+                "00",
+                "--session-metadata",
+                pair,
+            ])
+            .is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bonsai_client_opts() -> anyhow::Result<()> {
+        let app = App::try_parse_from(["relay", "upload"])?;
+        assert_eq!(
+            app.global_opts.bonsai_client(),
+            super::BonsaiClientOpts::default()
+        );
+
+        let app = App::try_parse_from([
+            "relay",
+            "upload",
+            "--bonsai-timeout",
+            "10m",
+            "--bonsai-connect-timeout",
+            "5s",
+            "--bonsai-proxy",
+            "http://proxy.internal:3128",
+            "--bonsai-ca-cert",
+            "ca.pem",
+            "--bonsai-insecure-tls",
+        ])?;
+        let opts = app.global_opts.bonsai_client();
+        assert_eq!(opts.timeout, std::time::Duration::from_secs(600));
+        assert_eq!(opts.connect_timeout, std::time::Duration::from_secs(5));
+        assert_eq!(opts.proxy.as_deref(), Some("http://proxy.internal:3128"));
+        assert_eq!(opts.ca_cert, Some("ca.pem".into()));
+        assert!(opts.insecure_tls);
+        assert_eq!(opts.hmac_secret, None);
+        assert_eq!(opts.tls_identity, None);
+
+        let app = App::try_parse_from([
+            "relay",
+            "upload",
+            "--bonsai-tls-cert",
+            "client.pem",
+            "--bonsai-tls-key",
+            "client.key",
+        ])?;
+        assert_eq!(
+            app.global_opts.bonsai_client().tls_identity,
+            Some(("client.pem".into(), "client.key".into()))
+        );
+        assert!(
+            App::try_parse_from(["relay", "upload", "--bonsai-tls-cert", "client.pem"]).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_session_requires_a_session_id() -> anyhow::Result<()> {
+        assert!(App::try_parse_from(["relay", "cancel-session"]).is_err());
+        let app = App::try_parse_from([
+            "relay",
+            "cancel-session",
+            "--session-id",
+            "0b7c8a4e-5a2a-4f4c-9f2e-3f1b6f0e8c11",
+        ])?;
+        let Command::CancelSession { session_id } = app.command else {
+            anyhow::bail!("expected the cancel-session command");
+        };
+        assert_eq!(session_id, "0b7c8a4e-5a2a-4f4c-9f2e-3f1b6f0e8c11");
+        Ok(())
+    }
+
+    #[test]
+    fn diff_image_ids_sources() {
+        let contract = "0x5FbDB2315678afecb367f032d93F642f64180aa3";
+        assert!(App::try_parse_from(["relay", "diff-image-ids", "--contract", contract]).is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "diff-image-ids",
+            "--contract",
+            contract,
+            "--getter",
+            "imageId()",
+            "--storage-slot",
+            "0",
+        ])
+        .is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "diff-image-ids",
+            "--contract",
+            contract,
+            "--getter",
+            "imageId()",
+            "--format",
+            "json",
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "diff-image-ids",
+            "--contract",
+            contract,
+            "--mapping-file",
+            "image_ids.json",
+        ])
+        .is_ok());
+    }
+
+    #[test]
+    fn image_id_sources() {
+        assert!(App::try_parse_from(["relay", "image-id"]).is_ok());
+        assert!(App::try_parse_from(["relay", "image-id", "echo_frames"]).is_ok());
+        assert!(App::try_parse_from(["relay", "image-id", "--elf", "guest.elf"]).is_ok());
+        assert!(
+            App::try_parse_from(["relay", "image-id", "echo_frames", "--elf", "guest.elf"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn encode_image_ids_as_bytes32_array() {
+        let output = encode_image_ids([Digest::from([1u32; 8])]);
+        // Offset, length, then the single bytes32 element.
+        assert_eq!(output.len(), 3 * 64);
+        assert!(output.ends_with(&hex::encode(<[u8; 32]>::from(Digest::from([1u32; 8])))));
+    }
+
+    #[test]
+    fn hmac_opts() -> anyhow::Result<()> {
+        assert!(App::try_parse_from(["relay", "upload", "--use-hmac"]).is_err());
+        assert!(App::try_parse_from(["relay", "upload", "--hmac-secret", "not hex"]).is_err());
+
+        let app = App::try_parse_from(["relay", "upload", "--hmac-secret", "0x00ff"])?;
+        assert_eq!(app.global_opts.bonsai_client().hmac_secret, None);
+
+        let app =
+            App::try_parse_from(["relay", "upload", "--use-hmac", "--hmac-secret", "0x00ff"])?;
+        assert_eq!(
+            app.global_opts.bonsai_client().hmac_secret,
+            Some(vec![0x00, 0xff])
+        );
+        Ok(())
+    }
+
+    /// Held by the tests setting the environment variables of the options,
+    /// as the environment is shared by the tests of the process.
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn global_opts_comparison() -> anyhow::Result<()> {
+        let _env = ENV_LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let parse_with_dev_mode = |dev_mode: &str| {
+            std::env::set_var("RISC0_DEV_MODE", dev_mode);
+            let app = App::try_parse_from(["relay", "--elf-cache-dir", "/tmp/elf-cache", "upload"]);
+            std::env::remove_var("RISC0_DEV_MODE");
+            app.map(|app| app.global_opts)
+        };
+
+        let first = parse_with_dev_mode("true")?;
+        let second = parse_with_dev_mode("true")?;
+        assert!(first.risc0_dev_mode);
+        assert_eq!(first.elf_cache_dir, Some("/tmp/elf-cache".into()));
+        assert_eq!(first, second);
+        assert_eq!(first.clone(), second);
+
+        let prod = parse_with_dev_mode("false")?;
+        assert!(!prod.risc0_dev_mode);
+        assert_ne!(first, prod);
+        Ok(())
+    }
+
+    #[test]
+    fn decode_journal_sources() {
+        assert!(App::try_parse_from([
+            "relay",
+            "decode-journal",
+            "--abi",
+            "(bytes32)",
+            "--journal",
+            "0x00",
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "decode-journal",
+            "finalize_votes",
+            "--journal-abi-file",
+            "journal-abi.json",
+            "--query-output",
+            "-",
+        ])
+        .is_ok());
+        // A guest binary needs a manifest to resolve its journal ABI.
+        assert!(App::try_parse_from([
+            "relay",
+            "decode-journal",
+            "finalize_votes",
+            "--journal",
+            "0x00"
+        ])
+        .is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "decode-journal",
+            "--abi",
+            "(bytes32)",
+            "--journal",
+            "0x00",
+            "--query-output",
+            "-",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn groth16_seal_round_trips() -> anyhow::Result<()> {
+        let proof = SnarkProof {
+            a: vec!["01".into(), "02".into()],
+            b: vec![
+                vec!["03".into(), "04".into()],
+                vec!["05".into(), "06".into()],
+            ],
+            c: vec!["07".into(), "08".into()],
+            public: vec![],
+        };
+        let selector = parse_seal_selector("0x310fe598")?;
+        let seal = encode_groth16_seal(&proof, selector)?;
+        assert_eq!(seal[..4], selector);
+
+        // Decode the seal back into the proof points.
+        let point = ParamType::FixedArray(Box::new(ParamType::Uint(256)), 2);
+        let tokens = ethers::abi::decode(
+            &[
+                point.clone(),
+                ParamType::FixedArray(Box::new(point.clone()), 2),
+                point,
+            ],
+            &seal[4..],
+        )?;
+        assert_eq!(Token::FixedArray(tokens), tokenize_snark_proof(&proof)?);
+        Ok(())
+    }
+
+    #[test]
+    fn seal_selector_args() {
+        assert!(parse_seal_selector("0xdeadbeef").is_ok());
+        assert!(parse_seal_selector("deadbeef").is_ok());
+        assert!(parse_seal_selector("0xdeadbe").is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--seal-format",
+            "groth16-selector",
+            "--seal-selector",
+            "0x12345678"
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "00",
+            "--seal-selector",
+            "0x1234"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn run_event_filter_topics() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--relay-event-filter-topics",
+            "finalize_votes,echo_frames",
+            "--shutdown-timeout",
+            "90s",
+        ])?;
+        let Command::Run(RunArgs {
+            relay_event_filter_topics,
+            shutdown_timeout,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(relay_event_filter_topics, ["finalize_votes", "echo_frames"]);
+        assert_eq!(*shutdown_timeout, std::time::Duration::from_secs(90));
+        Ok(())
+    }
+
+    #[test]
+    fn run_multiple_relay_addresses() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3,0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512",
+            "--relay-address",
+            "0x9fE46736679d2D9a65F0992F2272dE9f3c7fa6e0",
+        ])?;
+        let Command::Run(RunArgs { relay_address, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(relay_address.len(), 3);
+        assert_eq!(
+            relay_address[1],
+            "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512".parse()?
+        );
+
+        assert!(App::try_parse_from(["relay", "run"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_relay_concurrency() {
+        let run = |args: &[&str]| {
+            App::try_parse_from(
+                [
+                    "relay",
+                    "run",
+                    "--relay-address",
+                    "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                ]
+                .iter()
+                .chain(args),
+            )
+        };
+        assert!(run(&["--max-concurrent-relays", "1", "--relay-queue-depth", "1"]).is_ok());
+        assert!(run(&["--max-concurrent-relays", "0"]).is_err());
+        assert!(run(&["--relay-queue-depth", "0"]).is_err());
+    }
+
+    #[test]
+    fn run_upload_flags() -> anyhow::Result<()> {
+        let run = |flags: &[&str]| {
+            App::try_parse_from(
+                [
+                    "relay",
+                    "run",
+                    "--relay-address",
+                    "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                ]
+                .iter()
+                .chain(flags),
+            )
+        };
+        let Command::Run(RunArgs {
+            no_upload,
+            upload_only,
+            ..
+        }) = run(&[])?.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert!(!no_upload && !upload_only);
+
+        let Command::Run(RunArgs { no_upload, .. }) = run(&["--no-upload"])?.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert!(no_upload);
+        let Command::Run(RunArgs { upload_only, .. }) = run(&["--upload-only"])?.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert!(upload_only);
+
+        assert!(run(&["--no-upload", "--upload-only"]).is_err());
+        assert!(run(&["--no-upload", "--submission-only"]).is_err());
+        assert!(run(&["--upload-only", "--submission-only"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_tx_endpoint() -> anyhow::Result<()> {
+        let run = |flags: &[&str]| {
+            App::try_parse_from(
+                [
+                    "relay",
+                    "run",
+                    "--relay-address",
+                    "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                ]
+                .iter()
+                .chain(flags),
+            )
+        };
+        let Command::Run(RunArgs {
+            tx_endpoint,
+            private_relay_fallback_blocks,
+            ..
+        }) = run(&[])?.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(tx_endpoint, TxEndpoint::Public);
+        assert_eq!(
+            private_relay_fallback_blocks,
+            DEFAULT_PRIVATE_RELAY_FALLBACK_BLOCKS
+        );
+
+        assert!(run(&["--tx-endpoint", "private"]).is_err());
+        let Command::Run(RunArgs {
+            tx_endpoint,
+            private_relay_url,
+            ..
+        }) = run(&[
+            "--tx-endpoint",
+            "private",
+            "--private-relay-url",
+            "https://rpc.flashbots.net",
+        ])?
+        .command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(tx_endpoint, TxEndpoint::Private);
+        assert_eq!(
+            private_relay_url.as_deref(),
+            Some("https://rpc.flashbots.net")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn run_watch_gas_price() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--watch-gas-price",
+            "30s",
+        ])?;
+        let Command::Run(RunArgs {
+            watch_gas_price, ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(
+            watch_gas_price.map(Duration::from),
+            Some(Duration::from_secs(30))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn run_journal_filters() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--journal-filter",
+            "0x12345678",
+            "--journal-filter",
+            "cafe",
+        ])?;
+        let Command::Run(RunArgs { journal_filter, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(
+            journal_filter,
+            [
+                Bytes::from(vec![0x12, 0x34, 0x56, 0x78]),
+                Bytes::from(vec![0xca, 0xfe])
+            ]
+        );
+
+        for filter in ["0x", "0x123", "selector"] {
+            assert!(
+                App::try_parse_from([
+                    "relay",
+                    "run",
+                    "--relay-address",
+                    "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                    "--journal-filter",
+                    filter,
+                ])
+                .is_err(),
+                "{filter}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn run_chain_config() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--chain-config",
+            "chains.toml",
+        ])?;
+        let Command::Run(RunArgs { chain_config, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(chain_config, Some("chains.toml".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn run_config_file() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--config-file",
+            "relay.toml",
+        ])?;
+        let Command::Run(RunArgs { config_file, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(config_file, Some("relay.toml".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn run_event_persistence_db() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--event-persistence-db",
+            "events.db",
+            "--prune-events-older-than",
+            "30d",
+        ])?;
+        let Command::Run(RunArgs {
+            event_persistence_db,
+            prune_events_older_than,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(event_persistence_db, Some("events.db".into()));
+        assert_eq!(
+            prune_events_older_than.map(Duration::from),
+            Some(Duration::from_secs(30 * 24 * 60 * 60))
+        );
+
+        // Pruning requires a database.
+        assert!(App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--prune-events-older-than",
+            "30d",
+        ])
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_request_deadlines() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--request-max-age",
+            "1h",
+            "--cancel-expired-sessions",
+        ])?;
+        let Command::Run(RunArgs {
+            request_max_age,
+            cancel_expired_sessions,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(
+            request_max_age.map(Duration::from),
+            Some(Duration::from_secs(60 * 60))
+        );
+        assert!(cancel_expired_sessions);
+        Ok(())
+    }
+
+    #[test]
+    fn run_kms_options() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "alias/relayer",
+            "--kms-region",
+            "eu-west-1",
+            "--kms-endpoint",
+            "http://localhost:4566",
+        ])?;
+        let Command::Run(RunArgs {
+            private_key,
+            kms_region,
+            kms_profile,
+            kms_endpoint,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(private_key, vec!["alias/relayer"]);
+        assert_eq!(kms_region.as_deref(), Some("eu-west-1"));
+        assert_eq!(kms_profile, None);
+        assert_eq!(kms_endpoint.as_deref(), Some("http://localhost:4566"));
+        Ok(())
+    }
+
+    #[test]
+    fn run_multiple_private_keys() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "alias/relayer",
+            "--private-key",
+            "alias/second,alias/third",
+        ])?;
+        let Command::Run(RunArgs { private_key, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(
+            private_key,
+            vec!["alias/relayer", "alias/second", "alias/third"]
+        );
+
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+        ])?;
+        let Command::Run(RunArgs { private_key, .. }) = app.command else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(private_key, vec![ANVIL_DEFAULT_KEY]);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_config_takes_the_run_options() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "validate-config",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "alias/relayer",
+            "--min-eth-balance",
+            "0.5",
+            "--json-rpc-batch",
+        ])?;
+        let Command::ValidateConfig(RunArgs {
+            relay_address,
+            private_key,
+            min_eth_balance,
+            json_rpc_batch,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the validate-config command");
+        };
+        assert_eq!(
+            relay_address,
+            vec!["0x5FbDB2315678afecb367f032d93F642f64180aa3".parse::<Address>()?]
+        );
+        assert_eq!(private_key, vec!["alias/relayer"]);
+        assert_eq!(min_eth_balance, ethers::utils::parse_ether("0.5")?);
+        assert!(json_rpc_batch);
+        assert!(App::try_parse_from(["relay", "validate-config"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_block_range() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--start-block",
+            "18000000",
+            "--end-block",
+            "18001000",
+        ])?;
+        let Command::Run(RunArgs {
+            start_block,
+            end_block,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(start_block, Some(18_000_000));
+        assert_eq!(end_block, Some(18_001_000));
+        assert!(App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--end-block",
+            "18001000",
+        ])
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn run_remote_signer_options() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "remote:https://web3signer.example.com:9000",
+            "--signer-address",
+            "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266",
+            "--signer-tls-client-cert",
+            "relay.pem",
+        ])?;
+        let Command::Run(RunArgs {
+            private_key,
+            signer_address,
+            signer_tls_ca_cert,
+            signer_tls_client_cert,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(
+            private_key,
+            vec!["remote:https://web3signer.example.com:9000"]
+        );
+        assert_eq!(
+            signer_address,
+            Some("0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266".parse::<Address>()?)
+        );
+        assert_eq!(signer_tls_ca_cert, None);
+        assert_eq!(signer_tls_client_cert, Some(PathBuf::from("relay.pem")));
+        Ok(())
+    }
+
+    #[test]
+    fn run_ledger_options() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "ledger:m/44'/60'/0'/0/0",
+            "--ledger-signing-timeout",
+            "5m",
+        ])?;
+        let Command::Run(RunArgs {
+            private_key,
+            ledger_signing_timeout,
+            ledger_unlock_timeout,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert!(private_key[0]
+            .parse::<WalletKeyIdentifier>()?
+            .is_hardware_wallet());
+        assert_eq!(*ledger_signing_timeout, Duration::from_secs(300));
+        assert_eq!(*ledger_unlock_timeout, DEFAULT_LEDGER_UNLOCK_TIMEOUT);
+        Ok(())
+    }
+
+    #[test]
+    fn run_stuck_tx_defaults() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--tx-resubmit-bump",
+            "25",
+        ])?;
+        let Command::Run(RunArgs {
+            stuck_tx_timeout,
+            tx_resubmit_bump,
+            reorg_depth,
+            poll_interval,
+            ws_heartbeat_interval,
+            ws_stale_timeout,
+            eth_node_ws_ping_interval,
+            eth_node_ws_pong_timeout,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(*stuck_tx_timeout, DEFAULT_STUCK_TX_TIMEOUT);
+        assert_eq!(tx_resubmit_bump, 25);
+        assert_eq!(reorg_depth, 0);
+        assert_eq!(*poll_interval, DEFAULT_ETH_NODE_POLL_INTERVAL);
+        assert_eq!(*ws_heartbeat_interval, DEFAULT_WS_HEARTBEAT_INTERVAL);
+        assert_eq!(*ws_stale_timeout, DEFAULT_WS_STALE_TIMEOUT);
+        assert_eq!(*eth_node_ws_ping_interval, DEFAULT_WS_PING_INTERVAL);
+        assert_eq!(*eth_node_ws_pong_timeout, DEFAULT_WS_PONG_TIMEOUT);
+        Ok(())
+    }
+
+    #[test]
+    fn run_relay_concurrency_defaults() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--max-concurrent-relays",
+            "4",
+        ])?;
+        let Command::Run(RunArgs {
+            max_concurrent_relays,
+            relay_queue_depth,
+            ..
+        }) = app.command
+        else {
+            anyhow::bail!("expected the run command");
+        };
+        assert_eq!(max_concurrent_relays, 4);
+        assert_eq!(relay_queue_depth, DEFAULT_RELAY_QUEUE_DEPTH);
+        Ok(())
+    }
+
+    #[test]
+    fn rotate_key_args() {
+        assert!(App::try_parse_from([
+            "relay",
+            "rotate-key",
+            "--old-key",
+            "0x01",
+            "--new-key",
+            "0x02",
+            "--keystore",
+            "relay.key",
+            "--yes"
+        ])
+        .is_ok());
+        assert!(App::try_parse_from(["relay", "rotate-key", "--old-key", "0x01"]).is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            "0x01",
+            "--keystore",
+            "relay.key"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn command_display_redacts_secrets() -> anyhow::Result<()> {
+        let private_key = "0x2a871d0798f97d79848a013d4936a73bf4cc922c825d33c1cf7073dff6d409c6";
+        let app = App::try_parse_from([
+            "relay",
+            "run",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--private-key",
+            private_key,
+            "--admin-api-key",
+            "admin-secret",
+            "--eth-node",
+            "wss://mainnet.example.com/v3/node-secret",
+        ])?;
+        let display = app.command.to_string();
+        assert!(
+            display
+                .starts_with("Run { relay_address: [0x5fbdb2315678afecb367f032d93f642f64180aa3]"),
+            "{display}"
+        );
+        assert!(display.contains("private_key: [REDACTED]"), "{display}");
+        assert!(
+            display.contains("admin_api_key: Some([REDACTED])"),
+            "{display}"
+        );
+        for secret in [private_key, "admin-secret", "node-secret"] {
+            assert!(!display.contains(secret), "{display}");
+        }
+
+        let app = App::try_parse_from([
+            "relay",
+            "rotate-key",
+            "--old-key",
+            "0x01",
+            "--new-key",
+            "0x02",
+        ])?;
+        let display = app.command.to_string();
+        assert!(
+            display.contains("old_key: [REDACTED], new_key: [REDACTED]"),
+            "{display}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn request_callback_args() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "request-callback",
+            "--relay-address",
+            "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+            "--callback-contract",
+            "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512",
+            "--function-selector",
+            "settle(bytes)",
+            "echo_frames",
+            "--frame",
+            "0x01",
+            "--wait",
+        ])?;
+        let Command::RequestCallback {
+            function_selector,
+            guest_binary,
+            frames,
+            gas_limit,
+            wait,
+            ..
+        } = app.command
+        else {
+            panic!("expected a request-callback command");
+        };
+        assert_eq!(function_selector, ethers::utils::id("settle(bytes)"));
+        assert_eq!(guest_binary, GuestSelector::Name("echo_frames".to_string()));
+        assert_eq!(frames, vec!["0x01".to_string()]);
+        assert_eq!(gas_limit, 3000000);
+        assert!(wait);
+
+        let request = |args: &[&str]| {
+            App::try_parse_from(
+                [
+                    "relay",
+                    "request-callback",
+                    "--relay-address",
+                    "0x5FbDB2315678afecb367f032d93F642f64180aa3",
+                    "--callback-contract",
+                    "0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512",
+                ]
+                .iter()
+                .chain(args),
+            )
+        };
+        assert!(request(&["--function-selector", "0x12345678", "echo_frames"]).is_ok());
+        assert!(request(&["--function-selector", "0x1234", "echo_frames"]).is_err());
+        assert!(request(&[
+            "--function-selector",
+            "0x12345678",
+            "echo_frames",
+            "--timeout",
+            "1m"
+        ])
+        .is_err());
+        assert!(request(&[
+            "--function-selector",
+            "0x12345678",
+            "echo_frames",
+            "0x01",
+            "--frame",
+            "0x02"
+        ])
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn bench_args() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "bench",
+            "finalize_votes",
+            "00",
+            "--iterations",
+            "5",
+            "--warmup",
+            "2",
+            "--concurrency",
+            "3",
+            "--receipt-kind",
+            "stark",
+            "--format",
+            "json",
+        ])?;
+        let Command::Bench {
+            input,
+            iterations,
+            concurrency,
+            receipt_kind,
+            format,
+            ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the bench command");
+        };
+        assert_eq!(input.as_deref(), Some("00"));
+        assert_eq!((iterations, concurrency), (5, 3));
+        assert_eq!(receipt_kind, ReceiptKind::Stark);
+        assert!(matches!(format, ReportFormat::Json));
+
+        // The former name and output format flag are kept as aliases.
+        assert!(App::try_parse_from([
+            "relay",
+            "benchmark",
+            "finalize_votes",
+            "00",
+            "--output-format",
+            "json"
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "bench",
+            "finalize_votes",
+            "--abi",
+            "(uint256)",
+            "--arg",
+            "1"
+        ])
+        .is_ok());
+        // An input is required, and at least one run.
+        assert!(App::try_parse_from(["relay", "bench", "finalize_votes"]).is_err());
+        for flag in ["--iterations", "--concurrency"] {
+            assert!(
+                App::try_parse_from(["relay", "bench", "finalize_votes", "00", flag, "0"]).is_err()
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn verify_receipt_image_id_sources() {
+        assert!(App::try_parse_from([
+            "relay",
+            "verify-receipt",
+            "receipt.json",
+            "--image-id",
+            "00ff"
+        ])
+        .is_ok());
+        assert!(App::try_parse_from([
+            "relay",
+            "verify-receipt",
+            "receipt.json",
+            "--guest-binary",
+            "finalize_votes"
+        ])
+        .is_ok());
+        assert!(App::try_parse_from(["relay", "verify-receipt", "receipt.json"]).is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "verify-receipt",
+            "receipt.json",
+            "--image-id",
+            "00ff",
+            "--guest-binary",
+            "finalize_votes"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn upload_checksums() {
+        assert!(
+            App::try_parse_from(["relay", "upload", "finalize_votes", "--checksum", "00ff"])
+                .is_ok()
+        );
+        assert!(
+            App::try_parse_from(["relay", "upload", "--checksum-file", "checksums.txt"]).is_ok()
+        );
+        assert!(App::try_parse_from(["relay", "upload", "--checksum", "00ff"]).is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "upload",
+            "finalize_votes",
+            "--checksum-file",
+            "checksums.txt"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn query_abi_args() {
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "--abi",
+            "(uint256,int256)",
+            "--arg",
+            "5",
+            "--arg",
+            "-5",
+        ])
+        .is_ok());
+        assert!(App::try_parse_from(["relay", "query", "finalize_votes", "--arg", "5"]).is_err());
+        assert!(App::try_parse_from([
+            "relay",
+            "query",
+            "finalize_votes",
+            "0x01",
+            "--abi",
+            "uint256",
+            "--arg",
+            "5",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn resubmit_args() -> anyhow::Result<()> {
+        let app = App::try_parse_from([
+            "relay",
+            "resubmit",
+            "--event-db",
+            "events.db",
+            "--event-id",
+            "42",
+        ])?;
+        let Command::Resubmit {
+            event_db,
+            event_id,
+            eth_chain_id,
+            ..
+        } = app.command
+        else {
+            anyhow::bail!("expected the resubmit command");
+        };
+        assert_eq!(event_db, PathBuf::from("events.db"));
+        assert_eq!(event_id, 42);
+        assert_eq!(eth_chain_id, 31337);
+
+        assert!(App::try_parse_from(["relay", "resubmit", "--event-db", "events.db"]).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,664 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use bonsai_ethereum_relay_cli::{
+    cancel_session, exit_code, fetch_session_receipt, prove_alpha, upload_images,
+    wait_for_bonsai_ready, BonsaiClientOpts, CancelOutcome, GuestSelector, Output, PollOpts,
+    ReceiptKind, DEFAULT_UPLOAD_TIMEOUT,
+};
+use bonsai_rest_api_mock::testing::{Fault, FaultKind, MockBonsai, MockBonsaiConfig};
+use bonsai_sdk::{
+    alpha::{responses::SnarkProof, Client},
+    alpha_async::get_client_from_parts,
+};
+use methods::{ECHO_FRAMES_ID, FINALIZE_VOTES_ELF, FINALIZE_VOTES_ID, GUEST_LIST};
+use risc0_zkvm::{
+    recursion::SuccinctReceipt, sha::Digest, ExitCode, InnerReceipt, Receipt, ReceiptMetadata,
+    SystemState,
+};
+
+const API_KEY: &str = "test-key";
+
+fn image_id(image_id: [u32; 8]) -> String {
+    hex::encode(bytemuck::cast::<_, [u8; 32]>(image_id))
+}
+
+fn receipt(journal: &[u8]) -> Result<Vec<u8>> {
+    let state = SystemState {
+        pc: 0,
+        merkle_root: Digest::from([0u32; 8]),
+    };
+    let receipt = Receipt::new(
+        InnerReceipt::Succinct(SuccinctReceipt {
+            seal: vec![],
+            control_id: Digest::from([0u32; 8]),
+            meta: ReceiptMetadata {
+                pre: state.clone(),
+                post: state,
+                exit_code: ExitCode::Halted(0),
+                input: Digest::from([0u32; 8]),
+                output: Digest::from([0u32; 8]),
+            },
+        }),
+        journal.to_vec(),
+    );
+    Ok(bincode::serialize(&receipt)?)
+}
+
+fn snark_proof() -> SnarkProof {
+    SnarkProof {
+        a: vec!["0x01".to_string(), "0x02".to_string()],
+        b: vec![
+            vec!["0x03".to_string(), "0x04".to_string()],
+            vec!["0x05".to_string(), "0x06".to_string()],
+        ],
+        c: vec!["0x07".to_string(), "0x08".to_string()],
+        public: vec!["0x09".to_string()],
+    }
+}
+
+/// Proves FINALIZE_VOTES on the mock, from a blocking task as the Bonsai client
+/// is blocking.
+async fn prove(mock: &MockBonsai) -> Result<Output> {
+    prove_into(mock, ReceiptKind::Snark).await
+}
+
+/// Proves FINALIZE_VOTES on the mock into a receipt of the given kind.
+async fn prove_into(mock: &MockBonsai, receipt_kind: ReceiptKind) -> Result<Output> {
+    prove_polling(mock, receipt_kind, fast_poll()).await
+}
+
+/// Polling options with short delays, for the tests not to wait on the mock.
+fn fast_poll() -> PollOpts {
+    PollOpts {
+        interval: Duration::from_millis(10),
+        timeout: None,
+        retry_duration: Duration::from_secs(5),
+    }
+}
+
+/// Proves FINALIZE_VOTES on the mock, polling the session as given.
+async fn prove_polling(
+    mock: &MockBonsai,
+    receipt_kind: ReceiptKind,
+    poll: PollOpts,
+) -> Result<Output> {
+    let url = mock.url();
+    tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        prove_alpha(
+            &client,
+            FINALIZE_VOTES_ELF,
+            b"input".to_vec(),
+            false,
+            receipt_kind,
+            &poll,
+            HashMap::new(),
+            None,
+        )
+    })
+    .await?
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upload_images_skips_existing_images() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        existing_images: HashSet::from([image_id(ECHO_FRAMES_ID)]),
+        ..Default::default()
+    })
+    .await?;
+
+    let results = upload_images(
+        GUEST_LIST,
+        None,
+        None,
+        &mock.url(),
+        API_KEY,
+        DEFAULT_UPLOAD_TIMEOUT,
+        &BonsaiClientOpts::default(),
+    )
+    .await?;
+    assert_eq!(results.len(), GUEST_LIST.len());
+    for result in results {
+        let uploaded = result.image_id != Digest::from(ECHO_FRAMES_ID);
+        assert_eq!(result.uploaded, uploaded, "{}", result.name);
+    }
+    assert_eq!(
+        mock.uploaded_images(),
+        HashSet::from([image_id(FINALIZE_VOTES_ID)])
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_bonsai_ready_polls_until_healthy() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        faults: vec![
+            Fault::new("/health", FaultKind::ServerError),
+            Fault::new("/health", FaultKind::ServerError),
+        ],
+        ..Default::default()
+    })
+    .await?;
+
+    let client = get_client_from_parts(mock.url(), API_KEY.to_string()).await?;
+    wait_for_bonsai_ready(&client, Duration::from_secs(5)).await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_bonsai_ready_names_unreachable_url() -> Result<()> {
+    // Nothing listens on the discard port.
+    let url = "http://127.0.0.1:9".to_string();
+    let client = get_client_from_parts(url.clone(), API_KEY.to_string()).await?;
+    let Err(err) = wait_for_bonsai_ready(&client, Duration::from_secs(1)).await else {
+        bail!("expected the unreachable Bonsai not to be ready");
+    };
+    let message = err.to_string();
+    assert!(message.contains(&url), "{message}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upload_images_fails_when_rate_limited() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        faults: vec![Fault::new("/images/upload", FaultKind::RateLimited)],
+        ..Default::default()
+    })
+    .await?;
+
+    let result = upload_images(
+        GUEST_LIST,
+        Some(GuestSelector::Name("finalize_votes".to_string())),
+        None,
+        &mock.url(),
+        API_KEY,
+        DEFAULT_UPLOAD_TIMEOUT,
+        &BonsaiClientOpts::default(),
+    )
+    .await;
+    let Err(err) = result else {
+        bail!("expected the rate limited upload to fail");
+    };
+    assert_eq!(exit_code(&err), 10);
+    assert!(mock.uploaded_images().is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upload_images_times_out() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        latency: Duration::from_secs(2),
+        ..Default::default()
+    })
+    .await?;
+
+    let result = upload_images(
+        GUEST_LIST,
+        Some(GuestSelector::Name("finalize_votes".to_string())),
+        None,
+        &mock.url(),
+        API_KEY,
+        Duration::from_millis(100),
+        &BonsaiClientOpts::default(),
+    )
+    .await;
+    let Err(err) = result else {
+        bail!("expected the slow upload to time out");
+    };
+    assert_eq!(exit_code(&err), 10);
+    let message = format!("{err:#}");
+    assert!(
+        message.contains("upload of guest finalize_votes"),
+        "{message}"
+    );
+    assert!(message.contains("--upload-timeout"), "{message}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_polls_until_succeeded() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["RUNNING".to_string(), "SUCCEEDED".to_string()],
+        receipt: receipt(b"journal")?,
+        snark_proof: snark_proof(),
+        ..Default::default()
+    })
+    .await?;
+
+    let Output::Bonsai {
+        journal,
+        snark_proof: proof,
+        ..
+    } = prove(&mock).await?
+    else {
+        bail!("expected a Bonsai output");
+    };
+    assert_eq!(journal, b"journal");
+    assert_eq!(proof, snark_proof());
+    assert_eq!(
+        mock.uploaded_images(),
+        HashSet::from([image_id(FINALIZE_VOTES_ID)])
+    );
+    assert_eq!(mock.uploaded_inputs(), vec![b"input".to_vec()]);
+    assert_eq!(mock.sessions().len(), 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_stark_skips_snark_conversion() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        receipt: receipt(b"journal")?,
+        // Requesting a SNARK would fail the proof.
+        snark_statuses: vec!["FAILED".to_string()],
+        ..Default::default()
+    })
+    .await?;
+
+    let Output::Stark { journal, stats, .. } = prove_into(&mock, ReceiptKind::Stark).await? else {
+        bail!("expected a STARK output");
+    };
+    assert_eq!(journal, b"journal");
+    assert!(stats.proving_time_ms.is_some());
+    assert!(stats.snark_time_ms.is_none());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_attaches_session_metadata() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        receipt: receipt(b"journal")?,
+        ..Default::default()
+    })
+    .await?;
+
+    let metadata = HashMap::from([
+        ("governance_proposal_id".to_string(), "42".to_string()),
+        ("environment".to_string(), "staging".to_string()),
+    ]);
+    let url = mock.url();
+    tokio::task::spawn_blocking({
+        let metadata = metadata.clone();
+        move || {
+            let client = Client::from_parts(url, API_KEY.to_string())?;
+            prove_alpha(
+                &client,
+                FINALIZE_VOTES_ELF,
+                b"input".to_vec(),
+                false,
+                ReceiptKind::Stark,
+                &fast_poll(),
+                metadata,
+                None,
+            )
+        }
+    })
+    .await??;
+    assert_eq!(mock.session_metadata(), vec![metadata]);
+
+    // Sessions without metadata send none.
+    prove_into(&mock, ReceiptKind::Stark).await?;
+    assert_eq!(mock.session_metadata()[1], HashMap::new());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_retries_session_status_errors() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        receipt: receipt(b"journal")?,
+        faults: vec![Fault::new("/sessions/status", FaultKind::ServerError)],
+        ..Default::default()
+    })
+    .await?;
+
+    let Output::Bonsai { journal, .. } = prove(&mock).await? else {
+        bail!("expected a Bonsai output");
+    };
+    assert_eq!(journal, b"journal");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_resumes_after_intermittent_failures() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["RUNNING".to_string(), "SUCCEEDED".to_string()],
+        snark_statuses: vec!["RUNNING".to_string(), "SUCCEEDED".to_string()],
+        receipt: receipt(b"journal")?,
+        snark_proof: snark_proof(),
+        faults: vec![
+            Fault::new("/sessions/status", FaultKind::ServerError),
+            Fault::new("/sessions/status", FaultKind::RateLimited),
+            Fault::new("/receipts", FaultKind::ServerError),
+            Fault::new("/snark/status", FaultKind::ServerError),
+            Fault::new("/snark/status", FaultKind::ServerError),
+        ],
+        ..Default::default()
+    })
+    .await?;
+
+    let Output::Bonsai {
+        journal,
+        snark_proof: proof,
+        ..
+    } = prove(&mock).await?
+    else {
+        bail!("expected a Bonsai output");
+    };
+    assert_eq!(journal, b"journal");
+    assert_eq!(proof, snark_proof());
+    assert_eq!(mock.sessions().len(), 1);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_gives_up_after_retry_duration() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        receipt: receipt(b"journal")?,
+        faults: vec![Fault::new("/sessions/status", FaultKind::ServerError); 100],
+        ..Default::default()
+    })
+    .await?;
+
+    let poll = PollOpts {
+        retry_duration: Duration::from_millis(100),
+        ..fast_poll()
+    };
+    let Err(err) = prove_polling(&mock, ReceiptKind::Snark, poll).await else {
+        bail!("expected polling to give up");
+    };
+    assert_eq!(exit_code(&err), 10);
+    let err = err.to_string();
+    assert!(err.contains("Giving up on session"), "{err}");
+    assert!(err.contains("may still complete on Bonsai"), "{err}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_times_out_running_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["RUNNING".to_string()],
+        ..Default::default()
+    })
+    .await?;
+
+    let poll = PollOpts {
+        timeout: Some(Duration::from_millis(100)),
+        ..fast_poll()
+    };
+    let Err(err) = prove_polling(&mock, ReceiptKind::Snark, poll).await else {
+        bail!("expected polling to time out");
+    };
+    assert_eq!(exit_code(&err), 10);
+    let err = err.to_string();
+    assert!(err.contains("did not complete within 100ms"), "{err}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_names_timed_out_request() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        latency: Duration::from_secs(2),
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let result = tokio::task::spawn_blocking(move || {
+        let opts = BonsaiClientOpts {
+            timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let client =
+            Client::from_parts_with_config(url, API_KEY.to_string(), &opts.client_config()?)?;
+        prove_alpha(
+            &client,
+            FINALIZE_VOTES_ELF,
+            b"input".to_vec(),
+            false,
+            ReceiptKind::Snark,
+            &fast_poll(),
+            HashMap::new(),
+            None,
+        )
+    })
+    .await?;
+    let Err(err) = result else {
+        bail!("expected the request to time out");
+    };
+    assert_eq!(exit_code(&err), 10);
+    let err = err.to_string();
+    assert!(
+        err.contains("Request to upload image timed out after 100ms"),
+        "{err}"
+    );
+    Ok(())
+}
+
+/// Bonsai URL whose host does not resolve, for the requests to only reach the
+/// mock through the proxy.
+const UNREACHABLE_BONSAI_URL: &str = "http://bonsai.invalid";
+
+/// Client options sending the requests through the mock, acting as a proxy.
+fn proxied_through(mock: &MockBonsai) -> BonsaiClientOpts {
+    BonsaiClientOpts {
+        proxy: Some(mock.url()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn upload_images_through_proxy() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig::default()).await?;
+
+    upload_images(
+        GUEST_LIST,
+        Some(GuestSelector::Name("finalize_votes".to_string())),
+        None,
+        UNREACHABLE_BONSAI_URL,
+        API_KEY,
+        DEFAULT_UPLOAD_TIMEOUT,
+        &proxied_through(&mock),
+    )
+    .await?;
+    assert_eq!(
+        mock.uploaded_images(),
+        HashSet::from([image_id(FINALIZE_VOTES_ID)])
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_through_proxy() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["RUNNING".to_string(), "SUCCEEDED".to_string()],
+        snark_statuses: vec!["RUNNING".to_string(), "SUCCEEDED".to_string()],
+        receipt: receipt(b"journal")?,
+        snark_proof: snark_proof(),
+        ..Default::default()
+    })
+    .await?;
+
+    // Uploads, session polling and SNARK retrieval all go through the proxy.
+    let client_config = proxied_through(&mock).client_config()?;
+    let output = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts_with_config(
+            UNREACHABLE_BONSAI_URL.to_string(),
+            API_KEY.to_string(),
+            &client_config,
+        )?;
+        prove_alpha(
+            &client,
+            FINALIZE_VOTES_ELF,
+            b"input".to_vec(),
+            false,
+            ReceiptKind::Snark,
+            &fast_poll(),
+            HashMap::new(),
+            None,
+        )
+    })
+    .await??;
+    let Output::Bonsai {
+        journal,
+        snark_proof: proof,
+        ..
+    } = output
+    else {
+        bail!("expected a Bonsai output");
+    };
+    assert_eq!(journal, b"journal");
+    assert_eq!(proof, snark_proof());
+    assert_eq!(mock.uploaded_inputs(), vec![b"input".to_vec()]);
+    Ok(())
+}
+
+#[test]
+fn bonsai_client_opts_report_unreadable_ca_cert() {
+    let opts = BonsaiClientOpts {
+        ca_cert: Some("does/not/exist.pem".into()),
+        ..Default::default()
+    };
+    let Err(err) = opts.client_config() else {
+        panic!("expected the CA certificate to be unreadable");
+    };
+    assert_eq!(exit_code(&err), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn prove_alpha_reports_failed_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["FAILED".to_string()],
+        error_msg: Some("guest panicked".to_string()),
+        ..Default::default()
+    })
+    .await?;
+
+    let Err(err) = prove(&mock).await else {
+        bail!("expected the failed session to be reported");
+    };
+    assert_eq!(exit_code(&err), 11);
+    let err = err.to_string();
+    assert!(err.contains("FAILED: guest panicked"), "{err}");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fetch_session_receipt_of_completed_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["SUCCEEDED".to_string()],
+        receipt: receipt(b"journal")?,
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let receipt = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        fetch_session_receipt(&client, "session-a")
+    })
+    .await??;
+    assert_eq!(receipt.journal, b"journal");
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn fetch_session_receipt_of_failed_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["FAILED".to_string()],
+        error_msg: Some("guest panicked".to_string()),
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let Err(err) = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        fetch_session_receipt(&client, "session-a")
+    })
+    .await?
+    else {
+        bail!("expected the failed session to have no receipt");
+    };
+    assert_eq!(exit_code(&err), 11);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_session_stops_running_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["RUNNING".to_string()],
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        cancel_session(&client, "session-a")
+    })
+    .await??;
+    assert_eq!(outcome, CancelOutcome::Cancelled);
+    assert_eq!(mock.stopped_sessions(), vec!["session-a".to_string()]);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_session_leaves_completed_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        session_statuses: vec!["SUCCEEDED".to_string()],
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        cancel_session(&client, "session-a")
+    })
+    .await??;
+    assert_eq!(
+        outcome,
+        CancelOutcome::AlreadyComplete("SUCCEEDED".to_string())
+    );
+    assert!(mock.stopped_sessions().is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancel_session_reports_unknown_session() -> Result<()> {
+    let mock = MockBonsai::start(MockBonsaiConfig {
+        missing_sessions: HashSet::from(["session-a".to_string()]),
+        ..Default::default()
+    })
+    .await?;
+
+    let url = mock.url();
+    let Err(err) = tokio::task::spawn_blocking(move || {
+        let client = Client::from_parts(url, API_KEY.to_string())?;
+        cancel_session(&client, "session-a")
+    })
+    .await?
+    else {
+        bail!("expected the unknown session to be reported");
+    };
+    assert_eq!(exit_code(&err), 2);
+    let err = err.to_string();
+    assert!(err.contains("session session-a not found"), "{err}");
+    Ok(())
+}
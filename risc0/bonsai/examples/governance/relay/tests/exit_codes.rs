@@ -0,0 +1,141 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exit codes of the relay CLI, run as a separate process.
+
+use std::process::{Command, Output};
+
+use anyhow::Result;
+use ethers::utils::Anvil;
+
+/// Index 0 private key generated by default in Anvil.
+const ANVIL_DEFAULT_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+fn relay(args: &[&str]) -> Result<Output> {
+    Ok(
+        Command::new(env!("CARGO_BIN_EXE_bonsai-ethereum-relay-cli"))
+            .args(args)
+            .output()?,
+    )
+}
+
+#[test]
+fn usage_error() -> Result<()> {
+    let output = relay(&[
+        "query",
+        "echo_frames",
+        "00",
+        "--risc0-dev-mode",
+        "--receipt-out",
+        "receipt.json",
+    ])?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)?.contains("a receipt is only produced"));
+    Ok(())
+}
+
+#[test]
+fn guest_not_found_as_json() -> Result<()> {
+    let output = relay(&["query", "no_such_guest", "--format", "json"])?;
+    assert_eq!(output.status.code(), Some(3));
+
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(error["error"]["kind"], "guest_resolution");
+    assert_eq!(error["error"]["code"], 3);
+    let message = error["error"]["message"].as_str().unwrap_or_default();
+    assert!(
+        message.contains("Unknown guest binary no_such_guest"),
+        "{message}"
+    );
+    Ok(())
+}
+
+#[test]
+fn invalid_input() -> Result<()> {
+    let output = relay(&["query", "echo_frames", "--frame", "0xzz"])?;
+    assert_eq!(output.status.code(), Some(4));
+    Ok(())
+}
+
+#[test]
+fn execution_limit_exceeded() -> Result<()> {
+    // The spin guest would run for u64::MAX rounds.
+    let output = relay(&[
+        "query",
+        "spin",
+        "ffffffffffffffff",
+        "--risc0-dev-mode",
+        "--max-cycles",
+        "100000",
+    ])?;
+    assert_eq!(output.status.code(), Some(5));
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("guest SPIN exceeded the limit of 100000 cycles"),
+        "{stderr}"
+    );
+    Ok(())
+}
+
+#[test]
+fn bonsai_unreachable() -> Result<()> {
+    let output = relay(&[
+        "upload",
+        "echo_frames",
+        "--bonsai-api-url",
+        "http://127.0.0.1:1",
+    ])?;
+    assert_eq!(output.status.code(), Some(10));
+    Ok(())
+}
+
+#[test]
+fn ethereum_unreachable() -> Result<()> {
+    let output = relay(&[
+        "rotate-key",
+        "--eth-node",
+        "ws://127.0.0.1:1",
+        "--old-key",
+        ANVIL_DEFAULT_KEY,
+        "--new-key",
+        ANVIL_DEFAULT_KEY,
+        "--yes",
+    ])?;
+    assert_eq!(output.status.code(), Some(20));
+    Ok(())
+}
+
+#[test]
+#[ignore]
+fn contract_call_failed() -> Result<()> {
+    // Checking the owners of a Safe fails if no contract is deployed at its
+    // address.
+    let anvil = Anvil::new().spawn();
+    let output = relay(&[
+        "rotate-key",
+        "--eth-node",
+        &anvil.ws_endpoint(),
+        "--eth-chain-id",
+        &anvil.chain_id().to_string(),
+        "--old-key",
+        ANVIL_DEFAULT_KEY,
+        "--new-key",
+        ANVIL_DEFAULT_KEY,
+        "--safe-address",
+        "0x0000000000000000000000000000000000000001",
+        "--yes",
+    ])?;
+    assert_eq!(output.status.code(), Some(21));
+    Ok(())
+}
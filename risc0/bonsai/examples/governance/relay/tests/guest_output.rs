@@ -0,0 +1,76 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Guest output and exit code surfaced by the relay CLI on local executions,
+//! run as a separate process.
+
+use std::process::{Command, Output};
+
+use anyhow::Result;
+
+/// Executes the `guest_output` guest in dev mode, pausing it if `pause` is
+/// set.
+fn query_guest_output(pause: bool, args: &[&str]) -> Result<Output> {
+    // A single little-endian word.
+    let input = if pause { "01000000" } else { "00000000" };
+    Ok(
+        Command::new(env!("CARGO_BIN_EXE_bonsai-ethereum-relay-cli"))
+            .args(["query", "guest_output", input, "--risc0-dev-mode"])
+            .args(args)
+            .output()?,
+    )
+}
+
+#[test]
+fn bad_exit_code_fails_with_guest_output() -> Result<()> {
+    let output = query_guest_output(true, &[])?;
+    assert!(!output.status.success());
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(
+        stderr.contains("Guest exited with bad exit code: Paused(0)"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("Guest exit code: Paused(0)"), "{stderr}");
+    assert!(
+        stderr.contains("Guest stdout:\nguest stdout line"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("Guest stderr:\nguest stderr line"),
+        "{stderr}"
+    );
+    Ok(())
+}
+
+#[test]
+fn guest_output_shown_on_request() -> Result<()> {
+    let output = query_guest_output(false, &[])?;
+    assert!(output.status.success());
+    assert!(!String::from_utf8(output.stderr)?.contains("guest stdout line"));
+
+    let output = query_guest_output(false, &["--show-guest-output"])?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Guest exit code: Halted(0)"), "{stderr}");
+    assert!(
+        stderr.contains("Guest stdout:\nguest stdout line"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("Guest stderr:\nguest stderr line"),
+        "{stderr}"
+    );
+    Ok(())
+}
@@ -26,6 +26,8 @@ use tokio::task::JoinError;
 pub(crate) enum Error {
     #[error("ImageIdExists")]
     ImageIdExists,
+    #[error("Session not found")]
+    SessionNotFound,
     #[error("Bincode error")]
     Bincode(#[from] bincode::Error),
     #[error("Hex decode error")]
@@ -52,6 +54,7 @@ impl Error {
     pub fn status_code(&self) -> StatusCode {
         match self {
             Error::ImageIdExists { .. } => StatusCode::NO_CONTENT,
+            Error::SessionNotFound => StatusCode::NOT_FOUND,
             Error::Poisoned
             | Error::Bincode { .. }
             | Error::Unspecified { .. }
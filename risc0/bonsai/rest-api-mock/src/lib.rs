@@ -16,13 +16,15 @@ mod error;
 mod prover;
 mod routes;
 mod state;
+#[cfg(feature = "test-utils")]
+pub mod testing;
 
 use std::sync::{Arc, RwLock};
 
 use anyhow::Context;
 use axum::{
     extract::DefaultBodyLimit,
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Extension, Router,
 };
 use tokio::sync::mpsc;
@@ -32,20 +34,22 @@ use tracing::{info, Level};
 use crate::{
     prover::{Prover, ProverHandle},
     routes::{
-        create_session, create_snark, get_image_upload, get_input_upload, get_receipt,
-        put_image_upload, put_input_upload, session_status, snark_status,
+        create_session, create_snark, get_image_upload, get_input_upload, get_receipt, health,
+        put_image_upload, put_input_upload, session_status, snark_status, stop_session,
     },
     state::BonsaiState,
 };
 
 fn app(state: Arc<RwLock<BonsaiState>>, prover_handle: ProverHandle) -> Router {
     Router::new()
+        .route("/health", get(health))
         .route("/images/upload/:image_id", get(get_image_upload))
         .route("/images/:image_id", put(put_image_upload))
         .route("/inputs/upload", get(get_input_upload))
         .route("/inputs/:input_id", put(put_input_upload))
         .route("/sessions/create", post(create_session))
         .route("/sessions/status/:session_id", get(session_status))
+        .route("/sessions/:session_id", delete(stop_session))
         .route("/snark/create", post(create_snark))
         .route("/snark/status/:snark_id", get(snark_status))
         .route("/receipts/:session_id", get(get_receipt))
@@ -18,6 +18,7 @@ use std::{
 };
 
 use anyhow::Context;
+use bonsai_sdk::alpha::responses::SessionStats;
 use risc0_zkvm::{
     Executor, ExecutorEnv, InnerReceipt, MemoryImage, Program, Receipt, MEM_SIZE, PAGE_SIZE,
 };
@@ -81,6 +82,10 @@ impl Prover {
     pub async fn handle_message(&mut self, msg: &ProverMessage) -> Result<(), Error> {
         match msg {
             ProverMessage::RunSession(task) => {
+                if self.is_aborted(task)? {
+                    tracing::info!("Session {} was stopped, skipping", task.session_id);
+                    return Ok(());
+                }
                 tracing::info!("Running task...");
                 let image = self.get_image(task).await?;
                 let input = self.get_input(task).await?;
@@ -103,6 +108,12 @@ impl Prover {
                     })?;
                 let mut exec = Executor::new(env, mem_img)?;
                 let session = exec.run()?;
+                let segments = session.resolve()?;
+                let stats = SessionStats {
+                    segments: segments.len(),
+                    total_cycles: segments.iter().map(|s| 1u64 << s.po2).sum(),
+                    cycles: segments.iter().map(|s| s.insn_cycles as u64).sum(),
+                };
 
                 let receipt = Receipt {
                     inner: InnerReceipt::Fake,
@@ -114,7 +125,12 @@ impl Prover {
                     .put_receipt(task.session_id.clone(), receipt_bytes);
                 self.storage
                     .write()?
-                    .put_session(task.session_id.clone(), "SUCCEEDED".to_string());
+                    .put_stats(task.session_id.clone(), stats);
+                let mut storage = self.storage.write()?;
+                // A session stopped while executing stays aborted
+                if storage.get_session(&task.session_id).as_deref() != Some("ABORTED") {
+                    storage.put_session(task.session_id.clone(), "SUCCEEDED".to_string());
+                }
             }
         }
 
@@ -155,4 +171,13 @@ impl Prover {
             .get_input(&task.input_id)
             .ok_or_else(|| anyhow::anyhow!("Failed to get input for ID: {:?}", task.input_id))?)
     }
+
+    fn is_aborted(&self, task: &Task) -> Result<bool, Error> {
+        Ok(self
+            .storage
+            .read()?
+            .get_session(&task.session_id)
+            .as_deref()
+            == Some("ABORTED"))
+    }
 }
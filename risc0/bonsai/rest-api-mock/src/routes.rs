@@ -31,6 +31,8 @@ use crate::{
     state::AppState,
 };
 
+pub(crate) async fn health() {}
+
 pub(crate) async fn get_image_upload(
     State(s): State<AppState>,
     Path(image_id): Path<String>,
@@ -99,7 +101,7 @@ pub(crate) async fn session_status(
     let storage = s.read()?;
     let status = storage
         .get_session(&session_id)
-        .ok_or_else(|| anyhow::anyhow!("Session not found for session id: {:?}", &session_id))?;
+        .ok_or(Error::SessionNotFound)?;
     let receipt = storage.get_receipt(&session_id);
     match receipt {
         Some(_) => Ok(Json(SessionStatusRes {
@@ -107,16 +109,33 @@ pub(crate) async fn session_status(
             receipt_url: Some(format!("{}/receipts/{}", storage.local_url, session_id)),
             error_msg: None,
             state: None,
+            stats: storage.get_stats(&session_id),
         })),
         None => Ok(Json(SessionStatusRes {
             status,
             receipt_url: None,
             error_msg: None,
             state: None,
+            stats: None,
         })),
     }
 }
 
+pub(crate) async fn stop_session(
+    State(s): State<AppState>,
+    Path(session_id): Path<String>,
+) -> Result<(), Error> {
+    let mut storage = s.write()?;
+    let status = storage
+        .get_session(&session_id)
+        .ok_or(Error::SessionNotFound)?;
+    if status == "RUNNING" {
+        info!("Stopping session {session_id}");
+        storage.put_session(session_id, "ABORTED".to_string());
+    }
+    Ok(())
+}
+
 pub(crate) async fn create_snark(
     Json(_request): Json<SnarkReq>,
 ) -> Result<Json<CreateSessRes>, Error> {
@@ -17,6 +17,8 @@ use std::{
     sync::{Arc, RwLock},
 };
 
+use bonsai_sdk::alpha::responses::SessionStats;
+
 pub(crate) type AppState = Arc<RwLock<BonsaiState>>;
 
 #[derive(Clone, Default)]
@@ -30,6 +32,8 @@ pub(crate) struct BonsaiState {
     pub(crate) sessions: HashMap<String, String>,
     // SessionID - Receipts
     pub(crate) receipts: HashMap<String, Vec<u8>>,
+    // SessionID - Stats
+    pub(crate) stats: HashMap<String, SessionStats>,
 }
 
 impl BonsaiState {
@@ -40,6 +44,7 @@ impl BonsaiState {
             inputs: HashMap::new(),
             sessions: HashMap::new(),
             receipts: HashMap::new(),
+            stats: HashMap::new(),
         }
     }
     pub(crate) fn put_image(&mut self, image_id: String, image: Vec<u8>) -> Option<Vec<u8>> {
@@ -66,4 +71,14 @@ impl BonsaiState {
     pub(crate) fn get_receipt(&self, session_id: impl AsRef<str>) -> Option<Vec<u8>> {
         self.receipts.get(session_id.as_ref()).cloned()
     }
+    pub(crate) fn put_stats(
+        &mut self,
+        session_id: String,
+        stats: SessionStats,
+    ) -> Option<SessionStats> {
+        self.stats.insert(session_id, stats)
+    }
+    pub(crate) fn get_stats(&self, session_id: impl AsRef<str>) -> Option<SessionStats> {
+        self.stats.get(session_id.as_ref()).cloned()
+    }
 }
@@ -0,0 +1,455 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A scriptable mock of the Bonsai alpha REST API, to test Bonsai clients
+//! without credentials.
+//!
+//! Unlike [crate::serve], the mock does not execute guests: sessions follow
+//! scripted status progressions and serve a configured receipt and SNARK
+//! proof. Faults can be injected to test how clients handle latency, server
+//! errors, and rate limiting.
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, State},
+    http::{Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use bonsai_sdk::alpha::responses::{
+    CreateSessRes, ImgUploadRes, ProofReq, SessionStatusRes, SnarkProof, SnarkReq, SnarkStatusRes,
+    UploadRes,
+};
+use tokio::task::JoinHandle;
+
+/// Kind of fault returned in place of a response.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultKind {
+    /// Respond with `500 Internal Server Error`.
+    ServerError,
+    /// Respond with `429 Too Many Requests`.
+    RateLimited,
+}
+
+/// A fault returned in place of the response to the next request whose path
+/// starts with `path_prefix`.
+#[derive(Clone, Debug)]
+pub struct Fault {
+    pub path_prefix: String,
+    pub kind: FaultKind,
+}
+
+impl Fault {
+    pub fn new(path_prefix: impl Into<String>, kind: FaultKind) -> Self {
+        Self {
+            path_prefix: path_prefix.into(),
+            kind,
+        }
+    }
+}
+
+/// Scripted behavior of a [MockBonsai].
+#[derive(Clone, Debug)]
+pub struct MockBonsaiConfig {
+    /// Image IDs reported as already uploaded.
+    pub existing_images: HashSet<String>,
+    /// Statuses returned by successive status polls of each session. The last
+    /// status is repeated once reached.
+    pub session_statuses: Vec<String>,
+    /// Statuses returned by successive status polls of each SNARK session. The
+    /// last status is repeated once reached.
+    pub snark_statuses: Vec<String>,
+    /// Error message reported along with `FAILED`, `TIMED_OUT` or `ABORTED`
+    /// statuses.
+    pub error_msg: Option<String>,
+    /// Session IDs answered with `404 Not Found`, as if they never existed.
+    pub missing_sessions: HashSet<String>,
+    /// Receipt served for `SUCCEEDED` sessions.
+    pub receipt: Vec<u8>,
+    /// Proof served for `SUCCEEDED` SNARK sessions.
+    pub snark_proof: SnarkProof,
+    /// Latency added to every response.
+    pub latency: Duration,
+    /// Faults returned, each once, in place of the matching responses.
+    pub faults: Vec<Fault>,
+}
+
+impl Default for MockBonsaiConfig {
+    fn default() -> Self {
+        Self {
+            existing_images: HashSet::new(),
+            session_statuses: vec!["SUCCEEDED".to_string()],
+            snark_statuses: vec!["SUCCEEDED".to_string()],
+            error_msg: None,
+            missing_sessions: HashSet::new(),
+            receipt: vec![],
+            snark_proof: SnarkProof {
+                a: vec![],
+                b: vec![],
+                c: vec![],
+                public: vec![],
+            },
+            latency: Duration::ZERO,
+            faults: vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    url: String,
+    config: MockBonsaiConfig,
+    images: HashMap<String, Vec<u8>>,
+    inputs: HashMap<String, Vec<u8>>,
+    sessions: Vec<(String, String)>,
+    session_metadata: Vec<HashMap<String, String>>,
+    stopped_sessions: Vec<String>,
+    session_polls: HashMap<String, usize>,
+    snark_polls: HashMap<String, usize>,
+}
+
+type SharedState = Arc<Mutex<MockState>>;
+
+/// Returns the status of the next poll, following the scripted progression.
+fn next_status(polls: &mut HashMap<String, usize>, id: &str, statuses: &[String]) -> String {
+    let poll = polls.entry(id.to_string()).or_default();
+    let status = statuses
+        .get(*poll)
+        .or(statuses.last())
+        .cloned()
+        .unwrap_or_else(|| "SUCCEEDED".to_string());
+    *poll += 1;
+    status
+}
+
+fn error_msg(state: &MockState, status: &str) -> Option<String> {
+    match status {
+        "RUNNING" | "SUCCEEDED" => None,
+        _ => state.config.error_msg.clone(),
+    }
+}
+
+/// A mock of the Bonsai alpha REST API, served on localhost until dropped.
+pub struct MockBonsai {
+    url: String,
+    state: SharedState,
+    server: JoinHandle<()>,
+}
+
+impl MockBonsai {
+    /// Starts the mock on a free localhost port.
+    pub async fn start(config: MockBonsaiConfig) -> anyhow::Result<Self> {
+        let state = Arc::new(Mutex::new(MockState {
+            config,
+            ..Default::default()
+        }));
+        let server = axum::Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))
+            .context("failed to bind mock Bonsai")?
+            .serve(app(state.clone()).into_make_service());
+        let url = format!("http://{}", server.local_addr());
+        state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Poisoned lock"))?
+            .url = url.clone();
+        let server = tokio::spawn(async move {
+            let _ = server.await;
+        });
+        Ok(Self { url, state, server })
+    }
+
+    /// URL of the mock, to use as the Bonsai API URL.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// IDs of the images uploaded to the mock.
+    pub fn uploaded_images(&self) -> HashSet<String> {
+        self.state
+            .lock()
+            .map(|state| state.images.keys().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Inputs uploaded to the mock.
+    pub fn uploaded_inputs(&self) -> Vec<Vec<u8>> {
+        self.state
+            .lock()
+            .map(|state| state.inputs.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Image and input IDs of the sessions created on the mock.
+    pub fn sessions(&self) -> Vec<(String, String)> {
+        self.state
+            .lock()
+            .map(|state| state.sessions.clone())
+            .unwrap_or_default()
+    }
+
+    /// Metadata attached to the sessions created on the mock, in the order of
+    /// [MockBonsai::sessions].
+    pub fn session_metadata(&self) -> Vec<HashMap<String, String>> {
+        self.state
+            .lock()
+            .map(|state| state.session_metadata.clone())
+            .unwrap_or_default()
+    }
+
+    /// IDs of the sessions stopped on the mock, in order.
+    pub fn stopped_sessions(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .map(|state| state.stopped_sessions.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockBonsai {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn app(state: SharedState) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/images/upload/:image_id", get(get_image_upload))
+        .route("/images/:image_id", put(put_image_upload))
+        .route("/inputs/upload", get(get_input_upload))
+        .route("/inputs/:input_id", put(put_input_upload))
+        .route("/sessions/create", post(create_session))
+        .route("/sessions/status/:session_id", get(session_status))
+        .route("/sessions/:session_id", delete(stop_session))
+        .route("/snark/create", post(create_snark))
+        .route("/snark/status/:snark_id", get(snark_status))
+        .route("/receipts/:session_id", get(get_receipt))
+        .layer(middleware::from_fn_with_state(state.clone(), inject_faults))
+        .with_state(state)
+}
+
+/// Delays every response by the configured latency, and replaces the
+/// responses matching a configured fault.
+async fn inject_faults(
+    State(state): State<SharedState>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let (latency, fault) = {
+        let Ok(mut state) = state.lock() else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        let path = request.uri().path();
+        let fault = state
+            .config
+            .faults
+            .iter()
+            .position(|fault| path.starts_with(&fault.path_prefix))
+            .map(|index| state.config.faults.remove(index));
+        (state.config.latency, fault)
+    };
+    tokio::time::sleep(latency).await;
+    match fault.map(|fault| fault.kind) {
+        Some(FaultKind::ServerError) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, "injected server error").into_response()
+        }
+        Some(FaultKind::RateLimited) => {
+            (StatusCode::TOO_MANY_REQUESTS, "injected rate limit").into_response()
+        }
+        None => next.run(request).await,
+    }
+}
+
+async fn health() {}
+
+fn poisoned() -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, "Poisoned lock").into_response()
+}
+
+async fn get_image_upload(
+    State(state): State<SharedState>,
+    Path(image_id): Path<String>,
+) -> Response {
+    let Ok(state) = state.lock() else {
+        return poisoned();
+    };
+    if state.config.existing_images.contains(&image_id) || state.images.contains_key(&image_id) {
+        return StatusCode::NO_CONTENT.into_response();
+    }
+    Json(ImgUploadRes {
+        url: format!("{}/images/{image_id}", state.url),
+    })
+    .into_response()
+}
+
+async fn put_image_upload(
+    State(state): State<SharedState>,
+    Path(image_id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    state.images.insert(image_id, body.to_vec());
+    StatusCode::OK.into_response()
+}
+
+async fn get_input_upload(State(state): State<SharedState>) -> Response {
+    let Ok(state) = state.lock() else {
+        return poisoned();
+    };
+    let input_id = uuid::Uuid::new_v4().to_string();
+    Json(UploadRes {
+        url: format!("{}/inputs/{input_id}", state.url),
+        uuid: input_id,
+    })
+    .into_response()
+}
+
+async fn put_input_upload(
+    State(state): State<SharedState>,
+    Path(input_id): Path<String>,
+    body: Bytes,
+) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    state.inputs.insert(input_id, body.to_vec());
+    StatusCode::OK.into_response()
+}
+
+async fn create_session(
+    State(state): State<SharedState>,
+    Json(request): Json<ProofReq>,
+) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    if !state.config.existing_images.contains(&request.img)
+        && !state.images.contains_key(&request.img)
+    {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Image {} not found", request.img),
+        )
+            .into_response();
+    }
+    if !state.inputs.contains_key(&request.input) {
+        return (
+            StatusCode::NOT_FOUND,
+            format!("Input {} not found", request.input),
+        )
+            .into_response();
+    }
+    state.sessions.push((request.img, request.input));
+    state.session_metadata.push(request.metadata);
+    Json(CreateSessRes {
+        uuid: uuid::Uuid::new_v4().to_string(),
+    })
+    .into_response()
+}
+
+async fn session_status(
+    State(state): State<SharedState>,
+    Path(session_id): Path<String>,
+) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    if state.config.missing_sessions.contains(&session_id) {
+        return session_not_found(&session_id);
+    }
+    let state = &mut *state;
+    let status = if state.stopped_sessions.contains(&session_id) {
+        "ABORTED".to_string()
+    } else {
+        next_status(
+            &mut state.session_polls,
+            &session_id,
+            &state.config.session_statuses,
+        )
+    };
+    Json(SessionStatusRes {
+        receipt_url: (status == "SUCCEEDED")
+            .then(|| format!("{}/receipts/{session_id}", state.url)),
+        error_msg: error_msg(state, &status),
+        state: None,
+        stats: None,
+        status,
+    })
+    .into_response()
+}
+
+async fn stop_session(
+    State(state): State<SharedState>,
+    Path(session_id): Path<String>,
+) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    if state.config.missing_sessions.contains(&session_id) {
+        return session_not_found(&session_id);
+    }
+    state.stopped_sessions.push(session_id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+fn session_not_found(session_id: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        format!("Session {session_id} not found"),
+    )
+        .into_response()
+}
+
+async fn create_snark(Json(_request): Json<SnarkReq>) -> Response {
+    Json(CreateSessRes {
+        uuid: uuid::Uuid::new_v4().to_string(),
+    })
+    .into_response()
+}
+
+async fn snark_status(State(state): State<SharedState>, Path(snark_id): Path<String>) -> Response {
+    let Ok(mut state) = state.lock() else {
+        return poisoned();
+    };
+    let state = &mut *state;
+    let status = next_status(
+        &mut state.snark_polls,
+        &snark_id,
+        &state.config.snark_statuses,
+    );
+    Json(SnarkStatusRes {
+        output: (status == "SUCCEEDED").then(|| state.config.snark_proof.clone()),
+        error_msg: error_msg(state, &status),
+        status,
+    })
+    .into_response()
+}
+
+async fn get_receipt(State(state): State<SharedState>) -> Response {
+    let Ok(state) = state.lock() else {
+        return poisoned();
+    };
+    state.config.receipt.clone().into_response()
+}
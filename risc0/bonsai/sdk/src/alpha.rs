@@ -12,14 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fs::File, path::Path};
+use std::{collections::HashMap, fs::File, path::Path, time::Duration};
 
-use reqwest::{blocking::Client as BlockingClient, header};
+use reqwest::{
+    blocking::{Client as BlockingClient, RequestBuilder, Response},
+    header, Certificate, Identity, Proxy,
+};
 use thiserror::Error;
 
 use self::responses::{
     CreateSessRes, ImgUploadRes, ProofReq, SessionStatusRes, SnarkReq, SnarkStatusRes, UploadRes,
 };
+use crate::hmac_auth::HmacSigner;
 
 /// Bonsai Alpha SDK error classes
 #[derive(Debug, Error)]
@@ -45,10 +49,18 @@ pub enum SdkErr {
     /// Missing file
     #[error("failed to find file on disk")]
     FileNotFound(#[from] std::io::Error),
+    /// TLS client certificate or private key failing to parse
+    #[error("invalid TLS client certificate or private key")]
+    InvalidTlsIdentity(#[source] reqwest::Error),
+    /// The API has no session with the supplied UUID
+    #[error("session `{0}` not found")]
+    SessionNotFound(String),
 }
 
 /// Collection of serialization object for the REST api
 pub mod responses {
+    use std::collections::HashMap;
+
     use serde::{Deserialize, Serialize};
 
     /// Response of a upload request
@@ -81,6 +93,18 @@ pub mod responses {
         pub img: String,
         /// Input UUID
         pub input: String,
+        /// Custom key-value metadata attached to the Session, e.g. for billing
+        /// or audit
+        ///
+        /// Left out of the request if empty.
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+        pub metadata: HashMap<String, String>,
+        /// Maximum number of cycles the execution may run for before the
+        /// Session fails
+        ///
+        /// Left out of the request if unset.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub exec_cycle_limit: Option<u64>,
     }
 
     /// Session Status response
@@ -113,6 +137,22 @@ pub mod responses {
         /// * `Finalize`
         /// * `InProgress`
         pub state: Option<String>,
+        /// Session Statistics
+        ///
+        /// If the status is `SUCCEEDED`, this holds the execution statistics
+        /// of the session, otherwise it is [None].
+        pub stats: Option<SessionStats>,
+    }
+
+    /// Session statistics metadata file
+    #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+    pub struct SessionStats {
+        /// Count of segments in this proof request
+        pub segments: usize,
+        /// Total cycles run within guest
+        pub total_cycles: u64,
+        /// User cycles run within guest, slightly below total overhead cycles
+        pub cycles: u64,
     }
 
     /// Snark proof request object
@@ -123,7 +163,7 @@ pub mod responses {
     }
 
     /// Snark Proof object
-    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
     pub struct SnarkProof {
         /// Proof 'a' value
         pub a: Vec<String>,
@@ -169,14 +209,40 @@ impl SessionId {
 
     /// Fetches the current status of the Session
     pub fn status(&self, client: &Client) -> Result<SessionStatusRes, SdkErr> {
+        Ok(self.try_status(client)?)
+    }
+
+    /// Fetches the current status of the Session, telling server side
+    /// failures apart
+    pub(crate) fn try_status(&self, client: &Client) -> Result<SessionStatusRes, Failure> {
         let url = format!("{}/sessions/status/{}", client.url, self.uuid);
-        let res = client.client.get(url).send()?;
+        let res = client.send(client.client.get(url))?;
 
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(SdkErr::SessionNotFound(self.uuid.clone()).into());
+        }
+        if !res.status().is_success() {
+            return Err(Failure::from_response(res));
+        }
+        Ok(res.json::<SessionStatusRes>().map_err(SdkErr::from)?)
+    }
+
+    /// Stops the Session, aborting its proving
+    ///
+    /// The session status turns to ABORTED. Sessions that are no longer
+    /// running are left as they are.
+    pub fn stop(&self, client: &Client) -> Result<(), SdkErr> {
+        let url = format!("{}/sessions/{}", client.url, self.uuid);
+        let res = client.send(client.client.delete(url))?;
+
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(SdkErr::SessionNotFound(self.uuid.clone()));
+        }
         if !res.status().is_success() {
             let body = res.text()?;
             return Err(SdkErr::InternalServerErr(body));
         }
-        Ok(res.json::<SessionStatusRes>()?)
+        Ok(())
     }
 }
 
@@ -196,7 +262,7 @@ impl SnarkId {
     /// Fetches the current status of the Snark Session
     pub fn status(&self, client: &Client) -> Result<SnarkStatusRes, SdkErr> {
         let url = format!("{}/snark/status/{}", client.url, self.uuid);
-        let res = client.client.get(url).send()?;
+        let res = client.send(client.client.get(url))?;
 
         if !res.status().is_success() {
             let body = res.text()?;
@@ -206,22 +272,135 @@ impl SnarkId {
     }
 }
 
+/// Timeouts of the requests sent by a [Client]
+///
+/// Unset timeouts keep the defaults of [reqwest::blocking::Client]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClientTimeouts {
+    /// Timeout to establish the connection to the server
+    pub connect: Option<Duration>,
+    /// Timeout of a whole request, from connecting to reading the response
+    pub request: Option<Duration>,
+}
+
+/// Connection settings of a [Client]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// Timeouts of the requests
+    pub timeouts: ClientTimeouts,
+    /// Proxy URL to send all requests through
+    ///
+    /// If unset, the system proxy of the HTTPS_PROXY, HTTP_PROXY and ALL_PROXY
+    /// environment variables is used, if any
+    pub proxy: Option<String>,
+    /// PEM encoded root certificates to trust, in addition to the system ones
+    pub root_certificates: Vec<Vec<u8>>,
+    /// Accept invalid TLS certificates, e.g. self-signed ones
+    ///
+    /// This disables the authentication of the server, so it must only be
+    /// used in lab environments
+    pub accept_invalid_certs: bool,
+    /// Shared secret to sign the requests to the REST api with, see
+    /// [crate::hmac_auth]
+    pub hmac_secret: Option<Vec<u8>>,
+    /// TLS client certificate to authenticate with, for servers requiring
+    /// mutual TLS
+    pub tls_identity: Option<TlsIdentity>,
+}
+
+/// TLS client certificate and private key of a [Client]
+#[derive(Clone, PartialEq, Eq)]
+pub struct TlsIdentity {
+    /// PEM encoded certificate chain, starting with the client certificate
+    pub certificate_pem: Vec<u8>,
+    /// PEM encoded PKCS#8 private key of the client certificate
+    pub private_key_pem: Vec<u8>,
+}
+
+impl std::fmt::Debug for TlsIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsIdentity")
+            .field(
+                "certificate_pem",
+                &String::from_utf8_lossy(&self.certificate_pem),
+            )
+            .field("private_key_pem", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Failed request, telling server side failures apart from the others, e.g.
+/// to retry them on another instance of the REST api
+#[derive(Debug)]
+pub(crate) struct Failure {
+    pub(crate) err: SdkErr,
+    /// Whether the server answered with a 5xx status, or could not be
+    /// connected to
+    pub(crate) server_error: bool,
+}
+
+impl Failure {
+    /// Failure of a response with an unsuccessful status
+    fn from_response(res: Response) -> Self {
+        let server_error = res.status().is_server_error();
+        let err = match res.text() {
+            Ok(body) => SdkErr::InternalServerErr(body),
+            Err(err) => err.into(),
+        };
+        Self { err, server_error }
+    }
+}
+
+impl From<SdkErr> for Failure {
+    fn from(err: SdkErr) -> Self {
+        let server_error = matches!(&err, SdkErr::HttpErr(err) if err.is_connect());
+        Self { err, server_error }
+    }
+}
+
+impl From<Failure> for SdkErr {
+    fn from(failure: Failure) -> Self {
+        failure.err
+    }
+}
+
 /// Represents a client of the REST api
 #[derive(Clone)]
 pub struct Client {
     pub(crate) url: String,
     pub(crate) client: BlockingClient,
+    pub(crate) timeouts: ClientTimeouts,
+    pub(crate) signer: Option<HmacSigner>,
 }
 
 /// Creates a [reqwest::Client] for internal connection pooling
-fn construct_req_client(api_key: &str) -> Result<BlockingClient, SdkErr> {
+fn construct_req_client(api_key: &str, config: &ClientConfig) -> Result<BlockingClient, SdkErr> {
     let mut headers = header::HeaderMap::new();
     headers.insert("x-api-key", header::HeaderValue::from_str(api_key)?);
 
-    Ok(BlockingClient::builder()
+    let mut builder = BlockingClient::builder()
         .default_headers(headers)
         .pool_max_idle_per_host(0)
-        .build()?)
+        .danger_accept_invalid_certs(config.accept_invalid_certs);
+    if let Some(connect) = config.timeouts.connect {
+        builder = builder.connect_timeout(connect);
+    }
+    if let Some(request) = config.timeouts.request {
+        builder = builder.timeout(request);
+    }
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    for certificate in &config.root_certificates {
+        builder = builder.add_root_certificate(Certificate::from_pem(certificate)?);
+    }
+    if let Some(identity) = &config.tls_identity {
+        let identity =
+            Identity::from_pkcs8_pem(&identity.certificate_pem, &identity.private_key_pem)
+                .map_err(SdkErr::InvalidTlsIdentity)?;
+        builder = builder.identity(identity);
+    }
+    Ok(builder.build()?)
 }
 
 impl Client {
@@ -230,31 +409,66 @@ impl Client {
     /// Uses the BONSAI_API_URL and BONSAI_API_KEY environment variables to
     /// construct a client
     pub fn from_env() -> Result<Self, SdkErr> {
+        Self::from_env_with_config(&ClientConfig::default())
+    }
+
+    /// Construct a [Client] from env var, with the given connection settings
+    pub fn from_env_with_config(config: &ClientConfig) -> Result<Self, SdkErr> {
         let api_url = std::env::var("BONSAI_API_URL").map_err(|_| SdkErr::MissingApiUrl)?;
-        let api_url = api_url.strip_suffix('/').unwrap_or(&api_url);
         let api_key = std::env::var("BONSAI_API_KEY").map_err(|_| SdkErr::MissingApiKey)?;
 
-        let client = construct_req_client(&api_key)?;
+        Self::from_parts_with_config(api_url, api_key, config)
+    }
+
+    /// Construct a [Client] from url + api key strings
+    pub fn from_parts(url: String, key: String) -> Result<Self, SdkErr> {
+        Self::from_parts_with_config(url, key, &ClientConfig::default())
+    }
 
+    /// Construct a [Client] from url + api key strings, with the given
+    /// connection settings
+    pub fn from_parts_with_config(
+        url: String,
+        key: String,
+        config: &ClientConfig,
+    ) -> Result<Self, SdkErr> {
+        let client = construct_req_client(&key, config)?;
+        let url = url.strip_suffix('/').unwrap_or(&url).to_string();
         Ok(Self {
-            url: api_url.to_string(),
+            url,
             client,
+            timeouts: config.timeouts,
+            signer: config.hmac_secret.clone().map(HmacSigner::new),
         })
     }
 
-    /// Construct a [Client] from url + api key strings
-    pub fn from_parts(url: String, key: String) -> Result<Self, SdkErr> {
-        let client = construct_req_client(&key)?;
-        let url = url.strip_suffix('/').unwrap_or(&url).to_string();
-        Ok(Self { url, client })
+    /// Request timeouts the [Client] was constructed with
+    pub fn timeouts(&self) -> ClientTimeouts {
+        self.timeouts
+    }
+
+    /// URL of the REST api the [Client] was constructed with
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Send a request, signing it if it targets the REST api and the [Client]
+    /// has a HMAC secret
+    ///
+    /// Presigned upload and download URLs are left unsigned
+    fn send(&self, request: RequestBuilder) -> Result<Response, SdkErr> {
+        let mut request = request.build()?;
+        if let Some(signer) = &self.signer {
+            if request.url().as_str().starts_with(&self.url) {
+                signer.sign(&mut request)?;
+            }
+        }
+        Ok(self.client.execute(request)?)
     }
 
     /// Fetch a upload presigned url for a given route
     fn get_upload_url(&self, route: &str) -> Result<UploadRes, SdkErr> {
-        let res = self
-            .client
-            .get(format!("{}/{}/upload", self.url, route))
-            .send()?;
+        let res = self.send(self.client.get(format!("{}/{}/upload", self.url, route)))?;
 
         if !res.status().is_success() {
             let body = res.text()?;
@@ -264,30 +478,32 @@ impl Client {
         Ok(res.json::<UploadRes>()?)
     }
 
-    fn get_image_upload_url(&self, image_id: &str) -> Result<ImgUploadRes, SdkErr> {
-        let res = self
-            .client
-            .get(format!("{}/images/upload/{}", self.url, image_id))
-            .send()?;
+    fn get_image_upload_url(&self, image_id: &str) -> Result<ImgUploadRes, Failure> {
+        let res = self.send(
+            self.client
+                .get(format!("{}/images/upload/{}", self.url, image_id)),
+        )?;
 
         if res.status() == 204 {
-            return Err(SdkErr::ImageIdExists);
+            return Err(SdkErr::ImageIdExists.into());
         }
 
         if !res.status().is_success() {
-            let body = res.text()?;
-            return Err(SdkErr::InternalServerErr(body));
+            return Err(Failure::from_response(res));
         }
 
-        Ok(res.json::<ImgUploadRes>()?)
+        Ok(res.json::<ImgUploadRes>().map_err(SdkErr::from)?)
     }
 
     /// Upload body to a given URL
-    fn put_data<T: Into<reqwest::blocking::Body>>(&self, url: &str, body: T) -> Result<(), SdkErr> {
-        let res = self.client.put(url).body(body).send()?;
+    fn put_data<T: Into<reqwest::blocking::Body>>(
+        &self,
+        url: &str,
+        body: T,
+    ) -> Result<(), Failure> {
+        let res = self.send(self.client.put(url).body(body))?;
         if !res.status().is_success() {
-            let body = res.text()?;
-            return Err(SdkErr::InternalServerErr(body));
+            return Err(Failure::from_response(res));
         }
 
         Ok(())
@@ -301,6 +517,12 @@ impl Client {
     /// * ELF file bytes
     /// * bincode encoded MemoryImage
     pub fn upload_img(&self, image_id: &str, buf: Vec<u8>) -> Result<(), SdkErr> {
+        Ok(self.try_upload_img(image_id, buf)?)
+    }
+
+    /// Upload a image buffer to the /images/ route, telling server side
+    /// failures apart
+    pub(crate) fn try_upload_img(&self, image_id: &str, buf: Vec<u8>) -> Result<(), Failure> {
         let upload_res = self.get_image_upload_url(image_id)?;
         self.put_data(&upload_res.url, buf)?;
         Ok(())
@@ -346,14 +568,43 @@ impl Client {
     /// Supply the image_id and input_id created from uploading those files in
     /// previous steps
     pub fn create_session(&self, img_id: String, input_id: String) -> Result<SessionId, SdkErr> {
+        self.create_session_with_metadata(img_id, input_id, HashMap::new())
+    }
+
+    /// Create a new proof request Session tagged with custom metadata
+    ///
+    /// The metadata is sent as a JSON object of the session request, and is
+    /// ignored by API versions that do not support it
+    pub fn create_session_with_metadata(
+        &self,
+        img_id: String,
+        input_id: String,
+        metadata: HashMap<String, String>,
+    ) -> Result<SessionId, SdkErr> {
+        self.create_session_with_limit(img_id, input_id, metadata, None)
+    }
+
+    /// Create a new proof request Session tagged with custom metadata, whose
+    /// execution fails past `exec_cycle_limit` cycles
+    ///
+    /// The limit is ignored by API versions that do not support it
+    pub fn create_session_with_limit(
+        &self,
+        img_id: String,
+        input_id: String,
+        metadata: HashMap<String, String>,
+        exec_cycle_limit: Option<u64>,
+    ) -> Result<SessionId, SdkErr> {
         let url = format!("{}/sessions/create", self.url);
 
         let req = ProofReq {
             img: img_id,
             input: input_id,
+            metadata,
+            exec_cycle_limit,
         };
 
-        let res = self.client.post(url).json(&req).send()?;
+        let res = self.send(self.client.post(url).json(&req))?;
 
         if !res.status().is_success() {
             let body = res.text()?;
@@ -367,11 +618,25 @@ impl Client {
 
     // Utilities
 
+    /// Checks the REST api is up
+    ///
+    /// Succeeds once the /health route answers with a success status
+    pub fn health(&self) -> Result<(), SdkErr> {
+        let res = self.send(self.client.get(format!("{}/health", self.url)))?;
+
+        if !res.status().is_success() {
+            let body = res.text()?;
+            return Err(SdkErr::InternalServerErr(body));
+        }
+
+        Ok(())
+    }
+
     /// Download a given url to a buffer
     ///
     /// Useful to download a [SessionId] receipt_url
     pub fn download(&self, url: &str) -> Result<Vec<u8>, SdkErr> {
-        let data = self.client.get(url).send()?.bytes()?;
+        let data = self.send(self.client.get(url))?.bytes()?;
 
         Ok(data.into())
     }
@@ -387,7 +652,7 @@ impl Client {
 
         let snark_req = SnarkReq { session_id };
 
-        let res = self.client.post(url).json(&snark_req).send()?;
+        let res = self.send(self.client.post(url).json(&snark_req))?;
 
         if !res.status().is_success() {
             let body = res.text()?;
@@ -403,10 +668,10 @@ impl Client {
 
 #[cfg(test)]
 mod tests {
-    use httpmock::prelude::*;
+    use httpmock::{prelude::*, HttpMockRequest};
     use uuid::Uuid;
 
-    use super::*;
+    use super::{responses::SessionStats, *};
 
     const TEST_KEY: &str = "TESTKEY";
     const TEST_ID: &str = "0x5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
@@ -436,6 +701,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn client_from_parts_with_config() {
+        let timeouts = ClientTimeouts {
+            connect: Some(Duration::from_secs(30)),
+            request: Some(Duration::from_secs(120)),
+        };
+        let client = super::Client::from_parts_with_config(
+            "http://127.0.0.1/stage".to_string(),
+            TEST_KEY.to_string(),
+            &ClientConfig {
+                timeouts,
+                proxy: Some("http://127.0.0.1:3128".to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(client.timeouts(), timeouts);
+    }
+
+    #[test]
+    fn client_rejects_invalid_root_certificate() {
+        let result = super::Client::from_parts_with_config(
+            "http://127.0.0.1/stage".to_string(),
+            TEST_KEY.to_string(),
+            &ClientConfig {
+                root_certificates: vec![b"not a certificate".to_vec()],
+                ..Default::default()
+            },
+        );
+
+        assert!(matches!(result, Err(SdkErr::HttpErr(_))));
+    }
+
+    #[test]
+    fn client_rejects_invalid_tls_identity() {
+        let result = super::Client::from_parts_with_config(
+            "http://127.0.0.1/stage".to_string(),
+            TEST_KEY.to_string(),
+            &ClientConfig {
+                tls_identity: Some(TlsIdentity {
+                    certificate_pem: b"not a certificate".to_vec(),
+                    private_key_pem: b"not a key".to_vec(),
+                }),
+                ..Default::default()
+            },
+        );
+
+        assert!(matches!(result, Err(SdkErr::InvalidTlsIdentity(_))));
+    }
+
+    #[test]
+    fn image_upload_through_proxy() {
+        let server = MockServer::start();
+
+        // The proxy receives the requests to the unresolvable Bonsai host.
+        let put_url = format!("http://bonsai.invalid/upload/{TEST_ID}");
+        let response = ImgUploadRes { url: put_url };
+        let get_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/images/upload/{TEST_ID}"))
+                .header("host", "bonsai.invalid");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body_obj(&response);
+        });
+        let put_mock = server.mock(|when, then| {
+            when.method(PUT)
+                .path(format!("/upload/{TEST_ID}"))
+                .header("host", "bonsai.invalid");
+            then.status(200);
+        });
+
+        let client = super::Client::from_parts_with_config(
+            "http://bonsai.invalid".to_string(),
+            TEST_KEY.to_string(),
+            &ClientConfig {
+                proxy: Some(format!("http://{}", server.address())),
+                ..Default::default()
+            },
+        )
+        .expect("Failed to construct client");
+        client
+            .upload_img(TEST_ID, vec![])
+            .expect("Failed to upload image through the proxy");
+        get_mock.assert();
+        put_mock.assert();
+    }
+
     #[test]
     fn client_test_slash_strip() {
         let url = "http://127.0.0.1/".to_string();
@@ -559,6 +913,8 @@ mod tests {
         let request = ProofReq {
             img: TEST_ID.to_string(),
             input: Uuid::new_v4().to_string(),
+            metadata: HashMap::new(),
+            exec_cycle_limit: None,
         };
         let response = CreateSessRes {
             uuid: Uuid::new_v4().to_string(),
@@ -584,6 +940,112 @@ mod tests {
         create_mock.assert();
     }
 
+    #[test]
+    fn session_create_with_metadata() {
+        let server = MockServer::start();
+
+        let request = ProofReq {
+            img: TEST_ID.to_string(),
+            input: Uuid::new_v4().to_string(),
+            metadata: HashMap::from([
+                ("governance_proposal_id".to_string(), "42".to_string()),
+                ("environment".to_string(), "staging".to_string()),
+            ]),
+            exec_cycle_limit: None,
+        };
+        let response = CreateSessRes {
+            uuid: Uuid::new_v4().to_string(),
+        };
+
+        let create_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/sessions/create")
+                .header("x-api-key", TEST_KEY)
+                .json_body_obj(&request);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body_obj(&response);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        let res = client
+            .create_session_with_metadata(request.img, request.input, request.metadata)
+            .unwrap();
+        assert_eq!(res.uuid, response.uuid);
+
+        create_mock.assert();
+    }
+
+    #[test]
+    fn session_create_with_limit() {
+        let server = MockServer::start();
+
+        let request = ProofReq {
+            img: TEST_ID.to_string(),
+            input: Uuid::new_v4().to_string(),
+            metadata: HashMap::new(),
+            exec_cycle_limit: Some(1 << 20),
+        };
+        let response = CreateSessRes {
+            uuid: Uuid::new_v4().to_string(),
+        };
+
+        let create_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/sessions/create")
+                .header("x-api-key", TEST_KEY)
+                .json_body_obj(&request);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body_obj(&response);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        let res = client
+            .create_session_with_limit(
+                request.img,
+                request.input,
+                request.metadata,
+                request.exec_cycle_limit,
+            )
+            .unwrap();
+        assert_eq!(res.uuid, response.uuid);
+
+        create_mock.assert();
+    }
+
+    #[test]
+    fn health() {
+        let server = MockServer::start();
+
+        let mut health_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/health")
+                .header("x-api-key", TEST_KEY);
+            then.status(200);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        client.health().unwrap();
+        health_mock.assert();
+        health_mock.delete();
+
+        server.mock(|when, then| {
+            when.method(GET).path("/health");
+            then.status(503).body("starting");
+        });
+        assert!(matches!(
+            client.health(),
+            Err(SdkErr::InternalServerErr(body)) if body == "starting"
+        ));
+    }
+
     #[test]
     fn session_status() {
         let server = MockServer::start();
@@ -595,6 +1057,7 @@ mod tests {
             receipt_url: None,
             error_msg: None,
             state: None,
+            stats: None,
         };
 
         let create_mock = server.mock(|when, then| {
@@ -616,6 +1079,115 @@ mod tests {
         create_mock.assert();
     }
 
+    #[test]
+    fn session_status_not_found() {
+        let server = MockServer::start();
+
+        let session_id = SessionId::new(Uuid::new_v4().to_string());
+
+        let status_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/sessions/status/{}", session_id.uuid));
+            then.status(404);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        let result = session_id.status(&client);
+        assert!(matches!(
+            result,
+            Err(SdkErr::SessionNotFound(uuid)) if uuid == session_id.uuid
+        ));
+
+        status_mock.assert();
+    }
+
+    #[test]
+    fn session_stop() {
+        let server = MockServer::start();
+
+        let session_id = SessionId::new(Uuid::new_v4().to_string());
+
+        let stop_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path(format!("/sessions/{}", session_id.uuid))
+                .header("x-api-key", TEST_KEY);
+            then.status(204);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        session_id.stop(&client).unwrap();
+
+        stop_mock.assert();
+    }
+
+    #[test]
+    fn session_stop_not_found() {
+        let server = MockServer::start();
+
+        let session_id = SessionId::new(Uuid::new_v4().to_string());
+
+        let stop_mock = server.mock(|when, then| {
+            when.method(DELETE)
+                .path(format!("/sessions/{}", session_id.uuid));
+            then.status(404);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        let result = session_id.stop(&client);
+        assert!(matches!(
+            result,
+            Err(SdkErr::SessionNotFound(uuid)) if uuid == session_id.uuid
+        ));
+
+        stop_mock.assert();
+    }
+
+    #[test]
+    fn session_status_stats() {
+        let server = MockServer::start();
+
+        let uuid = Uuid::new_v4().to_string();
+        let session_id = SessionId::new(uuid);
+        let response = SessionStatusRes {
+            status: "SUCCEEDED".to_string(),
+            receipt_url: Some(format!(
+                "http://{}/receipts/{}",
+                server.address(),
+                session_id.uuid
+            )),
+            error_msg: None,
+            state: None,
+            stats: Some(SessionStats {
+                segments: 2,
+                total_cycles: 1 << 21,
+                cycles: 1_500_000,
+            }),
+        };
+
+        let create_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path(format!("/sessions/status/{}", session_id.uuid))
+                .header("x-api-key", TEST_KEY);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body_obj(&response);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let client = super::Client::from_parts(server_url, TEST_KEY.to_string()).unwrap();
+
+        let status = session_id.status(&client).unwrap();
+        assert_eq!(status.stats, response.stats);
+
+        create_mock.assert();
+    }
+
     #[test]
     fn snark_create() {
         let server = MockServer::start();
@@ -647,6 +1219,73 @@ mod tests {
         create_mock.assert();
     }
 
+    const TEST_HMAC_SECRET: &[u8] = b"test-hmac-secret";
+
+    fn signed_with_test_secret(req: &HttpMockRequest) -> bool {
+        let header = |name: &str| {
+            req.headers
+                .iter()
+                .flatten()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        let (Some(timestamp), Some(signature)) = (
+            header(crate::hmac_auth::TIMESTAMP_HEADER),
+            header(crate::hmac_auth::SIGNATURE_HEADER),
+        ) else {
+            return false;
+        };
+        let Ok(timestamp) = timestamp.parse() else {
+            return false;
+        };
+        let body = req.body.clone().unwrap_or_default();
+        signature.len() == 64
+            && signature
+                == HmacSigner::new(TEST_HMAC_SECRET.to_vec()).signature(
+                    timestamp,
+                    &req.method,
+                    &req.path,
+                    &body,
+                )
+    }
+
+    #[test]
+    fn snark_create_signed() {
+        let server = MockServer::start();
+
+        let request = SnarkReq {
+            session_id: Uuid::new_v4().to_string(),
+        };
+        let response = CreateSessRes {
+            uuid: Uuid::new_v4().to_string(),
+        };
+
+        let create_mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/snark/create")
+                .header("x-api-key", TEST_KEY)
+                .json_body_obj(&request)
+                .matches(signed_with_test_secret);
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body_obj(&response);
+        });
+
+        let server_url = format!("http://{}", server.address());
+        let config = ClientConfig {
+            hmac_secret: Some(TEST_HMAC_SECRET.to_vec()),
+            ..Default::default()
+        };
+        let client =
+            super::Client::from_parts_with_config(server_url, TEST_KEY.to_string(), &config)
+                .unwrap();
+
+        let res = client.create_snark(request.session_id).unwrap();
+        assert_eq!(res.uuid, response.uuid);
+
+        create_mock.assert();
+    }
+
     #[test]
     fn snark_status() {
         let server = MockServer::start();
@@ -12,9 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
 use crate::alpha::{
     responses::{SessionStatusRes, SnarkStatusRes},
-    Client, SdkErr, SessionId, SnarkId,
+    Client, ClientConfig, Failure, SdkErr, SessionId, SnarkId,
 };
 
 /// Construct a Bonsai SDK Client from env var
@@ -34,6 +39,124 @@ pub async fn get_client_from_parts(url: String, api_key: String) -> Result<Clien
         .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
 }
 
+/// Construct a Bonsai SDK Client from url + api key strings, with the given
+/// connection settings
+pub async fn get_client_from_parts_with_config(
+    url: String,
+    api_key: String,
+    config: ClientConfig,
+) -> Result<Client, SdkErr> {
+    tokio::task::spawn_blocking(move || Client::from_parts_with_config(url, api_key, &config))
+        .await
+        .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
+}
+
+/// Construct a [RoundRobinBonsaiClient] from the urls of several instances of
+/// the REST api sharing an api key
+pub async fn get_round_robin_client_from_parts(
+    urls: Vec<String>,
+    api_key: String,
+) -> Result<RoundRobinBonsaiClient, SdkErr> {
+    get_round_robin_client_from_parts_with_config(urls, api_key, ClientConfig::default()).await
+}
+
+/// Construct a [RoundRobinBonsaiClient] from the urls of several instances of
+/// the REST api sharing an api key, with the given connection settings
+pub async fn get_round_robin_client_from_parts_with_config(
+    urls: Vec<String>,
+    api_key: String,
+    config: ClientConfig,
+) -> Result<RoundRobinBonsaiClient, SdkErr> {
+    tokio::task::spawn_blocking(move || {
+        let clients = urls
+            .into_iter()
+            .map(|url| Client::from_parts_with_config(url, api_key.clone(), &config))
+            .collect::<Result<Vec<_>, _>>()?;
+        RoundRobinBonsaiClient::new(clients)
+    })
+    .await
+    .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
+}
+
+/// Clients of several instances of the REST api, e.g. behind a load balancer,
+/// taking turns to upload images and fetch session statuses
+///
+/// A request failing on an instance with a 5xx status, or unable to connect
+/// to it, is retried on the next instance, trying each instance once
+#[derive(Clone)]
+pub struct RoundRobinBonsaiClient {
+    clients: Arc<[Client]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl From<Client> for RoundRobinBonsaiClient {
+    fn from(client: Client) -> Self {
+        Self {
+            clients: Arc::from(vec![client]),
+            next: Arc::default(),
+        }
+    }
+}
+
+impl RoundRobinBonsaiClient {
+    /// Construct a [RoundRobinBonsaiClient] from the clients of the instances
+    ///
+    /// Fails with [SdkErr::MissingApiUrl] if there are none
+    pub fn new(clients: Vec<Client>) -> Result<Self, SdkErr> {
+        if clients.is_empty() {
+            return Err(SdkErr::MissingApiUrl);
+        }
+        Ok(Self {
+            clients: clients.into(),
+            next: Arc::default(),
+        })
+    }
+
+    /// Client of the first instance, for the requests that are not shared
+    /// between the instances
+    pub fn primary(&self) -> &Client {
+        &self.clients[0]
+    }
+
+    /// Upload a image buffer to the /images/ route of the next instance
+    ///
+    /// See [put_image]
+    pub async fn put_image(&self, image_id: String, image: Vec<u8>) -> Result<(), SdkErr> {
+        self.round_robin(move |client| client.try_upload_img(&image_id, image.clone()))
+            .await
+    }
+
+    /// Fetches the current status of the Session from the next instance
+    pub async fn session_status(&self, session: SessionId) -> Result<SessionStatusRes, SdkErr> {
+        self.round_robin(move |client| session.try_status(client))
+            .await
+    }
+
+    /// Send a request to the next instance, then to the following ones while
+    /// it fails on the server side
+    async fn round_robin<T: Send + 'static>(
+        &self,
+        request: impl Fn(&Client) -> Result<T, Failure> + Send + Sync + 'static,
+    ) -> Result<T, SdkErr> {
+        let request = Arc::new(request);
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        let mut attempt = 0;
+        loop {
+            let client = self.clients[(first + attempt) % self.clients.len()].clone();
+            let request = request.clone();
+            let result = tokio::task::spawn_blocking(move || request(&client))
+                .await
+                .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?;
+            attempt += 1;
+            match result {
+                Ok(res) => return Ok(res),
+                Err(failure) if failure.server_error && attempt < self.clients.len() => {}
+                Err(failure) => return Err(failure.err),
+            }
+        }
+    }
+}
+
 /// Upload a input buffer to the /inputs/ route
 pub async fn put_input(bonsai_client: Client, buf: Vec<u8>) -> Result<String, SdkErr> {
     tokio::task::spawn_blocking(move || bonsai_client.upload_input(buf))
@@ -80,6 +203,13 @@ pub async fn session_status(
         .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
 }
 
+/// Stops the Session, aborting its proving
+pub async fn stop_session(bonsai_client: Client, session: SessionId) -> Result<(), SdkErr> {
+    tokio::task::spawn_blocking(move || session.stop(&bonsai_client))
+        .await
+        .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
+}
+
 /// Requests a SNARK proof be created from a existing sessionId
 ///
 /// Supply a completed sessionId to convert the risc0 STARK proof into
@@ -97,6 +227,13 @@ pub async fn snark_status(bonsai_client: Client, snark: SnarkId) -> Result<Snark
         .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
 }
 
+/// Checks the REST api is up
+pub async fn health(bonsai_client: Client) -> Result<(), SdkErr> {
+    tokio::task::spawn_blocking(move || bonsai_client.health())
+        .await
+        .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
+}
+
 /// Download a given url to a buffer
 ///
 /// Useful to download a [SessionId] receipt_url
@@ -105,3 +242,157 @@ pub async fn download(bonsai_client: Client, url: String) -> Result<Vec<u8>, Sdk
         .await
         .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
 }
+
+#[cfg(test)]
+mod tests {
+    use httpmock::prelude::*;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::alpha::responses::ImgUploadRes;
+
+    const TEST_KEY: &str = "TESTKEY";
+    const TEST_ID: &str = "0x5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    fn running() -> SessionStatusRes {
+        SessionStatusRes {
+            status: "RUNNING".to_string(),
+            receipt_url: None,
+            error_msg: None,
+            state: None,
+            stats: None,
+        }
+    }
+
+    async fn round_robin_client(servers: &[&MockServer]) -> RoundRobinBonsaiClient {
+        let urls = servers
+            .iter()
+            .map(|server| format!("http://{}", server.address()))
+            .collect();
+        get_round_robin_client_from_parts(urls, TEST_KEY.to_string())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_robin_client_takes_turns() {
+        let session_id = SessionId::new(Uuid::new_v4().to_string());
+        let first = MockServer::start_async().await;
+        let second = MockServer::start_async().await;
+        let mut mocks = Vec::new();
+        for server in [&first, &second] {
+            mocks.push(
+                server
+                    .mock_async(|when, then| {
+                        when.method(GET)
+                            .path(format!("/sessions/status/{}", session_id.uuid))
+                            .header("x-api-key", TEST_KEY);
+                        then.status(200)
+                            .header("content-type", "application/json")
+                            .json_body_obj(&running());
+                    })
+                    .await,
+            );
+        }
+
+        let client = round_robin_client(&[&first, &second]).await;
+        assert_eq!(
+            client.primary().url(),
+            format!("http://{}", first.address())
+        );
+        for _ in 0..4 {
+            let status = client.session_status(session_id.clone()).await.unwrap();
+            assert_eq!(status.status, "RUNNING");
+        }
+        for mock in mocks {
+            mock.assert_hits_async(2).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn round_robin_client_retries_server_errors_on_the_next_instance() {
+        let failing = MockServer::start_async().await;
+        let healthy = MockServer::start_async().await;
+        let failing_mock = failing
+            .mock_async(|when, then| {
+                when.method(GET).path(format!("/images/upload/{TEST_ID}"));
+                then.status(503).body("unavailable");
+            })
+            .await;
+        let response = ImgUploadRes {
+            url: format!("http://{}/upload/{TEST_ID}", healthy.address()),
+        };
+        let get_mock = healthy
+            .mock_async(|when, then| {
+                when.method(GET).path(format!("/images/upload/{TEST_ID}"));
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body_obj(&response);
+            })
+            .await;
+        let put_mock = healthy
+            .mock_async(|when, then| {
+                when.method(PUT).path(format!("/upload/{TEST_ID}"));
+                then.status(200);
+            })
+            .await;
+
+        let client = round_robin_client(&[&failing, &healthy]).await;
+        client.put_image(TEST_ID.to_string(), vec![]).await.unwrap();
+        failing_mock.assert_async().await;
+        get_mock.assert_async().await;
+        put_mock.assert_async().await;
+
+        // Each instance is tried once.
+        get_mock.delete_async().await;
+        healthy
+            .mock_async(|when, then| {
+                when.method(GET).path(format!("/images/upload/{TEST_ID}"));
+                then.status(500).body("internal error");
+            })
+            .await;
+        let err = client
+            .put_image(TEST_ID.to_string(), vec![])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, SdkErr::InternalServerErr(_)));
+        failing_mock.assert_hits_async(2).await;
+    }
+
+    #[tokio::test]
+    async fn round_robin_client_does_not_retry_client_errors() {
+        let session_id = SessionId::new(Uuid::new_v4().to_string());
+        let first = MockServer::start_async().await;
+        let second = MockServer::start_async().await;
+        let not_found = first
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/sessions/status/{}", session_id.uuid));
+                then.status(404);
+            })
+            .await;
+        let unused = second
+            .mock_async(|when, then| {
+                when.method(GET)
+                    .path(format!("/sessions/status/{}", session_id.uuid));
+                then.status(200)
+                    .header("content-type", "application/json")
+                    .json_body_obj(&running());
+            })
+            .await;
+
+        let client = round_robin_client(&[&first, &second]).await;
+        let err = client.session_status(session_id.clone()).await.unwrap_err();
+        assert!(matches!(err, SdkErr::SessionNotFound(uuid) if uuid == session_id.uuid));
+        not_found.assert_async().await;
+        unused.assert_hits_async(0).await;
+    }
+
+    #[tokio::test]
+    async fn round_robin_client_needs_an_instance() {
+        assert!(matches!(
+            get_round_robin_client_from_parts(Vec::new(), TEST_KEY.to_string()).await,
+            Err(SdkErr::MissingApiUrl)
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2023 RISC Zero, Inc.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use reqwest::{blocking::Request, header::HeaderValue};
+use sha2::{Digest, Sha256};
+
+use crate::alpha::SdkErr;
+
+/// Header carrying the hex encoded HMAC-SHA256 signature of a request
+pub const SIGNATURE_HEADER: &str = "x-bonsai-signature";
+
+/// Header carrying the time a request was signed at, in milliseconds since the
+/// Unix epoch
+pub const TIMESTAMP_HEADER: &str = "x-bonsai-timestamp";
+
+/// Signs requests with HMAC-SHA256, for Bonsai deployments authenticating
+/// requests with a shared secret rather than a static API key
+#[derive(Clone)]
+pub struct HmacSigner {
+    secret: Vec<u8>,
+}
+
+impl HmacSigner {
+    /// Construct a [HmacSigner] from the shared secret
+    pub fn new(secret: Vec<u8>) -> Self {
+        Self { secret }
+    }
+
+    /// Signature of a request, as the hex encoded
+    /// `HMAC-SHA256(timestamp_ms + method + path + hex(SHA-256(body)))`
+    pub fn signature(&self, timestamp_ms: u128, method: &str, path: &str, body: &[u8]) -> String {
+        let body_hash = hex::encode(Sha256::digest(body));
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(format!("{timestamp_ms}{method}{path}{body_hash}").as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Add the timestamp and signature headers to a request
+    ///
+    /// Streamed bodies can't be hashed ahead of sending, and are signed as
+    /// empty bodies
+    pub fn sign(&self, request: &mut Request) -> Result<(), SdkErr> {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| SdkErr::InternalServerErr(format!("{err}")))?
+            .as_millis();
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .unwrap_or_default();
+        let signature = self.signature(
+            timestamp_ms,
+            request.method().as_str(),
+            request.url().path(),
+            body,
+        );
+        let headers = request.headers_mut();
+        headers.insert(TIMESTAMP_HEADER, HeaderValue::from(timestamp_ms as u64));
+        headers.insert(SIGNATURE_HEADER, HeaderValue::from_str(&signature)?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HmacSigner;
+
+    #[test]
+    fn signature_of_request() {
+        let signer = HmacSigner::new(hex::decode("00112233445566778899aabbccddeeff").unwrap());
+        assert_eq!(
+            signer.signature(
+                1700000000000,
+                "POST",
+                "/snark/create",
+                br#"{"session_id":"abc"}"#
+            ),
+            "7cc7eb8e1a4376110b8abbd3f788e377c79e9678376a998a4deba5b4d9faf2cf"
+        );
+    }
+}
@@ -20,3 +20,5 @@ pub mod alpha;
 #[cfg(feature = "async")]
 /// Bonsai Alpha SDK async
 pub mod alpha_async;
+/// HMAC-SHA256 signing of requests to the REST api
+pub mod hmac_auth;